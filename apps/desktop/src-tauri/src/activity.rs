@@ -0,0 +1,555 @@
+// Read Master Desktop - Activity Statistics
+//
+// Per-day reading minutes and flashcard reviews for the stats page
+// heatmaps, reading streaks, and a forecast of upcoming reviews.
+//
+// Days are the user's local calendar days. Rust works out when each local
+// day starts and ends in UTC (so DST days are 23 or 25 hours long), passes
+// the boundaries to SQLite as JSON, and the database does the aggregation.
+// A session that runs past midnight is split between the two days.
+//
+// Results are cached until a session is recorded or a card reviewed, or
+// the date changes.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::db::Database;
+use crate::srs::{self, CardState};
+
+/// Missed days a streak survives
+const GRACE_DAYS: i64 = 1;
+
+/// Longest range a heatmap covers
+const MAX_RANGE_DAYS: i64 = 3 * 366;
+
+const MAX_FORECAST_DAYS: u32 = 365;
+
+/// Reading minutes per day: each session's overlap with each day
+const READING_MINUTES_SQL: &str = "
+    WITH days AS (
+        SELECT json_extract(value, '$[0]') AS day,
+               julianday(json_extract(value, '$[1]')) AS day_start,
+               julianday(json_extract(value, '$[2]')) AS day_end
+        FROM json_each(?1)
+    ),
+    sessions AS (
+        SELECT julianday(started_at) AS s, julianday(ended_at) AS e
+        FROM reading_sessions
+        WHERE started_at < ?3 AND ended_at > ?2 AND ended_at > started_at
+    )
+    SELECT day, SUM(MIN(e, day_end) - MAX(s, day_start)) * 1440.0
+    FROM days JOIN sessions ON s < day_end AND e > day_start
+    GROUP BY day";
+
+/// Flashcard reviews per day
+const REVIEWS_SQL: &str = "
+    WITH days AS (
+        SELECT json_extract(value, '$[0]') AS day,
+               julianday(json_extract(value, '$[1]')) AS day_start,
+               julianday(json_extract(value, '$[2]')) AS day_end
+        FROM json_each(?1)
+    ),
+    reviews AS (
+        SELECT julianday(reviewed_at) AS t
+        FROM card_reviews
+        WHERE reviewed_at >= ?2 AND reviewed_at < ?3
+    )
+    SELECT day, COUNT(*)
+    FROM days JOIN reviews ON t >= day_start AND t < day_end
+    GROUP BY day";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Metric {
+    ReadingMinutes,
+    Reviews,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DayActivity {
+    pub date: NaiveDate,
+    /// Minutes read or cards reviewed
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Streaks {
+    /// Active days in the running streak; 0 once it has lapsed
+    pub current: u32,
+    pub longest: u32,
+    pub last_active: Option<NaiveDate>,
+    /// The current streak only survives thanks to the grace period, so
+    /// today is needed to keep it
+    pub in_grace: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ForecastDay {
+    pub date: NaiveDate,
+    /// Cards falling due that day; today also counts overdue cards
+    pub due: u32,
+}
+
+/// A local calendar day as a UTC interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DaySpan {
+    date: NaiveDate,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    heatmaps: HashMap<(Metric, NaiveDate, NaiveDate, NaiveDate), Vec<DayActivity>>,
+    streaks: HashMap<(Metric, NaiveDate), Streaks>,
+    forecasts: HashMap<(u32, NaiveDate), Vec<ForecastDay>>,
+}
+
+/// Cached statistics, keyed by their arguments and today's date
+#[derive(Default)]
+pub struct ActivityCache(Mutex<CacheEntries>);
+
+/// Drop cached statistics after new reading or review activity
+pub fn invalidate<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(cache) = app.try_state::<ActivityCache>() {
+        if let Ok(mut entries) = cache.0.lock() {
+            *entries = CacheEntries::default();
+        }
+    }
+}
+
+// ============================================================================
+// Days
+// ============================================================================
+
+/// When `date` starts in the local timezone. Zones that skip midnight for
+/// DST start the day at the first hour that exists.
+pub fn local_midnight(date: NaiveDate) -> DateTime<Utc> {
+    (0..24)
+        .filter_map(|hour| NaiveTime::from_hms_opt(hour, 0, 0))
+        .find_map(|time| Local.from_local_datetime(&date.and_time(time)).earliest())
+        .map(|start| start.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN)))
+}
+
+fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+/// Days from `start` to `end` inclusive, bounded by `midnight`
+fn day_spans(
+    start: NaiveDate,
+    end: NaiveDate,
+    midnight: impl Fn(NaiveDate) -> DateTime<Utc>,
+) -> Vec<DaySpan> {
+    start
+        .iter_days()
+        .take_while(|date| *date <= end)
+        .filter_map(|date| {
+            Some(DaySpan {
+                date,
+                start: midnight(date),
+                end: midnight(date.succ_opt()?),
+            })
+        })
+        .collect()
+}
+
+/// Per-day totals of `metric` over `spans`, including days without any
+fn aggregate(
+    conn: &Connection,
+    metric: Metric,
+    spans: &[DaySpan],
+) -> rusqlite::Result<Vec<DayActivity>> {
+    let (Some(first), Some(last)) = (spans.first(), spans.last()) else {
+        return Ok(Vec::new());
+    };
+    let days: Vec<(String, String, String)> = spans
+        .iter()
+        .map(|span| {
+            (
+                span.date.to_string(),
+                span.start.to_rfc3339(),
+                span.end.to_rfc3339(),
+            )
+        })
+        .collect();
+    let days = serde_json::to_string(&days)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    let sql = match metric {
+        Metric::ReadingMinutes => READING_MINUTES_SQL,
+        Metric::Reviews => REVIEWS_SQL,
+    };
+    let mut stmt = conn.prepare(sql)?;
+    let totals = stmt
+        .query_map(
+            params![days, first.start.to_rfc3339(), last.end.to_rfc3339()],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?)),
+        )?
+        .collect::<rusqlite::Result<HashMap<String, f64>>>()?;
+
+    Ok(spans
+        .iter()
+        .map(|span| DayActivity {
+            date: span.date,
+            value: totals.get(&span.date.to_string()).copied().unwrap_or(0.0),
+        })
+        .collect())
+}
+
+/// Earliest activity of `metric` on record
+fn first_activity(conn: &Connection, metric: Metric) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    let sql = match metric {
+        Metric::ReadingMinutes => "SELECT MIN(started_at) FROM reading_sessions",
+        Metric::Reviews => "SELECT MIN(reviewed_at) FROM card_reviews",
+    };
+    let first: Option<String> = conn
+        .query_row(sql, [], |row| row.get(0))
+        .optional()?
+        .flatten();
+    Ok(first
+        .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+        .map(|at| at.with_timezone(&Utc)))
+}
+
+// ============================================================================
+// Streaks & Forecast
+// ============================================================================
+
+/// Streaks over `active` days (ascending). Gaps of up to `GRACE_DAYS`
+/// missed days don't break a streak, but don't count towards it either.
+fn streaks(active: &[NaiveDate], today: NaiveDate) -> Streaks {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut last: Option<NaiveDate> = None;
+    for &day in active {
+        run = match last {
+            Some(previous) if (day - previous).num_days() <= GRACE_DAYS + 1 => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        last = Some(day);
+    }
+
+    let since_last = last.map(|day| (today - day).num_days());
+    let alive = since_last.is_some_and(|days| days <= GRACE_DAYS + 1);
+    Streaks {
+        current: if alive { run } else { 0 },
+        longest,
+        last_active: last,
+        in_grace: alive && since_last.is_some_and(|days| days > 1),
+    }
+}
+
+/// Reading minutes for each local day from `start` to `end` (inclusive)
+pub fn daily_reading(
+    conn: &Connection,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> rusqlite::Result<Vec<DayActivity>> {
+    aggregate(
+        conn,
+        Metric::ReadingMinutes,
+        &day_spans(start, end, local_midnight),
+    )
+}
+
+/// Longest streak within `days`, with the same grace period as `get_streaks`
+pub fn longest_streak(days: &[DayActivity]) -> u32 {
+    let active: Vec<NaiveDate> = days
+        .iter()
+        .filter(|day| day.value > 0.0)
+        .map(|day| day.date)
+        .collect();
+    active
+        .last()
+        .map_or(0, |&last| streaks(&active, last).longest)
+}
+
+/// Cards due on each day of `spans`. Overdue cards count on the first day;
+/// new cards, which have no due date, aren't counted.
+fn forecast(cards: &[CardState], spans: &[DaySpan]) -> Vec<ForecastDay> {
+    let mut due = vec![0u32; spans.len()];
+    for card in cards {
+        let Some(at) = card.due_at else {
+            continue;
+        };
+        let day = match spans.iter().position(|span| at < span.end) {
+            Some(0) => 0,
+            Some(i) if at >= spans[i].start => i,
+            _ => continue,
+        };
+        due[day] += 1;
+    }
+    spans
+        .iter()
+        .zip(due)
+        .map(|(span, due)| ForecastDay {
+            date: span.date,
+            due,
+        })
+        .collect()
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Daily totals of `metric` from `start_date` to `end_date` (inclusive,
+/// local dates), with zero for days without activity
+#[tauri::command]
+pub async fn get_activity_heatmap(
+    db: State<'_, Database>,
+    cache: State<'_, ActivityCache>,
+    metric: Metric,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<Vec<DayActivity>, String> {
+    if end_date < start_date {
+        return Err("End date is before start date".to_string());
+    }
+    if (end_date - start_date).num_days() >= MAX_RANGE_DAYS {
+        return Err(format!("Range is longer than {} days", MAX_RANGE_DAYS));
+    }
+
+    let key = (metric, start_date, end_date, today());
+    if let Some(days) = cache
+        .0
+        .lock()
+        .ok()
+        .and_then(|c| c.heatmaps.get(&key).cloned())
+    {
+        return Ok(days);
+    }
+
+    let spans = day_spans(start_date, end_date, local_midnight);
+    let days = db.with_conn(|conn| aggregate(conn, metric, &spans))?;
+    if let Ok(mut entries) = cache.0.lock() {
+        entries.heatmaps.insert(key, days.clone());
+    }
+    Ok(days)
+}
+
+/// Current and longest run of active days for `metric`
+#[tauri::command]
+pub async fn get_streaks(
+    db: State<'_, Database>,
+    cache: State<'_, ActivityCache>,
+    metric: Metric,
+) -> Result<Streaks, String> {
+    let today = today();
+    let key = (metric, today);
+    if let Some(streaks) = cache
+        .0
+        .lock()
+        .ok()
+        .and_then(|c| c.streaks.get(&key).copied())
+    {
+        return Ok(streaks);
+    }
+
+    let result = db.with_conn(|conn| {
+        let Some(first) = first_activity(conn, metric)? else {
+            return Ok(Streaks::default());
+        };
+        let first = first.with_timezone(&Local).date_naive().min(today);
+        let spans = day_spans(first, today, local_midnight);
+        let active: Vec<NaiveDate> = aggregate(conn, metric, &spans)?
+            .into_iter()
+            .filter(|day| day.value > 0.0)
+            .map(|day| day.date)
+            .collect();
+        Ok(streaks(&active, today))
+    })?;
+
+    if let Ok(mut entries) = cache.0.lock() {
+        entries.streaks.insert(key, result);
+    }
+    Ok(result)
+}
+
+/// Flashcards falling due on each of the next `days` days, starting today
+#[tauri::command]
+pub async fn get_review_forecast<R: Runtime>(
+    app: AppHandle<R>,
+    cache: State<'_, ActivityCache>,
+    days: u32,
+) -> Result<Vec<ForecastDay>, String> {
+    let days = days.clamp(1, MAX_FORECAST_DAYS);
+    let today = today();
+    let key = (days, today);
+    if let Some(result) = cache
+        .0
+        .lock()
+        .ok()
+        .and_then(|c| c.forecasts.get(&key).cloned())
+    {
+        return Ok(result);
+    }
+
+    let last = today + chrono::Duration::days(i64::from(days) - 1);
+    let spans = day_spans(today, last, local_midnight);
+    let result = forecast(&srs::load_all(&app)?, &spans);
+    if let Ok(mut entries) = cache.0.lock() {
+        entries.forecasts.insert(key, result.clone());
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    /// Central European time: UTC+1, and UTC+2 from 2024-03-31 02:00 to
+    /// 2024-10-27 03:00 local
+    fn cet_midnight(date: NaiveDate) -> DateTime<Utc> {
+        let summer = date > self::date("2024-03-31") && date <= self::date("2024-10-27");
+        let offset = if summer { 2 } else { 1 };
+        Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN)) - chrono::Duration::hours(offset)
+    }
+
+    fn sessions(rows: &[(&str, &str)]) -> Connection {
+        let conn = crate::db::test_connection();
+        conn.execute(
+            "INSERT INTO books (id, title, added_at, updated_at) VALUES (1, 'Book', '', '')",
+            [],
+        )
+        .unwrap();
+        for (start, end) in rows {
+            conn.execute(
+                "INSERT INTO reading_sessions (book_id, started_at, ended_at) VALUES (1, ?1, ?2)",
+                params![utc(start).to_rfc3339(), utc(end).to_rfc3339()],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn dst_days_are_23_and_25_hours() {
+        let spans = day_spans(date("2024-03-30"), date("2024-03-31"), cet_midnight);
+        assert_eq!((spans[0].end - spans[0].start).num_hours(), 24);
+        assert_eq!((spans[1].end - spans[1].start).num_hours(), 23);
+
+        let spans = day_spans(date("2024-10-27"), date("2024-10-27"), cet_midnight);
+        assert_eq!((spans[0].end - spans[0].start).num_hours(), 25);
+    }
+
+    #[test]
+    fn splits_sessions_at_local_midnight() {
+        // 23:30 to 00:45 local time (UTC+1)
+        let conn = sessions(&[("2024-01-10T22:30:00Z", "2024-01-10T23:45:00Z")]);
+        let spans = day_spans(date("2024-01-10"), date("2024-01-12"), cet_midnight);
+        let days = aggregate(&conn, Metric::ReadingMinutes, &spans).unwrap();
+
+        let minutes: Vec<i64> = days.iter().map(|d| d.value.round() as i64).collect();
+        assert_eq!(minutes, [30, 45, 0]);
+    }
+
+    #[test]
+    fn counts_the_whole_of_a_long_dst_day() {
+        // Reading all 25 hours of the day summer time ends
+        let conn = sessions(&[("2024-10-26T22:00:00Z", "2024-10-27T23:00:00Z")]);
+        let spans = day_spans(date("2024-10-27"), date("2024-10-27"), cet_midnight);
+        let days = aggregate(&conn, Metric::ReadingMinutes, &spans).unwrap();
+        assert_eq!(days[0].value.round() as i64, 25 * 60);
+    }
+
+    #[test]
+    fn counts_reviews_per_local_day() {
+        let conn = sessions(&[]);
+        for at in [
+            "2024-01-10T22:59:00Z",
+            "2024-01-10T23:01:00Z",
+            "2024-01-11T12:00:00Z",
+        ] {
+            conn.execute(
+                "INSERT INTO card_reviews (card_id, grade, reviewed_at) VALUES ('c', 4, ?1)",
+                [utc(at).to_rfc3339()],
+            )
+            .unwrap();
+        }
+        let spans = day_spans(date("2024-01-10"), date("2024-01-11"), cet_midnight);
+        let days = aggregate(&conn, Metric::Reviews, &spans).unwrap();
+        assert_eq!(days[0].value, 1.0);
+        assert_eq!(days[1].value, 2.0);
+    }
+
+    #[test]
+    fn streaks_allow_a_grace_day() {
+        let active = [
+            date("2024-01-01"),
+            date("2024-01-02"),
+            // 3rd missed: covered by grace
+            date("2024-01-04"),
+            // 5th and 6th missed: streak broken
+            date("2024-01-07"),
+            date("2024-01-08"),
+        ];
+        let result = streaks(&active, date("2024-01-09"));
+        assert_eq!(result.longest, 3);
+        assert_eq!(result.current, 2);
+        assert!(!result.in_grace);
+
+        let result = streaks(&active, date("2024-01-10"));
+        assert_eq!(result.current, 2);
+        assert!(result.in_grace);
+
+        assert_eq!(streaks(&active, date("2024-01-11")).current, 0);
+        assert_eq!(streaks(&[], date("2024-01-11")), Streaks::default());
+    }
+
+    #[test]
+    fn forecasts_due_cards_per_day() {
+        let card = |due: Option<&str>| CardState {
+            id: "c".to_string(),
+            deck_id: "d".to_string(),
+            front: String::new(),
+            back: String::new(),
+            tags: Vec::new(),
+            hint: None,
+            source: None,
+            note_id: None,
+            created_at: String::new(),
+            repetitions: 1,
+            interval_days: 1,
+            ease_factor: srs::DEFAULT_EASE,
+            due_at: due.map(utc),
+            last_reviewed_at: None,
+            lapses: 0,
+        };
+        let cards = [
+            card(Some("2024-01-01T08:00:00Z")),
+            card(Some("2024-01-10T12:00:00Z")),
+            // 00:30 on the 11th local time
+            card(Some("2024-01-10T23:30:00Z")),
+            card(Some("2024-03-01T00:00:00Z")),
+            card(None),
+        ];
+        let spans = day_spans(date("2024-01-10"), date("2024-01-12"), cet_midnight);
+        let due: Vec<u32> = forecast(&cards, &spans).iter().map(|d| d.due).collect();
+        assert_eq!(due, [2, 1, 0]);
+    }
+}
@@ -0,0 +1,559 @@
+// Read Master Desktop - Launch at Login
+//
+// Starts the app when the user logs in, optionally hidden in the tray so
+// reminders and sync run without a window in the way. The login item is a
+// LaunchAgent plist on macOS (SMAppService can't pass arguments), a value
+// under the current user's `Run` key on Windows, and an XDG autostart
+// desktop file on Linux. Whatever is installed is the setting: status is
+// read back from it, and disabling removes it.
+//
+// A hidden launch passes `--hidden`. The main window is created invisible
+// (see tauri.conf.json) and only shown at startup when the flag is absent,
+// so it never flashes; the tray, a notification click or a second launch
+// bring it up later. Safe mode ignores the flag, since the user needs the
+// recovery screen.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{info, warn};
+use serde::Serialize;
+use tauri::{AppHandle, Runtime};
+
+use crate::safe_mode;
+
+pub const HIDDEN_FLAG: &str = "--hidden";
+
+/// Whether this process started hidden in the tray, set by `init`
+static LAUNCHED_HIDDEN: AtomicBool = AtomicBool::new(false);
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LaunchAtLoginStatus {
+    pub enabled: bool,
+    /// Whether the login launch starts hidden in the tray
+    pub start_hidden: bool,
+    /// The installed plist, registry value or desktop file
+    pub location: Option<String>,
+    /// Whether this process was started hidden
+    pub launched_hidden: bool,
+}
+
+/// The app as a login item
+struct LoginItem {
+    /// Bundle identifier, naming the plist and desktop file
+    identifier: String,
+    /// Display name, naming the registry value
+    name: String,
+}
+
+/// An installed login item
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Installed {
+    start_hidden: bool,
+    location: String,
+}
+
+// ============================================================================
+// Launch
+// ============================================================================
+
+/// Record whether this launch stays in the tray. Call after
+/// `safe_mode::init`.
+pub fn init(flag: bool) {
+    let hidden = flag && !safe_mode::is_active();
+    if hidden {
+        info!("Launched hidden in the tray");
+    } else if flag {
+        info!("Safe mode: showing the window despite {}", HIDDEN_FLAG);
+    }
+    LAUNCHED_HIDDEN.store(hidden, Ordering::Relaxed);
+}
+
+pub fn launched_hidden() -> bool {
+    LAUNCHED_HIDDEN.load(Ordering::Relaxed)
+}
+
+/// Arguments a login launch passes
+fn launch_args(start_hidden: bool) -> &'static [&'static str] {
+    if start_hidden {
+        &[HIDDEN_FLAG]
+    } else {
+        &[]
+    }
+}
+
+/// The file to launch at login. An AppImage runs from a temporary mount,
+/// so its own path is used instead.
+fn executable() -> Result<PathBuf, String> {
+    if let Some(appimage) = std::env::var_os("APPIMAGE") {
+        return Ok(PathBuf::from(appimage));
+    }
+    std::env::current_exe().map_err(|e| format!("Failed to find the app executable: {}", e))
+}
+
+// ============================================================================
+// Login Item Files
+// ============================================================================
+
+/// LaunchAgent running the app once at login
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn launch_agent_plist(label: &str, exe: &Path, args: &[&str]) -> String {
+    use crate::formats::escape;
+
+    let arguments: String = std::iter::once(exe.to_string_lossy().as_ref())
+        .chain(args.iter().copied())
+        .map(|arg| format!("        <string>{}</string>\n", escape(arg)))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+{}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>ProcessType</key>
+    <string>Interactive</string>
+</dict>
+</plist>
+"#,
+        escape(label),
+        arguments
+    )
+}
+
+/// `ProgramArguments` of a LaunchAgent plist
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn launch_agent_args(plist: &str) -> Result<Vec<String>, String> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let doc = roxmltree::Document::parse_with_options(plist, options)
+        .map_err(|e| format!("Invalid launch agent: {}", e))?;
+
+    let key = doc
+        .descendants()
+        .find(|n| n.has_tag_name("key") && n.text() == Some("ProgramArguments"));
+    let array = key
+        .and_then(|k| k.next_siblings().skip(1).find(|n| n.is_element()))
+        .filter(|n| n.has_tag_name("array"));
+    Ok(array
+        .map(|a| {
+            a.children()
+                .filter(|n| n.has_tag_name("string"))
+                .map(|n| n.text().unwrap_or_default().to_string())
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Quote an argument for a desktop entry's `Exec` key
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn exec_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || "\"'\\><~|&;$*?#()`".contains(c));
+    if plain {
+        return arg.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    // The desktop entry format unescapes backslashes once more
+    quoted.replace('\\', "\\\\")
+}
+
+/// XDG autostart entry running the app at login
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn desktop_entry(name: &str, exe: &Path, args: &[&str]) -> String {
+    let exec: Vec<String> = std::iter::once(exe.to_string_lossy().as_ref())
+        .chain(args.iter().copied())
+        .map(exec_quote)
+        .collect();
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name={}\n\
+         Comment=Start {} at login\n\
+         Exec={}\n\
+         Terminal=false\n\
+         X-GNOME-Autostart-enabled=true\n",
+        name,
+        name,
+        exec.join(" ")
+    )
+}
+
+/// Whether an autostart entry is enabled, and its `Exec` line
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn desktop_entry_exec(entry: &str) -> Option<String> {
+    let mut exec = None;
+    let mut enabled = true;
+    for line in entry.lines().map(str::trim) {
+        match line.split_once('=') {
+            Some(("Exec", value)) => exec = Some(value.to_string()),
+            Some(("Hidden", value)) => enabled &= value.trim() != "true",
+            Some(("X-GNOME-Autostart-enabled", value)) => enabled &= value.trim() != "false",
+            _ => {}
+        }
+    }
+    exec.filter(|_| enabled)
+}
+
+/// A Windows `Run` command line: the quoted executable, then arguments
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn run_command(exe: &Path, args: &[&str]) -> String {
+    std::iter::once(format!("\"{}\"", exe.display()))
+        .chain(args.iter().map(|arg| arg.to_string()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether a command line passes `--hidden` after its executable
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+fn passes_hidden(command: &str) -> bool {
+    let args = match command.trim_start().strip_prefix('"') {
+        Some(rest) => rest.split_once('"').map_or("", |(_, args)| args),
+        None => command,
+    };
+    args.split_whitespace().any(|arg| arg == HIDDEN_FLAG)
+}
+
+// ============================================================================
+// macOS
+// ============================================================================
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::{launch_agent_args, launch_agent_plist, Installed, LoginItem, HIDDEN_FLAG};
+
+    fn plist_path(item: &LoginItem) -> Result<PathBuf, String> {
+        let home = std::env::var_os("HOME").ok_or("HOME is not set")?;
+        Ok(PathBuf::from(home)
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", item.identifier)))
+    }
+
+    pub fn install(item: &LoginItem, exe: &Path, args: &[&str]) -> Result<(), String> {
+        let path = plist_path(item)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create LaunchAgents folder: {}", e))?;
+        }
+        fs::write(&path, launch_agent_plist(&item.identifier, exe, args))
+            .map_err(|e| format!("Failed to write launch agent: {}", e))
+    }
+
+    pub fn uninstall(item: &LoginItem) -> Result<(), String> {
+        match fs::remove_file(plist_path(item)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to remove launch agent: {}", e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn installed(item: &LoginItem) -> Result<Option<Installed>, String> {
+        let path = plist_path(item)?;
+        let Ok(plist) = fs::read_to_string(&path) else {
+            return Ok(None);
+        };
+        let args = launch_agent_args(&plist)?;
+        Ok(Some(Installed {
+            start_hidden: args.iter().skip(1).any(|arg| arg == HIDDEN_FLAG),
+            location: path.display().to_string(),
+        }))
+    }
+}
+
+// ============================================================================
+// Windows
+// ============================================================================
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::path::Path;
+
+    use windows::core::{w, HSTRING, PCWSTR};
+    use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+    use windows::Win32::System::Registry::{
+        RegDeleteKeyValueW, RegGetValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ, RRF_RT_REG_SZ,
+    };
+
+    use super::{passes_hidden, run_command, Installed, LoginItem};
+
+    const RUN_KEY: PCWSTR = w!("Software\\Microsoft\\Windows\\CurrentVersion\\Run");
+    const RUN_KEY_NAME: &str = "HKCU\\Software\\Microsoft\\Windows\\CurrentVersion\\Run";
+
+    pub fn install(item: &LoginItem, exe: &Path, args: &[&str]) -> Result<(), String> {
+        let command: Vec<u16> = run_command(exe, args)
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .collect();
+        unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                RUN_KEY,
+                &HSTRING::from(item.name.as_str()),
+                REG_SZ.0,
+                Some(command.as_ptr().cast()),
+                (command.len() * 2) as u32,
+            )
+        }
+        .ok()
+        .map_err(|e| format!("Failed to add the Run entry: {}", e))
+    }
+
+    pub fn uninstall(item: &LoginItem) -> Result<(), String> {
+        let result = unsafe {
+            RegDeleteKeyValueW(
+                HKEY_CURRENT_USER,
+                RUN_KEY,
+                &HSTRING::from(item.name.as_str()),
+            )
+        };
+        if result == ERROR_FILE_NOT_FOUND {
+            return Ok(());
+        }
+        result
+            .ok()
+            .map_err(|e| format!("Failed to remove the Run entry: {}", e))
+    }
+
+    pub fn installed(item: &LoginItem) -> Result<Option<Installed>, String> {
+        let name = HSTRING::from(item.name.as_str());
+        let mut size = 0u32;
+        let result = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                RUN_KEY,
+                &name,
+                RRF_RT_REG_SZ,
+                None,
+                None,
+                Some(&mut size),
+            )
+        };
+        if result == ERROR_FILE_NOT_FOUND {
+            return Ok(None);
+        }
+        result
+            .ok()
+            .map_err(|e| format!("Failed to read the Run entry: {}", e))?;
+
+        let mut buffer = vec![0u16; (size as usize).div_ceil(2)];
+        unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                RUN_KEY,
+                &name,
+                RRF_RT_REG_SZ,
+                None,
+                Some(buffer.as_mut_ptr().cast()),
+                Some(&mut size),
+            )
+        }
+        .ok()
+        .map_err(|e| format!("Failed to read the Run entry: {}", e))?;
+
+        let command = String::from_utf16_lossy(&buffer);
+        Ok(Some(Installed {
+            start_hidden: passes_hidden(command.trim_end_matches('\0')),
+            location: format!("{}\\{}", RUN_KEY_NAME, item.name),
+        }))
+    }
+}
+
+// ============================================================================
+// Linux
+// ============================================================================
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+mod platform {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::{desktop_entry, desktop_entry_exec, passes_hidden, Installed, LoginItem};
+
+    fn entry_path(item: &LoginItem) -> Result<PathBuf, String> {
+        let config = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .ok_or("Neither XDG_CONFIG_HOME nor HOME is set")?;
+        Ok(config
+            .join("autostart")
+            .join(format!("{}.desktop", item.identifier)))
+    }
+
+    pub fn install(item: &LoginItem, exe: &Path, args: &[&str]) -> Result<(), String> {
+        let path = entry_path(item)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create autostart folder: {}", e))?;
+        }
+        fs::write(&path, desktop_entry(&item.name, exe, args))
+            .map_err(|e| format!("Failed to write autostart entry: {}", e))
+    }
+
+    pub fn uninstall(item: &LoginItem) -> Result<(), String> {
+        match fs::remove_file(entry_path(item)?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to remove autostart entry: {}", e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn installed(item: &LoginItem) -> Result<Option<Installed>, String> {
+        let path = entry_path(item)?;
+        let Ok(entry) = fs::read_to_string(&path) else {
+            return Ok(None);
+        };
+        Ok(desktop_entry_exec(&entry).map(|exec| Installed {
+            start_hidden: passes_hidden(&exec),
+            location: path.display().to_string(),
+        }))
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+fn login_item<R: Runtime>(app: &AppHandle<R>) -> LoginItem {
+    let config = app.config();
+    LoginItem {
+        identifier: config.identifier.clone(),
+        name: config
+            .product_name
+            .clone()
+            .unwrap_or_else(|| "Read Master".to_string()),
+    }
+}
+
+fn status(item: &LoginItem) -> Result<LaunchAtLoginStatus, String> {
+    let installed = platform::installed(item)?;
+    Ok(LaunchAtLoginStatus {
+        enabled: installed.is_some(),
+        start_hidden: installed.as_ref().is_some_and(|i| i.start_hidden),
+        location: installed.map(|i| i.location),
+        launched_hidden: launched_hidden(),
+    })
+}
+
+/// Start the app at login, hidden in the tray when `start_hidden` is set,
+/// or remove the login item when `enabled` is off
+#[tauri::command]
+pub async fn set_launch_at_login<R: Runtime>(
+    app: AppHandle<R>,
+    enabled: bool,
+    start_hidden: bool,
+) -> Result<LaunchAtLoginStatus, String> {
+    let item = login_item(&app);
+    if enabled {
+        let exe = executable()?;
+        platform::install(&item, &exe, launch_args(start_hidden))?;
+        info!(
+            "Launching at login{}: {}",
+            if start_hidden { " (hidden)" } else { "" },
+            exe.display()
+        );
+    } else {
+        platform::uninstall(&item)?;
+        info!("No longer launching at login");
+    }
+
+    let status = status(&item)?;
+    if status.enabled != enabled {
+        warn!("Login item state is {:?} after setting {}", status, enabled);
+    }
+    Ok(status)
+}
+
+/// Whether the app starts at login, and whether hidden
+#[tauri::command]
+pub async fn get_launch_at_login_status<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<LaunchAtLoginStatus, String> {
+    status(&login_item(&app))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_launch_agent_arguments() {
+        let plist = launch_agent_plist(
+            "com.readmaster.app",
+            Path::new("/Applications/Read & Master.app/Contents/MacOS/read-master"),
+            launch_args(true),
+        );
+        assert_eq!(
+            launch_agent_args(&plist).unwrap(),
+            [
+                "/Applications/Read & Master.app/Contents/MacOS/read-master",
+                HIDDEN_FLAG
+            ]
+        );
+
+        let plist = launch_agent_plist("com.readmaster.app", Path::new("/rm"), launch_args(false));
+        assert_eq!(launch_agent_args(&plist).unwrap(), ["/rm"]);
+    }
+
+    #[test]
+    fn quotes_desktop_entry_exec_arguments() {
+        assert_eq!(exec_quote("/usr/bin/read-master"), "/usr/bin/read-master");
+        assert_eq!(
+            exec_quote("/opt/Read Master/app"),
+            "\"/opt/Read Master/app\""
+        );
+        assert_eq!(exec_quote("/tmp/$x"), "\"/tmp/\\\\$x\"");
+    }
+
+    #[test]
+    fn writes_and_reads_desktop_entries() {
+        let entry = desktop_entry(
+            "Read Master",
+            Path::new("/opt/Read Master/app"),
+            launch_args(true),
+        );
+        assert!(entry.contains("Exec=\"/opt/Read Master/app\" --hidden\n"));
+        let exec = desktop_entry_exec(&entry).unwrap();
+        assert!(passes_hidden(&exec));
+
+        let disabled = entry.replace(
+            "X-GNOME-Autostart-enabled=true",
+            "X-GNOME-Autostart-enabled=false",
+        );
+        assert_eq!(desktop_entry_exec(&disabled), None);
+    }
+
+    #[test]
+    fn finds_the_hidden_flag_after_the_executable() {
+        let exe = Path::new("C:\\Program Files\\Read Master\\--hidden.exe");
+        assert!(!passes_hidden(&run_command(exe, launch_args(false))));
+        assert!(passes_hidden(&run_command(exe, launch_args(true))));
+        assert!(passes_hidden("/usr/bin/read-master --hidden"));
+        assert!(!passes_hidden("/usr/bin/read-master"));
+    }
+}
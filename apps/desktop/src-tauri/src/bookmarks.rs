@@ -0,0 +1,881 @@
+// Read Master Desktop - Bookmarks
+//
+// Named, colored bookmarks stored in the `bookmarks` table. A position is
+// an EPUB CFI, a PDF page and character offset, or a byte offset into a
+// plain-text file, and each bookmark keeps a snippet of the ~20 words
+// around it for the bookmarks panel.
+//
+// Bookmarks record the content hash of the file they were made in. Listing
+// a book adopts bookmarks left by an earlier copy of the same file (deleted
+// and re-imported), and when the book's file has changed, re-anchors each
+// bookmark by finding its snippet in the new text.
+//
+// Bookmarks from the old `bookmarks.json` store are moved into the table
+// on startup.
+
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+use log::{info, warn};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::db::Database;
+use crate::epub::cfi::{self, Cfi};
+use crate::epub::text::document_text;
+use crate::epub::EpubArchive;
+use crate::library::{self, Book};
+use crate::profiles::ProfileStoreExt;
+use crate::sync::changelog::{self, ChangeEntity};
+use crate::{pdf, persist};
+
+/// Store the bookmarks lived in before the table
+const LEGACY_STORE_FILE: &str = "bookmarks.json";
+
+/// Words of context kept around a bookmark
+const SNIPPET_WORDS: usize = 20;
+
+/// Bytes read on each side of a plain-text position for its snippet
+const TEXT_WINDOW: u64 = 2048;
+
+/// Words around the anchor matched when the full snippet isn't found
+const FALLBACK_WORDS: usize = 4;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Where a bookmark points, by format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum BookmarkPosition {
+    #[serde(rename_all = "camelCase")]
+    EpubCfi { cfi: String },
+    /// Page index (0-based) and character offset into the page's text
+    #[serde(rename_all = "camelCase")]
+    PdfPage { page: u32, offset: usize },
+    /// Byte offset into a plain-text file
+    #[serde(rename_all = "camelCase")]
+    TextOffset { offset: u64 },
+}
+
+impl BookmarkPosition {
+    /// Locator string ordered by `compare_locators`
+    fn locator(&self) -> String {
+        match self {
+            Self::EpubCfi { cfi } => cfi.clone(),
+            Self::PdfPage { page, offset } => format!("{}:{}", page, offset),
+            Self::TextOffset { offset } => offset.to_string(),
+        }
+    }
+
+    /// Position from an old locator: a CFI, or a 1-based page number as
+    /// the page field in the reader showed it
+    fn from_legacy(locator: &str) -> Option<Self> {
+        let locator = locator.trim();
+        if locator.starts_with("epubcfi(") {
+            return Some(Self::EpubCfi {
+                cfi: locator.to_string(),
+            });
+        }
+        let page: u32 = locator.parse().ok()?;
+        Some(Self::PdfPage {
+            page: page.saturating_sub(1),
+            offset: 0,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bookmark {
+    pub id: String,
+    pub book_id: i64,
+    pub position: BookmarkPosition,
+    pub name: Option<String>,
+    /// CSS color, e.g. "#f5c518" or "yellow"
+    pub color: Option<String>,
+    /// Words around the position
+    pub snippet: Option<String>,
+    /// The book's file changed and the snippet wasn't found in it
+    pub anchor_lost: bool,
+    pub created_at: String,
+    pub updated_at: String,
+    /// Index of the bookmarked word within the snippet
+    #[serde(skip)]
+    anchor_word: Option<usize>,
+    /// Hash of the file the position refers to
+    #[serde(skip)]
+    content_hash: Option<String>,
+}
+
+/// Shape of bookmarks in the old store
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LegacyBookmark {
+    id: String,
+    locator: String,
+    label: Option<String>,
+    created_at: String,
+}
+
+// ============================================================================
+// Ordering
+// ============================================================================
+
+/// Numeric path of a locator: CFI steps (`epubcfi(/6/4!/4/2:10)` becomes
+/// `[6, 4, 4, 2, 10]`) or a bare page number. Comparing these numerically
+/// orders bookmarks by position, which plain string comparison does not.
+fn locator_path(locator: &str) -> Option<Vec<u64>> {
+    let inner = locator
+        .trim()
+        .strip_prefix("epubcfi(")
+        .and_then(|s| s.strip_suffix(')'))
+        .unwrap_or(locator.trim());
+
+    let steps: Vec<u64> = inner
+        .split(['/', '!', ':', ','])
+        .filter(|s| !s.is_empty())
+        .map(|step| {
+            // Drop id assertions like "4[chap01]" and offsets like "~1.5"
+            let digits: String = step.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse::<u64>().ok()
+        })
+        .collect::<Option<_>>()?;
+
+    (!steps.is_empty()).then_some(steps)
+}
+
+/// Order locators by reading position, falling back to string order for
+/// locators that can't be parsed
+pub fn compare_locators(a: &str, b: &str) -> Ordering {
+    match (locator_path(a), locator_path(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+// ============================================================================
+// Snippets
+// ============================================================================
+
+/// Up to `SNIPPET_WORDS` words centered on `index`, and the position of
+/// `index` within them
+fn snippet_around(words: &[&str], index: usize) -> Option<(String, usize)> {
+    if words.is_empty() {
+        return None;
+    }
+    let index = index.min(words.len() - 1);
+    let start = index
+        .saturating_sub(SNIPPET_WORDS / 2)
+        .min(words.len().saturating_sub(SNIPPET_WORDS));
+    let end = (start + SNIPPET_WORDS).min(words.len());
+    Some((words[start..end].join(" "), index - start))
+}
+
+/// Byte offset of the `index`th word of `text`
+pub fn word_byte_offset(text: &str, index: usize) -> Option<usize> {
+    let word = text.split_whitespace().nth(index)?;
+    Some(word.as_ptr() as usize - text.as_ptr() as usize)
+}
+
+/// Spine document a CFI points into, with its markup
+fn cfi_document(epub: &mut EpubArchive, cfi: &Cfi) -> Result<String, String> {
+    let href = epub
+        .spine_paths()
+        .into_iter()
+        .nth(cfi.spine_index)
+        .ok_or_else(|| format!("Spine index {} out of range", cfi.spine_index))?;
+    epub.read_string(&href)
+}
+
+/// Snippet around a position, with the anchor word's place in it
+fn snippet_for(path: &str, position: &BookmarkPosition) -> Result<Option<(String, usize)>, String> {
+    match position {
+        BookmarkPosition::EpubCfi { cfi } => {
+            let cfi = Cfi::parse(cfi).ok_or_else(|| format!("Invalid CFI: {}", cfi))?;
+            let markup = cfi_document(
+                &mut EpubArchive::open(path).map_err(|e| e.to_string())?,
+                &cfi,
+            )?;
+            let word = cfi::word_index(&markup, &cfi).unwrap_or(0);
+            let (text, _) = document_text(&markup);
+            let words: Vec<&str> = text.split_whitespace().collect();
+            Ok(snippet_around(&words, word))
+        }
+        BookmarkPosition::PdfPage { page, offset } => {
+            let file = pdf::load(path)?;
+            let text = pdf::page_text(&file, *page)?;
+            let words: Vec<&str> = text.split_whitespace().collect();
+            Ok(snippet_around(&words, cfi::words_before(&text, *offset)))
+        }
+        BookmarkPosition::TextOffset { offset } => {
+            let mut file = File::open(path).map_err(|e| format!("Failed to open book: {}", e))?;
+            let start = offset.saturating_sub(TEXT_WINDOW);
+            file.seek(SeekFrom::Start(start))
+                .map_err(|e| format!("Failed to read book: {}", e))?;
+            let mut window = Vec::new();
+            file.take((offset - start) + TEXT_WINDOW)
+                .read_to_end(&mut window)
+                .map_err(|e| format!("Failed to read book: {}", e))?;
+
+            let before = window.len().min((offset - start) as usize);
+            let text = String::from_utf8_lossy(&window);
+            let chars_before = String::from_utf8_lossy(&window[..before]).chars().count();
+            let words: Vec<&str> = text.split_whitespace().collect();
+            Ok(snippet_around(
+                &words,
+                cfi::words_before(&text, chars_before),
+            ))
+        }
+    }
+}
+
+// ============================================================================
+// Re-anchoring
+// ============================================================================
+
+/// Lowercase letters and digits only, so punctuation and case changes
+/// between editions don't break a match
+pub fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Index in `words` of the snippet's anchor word. The whole snippet is
+/// tried first, then the few words around the anchor.
+fn find_anchor(words: &[&str], snippet: &str, anchor: usize) -> Option<usize> {
+    let haystack: Vec<String> = words.iter().map(|w| normalize(w)).collect();
+    let needle: Vec<String> = snippet.split_whitespace().map(normalize).collect();
+    let anchor = anchor.min(needle.len().checked_sub(1)?);
+
+    let around = (
+        anchor.saturating_sub(FALLBACK_WORDS),
+        (anchor + FALLBACK_WORDS + 1).min(needle.len()),
+    );
+    [(0, needle.len()), around]
+        .into_iter()
+        .find_map(|(start, end)| {
+            let part = &needle[start..end];
+            haystack
+                .windows(part.len())
+                .position(|window| window == part)
+                .map(|found| found + anchor - start)
+        })
+}
+
+/// Find a bookmark's snippet in the book's current file and rebuild its
+/// position there
+fn reanchor(
+    path: &str,
+    position: &BookmarkPosition,
+    snippet: &str,
+    anchor: usize,
+) -> Result<Option<BookmarkPosition>, String> {
+    match position {
+        BookmarkPosition::EpubCfi { .. } => {
+            let mut epub = EpubArchive::open(path).map_err(|e| e.to_string())?;
+            for (index, href) in epub.spine_paths().into_iter().enumerate() {
+                let Ok(markup) = epub.read_string(&href) else {
+                    continue;
+                };
+                let (text, _) = document_text(&markup);
+                let words: Vec<&str> = text.split_whitespace().collect();
+                if let Some(word) = find_anchor(&words, snippet, anchor) {
+                    return Ok(cfi::from_word_index(&markup, index, word).map(|cfi| {
+                        BookmarkPosition::EpubCfi {
+                            cfi: cfi.to_string(),
+                        }
+                    }));
+                }
+            }
+            Ok(None)
+        }
+        BookmarkPosition::PdfPage { .. } => {
+            let file = pdf::load(path)?;
+            for page in 0..file.num_pages() {
+                let Ok(text) = pdf::page_text(&file, page) else {
+                    continue;
+                };
+                let words: Vec<&str> = text.split_whitespace().collect();
+                if let Some(word) = find_anchor(&words, snippet, anchor) {
+                    let byte = word_byte_offset(&text, word).unwrap_or(0);
+                    return Ok(Some(BookmarkPosition::PdfPage {
+                        page,
+                        offset: text[..byte].chars().count(),
+                    }));
+                }
+            }
+            Ok(None)
+        }
+        BookmarkPosition::TextOffset { .. } => {
+            let data = std::fs::read(path).map_err(|e| format!("Failed to read book: {}", e))?;
+            let text = String::from_utf8_lossy(&data);
+            let words: Vec<&str> = text.split_whitespace().collect();
+            Ok(find_anchor(&words, snippet, anchor)
+                .and_then(|word| word_byte_offset(&text, word))
+                .map(|offset| BookmarkPosition::TextOffset {
+                    offset: offset as u64,
+                }))
+        }
+    }
+}
+
+// ============================================================================
+// Database
+// ============================================================================
+
+const BOOKMARK_COLUMNS: &str = "id, book_id, position, name, color, snippet, anchor_word, \
+     content_hash, created_at, updated_at";
+
+fn bookmark_from_row(row: &Row) -> rusqlite::Result<Bookmark> {
+    let position: String = row.get(2)?;
+    let position = serde_json::from_str(&position)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(e)))?;
+
+    Ok(Bookmark {
+        id: row.get(0)?,
+        book_id: row.get(1)?,
+        position,
+        name: row.get(3)?,
+        color: row.get(4)?,
+        snippet: row.get(5)?,
+        anchor_word: row.get::<_, Option<i64>>(6)?.map(|n| n as usize),
+        content_hash: row.get(7)?,
+        anchor_lost: false,
+        created_at: row.get(8)?,
+        updated_at: row.get(9)?,
+    })
+}
+
+fn get_bookmark(conn: &Connection, id: &str) -> rusqlite::Result<Option<Bookmark>> {
+    conn.query_row(
+        &format!("SELECT {} FROM bookmarks WHERE id = ?1", BOOKMARK_COLUMNS),
+        [id],
+        bookmark_from_row,
+    )
+    .optional()
+}
+
+fn save_bookmark(conn: &Connection, bookmark: &Bookmark) -> rusqlite::Result<()> {
+    let position = serde_json::to_string(&bookmark.position)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute(
+        "INSERT INTO bookmarks (id, book_id, position, name, color, snippet, anchor_word,
+                                content_hash, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+         ON CONFLICT(id) DO UPDATE SET
+            book_id = excluded.book_id, position = excluded.position,
+            name = excluded.name, color = excluded.color, snippet = excluded.snippet,
+            anchor_word = excluded.anchor_word, content_hash = excluded.content_hash,
+            updated_at = excluded.updated_at",
+        params![
+            bookmark.id,
+            bookmark.book_id,
+            position,
+            bookmark.name,
+            bookmark.color,
+            bookmark.snippet,
+            bookmark.anchor_word.map(|n| n as i64),
+            bookmark.content_hash,
+            bookmark.created_at,
+            bookmark.updated_at,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Give this book the bookmarks of earlier copies of the same file whose
+/// records have since been deleted
+fn adopt_orphans(conn: &Connection, book_id: i64, hash: &str) -> rusqlite::Result<usize> {
+    conn.execute(
+        "UPDATE bookmarks SET book_id = ?1
+         WHERE content_hash = ?2 AND book_id != ?1
+           AND book_id NOT IN (SELECT id FROM books WHERE deleted_at IS NULL)",
+        params![book_id, hash],
+    )
+}
+
+fn book_bookmarks(conn: &Connection, book_id: i64) -> rusqlite::Result<Vec<Bookmark>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM bookmarks WHERE book_id = ?1",
+        BOOKMARK_COLUMNS
+    ))?;
+    let bookmarks = stmt
+        .query_map([book_id], bookmark_from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(bookmarks)
+}
+
+// ============================================================================
+// Operations
+// ============================================================================
+
+fn clean(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Hex colors and CSS color names; anything else would end up in a style
+/// attribute
+fn validate_color(color: &str) -> Result<(), String> {
+    let hex = color
+        .strip_prefix('#')
+        .is_some_and(|h| matches!(h.len(), 3 | 6 | 8) && h.chars().all(|c| c.is_ascii_hexdigit()));
+    let name = color.len() <= 20 && color.chars().all(|c| c.is_ascii_alphabetic());
+    if hex || name {
+        Ok(())
+    } else {
+        Err(format!("Invalid bookmark color: {}", color))
+    }
+}
+
+fn load_book<R: Runtime>(app: &AppHandle<R>, book_id: i64) -> Result<Book, String> {
+    app.state::<Database>()
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))
+}
+
+/// Point a bookmark at the book's current file, refreshing its snippet
+fn anchor_to(bookmark: &mut Bookmark, book: &Book) {
+    bookmark.content_hash = book.content_hash.clone();
+    let Some(path) = book.path.as_deref() else {
+        return;
+    };
+    match snippet_for(path, &bookmark.position) {
+        Ok(Some((snippet, anchor))) => {
+            bookmark.snippet = Some(snippet);
+            bookmark.anchor_word = Some(anchor);
+        }
+        Ok(None) => {}
+        Err(e) => warn!("No snippet for bookmark {}: {}", bookmark.id, e),
+    }
+}
+
+fn list<R: Runtime>(app: &AppHandle<R>, book_id: i64) -> Result<Vec<Bookmark>, String> {
+    let db = app.state::<Database>();
+    let book = load_book(app, book_id)?;
+
+    if let Some(hash) = &book.content_hash {
+        let adopted = db.with_conn(|conn| adopt_orphans(conn, book_id, hash))?;
+        if adopted > 0 {
+            info!(
+                "Adopted {} bookmarks for re-imported book {}",
+                adopted, book_id
+            );
+        }
+    }
+
+    let mut bookmarks = db.with_conn(|conn| book_bookmarks(conn, book_id))?;
+    for bookmark in &mut bookmarks {
+        let current = bookmark.content_hash == book.content_hash;
+        if current && bookmark.snippet.is_some() {
+            continue;
+        }
+
+        // Made against a different version of the file: find it again
+        if bookmark.content_hash.is_some() && !current {
+            let found = match (book.path.as_deref(), bookmark.snippet.as_deref()) {
+                (Some(path), Some(snippet)) => reanchor(
+                    path,
+                    &bookmark.position,
+                    snippet,
+                    bookmark.anchor_word.unwrap_or(SNIPPET_WORDS / 2),
+                )
+                .unwrap_or_else(|e| {
+                    warn!("Failed to re-anchor bookmark {}: {}", bookmark.id, e);
+                    None
+                }),
+                _ => None,
+            };
+            match found {
+                Some(position) => {
+                    info!("Re-anchored bookmark {}", bookmark.id);
+                    bookmark.position = position;
+                }
+                None => {
+                    bookmark.anchor_lost = true;
+                    continue;
+                }
+            }
+        }
+
+        anchor_to(bookmark, &book);
+        db.with_conn(|conn| save_bookmark(conn, bookmark))?;
+    }
+
+    bookmarks.sort_by(|a, b| compare_locators(&a.position.locator(), &b.position.locator()));
+    Ok(bookmarks)
+}
+
+/// Move bookmarks from the old store into the table
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    let Ok(store) = app.profile_store(LEGACY_STORE_FILE) else {
+        return;
+    };
+    let entries = store.entries();
+    if entries.is_empty() {
+        return;
+    }
+
+    let db = app.state::<Database>();
+    let mut moved = 0;
+    for (book_id, value) in entries {
+        let Ok(book_id) = book_id.parse::<i64>() else {
+            continue;
+        };
+        let legacy: Vec<LegacyBookmark> = match serde_json::from_value(value) {
+            Ok(legacy) => legacy,
+            Err(e) => {
+                warn!("Skipping corrupt bookmarks for book {}: {}", book_id, e);
+                continue;
+            }
+        };
+
+        for old in legacy {
+            let Some(position) = BookmarkPosition::from_legacy(&old.locator) else {
+                warn!("Skipping bookmark with unknown locator: {}", old.locator);
+                continue;
+            };
+            // Snippet and hash are filled in the first time the book is listed
+            let bookmark = Bookmark {
+                id: old.id,
+                book_id,
+                position,
+                name: clean(old.label),
+                color: None,
+                snippet: None,
+                anchor_lost: false,
+                updated_at: old.created_at.clone(),
+                created_at: old.created_at,
+                anchor_word: None,
+                content_hash: None,
+            };
+            match db.with_conn(|conn| save_bookmark(conn, &bookmark)) {
+                Ok(()) => moved += 1,
+                Err(e) => warn!("Failed to move bookmark {}: {}", bookmark.id, e),
+            }
+        }
+    }
+
+    info!("Moved {} bookmarks from {}", moved, LEGACY_STORE_FILE);
+    store.clear();
+    persist::mark_dirty(app, LEGACY_STORE_FILE);
+}
+
+// ============================================================================
+// Sync
+// ============================================================================
+
+/// A bookmark as a sync record. Records are shared between devices by
+/// content hash, so the local book id is left out.
+fn sync_record(bookmark: &Bookmark) -> serde_json::Result<Value> {
+    let mut record = serde_json::to_value(bookmark)?;
+    if let Value::Object(fields) = &mut record {
+        fields.remove("bookId");
+        fields.remove("anchorLost");
+        fields.insert("anchorWord".to_string(), bookmark.anchor_word.into());
+    }
+    Ok(record)
+}
+
+/// A book's bookmarks as records for a sync bundle
+pub fn sync_records(conn: &Connection, book_id: i64) -> rusqlite::Result<Vec<Value>> {
+    book_bookmarks(conn, book_id)?
+        .iter()
+        .map(|bookmark| {
+            sync_record(bookmark).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+        })
+        .collect()
+}
+
+/// Save a sync record as one of `book`'s bookmarks, returning its id
+pub fn apply_sync_record(
+    conn: &Connection,
+    book: &Book,
+    record: &Value,
+) -> rusqlite::Result<String> {
+    let mut record = record.clone();
+    if let Value::Object(fields) = &mut record {
+        fields.remove("bookHash");
+        fields.insert("bookId".to_string(), book.id.into());
+        fields.insert("anchorLost".to_string(), false.into());
+    }
+    let anchor_word = record
+        .get("anchorWord")
+        .and_then(Value::as_u64)
+        .map(|n| n as usize);
+
+    let mut bookmark: Bookmark = serde_json::from_value(record)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    bookmark.anchor_word = anchor_word;
+    bookmark.content_hash = book.content_hash.clone();
+    save_bookmark(conn, &bookmark)?;
+    Ok(bookmark.id)
+}
+
+/// Replace a book's bookmarks with merged sync records
+pub fn apply_sync_records(
+    conn: &Connection,
+    book: &Book,
+    records: &[Value],
+) -> rusqlite::Result<()> {
+    let mut keep = Vec::with_capacity(records.len());
+    for record in records {
+        keep.push(apply_sync_record(conn, book, record)?);
+    }
+
+    for existing in book_bookmarks(conn, book.id)? {
+        if !keep.contains(&existing.id) {
+            remove(conn, &existing.id)?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete a bookmark, returning whether it existed
+pub fn remove(conn: &Connection, id: &str) -> rusqlite::Result<bool> {
+    Ok(conn.execute("DELETE FROM bookmarks WHERE id = ?1", [id])? > 0)
+}
+
+// ============================================================================
+// Change Log
+// ============================================================================
+
+/// A sync record as a change log record, with its book named by hash
+fn with_book_hash(mut record: Value, book: &Book) -> Value {
+    if let Value::Object(fields) = &mut record {
+        fields.insert("bookHash".to_string(), book.content_hash.clone().into());
+    }
+    record
+}
+
+fn record_id(record: &Value) -> Option<&str> {
+    record.get("id").and_then(Value::as_str)
+}
+
+/// Log a bookmark created or changed here
+fn record_change<R: Runtime>(app: &AppHandle<R>, bookmark: &Bookmark, book: &Book) {
+    let data = sync_record(bookmark)
+        .ok()
+        .map(|record| with_book_hash(record, book));
+    changelog::record(app, ChangeEntity::Bookmark, &bookmark.id, data);
+}
+
+/// Log how merged sync records changed a book's bookmarks, given its
+/// records from before the merge
+pub fn record_sync_changes<R: Runtime>(
+    app: &AppHandle<R>,
+    book: &Book,
+    before: &[Value],
+    after: &[Value],
+) {
+    for record in before {
+        let Some(id) = record_id(record) else {
+            continue;
+        };
+        if !after.iter().any(|a| record_id(a) == Some(id)) {
+            changelog::record(app, ChangeEntity::Bookmark, id, None);
+        }
+    }
+    for record in after {
+        let Some(id) = record_id(record) else {
+            continue;
+        };
+        if !before.contains(record) {
+            let data = with_book_hash(record.clone(), book);
+            changelog::record(app, ChangeEntity::Bookmark, id, Some(data));
+        }
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Add a bookmark, returning it with its snippet
+#[tauri::command]
+pub async fn add_bookmark<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: i64,
+    position: BookmarkPosition,
+    name: Option<String>,
+    color: Option<String>,
+) -> Result<Bookmark, String> {
+    let color = clean(color);
+    if let Some(color) = &color {
+        validate_color(color)?;
+    }
+
+    info!("Adding bookmark to {}: {}", book_id, position.locator());
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let book = load_book(&app, book_id)?;
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut bookmark = Bookmark {
+            id: uuid::Uuid::new_v4().to_string(),
+            book_id,
+            position,
+            name: clean(name),
+            color,
+            snippet: None,
+            anchor_lost: false,
+            created_at: now.clone(),
+            updated_at: now,
+            anchor_word: None,
+            content_hash: None,
+        };
+        anchor_to(&mut bookmark, &book);
+
+        app.state::<Database>()
+            .with_conn(|conn| save_bookmark(conn, &bookmark))?;
+        record_change(&app, &bookmark, &book);
+        Ok(bookmark)
+    })
+    .await
+    .map_err(|e| format!("Bookmark task failed: {}", e))?
+}
+
+/// List a book's bookmarks in reading order, re-anchoring any made against
+/// an earlier version of its file
+#[tauri::command]
+pub async fn list_bookmarks<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: i64,
+) -> Result<Vec<Bookmark>, String> {
+    tauri::async_runtime::spawn_blocking(move || list(&app, book_id))
+        .await
+        .map_err(|e| format!("Bookmark task failed: {}", e))?
+}
+
+/// Rename, recolor, or move a bookmark. Omitted fields are left alone; an
+/// empty name or color clears it.
+#[tauri::command]
+pub async fn update_bookmark<R: Runtime>(
+    app: AppHandle<R>,
+    id: String,
+    name: Option<String>,
+    color: Option<String>,
+    position: Option<BookmarkPosition>,
+) -> Result<Bookmark, String> {
+    if let Some(color) = clean(color.clone()) {
+        validate_color(&color)?;
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let db = app.state::<Database>();
+        let mut bookmark = db
+            .with_conn(|conn| get_bookmark(conn, &id))?
+            .ok_or_else(|| format!("Bookmark not found: {}", id))?;
+
+        if let Some(name) = name {
+            bookmark.name = clean(Some(name));
+        }
+        if let Some(color) = color {
+            bookmark.color = clean(Some(color));
+        }
+        let book = load_book(&app, bookmark.book_id)?;
+        if let Some(position) = position {
+            bookmark.position = position;
+            anchor_to(&mut bookmark, &book);
+        }
+        bookmark.updated_at = chrono::Utc::now().to_rfc3339();
+
+        info!("Updating bookmark: {}", id);
+        db.with_conn(|conn| save_bookmark(conn, &bookmark))?;
+        record_change(&app, &bookmark, &book);
+        Ok(bookmark)
+    })
+    .await
+    .map_err(|e| format!("Bookmark task failed: {}", e))?
+}
+
+/// Delete a bookmark by id
+#[tauri::command]
+pub async fn delete_bookmark<R: Runtime>(app: AppHandle<R>, id: String) -> Result<(), String> {
+    info!("Deleting bookmark: {}", id);
+
+    let deleted = app
+        .state::<Database>()
+        .with_conn(|conn| remove(conn, &id))?;
+    if !deleted {
+        return Err(format!("Bookmark not found: {}", id));
+    }
+    changelog::record(&app, ChangeEntity::Bookmark, &id, None);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_serialize_tagged() {
+        let position = BookmarkPosition::PdfPage {
+            page: 3,
+            offset: 120,
+        };
+        assert_eq!(
+            serde_json::to_value(&position).unwrap(),
+            serde_json::json!({ "kind": "pdfPage", "page": 3, "offset": 120 })
+        );
+        assert_eq!(
+            BookmarkPosition::from_legacy("12"),
+            Some(BookmarkPosition::PdfPage {
+                page: 11,
+                offset: 0
+            })
+        );
+    }
+
+    #[test]
+    fn snippets_center_on_the_position() {
+        let words: Vec<String> = (0..100).map(|n| format!("w{}", n)).collect();
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+        let (snippet, anchor) = snippet_around(&words, 50).unwrap();
+        assert_eq!(snippet.split_whitespace().count(), SNIPPET_WORDS);
+        assert_eq!(snippet.split_whitespace().nth(anchor), Some("w50"));
+
+        let (snippet, anchor) = snippet_around(&words, 98).unwrap();
+        assert!(snippet.ends_with("w99"));
+        assert_eq!(snippet.split_whitespace().nth(anchor), Some("w98"));
+    }
+
+    #[test]
+    fn reanchors_by_snippet_after_edits() {
+        let old = "the quick brown fox jumps over the lazy dog";
+        // A new edition with a preface and changed punctuation and case
+        let new: Vec<&str> = "Preface text. The quick, brown fox jumps over the lazy dog!"
+            .split_whitespace()
+            .collect();
+        // "fox" is word 3 of the snippet, word 5 of the new text
+        assert_eq!(find_anchor(&new, old, 3), Some(5));
+
+        // Only the words near the anchor survived a revision
+        let old: Vec<String> = (0..20).map(|n| format!("a{}", n)).collect();
+        let revised: Vec<&str> = "x y a6 a7 a8 a9 a10 a11 a12 a13 a14 z"
+            .split_whitespace()
+            .collect();
+        assert_eq!(find_anchor(&revised, &old.join(" "), 10), Some(6));
+        assert_eq!(find_anchor(&["nothing", "here"], &old.join(" "), 10), None);
+    }
+
+    #[test]
+    fn orders_locators_numerically() {
+        assert_eq!(
+            compare_locators("epubcfi(/6/4!/4/10)", "epubcfi(/6/12!/4/2)"),
+            Ordering::Less
+        );
+        assert_eq!(compare_locators("2:40", "10:0"), Ordering::Less);
+    }
+}
@@ -0,0 +1,1126 @@
+// Read Master Desktop - Anki Import
+//
+// Flashcard import from Anki deck packages (.apkg), with media and scheduling.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use regex::{Captures, Regex};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+use zip::ZipArchive;
+
+use crate::db::Database;
+use crate::epub::limits::{self, ArchiveLimits};
+use crate::srs::{self, FlashCard, DEFAULT_EASE, MIN_EASE};
+use crate::{activity, profiles};
+
+/// Collection files, newest format first. `collection.anki21b` is
+/// zstd-compressed, and so are its media list and files.
+const COLLECTIONS: [&str; 3] = [
+    "collection.anki21b",
+    "collection.anki21",
+    "collection.anki2",
+];
+
+const MEDIA_LIST: &str = "media";
+
+/// Folder in the profile's data directory holding card pictures and sounds
+pub const MEDIA_DIR: &str = "card-media";
+
+/// Replacement for the deletion being asked about
+const CLOZE_BLANK: &str = "[...]";
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Anki's `original_stock_kind` for image occlusion note types
+const IMAGE_OCCLUSION_KIND: u64 = 6;
+
+/// Anki separates the fields of a note with this
+const FIELD_SEPARATOR: char = '\u{1f}';
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Which note type fields (by name) fill each card field
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnkiFieldMapping {
+    pub front: String,
+    #[serde(default)]
+    pub back: Option<String>,
+    #[serde(default)]
+    pub hint: Option<String>,
+    /// Fields added to the back, after it
+    #[serde(default)]
+    pub extras: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnkiImportOptions {
+    /// Deck every card goes into; by default cards keep their Anki deck's
+    /// name
+    #[serde(default)]
+    pub deck_id: Option<String>,
+    /// Mappings by note type name
+    #[serde(default)]
+    pub field_mappings: HashMap<String, AnkiFieldMapping>,
+    /// Keep intervals, ease, due dates, lapses, and review history
+    #[serde(default)]
+    pub include_scheduling: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedNoteType {
+    pub name: String,
+    pub notes: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnkiImportReport {
+    pub notes: usize,
+    pub imported: usize,
+    /// Cards already imported before
+    pub duplicates: usize,
+    pub media: usize,
+    /// Review log entries carried over
+    pub reviews: usize,
+    /// Notes of the note types below
+    pub skipped_notes: usize,
+    pub skipped_note_types: Vec<SkippedNoteType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct NoteType {
+    name: String,
+    cloze: bool,
+    /// A kind no field mapping can express
+    unsupported: bool,
+    /// Field names in order
+    fields: Vec<String>,
+}
+
+/// Field indexes a mapping resolved to for one note type
+#[derive(Debug, Clone, PartialEq)]
+struct Resolved {
+    front: usize,
+    back: Option<usize>,
+    hint: Option<usize>,
+    extras: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct AnkiCard {
+    id: i64,
+    note_id: i64,
+    deck_id: i64,
+    /// Template, or cloze number - 1
+    ord: u32,
+    /// 0 new, 1 learning, 2 review, 3 relearning
+    kind: u8,
+    due: i64,
+    /// Days, or negative seconds while learning
+    interval: i64,
+    /// Ease in permille
+    factor: i64,
+    reps: u32,
+    lapses: u32,
+}
+
+// ============================================================================
+// Protobuf
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ProtoValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos)?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Top-level fields of a protobuf message; fixed-width ones are skipped
+fn proto_fields(buf: &[u8]) -> Vec<(u64, ProtoValue<'_>)> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let Some(key) = read_varint(buf, &mut pos) else {
+            break;
+        };
+        let value = match key & 7 {
+            0 => read_varint(buf, &mut pos).map(ProtoValue::Varint),
+            1 => {
+                pos += 8;
+                None
+            }
+            2 => read_varint(buf, &mut pos).and_then(|len| {
+                let end = pos.checked_add(usize::try_from(len).ok()?)?;
+                let bytes = buf.get(pos..end)?;
+                pos = end;
+                Some(ProtoValue::Bytes(bytes))
+            }),
+            5 => {
+                pos += 4;
+                None
+            }
+            _ => break,
+        };
+        if let Some(value) = value {
+            fields.push((key >> 3, value));
+        }
+    }
+    fields
+}
+
+// ============================================================================
+// Package
+// ============================================================================
+
+/// Unpack zstd-compressed entries, capped like any other entry since the
+/// archive's sizes only cover the outer compression
+fn decompress(data: Vec<u8>, name: &str, limits: &ArchiveLimits) -> Result<Vec<u8>, String> {
+    if !data.starts_with(&ZSTD_MAGIC) {
+        return Ok(data);
+    }
+    let decoder = zstd::stream::Decoder::new(data.as_slice())
+        .map_err(|e| format!("Failed to decompress package data: {}", e))?;
+    limits::read_limited(decoder, name, 0, limits).map_err(|e| e.to_string())
+}
+
+fn read_entry(zip: &mut ZipArchive<File>, name: &str) -> Result<Option<Vec<u8>>, String> {
+    let mut entry = match zip.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(format!("Failed to read {}: {}", name, e)),
+    };
+    let limits = limits::current();
+    let size = entry.size();
+    let data = limits::read_limited(&mut entry, name, size, &limits).map_err(|e| e.to_string())?;
+    decompress(data, name, &limits).map(Some)
+}
+
+/// Media file names by package entry name. Older packages list them as
+/// JSON, newer ones as a protobuf list whose entries are numbered in order.
+fn media_list(data: &[u8]) -> HashMap<String, String> {
+    if let Ok(map) = serde_json::from_slice::<HashMap<String, String>>(data) {
+        return map;
+    }
+    proto_fields(data)
+        .into_iter()
+        .filter_map(|(field, value)| match (field, value) {
+            (1, ProtoValue::Bytes(entry)) => Some(entry),
+            _ => None,
+        })
+        .enumerate()
+        .filter_map(|(index, entry)| {
+            let mut name = None;
+            let mut zip_name = index.to_string();
+            for (field, value) in proto_fields(entry) {
+                match (field, value) {
+                    (1, ProtoValue::Bytes(bytes)) => {
+                        name = Some(String::from_utf8_lossy(bytes).into_owned())
+                    }
+                    (255, ProtoValue::Varint(legacy)) => zip_name = legacy.to_string(),
+                    _ => {}
+                }
+            }
+            Some((zip_name, name?))
+        })
+        .collect()
+}
+
+/// Keep only the file name part, so entries can't escape the media folder
+fn safe_media_name(name: &str) -> Option<&str> {
+    Path::new(name)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .filter(|n| !n.starts_with('.'))
+}
+
+/// Name for a media file that doesn't clash with a different file
+/// already in `dir`
+fn free_media_name(dir: &Path, name: &str, data: &[u8]) -> String {
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let ext = path.extension().and_then(|e| e.to_str());
+
+    let mut candidate = name.to_string();
+    for n in 2.. {
+        match fs::read(dir.join(&candidate)) {
+            Ok(existing) if existing != data => {}
+            _ => break,
+        }
+        candidate = match ext {
+            Some(ext) => format!("{}-{}.{}", stem, n, ext),
+            None => format!("{}-{}", stem, n),
+        };
+    }
+    candidate
+}
+
+/// Copy the package's media into `dir`. Returns stored names by the names
+/// notes use.
+fn import_media(zip: &mut ZipArchive<File>, dir: &Path) -> Result<HashMap<String, String>, String> {
+    let Some(list) = read_entry(zip, MEDIA_LIST)? else {
+        return Ok(HashMap::new());
+    };
+    let list = media_list(&list);
+    if list.is_empty() {
+        return Ok(HashMap::new());
+    }
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create media folder: {}", e))?;
+
+    let mut stored = HashMap::new();
+    for (entry, name) in list {
+        let Some(safe) = safe_media_name(&name) else {
+            warn!("Skipping media file {:?}", name);
+            continue;
+        };
+        let data = match read_entry(zip, &entry) {
+            Ok(Some(data)) => data,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!("Skipping media file {}: {}", name, e);
+                continue;
+            }
+        };
+        let target = free_media_name(dir, safe, &data);
+        if !dir.join(&target).exists() {
+            fs::write(dir.join(&target), &data)
+                .map_err(|e| format!("Failed to save {}: {}", target, e))?;
+        }
+        stored.insert(name, target);
+    }
+    Ok(stored)
+}
+
+/// Write the collection database to a temporary file and open it
+fn open_collection(zip: &mut ZipArchive<File>) -> Result<(Connection, PathBuf), String> {
+    for name in COLLECTIONS {
+        let Some(data) = read_entry(zip, name)? else {
+            continue;
+        };
+        let path = std::env::temp_dir().join(format!("rm-anki-{}.db", uuid::Uuid::new_v4()));
+        fs::write(&path, data).map_err(|e| format!("Failed to unpack {}: {}", name, e))?;
+        return match Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY) {
+            Ok(conn) => Ok((conn, path)),
+            Err(e) => {
+                let _ = fs::remove_file(&path);
+                Err(format!("Failed to open Anki collection: {}", e))
+            }
+        };
+    }
+    Err("Not an Anki package: no collection found".to_string())
+}
+
+// ============================================================================
+// Collection
+// ============================================================================
+
+fn has_table(conn: &Connection, name: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|row| row.is_some())
+}
+
+/// Note types of a collection, from the `notetypes` table of newer
+/// collections or the `models` JSON of older ones
+fn note_types(conn: &Connection) -> rusqlite::Result<HashMap<i64, NoteType>> {
+    let mut types = HashMap::new();
+
+    if has_table(conn, "notetypes")? {
+        let mut fields: HashMap<i64, Vec<(u32, String)>> = HashMap::new();
+        let mut stmt = conn.prepare("SELECT ntid, ord, name FROM fields")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        for row in rows {
+            let (ntid, ord, name): (i64, u32, String) = row?;
+            fields.entry(ntid).or_default().push((ord, name));
+        }
+
+        let mut stmt = conn.prepare("SELECT id, name, config FROM notetypes")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Vec<u8>>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (id, name, config) = row?;
+            let config = proto_fields(&config);
+            let varint = |number: u64| {
+                config.iter().find_map(|(field, value)| match value {
+                    ProtoValue::Varint(v) if *field == number => Some(*v),
+                    _ => None,
+                })
+            };
+            let kind = varint(1).unwrap_or(0);
+            let mut names = fields.remove(&id).unwrap_or_default();
+            names.sort();
+            types.insert(
+                id,
+                NoteType {
+                    name,
+                    cloze: kind == 1,
+                    unsupported: kind > 1 || varint(9) == Some(IMAGE_OCCLUSION_KIND),
+                    fields: names.into_iter().map(|(_, name)| name).collect(),
+                },
+            );
+        }
+        return Ok(types);
+    }
+
+    let models: String = conn.query_row("SELECT models FROM col", [], |row| row.get(0))?;
+    let models: serde_json::Value = serde_json::from_str(&models).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+    for (id, model) in models.as_object().into_iter().flatten() {
+        let Ok(id) = id.parse() else {
+            continue;
+        };
+        let mut fields: Vec<(u64, String)> = model["flds"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|f| Some((f["ord"].as_u64()?, f["name"].as_str()?.to_string())))
+            .collect();
+        fields.sort();
+        let kind = model["type"].as_u64().unwrap_or(0);
+        types.insert(
+            id,
+            NoteType {
+                name: model["name"].as_str().unwrap_or_default().to_string(),
+                cloze: kind == 1,
+                unsupported: kind > 1,
+                fields: fields.into_iter().map(|(_, name)| name).collect(),
+            },
+        );
+    }
+    Ok(types)
+}
+
+/// Deck names by id; nested decks are joined with `::`
+fn deck_names(conn: &Connection) -> rusqlite::Result<HashMap<i64, String>> {
+    if has_table(conn, "decks")? {
+        let mut stmt = conn.prepare("SELECT id, name FROM decks")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get::<_, String>(1)?.replace(FIELD_SEPARATOR, "::"),
+            ))
+        })?;
+        return rows.collect();
+    }
+
+    let decks: String = conn.query_row("SELECT decks FROM col", [], |row| row.get(0))?;
+    let decks: serde_json::Value = serde_json::from_str(&decks).unwrap_or_default();
+    Ok(decks
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(id, deck)| Some((id.parse().ok()?, deck["name"].as_str()?.to_string())))
+        .collect())
+}
+
+/// Cards by note, in template order. Cards in filtered decks are put
+/// back in their home deck.
+fn cards_by_note(conn: &Connection) -> rusqlite::Result<HashMap<i64, Vec<AnkiCard>>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, nid, did, ord, type, due, ivl, factor, reps, lapses, odue, odid
+         FROM cards ORDER BY nid, ord",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let (odue, odid): (i64, i64) = (row.get(10)?, row.get(11)?);
+        Ok(AnkiCard {
+            id: row.get(0)?,
+            note_id: row.get(1)?,
+            deck_id: if odid != 0 { odid } else { row.get(2)? },
+            ord: row.get(3)?,
+            kind: row.get(4)?,
+            due: if odid != 0 && odue != 0 {
+                odue
+            } else {
+                row.get(5)?
+            },
+            interval: row.get(6)?,
+            factor: row.get(7)?,
+            reps: row.get(8)?,
+            lapses: row.get(9)?,
+        })
+    })?;
+
+    let mut cards: HashMap<i64, Vec<AnkiCard>> = HashMap::new();
+    for card in rows {
+        let card = card?;
+        cards.entry(card.note_id).or_default().push(card);
+    }
+    Ok(cards)
+}
+
+// ============================================================================
+// Cards
+// ============================================================================
+
+fn field_index(note_type: &NoteType, name: &str) -> Option<usize> {
+    note_type.fields.iter().position(|f| f == name)
+}
+
+/// Field indexes for a note type, or None when it can't be imported (like
+/// image occlusion). Without a mapping the first field is the front and the
+/// second the back.
+fn resolve(note_type: &NoteType, mapping: Option<&AnkiFieldMapping>) -> Option<Resolved> {
+    if note_type.unsupported {
+        return None;
+    }
+    let resolved = match mapping {
+        Some(mapping) => Resolved {
+            front: field_index(note_type, &mapping.front)?,
+            back: match &mapping.back {
+                Some(back) => Some(field_index(note_type, back)?),
+                None => None,
+            },
+            hint: match &mapping.hint {
+                Some(hint) => Some(field_index(note_type, hint)?),
+                None => None,
+            },
+            extras: mapping
+                .extras
+                .iter()
+                .map(|extra| field_index(note_type, extra))
+                .collect::<Option<_>>()?,
+        },
+        None if note_type.fields.is_empty() => return None,
+        None => Resolved {
+            front: 0,
+            back: (note_type.fields.len() > 1).then_some(1),
+            hint: None,
+            extras: (2..note_type.fields.len()).collect(),
+        },
+    };
+    // A standard card needs something on its back
+    if !note_type.cloze && resolved.back.is_none() && resolved.extras.is_empty() {
+        return None;
+    }
+    Some(resolved)
+}
+
+fn cloze_regex() -> &'static Regex {
+    static CLOZE: OnceLock<Regex> = OnceLock::new();
+    CLOZE.get_or_init(|| Regex::new(r"(?s)\{\{c(\d+)::(.*?)(?:::(.*?))?\}\}").expect("valid regex"))
+}
+
+/// Deletion numbers used in a cloze field
+fn cloze_numbers(text: &str) -> BTreeSet<u32> {
+    cloze_regex()
+        .captures_iter(text)
+        .filter_map(|c| c[1].parse().ok())
+        .collect()
+}
+
+/// Cloze text with deletion `number` blanked out (or its hint shown), or
+/// revealed; other deletions always show their text
+fn cloze_side(text: &str, number: u32, reveal: bool) -> String {
+    cloze_regex()
+        .replace_all(text, |c: &Captures| {
+            let asked = c[1].parse::<u32>().ok() == Some(number);
+            match c.get(3) {
+                _ if !asked || reveal => c[2].to_string(),
+                Some(hint) => format!("[{}]", hint.as_str()),
+                None => CLOZE_BLANK.to_string(),
+            }
+        })
+        .into_owned()
+}
+
+fn decode_entities(text: &str) -> String {
+    static ENTITY: OnceLock<Regex> = OnceLock::new();
+    let entity = ENTITY
+        .get_or_init(|| Regex::new(r"&(#x[0-9a-fA-F]+|#[0-9]+|[a-zA-Z]+);").expect("valid regex"));
+    entity
+        .replace_all(text, |c: &Captures| {
+            let name = &c[1];
+            let decoded = match name {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ if name.starts_with("#x") => u32::from_str_radix(&name[2..], 16)
+                    .ok()
+                    .and_then(char::from_u32),
+                _ if name.starts_with('#') => name[1..].parse().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            decoded.map_or_else(|| c[0].to_string(), String::from)
+        })
+        .into_owned()
+}
+
+/// Card text of an Anki field: line breaks kept, pictures and sounds as
+/// `[image:name]` / `[sound:name]` under their stored names, other markup
+/// dropped
+fn field_text(html: &str, media: &HashMap<String, String>) -> String {
+    static BREAK: OnceLock<Regex> = OnceLock::new();
+    static IMAGE: OnceLock<Regex> = OnceLock::new();
+    static SOUND: OnceLock<Regex> = OnceLock::new();
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    static BLANK_LINES: OnceLock<Regex> = OnceLock::new();
+
+    let stored = |name: &str| {
+        let name = decode_entities(name);
+        media.get(&name).cloned().unwrap_or(name)
+    };
+
+    let text = IMAGE
+        .get_or_init(|| {
+            Regex::new(r#"(?i)<img[^>]*?\ssrc\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))[^>]*>"#)
+                .expect("valid regex")
+        })
+        .replace_all(html, |c: &Captures| {
+            let src = c
+                .get(1)
+                .or(c.get(2))
+                .or(c.get(3))
+                .map_or("", |m| m.as_str());
+            format!("[image:{}]", stored(src))
+        });
+    let text = SOUND
+        .get_or_init(|| Regex::new(r"\[sound:([^\]]+)\]").expect("valid regex"))
+        .replace_all(&text, |c: &Captures| format!("[sound:{}]", stored(&c[1])));
+    let text = BREAK
+        .get_or_init(|| Regex::new(r"(?i)<br\s*/?>|</(div|p|li|tr|h[1-6])>").expect("valid regex"))
+        .replace_all(&text, "\n");
+    let text = TAG
+        .get_or_init(|| Regex::new(r"(?s)<[^>]*>").expect("valid regex"))
+        .replace_all(&text, "");
+    let text = decode_entities(&text);
+    let text: Vec<&str> = text.lines().map(str::trim_end).collect();
+    BLANK_LINES
+        .get_or_init(|| Regex::new(r"\n{3,}").expect("valid regex"))
+        .replace_all(text.join("\n").trim(), "\n\n")
+        .into_owned()
+}
+
+/// Front, back, and hint of the card for template (or cloze) `ord`. A
+/// standard note makes one card, from its first template; a cloze note
+/// makes one per deletion, with that deletion blanked out.
+fn card_faces(
+    resolved: &Resolved,
+    cloze: bool,
+    fields: &[&str],
+    ord: u32,
+    media: &HashMap<String, String>,
+) -> (String, String, Option<String>) {
+    let field = |index: usize| fields.get(index).copied().unwrap_or_default();
+    let text = |html: &str| field_text(html, media);
+
+    let (front, answer) = if cloze {
+        let source = field(resolved.front);
+        (
+            text(&cloze_side(source, ord + 1, false)),
+            Some(text(&cloze_side(source, ord + 1, true))),
+        )
+    } else {
+        (text(field(resolved.front)), None)
+    };
+
+    let back = answer
+        .into_iter()
+        .chain(resolved.back.map(|index| text(field(index))))
+        .chain(resolved.extras.iter().map(|&index| text(field(index))))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let hint = resolved
+        .hint
+        .map(|index| text(field(index)))
+        .filter(|hint| !hint.is_empty());
+    (front, back, hint)
+}
+
+fn timestamp(secs: i64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(secs, 0)
+}
+
+/// Carry an Anki card's scheduling over. `created` is the collection's
+/// creation time, which review due dates count days from.
+fn apply_schedule(card: &mut FlashCard, anki: &AnkiCard, created: i64) {
+    card.lapses = anki.lapses;
+    if anki.factor > 0 {
+        card.ease_factor = (anki.factor as f64 / 1000.0).max(MIN_EASE);
+    }
+    match anki.kind {
+        // Review: due counts days from the collection's creation
+        2 => {
+            card.interval_days = u32::try_from(anki.interval.max(1)).unwrap_or(u32::MAX);
+            card.repetitions = anki
+                .reps
+                .saturating_sub(anki.lapses)
+                .max(if card.interval_days >= 6 { 2 } else { 1 });
+            card.due_at = timestamp(created + anki.due * SECONDS_PER_DAY);
+        }
+        // (Re)learning: due is a timestamp, or a day number after a day
+        1 | 3 => {
+            card.interval_days = u32::try_from(anki.interval.max(0)).unwrap_or(0);
+            card.repetitions = 0;
+            card.due_at = if anki.due > 1_000_000_000 {
+                timestamp(anki.due)
+            } else {
+                timestamp(created + anki.due * SECONDS_PER_DAY)
+            };
+        }
+        _ => {}
+    }
+}
+
+/// Our 0-5 grade for an Anki answer button (1 Again to 4 Easy)
+fn grade(ease: u8) -> Option<u8> {
+    match ease {
+        1 => Some(1),
+        2 => Some(3),
+        3 => Some(4),
+        4 => Some(5),
+        _ => None,
+    }
+}
+
+/// Review history of the imported cards as (card id, grade, time)
+fn review_log(
+    conn: &Connection,
+    cards: &HashMap<i64, String>,
+) -> rusqlite::Result<Vec<(String, u8, String)>> {
+    let mut stmt = conn.prepare("SELECT id, cid, ease FROM revlog ORDER BY id")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, u8>(2)?,
+        ))
+    })?;
+
+    let mut log = Vec::new();
+    for row in rows {
+        let (at, cid, ease) = row?;
+        let (Some(card_id), Some(grade), Some(at)) = (
+            cards.get(&cid),
+            grade(ease),
+            DateTime::from_timestamp_millis(at),
+        ) else {
+            continue;
+        };
+        log.push((card_id.clone(), grade, at.to_rfc3339()));
+    }
+    Ok(log)
+}
+
+// ============================================================================
+// Import
+// ============================================================================
+
+fn sql_error(e: rusqlite::Error) -> String {
+    format!("Failed to read Anki collection: {}", e)
+}
+
+fn import<R: Runtime>(
+    app: &AppHandle<R>,
+    path: &str,
+    options: &AnkiImportOptions,
+) -> Result<AnkiImportReport, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let mut zip = ZipArchive::new(file).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    limits::check_archive(&mut zip, &limits::current()).map_err(|e| e.to_string())?;
+
+    let (conn, db_path) = open_collection(&mut zip)?;
+    let result = import_collection(app, &mut zip, &conn, options);
+    drop(conn);
+    let _ = fs::remove_file(&db_path);
+    result
+}
+
+fn import_collection<R: Runtime>(
+    app: &AppHandle<R>,
+    zip: &mut ZipArchive<File>,
+    conn: &Connection,
+    options: &AnkiImportOptions,
+) -> Result<AnkiImportReport, String> {
+    let types = note_types(conn).map_err(sql_error)?;
+    let decks = deck_names(conn).map_err(sql_error)?;
+    let mut cards = cards_by_note(conn).map_err(sql_error)?;
+    let created: i64 = conn
+        .query_row("SELECT crt FROM col", [], |row| row.get(0))
+        .map_err(sql_error)?;
+
+    let existing: HashSet<String> = srs::load_all(app)?
+        .into_iter()
+        .map(|card| card.id)
+        .collect();
+    let media = import_media(zip, &profiles::data_dir(app)?.join(MEDIA_DIR))?;
+
+    let mut report = AnkiImportReport {
+        media: media.len(),
+        ..Default::default()
+    };
+    let mut resolved: HashMap<i64, Option<Resolved>> = HashMap::new();
+    let mut skipped: HashMap<String, usize> = HashMap::new();
+    let mut imported: HashMap<i64, String> = HashMap::new();
+
+    let mut stmt = conn
+        .prepare("SELECT id, mid, tags, flds FROM notes ORDER BY id")
+        .map_err(sql_error)?;
+    let notes = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })
+        .map_err(sql_error)?;
+
+    for note in notes {
+        let (note_id, type_id, tags, fields) = note.map_err(sql_error)?;
+        report.notes += 1;
+
+        let Some(note_type) = types.get(&type_id) else {
+            report.skipped_notes += 1;
+            *skipped.entry(format!("Note type {}", type_id)).or_default() += 1;
+            continue;
+        };
+        let mapping = resolved
+            .entry(type_id)
+            .or_insert_with(|| resolve(note_type, options.field_mappings.get(&note_type.name)));
+        let Some(mapping) = mapping.as_ref() else {
+            report.skipped_notes += 1;
+            *skipped.entry(note_type.name.clone()).or_default() += 1;
+            continue;
+        };
+
+        let fields: Vec<&str> = fields.split(FIELD_SEPARATOR).collect();
+        let note_cards = cards.remove(&note_id).unwrap_or_default();
+        let note_cards: Vec<AnkiCard> = if note_type.cloze {
+            let numbers = cloze_numbers(fields.get(mapping.front).copied().unwrap_or_default());
+            note_cards
+                .into_iter()
+                .filter(|card| numbers.contains(&(card.ord + 1)))
+                .collect()
+        } else {
+            note_cards.into_iter().take(1).collect()
+        };
+
+        let tags: Vec<String> = tags.split_whitespace().map(str::to_string).collect();
+        let created_at = DateTime::from_timestamp_millis(note_id)
+            .unwrap_or_else(Utc::now)
+            .to_rfc3339();
+
+        for anki in note_cards {
+            let id = format!("anki-{}", anki.id);
+            if existing.contains(&id) {
+                report.duplicates += 1;
+                continue;
+            }
+            let (front, back, hint) =
+                card_faces(mapping, note_type.cloze, &fields, anki.ord, &media);
+            if front.is_empty() {
+                continue;
+            }
+
+            let deck_id = options.deck_id.clone().unwrap_or_else(|| {
+                decks
+                    .get(&anki.deck_id)
+                    .cloned()
+                    .unwrap_or_else(|| "Default".to_string())
+            });
+            let mut card = FlashCard {
+                id: id.clone(),
+                deck_id,
+                front,
+                back,
+                tags: tags.clone(),
+                hint,
+                source: Some("anki".to_string()),
+                note_id: None,
+                created_at: created_at.clone(),
+                repetitions: 0,
+                interval_days: 0,
+                ease_factor: DEFAULT_EASE,
+                due_at: None,
+                last_reviewed_at: None,
+                lapses: 0,
+            };
+            if options.include_scheduling {
+                apply_schedule(&mut card, &anki, created);
+            }
+            srs::save(app, &card)?;
+            imported.insert(anki.id, id);
+            report.imported += 1;
+        }
+    }
+
+    report.skipped_note_types = skipped
+        .into_iter()
+        .map(|(name, notes)| SkippedNoteType { name, notes })
+        .collect();
+    report
+        .skipped_note_types
+        .sort_by(|a, b| b.notes.cmp(&a.notes).then_with(|| a.name.cmp(&b.name)));
+
+    if options.include_scheduling && !imported.is_empty() {
+        report.reviews = import_reviews(app, conn, &imported)?;
+    }
+    Ok(report)
+}
+
+/// Add the imported cards' Anki reviews to the review log, and set each
+/// card's last review time from it
+fn import_reviews<R: Runtime>(
+    app: &AppHandle<R>,
+    conn: &Connection,
+    imported: &HashMap<i64, String>,
+) -> Result<usize, String> {
+    let log = review_log(conn, imported).map_err(sql_error)?;
+    if log.is_empty() {
+        return Ok(0);
+    }
+
+    app.state::<Database>().with_conn(|db| {
+        let tx = db.transaction()?;
+        for (card_id, grade, reviewed_at) in &log {
+            tx.execute(
+                "INSERT INTO card_reviews (card_id, grade, reviewed_at) VALUES (?1, ?2, ?3)",
+                params![card_id, grade, reviewed_at],
+            )?;
+        }
+        tx.commit()
+    })?;
+
+    let last: HashMap<&str, &str> = log
+        .iter()
+        .map(|(card_id, _, at)| (card_id.as_str(), at.as_str()))
+        .collect();
+    for mut card in srs::load_all(app)? {
+        let Some(at) = last.get(card.id.as_str()) else {
+            continue;
+        };
+        card.last_reviewed_at = at.parse().ok();
+        srs::save(app, &card)?;
+    }
+    activity::invalidate(app);
+    Ok(log.len())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Import an Anki deck package (.apkg) as flashcards, with its media and,
+/// optionally, its scheduling and review history. Card ids come from
+/// Anki's, so importing a deck again skips cards already there.
+#[tauri::command]
+pub async fn import_anki_apkg<R: Runtime>(
+    app: AppHandle<R>,
+    path: String,
+    options: Option<AnkiImportOptions>,
+) -> Result<AnkiImportReport, String> {
+    info!("Importing Anki package {}", path);
+    let options = options.unwrap_or_default();
+
+    let worker = app.clone();
+    let report = tauri::async_runtime::spawn_blocking(move || import(&worker, &path, &options))
+        .await
+        .map_err(|e| format!("Anki import task failed: {}", e))??;
+
+    info!(
+        "Imported {} card(s) from {} Anki note(s) ({} duplicate(s), {} skipped note(s), {} media file(s), {} review(s))",
+        report.imported,
+        report.notes,
+        report.duplicates,
+        report.skipped_notes,
+        report.media,
+        report.reviews
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn note_type(cloze: bool, fields: &[&str]) -> NoteType {
+        NoteType {
+            name: "Basic".to_string(),
+            cloze,
+            unsupported: false,
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn expands_cloze_deletions() {
+        let text = "{{c1::Paris}} is the capital of {{c2::France::country}}";
+        assert_eq!(cloze_numbers(text), BTreeSet::from([1, 2]));
+        assert_eq!(cloze_side(text, 1, false), "[...] is the capital of France");
+        assert_eq!(
+            cloze_side(text, 2, false),
+            "Paris is the capital of [country]"
+        );
+        assert_eq!(cloze_side(text, 2, true), "Paris is the capital of France");
+    }
+
+    #[test]
+    fn converts_field_html_to_text() {
+        let media = HashMap::from([("a b.jpg".to_string(), "a b-2.jpg".to_string())]);
+        let html = "<div><b>Hund</b> &amp; Katze</div><div><img src=\"a b.jpg\"></div>[sound:bark.mp3]<br>&#x263A;";
+        assert_eq!(
+            field_text(html, &media),
+            "Hund & Katze\n[image:a b-2.jpg]\n[sound:bark.mp3]\n☺"
+        );
+    }
+
+    #[test]
+    fn resolves_default_and_custom_mappings() {
+        let basic = note_type(false, &["Front", "Back", "Notes"]);
+        assert_eq!(
+            resolve(&basic, None),
+            Some(Resolved {
+                front: 0,
+                back: Some(1),
+                hint: None,
+                extras: vec![2],
+            })
+        );
+
+        let mapping = AnkiFieldMapping {
+            front: "Back".to_string(),
+            back: Some("Front".to_string()),
+            hint: Some("Notes".to_string()),
+            extras: Vec::new(),
+        };
+        assert_eq!(resolve(&basic, Some(&mapping)).unwrap().hint, Some(2));
+
+        let missing = AnkiFieldMapping {
+            front: "Word".to_string(),
+            ..mapping
+        };
+        assert_eq!(resolve(&basic, Some(&missing)), None);
+        assert_eq!(resolve(&note_type(false, &["Only"]), None), None);
+        assert!(resolve(&note_type(true, &["Text"]), None).is_some());
+    }
+
+    #[test]
+    fn builds_cloze_cards_with_extras() {
+        let cloze = note_type(true, &["Text", "Back Extra"]);
+        let resolved = resolve(&cloze, None).unwrap();
+        let fields = ["{{c1::Bonn}} was the capital", "until 1990"];
+        let (front, back, hint) = card_faces(&resolved, true, &fields, 0, &HashMap::new());
+        assert_eq!(front, "[...] was the capital");
+        assert_eq!(back, "Bonn was the capital\n\nuntil 1990");
+        assert_eq!(hint, None);
+    }
+
+    #[test]
+    fn carries_review_scheduling_over() {
+        let mut card = FlashCard {
+            id: "anki-1".to_string(),
+            deck_id: "Default".to_string(),
+            front: "f".to_string(),
+            back: "b".to_string(),
+            tags: Vec::new(),
+            hint: None,
+            source: None,
+            note_id: None,
+            created_at: String::new(),
+            repetitions: 0,
+            interval_days: 0,
+            ease_factor: DEFAULT_EASE,
+            due_at: None,
+            last_reviewed_at: None,
+            lapses: 0,
+        };
+        let created = 1_700_000_000;
+        let anki = AnkiCard {
+            kind: 2,
+            due: 30,
+            interval: 12,
+            factor: 2300,
+            reps: 6,
+            lapses: 1,
+            ..Default::default()
+        };
+        apply_schedule(&mut card, &anki, created);
+        assert_eq!(card.interval_days, 12);
+        assert_eq!(card.repetitions, 5);
+        assert_eq!(card.lapses, 1);
+        assert!((card.ease_factor - 2.3).abs() < 1e-9);
+        assert_eq!(
+            card.due_at,
+            timestamp(created).map(|t| t + Duration::days(30))
+        );
+    }
+
+    #[test]
+    fn reads_both_media_lists() {
+        let json = br#"{"0": "cat.jpg"}"#;
+        assert_eq!(media_list(json)["0"], "cat.jpg");
+
+        // MediaEntries { entries: [{ name: "dog.png", size: 3 }] }
+        let entry = [
+            0x0a, 0x07, b'd', b'o', b'g', b'.', b'p', b'n', b'g', 0x10, 0x03,
+        ];
+        let mut list = vec![0x0a, entry.len() as u8];
+        list.extend_from_slice(&entry);
+        assert_eq!(media_list(&list)["0"], "dog.png");
+    }
+
+    #[test]
+    fn caps_decompressed_entries() {
+        let limits = ArchiveLimits {
+            max_entry_size: 1024,
+            ..ArchiveLimits::default()
+        };
+        let small = zstd::stream::encode_all(&[b'a'; 1024][..], 0).unwrap();
+        assert_eq!(decompress(small, "media", &limits).unwrap().len(), 1024);
+
+        let bomb = zstd::stream::encode_all(&[0u8; 1025][..], 0).unwrap();
+        assert!(decompress(bomb, "media", &limits).is_err());
+
+        let plain = b"{\"0\": \"a.png\"}".to_vec();
+        assert_eq!(decompress(plain.clone(), "media", &limits).unwrap(), plain);
+    }
+
+    #[test]
+    fn maps_answer_buttons_to_grades() {
+        assert_eq!(grade(0), None);
+        assert_eq!(grade(1), Some(1));
+        assert_eq!(grade(3), Some(4));
+        assert_eq!(grade(4), Some(5));
+    }
+}
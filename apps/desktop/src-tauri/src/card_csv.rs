@@ -0,0 +1,477 @@
+// Read Master Desktop - Flashcard CSV
+//
+// Flashcard import from CSV/TSV files exported by other apps, and export
+// in the same shape. The caller maps file columns to card fields; the
+// delimiter is detected from the first rows. A dry run parses everything
+// and returns a preview without saving.
+//
+// Cards whose front matches an existing card of the deck (ignoring case
+// and spacing), or an earlier row of the file, are skipped as duplicates.
+// Rows that can't be used are reported with their line number; the rest
+// are still imported.
+
+use std::collections::HashSet;
+
+use chrono::Utc;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::srs::{self, FlashCard, DEFAULT_EASE};
+
+/// Delimiters tried by detection, preferred in this order on a tie
+const DELIMITERS: [u8; 4] = [b'\t', b',', b';', b'|'];
+
+/// Rows sampled for delimiter detection
+const SNIFF_ROWS: usize = 10;
+
+/// Rows returned by a dry run
+const PREVIEW_ROWS: usize = 20;
+
+const CSV_HEADERS: [&str; 4] = ["front", "back", "tags", "hint"];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Which file column (0-based) holds each card field
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CardColumnMapping {
+    pub front: usize,
+    pub back: usize,
+    #[serde(default)]
+    pub tags: Option<usize>,
+    #[serde(default)]
+    pub hint: Option<usize>,
+    /// Whether the first row is a header to skip
+    #[serde(default)]
+    pub has_header: bool,
+}
+
+/// A row parsed into card fields
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParsedCard {
+    /// 1-based line the row starts on
+    pub line: u64,
+    pub front: String,
+    pub back: String,
+    pub tags: Vec<String>,
+    pub hint: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RowError {
+    pub line: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CardImportReport {
+    /// Detected delimiter, e.g. "\t"
+    pub delimiter: String,
+    /// Data rows in the file, excluding the header
+    pub total_rows: usize,
+    /// Cards saved, or that would be saved on a dry run
+    pub imported: usize,
+    pub duplicates: usize,
+    pub errors: Vec<RowError>,
+    /// First parsed rows; only filled on a dry run
+    pub preview: Vec<ParsedCard>,
+}
+
+// ============================================================================
+// Parsing
+// ============================================================================
+
+/// Count of `delimiter` outside quotes in each of the first non-blank
+/// rows. Line breaks inside quotes don't end a row.
+fn delimiter_counts(text: &str, delimiter: u8) -> Vec<usize> {
+    let mut counts = Vec::new();
+    let (mut count, mut blank, mut quoted) = (0, true, false);
+
+    for b in text.bytes() {
+        if b == b'\n' && !quoted {
+            if !blank {
+                counts.push(count);
+                if counts.len() == SNIFF_ROWS {
+                    return counts;
+                }
+            }
+            (count, blank) = (0, true);
+            continue;
+        }
+        if b == b'"' {
+            quoted = !quoted;
+        } else if b == delimiter && !quoted {
+            count += 1;
+        }
+        if !b.is_ascii_whitespace() {
+            blank = false;
+        }
+    }
+    if !blank {
+        counts.push(count);
+    }
+    counts
+}
+
+/// The delimiter appearing the same nonzero number of times in every
+/// sampled row, falling back to the most frequent one, then to a comma
+pub fn detect_delimiter(text: &str) -> u8 {
+    let counts: Vec<(u8, Vec<usize>)> = DELIMITERS
+        .iter()
+        .map(|&d| (d, delimiter_counts(text, d)))
+        .collect();
+
+    let consistent = counts.iter().find(|(_, per_row)| {
+        per_row.first().is_some_and(|&first| first > 0) && per_row.iter().all(|&n| n == per_row[0])
+    });
+    if let Some((delimiter, _)) = consistent {
+        return *delimiter;
+    }
+
+    counts
+        .iter()
+        .map(|(d, per_row)| (*d, per_row.iter().sum::<usize>()))
+        .filter(|(_, total)| *total > 0)
+        .rev()
+        .max_by_key(|(_, total)| *total)
+        .map_or(b',', |(d, _)| d)
+}
+
+/// Lowercased with runs of whitespace collapsed, for duplicate checks
+fn normalize_front(front: &str) -> String {
+    front
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn split_tags(raw: &str) -> Vec<String> {
+    let mut tags: Vec<String> = raw
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect();
+    tags.dedup();
+    tags
+}
+
+fn parse_row(
+    record: &csv::StringRecord,
+    line: u64,
+    mapping: &CardColumnMapping,
+) -> Result<ParsedCard, String> {
+    let field = |column: usize, name: &str| {
+        record
+            .get(column)
+            .map(str::trim)
+            .ok_or_else(|| format!("No column {} for {}", column + 1, name))
+    };
+    let optional = |column: Option<usize>| {
+        column
+            .and_then(|c| record.get(c))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+
+    let front = field(mapping.front, "front")?;
+    let back = field(mapping.back, "back")?;
+    if front.is_empty() {
+        return Err("Empty front".to_string());
+    }
+    if back.is_empty() {
+        return Err("Empty back".to_string());
+    }
+
+    Ok(ParsedCard {
+        line,
+        front: front.to_string(),
+        back: back.to_string(),
+        tags: optional(mapping.tags).map(split_tags).unwrap_or_default(),
+        hint: optional(mapping.hint).map(str::to_string),
+    })
+}
+
+/// Parse `text` into cards, reporting unusable rows and duplicates of
+/// `existing` (normalized fronts) or of earlier rows
+fn parse_cards(
+    text: &str,
+    mapping: &CardColumnMapping,
+    existing: &mut HashSet<String>,
+) -> (Vec<ParsedCard>, CardImportReport) {
+    let delimiter = detect_delimiter(text);
+    let mut report = CardImportReport {
+        delimiter: char::from(delimiter).to_string(),
+        ..Default::default()
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(mapping.has_header)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+
+    let mut cards = Vec::new();
+    let mut record = csv::StringRecord::new();
+    loop {
+        let line = reader.position().line();
+        match reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => {}
+            Err(e) => {
+                report.total_rows += 1;
+                report.errors.push(RowError {
+                    line: e.position().map_or(line, |p| p.line()),
+                    reason: format!("Malformed row: {}", e),
+                });
+                continue;
+            }
+        }
+
+        let line = record.position().map_or(line, |p| p.line());
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        report.total_rows += 1;
+
+        match parse_row(&record, line, mapping) {
+            Ok(card) => {
+                if existing.insert(normalize_front(&card.front)) {
+                    cards.push(card);
+                } else {
+                    report.duplicates += 1;
+                }
+            }
+            Err(reason) => report.errors.push(RowError { line, reason }),
+        }
+    }
+
+    report.imported = cards.len();
+    (cards, report)
+}
+
+fn new_card(card: ParsedCard, deck_id: &str, created_at: &str) -> FlashCard {
+    FlashCard {
+        id: uuid::Uuid::new_v4().to_string(),
+        deck_id: deck_id.to_string(),
+        front: card.front,
+        back: card.back,
+        tags: card.tags,
+        hint: card.hint,
+        source: Some("csv".to_string()),
+        note_id: None,
+        created_at: created_at.to_string(),
+        repetitions: 0,
+        interval_days: 0,
+        ease_factor: DEFAULT_EASE,
+        due_at: None,
+        last_reviewed_at: None,
+        lapses: 0,
+    }
+}
+
+// ============================================================================
+// Writing
+// ============================================================================
+
+/// Cards as delimited text with a header row, tags separated by spaces
+fn to_csv(cards: &[FlashCard], delimiter: u8) -> Result<Vec<u8>, String> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(Vec::new());
+    let csv_error = |e: csv::Error| format!("Failed to write CSV: {}", e);
+
+    writer.write_record(CSV_HEADERS).map_err(csv_error)?;
+    for card in cards {
+        writer
+            .write_record([
+                card.front.as_str(),
+                card.back.as_str(),
+                &card.tags.join(" "),
+                card.hint.as_deref().unwrap_or_default(),
+            ])
+            .map_err(csv_error)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| format!("Failed to write CSV: {}", e))
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Import cards into a deck from a CSV/TSV file. With `dry_run`, nothing
+/// is saved and the report carries a preview of the first rows.
+#[tauri::command]
+pub async fn import_cards_csv<R: Runtime>(
+    app: AppHandle<R>,
+    path: String,
+    mapping: CardColumnMapping,
+    deck_id: String,
+    dry_run: Option<bool>,
+) -> Result<CardImportReport, String> {
+    info!("Importing cards from {} into deck {}", path, deck_id);
+
+    let bytes = std::fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let text = String::from_utf8_lossy(&bytes);
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+
+    let mut existing: HashSet<String> = srs::load_all(&app)?
+        .iter()
+        .filter(|card| card.deck_id == deck_id)
+        .map(|card| normalize_front(&card.front))
+        .collect();
+    let (cards, mut report) = parse_cards(text, &mapping, &mut existing);
+
+    if dry_run.unwrap_or(false) {
+        report.preview = cards.into_iter().take(PREVIEW_ROWS).collect();
+        return Ok(report);
+    }
+
+    let created_at = Utc::now().to_rfc3339();
+    for card in cards {
+        srs::save(&app, &new_card(card, &deck_id, &created_at))?;
+    }
+
+    info!(
+        "Imported {} card(s) into deck {} ({} duplicate(s), {} error(s))",
+        report.imported,
+        deck_id,
+        report.duplicates,
+        report.errors.len()
+    );
+    Ok(report)
+}
+
+/// Export a deck's cards with a front/back/tags/hint header. `.tsv` files
+/// are tab separated, anything else comma separated. Returns the number
+/// of cards written.
+#[tauri::command]
+pub async fn export_cards_csv<R: Runtime>(
+    app: AppHandle<R>,
+    deck_id: String,
+    out_path: String,
+) -> Result<usize, String> {
+    let mut cards: Vec<FlashCard> = srs::load_all(&app)?
+        .into_iter()
+        .filter(|card| card.deck_id == deck_id)
+        .collect();
+    cards.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+    let delimiter = if out_path.to_lowercase().ends_with(".tsv") {
+        b'\t'
+    } else {
+        b','
+    };
+    std::fs::write(&out_path, to_csv(&cards, delimiter)?)
+        .map_err(|e| format!("Failed to write {}: {}", out_path, e))?;
+
+    info!(
+        "Exported {} card(s) from deck {} to {}",
+        cards.len(),
+        deck_id,
+        out_path
+    );
+    Ok(cards.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping() -> CardColumnMapping {
+        CardColumnMapping {
+            front: 0,
+            back: 1,
+            tags: Some(2),
+            hint: None,
+            has_header: false,
+        }
+    }
+
+    #[test]
+    fn detects_delimiters() {
+        assert_eq!(detect_delimiter("hola\thello\tes\nadiós\tbye\tes\n"), b'\t');
+        assert_eq!(detect_delimiter("\"a, b\",c\nd,e\n"), b',');
+        assert_eq!(detect_delimiter("a;b;c\nd;e;f\n"), b';');
+        assert_eq!(detect_delimiter("single column\n"), b',');
+    }
+
+    #[test]
+    fn parses_quoted_fields_and_tags() {
+        let text = "hola\t\"hello,\nhi\"\tspanish greeting\nperro\tdog\t\n";
+        let (cards, report) = parse_cards(text, &mapping(), &mut HashSet::new());
+
+        assert_eq!(report.delimiter, "\t");
+        assert_eq!(report.total_rows, 2);
+        assert_eq!(cards[0].back, "hello,\nhi");
+        assert_eq!(cards[0].tags, ["spanish", "greeting"]);
+        assert_eq!(cards[1].line, 3);
+        assert!(cards[1].tags.is_empty());
+    }
+
+    #[test]
+    fn collects_errors_and_duplicates() {
+        let text = "front,back\nGato,cat\nonly front\n,missing\n  gato ,again\nperro,dog\n";
+        let mut existing = HashSet::from(["perro".to_string()]);
+        let mapping = CardColumnMapping {
+            has_header: true,
+            tags: None,
+            ..mapping()
+        };
+        let (cards, report) = parse_cards(text, &mapping, &mut existing);
+
+        assert_eq!(cards.len(), 1);
+        assert_eq!(report.total_rows, 5);
+        assert_eq!(report.duplicates, 2);
+        assert_eq!(
+            report.errors,
+            [
+                RowError {
+                    line: 3,
+                    reason: "No column 2 for back".to_string()
+                },
+                RowError {
+                    line: 4,
+                    reason: "Empty front".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let mut card = new_card(
+            ParsedCard {
+                line: 1,
+                front: "der Hund".to_string(),
+                back: "the dog, \"Hund\"".to_string(),
+                tags: vec!["german".to_string(), "animals".to_string()],
+                hint: Some("noun".to_string()),
+            },
+            "de",
+            "2026-01-01T00:00:00Z",
+        );
+        card.id = "c1".to_string();
+        let text = String::from_utf8(to_csv(&[card], b'\t').unwrap()).unwrap();
+
+        let mapping = CardColumnMapping {
+            hint: Some(3),
+            has_header: true,
+            ..mapping()
+        };
+        let (cards, report) = parse_cards(&text, &mapping, &mut HashSet::new());
+        assert!(report.errors.is_empty());
+        assert_eq!(cards[0].front, "der Hund");
+        assert_eq!(cards[0].back, "the dog, \"Hund\"");
+        assert_eq!(cards[0].tags, ["german", "animals"]);
+        assert_eq!(cards[0].hint.as_deref(), Some("noun"));
+    }
+}
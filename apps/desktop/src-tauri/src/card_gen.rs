@@ -0,0 +1,284 @@
+// Read Master Desktop - Card Generation
+//
+// Turns highlights into draft flashcards. Drafts are returned for review
+// and only written to `flashcards.json` through `save_generated_cards`.
+//
+// Card ids are derived from the note id and mode, and cards that already
+// exist are skipped, so re-running generation never duplicates cards.
+
+use std::collections::HashSet;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::notes::{self, Note};
+use crate::srs::{self, FlashCard, DEFAULT_EASE};
+
+/// Deck generated cards go into
+const DEFAULT_DECK: &str = "default";
+
+/// Replacement for the blanked-out keyword
+const CLOZE_BLANK: &str = "[...]";
+
+/// Words too common to make a useful cloze
+const STOPWORDS: &[&str] = &[
+    "about", "after", "again", "because", "before", "being", "could", "every", "from", "have",
+    "into", "other", "should", "some", "than", "that", "their", "there", "these", "they", "this",
+    "those", "through", "very", "were", "what", "when", "where", "which", "while", "with", "would",
+    "your",
+];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CardGenMode {
+    /// Front is the quote with a keyword blanked out, back is the keyword
+    ClozeFromQuote,
+    /// Front is the quote, back is the note text
+    QuoteToNote,
+}
+
+impl CardGenMode {
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::ClozeFromQuote => "cloze",
+            Self::QuoteToNote => "quote",
+        }
+    }
+}
+
+// ============================================================================
+// Generation
+// ============================================================================
+
+/// Pick the keyword to blank out. A tag or a single-word note that appears
+/// in the quote wins (that's usually the vocabulary word); otherwise the
+/// longest non-stopword, earliest on ties.
+fn pick_keyword(note: &Note, quote: &str) -> Option<String> {
+    let words: Vec<&str> = quote
+        .split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '-')
+        .map(|w| w.trim_matches(|c| c == '\'' || c == '-'))
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let hints = note
+        .tags
+        .iter()
+        .map(String::as_str)
+        .chain(Some(note.body.trim()).filter(|b| !b.contains(char::is_whitespace)));
+    for hint in hints {
+        if let Some(word) = words.iter().find(|w| w.eq_ignore_ascii_case(hint)) {
+            return Some(word.to_string());
+        }
+    }
+
+    words
+        .iter()
+        .filter(|w| w.chars().count() >= 4)
+        .filter(|w| !STOPWORDS.contains(&w.to_lowercase().as_str()))
+        .fold(None::<&str>, |best, w| match best {
+            Some(b) if b.chars().count() >= w.chars().count() => Some(b),
+            _ => Some(w),
+        })
+        .map(str::to_string)
+}
+
+/// Blank out the first whole-word occurrence of `keyword`
+fn cloze(quote: &str, keyword: &str) -> Option<String> {
+    let is_word_char = |c: char| c.is_alphanumeric();
+
+    quote.match_indices(keyword).find_map(|(start, _)| {
+        let end = start + keyword.len();
+        let before_ok = !quote[..start].chars().next_back().is_some_and(is_word_char);
+        let after_ok = !quote[end..].chars().next().is_some_and(is_word_char);
+        (before_ok && after_ok)
+            .then(|| format!("{}{}{}", &quote[..start], CLOZE_BLANK, &quote[end..]))
+    })
+}
+
+/// Build the draft card for a note, if the note has what the mode needs
+pub fn card_for_note(note: &Note, mode: CardGenMode, deck_id: &str) -> Option<FlashCard> {
+    let quote = note.quote.as_deref()?.trim();
+    if quote.is_empty() {
+        return None;
+    }
+
+    let (front, back) = match mode {
+        CardGenMode::ClozeFromQuote => {
+            let keyword = pick_keyword(note, quote)?;
+            let front = cloze(quote, &keyword)?;
+            let back = if note.body.trim().is_empty() || note.body.trim() == keyword {
+                keyword
+            } else {
+                format!("{}\n\n{}", keyword, note.body.trim())
+            };
+            (front, back)
+        }
+        CardGenMode::QuoteToNote => {
+            if note.body.trim().is_empty() {
+                return None;
+            }
+            (quote.to_string(), note.body.trim().to_string())
+        }
+    };
+
+    Some(FlashCard {
+        id: format!("{}-{}", note.id, mode.suffix()),
+        deck_id: deck_id.to_string(),
+        front,
+        back,
+        tags: Vec::new(),
+        hint: None,
+        source: Some("highlight".to_string()),
+        note_id: Some(note.id.clone()),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        repetitions: 0,
+        interval_days: 0,
+        ease_factor: DEFAULT_EASE,
+        due_at: None,
+        last_reviewed_at: None,
+        lapses: 0,
+    })
+}
+
+/// Draft cards for `notes`, skipping any whose id is already in `existing`.
+/// A note carded in one mode can still be carded in the other.
+fn drafts(
+    notes: &[Note],
+    mode: CardGenMode,
+    deck_id: &str,
+    existing: &HashSet<String>,
+) -> Vec<FlashCard> {
+    notes
+        .iter()
+        .filter_map(|note| card_for_note(note, mode, deck_id))
+        .filter(|card| !existing.contains(&card.id))
+        .collect()
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Draft cards from a book's highlights, skipping notes that already have
+/// a card in this mode. Nothing is saved until `save_generated_cards` is called.
+#[tauri::command]
+pub async fn generate_cards_from_notes<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: String,
+    mode: CardGenMode,
+    deck_id: Option<String>,
+) -> Result<Vec<FlashCard>, String> {
+    let existing: HashSet<String> = srs::load_all(&app)?
+        .into_iter()
+        .map(|card| card.id)
+        .collect();
+
+    let deck_id = deck_id.unwrap_or_else(|| DEFAULT_DECK.to_string());
+    let cards = drafts(
+        &notes::book_notes(&app, &book_id)?,
+        mode,
+        &deck_id,
+        &existing,
+    );
+
+    info!(
+        "Generated {} draft card(s) from notes of {} ({:?})",
+        cards.len(),
+        book_id,
+        mode
+    );
+    Ok(cards)
+}
+
+/// Save reviewed draft cards. Cards already in the store are left as they
+/// are, keeping their review history. Returns the number saved.
+#[tauri::command]
+pub async fn save_generated_cards<R: Runtime>(
+    app: AppHandle<R>,
+    cards: Vec<FlashCard>,
+) -> Result<usize, String> {
+    let existing: HashSet<String> = srs::load_all(&app)?
+        .into_iter()
+        .map(|card| card.id)
+        .collect();
+
+    let mut saved = 0;
+    for card in cards.iter().filter(|card| !existing.contains(&card.id)) {
+        srs::save(&app, card)?;
+        saved += 1;
+    }
+
+    info!("Saved {} generated card(s)", saved);
+    Ok(saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(quote: &str, body: &str, tags: &[&str]) -> Note {
+        Note {
+            id: "n1".to_string(),
+            book_id: Some("b1".to_string()),
+            locator: None,
+            quote: Some(quote.to_string()),
+            body: body.to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            color: None,
+            source: None,
+            anchor: None,
+            content_hash: None,
+            orphaned: false,
+            anchor_confidence: None,
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+            updated_at: "2026-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn cloze_prefers_tagged_word() {
+        let n = note("The sky was a vast cerulean dome.", "", &["vast"]);
+        let card = card_for_note(&n, CardGenMode::ClozeFromQuote, "default").unwrap();
+        assert_eq!(card.front, "The sky was a [...] cerulean dome.");
+        assert_eq!(card.back, "vast");
+    }
+
+    #[test]
+    fn cloze_falls_back_to_longest_word() {
+        let n = note("His laconic reply ended it.", "brief, terse", &[]);
+        let card = card_for_note(&n, CardGenMode::ClozeFromQuote, "default").unwrap();
+        assert_eq!(card.front, "His [...] reply ended it.");
+        assert_eq!(card.back, "laconic\n\nbrief, terse");
+    }
+
+    #[test]
+    fn quote_to_note_needs_a_body() {
+        let n = note("A quote", "", &[]);
+        assert!(card_for_note(&n, CardGenMode::QuoteToNote, "default").is_none());
+    }
+
+    #[test]
+    fn card_ids_are_deterministic() {
+        let n = note("A quote", "meaning", &[]);
+        let a = card_for_note(&n, CardGenMode::QuoteToNote, "default").unwrap();
+        let b = card_for_note(&n, CardGenMode::QuoteToNote, "default").unwrap();
+        assert_eq!(a.id, b.id);
+        assert_eq!(a.note_id.as_deref(), Some("n1"));
+    }
+
+    #[test]
+    fn drafts_skip_existing_cards_per_mode() {
+        let notes = vec![note("A vivid quote", "meaning", &[])];
+        let existing = HashSet::from(["n1-quote".to_string()]);
+
+        assert!(drafts(&notes, CardGenMode::QuoteToNote, "default", &existing).is_empty());
+        let cloze = drafts(&notes, CardGenMode::ClozeFromQuote, "default", &existing);
+        assert_eq!(cloze.len(), 1);
+        assert_eq!(cloze[0].id, "n1-cloze");
+    }
+}
@@ -0,0 +1,207 @@
+// Read Master Desktop - Catalog Export
+//
+// The library's metadata and reading state written out as CSV (for
+// spreadsheets) or JSON, one row per book. The destination comes from
+// `save_file_dialog`.
+
+use std::path::Path;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::db::Database;
+use crate::library::{self, Book};
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CatalogFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogRow {
+    pub title: String,
+    pub authors: Option<String>,
+    pub format: Option<String>,
+    pub page_count: Option<i64>,
+    pub word_count: Option<i64>,
+    pub added_at: String,
+    /// Reading progress from 0.0 to 1.0
+    pub progress: Option<f64>,
+    pub finished: bool,
+    pub finished_at: Option<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub rating: Option<i64>,
+    pub tags: Vec<String>,
+}
+
+impl From<Book> for CatalogRow {
+    fn from(book: Book) -> Self {
+        Self {
+            title: book.title,
+            authors: book.author,
+            format: book.format,
+            page_count: book.page_count,
+            word_count: book.word_count,
+            added_at: book.added_at,
+            progress: book.progress,
+            finished: book.finished_at.is_some(),
+            finished_at: book.finished_at,
+            series: book.series_name,
+            series_index: book.series_index,
+            rating: book.rating,
+            tags: book.tags,
+        }
+    }
+}
+
+const CSV_HEADERS: &[&str] = &[
+    "Title",
+    "Authors",
+    "Format",
+    "Pages",
+    "Words",
+    "Date Added",
+    "Progress",
+    "Finished",
+    "Date Finished",
+    "Series",
+    "Series Index",
+    "Rating",
+    "Tags",
+];
+
+// ============================================================================
+// Writing
+// ============================================================================
+
+fn opt<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(T::to_string).unwrap_or_default()
+}
+
+/// Rows as CSV with a header line. Fields with quotes, commas, or line
+/// breaks are quoted.
+fn to_csv(rows: &[CatalogRow]) -> Result<Vec<u8>, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let csv_error = |e: csv::Error| format!("Failed to write CSV: {}", e);
+
+    writer.write_record(CSV_HEADERS).map_err(csv_error)?;
+    for row in rows {
+        writer
+            .write_record([
+                row.title.clone(),
+                opt(&row.authors),
+                opt(&row.format),
+                opt(&row.page_count),
+                opt(&row.word_count),
+                row.added_at.clone(),
+                opt(&row.progress),
+                row.finished.to_string(),
+                opt(&row.finished_at),
+                opt(&row.series),
+                opt(&row.series_index),
+                opt(&row.rating),
+                row.tags.join("; "),
+            ])
+            .map_err(csv_error)?;
+    }
+
+    writer
+        .into_inner()
+        .map_err(|e| format!("Failed to write CSV: {}", e))
+}
+
+fn to_json(rows: &[CatalogRow]) -> Result<Vec<u8>, String> {
+    serde_json::to_vec_pretty(rows).map_err(|e| format!("Failed to write JSON: {}", e))
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Write the library catalog to `out_path` as CSV or JSON
+#[tauri::command]
+pub async fn export_catalog(
+    db: State<'_, Database>,
+    format: CatalogFormat,
+    out_path: String,
+) -> Result<(), String> {
+    let rows: Vec<CatalogRow> = db
+        .with_conn(|conn| library::list_books(conn))?
+        .into_iter()
+        .map(CatalogRow::from)
+        .collect();
+
+    let data = match format {
+        CatalogFormat::Csv => to_csv(&rows)?,
+        CatalogFormat::Json => to_json(&rows)?,
+    };
+
+    info!(
+        "Exporting catalog of {} book(s) to {}",
+        rows.len(),
+        out_path
+    );
+    std::fs::write(Path::new(&out_path), data)
+        .map_err(|e| format!("Failed to write catalog: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(title: &str, authors: Option<&str>) -> CatalogRow {
+        CatalogRow {
+            title: title.to_string(),
+            authors: authors.map(str::to_string),
+            format: Some("epub".to_string()),
+            page_count: Some(320),
+            word_count: None,
+            added_at: "2024-01-01T00:00:00Z".to_string(),
+            progress: Some(0.5),
+            finished: false,
+            finished_at: None,
+            series: None,
+            series_index: None,
+            rating: None,
+            tags: vec!["fiction".to_string(), "to read".to_string()],
+        }
+    }
+
+    #[test]
+    fn csv_escapes_quotes_commas_and_newlines() {
+        let rows = [
+            row("Say \"Hello\", World", Some("Tolkien, J.R.R.")),
+            row("Line one\nLine two", None),
+        ];
+        let csv = to_csv(&rows).unwrap();
+
+        let mut reader = csv::Reader::from_reader(csv.as_slice());
+        assert_eq!(reader.headers().unwrap().len(), CSV_HEADERS.len());
+        let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(&records[0][0], "Say \"Hello\", World");
+        assert_eq!(&records[0][1], "Tolkien, J.R.R.");
+        assert_eq!(&records[0][12], "fiction; to read");
+        assert_eq!(&records[1][0], "Line one\nLine two");
+        assert_eq!(&records[1][1], "");
+    }
+
+    #[test]
+    fn json_uses_camel_case_fields() {
+        let json: serde_json::Value =
+            serde_json::from_slice(&to_json(&[row("Emma", Some("Jane Austen"))]).unwrap()).unwrap();
+        assert_eq!(json[0]["title"], "Emma");
+        assert_eq!(json[0]["pageCount"], 320);
+        assert_eq!(json[0]["finished"], false);
+        assert_eq!(json[0]["tags"][1], "to read");
+    }
+}
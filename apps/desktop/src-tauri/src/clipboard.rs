@@ -0,0 +1,175 @@
+// Read Master Desktop - Clipboard History
+//
+// Quotes copied from within Read Master, newest first, persisted to
+// `clipboard.json` as a bounded ring: once full, the oldest copy drops off.
+// Only copies made through `copy_quote` are recorded; the system clipboard
+// is never watched.
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime, State};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+use crate::db::Database;
+use crate::library;
+use crate::persist;
+use crate::profiles::ProfileStoreExt;
+
+const STORE_FILE: &str = "clipboard.json";
+const HISTORY_KEY: &str = "history";
+
+/// Copies kept before the oldest is dropped
+const MAX_ENTRIES: usize = 200;
+
+/// Entries returned when no limit is given
+const DEFAULT_LIMIT: usize = 50;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipEntry {
+    pub id: String,
+    pub text: String,
+    pub book_id: Option<i64>,
+    /// Title at the time of copying, so entries outlive the book
+    pub book_title: Option<String>,
+    pub locator: Option<String>,
+    pub copied_at: String,
+}
+
+// ============================================================================
+// History
+// ============================================================================
+
+/// Add a copy at the front. Copying the same passage again moves it up
+/// rather than listing it twice.
+fn push(history: &mut Vec<ClipEntry>, entry: ClipEntry, max: usize) {
+    history.retain(|e| !(e.text == entry.text && e.book_id == entry.book_id));
+    history.insert(0, entry);
+    history.truncate(max);
+}
+
+fn load<R: Runtime>(app: &AppHandle<R>) -> Result<Vec<ClipEntry>, String> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    Ok(store
+        .get(HISTORY_KEY)
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default())
+}
+
+fn save<R: Runtime>(app: &AppHandle<R>, history: &[ClipEntry]) -> Result<(), String> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    store.set(HISTORY_KEY, serde_json::json!(history));
+    persist::mark_dirty(app, STORE_FILE);
+    Ok(())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Copy a quote to the system clipboard and record it in the history
+#[tauri::command]
+pub fn copy_quote<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    text: String,
+    book_id: Option<i64>,
+    locator: Option<String>,
+) -> Result<ClipEntry, String> {
+    if text.trim().is_empty() {
+        return Err("Nothing to copy".to_string());
+    }
+
+    app.clipboard()
+        .write_text(text.clone())
+        .map_err(|e| format!("Failed to copy to clipboard: {}", e))?;
+
+    let book_title = match book_id {
+        Some(id) => db
+            .with_conn(|conn| library::get_book(conn, id))?
+            .map(|book| book.title),
+        None => None,
+    };
+    let entry = ClipEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        text,
+        book_id,
+        book_title,
+        locator,
+        copied_at: chrono::Utc::now().to_rfc3339(),
+    };
+
+    let mut history = load(&app)?;
+    push(&mut history, entry.clone(), MAX_ENTRIES);
+    save(&app, &history)?;
+    Ok(entry)
+}
+
+/// Copied quotes, newest first
+#[tauri::command]
+pub fn get_clipboard_history<R: Runtime>(
+    app: AppHandle<R>,
+    limit: Option<usize>,
+) -> Result<Vec<ClipEntry>, String> {
+    let mut history = load(&app)?;
+    history.truncate(limit.unwrap_or(DEFAULT_LIMIT));
+    Ok(history)
+}
+
+/// Forget every copied quote
+#[tauri::command]
+pub fn clear_clipboard_history<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
+    info!("Clearing clipboard history");
+    save(&app, &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(text: &str, book_id: Option<i64>) -> ClipEntry {
+        ClipEntry {
+            id: text.to_string(),
+            text: text.to_string(),
+            book_id,
+            book_title: None,
+            locator: None,
+            copied_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn drops_the_oldest_when_full() {
+        let mut history = Vec::new();
+        for text in ["a", "b", "c", "d"] {
+            push(&mut history, entry(text, Some(1)), 3);
+        }
+        let texts: Vec<&str> = history.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, ["d", "c", "b"]);
+    }
+
+    #[test]
+    fn recopying_moves_an_entry_to_the_front() {
+        let mut history = Vec::new();
+        push(&mut history, entry("a", Some(1)), 10);
+        push(&mut history, entry("b", Some(1)), 10);
+        push(&mut history, entry("a", Some(2)), 10);
+        push(&mut history, entry("a", Some(1)), 10);
+
+        let keys: Vec<(&str, Option<i64>)> = history
+            .iter()
+            .map(|e| (e.text.as_str(), e.book_id))
+            .collect();
+        assert_eq!(keys, [("a", Some(1)), ("a", Some(2)), ("b", Some(1))]);
+    }
+}
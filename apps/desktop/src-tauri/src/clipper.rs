@@ -0,0 +1,288 @@
+// Read Master Desktop - Web Clipper
+//
+// Imports a web article as a one-chapter book. File > Import from URL
+// fetches the page, `formats::html` extracts the article, its images are
+// fetched into the book, and the resulting EPUB is imported like any other
+// file, with the page title, author, site name, and retrieval date as its
+// metadata.
+//
+// Pages without an extractable article (paywalls, pages built by scripts)
+// fail with the `no_article` code, so the UI can offer `import_html` with
+// HTML copied from the browser instead.
+//
+// Fetching never sends cookies (the HTTP client has no cookie store), is
+// HTTPS-only unless `clipper.allowHttp` is on, times out, and caps the size
+// of the page and of each image.
+
+use std::time::Duration;
+
+use log::{info, warn};
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Runtime};
+use url::Url;
+
+use crate::error::AppError;
+use crate::formats::html::{self, Article, ImageData};
+use crate::formats::BookInfo;
+use crate::import::{self, ImportOutcome};
+use crate::library;
+use crate::net;
+use crate::profiles::ProfileStoreExt;
+
+const SETTINGS_STORE: &str = "settings.json";
+const ALLOW_HTTP_SETTING: &str = "clipper.allowHttp";
+
+/// Whole-request timeout for the page and each image
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+const MAX_PAGE_BYTES: usize = 10 * 1024 * 1024;
+const MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+
+/// Images fetched per article; the rest are left out
+const MAX_IMAGES: usize = 100;
+
+// ============================================================================
+// Fetching
+// ============================================================================
+
+fn allow_http<R: Runtime>(app: &AppHandle<R>) -> bool {
+    app.profile_store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(ALLOW_HTTP_SETTING))
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+fn client(allow_http: bool) -> Result<Client, String> {
+    // `https_only` also refuses redirects to plain HTTP
+    net::client_builder()
+        .timeout(FETCH_TIMEOUT)
+        .https_only(!allow_http)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+fn parse_url(url: &str, allow_http: bool) -> Result<Url, AppError> {
+    let url = Url::parse(url.trim())
+        .map_err(|e| AppError::invalid_input(format!("Invalid URL: {}", e)))?;
+    match url.scheme() {
+        "https" => Ok(url),
+        "http" if allow_http => Ok(url),
+        "http" => Err(AppError::invalid_input(
+            "Only HTTPS pages can be imported (see clipper.allowHttp)",
+        )),
+        scheme => Err(AppError::invalid_input(format!(
+            "Unsupported URL scheme: {}",
+            scheme
+        ))),
+    }
+}
+
+/// Body of `url`, up to `cap` bytes, and the URL it was finally served
+/// from after redirects
+async fn fetch(client: &Client, url: &Url, cap: usize) -> Result<(Vec<u8>, Url), String> {
+    let mut response = client
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch {}: {}", url, e))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("Failed to fetch {}: HTTP {}", url, status));
+    }
+
+    let too_large = || format!("{} is larger than {} MB", url, cap / (1024 * 1024));
+    if response
+        .content_length()
+        .is_some_and(|len| len > cap as u64)
+    {
+        return Err(too_large());
+    }
+    let final_url = response.url().clone();
+
+    let mut data = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to fetch {}: {}", url, e))?
+    {
+        // Servers can leave out or understate the length
+        if data.len() + chunk.len() > cap {
+            return Err(too_large());
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok((data, final_url))
+}
+
+/// The article's images, in order; `None` for any that couldn't be fetched
+async fn fetch_images(client: &Client, urls: &[Url]) -> Vec<Option<ImageData>> {
+    if urls.len() > MAX_IMAGES {
+        warn!(
+            "Article has {} images; keeping the first {}",
+            urls.len(),
+            MAX_IMAGES
+        );
+    }
+
+    let mut images = Vec::with_capacity(urls.len());
+    for (i, url) in urls.iter().enumerate() {
+        if i >= MAX_IMAGES {
+            images.push(None);
+            continue;
+        }
+        let image = match fetch(client, url, MAX_IMAGE_BYTES).await {
+            Ok((data, _)) => match html::image_extension(&data) {
+                Some(extension) => Some(ImageData { extension, data }),
+                None => {
+                    warn!("Skipping {}: not a supported image", url);
+                    None
+                }
+            },
+            Err(e) => {
+                warn!("Skipping article image: {}", e);
+                None
+            }
+        };
+        images.push(image);
+    }
+    images
+}
+
+// ============================================================================
+// Importing
+// ============================================================================
+
+/// File name for an article's EPUB, from its title
+fn file_name(title: &str) -> String {
+    let stem: String = title
+        .chars()
+        .map(|c| {
+            if c.is_control() || r#"/\:*?"<>|"#.contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(80)
+        .collect();
+    let stem = stem.trim().trim_matches('.');
+    format!("{}.epub", if stem.is_empty() { "Article" } else { stem })
+}
+
+/// Fetch the article's images, write it as an EPUB, and import that
+async fn import_article<R: Runtime>(
+    app: AppHandle<R>,
+    article: Article,
+    source: Option<Url>,
+    client: Client,
+) -> Result<ImportOutcome, AppError> {
+    let images = fetch_images(&client, &article.images).await;
+    let info = BookInfo {
+        title: article.title.clone(),
+        author: article.author.clone(),
+        publisher: article
+            .site_name
+            .clone()
+            .or_else(|| source.as_ref().and_then(Url::host_str).map(str::to_string)),
+        source: source.map(String::from),
+        date: Some(chrono::Local::now().format("%Y-%m-%d").to_string()),
+    };
+
+    let outcome = tauri::async_runtime::spawn_blocking(move || -> Result<_, AppError> {
+        let dir = library::library_dir(&app)?;
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create library folder: {}", e))?;
+        let dest = import::unique_path(&dir, &file_name(&article.title));
+        let partial = dest.with_extension("epub.part");
+
+        let identifier = format!(
+            "urn:sha256:{:x}",
+            Sha256::digest(article.xhtml(&[]).as_bytes())
+        );
+        let written =
+            html::write_epub(&partial, &article, &images, &info, &identifier).and_then(|_| {
+                std::fs::rename(&partial, &dest).map_err(|e| format!("Failed to save EPUB: {}", e))
+            });
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e.into());
+        }
+
+        import::import_converted(&app, &dest, &dest)
+    })
+    .await
+    .map_err(|e| format!("Import task failed: {}", e))??;
+
+    info!("Imported article as book {}", outcome.book.id);
+    Ok(outcome)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Import the article at `url` as a book, returning its id. Fails with
+/// `no_article` when the page has no readable article.
+#[tauri::command]
+pub async fn import_url<R: Runtime>(app: AppHandle<R>, url: String) -> Result<i64, AppError> {
+    let allow_http = allow_http(&app);
+    let url = parse_url(&url, allow_http)?;
+    info!("Importing article from {}", url);
+
+    let client = client(allow_http)?;
+    let (page, final_url) = fetch(&client, &url, MAX_PAGE_BYTES)
+        .await
+        .map_err(|e| AppError::network("Failed to fetch page", e))?;
+    let article = html::extract(&String::from_utf8_lossy(&page), Some(&final_url))?;
+
+    Ok(import_article(app, article, Some(url), client)
+        .await?
+        .book
+        .id)
+}
+
+/// Import an article from HTML copied out of the browser, for pages
+/// `import_url` can't read. `source_url` resolves relative links and
+/// images and is recorded as the book's source.
+#[tauri::command]
+pub async fn import_html<R: Runtime>(
+    app: AppHandle<R>,
+    html: String,
+    source_url: Option<String>,
+) -> Result<i64, AppError> {
+    let allow_http = allow_http(&app);
+    let source = source_url
+        .filter(|url| !url.trim().is_empty())
+        .map(|url| parse_url(&url, allow_http))
+        .transpose()?;
+
+    let article = html::extract(&html, source.as_ref())?;
+    let client = client(allow_http)?;
+    Ok(import_article(app, article, source, client).await?.book.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_https_urls_only_by_default() {
+        assert!(parse_url("https://example.com/a", false).is_ok());
+        assert_eq!(
+            parse_url("http://example.com/a", false).unwrap_err().code(),
+            "invalid_input"
+        );
+        assert!(parse_url("http://example.com/a", true).is_ok());
+        assert!(parse_url("file:///etc/passwd", true).is_err());
+        assert!(parse_url("not a url", false).is_err());
+    }
+
+    #[test]
+    fn names_files_after_titles() {
+        assert_eq!(file_name("Why Rivers Bend"), "Why Rivers Bend.epub");
+        assert_eq!(file_name("A/B: \"C\"?"), "A_B_ _C__.epub");
+        assert_eq!(file_name("..."), "Article.epub");
+    }
+}
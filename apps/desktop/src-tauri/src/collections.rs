@@ -0,0 +1,789 @@
+// Read Master Desktop - Smart Collections
+//
+// Saved library queries. Rules are a small filter expression tree stored
+// as JSON and compiled to SQL predicates at evaluation time.
+
+use chrono::{Datelike, Duration, Months, NaiveDate, Utc};
+use log::{info, warn};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::db::Database;
+use crate::library::{self, Book, BOOK_COLUMNS};
+
+/// Deepest allowed nesting of rule groups
+const MAX_DEPTH: usize = 8;
+
+/// Largest page `evaluate_collection` returns
+const MAX_PAGE_SIZE: u32 = 500;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Field {
+    Title,
+    Tag,
+    Author,
+    Series,
+    Format,
+    WordCount,
+    /// Reading progress as a percentage (0-100)
+    Progress,
+    Rating,
+    AddedAt,
+    FinishedAt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Operator {
+    Eq,
+    Ne,
+    Contains,
+    StartsWith,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    /// Inclusive range; value is a two-element array
+    Between,
+    IsEmpty,
+    IsNotEmpty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupOp {
+    And,
+    Or,
+}
+
+/// A node in the rule tree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Rule {
+    Group {
+        op: GroupOp,
+        rules: Vec<Rule>,
+    },
+    Condition {
+        field: Field,
+        operator: Operator,
+        #[serde(default)]
+        value: serde_json::Value,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Collection {
+    pub id: i64,
+    pub name: String,
+    pub rules: Rule,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionPage {
+    pub collection_id: i64,
+    pub total: i64,
+    pub offset: u32,
+    pub books: Vec<Book>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Tag,
+    Number,
+    Date,
+}
+
+impl Field {
+    fn kind(self) -> Kind {
+        match self {
+            Self::Title | Self::Author | Self::Series | Self::Format => Kind::Text,
+            Self::Tag => Kind::Tag,
+            Self::WordCount | Self::Progress | Self::Rating => Kind::Number,
+            Self::AddedAt | Self::FinishedAt => Kind::Date,
+        }
+    }
+
+    fn column(self) -> &'static str {
+        match self {
+            Self::Title => "books.title",
+            Self::Author => "books.author",
+            Self::Series => "books.series_name",
+            Self::Format => "books.format",
+            Self::WordCount => "books.word_count",
+            Self::Progress => "(books.progress * 100.0)",
+            Self::Rating => "books.rating",
+            Self::AddedAt => "substr(books.added_at, 1, 10)",
+            Self::FinishedAt => "substr(books.finished_at, 1, 10)",
+            // Tags live in their own table; handled separately
+            Self::Tag => "",
+        }
+    }
+}
+
+// ============================================================================
+// Compilation
+// ============================================================================
+
+/// Resolve a date value: `YYYY-MM-DD`, `today`, `startOfWeek`,
+/// `startOfMonth`, `startOfYear`, or a relative offset like `-30d`, `-2w`,
+/// `-6m`, `-1y`, counted back from `today`. Dates are compared against the
+/// UTC date prefix of the stored timestamps, so `today` is a UTC date too.
+fn resolve_date(raw: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    match raw {
+        "today" => return Ok(today),
+        "startOfWeek" => {
+            return Ok(today - Duration::days(today.weekday().num_days_from_monday() as i64))
+        }
+        "startOfMonth" => return Ok(today.with_day(1).unwrap_or(today)),
+        "startOfYear" => return Ok(today.with_ordinal(1).unwrap_or(today)),
+        _ => {}
+    }
+
+    if let Some(offset) = raw.strip_prefix('-') {
+        let invalid = || format!("Invalid relative date '{}'", raw);
+        let (index, unit) = offset.char_indices().last().ok_or_else(invalid)?;
+        let amount: u32 = offset[..index].parse().map_err(|_| invalid())?;
+
+        let date = match unit {
+            'd' => today.checked_sub_signed(Duration::days(amount as i64)),
+            'w' => today.checked_sub_signed(Duration::weeks(amount as i64)),
+            'm' => today.checked_sub_months(Months::new(amount)),
+            'y' => amount
+                .checked_mul(12)
+                .and_then(|months| today.checked_sub_months(Months::new(months))),
+            _ => None,
+        };
+        return date.ok_or_else(invalid);
+    }
+
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}': use YYYY-MM-DD or a relative date", raw))
+}
+
+fn scalar(field: Field, value: &serde_json::Value) -> Result<Value, String> {
+    let invalid = || format!("Invalid value {} for field {:?}", value, field);
+
+    match field.kind() {
+        Kind::Text | Kind::Tag => value
+            .as_str()
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| Value::Text(s.trim().to_lowercase()))
+            .ok_or_else(invalid),
+        Kind::Number => value.as_f64().map(Value::Real).ok_or_else(invalid),
+        Kind::Date => {
+            let raw = value.as_str().ok_or_else(invalid)?;
+            Ok(Value::Text(
+                resolve_date(raw, Utc::now().date_naive())?
+                    .format("%Y-%m-%d")
+                    .to_string(),
+            ))
+        }
+    }
+}
+
+fn compile_condition(
+    field: Field,
+    operator: Operator,
+    value: &serde_json::Value,
+    params: &mut Vec<Value>,
+) -> Result<String, String> {
+    use Operator::*;
+
+    let kind = field.kind();
+    let unsupported = || {
+        Err(format!(
+            "Operator {:?} is not supported for field {:?}",
+            operator, field
+        ))
+    };
+
+    if kind == Kind::Tag {
+        const HAS_TAG: &str = "EXISTS (SELECT 1 FROM book_tags t WHERE t.book_id = books.id";
+        return match operator {
+            Eq => {
+                params.push(scalar(field, value)?);
+                Ok(format!("{} AND lower(t.tag) = ?)", HAS_TAG))
+            }
+            Ne => {
+                params.push(scalar(field, value)?);
+                Ok(format!("NOT {} AND lower(t.tag) = ?)", HAS_TAG))
+            }
+            Contains => {
+                params.push(scalar(field, value)?);
+                Ok(format!("{} AND instr(lower(t.tag), ?) > 0)", HAS_TAG))
+            }
+            IsEmpty => Ok(format!("NOT {})", HAS_TAG)),
+            IsNotEmpty => Ok(format!("{})", HAS_TAG)),
+            _ => unsupported(),
+        };
+    }
+
+    let column = field.column();
+    let text = kind == Kind::Text;
+    let lhs = if text {
+        format!("lower({})", column)
+    } else {
+        column.to_string()
+    };
+
+    match operator {
+        IsEmpty if text => Ok(format!("({0} IS NULL OR {0} = '')", column)),
+        IsNotEmpty if text => Ok(format!("({0} IS NOT NULL AND {0} <> '')", column)),
+        IsEmpty => Ok(format!("{} IS NULL", column)),
+        IsNotEmpty => Ok(format!("{} IS NOT NULL", column)),
+        Eq => {
+            params.push(scalar(field, value)?);
+            Ok(format!("{} = ?", lhs))
+        }
+        Ne => {
+            params.push(scalar(field, value)?);
+            Ok(format!("({} IS NULL OR {} <> ?)", column, lhs))
+        }
+        Contains | StartsWith if text => {
+            params.push(scalar(field, value)?);
+            let cmp = if operator == Contains { "> 0" } else { "= 1" };
+            Ok(format!("instr({}, ?) {}", lhs, cmp))
+        }
+        Gt | Gte | Lt | Lte if !text => {
+            params.push(scalar(field, value)?);
+            let op = match operator {
+                Gt => ">",
+                Gte => ">=",
+                Lt => "<",
+                _ => "<=",
+            };
+            Ok(format!("{} {} ?", lhs, op))
+        }
+        Between if !text => {
+            let bounds = value
+                .as_array()
+                .filter(|a| a.len() == 2)
+                .ok_or_else(|| format!("Between on {:?} needs a [from, to] array", field))?;
+            params.push(scalar(field, &bounds[0])?);
+            params.push(scalar(field, &bounds[1])?);
+            Ok(format!("{} BETWEEN ? AND ?", lhs))
+        }
+        _ => unsupported(),
+    }
+}
+
+fn compile_rule(rule: &Rule, depth: usize, params: &mut Vec<Value>) -> Result<String, String> {
+    match rule {
+        Rule::Condition {
+            field,
+            operator,
+            value,
+        } => compile_condition(*field, *operator, value, params),
+        Rule::Group { op, rules } => {
+            if depth >= MAX_DEPTH {
+                return Err(format!("Rule groups nest deeper than {} levels", MAX_DEPTH));
+            }
+            if rules.is_empty() {
+                return Err("Rule groups must contain at least one rule".to_string());
+            }
+
+            let joiner = match op {
+                GroupOp::And => " AND ",
+                GroupOp::Or => " OR ",
+            };
+            let parts = rules
+                .iter()
+                .map(|r| compile_rule(r, depth + 1, params))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(format!("({})", parts.join(joiner)))
+        }
+    }
+}
+
+/// Compile a rule tree to a SQL predicate over `books` plus its parameters
+pub fn compile(rule: &Rule) -> Result<(String, Vec<Value>), String> {
+    let mut params = Vec::new();
+    let sql = compile_rule(rule, 0, &mut params)?;
+    // Books in the trash never belong to a collection
+    Ok((format!("books.deleted_at IS NULL AND {}", sql), params))
+}
+
+// ============================================================================
+// Queries
+// ============================================================================
+
+fn load_collection(conn: &Connection, id: i64) -> rusqlite::Result<Option<Collection>> {
+    conn.query_row(
+        "SELECT id, name, rules, created_at, updated_at FROM collections WHERE id = ?1",
+        [id],
+        collection_from_row,
+    )
+    .optional()
+}
+
+fn collection_from_row(row: &rusqlite::Row) -> rusqlite::Result<Collection> {
+    let rules: String = row.get(2)?;
+    Ok(Collection {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        rules: serde_json::from_str(&rules).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+    })
+}
+
+fn list_all(conn: &Connection) -> rusqlite::Result<Vec<Collection>> {
+    let mut stmt = conn
+        .prepare("SELECT id, name, rules, created_at, updated_at FROM collections ORDER BY name")?;
+    let collections = stmt
+        .query_map([], collection_from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(collections)
+}
+
+/// Rebuild the stored membership of one collection
+fn refresh_collection(conn: &Connection, collection: &Collection) -> rusqlite::Result<()> {
+    let (predicate, mut params) = match compile(&collection.rules) {
+        Ok(compiled) => compiled,
+        Err(e) => {
+            warn!("Skipping collection {}: {}", collection.id, e);
+            return Ok(());
+        }
+    };
+
+    conn.execute(
+        "DELETE FROM collection_members WHERE collection_id = ?1",
+        [collection.id],
+    )?;
+
+    params.insert(0, Value::Integer(collection.id));
+    conn.execute(
+        &format!(
+            "INSERT INTO collection_members (collection_id, book_id)
+             SELECT ?, books.id FROM books WHERE {}",
+            predicate
+        ),
+        params_from_iter(params),
+    )?;
+    Ok(())
+}
+
+/// Re-check one book against every collection. Called whenever a book's
+/// metadata changes so memberships stay current.
+pub fn refresh_book_memberships(conn: &Connection, book_id: i64) -> rusqlite::Result<()> {
+    for collection in list_all(conn)? {
+        let (predicate, mut params) = match compile(&collection.rules) {
+            Ok(compiled) => compiled,
+            Err(e) => {
+                warn!("Skipping collection {}: {}", collection.id, e);
+                continue;
+            }
+        };
+
+        params.push(Value::Integer(book_id));
+        let matches: bool = conn.query_row(
+            &format!(
+                "SELECT EXISTS (SELECT 1 FROM books WHERE ({}) AND books.id = ?)",
+                predicate
+            ),
+            params_from_iter(params),
+            |row| row.get(0),
+        )?;
+
+        if matches {
+            conn.execute(
+                "INSERT OR IGNORE INTO collection_members (collection_id, book_id) VALUES (?1, ?2)",
+                params![collection.id, book_id],
+            )?;
+        } else {
+            conn.execute(
+                "DELETE FROM collection_members WHERE collection_id = ?1 AND book_id = ?2",
+                params![collection.id, book_id],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Create a smart collection. Rules are validated (and compiled) up front
+/// so invalid combinations fail here rather than at evaluation.
+#[tauri::command]
+pub async fn create_collection(
+    db: State<'_, Database>,
+    name: String,
+    rules: Rule,
+) -> Result<Collection, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Collection name cannot be empty".to_string());
+    }
+
+    compile(&rules)?;
+    info!("Creating collection: {}", name);
+
+    let rules_json =
+        serde_json::to_string(&rules).map_err(|e| format!("Failed to serialize rules: {}", e))?;
+    let now = chrono::Utc::now().to_rfc3339();
+
+    db.with_conn(|conn| {
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO collections (name, rules, created_at, updated_at) VALUES (?1, ?2, ?3, ?3)",
+            params![name, rules_json, now],
+        )?;
+        let collection = Collection {
+            id: tx.last_insert_rowid(),
+            name,
+            rules,
+            created_at: now.clone(),
+            updated_at: now,
+        };
+        refresh_collection(&tx, &collection)?;
+        tx.commit()?;
+        Ok(collection)
+    })
+}
+
+/// List all smart collections
+#[tauri::command]
+pub async fn list_collections(db: State<'_, Database>) -> Result<Vec<Collection>, String> {
+    db.with_conn(|conn| list_all(conn))
+}
+
+/// Delete a smart collection (books are untouched)
+#[tauri::command]
+pub async fn delete_collection(db: State<'_, Database>, collection_id: i64) -> Result<(), String> {
+    db.with_conn(|conn| {
+        conn.execute("DELETE FROM collections WHERE id = ?1", [collection_id])
+            .map(|_| ())
+    })
+}
+
+/// Evaluate a collection, returning one page of matching books
+#[tauri::command]
+pub async fn evaluate_collection(
+    db: State<'_, Database>,
+    collection_id: i64,
+    offset: Option<u32>,
+    limit: Option<u32>,
+) -> Result<CollectionPage, String> {
+    let offset = offset.unwrap_or(0);
+    let limit = limit.unwrap_or(100).min(MAX_PAGE_SIZE);
+
+    let collection = db
+        .with_conn(|conn| load_collection(conn, collection_id))?
+        .ok_or_else(|| format!("Collection not found: {}", collection_id))?;
+
+    // Relative dates ("-30d") resolve against today on every evaluation
+    let (predicate, params) = compile(&collection.rules)?;
+
+    db.with_conn(|conn| {
+        // Relative date rules drift over time, so resync stored membership
+        refresh_collection(conn, &collection)?;
+
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM books WHERE {}", predicate),
+            params_from_iter(params.iter()),
+            |row| row.get(0),
+        )?;
+
+        let mut page_params = params.clone();
+        page_params.push(Value::Integer(limit as i64));
+        page_params.push(Value::Integer(offset as i64));
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM books WHERE {} ORDER BY books.title COLLATE NOCASE, books.id
+             LIMIT ? OFFSET ?",
+            BOOK_COLUMNS, predicate
+        ))?;
+        let mut books = stmt
+            .query_map(params_from_iter(page_params), library::book_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for book in &mut books {
+            book.tags = library::book_tags(conn, book.id)?;
+        }
+
+        Ok(CollectionPage {
+            collection_id,
+            total,
+            offset,
+            books,
+        })
+    })
+}
+
+/// Ids of the collections a book currently belongs to
+#[tauri::command]
+pub async fn get_book_collections(
+    db: State<'_, Database>,
+    book_id: i64,
+) -> Result<Vec<i64>, String> {
+    db.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT collection_id FROM collection_members WHERE book_id = ?1 ORDER BY collection_id",
+        )?;
+        let ids = stmt
+            .query_map([book_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+        Ok(ids)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const FIELDS: [Field; 10] = [
+        Field::Title,
+        Field::Tag,
+        Field::Author,
+        Field::Series,
+        Field::Format,
+        Field::WordCount,
+        Field::Progress,
+        Field::Rating,
+        Field::AddedAt,
+        Field::FinishedAt,
+    ];
+
+    const OPERATORS: [Operator; 11] = [
+        Operator::Eq,
+        Operator::Ne,
+        Operator::Contains,
+        Operator::StartsWith,
+        Operator::Gt,
+        Operator::Gte,
+        Operator::Lt,
+        Operator::Lte,
+        Operator::Between,
+        Operator::IsEmpty,
+        Operator::IsNotEmpty,
+    ];
+
+    fn date(raw: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(raw, "%Y-%m-%d").unwrap()
+    }
+
+    fn sample(field: Field) -> serde_json::Value {
+        match field.kind() {
+            Kind::Text | Kind::Tag => json!("Fantasy"),
+            Kind::Number => json!(5),
+            Kind::Date => json!("2024-01-01"),
+        }
+    }
+
+    fn condition(field: Field, operator: Operator) -> Result<(String, Vec<Value>), String> {
+        let value = if operator == Operator::Between {
+            json!([sample(field), sample(field)])
+        } else {
+            sample(field)
+        };
+        let mut params = Vec::new();
+        compile_condition(field, operator, &value, &mut params).map(|sql| (sql, params))
+    }
+
+    #[test]
+    fn supports_operators_by_field_kind() {
+        use Operator::*;
+
+        for field in FIELDS {
+            for operator in OPERATORS {
+                let supported = match field.kind() {
+                    Kind::Text => matches!(
+                        operator,
+                        Eq | Ne | Contains | StartsWith | IsEmpty | IsNotEmpty
+                    ),
+                    Kind::Tag => matches!(operator, Eq | Ne | Contains | IsEmpty | IsNotEmpty),
+                    Kind::Number | Kind::Date => !matches!(operator, Contains | StartsWith),
+                };
+                let result = condition(field, operator);
+                assert_eq!(
+                    result.is_ok(),
+                    supported,
+                    "{:?} {:?}: {:?}",
+                    field,
+                    operator,
+                    result
+                );
+                if !supported {
+                    assert!(result.unwrap_err().contains("is not supported"));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compiles_conditions_to_sql() {
+        let (sql, params) = condition(Field::Title, Operator::Contains).unwrap();
+        assert_eq!(sql, "instr(lower(books.title), ?) > 0");
+        assert_eq!(params, [Value::Text("fantasy".into())]);
+
+        let (sql, _) = condition(Field::Author, Operator::StartsWith).unwrap();
+        assert_eq!(sql, "instr(lower(books.author), ?) = 1");
+
+        let (sql, _) = condition(Field::Series, Operator::Ne).unwrap();
+        assert_eq!(
+            sql,
+            "(books.series_name IS NULL OR lower(books.series_name) <> ?)"
+        );
+
+        let (sql, params) = condition(Field::Rating, Operator::Gte).unwrap();
+        assert_eq!(sql, "books.rating >= ?");
+        assert_eq!(params, [Value::Real(5.0)]);
+
+        let (sql, params) = condition(Field::AddedAt, Operator::Between).unwrap();
+        assert_eq!(sql, "substr(books.added_at, 1, 10) BETWEEN ? AND ?");
+        assert_eq!(params.len(), 2);
+
+        let (sql, params) = condition(Field::Format, Operator::IsEmpty).unwrap();
+        assert_eq!(sql, "(books.format IS NULL OR books.format = '')");
+        assert!(params.is_empty());
+
+        let (sql, _) = condition(Field::WordCount, Operator::IsNotEmpty).unwrap();
+        assert_eq!(sql, "books.word_count IS NOT NULL");
+
+        let (sql, _) = condition(Field::Tag, Operator::Ne).unwrap();
+        assert!(sql.starts_with("NOT EXISTS (SELECT 1 FROM book_tags t"));
+        let (sql, _) = condition(Field::Tag, Operator::IsEmpty).unwrap();
+        assert!(sql.starts_with("NOT EXISTS") && !sql.contains('?'));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut params = Vec::new();
+        let mut compile =
+            |field, operator, value| compile_condition(field, operator, &value, &mut params);
+
+        assert!(compile(Field::Title, Operator::Eq, json!("   ")).is_err());
+        assert!(compile(Field::Tag, Operator::Eq, json!(3)).is_err());
+        assert!(compile(Field::Rating, Operator::Gt, json!("five")).is_err());
+        assert!(compile(Field::AddedAt, Operator::Lt, json!("March 1st")).is_err());
+        assert!(compile(Field::AddedAt, Operator::Lt, json!(20240301)).is_err());
+        assert!(compile(Field::Progress, Operator::Between, json!(50)).is_err());
+        assert!(compile(Field::Progress, Operator::Between, json!([1, 2, 3])).is_err());
+        assert!(compile(Field::Progress, Operator::Between, json!([1, "x"])).is_err());
+    }
+
+    #[test]
+    fn compiles_rule_groups() {
+        let rule: Rule = serde_json::from_value(json!({
+            "type": "group",
+            "op": "or",
+            "rules": [
+                { "type": "condition", "field": "rating", "operator": "gte", "value": 4 },
+                {
+                    "type": "group",
+                    "op": "and",
+                    "rules": [
+                        { "type": "condition", "field": "format", "operator": "eq", "value": "EPUB" },
+                        { "type": "condition", "field": "finishedAt", "operator": "isEmpty" }
+                    ]
+                }
+            ]
+        }))
+        .unwrap();
+
+        let (sql, params) = compile(&rule).unwrap();
+        assert_eq!(
+            sql,
+            "books.deleted_at IS NULL AND (books.rating >= ? OR \
+             (lower(books.format) = ? AND substr(books.finished_at, 1, 10) IS NULL))"
+        );
+        assert_eq!(params, [Value::Real(4.0), Value::Text("epub".into())]);
+    }
+
+    #[test]
+    fn rejects_invalid_rule_groups() {
+        let empty = Rule::Group {
+            op: GroupOp::And,
+            rules: Vec::new(),
+        };
+        assert!(compile(&empty).unwrap_err().contains("at least one rule"));
+
+        let mut nested = Rule::Condition {
+            field: Field::Title,
+            operator: Operator::IsEmpty,
+            value: serde_json::Value::Null,
+        };
+        for _ in 0..MAX_DEPTH {
+            nested = Rule::Group {
+                op: GroupOp::And,
+                rules: vec![nested],
+            };
+        }
+        assert!(compile(&nested).is_ok());
+
+        let too_deep = Rule::Group {
+            op: GroupOp::And,
+            rules: vec![nested],
+        };
+        assert!(compile(&too_deep).unwrap_err().contains("nest deeper"));
+
+        let bad_child = Rule::Group {
+            op: GroupOp::Or,
+            rules: vec![Rule::Condition {
+                field: Field::Tag,
+                operator: Operator::Gt,
+                value: json!("x"),
+            }],
+        };
+        assert!(compile(&bad_child).is_err());
+    }
+
+    #[test]
+    fn resolves_named_and_relative_dates() {
+        // A Friday
+        let today = date("2024-03-15");
+
+        assert_eq!(resolve_date("today", today), Ok(today));
+        assert_eq!(resolve_date("startOfWeek", today), Ok(date("2024-03-11")));
+        assert_eq!(resolve_date("startOfMonth", today), Ok(date("2024-03-01")));
+        assert_eq!(resolve_date("startOfYear", today), Ok(date("2024-01-01")));
+        assert_eq!(resolve_date("-30d", today), Ok(date("2024-02-14")));
+        assert_eq!(resolve_date("-2w", today), Ok(date("2024-03-01")));
+        assert_eq!(resolve_date("-6m", today), Ok(date("2023-09-15")));
+        assert_eq!(resolve_date("-1y", today), Ok(date("2023-03-15")));
+        assert_eq!(resolve_date("-0d", today), Ok(today));
+        assert_eq!(resolve_date("2024-02-29", today), Ok(date("2024-02-29")));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        let today = date("2024-03-15");
+
+        for raw in [
+            "-",
+            "-d",
+            "-30",
+            "-30x",
+            "-3é",
+            "-é",
+            "-+3d",
+            "-1.5m",
+            "-4294967295y",
+            "2024-02-30",
+            "yesterday",
+            "",
+        ] {
+            assert!(resolve_date(raw, today).is_err(), "{:?}", raw);
+        }
+    }
+}
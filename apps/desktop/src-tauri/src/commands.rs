@@ -6,8 +6,14 @@ use log::info;
 use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Runtime};
 use tauri_plugin_dialog::DialogExt;
-use tauri_plugin_notification::NotificationExt;
-use tauri_plugin_store::StoreExt;
+use tauri_plugin_shell::ShellExt;
+
+use crate::error::AppError;
+use crate::notification_policy::NotificationCategory;
+use crate::notifications::{self, NotificationAction};
+use crate::persist;
+use crate::profiles::ProfileStoreExt;
+use crate::safe_mode;
 
 // ============================================================================
 // Types
@@ -67,7 +73,7 @@ pub async fn open_file_dialog<R: Runtime>(
     app: AppHandle<R>,
     title: Option<String>,
     multiple: Option<bool>,
-) -> Result<FileDialogResult, String> {
+) -> Result<FileDialogResult, AppError> {
     info!("Opening file dialog: {:?}", title);
 
     let mut dialog = app.dialog().file();
@@ -78,9 +84,16 @@ pub async fn open_file_dialog<R: Runtime>(
 
     // Add book file filters
     dialog = dialog
-        .add_filter("Books", &["epub", "pdf"])
+        .add_filter(
+            "Books",
+            &[
+                "epub", "pdf", "mobi", "azw3", "azw", "md", "markdown", "txt",
+            ],
+        )
         .add_filter("EPUB", &["epub"])
         .add_filter("PDF", &["pdf"])
+        .add_filter("Kindle", &["mobi", "azw3", "azw"])
+        .add_filter("Markdown & Text", &["md", "markdown", "txt"])
         .add_filter("All Files", &["*"]);
 
     let result = if multiple.unwrap_or(false) {
@@ -117,7 +130,8 @@ pub async fn save_file_dialog<R: Runtime>(
     app: AppHandle<R>,
     title: Option<String>,
     default_name: Option<String>,
-) -> Result<SaveDialogResult, String> {
+    extensions: Option<Vec<String>>,
+) -> Result<SaveDialogResult, AppError> {
     info!("Opening save dialog: {:?}", title);
 
     let mut dialog = app.dialog().file();
@@ -130,6 +144,11 @@ pub async fn save_file_dialog<R: Runtime>(
         dialog = dialog.set_file_name(&name);
     }
 
+    // One filter per allowed extension, e.g. CSV and JSON for exports
+    for ext in extensions.unwrap_or_default() {
+        dialog = dialog.add_filter(ext.to_uppercase(), &[ext.as_str()]);
+    }
+
     let result = match dialog.save_file() {
         Some(path) => SaveDialogResult {
             canceled: false,
@@ -151,41 +170,77 @@ pub async fn save_file_dialog<R: Runtime>(
 
 /// Read file contents
 #[tauri::command]
-pub async fn read_file(path: String) -> Result<Vec<u8>, String> {
+pub async fn read_file(path: String) -> Result<Vec<u8>, AppError> {
     info!("Reading file: {}", path);
-    std::fs::read(&path).map_err(|e| format!("Failed to read file: {}", e))
+    Ok(std::fs::read(&path)?)
 }
 
 /// Write file contents
 #[tauri::command]
-pub async fn write_file(path: String, contents: Vec<u8>) -> Result<(), String> {
+pub async fn write_file(path: String, contents: Vec<u8>) -> Result<(), AppError> {
     info!("Writing file: {}", path);
-    std::fs::write(&path, contents).map_err(|e| format!("Failed to write file: {}", e))
+    Ok(std::fs::write(&path, contents)?)
+}
+
+// ============================================================================
+// External Links
+// ============================================================================
+
+/// URL schemes allowed to leave the app
+const EXTERNAL_URL_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// Open a link from book content in the default browser or mail client.
+/// Only http, https and mailto are allowed so a malicious book can't open
+/// local files or trigger arbitrary protocol handlers.
+#[tauri::command]
+pub async fn open_external_url<R: Runtime>(app: AppHandle<R>, url: String) -> Result<(), AppError> {
+    let parsed = url::Url::parse(url.trim()).map_err(|e| AppError::InvalidInput {
+        message: "Invalid URL".to_string(),
+        details: Some(e.to_string()),
+    })?;
+
+    if !EXTERNAL_URL_SCHEMES.contains(&parsed.scheme()) {
+        return Err(AppError::invalid_input(format!(
+            "Refusing to open URL with scheme '{}'",
+            parsed.scheme()
+        )));
+    }
+
+    info!("Opening external URL: {}", parsed);
+
+    #[allow(deprecated)]
+    app.shell()
+        .open(parsed.as_str(), None)
+        .map_err(|e| AppError::tauri("Failed to open URL", e))
 }
 
 // ============================================================================
 // Notification Commands
 // ============================================================================
 
-/// Show a notification
+/// Show a notification and return its id, or `None` if the notification
+/// policy held it back. Clicking it shows the window and emits
+/// `notification-clicked` with `on_click_route`; each action becomes a
+/// button emitting `notification-action`.
 #[tauri::command]
 pub async fn show_notification<R: Runtime>(
     app: AppHandle<R>,
+    category: Option<NotificationCategory>,
     title: String,
     body: Option<String>,
-) -> Result<(), String> {
+    actions: Option<Vec<NotificationAction>>,
+    on_click_route: Option<String>,
+) -> Result<Option<u32>, AppError> {
     info!("Showing notification: {}", title);
 
-    let mut notification = app.notification().builder();
-    notification = notification.title(&title);
-
-    if let Some(b) = body {
-        notification = notification.body(&b);
-    }
-
-    notification
-        .show()
-        .map_err(|e| format!("Failed to show notification: {}", e))
+    Ok(notifications::show(
+        &app,
+        category,
+        &title,
+        body.as_deref(),
+        actions.unwrap_or_default(),
+        on_click_route,
+    )?)
 }
 
 // ============================================================================
@@ -197,12 +252,12 @@ pub async fn show_notification<R: Runtime>(
 pub async fn get_store_value<R: Runtime>(
     app: AppHandle<R>,
     key: String,
-) -> Result<Option<serde_json::Value>, String> {
+) -> Result<Option<serde_json::Value>, AppError> {
     info!("Getting store value: {}", key);
 
     let store = app
-        .store("settings.json")
-        .map_err(|e| format!("Failed to open store: {}", e))?;
+        .profile_store("settings.json")
+        .map_err(|e| AppError::tauri("Failed to open store", e))?;
 
     Ok(store.get(&key))
 }
@@ -213,17 +268,16 @@ pub async fn set_store_value<R: Runtime>(
     app: AppHandle<R>,
     key: String,
     value: serde_json::Value,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     info!("Setting store value: {} = {:?}", key, value);
 
     let store = app
-        .store("settings.json")
-        .map_err(|e| format!("Failed to open store: {}", e))?;
+        .profile_store("settings.json")
+        .map_err(|e| AppError::tauri("Failed to open store", e))?;
 
     store.set(&key, value);
-    store
-        .save()
-        .map_err(|e| format!("Failed to save store: {}", e))
+    persist::mark_dirty(&app, "settings.json");
+    Ok(())
 }
 
 // ============================================================================
@@ -232,7 +286,12 @@ pub async fn set_store_value<R: Runtime>(
 
 /// Check for application updates
 #[tauri::command]
-pub async fn check_for_updates<R: Runtime>(app: AppHandle<R>) -> Result<bool, String> {
+pub async fn check_for_updates<R: Runtime>(app: AppHandle<R>) -> Result<bool, AppError> {
+    if safe_mode::is_active() {
+        return Err(AppError::unavailable_in_safe_mode(
+            "Updates are off in safe mode",
+        ));
+    }
     info!("Checking for updates...");
 
     // Use the updater plugin
@@ -250,11 +309,9 @@ pub async fn check_for_updates<R: Runtime>(app: AppHandle<R>) -> Result<bool, St
                     info!("No updates available");
                     Ok(false)
                 }
-                Err(e) => {
-                    Err(format!("Failed to check for updates: {}", e))
-                }
+                Err(e) => Err(AppError::network("Failed to check for updates", e)),
             }
         }
-        Err(e) => Err(format!("Updater not available: {}", e)),
+        Err(e) => Err(AppError::tauri("Updater not available", e)),
     }
 }
@@ -0,0 +1,753 @@
+// Read Master Desktop - Cover Thumbnails
+//
+// Small WebP thumbnails for the library grid, fetched in batches instead of
+// one `read_file` per cover. Thumbnails live in `covers/thumbs` under app
+// data, one per book and size, and are generated on first request by a
+// small worker pool. Requests for the visible viewport are marked
+// `Visible` and jump ahead of queued off-screen work. Recently served
+// thumbnails are also kept in memory.
+//
+// The directory is capped (200 MB by default, configurable in settings).
+// Last access times are kept in a small `index.json` beside the
+// thumbnails, and on startup the least recently used files are deleted
+// until the directory is back under the cap.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::Cursor;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Condvar, Mutex, Once};
+
+use base64::Engine;
+use image::{DynamicImage, ImageFormat};
+use log::{info, warn};
+use lru::LruCache;
+use rusqlite::params_from_iter;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::db::Database;
+use crate::persist;
+use crate::profiles::{self, ProfileStoreExt};
+
+/// Thumbnails kept decoded in memory
+const CACHE_CAPACITY: usize = 1000;
+
+/// Longest side when no size is given
+const DEFAULT_SIZE: u32 = 160;
+const MIN_SIZE: u32 = 32;
+const MAX_SIZE: u32 = 512;
+
+/// Generation threads; decoding large covers is CPU bound
+const WORKERS: usize = 4;
+
+const THUMBS_DIR: &str = "thumbs";
+
+/// Access times of the files in `THUMBS_DIR`
+const INDEX_FILE: &str = "index.json";
+
+/// Disk space thumbnails may take before the oldest are evicted
+const DEFAULT_CACHE_LIMIT: u64 = 200 * 1024 * 1024;
+
+const SETTINGS_STORE: &str = "settings.json";
+const CACHE_LIMIT_SETTING: &str = "covers.cacheLimitBytes";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CoverPriority {
+    /// Covers on screen now
+    Visible,
+    /// Covers the user may scroll to
+    #[default]
+    Background,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverThumbnail {
+    pub book_id: i64,
+    /// `data:image/webp;base64,...`, or None when the book has no usable
+    /// cover
+    pub data_url: Option<String>,
+}
+
+struct Job {
+    book_id: i64,
+    source: PathBuf,
+    dest: PathBuf,
+    size: u32,
+    reply: Sender<(i64, Option<Vec<u8>>)>,
+}
+
+#[derive(Default)]
+struct Lanes {
+    visible: VecDeque<Job>,
+    background: VecDeque<Job>,
+}
+
+#[derive(Default)]
+struct Queue {
+    lanes: Mutex<Lanes>,
+    ready: Condvar,
+    started: Once,
+}
+
+impl Queue {
+    fn push(self: &Arc<Self>, job: Job, priority: CoverPriority) {
+        self.started.call_once(|| {
+            for i in 0..WORKERS {
+                let queue = Arc::clone(self);
+                let _ = std::thread::Builder::new()
+                    .name(format!("cover-thumbs-{}", i))
+                    .spawn(move || queue.work());
+            }
+        });
+
+        if let Ok(mut lanes) = self.lanes.lock() {
+            match priority {
+                CoverPriority::Visible => lanes.visible.push_back(job),
+                CoverPriority::Background => lanes.background.push_back(job),
+            }
+            self.ready.notify_one();
+        }
+    }
+
+    fn next(&self) -> Option<Job> {
+        let mut lanes = self.lanes.lock().ok()?;
+        loop {
+            if let Some(job) = lanes
+                .visible
+                .pop_front()
+                .or_else(|| lanes.background.pop_front())
+            {
+                return Some(job);
+            }
+            lanes = self.ready.wait(lanes).ok()?;
+        }
+    }
+
+    fn work(&self) {
+        while let Some(job) = self.next() {
+            let data = thumbnail(&job.source, &job.dest, job.size)
+                .map_err(|e| warn!("No cover thumbnail for book {}: {}", job.book_id, e))
+                .ok();
+            let _ = job.reply.send((job.book_id, data));
+        }
+    }
+}
+
+/// A thumbnail file considered for eviction
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CachedFile {
+    name: String,
+    size: u64,
+    /// Seconds since the epoch
+    accessed: i64,
+}
+
+/// Managed thumbnail cache and generation queue
+pub struct CoverThumbnails {
+    cache: Mutex<LruCache<(i64, u32), Arc<Vec<u8>>>>,
+    queue: Arc<Queue>,
+    /// Last access of each file on disk, by file name
+    access: Mutex<HashMap<String, i64>>,
+}
+
+impl Default for CoverThumbnails {
+    fn default() -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(
+                NonZeroUsize::new(CACHE_CAPACITY).expect("cache capacity is non-zero"),
+            )),
+            queue: Arc::default(),
+            access: Mutex::default(),
+        }
+    }
+}
+
+impl CoverThumbnails {
+    fn cached(&self, book_id: i64, size: u32) -> Option<Arc<Vec<u8>>> {
+        self.cache.lock().ok()?.get(&(book_id, size)).cloned()
+    }
+
+    fn insert(&self, book_id: i64, size: u32, data: Arc<Vec<u8>>) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.put((book_id, size), data);
+        }
+    }
+
+    /// Record that these thumbnails were just served and save the index
+    fn touch(&self, dir: &Path, book_ids: &[i64], size: u32) {
+        let now = chrono::Utc::now().timestamp();
+        let Ok(mut access) = self.access.lock() else {
+            return;
+        };
+        for &book_id in book_ids {
+            access.insert(thumb_name(book_id, size), now);
+        }
+        if let Err(e) = save_index(dir, &access) {
+            warn!("{}", e);
+        }
+    }
+}
+
+// ============================================================================
+// Thumbnails
+// ============================================================================
+
+fn thumbs_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    profiles::data_dir(app).map(|dir| dir.join("covers").join(THUMBS_DIR))
+}
+
+fn thumb_name(book_id: i64, size: u32) -> String {
+    format!("{}-{}.webp", book_id, size)
+}
+
+fn thumb_path(dir: &Path, book_id: i64, size: u32) -> PathBuf {
+    dir.join(thumb_name(book_id, size))
+}
+
+/// Whether `dest` was written after `source` last changed
+fn is_fresh(source: &Path, dest: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(source), modified(dest)) {
+        (Some(source), Some(dest)) => dest >= source,
+        _ => false,
+    }
+}
+
+/// Scale `data` to fit within `size` pixels on its longest side, as WebP
+fn encode_thumbnail(data: &[u8], size: u32) -> Result<Vec<u8>, String> {
+    let image =
+        image::load_from_memory(data).map_err(|e| format!("Failed to decode cover: {}", e))?;
+    let thumb = DynamicImage::from(image.thumbnail(size, size).to_rgba8());
+
+    let mut out = Cursor::new(Vec::new());
+    thumb
+        .write_to(&mut out, ImageFormat::WebP)
+        .map_err(|e| format!("Failed to encode thumbnail: {}", e))?;
+    Ok(out.into_inner())
+}
+
+/// The thumbnail on disk, generated first if missing or stale
+fn thumbnail(source: &Path, dest: &Path, size: u32) -> Result<Vec<u8>, String> {
+    if is_fresh(source, dest) {
+        if let Ok(data) = std::fs::read(dest) {
+            return Ok(data);
+        }
+    }
+
+    let cover = std::fs::read(source).map_err(|e| format!("Failed to read cover: {}", e))?;
+    let data = encode_thumbnail(&cover, size)?;
+    if let Some(dir) = dest.parent() {
+        std::fs::create_dir_all(dir)
+            .and_then(|_| std::fs::write(dest, &data))
+            .map_err(|e| format!("Failed to save thumbnail: {}", e))?;
+    }
+    Ok(data)
+}
+
+fn data_url(data: &[u8]) -> String {
+    format!(
+        "data:image/webp;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(data)
+    )
+}
+
+fn cover_paths(db: &Database, book_ids: &[i64]) -> Result<HashMap<i64, PathBuf>, String> {
+    if book_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let placeholders = vec!["?"; book_ids.len()].join(", ");
+    db.with_conn(|conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, cover_path FROM books WHERE cover_path IS NOT NULL AND id IN ({})",
+            placeholders
+        ))?;
+        let rows = stmt.query_map(params_from_iter(book_ids), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                PathBuf::from(row.get::<_, String>(1)?),
+            ))
+        })?;
+        rows.collect()
+    })
+}
+
+// ============================================================================
+// Eviction
+// ============================================================================
+
+fn load_index(dir: &Path) -> HashMap<String, i64> {
+    std::fs::read(dir.join(INDEX_FILE))
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+fn save_index(dir: &Path, access: &HashMap<String, i64>) -> Result<(), String> {
+    let data = serde_json::to_vec(access)
+        .map_err(|e| format!("Failed to serialize thumbnail index: {}", e))?;
+    std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(dir.join(INDEX_FILE), data))
+        .map_err(|e| format!("Failed to save thumbnail index: {}", e))
+}
+
+/// Thumbnail files in `dir` with their sizes and last access, falling back
+/// to the modification time for files the index doesn't know
+fn cached_files(dir: &Path, access: &HashMap<String, i64>) -> Vec<CachedFile> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let meta = entry.metadata().ok().filter(|meta| meta.is_file())?;
+            if name == INDEX_FILE {
+                return None;
+            }
+            let modified = meta
+                .modified()
+                .ok()
+                .map(|time| chrono::DateTime::<chrono::Utc>::from(time).timestamp())
+                .unwrap_or_default();
+            Some(CachedFile {
+                accessed: access.get(&name).copied().unwrap_or(modified),
+                size: meta.len(),
+                name,
+            })
+        })
+        .collect()
+}
+
+/// Files to delete, least recently used first, to bring the total under
+/// `limit`
+fn eviction_plan(mut files: Vec<CachedFile>, limit: u64) -> Vec<CachedFile> {
+    let mut total: u64 = files.iter().map(|file| file.size).sum();
+    files.sort_by(|a, b| {
+        a.accessed
+            .cmp(&b.accessed)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    files
+        .into_iter()
+        .take_while(|file| {
+            let over = total > limit;
+            total -= file.size;
+            over
+        })
+        .collect()
+}
+
+/// Delete least recently used thumbnails until `dir` is under `limit`,
+/// and drop index entries for files that are gone. Returns the number of
+/// files and bytes freed.
+fn evict(dir: &Path, access: &mut HashMap<String, i64>, limit: u64) -> (usize, u64) {
+    let files = cached_files(dir, access);
+    let mut freed = (0, 0);
+    for file in eviction_plan(files, limit) {
+        match std::fs::remove_file(dir.join(&file.name)) {
+            Ok(()) => {
+                freed.0 += 1;
+                freed.1 += file.size;
+            }
+            Err(e) => warn!("Failed to evict thumbnail {}: {}", file.name, e),
+        }
+    }
+
+    access.retain(|name, _| dir.join(name).is_file());
+    if let Err(e) = save_index(dir, access) {
+        warn!("{}", e);
+    }
+    freed
+}
+
+fn cache_limit<R: Runtime>(app: &AppHandle<R>) -> u64 {
+    app.profile_store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(CACHE_LIMIT_SETTING))
+        .and_then(|value| value.as_u64())
+        .unwrap_or(DEFAULT_CACHE_LIMIT)
+}
+
+fn evict_to_limit<R: Runtime>(app: &AppHandle<R>) {
+    let Ok(dir) = thumbs_dir(app) else {
+        return;
+    };
+    let limit = cache_limit(app);
+    let thumbs = app.state::<CoverThumbnails>();
+    let Ok(mut access) = thumbs.access.lock() else {
+        return;
+    };
+    let (files, bytes) = evict(&dir, &mut access, limit);
+    if files > 0 {
+        info!(
+            "Evicted {} cover thumbnails ({} bytes) to stay under {} bytes",
+            files, bytes, limit
+        );
+    }
+}
+
+/// Load the access index and trim the thumbnail directory to its cap, off
+/// the main thread
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        if let Ok(dir) = thumbs_dir(&app) {
+            let loaded = load_index(&dir);
+            if let Ok(mut access) = app.state::<CoverThumbnails>().access.lock() {
+                // Keep anything touched while the file was loading
+                for (name, time) in loaded {
+                    access.entry(name).or_insert(time);
+                }
+            }
+        }
+        evict_to_limit(&app);
+    });
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Thumbnails for `book_ids`, in the same order, generating missing ones.
+/// `size` is the longest side in pixels (32-512, default 160).
+#[tauri::command]
+pub async fn get_cover_thumbnails<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    thumbs: State<'_, CoverThumbnails>,
+    book_ids: Vec<i64>,
+    size: Option<u32>,
+    priority: Option<CoverPriority>,
+) -> Result<Vec<CoverThumbnail>, String> {
+    let size = size.unwrap_or(DEFAULT_SIZE).clamp(MIN_SIZE, MAX_SIZE);
+    let priority = priority.unwrap_or_default();
+
+    let mut found: HashMap<i64, Arc<Vec<u8>>> = HashMap::new();
+    let missing: Vec<i64> = book_ids
+        .iter()
+        .copied()
+        .filter(|&id| match thumbs.cached(id, size) {
+            Some(data) => {
+                found.insert(id, data);
+                false
+            }
+            None => true,
+        })
+        .collect();
+
+    if !missing.is_empty() {
+        let dir = thumbs_dir(&app)?;
+        let sources = cover_paths(&db, &missing)?;
+        let (reply, results) = mpsc::channel();
+        for (&book_id, source) in &sources {
+            thumbs.queue.push(
+                Job {
+                    book_id,
+                    source: source.clone(),
+                    dest: thumb_path(&dir, book_id, size),
+                    size,
+                    reply: reply.clone(),
+                },
+                priority,
+            );
+        }
+        drop(reply);
+
+        let generated: Vec<(i64, Option<Vec<u8>>)> =
+            tauri::async_runtime::spawn_blocking(move || results.iter().collect())
+                .await
+                .map_err(|e| format!("Thumbnail task failed: {}", e))?;
+        for (book_id, data) in generated {
+            if let Some(data) = data {
+                let data = Arc::new(data);
+                thumbs.insert(book_id, size, Arc::clone(&data));
+                found.insert(book_id, data);
+            }
+        }
+    }
+
+    if let Ok(dir) = thumbs_dir(&app) {
+        let served: Vec<i64> = found.keys().copied().collect();
+        thumbs.touch(&dir, &served, size);
+    }
+
+    Ok(book_ids
+        .into_iter()
+        .map(|book_id| CoverThumbnail {
+            book_id,
+            data_url: found.get(&book_id).map(|data| data_url(data)),
+        })
+        .collect())
+}
+
+/// Disk space taken by cover thumbnails, in bytes
+#[tauri::command]
+pub fn get_cache_size<R: Runtime>(app: AppHandle<R>) -> Result<u64, String> {
+    let dir = thumbs_dir(&app)?;
+    Ok(cached_files(&dir, &HashMap::new())
+        .iter()
+        .map(|file| file.size)
+        .sum())
+}
+
+/// Drop every cached thumbnail, in memory and on disk. They are regenerated
+/// on the next request.
+#[tauri::command]
+pub fn clear_thumbnail_cache<R: Runtime>(
+    app: AppHandle<R>,
+    thumbs: State<'_, CoverThumbnails>,
+) -> Result<(), String> {
+    if let Ok(mut cache) = thumbs.cache.lock() {
+        cache.clear();
+    }
+    if let Ok(mut access) = thumbs.access.lock() {
+        access.clear();
+    }
+
+    let dir = thumbs_dir(&app)?;
+    match std::fs::remove_dir_all(&dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("Failed to clear cover cache: {}", e)),
+    }
+    info!("Cleared cover thumbnail cache");
+    Ok(())
+}
+
+/// Older name for `clear_thumbnail_cache`, kept for existing callers
+#[tauri::command]
+pub fn clear_cover_cache<R: Runtime>(
+    app: AppHandle<R>,
+    thumbs: State<'_, CoverThumbnails>,
+) -> Result<(), String> {
+    clear_thumbnail_cache(app, thumbs)
+}
+
+/// Disk space thumbnails may take, in bytes
+#[tauri::command]
+pub fn get_thumbnail_cache_limit<R: Runtime>(app: AppHandle<R>) -> u64 {
+    cache_limit(&app)
+}
+
+/// Change the thumbnail cap, evicting right away if the cache is over it.
+/// `None` restores the default.
+#[tauri::command]
+pub async fn set_thumbnail_cache_limit<R: Runtime>(
+    app: AppHandle<R>,
+    limit_bytes: Option<u64>,
+) -> Result<u64, String> {
+    let limit = limit_bytes.unwrap_or(DEFAULT_CACHE_LIMIT);
+    let store = app
+        .profile_store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    store.set(CACHE_LIMIT_SETTING, limit);
+    persist::mark_dirty(&app, SETTINGS_STORE);
+    info!("Cover thumbnail cache limit set to {} bytes", limit);
+
+    tauri::async_runtime::spawn_blocking(move || evict_to_limit(&app))
+        .await
+        .map_err(|e| format!("Thumbnail eviction task failed: {}", e))?;
+    Ok(limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = DynamicImage::new_rgb8(width, height);
+        let mut out = Cursor::new(Vec::new());
+        image.write_to(&mut out, ImageFormat::Png).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn thumbnails_fit_the_requested_size() {
+        let data = encode_thumbnail(&png(600, 900), 160).unwrap();
+        let thumb = image::load_from_memory(&data).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (107, 160));
+        assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::WebP);
+    }
+
+    #[test]
+    fn regenerates_stale_thumbnails() {
+        let dir = std::env::temp_dir().join(format!("rm-covers-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("cover.png");
+        let dest = thumb_path(&dir.join(THUMBS_DIR), 1, 64);
+
+        std::fs::write(&source, png(200, 100)).unwrap();
+        assert!(!is_fresh(&source, &dest));
+        thumbnail(&source, &dest, 64).unwrap();
+        assert!(is_fresh(&source, &dest));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Load time of a 400-cover library grid, before (one full-size
+    /// `read_file` per cover) and after (batched thumbnails, cold and warm).
+    /// Run with `cargo test --release grid_load_time -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn grid_load_time() {
+        use std::time::Instant;
+
+        const COVERS: i64 = 400;
+        let dir = std::env::temp_dir().join(format!("rm-bench-{}", uuid::Uuid::new_v4()));
+        let thumbs = dir.join(THUMBS_DIR);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Detailed enough that the JPEGs are a realistic size
+        let cover = image::RgbImage::from_fn(600, 900, |x, y| {
+            image::Rgb([((x * 7) ^ (y * 3)) as u8, (x * y) as u8, (x + y * 5) as u8])
+        });
+        let mut jpeg = Cursor::new(Vec::new());
+        DynamicImage::from(cover)
+            .write_to(&mut jpeg, ImageFormat::Jpeg)
+            .unwrap();
+        let sources: Vec<PathBuf> = (0..COVERS)
+            .map(|id| {
+                let path = dir.join(format!("{}.jpg", id));
+                std::fs::write(&path, jpeg.get_ref()).unwrap();
+                path
+            })
+            .collect();
+
+        let before = Instant::now();
+        let mut before_bytes = 0;
+        for source in &sources {
+            let data = std::fs::read(source).unwrap();
+            // `read_file` hands bytes over IPC as a JSON number array
+            before_bytes += serde_json::to_vec(&data).unwrap().len();
+        }
+        let before = before.elapsed();
+
+        let load = || {
+            let chunks: Vec<&[PathBuf]> = sources.chunks(sources.len() / WORKERS).collect();
+            std::thread::scope(|scope| {
+                let workers: Vec<_> = chunks
+                    .into_iter()
+                    .enumerate()
+                    .map(|(chunk, sources)| {
+                        let thumbs = &thumbs;
+                        scope.spawn(move || {
+                            let first = (chunk * sources.len()) as i64;
+                            sources
+                                .iter()
+                                .zip(first..)
+                                .map(|(source, id)| {
+                                    let dest = thumb_path(thumbs, id, DEFAULT_SIZE);
+                                    data_url(&thumbnail(source, &dest, DEFAULT_SIZE).unwrap()).len()
+                                })
+                                .sum::<usize>()
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .map(|w| w.join().unwrap())
+                    .sum::<usize>()
+            })
+        };
+        let cold = Instant::now();
+        let after_bytes = load();
+        let cold = cold.elapsed();
+        let warm = Instant::now();
+        load();
+        let warm = warm.elapsed();
+
+        println!(
+            "{} covers: read_file {:?} ({} KB), thumbnails cold {:?}, warm {:?} ({} KB)",
+            COVERS,
+            before,
+            before_bytes / 1024,
+            cold,
+            warm,
+            after_bytes / 1024
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn file(name: &str, size: u64, accessed: i64) -> CachedFile {
+        CachedFile {
+            name: name.to_string(),
+            size,
+            accessed,
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let files = vec![
+            file("1-160.webp", 40, 300),
+            file("2-160.webp", 40, 100),
+            file("3-160.webp", 40, 200),
+            file("4-160.webp", 40, 400),
+        ];
+        let names = |plan: Vec<CachedFile>| -> Vec<String> {
+            plan.into_iter().map(|file| file.name).collect()
+        };
+
+        assert_eq!(
+            names(eviction_plan(files.clone(), 100)),
+            ["2-160.webp", "3-160.webp"]
+        );
+        assert!(eviction_plan(files.clone(), 160).is_empty());
+        assert_eq!(eviction_plan(files, 0).len(), 4);
+    }
+
+    #[test]
+    fn evicts_files_and_prunes_the_index() {
+        let dir = std::env::temp_dir().join(format!("rm-thumbs-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (book_id, len) in [(1, 30), (2, 30), (3, 30)] {
+            std::fs::write(thumb_path(&dir, book_id, 64), vec![0u8; len]).unwrap();
+        }
+        let mut access = HashMap::from([
+            (thumb_name(1, 64), 10),
+            (thumb_name(2, 64), 30),
+            (thumb_name(3, 64), 20),
+            (thumb_name(9, 64), 5),
+        ]);
+
+        assert_eq!(evict(&dir, &mut access, 60), (1, 30));
+        assert!(!thumb_path(&dir, 1, 64).exists());
+        assert!(thumb_path(&dir, 2, 64).exists());
+        // The index survives a restart and forgets deleted files
+        let saved = load_index(&dir);
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved.get(&thumb_name(3, 64)), Some(&20));
+        assert_eq!(saved, access);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn visible_jobs_run_first() {
+        let queue = Queue::default();
+        let (reply, _results) = mpsc::channel();
+        let job = |book_id| Job {
+            book_id,
+            source: PathBuf::new(),
+            dest: PathBuf::new(),
+            size: 64,
+            reply: reply.clone(),
+        };
+        {
+            let mut lanes = queue.lanes.lock().unwrap();
+            lanes.background.push_back(job(1));
+            lanes.background.push_back(job(2));
+            lanes.visible.push_back(job(3));
+        }
+        let order: Vec<i64> = (0..3).map(|_| queue.next().unwrap().book_id).collect();
+        assert_eq!(order, [3, 1, 2]);
+    }
+}
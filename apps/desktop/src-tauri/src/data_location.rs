@@ -0,0 +1,567 @@
+// Read Master Desktop - Data Location
+//
+// Where the app keeps its data: every profile's stores, database, covers,
+// thumbnails, caches, and logs. By default that is the OS app data
+// directory. `set_data_directory` moves it to a chosen folder, such as a
+// USB drive, and records the choice in `data-location.json` in the OS app
+// config directory, which is read first thing at launch. In portable mode
+// the data lives in `data` next to the executable instead, switched on by
+// a `portable` file beside it, so the drive works on any machine.
+//
+// Switching copies the current data into the new folder (unless it
+// already holds a library, which is then used as is), points book paths
+// inside the old folder at the copies, and restarts the app into the new
+// folder. The old folder is left in place. The new folder must be
+// writable; a copy that fails or is cancelled removes what it copied.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use log::{info, warn};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::db::{self, Database};
+use crate::find::FindSessions;
+use crate::jobs::{Job, JobKind};
+use crate::reader::ReaderSessions;
+use crate::{fsutil, persist, profiles, session, tts};
+
+const BOOTSTRAP_FILE: &str = "data-location.json";
+
+/// File next to the executable that switches portable mode on
+const PORTABLE_MARKER: &str = "portable";
+/// Data folder next to the executable in portable mode
+const PORTABLE_DIR: &str = "data";
+
+/// Subdirectories of a chosen data folder
+const CACHE_DIR: &str = "cache";
+const LOGS_DIR: &str = "logs";
+
+/// Where this process keeps its data, set by `init`
+static LOCATION: OnceLock<Location> = OnceLock::new();
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DataMode {
+    /// The OS app data directory
+    Default,
+    /// A folder chosen with `set_data_directory`
+    Custom,
+    /// `data` next to the executable
+    Portable,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Location {
+    mode: DataMode,
+    /// None in the default mode
+    dir: Option<PathBuf>,
+}
+
+/// Contents of `data-location.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Bootstrap {
+    data_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataLocation {
+    pub path: String,
+    pub mode: DataMode,
+}
+
+// ============================================================================
+// Location
+// ============================================================================
+
+fn exe_dir() -> Option<PathBuf> {
+    std::env::current_exe()
+        .ok()?
+        .parent()
+        .map(Path::to_path_buf)
+}
+
+fn bootstrap_path<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join(BOOTSTRAP_FILE))
+        .map_err(|e| format!("Failed to resolve app config directory: {}", e))
+}
+
+fn read_bootstrap<R: Runtime>(app: &AppHandle<R>) -> Bootstrap {
+    let Some(data) = bootstrap_path(app)
+        .ok()
+        .and_then(|path| fs::read(path).ok())
+    else {
+        return Bootstrap::default();
+    };
+    serde_json::from_slice(&data)
+        .map_err(|e| warn!("Ignoring corrupt data location: {}", e))
+        .unwrap_or_default()
+}
+
+/// Write `data-location.json`, or remove it when going back to the default
+fn write_bootstrap<R: Runtime>(app: &AppHandle<R>, data_dir: Option<&Path>) -> Result<(), String> {
+    let path = bootstrap_path(app)?;
+    let Some(data_dir) = data_dir else {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("Failed to reset data location: {}", e))
+            }
+            _ => Ok(()),
+        };
+    };
+
+    let data = serde_json::to_vec_pretty(&Bootstrap {
+        data_dir: Some(data_dir.to_path_buf()),
+    })
+    .map_err(|e| format!("Failed to encode data location: {}", e))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create app config directory: {}", e))?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, data)
+        .and_then(|_| fs::rename(&tmp, &path))
+        .map_err(|e| format!("Failed to save data location: {}", e))
+}
+
+/// Portable mode wins over a chosen folder. A chosen folder that is gone
+/// (an unplugged drive) falls back to the default for this launch.
+fn choose(portable: Option<PathBuf>, custom: Option<PathBuf>) -> Location {
+    if let Some(dir) = portable {
+        return Location {
+            mode: DataMode::Portable,
+            dir: Some(dir),
+        };
+    }
+    match custom.filter(|dir| dir.is_absolute()) {
+        Some(dir) if dir.is_dir() => Location {
+            mode: DataMode::Custom,
+            dir: Some(dir),
+        },
+        Some(dir) => {
+            warn!(
+                "Data directory {} is unavailable, using the default",
+                dir.display()
+            );
+            Location {
+                mode: DataMode::Default,
+                dir: None,
+            }
+        }
+        None => Location {
+            mode: DataMode::Default,
+            dir: None,
+        },
+    }
+}
+
+/// Decide where this launch keeps its data. Runs first in setup, before
+/// anything opens a store or the database.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    let portable = exe_dir()
+        .filter(|dir| dir.join(PORTABLE_MARKER).is_file())
+        .map(|dir| dir.join(PORTABLE_DIR));
+    let location = choose(portable, read_bootstrap(app).data_dir);
+    if let Some(dir) = &location.dir {
+        info!("Data directory: {} ({:?})", dir.display(), location.mode);
+    }
+    let _ = LOCATION.set(location);
+}
+
+fn chosen_dir() -> Option<&'static Path> {
+    LOCATION.get().and_then(|location| location.dir.as_deref())
+}
+
+/// Top of the app's data, above every profile
+pub fn base_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    match chosen_dir() {
+        Some(dir) => Ok(dir.to_path_buf()),
+        None => app
+            .path()
+            .app_data_dir()
+            .map_err(|e| format!("Failed to resolve app data directory: {}", e)),
+    }
+}
+
+/// Top of the app's logs, above every profile
+pub fn log_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    match chosen_dir() {
+        Some(dir) => Ok(dir.join(LOGS_DIR)),
+        None => app
+            .path()
+            .app_log_dir()
+            .map_err(|e| format!("Failed to resolve log directory: {}", e)),
+    }
+}
+
+/// Directory for disposable downloads
+pub fn cache_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    match chosen_dir() {
+        Some(dir) => Ok(dir.join(CACHE_DIR)),
+        None => app
+            .path()
+            .app_cache_dir()
+            .map_err(|e| format!("Failed to resolve cache directory: {}", e)),
+    }
+}
+
+/// Path for the store plugin: relative to the app data directory as it
+/// expects, or absolute inside a chosen data folder
+pub fn store_path(path: impl AsRef<Path>) -> PathBuf {
+    match chosen_dir() {
+        Some(dir) => dir.join(path),
+        None => path.as_ref().to_path_buf(),
+    }
+}
+
+// ============================================================================
+// Switching
+// ============================================================================
+
+/// Create `dir` and check a file can be written there
+fn check_writable(dir: &Path) -> Result<(), String> {
+    let probe = dir.join(format!(".write-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&probe, b"read-master"))
+        .and_then(|_| fs::remove_file(&probe))
+        .map_err(|e| format!("Can't write to {}: {}", dir.display(), e))
+}
+
+/// Files below `dir`, relative to it, without those `skip` rejects.
+/// Symlinks are left out.
+fn list_files(dir: &Path, rel: &Path, skip: &dyn Fn(&Path) -> bool, out: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir.join(rel)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to list {}: {}", dir.join(rel).display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = rel.join(entry.file_name());
+        if skip(&path) {
+            continue;
+        }
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => list_files(dir, &path, skip, out),
+            Ok(kind) if kind.is_file() => out.push(path),
+            _ => {}
+        }
+    }
+}
+
+fn rebase_books(tx: &rusqlite::Transaction, from: &Path, to: &Path) -> rusqlite::Result<()> {
+    let rows: Vec<(i64, [Option<String>; 3])> = tx
+        .prepare("SELECT id, path, cover_path, trashed_path FROM books")?
+        .query_map([], |row| {
+            Ok((row.get(0)?, [row.get(1)?, row.get(2)?, row.get(3)?]))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    for (id, paths) in rows {
+        let moved = paths.clone().map(|p| {
+            p.map(|p| {
+                fsutil::rebase(Path::new(&p), from, to)
+                    .map(|moved| moved.to_string_lossy().into_owned())
+                    .unwrap_or(p)
+            })
+        });
+        if moved != paths {
+            let [path, cover, trashed] = moved;
+            tx.execute(
+                "UPDATE books SET path = ?2, cover_path = ?3, trashed_path = ?4
+                 WHERE id = ?1",
+                params![id, path, cover, trashed],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Point book paths inside the old data folder at the new one
+fn rebase_database(path: &Path, from: &Path, to: &Path) -> Result<(), String> {
+    let mut conn =
+        Connection::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    conn.transaction()
+        .and_then(|tx| {
+            rebase_books(&tx, from, to)?;
+            tx.commit()
+        })
+        .map_err(|e| format!("Failed to update paths in {}: {}", path.display(), e))
+}
+
+fn create_parent(path: &Path) -> Result<(), String> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e)),
+        None => Ok(()),
+    }
+}
+
+/// Delete copied files, given relative to `target`, then the folders that
+/// held only them
+fn roll_back(target: &Path, copied: &[PathBuf]) {
+    let copies: Vec<PathBuf> = copied.iter().map(|rel| target.join(rel)).collect();
+    fsutil::remove_copies(target, &copies);
+}
+
+/// Copy `files`, then the open database, recording each copy made
+fn copy_files<R: Runtime>(
+    app: &AppHandle<R>,
+    (current, target): (&Path, &Path),
+    files: &[PathBuf],
+    active_db: &Path,
+    job: &Job<R>,
+    copied: &mut Vec<PathBuf>,
+) -> Result<(), String> {
+    let total = files.len() as u64 + 1;
+    for (index, rel) in files.iter().enumerate() {
+        if job.is_cancelled() {
+            return Err("Moving the data folder was cancelled".to_string());
+        }
+        job.progress(index as u64, total, Some(&rel.to_string_lossy()));
+        let to = target.join(rel);
+        create_parent(&to)?;
+        fs::copy(current.join(rel), &to)
+            .map_err(|e| format!("Failed to copy {}: {}", rel.display(), e))?;
+        copied.push(rel.clone());
+    }
+
+    job.progress(total - 1, total, Some(&active_db.to_string_lossy()));
+    let to = target.join(active_db);
+    create_parent(&to)?;
+    app.state::<Database>()
+        .with_conn(|conn| conn.execute("VACUUM INTO ?1", [to.to_string_lossy()]))
+        .map_err(|e| format!("Failed to copy the database: {}", e))?;
+    copied.push(active_db.to_path_buf());
+
+    for rel in copied.iter().filter(|rel| rel.ends_with(db::DB_FILE)) {
+        rebase_database(&target.join(rel), current, target)?;
+    }
+    Ok(())
+}
+
+/// Copy the data in `current` to `target`. The open database is written
+/// with `VACUUM INTO`, so the copy is consistent.
+fn copy_data<R: Runtime>(
+    app: &AppHandle<R>,
+    current: &Path,
+    target: &Path,
+    job: &Job<R>,
+) -> Result<(), String> {
+    let active_db = profiles::data_dir(app)?.join(db::DB_FILE);
+    let active_rel = active_db
+        .strip_prefix(current)
+        .map_err(|_| "The database is outside the data folder".to_string())?
+        .to_path_buf();
+    let active_name = active_rel.to_string_lossy().into_owned();
+    let skip = |path: &Path| {
+        let name = path.to_string_lossy();
+        path == Path::new(BOOTSTRAP_FILE)
+            || path == Path::new(LOGS_DIR)
+            || name
+                .strip_prefix(active_name.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest == "-wal" || rest == "-shm")
+    };
+
+    let mut files = Vec::new();
+    list_files(current, Path::new(""), &skip, &mut files);
+    info!(
+        "Copying {} data file(s) to {}",
+        files.len() + 1,
+        target.display()
+    );
+
+    let mut copied = Vec::new();
+    let result = copy_files(
+        app,
+        (current, target),
+        &files,
+        &active_rel,
+        job,
+        &mut copied,
+    );
+    if let Err(e) = &result {
+        warn!("{}; removing copies", e);
+        roll_back(target, &copied);
+    }
+    result
+}
+
+/// Move the app's data to `target` and restart into it. `commit` records
+/// the new location once the data is there.
+async fn switch<R: Runtime>(
+    app: AppHandle<R>,
+    target: PathBuf,
+    commit: impl FnOnce(&AppHandle<R>) -> Result<(), String>,
+) -> Result<(), String> {
+    let current = base_dir(&app)?;
+    if target == current {
+        return Ok(());
+    }
+    fsutil::validate_target(&current, &target, "data folder")?;
+    check_writable(&target)?;
+
+    let job = Job::start(&app, JobKind::DataMigration, "", "Moving the data folder")?;
+    session::save(&app);
+    persist::flush(&app);
+
+    let worker = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = if target.join(db::DB_FILE).is_file() {
+            info!("Using the data already in {}", target.display());
+            Ok(())
+        } else {
+            copy_data(&worker, &current, &target, &job)
+        };
+        job.finish(&result);
+        result
+    })
+    .await
+    .map_err(|e| format!("Data folder task failed: {}", e))??;
+    commit(&app)?;
+
+    // Windows close their books before the restart takes them down
+    let _ = app.emit("data-directory-switching", ());
+    let _ = tts::tts_stop(app.state());
+    app.state::<ReaderSessions>().clear();
+    if let Some(finds) = app.try_state::<FindSessions>() {
+        finds.clear();
+    }
+    app.restart()
+}
+
+fn set_portable_marker(exe_dir: &Path, enabled: bool) -> Result<(), String> {
+    let marker = exe_dir.join(PORTABLE_MARKER);
+    let result = if enabled {
+        fs::write(&marker, b"")
+    } else if marker.exists() {
+        fs::remove_file(&marker)
+    } else {
+        Ok(())
+    };
+    result.map_err(|e| format!("Failed to update {}: {}", marker.display(), e))
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Where the app's data is kept
+#[tauri::command]
+pub fn get_data_directory<R: Runtime>(app: AppHandle<R>) -> Result<DataLocation, String> {
+    Ok(DataLocation {
+        path: base_dir(&app)?.to_string_lossy().into_owned(),
+        mode: LOCATION.get().map_or(DataMode::Default, |l| l.mode),
+    })
+}
+
+/// Move the app's data to `path` and restart. Leaves portable mode. A
+/// folder that already holds a library is used as is.
+#[tauri::command]
+pub async fn set_data_directory<R: Runtime>(app: AppHandle<R>, path: String) -> Result<(), String> {
+    let target = PathBuf::from(path.trim());
+    let default = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data directory: {}", e))?;
+
+    switch(app, target.clone(), move |app| {
+        if let Some(dir) = exe_dir() {
+            set_portable_marker(&dir, false)?;
+        }
+        write_bootstrap(
+            app,
+            Some(target.as_path()).filter(|t| *t != default.as_path()),
+        )
+    })
+    .await
+}
+
+/// Move the app's data next to the executable (or back out) and restart
+#[tauri::command]
+pub async fn set_portable_mode<R: Runtime>(app: AppHandle<R>, enabled: bool) -> Result<(), String> {
+    let exe_dir = exe_dir().ok_or_else(|| "Failed to locate the executable".to_string())?;
+    let target = if enabled {
+        exe_dir.join(PORTABLE_DIR)
+    } else {
+        match read_bootstrap(&app).data_dir.filter(|dir| dir.is_dir()) {
+            Some(dir) => dir,
+            None => app
+                .path()
+                .app_data_dir()
+                .map_err(|e| format!("Failed to resolve app data directory: {}", e))?,
+        }
+    };
+
+    switch(app, target, move |_| set_portable_marker(&exe_dir, enabled)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn portable_mode_wins() {
+        let portable = PathBuf::from("/usb/data");
+        let chosen = choose(Some(portable.clone()), Some(std::env::temp_dir()));
+        assert_eq!(chosen.mode, DataMode::Portable);
+        assert_eq!(chosen.dir, Some(portable));
+
+        let custom = choose(None, Some(std::env::temp_dir()));
+        assert_eq!(custom.mode, DataMode::Custom);
+    }
+
+    #[test]
+    fn missing_or_relative_folders_fall_back_to_the_default() {
+        let gone = std::env::temp_dir().join(format!("rm-gone-{}", uuid::Uuid::new_v4()));
+        assert_eq!(choose(None, Some(gone)).mode, DataMode::Default);
+        assert_eq!(
+            choose(None, Some(PathBuf::from("data"))).mode,
+            DataMode::Default
+        );
+        assert_eq!(choose(None, None).dir, None);
+    }
+
+    #[test]
+    fn lists_files_without_skipped_ones() {
+        let dir = std::env::temp_dir().join(format!("rm-data-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("covers")).unwrap();
+        fs::create_dir_all(dir.join(LOGS_DIR)).unwrap();
+        fs::write(dir.join("settings.json"), b"{}").unwrap();
+        fs::write(dir.join("covers").join("1.jpg"), b"").unwrap();
+        fs::write(dir.join(LOGS_DIR).join("app.log"), b"").unwrap();
+
+        let mut files = Vec::new();
+        list_files(
+            &dir,
+            Path::new(""),
+            &|p| p == Path::new(LOGS_DIR),
+            &mut files,
+        );
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                PathBuf::from("covers").join("1.jpg"),
+                PathBuf::from("settings.json")
+            ]
+        );
+
+        roll_back(&dir, &files);
+        assert!(!dir.join("covers").exists());
+        assert!(dir.join(LOGS_DIR).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
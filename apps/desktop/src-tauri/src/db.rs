@@ -0,0 +1,260 @@
+// Read Master Desktop - Library Database
+//
+// SQLite database holding library records and reading history.
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::info;
+use rusqlite::Connection;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::profiles;
+
+/// Database file name inside the app data directory
+pub const DB_FILE: &str = "library.db";
+
+/// Schema migrations, applied in order. The index of each entry + 1 is
+/// its schema version, tracked through `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    // 1: books, tags, reading history
+    "CREATE TABLE books (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        title TEXT NOT NULL,
+        author TEXT,
+        isbn TEXT,
+        isbn13 TEXT,
+        path TEXT,
+        format TEXT,
+        on_disk INTEGER NOT NULL DEFAULT 0,
+        page_count INTEGER,
+        rating INTEGER,
+        review TEXT,
+        added_at TEXT NOT NULL,
+        finished_at TEXT,
+        updated_at TEXT NOT NULL
+    );
+    CREATE INDEX idx_books_isbn ON books(isbn);
+    CREATE INDEX idx_books_isbn13 ON books(isbn13);
+    CREATE TABLE book_tags (
+        book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (book_id, tag)
+    );
+    CREATE TABLE reading_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+        finished_at TEXT NOT NULL,
+        rating INTEGER,
+        source TEXT NOT NULL,
+        UNIQUE (book_id, finished_at)
+    );",
+    // 2: smart collections, plus the book fields they filter on
+    "ALTER TABLE books ADD COLUMN word_count INTEGER;
+    ALTER TABLE books ADD COLUMN series_name TEXT;
+    ALTER TABLE books ADD COLUMN series_index REAL;
+    ALTER TABLE books ADD COLUMN progress REAL;
+    CREATE TABLE collections (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE,
+        rules TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE TABLE collection_members (
+        collection_id INTEGER NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+        book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+        PRIMARY KEY (collection_id, book_id)
+    );
+    CREATE INDEX idx_collection_members_book ON collection_members(book_id);",
+    // 3: descriptive metadata from online lookups
+    "ALTER TABLE books ADD COLUMN description TEXT;
+    ALTER TABLE books ADD COLUMN cover_path TEXT;
+    ALTER TABLE books ADD COLUMN publish_year INTEGER;",
+    // 4: file hashes for duplicate detection
+    "ALTER TABLE books ADD COLUMN content_hash TEXT;
+    CREATE INDEX idx_books_content_hash ON books(content_hash);",
+    // 5: per-book typography overrides
+    "CREATE TABLE typography_profiles (
+        book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+        profile TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );",
+    // 6: reading sessions and cached text statistics
+    "CREATE TABLE reading_sessions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+        started_at TEXT NOT NULL,
+        ended_at TEXT NOT NULL,
+        words_read INTEGER
+    );
+    CREATE INDEX idx_reading_sessions_started ON reading_sessions(started_at);
+    CREATE TABLE text_stats (
+        book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+        content_hash TEXT NOT NULL,
+        stats TEXT NOT NULL,
+        computed_at TEXT NOT NULL
+    );",
+    // 7: full-text search index, with per-book progress for resuming
+    "CREATE VIRTUAL TABLE search_chunks USING fts5(
+        text,
+        book_id UNINDEXED,
+        chapter UNINDEXED,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+    CREATE TABLE search_index_state (
+        book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+        content_hash TEXT NOT NULL,
+        total_chapters INTEGER NOT NULL,
+        indexed_chapters INTEGER NOT NULL DEFAULT 0,
+        complete INTEGER NOT NULL DEFAULT 0,
+        updated_at TEXT NOT NULL
+    );",
+    // 8: soft delete with a restore window
+    "ALTER TABLE books ADD COLUMN deleted_at TEXT;
+    CREATE INDEX idx_books_deleted_at ON books(deleted_at);",
+    // 9: pending operations for the sync API
+    "CREATE TABLE sync_queue (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        idempotency_key TEXT NOT NULL UNIQUE,
+        operation TEXT NOT NULL,
+        queued_at TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        next_attempt_at TEXT,
+        last_error TEXT
+    );",
+    // 10: bookmarks, moved out of bookmarks.json. No foreign key, so they
+    // outlive a purged book and are adopted when its file is re-imported.
+    "CREATE TABLE bookmarks (
+        id TEXT PRIMARY KEY,
+        book_id INTEGER NOT NULL,
+        position TEXT NOT NULL,
+        name TEXT,
+        color TEXT,
+        snippet TEXT,
+        anchor_word INTEGER,
+        content_hash TEXT,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE INDEX idx_bookmarks_book ON bookmarks(book_id);
+    CREATE INDEX idx_bookmarks_hash ON bookmarks(content_hash);",
+    // 11: where a book's file sits in the app's own trash folder
+    "ALTER TABLE books ADD COLUMN trashed_path TEXT;",
+    // 12: reader zoom per book
+    "CREATE TABLE book_zoom (
+        book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+        factor REAL NOT NULL,
+        updated_at TEXT NOT NULL
+    );",
+    // 13: flashcard review log, for activity statistics
+    "CREATE TABLE card_reviews (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        card_id TEXT NOT NULL,
+        grade INTEGER NOT NULL,
+        reviewed_at TEXT NOT NULL
+    );
+    CREATE INDEX idx_card_reviews_reviewed ON card_reviews(reviewed_at);",
+    // 14: reading goals, one per kind
+    "CREATE TABLE reading_goals (
+        kind TEXT PRIMARY KEY,
+        target INTEGER NOT NULL,
+        period TEXT NOT NULL,
+        congratulated_period TEXT,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );",
+    // 15: read-aloud autoscroll per book
+    "CREATE TABLE book_autoscroll (
+        book_id INTEGER PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+        enabled INTEGER NOT NULL,
+        speed REAL NOT NULL,
+        updated_at TEXT NOT NULL
+    );",
+    // 16: merged duplicates, with what each merge moved so it can be undone
+    "CREATE TABLE book_merges (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        primary_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+        undo TEXT NOT NULL,
+        merged_at TEXT NOT NULL,
+        undone_at TEXT
+    );",
+];
+
+/// Shared database handle stored in managed state
+pub struct Database {
+    conn: Mutex<Connection>,
+}
+
+impl Database {
+    /// Open (or create) the database at `path` and run pending migrations
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| format!("Failed to open database: {}", e))?;
+
+        conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")
+            .map_err(|e| format!("Failed to configure database: {}", e))?;
+
+        migrate(&conn)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Run `f` with exclusive access to the connection
+    pub fn with_conn<T>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, String> {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| "Database lock poisoned".to_string())?;
+
+        f(&mut conn).map_err(|e| format!("Database error: {}", e))
+    }
+}
+
+/// Open the app database and register it as managed state
+pub fn init<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let dir = profiles::data_dir(app)?;
+
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+
+    let path = dir.join(DB_FILE);
+    info!("Opening library database: {:?}", path);
+
+    app.manage(Database::open(&path)?);
+    Ok(())
+}
+
+/// An in-memory database set up like `Database::open`, for tests
+#[cfg(test)]
+pub fn test_connection() -> Connection {
+    let conn = Connection::open_in_memory().expect("failed to open in-memory database");
+    conn.execute_batch("PRAGMA foreign_keys = ON;")
+        .expect("failed to enable foreign keys");
+    migrate(&conn).expect("migrations failed");
+    conn
+}
+
+/// Apply any migrations newer than the stored schema version
+fn migrate(conn: &Connection) -> Result<(), String> {
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| format!("Failed to read schema version: {}", e))?;
+
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        info!("Applying database migration {}", index + 1);
+
+        conn.execute_batch(&format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            sql,
+            index + 1
+        ))
+        .map_err(|e| format!("Migration {} failed: {}", index + 1, e))?;
+    }
+
+    Ok(())
+}
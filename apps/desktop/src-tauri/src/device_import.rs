@@ -0,0 +1,758 @@
+// Read Master Desktop - E-reader Import
+//
+// Pulls reading progress and highlights off a mounted Kobo or Kindle.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use log::{info, warn};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::db::Database;
+use crate::goodreads::{self, normalize};
+use crate::notes::{self, ImportedHighlight};
+use crate::{import, library};
+
+const KOBO_DATABASE: &str = ".kobo/KoboReader.sqlite";
+const KINDLE_CLIPPINGS: &str = "documents/My Clippings.txt";
+
+/// Where Kobo's ContentIDs place the device's storage
+const KOBO_MOUNT_PREFIX: &str = "file:///mnt/onboard/";
+
+/// Kindle's separator between clippings
+const CLIPPING_SEPARATOR: &str = "==========";
+
+/// Book file extensions next to Kindle `.sdr` sidecars
+const KINDLE_BOOK_EXTENSIONS: &[&str] = &["azw3", "azw", "kfx", "mobi", "pdf", "txt"];
+
+/// How long to wait on a Kobo database the device is still writing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(2);
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    Kobo,
+    Kindle,
+}
+
+impl DeviceKind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Kobo => "kobo",
+            Self::Kindle => "kindle",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceImportReport {
+    pub device: DeviceKind,
+    pub books_on_device: usize,
+    pub books_matched: usize,
+    pub progress_updated: usize,
+    pub highlights_added: usize,
+    pub duplicate_highlights: usize,
+    pub conflicts: Vec<DeviceEntryIssue>,
+    /// Device books not found in the library
+    pub unmatched: Vec<DeviceEntryIssue>,
+    /// Books that could not be read from the device
+    pub failures: Vec<DeviceEntryIssue>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceEntryIssue {
+    pub title: String,
+    pub book_id: Option<i64>,
+    pub reason: String,
+}
+
+/// A book as the device knows it
+#[derive(Debug, Default)]
+struct DeviceBook {
+    title: String,
+    author: Option<String>,
+    isbn: Option<String>,
+    /// The book's file on the device, for matching by content hash
+    file: Option<PathBuf>,
+    /// 0.0 to 1.0
+    progress: Option<f64>,
+    highlights: Vec<ImportedHighlight>,
+    /// Why highlights couldn't be read, if they couldn't
+    highlights_error: Option<String>,
+}
+
+/// One entry of My Clippings.txt
+#[derive(Debug, Clone, PartialEq)]
+struct Clipping {
+    title: String,
+    author: Option<String>,
+    kind: ClippingKind,
+    /// Kindle location range, e.g. "123-125"
+    location: Option<String>,
+    added: Option<String>,
+    text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClippingKind {
+    Highlight,
+    Note,
+    Bookmark,
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+fn detect(root: &Path) -> Option<DeviceKind> {
+    if root.join(KOBO_DATABASE).is_file() {
+        Some(DeviceKind::Kobo)
+    } else if root.join(KINDLE_CLIPPINGS).is_file() || root.join("system/thumbnails").is_dir() {
+        Some(DeviceKind::Kindle)
+    } else {
+        None
+    }
+}
+
+/// Device timestamps as RFC 3339; both devices record local-less times,
+/// taken here as UTC
+fn parse_timestamp(raw: &str, formats: &[&str]) -> Option<String> {
+    let raw = raw.trim();
+    if let Ok(date) = chrono::DateTime::parse_from_rfc3339(raw) {
+        return Some(date.with_timezone(&Utc).to_rfc3339());
+    }
+    formats
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(raw, fmt).ok())
+        .map(|date| Utc.from_utc_datetime(&date).to_rfc3339())
+}
+
+// ============================================================================
+// Kobo
+// ============================================================================
+
+fn columns(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    names.collect()
+}
+
+fn has_columns(conn: &Connection, table: &str, wanted: &[&str]) -> bool {
+    columns(conn, table)
+        .map(|have| wanted.iter().all(|w| have.iter().any(|h| h == w)))
+        .unwrap_or(false)
+}
+
+fn kobo_version(conn: &Connection) -> String {
+    conn.query_row("SELECT version FROM DbVersion", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map(|v| v.to_string())
+    .unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn kobo_highlights(conn: &Connection, content_id: &str) -> Result<Vec<ImportedHighlight>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT Text, Annotation, DateCreated FROM Bookmark
+             WHERE VolumeID = ?1 AND Text IS NOT NULL AND Text != ''
+             ORDER BY DateCreated",
+        )
+        .map_err(|e| format!("Failed to read highlights: {}", e))?;
+    let rows = stmt
+        .query_map([content_id], |row| {
+            let created: Option<String> = row.get(2)?;
+            Ok(ImportedHighlight {
+                quote: row.get(0)?,
+                body: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                created_at: created.and_then(|c| {
+                    parse_timestamp(&c, &["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S"])
+                }),
+            })
+        })
+        .map_err(|e| format!("Failed to read highlights: {}", e))?;
+    rows.collect::<rusqlite::Result<_>>()
+        .map_err(|e| format!("Failed to read highlights: {}", e))
+}
+
+/// Books and highlights from Kobo's database: books are `content` rows with
+/// ContentType 6, and highlights are `Bookmark` rows keyed by the book's
+/// ContentID
+fn read_kobo(root: &Path) -> Result<Vec<DeviceBook>, String> {
+    let conn = Connection::open_with_flags(
+        root.join(KOBO_DATABASE),
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|e| format!("Failed to open the Kobo database: {}", e))?;
+    conn.busy_timeout(BUSY_TIMEOUT)
+        .map_err(|e| format!("Failed to open the Kobo database: {}", e))?;
+
+    const CONTENT: &[&str] = &["ContentID", "ContentType", "Title", "Attribution"];
+    if !has_columns(&conn, "content", CONTENT) {
+        return Err(format!(
+            "Unsupported Kobo database (version {})",
+            kobo_version(&conn)
+        ));
+    }
+    let optional = |column: &str| {
+        if has_columns(&conn, "content", &[column]) {
+            column.to_string()
+        } else {
+            "NULL".to_string()
+        }
+    };
+    let highlights_supported = has_columns(
+        &conn,
+        "Bookmark",
+        &["VolumeID", "Text", "Annotation", "DateCreated"],
+    );
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT ContentID, Title, Attribution, {}, {}, {} FROM content
+             WHERE ContentType = 6",
+            optional("ISBN"),
+            optional("___PercentRead"),
+            optional("ReadStatus")
+        ))
+        .map_err(|e| format!("Failed to read the Kobo library: {}", e))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<f64>>(4)?,
+                row.get::<_, Option<i64>>(5)?,
+            ))
+        })
+        .and_then(|rows| rows.collect::<rusqlite::Result<Vec<_>>>())
+        .map_err(|e| format!("Failed to read the Kobo library: {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(content_id, title, author, isbn, percent, status)| {
+            // ReadStatus 2 is "finished", which can leave the percentage short
+            let progress = match status {
+                Some(2) => Some(1.0),
+                _ => percent.map(|p| (p / 100.0).clamp(0.0, 1.0)),
+            };
+            let highlights = if highlights_supported {
+                kobo_highlights(&conn, &content_id)
+            } else {
+                Err(format!(
+                    "Highlights not imported: unsupported Kobo database (version {})",
+                    kobo_version(&conn)
+                ))
+            };
+            let (highlights, highlights_error) = match highlights {
+                Ok(highlights) => (highlights, None),
+                Err(e) => (Vec::new(), Some(e)),
+            };
+            DeviceBook {
+                title: title.unwrap_or_else(|| content_id.clone()),
+                author,
+                isbn: isbn.as_deref().and_then(goodreads::clean_isbn),
+                file: content_id
+                    .strip_prefix(KOBO_MOUNT_PREFIX)
+                    .map(|relative| root.join(relative)),
+                progress,
+                highlights,
+                highlights_error,
+            }
+        })
+        .collect())
+}
+
+// ============================================================================
+// Kindle
+// ============================================================================
+
+/// "Title (Author)"; titles can contain parentheses of their own, so the
+/// author is the last group
+fn split_title_line(line: &str) -> (String, Option<String>) {
+    let line = line.trim_start_matches('\u{feff}').trim();
+    match line.rfind(" (") {
+        Some(i) if line.ends_with(')') => (
+            line[..i].trim().to_string(),
+            Some(line[i + 2..line.len() - 1].trim().to_string()),
+        ),
+        _ => (line.to_string(), None),
+    }
+}
+
+/// "- Your Highlight on page 12 | Location 123-125 | Added on Monday, ..."
+fn parse_clipping(entry: &str) -> Option<Clipping> {
+    let mut lines = entry.lines().map(str::trim).skip_while(|l| l.is_empty());
+    let (title, author) = split_title_line(lines.next()?);
+    let meta = lines.next()?;
+
+    let lower = meta.to_lowercase();
+    let kind = if lower.contains("highlight") {
+        ClippingKind::Highlight
+    } else if lower.contains("note") {
+        ClippingKind::Note
+    } else if lower.contains("bookmark") {
+        ClippingKind::Bookmark
+    } else {
+        return None;
+    };
+
+    let mut location = None;
+    let mut added = None;
+    for part in meta.split('|').map(str::trim) {
+        let part_lower = part.to_lowercase();
+        if let Some(i) = part_lower.find("location ") {
+            location = Some(part[i + "location ".len()..].trim().to_string());
+        } else if let Some(rest) = part.strip_prefix("Added on ") {
+            added = parse_timestamp(
+                rest,
+                &["%A, %B %d, %Y %I:%M:%S %p", "%A, %d %B %Y %H:%M:%S"],
+            );
+        }
+    }
+
+    let text = lines.collect::<Vec<_>>().join("\n").trim().to_string();
+    Some(Clipping {
+        title,
+        author,
+        kind,
+        location,
+        added,
+        text,
+    })
+}
+
+fn parse_clippings(data: &str) -> Vec<Clipping> {
+    data.split(CLIPPING_SEPARATOR)
+        .filter_map(parse_clipping)
+        .collect()
+}
+
+/// Start of a location range: "123-125" is 123
+fn location_start(location: &str) -> &str {
+    location.split('-').next().unwrap_or(location).trim()
+}
+
+/// End of a location range: "123-125" is 125
+fn location_end(location: &str) -> &str {
+    location.rsplit('-').next().unwrap_or(location).trim()
+}
+
+/// A book's highlights with notes attached. Kindle records a note at the
+/// end location of the highlight it belongs to, and records a highlight
+/// again each time it is extended.
+fn clipping_highlights(clippings: &[&Clipping]) -> Vec<ImportedHighlight> {
+    let mut highlights: Vec<(&Clipping, String)> = Vec::new();
+    for &clipping in clippings
+        .iter()
+        .filter(|c| c.kind == ClippingKind::Highlight && !c.text.is_empty())
+    {
+        // An extended highlight replaces the earlier one at the same start
+        let start = clipping.location.as_deref().map(location_start);
+        highlights.retain(|(earlier, _)| {
+            start.is_none()
+                || earlier.location.as_deref().map(location_start) != start
+                || !clipping.text.contains(&earlier.text)
+        });
+        if !highlights
+            .iter()
+            .any(|(c, _)| c.text == clipping.text && c.location == clipping.location)
+        {
+            highlights.push((clipping, String::new()));
+        }
+    }
+
+    let mut loose = Vec::new();
+    for &note in clippings
+        .iter()
+        .filter(|c| c.kind == ClippingKind::Note && !c.text.is_empty())
+    {
+        let attached = note.location.as_deref().and_then(|at| {
+            highlights.iter_mut().find(|(h, _)| {
+                h.location
+                    .as_deref()
+                    .is_some_and(|l| location_end(l) == location_end(at))
+            })
+        });
+        match attached {
+            Some((_, body)) => *body = note.text.clone(),
+            None => loose.push(note),
+        }
+    }
+    if !loose.is_empty() {
+        info!("{} Kindle note(s) without a highlight skipped", loose.len());
+    }
+
+    highlights
+        .into_iter()
+        .map(|(clipping, body)| ImportedHighlight {
+            quote: clipping.text.clone(),
+            body,
+            created_at: clipping.added.clone(),
+        })
+        .collect()
+}
+
+/// Book files under `dir` that have a `.sdr` sidecar
+fn kindle_book_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.filter_map(Result::ok).map(|e| e.path()) {
+        if !path.is_dir() {
+            continue;
+        }
+        if path.extension().is_some_and(|ext| ext == "sdr") {
+            let book = KINDLE_BOOK_EXTENSIONS
+                .iter()
+                .map(|ext| path.with_extension(ext))
+                .find(|file| file.is_file());
+            files.extend(book);
+        } else {
+            kindle_book_files(&path, files);
+        }
+    }
+}
+
+/// Highlights and notes from `My Clippings.txt`, where Kindle appends every
+/// one. The `.sdr` sidecar folders mark which files are books, so they can
+/// be matched by content hash. Kindle's reading position lives in an
+/// undocumented binary format inside the sidecar and isn't imported.
+fn read_kindle(root: &Path) -> Result<Vec<DeviceBook>, String> {
+    let clippings = match std::fs::read(root.join(KINDLE_CLIPPINGS)) {
+        Ok(data) => parse_clippings(&String::from_utf8_lossy(&data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(format!("Failed to read My Clippings.txt: {}", e)),
+    };
+    let mut files = Vec::new();
+    kindle_book_files(&root.join("documents"), &mut files);
+    files.sort();
+
+    // Group clippings by book, keeping first-seen order
+    let mut order: Vec<(String, Option<String>)> = Vec::new();
+    let mut by_book: HashMap<(String, Option<String>), Vec<&Clipping>> = HashMap::new();
+    for clipping in &clippings {
+        let key = (clipping.title.clone(), clipping.author.clone());
+        if !by_book.contains_key(&key) {
+            order.push(key.clone());
+        }
+        by_book.entry(key).or_default().push(clipping);
+    }
+
+    let mut used = vec![false; files.len()];
+    let mut books: Vec<DeviceBook> = order
+        .into_iter()
+        .map(|key| {
+            let title = normalize(&key.0);
+            // Sidecar names start with the title, often followed by the author
+            let file = files.iter().enumerate().find(|(i, file)| {
+                let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+                !used[*i] && !title.is_empty() && normalize(&stem).starts_with(&title)
+            });
+            let file = file.map(|(i, file)| {
+                used[i] = true;
+                file.clone()
+            });
+            DeviceBook {
+                highlights: clipping_highlights(&by_book[&key]),
+                title: key.0,
+                author: key.1,
+                file,
+                ..Default::default()
+            }
+        })
+        .collect();
+
+    // Books with a sidecar but no clippings still count as on the device
+    books.extend(
+        files
+            .into_iter()
+            .zip(used)
+            .filter(|(_, used)| !used)
+            .map(|(file, _)| DeviceBook {
+                title: file
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                file: Some(file),
+                ..Default::default()
+            }),
+    );
+    Ok(books)
+}
+
+// ============================================================================
+// Merging
+// ============================================================================
+
+/// Library book for a device book, by content hash, then ISBN, then title
+/// and author
+fn find_book(conn: &Connection, book: &DeviceBook) -> rusqlite::Result<Option<i64>> {
+    let hash = book.file.as_deref().and_then(|f| import::hash_path(f).ok());
+    if let Some(hash) = hash {
+        if let Some(id) = library::find_by_hash(conn, &hash)? {
+            return Ok(Some(id));
+        }
+    }
+    let isbns: Vec<&str> = book.isbn.as_deref().into_iter().collect();
+    goodreads::find_book(conn, &isbns, &book.title, book.author.as_deref())
+}
+
+/// Merge one book: progress by taking the further of the two, highlights
+/// through `notes::merge_highlights`
+fn merge_book<R: Runtime>(
+    app: &AppHandle<R>,
+    device: DeviceKind,
+    book: DeviceBook,
+    report: &mut DeviceImportReport,
+) -> Result<(), String> {
+    let db = app.state::<Database>();
+    let Some(book_id) = db.with_conn(|conn| find_book(conn, &book))? else {
+        report.unmatched.push(DeviceEntryIssue {
+            title: book.title,
+            book_id: None,
+            reason: "No matching book in the library".to_string(),
+        });
+        return Ok(());
+    };
+    let record = db
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book {} disappeared during import", book_id))?;
+    report.books_matched += 1;
+
+    if let Some(progress) = book.progress {
+        let current = record.progress.unwrap_or(0.0);
+        if progress > current {
+            db.with_conn(|conn| library::set_progress(conn, book_id, Some(progress)))?;
+            report.progress_updated += 1;
+        } else if progress < current {
+            report.conflicts.push(DeviceEntryIssue {
+                title: book.title.clone(),
+                book_id: Some(book_id),
+                reason: format!(
+                    "Device is at {:.0}%, library at {:.0}%; kept the library's",
+                    progress * 100.0,
+                    current * 100.0
+                ),
+            });
+        }
+    }
+
+    if let Some(reason) = book.highlights_error {
+        report.failures.push(DeviceEntryIssue {
+            title: book.title,
+            book_id: Some(book_id),
+            reason,
+        });
+        return Ok(());
+    }
+    let merge = notes::merge_highlights(app, &record, device.name(), book.highlights)?;
+    report.highlights_added += merge.added;
+    report.duplicate_highlights += merge.duplicates;
+    report
+        .conflicts
+        .extend(merge.conflicts.into_iter().map(|reason| DeviceEntryIssue {
+            title: book.title.clone(),
+            book_id: Some(book_id),
+            reason,
+        }));
+    Ok(())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Import reading progress and highlights from an e-reader mounted at
+/// `device_id` (the path of its storage). Each book is imported on its
+/// own, so a locked database or unknown schema fails that book (or its
+/// highlights) rather than the whole import.
+#[tauri::command]
+pub async fn import_device_annotations<R: Runtime>(
+    app: AppHandle<R>,
+    device_id: String,
+) -> Result<DeviceImportReport, String> {
+    let root = PathBuf::from(&device_id);
+    let device =
+        detect(&root).ok_or_else(|| format!("No Kobo or Kindle found at {}", device_id))?;
+    info!("Importing annotations from {:?} at {}", device, device_id);
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let books = match device {
+            DeviceKind::Kobo => read_kobo(&root)?,
+            DeviceKind::Kindle => read_kindle(&root)?,
+        };
+
+        let mut report = DeviceImportReport {
+            device,
+            books_on_device: books.len(),
+            books_matched: 0,
+            progress_updated: 0,
+            highlights_added: 0,
+            duplicate_highlights: 0,
+            conflicts: Vec::new(),
+            unmatched: Vec::new(),
+            failures: Vec::new(),
+        };
+        for book in books {
+            let title = book.title.clone();
+            if let Err(reason) = merge_book(&app, device, book, &mut report) {
+                warn!("Failed to import {} from {:?}: {}", title, device, reason);
+                report.failures.push(DeviceEntryIssue {
+                    title,
+                    book_id: None,
+                    reason,
+                });
+            }
+        }
+
+        info!(
+            "Device import complete: {} of {} books matched, {} highlights added, {} failed",
+            report.books_matched,
+            report.books_on_device,
+            report.highlights_added,
+            report.failures.len()
+        );
+        Ok(report)
+    })
+    .await
+    .map_err(|e| format!("Device import task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIPPINGS: &str = "\u{feff}The Left Hand of Darkness (Le Guin, Ursula K.)
+- Your Highlight on page 12 | Location 180-181 | Added on Monday, January 1, 2024 10:00:00 AM
+
+Light is the left hand of darkness
+==========
+The Left Hand of Darkness (Le Guin, Ursula K.)
+- Your Highlight on page 12 | Location 180-183 | Added on Monday, January 1, 2024 10:01:00 AM
+
+Light is the left hand of darkness and darkness the right hand of light
+==========
+The Left Hand of Darkness (Le Guin, Ursula K.)
+- Your Note on page 12 | Location 183 | Added on Monday, January 1, 2024 10:02:00 AM
+
+the Handdara chant
+==========
+Dune (Special Edition) (Frank Herbert)
+- Your Bookmark on Location 500 | Added on Tuesday, January 2, 2024 9:00:00 PM
+
+
+==========
+";
+
+    #[test]
+    fn parses_clippings() {
+        let clippings = parse_clippings(CLIPPINGS);
+        assert_eq!(clippings.len(), 4);
+        assert_eq!(clippings[0].title, "The Left Hand of Darkness");
+        assert_eq!(clippings[0].author.as_deref(), Some("Le Guin, Ursula K."));
+        assert_eq!(clippings[0].kind, ClippingKind::Highlight);
+        assert_eq!(clippings[0].location.as_deref(), Some("180-181"));
+        assert_eq!(
+            clippings[0].added.as_deref(),
+            Some("2024-01-01T10:00:00+00:00")
+        );
+        assert_eq!(clippings[2].kind, ClippingKind::Note);
+        assert_eq!(clippings[3].title, "Dune (Special Edition)");
+        assert_eq!(clippings[3].kind, ClippingKind::Bookmark);
+    }
+
+    #[test]
+    fn keeps_extended_highlights_and_attaches_notes() {
+        let clippings = parse_clippings(CLIPPINGS);
+        let book: Vec<&Clipping> = clippings.iter().take(3).collect();
+        let highlights = clipping_highlights(&book);
+        assert_eq!(highlights.len(), 1);
+        assert!(highlights[0].quote.ends_with("right hand of light"));
+        assert_eq!(highlights[0].body, "the Handdara chant");
+    }
+
+    #[test]
+    fn splits_title_lines() {
+        assert_eq!(
+            split_title_line("Dune (Frank Herbert)"),
+            ("Dune".to_string(), Some("Frank Herbert".to_string()))
+        );
+        assert_eq!(split_title_line("Untitled"), ("Untitled".to_string(), None));
+    }
+
+    #[test]
+    fn reads_kobo_progress_and_highlights() {
+        let dir = std::env::temp_dir().join(format!("kobo-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join(".kobo")).unwrap();
+        let conn = Connection::open(dir.join(KOBO_DATABASE)).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE content (ContentID TEXT, ContentType INTEGER, Title TEXT,
+                 Attribution TEXT, ISBN TEXT, ___PercentRead INTEGER, ReadStatus INTEGER);
+             CREATE TABLE Bookmark (VolumeID TEXT, Text TEXT, Annotation TEXT,
+                 DateCreated TEXT);
+             INSERT INTO content VALUES
+                 ('file:///mnt/onboard/Books/dune.epub', 6, 'Dune', 'Frank Herbert',
+                  '9780441172719', 42, 1),
+                 ('file:///mnt/onboard/Books/dune.epub!!ch1.xhtml', 9, 'Chapter 1',
+                  NULL, NULL, 0, 0),
+                 ('file:///mnt/onboard/Books/emma.epub', 6, 'Emma', 'Jane Austen',
+                  NULL, 97, 2);
+             INSERT INTO Bookmark VALUES
+                 ('file:///mnt/onboard/Books/dune.epub', 'Fear is the mind-killer.',
+                  'Litany', '2024-03-01T08:30:00.000');",
+        )
+        .unwrap();
+        drop(conn);
+
+        assert_eq!(detect(&dir), Some(DeviceKind::Kobo));
+        let books = read_kobo(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(books.len(), 2);
+        let dune = books.iter().find(|b| b.title == "Dune").unwrap();
+        assert_eq!(dune.progress, Some(0.42));
+        assert_eq!(dune.isbn.as_deref(), Some("9780441172719"));
+        assert_eq!(dune.file, Some(dir.join("Books/dune.epub")));
+        assert_eq!(dune.highlights[0].quote, "Fear is the mind-killer.");
+        assert_eq!(dune.highlights[0].body, "Litany");
+        let emma = books.iter().find(|b| b.title == "Emma").unwrap();
+        assert_eq!(emma.progress, Some(1.0));
+    }
+
+    #[test]
+    fn rejects_unknown_kobo_schemas() {
+        let dir = std::env::temp_dir().join(format!("kobo-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join(".kobo")).unwrap();
+        let conn = Connection::open(dir.join(KOBO_DATABASE)).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE content (ContentID TEXT, ContentType INTEGER, Title TEXT,
+                 Attribution TEXT);
+             INSERT INTO content VALUES ('file:///mnt/onboard/a.epub', 6, 'A', NULL);
+             CREATE TABLE DbVersion (version INTEGER);
+             INSERT INTO DbVersion VALUES (999);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let books = read_kobo(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The book is still listed; only its highlights fail
+        assert_eq!(books.len(), 1);
+        assert!(books[0].highlights_error.as_ref().unwrap().contains("999"));
+    }
+}
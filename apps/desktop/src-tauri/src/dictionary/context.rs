@@ -0,0 +1,521 @@
+// Read Master Desktop - Word Context
+//
+// Everything the hover-lookup popover shows, in one round trip: the word as
+// printed, its containing sentence, lemma, a part-of-speech guess, and the
+// dictionary entry for the lemma.
+//
+// Positions are a spine index (page index for PDFs) and a character offset
+// into its plain text, as in `rsvp`. Words hyphenated across a line break
+// are rejoined before the sentence is cut out, so print artifacts like
+// "exam-\nple" read as "example". Dictionary entries are cached per book
+// and lemma, the book's language per book, and recently hovered chapters'
+// text so neighbouring hovers don't re-extract them.
+
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use log::warn;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use super::lemma::{self, Candidate};
+use super::{dictionary_path, load_cached, parse_article, Definition, DictionaryCache};
+use crate::db::Database;
+use crate::epub::EpubArchive;
+use crate::{language, library, pdf, tts};
+
+/// Dictionary entries kept per (book, lemma)
+const ENTRY_CAPACITY: usize = 1_000;
+
+/// Chapter or page texts kept for nearby hovers
+const CHAPTER_CAPACITY: usize = 8;
+
+/// Language used when a book's can't be detected
+const UNKNOWN_LANGUAGE: &str = "und";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextPosition {
+    /// Spine index, or page index for PDFs
+    pub chapter: usize,
+    /// Character offset of the word in the chapter's text
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WordContext {
+    /// The word as printed, with line-break hyphenation rejoined
+    pub word: String,
+    pub lemma: String,
+    pub part_of_speech: Option<String>,
+    /// The containing sentence, on one line
+    pub sentence: String,
+    /// Language the lemma was looked up in
+    pub language: String,
+    /// Dictionary entry for the lemma; empty when there is none
+    pub definitions: Vec<Definition>,
+}
+
+/// Lookup state shared across hovers
+pub struct WordContextCache {
+    entries: Mutex<LruCache<(i64, String), Arc<Vec<Definition>>>>,
+    languages: Mutex<HashMap<i64, String>>,
+    chapters: Mutex<LruCache<(i64, usize), Arc<String>>>,
+}
+
+impl Default for WordContextCache {
+    fn default() -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(
+                NonZeroUsize::new(ENTRY_CAPACITY).expect("entry capacity is non-zero"),
+            )),
+            languages: Mutex::new(HashMap::new()),
+            chapters: Mutex::new(LruCache::new(
+                NonZeroUsize::new(CHAPTER_CAPACITY).expect("chapter capacity is non-zero"),
+            )),
+        }
+    }
+}
+
+// ============================================================================
+// Text
+// ============================================================================
+
+/// Whether the hyphen at `chars[i]` splits a word across a line break, and
+/// if so the index of the word's continuation
+fn line_break_continuation(chars: &[char], i: usize) -> Option<usize> {
+    if !i.checked_sub(1).is_some_and(|p| chars[p].is_alphabetic()) {
+        return None;
+    }
+    let end = (i + 1..chars.len())
+        .find(|&j| !chars[j].is_whitespace())
+        .unwrap_or(chars.len());
+    let breaks_line = chars[i + 1..end].contains(&'\n');
+    (breaks_line && chars.get(end).is_some_and(|c| c.is_alphabetic())).then_some(end)
+}
+
+/// Text with words hyphenated across line breaks rejoined, soft hyphens
+/// dropped and whitespace collapsed to single spaces, or to a newline
+/// between paragraphs when `line_paragraphs` is set. Returns the new index
+/// of character `offset`.
+fn clean_text(text: &str, offset: usize, line_paragraphs: bool) -> (String, usize) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut len: usize = 0;
+    let mut mapped = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (start, before) = (i, len);
+        let c = chars[i];
+
+        if let Some(next) = (c == '-' || c == '\u{AD}')
+            .then(|| line_break_continuation(&chars, i))
+            .flatten()
+        {
+            // "exam-\nple" joins; a real compound like "Anglo-\nSaxon"
+            // keeps its hyphen
+            if c == '-' && chars[next].is_uppercase() {
+                out.push('-');
+                len += 1;
+            }
+            i = next;
+        } else if c == '\u{AD}' {
+            i += 1;
+        } else if c.is_whitespace() {
+            let end = (i..chars.len())
+                .find(|&j| !chars[j].is_whitespace())
+                .unwrap_or(chars.len());
+            let newline = line_paragraphs && chars[i..end].contains(&'\n');
+            if !out.is_empty() && end < chars.len() {
+                out.push(if newline { '\n' } else { ' ' });
+                len += 1;
+            }
+            i = end;
+        } else {
+            out.push(c);
+            len += 1;
+            i += 1;
+        }
+
+        if mapped.is_none() && offset < i {
+            mapped = Some(before.min(len.saturating_sub(1)));
+        }
+        debug_assert!(i > start);
+    }
+    (out, mapped.unwrap_or(len))
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '\'' | '’' | '-')
+}
+
+/// The word spanning character `index` of `line`, and the word before it
+fn word_at(line: &[char], index: usize) -> Option<(String, Option<String>)> {
+    let index = index.min(line.len().checked_sub(1)?);
+    if !is_word_char(line[index]) {
+        return None;
+    }
+    let start = (0..index)
+        .rev()
+        .find(|&i| !is_word_char(line[i]))
+        .map_or(0, |i| i + 1);
+    let end = (index..line.len())
+        .find(|&i| !is_word_char(line[i]))
+        .unwrap_or(line.len());
+    let trim = |word: String| {
+        word.trim_matches(|c: char| !c.is_alphanumeric())
+            .to_string()
+    };
+
+    let word = trim(line[start..end].iter().collect());
+    let previous = line[..start]
+        .split(|c| c.is_whitespace())
+        .rev()
+        .map(|w| trim(w.iter().collect()))
+        .find(|w| !w.is_empty());
+    (!word.is_empty()).then_some((word, previous))
+}
+
+/// The sentence containing character `index` of a paragraph whose
+/// whitespace is already collapsed
+fn sentence_at(paragraph: &str, index: usize) -> String {
+    let mut start = 0;
+    for sentence in tts::split_sentences(paragraph) {
+        let end = start + sentence.chars().count();
+        if index <= end {
+            return sentence;
+        }
+        // Sentences were separated by one space
+        start = end + 1;
+    }
+    paragraph.to_string()
+}
+
+/// The word at `offset` of `text`, its containing sentence and the word
+/// before it. `line_paragraphs` is whether each line of `text` is its own
+/// paragraph (EPUB) or just a printed line (PDF).
+fn locate(
+    text: &str,
+    offset: usize,
+    line_paragraphs: bool,
+) -> Option<(String, String, Option<String>)> {
+    let (cleaned, index) = clean_text(text, offset, line_paragraphs);
+
+    let mut start = 0;
+    for paragraph in cleaned.split('\n') {
+        let chars: Vec<char> = paragraph.chars().collect();
+        if index <= start + chars.len() {
+            let local = index - start;
+            let (word, previous) = word_at(&chars, local)?;
+            return Some((word, sentence_at(paragraph, local), previous));
+        }
+        start += chars.len() + 1;
+    }
+    None
+}
+
+// ============================================================================
+// Lookup
+// ============================================================================
+
+/// Part of speech from the lemma's inflection or the word's context and
+/// form, preferring one the dictionary lists
+fn choose_part_of_speech(
+    candidate: &Candidate,
+    guessed: Option<&'static str>,
+    definitions: &[Definition],
+) -> Option<String> {
+    let mut listed: Vec<&str> = definitions
+        .iter()
+        .filter_map(|d| d.part_of_speech.as_deref())
+        .collect();
+    listed.sort_unstable();
+    listed.dedup();
+
+    let mut guesses = [candidate.part_of_speech, guessed].into_iter().flatten();
+    let chosen = if listed.is_empty() {
+        guesses.next()
+    } else {
+        guesses
+            .find(|pos| listed.contains(pos))
+            .or(listed.first().copied())
+    };
+    chosen.map(str::to_string)
+}
+
+impl WordContextCache {
+    fn chapter_text(
+        &self,
+        book_id: i64,
+        chapter: usize,
+        path: &str,
+        is_pdf: bool,
+    ) -> Result<Arc<String>, String> {
+        if let Some(text) = self
+            .chapters
+            .lock()
+            .ok()
+            .and_then(|mut c| c.get(&(book_id, chapter)).cloned())
+        {
+            return Ok(text);
+        }
+
+        let text = if is_pdf {
+            pdf::page_text(&pdf::load(path)?, chapter as u32)?
+        } else {
+            EpubArchive::open(path)
+                .map_err(|e| e.to_string())?
+                .chapter_text(chapter)?
+                .text
+        };
+        let text = Arc::new(text);
+        if let Ok(mut c) = self.chapters.lock() {
+            c.put((book_id, chapter), Arc::clone(&text));
+        }
+        Ok(text)
+    }
+
+    fn language(&self, book_id: i64, path: &str) -> String {
+        if let Some(lang) = self
+            .languages
+            .lock()
+            .ok()
+            .and_then(|l| l.get(&book_id).cloned())
+        {
+            return lang;
+        }
+
+        let lang = match language::detect(path) {
+            Ok(detection) => detection.code,
+            Err(e) => {
+                warn!("Looking up words in book {} as written: {}", book_id, e);
+                UNKNOWN_LANGUAGE.to_string()
+            }
+        };
+        if let Ok(mut l) = self.languages.lock() {
+            l.insert(book_id, lang.clone());
+        }
+        lang
+    }
+
+    /// The first candidate the dictionary for `lang` knows (the likeliest
+    /// one when it knows none) and its entry, cached per book and lemma
+    fn resolve<R: Runtime>(
+        &self,
+        app: &AppHandle<R>,
+        book_id: i64,
+        candidates: Vec<Candidate>,
+        lang: &str,
+    ) -> Result<(Candidate, Arc<Vec<Definition>>), String> {
+        let dictionary = dictionary_path(app, lang).and_then(|path| {
+            load_cached(&app.state::<DictionaryCache>(), path)
+                .map_err(|e| warn!("No dictionary entries for hover lookup: {}", e))
+                .ok()
+        });
+
+        let known = dictionary
+            .as_ref()
+            .and_then(|d| candidates.iter().position(|c| d.contains(&c.lemma)));
+        let candidate = candidates
+            .into_iter()
+            .nth(known.unwrap_or(0))
+            .ok_or("No lemma candidates")?;
+        let Some(dictionary) = dictionary.filter(|_| known.is_some()) else {
+            return Ok((candidate, Arc::new(Vec::new())));
+        };
+
+        let key = (book_id, candidate.lemma.clone());
+        if let Some(entry) = self
+            .entries
+            .lock()
+            .ok()
+            .and_then(|mut e| e.get(&key).cloned())
+        {
+            return Ok((candidate, entry));
+        }
+
+        let entry: Arc<Vec<Definition>> = Arc::new(
+            dictionary
+                .lookup(&candidate.lemma)?
+                .iter()
+                .flat_map(|article| parse_article(article, &dictionary.name))
+                .collect(),
+        );
+        if let Ok(mut e) = self.entries.lock() {
+            e.put(key, Arc::clone(&entry));
+        }
+        Ok((candidate, entry))
+    }
+}
+
+fn word_context<R: Runtime>(
+    app: &AppHandle<R>,
+    book_id: i64,
+    position: TextPosition,
+    word: &str,
+) -> Result<WordContext, String> {
+    let book = app
+        .state::<Database>()
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+    let is_pdf = match book.format.as_deref() {
+        Some("epub") => false,
+        Some("pdf") => true,
+        _ => return Err("Word lookup is only available for EPUB and PDF books".to_string()),
+    };
+    let path = book
+        .path
+        .ok_or_else(|| format!("Book {} has no file", book_id))?;
+
+    let cache = app.state::<WordContextCache>();
+    let text = cache.chapter_text(book_id, position.chapter, &path, is_pdf)?;
+    let (printed, sentence, previous) = locate(&text, position.offset, !is_pdf)
+        .ok_or_else(|| format!("No word at {:?}", position))?;
+
+    // The selected token may be half of a word hyphenated across lines;
+    // prefer the rejoined word unless the position has gone stale
+    let requested = word.trim_matches(|c: char| !c.is_alphanumeric());
+    let word = if printed.to_lowercase().contains(&requested.to_lowercase()) {
+        printed
+    } else {
+        warn!(
+            "Word at {:?} is '{}', not '{}'; looking up the latter",
+            position, printed, requested
+        );
+        requested.to_string()
+    };
+
+    let lang = cache.language(book_id, &path);
+    let lemmatizer = lemma::for_language(&lang);
+    let lower = word.to_lowercase();
+    let guessed = lemmatizer.part_of_speech(&lower, previous.map(|p| p.to_lowercase()).as_deref());
+    let (candidate, definitions) =
+        cache.resolve(app, book_id, lemmatizer.candidates(&lower), &lang)?;
+
+    Ok(WordContext {
+        word,
+        part_of_speech: choose_part_of_speech(&candidate, guessed, &definitions),
+        lemma: candidate.lemma,
+        sentence,
+        language: lang,
+        definitions: definitions.to_vec(),
+    })
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Sentence, lemma, part of speech and dictionary entry for the word at
+/// `position` of a book, for the hover-lookup popover
+#[tauri::command]
+pub async fn get_word_context<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: i64,
+    position: TextPosition,
+    word: String,
+) -> Result<WordContext, String> {
+    tauri::async_runtime::spawn_blocking(move || word_context(&app, book_id, position, &word))
+        .await
+        .map_err(|e| format!("Word lookup task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(part_of_speech: &str) -> Definition {
+        Definition {
+            word: "run".to_string(),
+            part_of_speech: Some(part_of_speech.to_string()),
+            senses: Vec::new(),
+            dictionary: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn rejoins_words_hyphenated_across_lines() {
+        let text = "An exam-\nple of the Anglo-\nSaxon style.";
+        let (cleaned, _) = clean_text(text, 0, true);
+        assert_eq!(cleaned, "An example of the Anglo-Saxon style.");
+
+        let (cleaned, index) = clean_text("Soft\u{AD}ly, gent\u{AD}\n  ly.", 12, true);
+        assert_eq!(cleaned, "Softly, gently.");
+        assert_eq!(index, 11);
+    }
+
+    #[test]
+    fn keeps_paragraphs_apart_unless_lines_are_printed_lines() {
+        let text = "First one.\n  Second -\nthird.";
+        assert_eq!(clean_text(text, 0, true).0, "First one.\nSecond -\nthird.");
+        assert_eq!(clean_text(text, 0, false).0, "First one. Second - third.");
+    }
+
+    #[test]
+    fn maps_offsets_through_cleanup() {
+        let text = "  Two  words";
+        let (cleaned, index) = clean_text(text, 7, true);
+        assert_eq!(cleaned, "Two words");
+        assert_eq!(index, 4);
+    }
+
+    #[test]
+    fn finds_the_word_and_its_sentence() {
+        let text = "He left. Dr. Smith ran the exam-\nple quickly! Then home.\nNext paragraph.";
+        let offset = text.find("ple").unwrap();
+        let (word, sentence, previous) = locate(text, offset, true).unwrap();
+        assert_eq!(word, "example");
+        assert_eq!(sentence, "Dr. Smith ran the example quickly!");
+        assert_eq!(previous.as_deref(), Some("the"));
+
+        let offset = text.find("Next").unwrap();
+        let (word, sentence, previous) = locate(text, offset, true).unwrap();
+        assert_eq!(word, "Next");
+        assert_eq!(sentence, "Next paragraph.");
+        assert_eq!(previous, None);
+    }
+
+    #[test]
+    fn finds_no_word_on_punctuation() {
+        assert!(locate("Wait — what?", 5, true).is_none());
+    }
+
+    #[test]
+    fn prefers_parts_of_speech_the_dictionary_lists() {
+        let verb = Candidate {
+            lemma: "run".to_string(),
+            part_of_speech: Some("verb"),
+        };
+        let plain = Candidate {
+            lemma: "run".to_string(),
+            part_of_speech: None,
+        };
+        let both = [definition("noun"), definition("verb")];
+
+        assert_eq!(
+            choose_part_of_speech(&verb, Some("noun"), &both).as_deref(),
+            Some("verb")
+        );
+        assert_eq!(
+            choose_part_of_speech(&plain, Some("noun"), &both).as_deref(),
+            Some("noun")
+        );
+        assert_eq!(
+            choose_part_of_speech(&plain, Some("adjective"), &[definition("verb")]).as_deref(),
+            Some("verb")
+        );
+        assert_eq!(
+            choose_part_of_speech(&plain, Some("adverb"), &[]).as_deref(),
+            Some("adverb")
+        );
+        assert_eq!(choose_part_of_speech(&plain, None, &[]), None);
+    }
+}
@@ -0,0 +1,436 @@
+// Read Master Desktop - Dictionary Formats
+//
+// Readers for StarDict (.ifo/.idx/.dict[.dz]) and dictd (.index/.dict[.dz])
+// dictionaries. Indexes are loaded into memory keyed by lowercased
+// headword; article data is read on demand from uncompressed files, or
+// decompressed once for dictzip files.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone)]
+struct IndexEntry {
+    headword: String,
+    offset: u64,
+    size: u64,
+}
+
+enum Data {
+    File(PathBuf),
+    Memory(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// StarDict, with the `sametypesequence` from the .ifo if present
+    StarDict,
+    Dictd,
+}
+
+/// A loaded dictionary
+pub struct Dictionary {
+    pub name: String,
+    format: Format,
+    same_type_sequence: Option<String>,
+    index: HashMap<String, Vec<IndexEntry>>,
+    data: Data,
+}
+
+/// One article: the headword as spelled in the dictionary, plus plain text
+#[derive(Debug, Clone)]
+pub struct Article {
+    pub headword: String,
+    pub text: String,
+}
+
+// ============================================================================
+// Loading
+// ============================================================================
+
+fn with_extension(path: &Path, ext: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(ext);
+    path.with_file_name(name)
+}
+
+/// The dictionary data file next to `base`, preferring uncompressed
+fn data_file(base: &Path) -> Result<Data, String> {
+    let plain = with_extension(base, "dict");
+    if plain.exists() {
+        return Ok(Data::File(plain));
+    }
+
+    let compressed = with_extension(base, "dict.dz");
+    let file =
+        File::open(&compressed).map_err(|e| format!("Failed to open dictionary data: {}", e))?;
+
+    // dictzip is gzip-compatible; decompress the whole thing once
+    let mut bytes = Vec::new();
+    GzDecoder::new(file)
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to decompress dictionary data: {}", e))?;
+    Ok(Data::Memory(bytes))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+/// Read `.idx` (or `.idx.gz`) next to the `.ifo`
+fn read_stardict_idx(ifo: &Path) -> Result<Vec<u8>, String> {
+    let idx = with_extension(ifo, "idx");
+    if idx.exists() {
+        return read_file(&idx);
+    }
+
+    let gz = with_extension(ifo, "idx.gz");
+    let mut bytes = Vec::new();
+    GzDecoder::new(read_file(&gz)?.as_slice())
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to decompress dictionary index: {}", e))?;
+    Ok(bytes)
+}
+
+fn insert(index: &mut HashMap<String, Vec<IndexEntry>>, entry: IndexEntry) {
+    index
+        .entry(entry.headword.to_lowercase())
+        .or_default()
+        .push(entry);
+}
+
+/// Load a StarDict dictionary from its `.ifo` file
+pub fn load_stardict(ifo: &Path) -> Result<Dictionary, String> {
+    let info = String::from_utf8_lossy(&read_file(ifo)?).into_owned();
+    if !info.starts_with("StarDict's dict ifo file") {
+        return Err(format!("Not a StarDict .ifo file: {}", ifo.display()));
+    }
+
+    let field = |key: &str| {
+        info.lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .map(|v| v.trim().to_string())
+    };
+
+    let name = field("bookname").unwrap_or_else(|| {
+        ifo.file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    });
+    let offset_bytes = match field("idxoffsetbits").as_deref() {
+        Some("64") => 8,
+        _ => 4,
+    };
+
+    let idx = read_stardict_idx(ifo)?;
+    let mut index = HashMap::new();
+    let mut pos = 0;
+
+    // word\0, offset (32 or 64 bit BE), size (32 bit BE)
+    while pos < idx.len() {
+        let Some(nul) = idx[pos..].iter().position(|&b| b == 0) else {
+            break;
+        };
+        let headword = String::from_utf8_lossy(&idx[pos..pos + nul]).into_owned();
+        pos += nul + 1;
+
+        if pos + offset_bytes + 4 > idx.len() {
+            return Err("Truncated dictionary index".to_string());
+        }
+        let offset = idx[pos..pos + offset_bytes]
+            .iter()
+            .fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+        pos += offset_bytes;
+        let size = idx[pos..pos + 4]
+            .iter()
+            .fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+        pos += 4;
+
+        insert(
+            &mut index,
+            IndexEntry {
+                headword,
+                offset,
+                size,
+            },
+        );
+    }
+
+    Ok(Dictionary {
+        name,
+        format: Format::StarDict,
+        same_type_sequence: field("sametypesequence"),
+        index,
+        data: data_file(ifo)?,
+    })
+}
+
+/// dictd encodes offsets and lengths in base64 digits
+fn decode_b64_number(value: &str) -> Option<u64> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    value.bytes().try_fold(0u64, |acc, c| {
+        let digit = ALPHABET.iter().position(|&a| a == c)? as u64;
+        Some(acc * 64 + digit)
+    })
+}
+
+/// Load a dictd dictionary from its `.index` file
+pub fn load_dictd(index_path: &Path) -> Result<Dictionary, String> {
+    let text = String::from_utf8_lossy(&read_file(index_path)?).into_owned();
+    let mut index = HashMap::new();
+    let mut name = None;
+
+    for line in text.lines() {
+        let mut parts = line.split('\t');
+        let (Some(headword), Some(offset), Some(size)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let (Some(offset), Some(size)) = (decode_b64_number(offset), decode_b64_number(size))
+        else {
+            continue;
+        };
+
+        // dictd keeps its metadata under reserved 00-database-* headwords
+        if headword == "00-database-short" || headword == "00databaseshort" {
+            name = Some((offset, size));
+            continue;
+        }
+        if headword.starts_with("00-database-") || headword.starts_with("00database") {
+            continue;
+        }
+
+        insert(
+            &mut index,
+            IndexEntry {
+                headword: headword.to_string(),
+                offset,
+                size,
+            },
+        );
+    }
+
+    let mut dictionary = Dictionary {
+        name: index_path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+        format: Format::Dictd,
+        same_type_sequence: None,
+        index,
+        data: data_file(index_path)?,
+    };
+
+    if let Some((offset, size)) = name {
+        if let Ok(bytes) = dictionary.read(offset, size) {
+            let text = String::from_utf8_lossy(&bytes);
+            // First line is the headword itself; the name follows
+            if let Some(short) = text.lines().map(str::trim).rfind(|l| !l.is_empty()) {
+                dictionary.name = short.to_string();
+            }
+        }
+    }
+
+    Ok(dictionary)
+}
+
+/// Load a dictionary from an `.ifo`, `.index`, or a directory holding one
+pub fn load(path: &Path) -> Result<Dictionary, String> {
+    if path.is_dir() {
+        let entries = std::fs::read_dir(path)
+            .map_err(|e| format!("Failed to read dictionary directory: {}", e))?;
+        let mut candidates: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| {
+                matches!(
+                    p.extension().and_then(|e| e.to_str()),
+                    Some("ifo" | "index")
+                )
+            })
+            .collect();
+        candidates.sort();
+
+        let file = candidates
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("No dictionary found in {}", path.display()))?;
+        return load(&file);
+    }
+
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("ifo") => load_stardict(path),
+        Some("index") => load_dictd(path),
+        _ => Err(format!("Unsupported dictionary file: {}", path.display())),
+    }
+}
+
+// ============================================================================
+// Lookup
+// ============================================================================
+
+/// Drop tags and decode the common entities of HTML/XDXF/Pango articles
+fn strip_markup(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    let mut tag = String::new();
+
+    for c in text.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let name = tag.trim_start_matches('/').to_ascii_lowercase();
+                if name.starts_with("br") || name.starts_with("p") || name.starts_with("li") {
+                    out.push('\n');
+                }
+            }
+            _ if in_tag => tag.push(c),
+            _ => out.push(c),
+        }
+    }
+
+    out.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+fn field_text(kind: char, bytes: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(bytes);
+    match kind {
+        'm' | 'l' | 't' | 'y' => Some(text.into_owned()),
+        'h' | 'g' | 'x' => Some(strip_markup(&text)),
+        // Resource references, images, wav, etc. have no text
+        _ => None,
+    }
+}
+
+/// Decode a StarDict article into its text fields
+fn stardict_text(bytes: &[u8], same_type_sequence: Option<&str>) -> String {
+    let mut parts = Vec::new();
+    let mut pos = 0;
+
+    let mut read_field = |kind: char, last: bool, pos: &mut usize| {
+        let rest = &bytes[(*pos).min(bytes.len())..];
+        let field = if kind.is_ascii_uppercase() {
+            // Sized binary data
+            if last {
+                *pos = bytes.len();
+                return;
+            }
+            let size = rest
+                .get(..4)
+                .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+                .unwrap_or(rest.len());
+            *pos += 4 + size;
+            return;
+        } else if last {
+            *pos = bytes.len();
+            rest
+        } else {
+            let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+            *pos += end + 1;
+            &rest[..end]
+        };
+
+        if let Some(text) = field_text(kind, field) {
+            parts.push(text);
+        }
+    };
+
+    match same_type_sequence {
+        Some(sequence) => {
+            let kinds: Vec<char> = sequence.chars().collect();
+            for (i, &kind) in kinds.iter().enumerate() {
+                read_field(kind, i + 1 == kinds.len(), &mut pos);
+            }
+        }
+        None => {
+            while pos < bytes.len() {
+                let kind = bytes[pos] as char;
+                pos += 1;
+                read_field(kind, false, &mut pos);
+            }
+        }
+    }
+
+    parts.join("\n")
+}
+
+impl Dictionary {
+    fn read(&self, offset: u64, size: u64) -> Result<Vec<u8>, String> {
+        match &self.data {
+            Data::Memory(bytes) => {
+                let start = offset as usize;
+                let end = start.saturating_add(size as usize);
+                bytes
+                    .get(start..end)
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| "Dictionary entry out of range".to_string())
+            }
+            Data::File(path) => {
+                let mut file =
+                    File::open(path).map_err(|e| format!("Failed to open dictionary: {}", e))?;
+                file.seek(SeekFrom::Start(offset))
+                    .map_err(|e| format!("Failed to read dictionary: {}", e))?;
+                let mut bytes = vec![0; size as usize];
+                file.read_exact(&mut bytes)
+                    .map_err(|e| format!("Failed to read dictionary: {}", e))?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Articles for `word` (case-insensitive)
+    pub fn lookup(&self, word: &str) -> Result<Vec<Article>, String> {
+        let Some(entries) = self.index.get(&word.to_lowercase()) else {
+            return Ok(Vec::new());
+        };
+
+        entries
+            .iter()
+            .map(|entry| {
+                let bytes = self.read(entry.offset, entry.size)?;
+                let text = match self.format {
+                    Format::StarDict => stardict_text(&bytes, self.same_type_sequence.as_deref()),
+                    Format::Dictd => {
+                        // dictd articles repeat the headword on their first line
+                        let text = String::from_utf8_lossy(&bytes).into_owned();
+                        match text.split_once('\n') {
+                            Some((first, rest))
+                                if first.trim().eq_ignore_ascii_case(&entry.headword) =>
+                            {
+                                rest.to_string()
+                            }
+                            _ => text,
+                        }
+                    }
+                };
+                Ok(Article {
+                    headword: entry.headword.clone(),
+                    text,
+                })
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.index.contains_key(&word.to_lowercase())
+    }
+}
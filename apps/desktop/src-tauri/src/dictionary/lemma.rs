@@ -0,0 +1,451 @@
+// Read Master Desktop - Lemmatization
+//
+// Base forms of inflected words, so a hover on "children" or "stopped"
+// finds the dictionary entry for "child" or "stop". Each language gets a
+// `Lemmatizer`; English has an exception table for irregular forms and
+// suffix rules for the rest. Languages without one look words up as
+// written.
+//
+// Lemmatizers propose candidates, most likely first; the caller keeps the
+// first one its dictionary knows, so rules may over-generate.
+
+use crate::language;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// A possible base form of a word
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub lemma: String,
+    /// Part of speech the inflection implies, if any
+    pub part_of_speech: Option<&'static str>,
+}
+
+impl Candidate {
+    fn new(lemma: impl Into<String>, part_of_speech: Option<&'static str>) -> Self {
+        Self {
+            lemma: lemma.into(),
+            part_of_speech,
+        }
+    }
+}
+
+/// Language-specific lemmatization and part-of-speech guessing
+pub trait Lemmatizer: Send + Sync {
+    /// Possible lemmas of a lowercased word, most likely first. Includes
+    /// the word itself.
+    fn candidates(&self, word: &str) -> Vec<Candidate>;
+
+    /// Part of speech of a lowercased word from its form and the word
+    /// before it
+    fn part_of_speech(&self, _word: &str, _previous: Option<&str>) -> Option<&'static str> {
+        None
+    }
+}
+
+/// The lemmatizer for a language tag
+pub fn for_language(lang: &str) -> &'static dyn Lemmatizer {
+    match language::primary_subtag(lang).to_lowercase().as_str() {
+        "en" => &English,
+        _ => &Unchanged,
+    }
+}
+
+/// Looks words up as written
+struct Unchanged;
+
+impl Lemmatizer for Unchanged {
+    fn candidates(&self, word: &str) -> Vec<Candidate> {
+        vec![Candidate::new(word, None)]
+    }
+}
+
+// ============================================================================
+// English
+// ============================================================================
+
+/// Irregular forms as (form, lemma, part of speech)
+const IRREGULAR: &[(&str, &str, &str)] = &[
+    ("am", "be", "verb"),
+    ("is", "be", "verb"),
+    ("are", "be", "verb"),
+    ("was", "be", "verb"),
+    ("were", "be", "verb"),
+    ("been", "be", "verb"),
+    ("being", "be", "verb"),
+    ("has", "have", "verb"),
+    ("had", "have", "verb"),
+    ("having", "have", "verb"),
+    ("does", "do", "verb"),
+    ("did", "do", "verb"),
+    ("done", "do", "verb"),
+    ("goes", "go", "verb"),
+    ("went", "go", "verb"),
+    ("gone", "go", "verb"),
+    ("said", "say", "verb"),
+    ("made", "make", "verb"),
+    ("took", "take", "verb"),
+    ("taken", "take", "verb"),
+    ("came", "come", "verb"),
+    ("saw", "see", "verb"),
+    ("seen", "see", "verb"),
+    ("knew", "know", "verb"),
+    ("known", "know", "verb"),
+    ("thought", "think", "verb"),
+    ("found", "find", "verb"),
+    ("gave", "give", "verb"),
+    ("given", "give", "verb"),
+    ("told", "tell", "verb"),
+    ("felt", "feel", "verb"),
+    ("became", "become", "verb"),
+    ("left", "leave", "verb"),
+    ("brought", "bring", "verb"),
+    ("began", "begin", "verb"),
+    ("begun", "begin", "verb"),
+    ("kept", "keep", "verb"),
+    ("held", "hold", "verb"),
+    ("stood", "stand", "verb"),
+    ("heard", "hear", "verb"),
+    ("ran", "run", "verb"),
+    ("wrote", "write", "verb"),
+    ("written", "write", "verb"),
+    ("sat", "sit", "verb"),
+    ("spoke", "speak", "verb"),
+    ("spoken", "speak", "verb"),
+    ("ate", "eat", "verb"),
+    ("eaten", "eat", "verb"),
+    ("fell", "fall", "verb"),
+    ("fallen", "fall", "verb"),
+    ("got", "get", "verb"),
+    ("gotten", "get", "verb"),
+    ("bought", "buy", "verb"),
+    ("caught", "catch", "verb"),
+    ("taught", "teach", "verb"),
+    ("fought", "fight", "verb"),
+    ("sought", "seek", "verb"),
+    ("meant", "mean", "verb"),
+    ("lost", "lose", "verb"),
+    ("paid", "pay", "verb"),
+    ("met", "meet", "verb"),
+    ("led", "lead", "verb"),
+    ("understood", "understand", "verb"),
+    ("chose", "choose", "verb"),
+    ("chosen", "choose", "verb"),
+    ("drove", "drive", "verb"),
+    ("driven", "drive", "verb"),
+    ("rose", "rise", "verb"),
+    ("risen", "rise", "verb"),
+    ("broke", "break", "verb"),
+    ("broken", "break", "verb"),
+    ("forgot", "forget", "verb"),
+    ("forgotten", "forget", "verb"),
+    ("wore", "wear", "verb"),
+    ("worn", "wear", "verb"),
+    ("threw", "throw", "verb"),
+    ("thrown", "throw", "verb"),
+    ("flew", "fly", "verb"),
+    ("flown", "fly", "verb"),
+    ("grew", "grow", "verb"),
+    ("grown", "grow", "verb"),
+    ("drew", "draw", "verb"),
+    ("drawn", "draw", "verb"),
+    ("sang", "sing", "verb"),
+    ("sung", "sing", "verb"),
+    ("swam", "swim", "verb"),
+    ("won", "win", "verb"),
+    ("built", "build", "verb"),
+    ("sent", "send", "verb"),
+    ("spent", "spend", "verb"),
+    ("slept", "sleep", "verb"),
+    ("wept", "weep", "verb"),
+    ("men", "man", "noun"),
+    ("women", "woman", "noun"),
+    ("children", "child", "noun"),
+    ("feet", "foot", "noun"),
+    ("teeth", "tooth", "noun"),
+    ("geese", "goose", "noun"),
+    ("mice", "mouse", "noun"),
+    ("people", "person", "noun"),
+    ("oxen", "ox", "noun"),
+    ("data", "datum", "noun"),
+    ("criteria", "criterion", "noun"),
+    ("phenomena", "phenomenon", "noun"),
+    ("better", "good", "adjective"),
+    ("best", "good", "adjective"),
+    ("worse", "bad", "adjective"),
+    ("worst", "bad", "adjective"),
+];
+
+/// Words after which a noun usually follows
+const DETERMINERS: &[&str] = &[
+    "the", "a", "an", "this", "that", "these", "those", "my", "your", "his", "her", "its", "our",
+    "their", "some", "every", "each",
+];
+
+/// Words after which a bare verb usually follows
+const VERB_MARKERS: &[&str] = &[
+    "to", "will", "would", "can", "could", "should", "must", "may", "might", "shall", "not", "i",
+    "we", "they", "you",
+];
+
+/// Words after which an adjective usually follows
+const INTENSIFIERS: &[&str] = &[
+    "very", "too", "so", "quite", "rather", "more", "most", "less",
+];
+
+/// Noun, adjective, adverb and verb-forming suffixes
+const SUFFIX_PARTS_OF_SPEECH: &[(&str, &str)] = &[
+    ("tion", "noun"),
+    ("sion", "noun"),
+    ("ment", "noun"),
+    ("ness", "noun"),
+    ("ity", "noun"),
+    ("ism", "noun"),
+    ("ship", "noun"),
+    ("hood", "noun"),
+    ("ous", "adjective"),
+    ("ful", "adjective"),
+    ("ive", "adjective"),
+    ("able", "adjective"),
+    ("ible", "adjective"),
+    ("less", "adjective"),
+    ("ical", "adjective"),
+    ("ly", "adverb"),
+    ("ize", "verb"),
+    ("ise", "verb"),
+    ("ify", "verb"),
+];
+
+struct English;
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u')
+}
+
+/// A stem long enough, and with a vowel, to be a word
+fn plausible(stem: &str, min_len: usize) -> bool {
+    stem.chars().count() >= min_len && stem.chars().any(|c| is_vowel(c) || c == 'y')
+}
+
+/// A stem whose dropped final "e" should come back: "bak" (baked),
+/// "creat" (created), "danc" (dancing)
+fn wants_final_e(stem: &str) -> bool {
+    if ["at", "bl", "iz", "v", "c", "dg", "us"]
+        .iter()
+        .any(|ending| stem.ends_with(ending))
+    {
+        return true;
+    }
+    // One short consonant-vowel-consonant syllable, as in "hop" or "bak"
+    let chars: Vec<char> = stem.chars().collect();
+    let vowel_groups = chars
+        .iter()
+        .enumerate()
+        .filter(|(i, c)| is_vowel(**c) && (*i == 0 || !is_vowel(chars[i - 1])))
+        .count();
+    match chars[..] {
+        [.., a, b, c] => {
+            vowel_groups == 1
+                && !is_vowel(a)
+                && is_vowel(b)
+                && !is_vowel(c)
+                && !matches!(c, 'w' | 'x' | 'y')
+        }
+        _ => false,
+    }
+}
+
+/// "stopp" -> "stop", but not "call" or "kiss"
+fn undouble(stem: &str) -> Option<&str> {
+    let mut chars = stem.chars().rev();
+    let (last, before) = (chars.next()?, chars.next()?);
+    (last == before && !is_vowel(last) && !matches!(last, 'l' | 's' | 'z'))
+        .then(|| &stem[..stem.len() - last.len_utf8()])
+}
+
+/// Verb stems for a word with "ed" or "ing" removed
+fn verb_stems(stem: &str, out: &mut Vec<Candidate>) {
+    if !plausible(stem, 2) {
+        return;
+    }
+    let verb = |lemma: String| Candidate::new(lemma, Some("verb"));
+    if let Some(single) = undouble(stem) {
+        out.push(verb(single.to_string()));
+    }
+    if wants_final_e(stem) {
+        out.push(verb(format!("{}e", stem)));
+        out.push(verb(stem.to_string()));
+    } else {
+        out.push(verb(stem.to_string()));
+        out.push(verb(format!("{}e", stem)));
+    }
+}
+
+impl Lemmatizer for English {
+    fn candidates(&self, word: &str) -> Vec<Candidate> {
+        let word = word.trim_end_matches(['\'', '’']);
+        let word = word
+            .strip_suffix("'s")
+            .or_else(|| word.strip_suffix("’s"))
+            .unwrap_or(word);
+
+        let mut out = Vec::new();
+        if let Some((_, lemma, pos)) = IRREGULAR.iter().find(|(form, _, _)| *form == word) {
+            out.push(Candidate::new(*lemma, Some(*pos)));
+        }
+
+        // Plurals and third person singular
+        if let Some(stem) = word.strip_suffix("ies").filter(|s| plausible(s, 2)) {
+            out.push(Candidate::new(format!("{}y", stem), None));
+        } else if let Some(stem) = word.strip_suffix("ves").filter(|s| plausible(s, 2)) {
+            out.push(Candidate::new(format!("{}f", stem), Some("noun")));
+            out.push(Candidate::new(format!("{}fe", stem), Some("noun")));
+        }
+        if let Some(stem) = word.strip_suffix("es").filter(|s| plausible(s, 2)) {
+            if stem.ends_with(['s', 'x', 'z', 'o']) || stem.ends_with("ch") || stem.ends_with("sh")
+            {
+                out.push(Candidate::new(stem, None));
+            }
+        }
+        if let Some(stem) = word.strip_suffix('s').filter(|s| plausible(s, 2)) {
+            if !word.ends_with("ss") && !word.ends_with("us") && !word.ends_with("is") {
+                out.push(Candidate::new(stem, None));
+            }
+        }
+
+        // Past tense and participles
+        if let Some(stem) = word.strip_suffix("ied").filter(|s| plausible(s, 2)) {
+            out.push(Candidate::new(format!("{}y", stem), Some("verb")));
+        } else if let Some(stem) = word.strip_suffix("ed") {
+            verb_stems(stem, &mut out);
+        }
+        if let Some(stem) = word.strip_suffix("ing") {
+            verb_stems(stem, &mut out);
+        }
+
+        // Derived forms are often headwords themselves, so try the word
+        // before stripping comparative and adverb suffixes
+        out.push(Candidate::new(word, None));
+
+        if let Some(stem) = word
+            .strip_suffix("ier")
+            .or_else(|| word.strip_suffix("iest"))
+        {
+            if plausible(stem, 2) {
+                out.push(Candidate::new(format!("{}y", stem), Some("adjective")));
+            }
+        } else if let Some(stem) = word.strip_suffix("er").or_else(|| word.strip_suffix("est")) {
+            if plausible(stem, 2) {
+                let adjective = |lemma: String| Candidate::new(lemma, Some("adjective"));
+                if let Some(single) = undouble(stem) {
+                    out.push(adjective(single.to_string()));
+                }
+                out.push(adjective(stem.to_string()));
+                out.push(adjective(format!("{}e", stem)));
+            }
+        }
+        if let Some(stem) = word.strip_suffix("ily").filter(|s| plausible(s, 2)) {
+            out.push(Candidate::new(format!("{}y", stem), Some("adverb")));
+        } else if let Some(stem) = word.strip_suffix("ly").filter(|s| plausible(s, 3)) {
+            out.push(Candidate::new(stem, Some("adverb")));
+        }
+
+        let mut seen = Vec::new();
+        out.retain(|c| {
+            let new = !seen.contains(&c.lemma);
+            seen.push(c.lemma.clone());
+            new
+        });
+        out
+    }
+
+    fn part_of_speech(&self, word: &str, previous: Option<&str>) -> Option<&'static str> {
+        if let Some(previous) = previous {
+            if DETERMINERS.contains(&previous) {
+                return Some("noun");
+            }
+            if VERB_MARKERS.contains(&previous) {
+                return Some("verb");
+            }
+            if INTENSIFIERS.contains(&previous) {
+                return Some("adjective");
+            }
+        }
+        SUFFIX_PARTS_OF_SPEECH
+            .iter()
+            .find(|(suffix, _)| word.len() > suffix.len() + 2 && word.ends_with(suffix))
+            .map(|(_, pos)| *pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lemmas(word: &str) -> Vec<String> {
+        English
+            .candidates(word)
+            .into_iter()
+            .map(|c| c.lemma)
+            .collect()
+    }
+
+    #[test]
+    fn uses_the_exception_table_first() {
+        assert_eq!(lemmas("children")[0], "child");
+        assert_eq!(lemmas("went")[0], "go");
+        assert_eq!(
+            English.candidates("was")[0],
+            Candidate::new("be", Some("verb"))
+        );
+    }
+
+    #[test]
+    fn strips_regular_inflections() {
+        assert_eq!(lemmas("stopped")[0], "stop");
+        assert_eq!(lemmas("baked")[0], "bake");
+        assert_eq!(lemmas("walked")[0], "walk");
+        assert_eq!(lemmas("created")[0], "create");
+        assert_eq!(lemmas("running")[0], "run");
+        assert_eq!(lemmas("carried")[0], "carry");
+        assert_eq!(lemmas("stories")[0], "story");
+        assert_eq!(lemmas("boxes")[0], "box");
+        assert_eq!(lemmas("wolves")[0], "wolf");
+        assert!(lemmas("knives").contains(&"knife".to_string()));
+    }
+
+    #[test]
+    fn keeps_words_that_only_look_inflected() {
+        assert_eq!(lemmas("kiss"), ["kiss"]);
+        assert_eq!(lemmas("bus"), ["bus"]);
+        assert_eq!(lemmas("bring"), ["bring"]);
+        assert_eq!(lemmas("water")[0], "water");
+    }
+
+    #[test]
+    fn tries_comparatives_and_adverbs_after_the_word() {
+        assert_eq!(lemmas("bigger"), ["bigger", "big", "bigg", "bigge"]);
+        assert_eq!(lemmas("happily"), ["happily", "happy"]);
+        assert_eq!(lemmas("quickly"), ["quickly", "quick"]);
+    }
+
+    #[test]
+    fn guesses_part_of_speech_from_context_and_suffix() {
+        assert_eq!(English.part_of_speech("run", Some("the")), Some("noun"));
+        assert_eq!(English.part_of_speech("run", Some("to")), Some("verb"));
+        assert_eq!(English.part_of_speech("kindness", None), Some("noun"));
+        assert_eq!(English.part_of_speech("famous", None), Some("adjective"));
+        assert_eq!(English.part_of_speech("run", None), None);
+    }
+
+    #[test]
+    fn other_languages_look_words_up_as_written() {
+        assert_eq!(
+            for_language("fr-CA").candidates("mangeons"),
+            [Candidate::new("mangeons", None)]
+        );
+        assert_eq!(for_language("en-GB").candidates("mice")[0].lemma, "mouse");
+    }
+}
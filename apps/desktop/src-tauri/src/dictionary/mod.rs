@@ -0,0 +1,298 @@
+// Read Master Desktop - Dictionary
+//
+// Offline word lookup backed by StarDict or dictd dictionaries. The
+// dictionary for a language comes from the `dictionary.paths` setting
+// (language -> file or directory), then `dictionary.path`, then a bundled
+// `dictionaries/<lang>` resource directory.
+//
+// `context` answers hover lookups with the word's sentence, lemma and entry
+// in one call; `lemma` reduces inflected words to dictionary headwords.
+
+pub mod context;
+mod formats;
+mod lemma;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use formats::{Article, Dictionary};
+
+use crate::profiles::ProfileStoreExt;
+
+/// Settings key holding language -> dictionary path
+const PATHS_SETTING: &str = "dictionary.paths";
+
+/// Part-of-speech markers that start a new definition block
+const PARTS_OF_SPEECH: &[(&str, &str)] = &[
+    ("n.", "noun"),
+    ("noun", "noun"),
+    ("v.", "verb"),
+    ("vt.", "verb"),
+    ("vi.", "verb"),
+    ("verb", "verb"),
+    ("adj.", "adjective"),
+    ("adjective", "adjective"),
+    ("adv.", "adverb"),
+    ("adverb", "adverb"),
+    ("pron.", "pronoun"),
+    ("pronoun", "pronoun"),
+    ("prep.", "preposition"),
+    ("preposition", "preposition"),
+    ("conj.", "conjunction"),
+    ("conjunction", "conjunction"),
+    ("interj.", "interjection"),
+    ("interjection", "interjection"),
+];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Definition {
+    pub word: String,
+    pub part_of_speech: Option<String>,
+    pub senses: Vec<String>,
+    /// Name of the dictionary the definition came from
+    pub dictionary: String,
+}
+
+/// Loaded dictionaries by path, so indexes are parsed once. Cheap to clone;
+/// clones share the same cache.
+#[derive(Clone, Default)]
+pub struct DictionaryCache(Arc<Mutex<HashMap<PathBuf, Arc<Dictionary>>>>);
+
+// ============================================================================
+// Configuration
+// ============================================================================
+
+/// Candidate languages for a tag: "pt-BR" tries "pt-BR", then "pt"
+fn language_candidates(lang: &str) -> Vec<String> {
+    let lang = lang.trim();
+    let mut candidates = vec![lang.to_string()];
+    if let Some((base, _)) = lang.split_once(['-', '_']) {
+        candidates.push(base.to_string());
+    }
+    candidates
+}
+
+fn dictionary_path<R: Runtime>(app: &AppHandle<R>, lang: &str) -> Option<PathBuf> {
+    let store = app.profile_store("settings.json").ok();
+    let setting = |key: &str| store.as_ref().and_then(|s| s.get(key));
+
+    let paths: HashMap<String, String> = setting(PATHS_SETTING)
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    let candidates = language_candidates(lang);
+
+    if let Some(path) = candidates.iter().find_map(|l| paths.get(l)) {
+        return Some(PathBuf::from(path));
+    }
+    if let Some(path) = setting("dictionary.path").and_then(|v| v.as_str().map(PathBuf::from)) {
+        return Some(path);
+    }
+
+    let bundled = app.path().resource_dir().ok()?.join("dictionaries");
+    candidates
+        .iter()
+        .map(|l| bundled.join(l))
+        .find(|dir| dir.is_dir())
+}
+
+fn load_cached(cache: &DictionaryCache, path: PathBuf) -> Result<Arc<Dictionary>, String> {
+    if let Some(dictionary) = cache.0.lock().ok().and_then(|c| c.get(&path).cloned()) {
+        return Ok(dictionary);
+    }
+
+    info!("Loading dictionary: {:?}", path);
+    let dictionary = Arc::new(formats::load(&path)?);
+    if let Ok(mut c) = cache.0.lock() {
+        c.insert(path, Arc::clone(&dictionary));
+    }
+    Ok(dictionary)
+}
+
+/// First sense for `word` in a bilingual dictionary, trying simple
+/// inflections of `from_lang` when the exact word is missing
+pub fn translate_word(
+    cache: &DictionaryCache,
+    path: PathBuf,
+    word: &str,
+    from_lang: &str,
+) -> Result<Option<String>, String> {
+    let dictionary = load_cached(cache, path)?;
+
+    let headword = std::iter::once(word.to_string())
+        .chain(fallback_forms(word, from_lang))
+        .find(|w| dictionary.contains(w));
+    let Some(headword) = headword else {
+        return Ok(None);
+    };
+
+    Ok(dictionary
+        .lookup(&headword)?
+        .iter()
+        .flat_map(|article| parse_article(article, &dictionary.name))
+        .flat_map(|definition| definition.senses)
+        .find(|sense| !sense.is_empty()))
+}
+
+// ============================================================================
+// Parsing
+// ============================================================================
+
+fn part_of_speech(line: &str) -> Option<(&'static str, &str)> {
+    let lower = line.to_lowercase();
+    PARTS_OF_SPEECH.iter().find_map(|(marker, name)| {
+        let rest = lower.strip_prefix(marker)?;
+        // "n." or "noun" must stand alone, not start a longer word
+        let boundary = marker.ends_with('.') || rest.is_empty() || rest.starts_with([' ', ':']);
+        boundary.then(|| (*name, line[marker.len()..].trim_start_matches([' ', ':'])))
+    })
+}
+
+/// Strip a sense number like "1.", "2)", "(3)" or "a." off a line
+fn sense_text(line: &str) -> (bool, &str) {
+    let trimmed = line.trim_start_matches('(');
+    let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+    let marker_len = if digits > 0 {
+        digits
+    } else if trimmed.len() > 1 && trimmed.as_bytes()[0].is_ascii_lowercase() {
+        1
+    } else {
+        return (false, line);
+    };
+
+    match trimmed[marker_len..].strip_prefix(['.', ')']) {
+        Some(rest) if rest.starts_with(' ') => (true, rest.trim()),
+        _ => (false, line),
+    }
+}
+
+/// Split an article into definitions by part of speech, with one sense per
+/// numbered line (or per line when unnumbered)
+fn parse_article(article: &Article, dictionary: &str) -> Vec<Definition> {
+    let mut definitions = Vec::new();
+    let mut current = Definition {
+        word: article.headword.clone(),
+        part_of_speech: None,
+        senses: Vec::new(),
+        dictionary: dictionary.to_string(),
+    };
+
+    for line in article
+        .text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+    {
+        if let Some((pos, rest)) = part_of_speech(line) {
+            if !current.senses.is_empty() || current.part_of_speech.is_some() {
+                definitions.push(current.clone());
+            }
+            current.part_of_speech = Some(pos.to_string());
+            current.senses.clear();
+
+            if !rest.is_empty() {
+                current.senses.push(sense_text(rest).1.to_string());
+            }
+            continue;
+        }
+
+        let (numbered, text) = sense_text(line);
+        match current.senses.last_mut() {
+            // Continuation of a wrapped sense
+            Some(last) if !numbered && !last.is_empty() && starts_lowercase(text) => {
+                last.push(' ');
+                last.push_str(text);
+            }
+            _ => current.senses.push(text.to_string()),
+        }
+    }
+
+    if !current.senses.is_empty() {
+        definitions.push(current);
+    }
+    definitions
+}
+
+fn starts_lowercase(text: &str) -> bool {
+    text.chars().next().is_some_and(char::is_lowercase)
+}
+
+/// Forms to try when the word itself isn't a headword
+fn fallback_forms(word: &str, lang: &str) -> Vec<String> {
+    if !lang.starts_with("en") {
+        return Vec::new();
+    }
+
+    let lower = word.to_lowercase();
+    let mut forms = Vec::new();
+    for (suffix, replacement) in [
+        ("ies", "y"),
+        ("es", ""),
+        ("s", ""),
+        ("ied", "y"),
+        ("ed", ""),
+        ("ed", "e"),
+        ("ing", ""),
+        ("ing", "e"),
+    ] {
+        if let Some(stem) = lower.strip_suffix(suffix) {
+            if stem.len() >= 2 {
+                forms.push(format!("{}{}", stem, replacement));
+            }
+        }
+    }
+    forms
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Look up a word in the dictionary configured for `lang`. Returns an empty
+/// list when the word isn't found.
+#[tauri::command]
+pub async fn lookup_word<R: Runtime>(
+    app: AppHandle<R>,
+    cache: State<'_, DictionaryCache>,
+    word: String,
+    lang: String,
+) -> Result<Vec<Definition>, String> {
+    let word = word
+        .trim()
+        .trim_matches(|c: char| !c.is_alphanumeric() && c != '\'' && c != '-')
+        .to_string();
+    if word.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let path = dictionary_path(&app, &lang)
+        .ok_or_else(|| format!("No dictionary configured for '{}'", lang))?;
+    let dictionary = load_cached(&cache, path)?;
+
+    let headword = std::iter::once(word.clone())
+        .chain(fallback_forms(&word, &lang))
+        .find(|w| dictionary.contains(w));
+    let Some(headword) = headword else {
+        return Ok(Vec::new());
+    };
+
+    let articles = dictionary.lookup(&headword)?;
+    let definitions: Vec<Definition> = articles
+        .iter()
+        .flat_map(|article| parse_article(article, &dictionary.name))
+        .collect();
+
+    if definitions.is_empty() {
+        warn!("Dictionary entry for '{}' had no text", headword);
+    }
+    Ok(definitions)
+}
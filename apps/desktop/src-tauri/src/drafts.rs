@@ -0,0 +1,368 @@
+// Read Master Desktop - Draft Autosave
+//
+// Crash-safe autosave for annotation drafts. Every `autosave_draft` call is
+// buffered in memory; the buffer is appended to a write-ahead log and
+// fsynced at most once per `FSYNC_INTERVAL`, so a crash loses at most that
+// much typing while calls stay cheap enough to make on every keystroke.
+//
+// The log is a JSON line per save or commit. On startup it is replayed:
+// drafts saved but never committed are offered back through a
+// `recovered-drafts` event once the main window has loaded, and the log is
+// rewritten with only those drafts, dropping committed ones. The log is
+// also compacted the same way whenever it outgrows `COMPACT_BYTES`.
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::webview::{PageLoadEvent, PageLoadPayload};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State, Webview};
+
+use crate::persist::Debouncer;
+use crate::{profiles, safe_mode};
+
+const WAL_FILE: &str = "drafts.wal";
+
+/// Longest a saved draft stays only in memory
+const FSYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Log size past which it is rewritten with just the open drafts
+const COMPACT_BYTES: u64 = 1024 * 1024;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Draft {
+    pub draft_id: String,
+    pub payload: Value,
+    pub saved_at: String,
+}
+
+/// One log line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum Entry {
+    Save(Draft),
+    #[serde(rename_all = "camelCase")]
+    Commit {
+        draft_id: String,
+    },
+}
+
+#[derive(Default)]
+struct Pending {
+    /// Entries not yet written to the log
+    buffer: Vec<Entry>,
+    /// Drafts saved and not committed, as the log will replay them
+    open: BTreeMap<String, Draft>,
+    /// Log size after the last write
+    wal_bytes: u64,
+}
+
+struct Shared {
+    path: PathBuf,
+    pending: Mutex<Pending>,
+    /// Serializes log writes between the flush thread and `flush`
+    write: Mutex<()>,
+}
+
+/// Managed draft log
+pub struct DraftLog {
+    shared: Arc<Shared>,
+    debouncer: Debouncer,
+    /// Drafts found at startup, until delivered to the main window
+    recovered: Mutex<Option<Vec<Draft>>>,
+}
+
+impl DraftLog {
+    fn record(&self, entry: Entry) -> Result<(), String> {
+        let mut pending = self
+            .shared
+            .pending
+            .lock()
+            .map_err(|_| "Draft log lock poisoned".to_string())?;
+        match &entry {
+            Entry::Save(draft) => {
+                pending.open.insert(draft.draft_id.clone(), draft.clone());
+            }
+            Entry::Commit { draft_id } => {
+                pending.open.remove(draft_id);
+            }
+        }
+        pending.buffer.push(entry);
+        drop(pending);
+
+        self.debouncer.mark_dirty(WAL_FILE);
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Log Files
+// ============================================================================
+
+/// Open drafts after replaying a log. A line torn by a crash mid-write is
+/// skipped.
+fn replay(reader: impl BufRead) -> BTreeMap<String, Draft> {
+    let mut open = BTreeMap::new();
+    for line in reader.lines().map_while(Result::ok) {
+        match serde_json::from_str::<Entry>(&line) {
+            Ok(Entry::Save(draft)) => {
+                open.insert(draft.draft_id.clone(), draft);
+            }
+            Ok(Entry::Commit { draft_id }) => {
+                open.remove(&draft_id);
+            }
+            Err(e) => warn!("Skipping unreadable draft log line: {}", e),
+        }
+    }
+    open
+}
+
+fn encode(entries: &[Entry]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut out, entry)
+            .map_err(|e| format!("Failed to encode draft: {}", e))?;
+        out.push(b'\n');
+    }
+    Ok(out)
+}
+
+/// Append entries and fsync. Returns the log's new size.
+fn append(path: &Path, entries: &[Entry]) -> Result<u64, String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open draft log: {}", e))?;
+    file.write_all(&encode(entries)?)
+        .and_then(|_| file.sync_data())
+        .map_err(|e| format!("Failed to write draft log: {}", e))?;
+    file.metadata()
+        .map(|m| m.len())
+        .map_err(|e| format!("Failed to read draft log size: {}", e))
+}
+
+/// Replace the log with one save per open draft. Returns its size.
+fn rewrite(path: &Path, open: &BTreeMap<String, Draft>) -> Result<u64, String> {
+    let entries: Vec<Entry> = open.values().cloned().map(Entry::Save).collect();
+    let data = encode(&entries)?;
+
+    let tmp = path.with_extension("wal.tmp");
+    let mut file = File::create(&tmp).map_err(|e| format!("Failed to write draft log: {}", e))?;
+    file.write_all(&data)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Failed to write draft log: {}", e))?;
+    std::fs::rename(&tmp, path).map_err(|e| format!("Failed to replace draft log: {}", e))?;
+    Ok(data.len() as u64)
+}
+
+/// Write buffered entries, compacting instead once the log is too large
+fn write_pending(shared: &Shared) -> Result<(), String> {
+    let _write = shared
+        .write
+        .lock()
+        .map_err(|_| "Draft log lock poisoned".to_string())?;
+
+    let (entries, snapshot) = {
+        let mut pending = shared
+            .pending
+            .lock()
+            .map_err(|_| "Draft log lock poisoned".to_string())?;
+        if pending.buffer.is_empty() {
+            return Ok(());
+        }
+        let entries = std::mem::take(&mut pending.buffer);
+        // The open drafts already include everything buffered
+        let snapshot = (pending.wal_bytes >= COMPACT_BYTES).then(|| pending.open.clone());
+        (entries, snapshot)
+    };
+
+    let size = match snapshot {
+        Some(open) => {
+            info!("Compacting draft log to {} open draft(s)", open.len());
+            rewrite(&shared.path, &open)?
+        }
+        None => append(&shared.path, &entries)?,
+    };
+    if let Ok(mut pending) = shared.pending.lock() {
+        pending.wal_bytes = size;
+    }
+    Ok(())
+}
+
+// ============================================================================
+// App Integration
+// ============================================================================
+
+/// Replay the draft log, drop committed drafts from it, and start the
+/// periodic fsync
+pub fn init<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let path = profiles::data_dir(app)?.join(WAL_FILE);
+
+    let open = match File::open(&path) {
+        Ok(file) => replay(BufReader::new(file)),
+        Err(_) => BTreeMap::new(),
+    };
+    let wal_bytes = rewrite(&path, &open)?;
+    if !open.is_empty() {
+        info!("Recovered {} unsaved draft(s)", open.len());
+    }
+
+    let shared = Arc::new(Shared {
+        path,
+        pending: Mutex::new(Pending {
+            buffer: Vec::new(),
+            open: open.clone(),
+            wal_bytes,
+        }),
+        write: Mutex::new(()),
+    });
+    let worker = Arc::clone(&shared);
+    let debouncer = Debouncer::new(FSYNC_INTERVAL, move |_| write_pending(&worker));
+
+    app.manage(DraftLog {
+        shared,
+        debouncer,
+        recovered: Mutex::new((!open.is_empty()).then(|| open.into_values().collect())),
+    });
+    Ok(())
+}
+
+/// Write buffered drafts now, logging failures
+pub fn flush<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(log) = app.try_state::<DraftLog>() {
+        let _ = log.debouncer.flush_all();
+    }
+}
+
+/// Builder-level page load hook offering recovered drafts to the main
+/// window once it can listen
+pub fn handle_page_load<R: Runtime>(webview: &Webview<R>, payload: &PageLoadPayload<'_>) {
+    if payload.event() != PageLoadEvent::Finished || webview.label() != "main" {
+        return;
+    }
+
+    // Held back in safe mode; they stay in the log for the next launch
+    if safe_mode::is_active() {
+        return;
+    }
+
+    let recovered = webview
+        .try_state::<DraftLog>()
+        .and_then(|log| log.recovered.lock().ok()?.take());
+    if let Some(drafts) = recovered {
+        let _ = webview.emit_to(webview.label(), "recovered-drafts", drafts);
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Save a draft's latest content. Cheap; call as often as the user types.
+#[tauri::command]
+pub fn autosave_draft(
+    log: State<'_, DraftLog>,
+    draft_id: String,
+    payload: Value,
+) -> Result<(), String> {
+    log.record(Entry::Save(Draft {
+        draft_id,
+        payload,
+        saved_at: chrono::Utc::now().to_rfc3339(),
+    }))
+}
+
+/// Drafts saved and not yet committed
+#[tauri::command]
+pub fn get_unsaved_drafts(log: State<'_, DraftLog>) -> Result<Vec<Draft>, String> {
+    let pending = log
+        .shared
+        .pending
+        .lock()
+        .map_err(|_| "Draft log lock poisoned".to_string())?;
+    Ok(pending.open.values().cloned().collect())
+}
+
+/// Mark a draft as saved for real (or discarded), so it isn't recovered
+#[tauri::command]
+pub fn commit_draft(log: State<'_, DraftLog>, draft_id: String) -> Result<(), String> {
+    log.record(Entry::Commit { draft_id })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn draft(id: &str, body: &str) -> Draft {
+        Draft {
+            draft_id: id.to_string(),
+            payload: json!({ "body": body }),
+            saved_at: "2024-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn replay_keeps_uncommitted_drafts() {
+        let path = std::env::temp_dir().join(format!("rm-drafts-{}.wal", uuid::Uuid::new_v4()));
+        let entries = [
+            Entry::Save(draft("a", "first")),
+            Entry::Save(draft("b", "note")),
+            Entry::Save(draft("a", "first draft")),
+            Entry::Commit {
+                draft_id: "b".to_string(),
+            },
+        ];
+        append(&path, &entries[..2]).unwrap();
+        append(&path, &entries[2..]).unwrap();
+
+        // A crash mid-write leaves a torn last line
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"op\":\"save\",\"draftId\":\"c\",\"pay")
+            .unwrap();
+
+        let open = replay(BufReader::new(File::open(&path).unwrap()));
+        assert_eq!(open.len(), 1);
+        assert_eq!(open["a"], draft("a", "first draft"));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn rewrite_drops_committed_drafts() {
+        let path = std::env::temp_dir().join(format!("rm-drafts-{}.wal", uuid::Uuid::new_v4()));
+        append(
+            &path,
+            &[
+                Entry::Save(draft("a", "kept")),
+                Entry::Save(draft("b", "done")),
+                Entry::Commit {
+                    draft_id: "b".to_string(),
+                },
+            ],
+        )
+        .unwrap();
+
+        let open = replay(BufReader::new(File::open(&path).unwrap()));
+        let size = rewrite(&path, &open).unwrap();
+        assert_eq!(size, std::fs::metadata(&path).unwrap().len());
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        assert_eq!(replay(contents.as_bytes()), open);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,290 @@
+// Read Master Desktop - EPUB CFI
+//
+// Mapping between EPUB canonical fragment identifiers and word positions in
+// a spine document, so a position can be turned into a text snippet and
+// rebuilt after the book's file changes. Only the parts of the grammar the
+// reader produces are handled: the spine step, element steps, a text step,
+// and a character offset. Ranges resolve to their start.
+//
+// Words are counted over the same text `document_text` extracts (the body,
+// minus scripts and styles), so word positions line up with chapter text.
+
+use std::fmt;
+
+use roxmltree::{Document, Node, ParsingOptions};
+
+use super::text::SKIPPED_ELEMENTS;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// A parsed position CFI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfi {
+    pub spine_index: usize,
+    /// Steps below the document's root element
+    pub steps: Vec<usize>,
+    /// Character offset into the target text node
+    pub offset: usize,
+}
+
+/// Leading number of a step or offset, dropping assertions like `[chap01]`
+fn leading_number(step: &str) -> Option<usize> {
+    let digits: String = step.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+fn parse_steps(path: &str) -> Option<Vec<usize>> {
+    path.split('/')
+        .filter(|s| !s.is_empty())
+        .map(leading_number)
+        .collect()
+}
+
+impl Cfi {
+    pub fn parse(cfi: &str) -> Option<Self> {
+        let inner = cfi.trim().strip_prefix("epubcfi(")?.strip_suffix(')')?;
+
+        // A range is "parent,start,end"; its start is parent + start
+        let mut parts = inner.split(',');
+        let path = match (parts.next(), parts.next()) {
+            (Some(parent), Some(start)) => format!("{}{}", parent, start),
+            (Some(parent), None) => parent.to_string(),
+            _ => return None,
+        };
+
+        let (package, content) = path.split_once('!')?;
+        // "/6" is the spine, the next step the itemref
+        let itemref = *parse_steps(package)?.get(1)?;
+        if itemref < 2 || itemref % 2 != 0 {
+            return None;
+        }
+
+        let (content, offset) = match content.split_once(':') {
+            Some((content, offset)) => (content, leading_number(offset)?),
+            None => (content, 0),
+        };
+        let steps = parse_steps(content)?;
+        if steps.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            spine_index: itemref / 2 - 1,
+            steps,
+            offset,
+        })
+    }
+}
+
+impl fmt::Display for Cfi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "epubcfi(/6/{}!", (self.spine_index + 1) * 2)?;
+        for step in &self.steps {
+            write!(f, "/{}", step)?;
+        }
+        if self.steps.last().is_some_and(|step| step % 2 == 1) {
+            write!(f, ":{}", self.offset)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// ============================================================================
+// Document Walking
+// ============================================================================
+
+pub(super) fn parse(markup: &str) -> Option<Document<'_>> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    Document::parse_with_options(markup, options).ok()
+}
+
+pub(super) fn is_reading_text(node: &Node) -> bool {
+    node.is_text()
+        && !node.ancestors().any(|a| {
+            a.is_element()
+                && SKIPPED_ELEMENTS.contains(&a.tag_name().name().to_ascii_lowercase().as_str())
+        })
+}
+
+/// Text nodes of the body, in document order
+fn text_nodes<'a, 'input>(doc: &'a Document<'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    let body = doc
+        .descendants()
+        .find(|n| n.tag_name().name() == "body")
+        .unwrap_or(doc.root_element());
+    body.descendants().filter(is_reading_text)
+}
+
+/// The node a step path points at. Even steps select element children;
+/// odd steps select the text after `(step - 1) / 2` elements.
+fn resolve<'a, 'input>(root: Node<'a, 'input>, steps: &[usize]) -> Option<Node<'a, 'input>> {
+    let mut node = root;
+    for &step in steps {
+        if step % 2 == 0 {
+            let index = (step / 2).checked_sub(1)?;
+            node = node.children().filter(Node::is_element).nth(index)?;
+        } else {
+            let slot = (step - 1) / 2;
+            let mut elements = 0;
+            let text = node.children().find(|child| {
+                if child.is_element() {
+                    elements += 1;
+                }
+                child.is_text() && elements == slot
+            });
+            // An empty slot points at the parent
+            if let Some(text) = text {
+                node = text;
+            }
+        }
+    }
+    Some(node)
+}
+
+/// Words before a character offset; a word the offset falls inside counts
+/// as the target, not as before it
+pub fn words_before(text: &str, offset: usize) -> usize {
+    let split = text
+        .char_indices()
+        .nth(offset)
+        .map_or(text.len(), |(i, _)| i);
+    let (prefix, rest) = text.split_at(split);
+    let count = prefix.split_whitespace().count();
+    let inside_word = !prefix.is_empty()
+        && !prefix.ends_with(char::is_whitespace)
+        && !rest.is_empty()
+        && !rest.starts_with(char::is_whitespace);
+    count - usize::from(inside_word)
+}
+
+/// Step from a node's parent to the node
+fn step_to(node: &Node) -> usize {
+    let elements_before = node
+        .prev_siblings()
+        .skip(1)
+        .filter(Node::is_element)
+        .count();
+    if node.is_element() {
+        elements_before * 2 + 2
+    } else {
+        elements_before * 2 + 1
+    }
+}
+
+/// Steps from the document's root element down to `node`
+pub(super) fn steps_to(node: &Node) -> Option<Vec<usize>> {
+    let root = node.document().root_element();
+    let mut steps = vec![step_to(node)];
+    let mut current = node.parent()?;
+    while current != root {
+        steps.push(step_to(&current));
+        current = current.parent()?;
+    }
+    steps.reverse();
+    Some(steps)
+}
+
+// ============================================================================
+// Conversion
+// ============================================================================
+
+/// Index of the word a CFI points at within its document's reading text
+pub fn word_index(markup: &str, cfi: &Cfi) -> Option<usize> {
+    let doc = parse(markup)?;
+    let target = resolve(doc.root_element(), &cfi.steps)?;
+
+    let mut words = 0;
+    for node in text_nodes(&doc) {
+        let text = node.text().unwrap_or_default();
+        if node == target {
+            return Some(words + words_before(text, cfi.offset));
+        }
+        if node.ancestors().any(|a| a == target) {
+            return Some(words);
+        }
+        words += text.split_whitespace().count();
+    }
+    Some(words)
+}
+
+/// CFI of the `word`th word of a document's reading text
+pub fn from_word_index(markup: &str, spine_index: usize, word: usize) -> Option<Cfi> {
+    let doc = parse(markup)?;
+    let mut words = 0;
+    for node in text_nodes(&doc) {
+        let text = node.text().unwrap_or_default();
+        let count = text.split_whitespace().count();
+        if word >= words + count {
+            words += count;
+            continue;
+        }
+
+        let start = text.split_whitespace().nth(word - words)?;
+        let byte = start.as_ptr() as usize - text.as_ptr() as usize;
+        let offset = text[..byte].chars().count();
+
+        return Some(Cfi {
+            spine_index,
+            steps: steps_to(&node)?,
+            offset,
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAPTER: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Ignored title</title><style>p { margin: 0 }</style></head>
+<body>
+  <h1 id="c1">Chapter One</h1>
+  <p>It was a <em>bright cold</em> day in April.</p>
+  <p>The clocks were striking thirteen.</p>
+</body>
+</html>"#;
+
+    #[test]
+    fn parses_positions_and_ranges() {
+        let cfi = Cfi::parse("epubcfi(/6/4[chap01]!/4/2/1:3)").unwrap();
+        assert_eq!(cfi.spine_index, 1);
+        assert_eq!(cfi.steps, [4, 2, 1]);
+        assert_eq!(cfi.offset, 3);
+        assert_eq!(cfi.to_string(), "epubcfi(/6/4!/4/2/1:3)");
+
+        let range = Cfi::parse("epubcfi(/6/2!/4/6,/1:4,/1:10)").unwrap();
+        assert_eq!(range.steps, [4, 6, 1]);
+        assert_eq!(range.offset, 4);
+
+        assert!(Cfi::parse("/6/4!/4/2").is_none());
+    }
+
+    #[test]
+    fn counts_words_up_to_a_position() {
+        // "clocks" in the second paragraph
+        let cfi = Cfi::parse("epubcfi(/6/2!/4/6/1:4)").unwrap();
+        assert_eq!(word_index(CHAPTER, &cfi), Some(11));
+
+        // The <em> element itself
+        let cfi = Cfi::parse("epubcfi(/6/2!/4/4/2)").unwrap();
+        assert_eq!(word_index(CHAPTER, &cfi), Some(5));
+    }
+
+    #[test]
+    fn round_trips_word_positions() {
+        for word in [0, 4, 5, 8, 14] {
+            let cfi = from_word_index(CHAPTER, 3, word).unwrap();
+            assert_eq!(cfi.spine_index, 3);
+            let reparsed = Cfi::parse(&cfi.to_string()).unwrap();
+            assert_eq!(word_index(CHAPTER, &reparsed), Some(word), "{}", cfi);
+        }
+        assert!(from_word_index(CHAPTER, 0, 100).is_none());
+    }
+}
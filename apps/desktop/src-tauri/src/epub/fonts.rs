@@ -0,0 +1,371 @@
+// Read Master Desktop - EPUB Fonts
+//
+// Embedded font discovery and font-face declarations.
+
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{parent_dir, resolve_href, EpubArchive, Obfuscation};
+
+/// Font media types from the EPUB core media types and common variants
+const FONT_MEDIA_TYPES: &[&str] = &[
+    "font/ttf",
+    "font/otf",
+    "font/woff",
+    "font/woff2",
+    "application/font-sfnt",
+    "application/font-woff",
+    "application/vnd.ms-opentype",
+    "application/x-font-ttf",
+    "application/x-font-otf",
+    "application/x-font-truetype",
+    "application/x-font-opentype",
+];
+
+const FONT_EXTENSIONS: &[&str] = &[".ttf", ".otf", ".woff", ".woff2"];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontEntry {
+    /// Archive path of the font file
+    pub href: String,
+    pub media_type: Option<String>,
+    /// Family name read from the font's `name` table
+    pub family: Option<String>,
+    pub obfuscated: bool,
+    pub obfuscation: Option<Obfuscation>,
+    /// Families declared for this file by `@font-face` rules
+    pub declared_families: Vec<String>,
+    /// Stylesheets declaring this file
+    pub declared_in: Vec<String>,
+    /// False when only referenced from CSS, not listed in the manifest
+    pub in_manifest: bool,
+    /// False when the file is missing from the archive
+    pub exists: bool,
+}
+
+/// A parsed `@font-face` rule
+#[derive(Debug, Clone)]
+pub struct FontFaceRule {
+    pub family: Option<String>,
+    /// Archive paths of `url()` sources, resolved against the stylesheet
+    pub sources: Vec<String>,
+}
+
+// ============================================================================
+// CSS
+// ============================================================================
+
+fn strip_css_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim().trim_matches(|c| c == '"' || c == '\'').trim()
+}
+
+/// Extract `@font-face` rules from a stylesheet located at `css_path`
+pub fn parse_font_faces(css: &str, css_path: &str) -> Vec<FontFaceRule> {
+    let css = strip_css_comments(css);
+    let base = parent_dir(css_path);
+    let lower = css.to_ascii_lowercase();
+    let mut rules = Vec::new();
+    let mut offset = 0;
+
+    while let Some(found) = lower[offset..].find("@font-face") {
+        let start = offset + found;
+        let Some(open) = css[start..].find('{').map(|i| start + i) else {
+            break;
+        };
+        let Some(close) = css[open..].find('}').map(|i| open + i) else {
+            break;
+        };
+        let body = &css[open + 1..close];
+        offset = close + 1;
+
+        let mut rule = FontFaceRule {
+            family: None,
+            sources: Vec::new(),
+        };
+
+        for declaration in body.split(';') {
+            let Some((property, value)) = declaration.split_once(':') else {
+                continue;
+            };
+
+            match property.trim().to_ascii_lowercase().as_str() {
+                "font-family" => rule.family = Some(unquote(value).to_string()),
+                "src" => {
+                    let mut rest = value;
+                    while let Some(i) = rest.find("url(") {
+                        let after = &rest[i + 4..];
+                        let Some(end) = after.find(')') else {
+                            break;
+                        };
+                        let url = unquote(&after[..end]);
+                        if !url.starts_with("data:") && !url.contains("://") {
+                            rule.sources.push(resolve_href(base, url));
+                        }
+                        rest = &after[end + 1..];
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        rules.push(rule);
+    }
+
+    rules
+}
+
+// ============================================================================
+// Font Files
+// ============================================================================
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    data.get(at..at + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Locate the `name` table, decompressing it for WOFF files
+fn name_table(data: &[u8]) -> Option<Vec<u8>> {
+    match data.get(0..4)? {
+        b"wOFF" => {
+            let num_tables = read_u16(data, 12)? as usize;
+            for i in 0..num_tables {
+                let record = 44 + i * 20;
+                if data.get(record..record + 4)? != b"name" {
+                    continue;
+                }
+                let offset = read_u32(data, record + 4)? as usize;
+                let comp_length = read_u32(data, record + 8)? as usize;
+                let orig_length = read_u32(data, record + 12)? as usize;
+                let table = data.get(offset..offset + comp_length)?;
+
+                if comp_length == orig_length {
+                    return Some(table.to_vec());
+                }
+
+                // The header's length is only a claim; stop at it, and at the
+                // ratio the archive limits allow
+                let limits = super::limits::current();
+                let cap = orig_length.min(comp_length.saturating_mul(limits.max_ratio as usize));
+                let mut out = Vec::with_capacity(cap);
+                ZlibDecoder::new(table)
+                    .take(cap as u64)
+                    .read_to_end(&mut out)
+                    .ok()?;
+                return Some(out);
+            }
+            None
+        }
+        // WOFF2 tables are Brotli-compressed as a whole; not worth decoding
+        // just for a family name
+        b"wOF2" => None,
+        header => {
+            // Font collections: use the first font
+            let base = if header == b"ttcf" {
+                read_u32(data, 12)? as usize
+            } else {
+                0
+            };
+
+            let num_tables = read_u16(data, base + 4)? as usize;
+            for i in 0..num_tables {
+                let record = base + 12 + i * 16;
+                if data.get(record..record + 4)? != b"name" {
+                    continue;
+                }
+                let offset = read_u32(data, record + 8)? as usize;
+                let length = read_u32(data, record + 12)? as usize;
+                return data.get(offset..offset + length).map(<[u8]>::to_vec);
+            }
+            None
+        }
+    }
+}
+
+fn decode_name(platform: u16, raw: &[u8]) -> String {
+    match platform {
+        // Macintosh Roman; close enough to Latin-1 for family names
+        1 => raw.iter().map(|&b| b as char).collect(),
+        _ => {
+            let units: Vec<u16> = raw
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+    }
+}
+
+/// Read the family name from an OpenType/TrueType/WOFF font
+pub fn font_family_name(data: &[u8]) -> Option<String> {
+    let table = name_table(data)?;
+    let count = read_u16(&table, 2)? as usize;
+    let strings = read_u16(&table, 4)? as usize;
+
+    // Typographic family (16) beats legacy family (1); English Windows
+    // names beat everything else
+    let mut best: Option<(u8, String)> = None;
+
+    for i in 0..count {
+        let record = 6 + i * 12;
+        let platform = read_u16(&table, record)?;
+        let language = read_u16(&table, record + 4)?;
+        let name_id = read_u16(&table, record + 6)?;
+        let length = read_u16(&table, record + 8)? as usize;
+        let offset = read_u16(&table, record + 10)? as usize;
+
+        if name_id != 1 && name_id != 16 {
+            continue;
+        }
+
+        let Some(raw) = table.get(strings + offset..strings + offset + length) else {
+            continue;
+        };
+        let name = decode_name(platform, raw).trim().to_string();
+        if name.is_empty() {
+            continue;
+        }
+
+        let score = u8::from(name_id == 16) * 4
+            + u8::from(platform == 3) * 2
+            + u8::from(language == 0x409 || language == 0);
+
+        if best.as_ref().map_or(true, |(s, _)| score > *s) {
+            best = Some((score, name));
+        }
+    }
+
+    best.map(|(_, name)| name)
+}
+
+fn is_font(media_type: &str, path: &str) -> bool {
+    let lower = path.to_ascii_lowercase();
+    FONT_MEDIA_TYPES.contains(&media_type)
+        || media_type.starts_with("font/")
+        || FONT_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// List fonts embedded in an EPUB, with obfuscation and CSS declarations
+#[tauri::command]
+pub async fn list_epub_fonts(path: String) -> Result<Vec<FontEntry>, String> {
+    info!("Listing EPUB fonts: {}", path);
+
+    let mut epub = EpubArchive::open(&path).map_err(|e| e.to_string())?;
+    let obfuscated = epub.obfuscated_resources()?;
+
+    let mut entries: Vec<FontEntry> = epub
+        .package
+        .manifest
+        .iter()
+        .filter(|item| is_font(&item.media_type, &item.path))
+        .map(|item| FontEntry {
+            href: item.path.clone(),
+            media_type: Some(item.media_type.clone()),
+            family: None,
+            obfuscated: obfuscated.contains_key(&item.path),
+            obfuscation: obfuscated.get(&item.path).copied(),
+            declared_families: Vec::new(),
+            declared_in: Vec::new(),
+            in_manifest: true,
+            exists: true,
+        })
+        .collect();
+
+    // Match @font-face rules from every stylesheet to font files
+    let stylesheets: Vec<String> = epub
+        .package
+        .manifest
+        .iter()
+        .filter(|item| item.media_type == "text/css")
+        .map(|item| item.path.clone())
+        .collect();
+
+    for css_path in stylesheets {
+        let css = match epub.read_string(&css_path) {
+            Ok(css) => css,
+            Err(e) => {
+                warn!("Skipping stylesheet {}: {}", css_path, e);
+                continue;
+            }
+        };
+
+        for rule in parse_font_faces(&css, &css_path) {
+            for source in rule.sources {
+                let index = match entries.iter().position(|e| e.href == source) {
+                    Some(index) => index,
+                    None => {
+                        entries.push(FontEntry {
+                            href: source.clone(),
+                            media_type: None,
+                            family: None,
+                            obfuscated: obfuscated.contains_key(&source),
+                            obfuscation: obfuscated.get(&source).copied(),
+                            declared_families: Vec::new(),
+                            declared_in: Vec::new(),
+                            in_manifest: false,
+                            exists: true,
+                        });
+                        entries.len() - 1
+                    }
+                };
+
+                let entry = &mut entries[index];
+                if let Some(family) = &rule.family {
+                    if !entry.declared_families.contains(family) {
+                        entry.declared_families.push(family.clone());
+                    }
+                }
+                if !entry.declared_in.contains(&css_path) {
+                    entry.declared_in.push(css_path.clone());
+                }
+            }
+        }
+    }
+
+    for entry in &mut entries {
+        entry.exists = epub.contains(&entry.href);
+        if !entry.exists {
+            continue;
+        }
+
+        match epub.read_resource(&entry.href) {
+            Ok(data) => entry.family = font_family_name(&data),
+            Err(e) => warn!("Failed to read font {}: {}", entry.href, e),
+        }
+    }
+
+    info!("Found {} fonts", entries.len());
+    Ok(entries)
+}
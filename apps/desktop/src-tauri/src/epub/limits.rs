@@ -0,0 +1,472 @@
+// Read Master Desktop - Archive Limits
+//
+// Guards against zip bombs and hostile archives. The central directory is
+// checked before anything is decompressed: entry count, declared sizes,
+// compression ratio, and entry paths. Reads are capped as well, since the
+// sizes in the directory are only what the archive claims.
+//
+// Nested archives are never unpacked, so a zip inside a zip is just another
+// entry and is bounded by the same limits.
+//
+// The defaults leave plenty of room for illustrated books; power users with
+// larger ones can raise them, and the change is saved in settings and
+// applied by `init` on the next start.
+
+use std::fmt;
+use std::io::{Read, Seek};
+use std::sync::RwLock;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+use zip::ZipArchive;
+
+use crate::error::AppError;
+use crate::persist;
+use crate::profiles::ProfileStoreExt;
+
+const SETTINGS_STORE: &str = "settings.json";
+const LIMITS_SETTING: &str = "archive.limits";
+
+const MIB: u64 = 1024 * 1024;
+
+/// Entries smaller than this are never rejected for their ratio; a page of
+/// whitespace compresses far better than any bomb needs to
+const RATIO_MIN_SIZE: u64 = MIB;
+
+const DEFAULT_LIMITS: ArchiveLimits = ArchiveLimits {
+    max_entry_size: 256 * MIB,
+    max_total_size: 2048 * MIB,
+    max_entries: 10_000,
+    max_path_depth: 32,
+    max_ratio: 100,
+};
+
+static LIMITS: RwLock<ArchiveLimits> = RwLock::new(DEFAULT_LIMITS);
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveLimits {
+    /// Decompressed bytes of a single entry
+    pub max_entry_size: u64,
+    /// Decompressed bytes of all entries together
+    pub max_total_size: u64,
+    pub max_entries: usize,
+    /// Directory levels in an entry path
+    pub max_path_depth: usize,
+    /// Decompressed size over compressed size
+    pub max_ratio: u64,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        DEFAULT_LIMITS
+    }
+}
+
+/// An archive tripped one of the limits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SuspiciousArchive {
+    TooManyEntries {
+        count: usize,
+        limit: usize,
+    },
+    EntryTooLarge {
+        path: String,
+        size: u64,
+        limit: u64,
+    },
+    TotalTooLarge {
+        size: u64,
+        limit: u64,
+    },
+    PathTooDeep {
+        path: String,
+        depth: usize,
+        limit: usize,
+    },
+    /// Absolute, drive-prefixed, or containing `..`
+    UnsafePath {
+        path: String,
+    },
+    CompressionRatio {
+        path: String,
+        ratio: u64,
+        limit: u64,
+    },
+}
+
+impl SuspiciousArchive {
+    /// Which limit tripped, for logs and the frontend
+    pub fn limit(&self) -> &'static str {
+        match self {
+            Self::TooManyEntries { .. } => "max_entries",
+            Self::EntryTooLarge { .. } => "max_entry_size",
+            Self::TotalTooLarge { .. } => "max_total_size",
+            Self::PathTooDeep { .. } => "max_path_depth",
+            Self::UnsafePath { .. } => "unsafe_path",
+            Self::CompressionRatio { .. } => "max_ratio",
+        }
+    }
+}
+
+impl fmt::Display for SuspiciousArchive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Suspicious archive: ")?;
+        match self {
+            Self::TooManyEntries { count, limit } => {
+                write!(f, "{} entries (limit {})", count, limit)
+            }
+            Self::EntryTooLarge { path, size, limit } => {
+                write!(f, "{} is {} bytes (limit {})", path, size, limit)
+            }
+            Self::TotalTooLarge { size, limit } => {
+                write!(f, "contents are {} bytes (limit {})", size, limit)
+            }
+            Self::PathTooDeep { path, depth, limit } => {
+                write!(f, "{} is {} levels deep (limit {})", path, depth, limit)
+            }
+            Self::UnsafePath { path } => write!(f, "unsafe entry path {}", path),
+            Self::CompressionRatio { path, ratio, limit } => write!(
+                f,
+                "{} expands {}x when decompressed (limit {}x)",
+                path, ratio, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SuspiciousArchive {}
+
+// ============================================================================
+// Checks
+// ============================================================================
+
+/// The limits in effect
+pub fn current() -> ArchiveLimits {
+    *LIMITS.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Absolute, drive-prefixed, or escaping the archive with `..`
+pub fn is_unsafe_path(path: &str) -> bool {
+    let path = path.replace('\\', "/");
+    let bytes = path.as_bytes();
+    path.starts_with('/')
+        || (bytes.len() >= 2 && bytes[1] == b':' && bytes[0].is_ascii_alphabetic())
+        || path.split('/').any(|segment| segment == "..")
+}
+
+fn path_depth(path: &str) -> usize {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .count()
+        .saturating_sub(1)
+}
+
+/// Check an archive's central directory against `limits` without
+/// decompressing anything
+pub fn check_archive<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    limits: &ArchiveLimits,
+) -> Result<(), SuspiciousArchive> {
+    if zip.len() > limits.max_entries {
+        return Err(SuspiciousArchive::TooManyEntries {
+            count: zip.len(),
+            limit: limits.max_entries,
+        });
+    }
+
+    let mut total = 0u64;
+    for index in 0..zip.len() {
+        let Ok(entry) = zip.by_index_raw(index) else {
+            // Unreadable entries fail when they're read, like in any archive
+            continue;
+        };
+        let path = entry.name().to_string();
+        let size = entry.size();
+
+        if is_unsafe_path(&path) {
+            return Err(SuspiciousArchive::UnsafePath { path });
+        }
+        let depth = path_depth(&path);
+        if depth > limits.max_path_depth {
+            return Err(SuspiciousArchive::PathTooDeep {
+                path,
+                depth,
+                limit: limits.max_path_depth,
+            });
+        }
+        if size > limits.max_entry_size {
+            return Err(SuspiciousArchive::EntryTooLarge {
+                path,
+                size,
+                limit: limits.max_entry_size,
+            });
+        }
+        let ratio = size / entry.compressed_size().max(1);
+        if size >= RATIO_MIN_SIZE && ratio > limits.max_ratio {
+            return Err(SuspiciousArchive::CompressionRatio {
+                path,
+                ratio,
+                limit: limits.max_ratio,
+            });
+        }
+
+        total = total.saturating_add(size);
+        if total > limits.max_total_size {
+            return Err(SuspiciousArchive::TotalTooLarge {
+                size: total,
+                limit: limits.max_total_size,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Read an entry, stopping at `max_entry_size` however large the archive
+/// says it is
+pub fn read_limited(
+    entry: impl Read,
+    path: &str,
+    declared: u64,
+    limits: &ArchiveLimits,
+) -> Result<Vec<u8>, AppError> {
+    let limit = limits.max_entry_size;
+    let mut bytes = Vec::with_capacity(declared.min(limit) as usize);
+    entry
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut bytes)
+        .map_err(|e| AppError::CorruptFile {
+            message: format!("Failed to read {}", path),
+            details: Some(e.to_string()),
+        })?;
+
+    if bytes.len() as u64 > limit {
+        return Err(SuspiciousArchive::EntryTooLarge {
+            path: path.to_string(),
+            size: bytes.len() as u64,
+            limit,
+        }
+        .into());
+    }
+    Ok(bytes)
+}
+
+fn validate(limits: &ArchiveLimits) -> Result<(), String> {
+    if limits.max_entry_size == 0
+        || limits.max_total_size == 0
+        || limits.max_entries == 0
+        || limits.max_ratio == 0
+    {
+        return Err("Archive limits must be greater than zero".to_string());
+    }
+    if limits.max_entry_size > limits.max_total_size {
+        return Err("The entry size limit can't exceed the total size limit".to_string());
+    }
+    Ok(())
+}
+
+fn apply(limits: ArchiveLimits) {
+    *LIMITS.write().unwrap_or_else(|e| e.into_inner()) = limits;
+}
+
+/// Apply the limits saved by `set_archive_limits`, if any
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    let saved = app
+        .profile_store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(LIMITS_SETTING))
+        .and_then(|value| serde_json::from_value::<ArchiveLimits>(value).ok());
+
+    if let Some(saved) = saved {
+        match validate(&saved) {
+            Ok(()) => apply(saved),
+            Err(e) => warn!("Ignoring saved archive limits: {}", e),
+        }
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+#[tauri::command]
+pub fn get_archive_limits() -> ArchiveLimits {
+    current()
+}
+
+/// Change the archive limits now and on future starts; `None` restores the
+/// defaults
+#[tauri::command]
+pub fn set_archive_limits<R: Runtime>(
+    app: AppHandle<R>,
+    limits: Option<ArchiveLimits>,
+) -> Result<ArchiveLimits, String> {
+    let limits = limits.unwrap_or_default();
+    validate(&limits)?;
+    apply(limits);
+    info!("Archive limits set to {:?}", limits);
+
+    let store = app
+        .profile_store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    store.set(
+        LIMITS_SETTING,
+        serde_json::to_value(limits).map_err(|e| format!("Failed to save limits: {}", e))?,
+    );
+    persist::mark_dirty(&app, SETTINGS_STORE);
+    Ok(limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    use super::*;
+
+    fn archive(entries: &[(&str, Vec<u8>)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, content) in entries {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    fn check(entries: &[(&str, Vec<u8>)], limits: ArchiveLimits) -> Result<(), &'static str> {
+        check_archive(&mut archive(entries), &limits).map_err(|e| e.limit())
+    }
+
+    fn text(len: usize) -> Vec<u8> {
+        (0..len).map(|i| b'a' + (i * 7 % 26) as u8).collect()
+    }
+
+    #[test]
+    fn accepts_an_ordinary_book() {
+        let entries = [
+            ("mimetype", b"application/epub+zip".to_vec()),
+            ("OEBPS/text/chapter1.xhtml", text(4000)),
+            ("OEBPS/images/cover.jpg", text(2000)),
+        ];
+        assert_eq!(check(&entries, ArchiveLimits::default()), Ok(()));
+    }
+
+    #[test]
+    fn rejects_too_many_entries() {
+        let entries = ["a", "b", "c", "d", "e", "f"].map(|name| (name, vec![]));
+        let limits = ArchiveLimits {
+            max_entries: 5,
+            ..Default::default()
+        };
+        assert_eq!(check(&entries, limits), Err("max_entries"));
+    }
+
+    #[test]
+    fn rejects_large_entries_and_totals() {
+        let entries = [("a.xhtml", text(600)), ("b.xhtml", text(600))];
+        let limits = ArchiveLimits {
+            max_entry_size: 500,
+            ..Default::default()
+        };
+        assert_eq!(check(&entries, limits), Err("max_entry_size"));
+
+        let limits = ArchiveLimits {
+            max_total_size: 1000,
+            ..Default::default()
+        };
+        assert_eq!(check(&entries, limits), Err("max_total_size"));
+    }
+
+    #[test]
+    fn rejects_unsafe_and_deep_paths() {
+        for path in [
+            "../evil.xhtml",
+            "OEBPS/../../evil",
+            "/etc/passwd",
+            "C:\\evil",
+        ] {
+            assert_eq!(
+                check(&[(path, vec![])], ArchiveLimits::default()),
+                Err("unsafe_path"),
+                "{}",
+                path
+            );
+        }
+
+        let deep = format!("{}file.xhtml", "d/".repeat(40));
+        assert_eq!(
+            check(&[(&deep, vec![])], ArchiveLimits::default()),
+            Err("max_path_depth")
+        );
+    }
+
+    #[test]
+    fn rejects_bomb_ratios_but_not_small_entries() {
+        let bomb = [("zeros.xhtml", vec![0u8; 4 * MIB as usize])];
+        assert_eq!(check(&bomb, ArchiveLimits::default()), Err("max_ratio"));
+
+        let small = [("spaces.xhtml", vec![b' '; 64 * 1024])];
+        assert_eq!(check(&small, ArchiveLimits::default()), Ok(()));
+    }
+
+    #[test]
+    fn caps_reads_whatever_the_declared_size() {
+        let limits = ArchiveLimits {
+            max_entry_size: 100,
+            ..Default::default()
+        };
+        let data = text(150);
+        let error = read_limited(&data[..], "a.xhtml", 10, &limits).unwrap_err();
+        assert_eq!(error.code(), "suspicious_archive");
+        assert_eq!(error.details(), Some("max_entry_size"));
+        assert!(error.message().starts_with("Suspicious archive: a.xhtml"));
+        assert_eq!(
+            read_limited(&data[..100], "a.xhtml", 100, &limits)
+                .unwrap()
+                .len(),
+            100
+        );
+    }
+
+    #[test]
+    fn opening_a_suspicious_epub_fails_with_its_limit() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("../evil.xhtml", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(&text(10)).unwrap();
+        let path = std::env::temp_dir().join(format!("rm-limits-{}.epub", uuid::Uuid::new_v4()));
+        std::fs::write(&path, writer.finish().unwrap().into_inner()).unwrap();
+
+        let error = crate::epub::EpubArchive::open(&path.to_string_lossy())
+            .err()
+            .expect("suspicious archive opened");
+        assert_eq!(error.code(), "suspicious_archive");
+        assert_eq!(error.details(), Some("unsafe_path"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn validates_limits() {
+        assert!(validate(&ArchiveLimits::default()).is_ok());
+        let zero = ArchiveLimits {
+            max_entries: 0,
+            ..Default::default()
+        };
+        assert!(validate(&zero).is_err());
+        let inverted = ArchiveLimits {
+            max_entry_size: 10 * MIB,
+            max_total_size: MIB,
+            ..Default::default()
+        };
+        assert!(validate(&inverted).is_err());
+    }
+}
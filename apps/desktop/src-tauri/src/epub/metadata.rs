@@ -0,0 +1,495 @@
+// Read Master Desktop - EPUB Metadata
+//
+// Book metadata and reading direction from the OPF package.
+//
+// Fixed-layout books (comics, picture books) declare `rendition:layout`
+// as `pre-paginated` for the whole book, or per spine item, and give each
+// page's size in a viewport `<meta>` of its content document. Older ones
+// only set Apple's `fixed-layout` display option.
+
+use std::sync::LazyLock;
+
+use log::{info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::EpubArchive;
+
+const APPLE_OPTIONS_PATH: &str = "META-INF/com.apple.ibooks.display-options.xml";
+
+/// Viewport `<meta>` of documents too broken to parse as XML
+static VIEWPORT_META: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)<meta\s[^>]*name\s*=\s*["']viewport["'][^>]*>"#).unwrap());
+static CONTENT_ATTR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)content\s*=\s*["']([^"']*)["']"#).unwrap());
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Page progression direction from the spine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageDirection {
+    Ltr,
+    Rtl,
+    /// Not declared; the reader decides (usually from the language)
+    Default,
+}
+
+/// `rendition:layout` of a book or spine item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    Reflowable,
+    /// Fixed pages, rendered whole at their viewport size
+    PrePaginated,
+}
+
+/// Page size a fixed-layout document was designed for, in CSS pixels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Viewport {
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpineLayout {
+    /// Archive path of the document
+    pub href: String,
+    pub layout: Layout,
+    /// Declared size of a fixed-layout page; `None` when reflowable or
+    /// undeclared
+    pub viewport: Option<Viewport>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpubMetadata {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub language: Option<String>,
+    pub identifier: Option<String>,
+    pub publisher: Option<String>,
+    pub description: Option<String>,
+    /// Archive path of the cover image
+    pub cover_href: Option<String>,
+    pub page_direction: PageDirection,
+    /// `writing-mode` declared in the content CSS (e.g. `vertical-rl`),
+    /// a hint for books that don't declare a page direction
+    pub writing_mode: Option<String>,
+    pub series_name: Option<String>,
+    pub series_index: Option<f64>,
+    /// Book-wide layout; spine items may override it
+    pub layout: Layout,
+    /// Book-wide `rendition:viewport`, for pages that don't declare one
+    pub viewport: Option<Viewport>,
+    /// Layout of every spine document, filled only when some are fixed
+    pub spine_layouts: Vec<SpineLayout>,
+}
+
+// ============================================================================
+// Parsing
+// ============================================================================
+
+fn page_direction(epub: &EpubArchive) -> PageDirection {
+    parse_page_direction(epub.package.page_progression_direction.as_deref())
+}
+
+/// Map the spine's `page-progression-direction` attribute
+fn parse_page_direction(value: Option<&str>) -> PageDirection {
+    match value {
+        Some("rtl") => PageDirection::Rtl,
+        Some("ltr") => PageDirection::Ltr,
+        _ => PageDirection::Default,
+    }
+}
+
+/// Find the first vertical `writing-mode` (including the `-epub-` and
+/// `-webkit-` prefixed forms) in the book's stylesheets
+fn vertical_writing_mode(epub: &mut EpubArchive) -> Option<String> {
+    let stylesheets: Vec<String> = epub
+        .package
+        .manifest
+        .iter()
+        .filter(|item| item.media_type == "text/css")
+        .map(|item| item.path.clone())
+        .collect();
+
+    for path in stylesheets {
+        match epub.read_string(&path) {
+            Ok(css) => {
+                if let Some(mode) = css_vertical_writing_mode(&css) {
+                    return Some(mode);
+                }
+            }
+            Err(e) => warn!("Skipping stylesheet {}: {}", path, e),
+        }
+    }
+
+    None
+}
+
+/// The first vertical `writing-mode` value declared in a stylesheet.
+/// Prefixed properties end in `writing-mode` too, so one scan finds them.
+fn css_vertical_writing_mode(css: &str) -> Option<String> {
+    let css = css.to_ascii_lowercase();
+
+    css.match_indices("writing-mode").find_map(|(i, _)| {
+        css[i + "writing-mode".len()..]
+            .trim_start()
+            .strip_prefix(':')
+            .and_then(|rest| rest.split([';', '}']).next())
+            .map(|v| v.replace("!important", "").trim().to_string())
+            .filter(|v| v.starts_with("vertical-"))
+    })
+}
+
+/// Series from an EPUB 3 `belongs-to-collection` (preferring ones typed
+/// "series"), falling back to calibre's `calibre:series` meta tags
+fn series(epub: &EpubArchive) -> Option<(String, Option<f64>)> {
+    let package = &epub.package;
+
+    let collection = package
+        .collections
+        .iter()
+        .find(|c| c.collection_type.as_deref() == Some("series"))
+        .or_else(|| {
+            package
+                .collections
+                .iter()
+                .find(|c| c.collection_type.is_none())
+        });
+    if let Some(collection) = collection {
+        let index = collection
+            .group_position
+            .as_deref()
+            .and_then(|p| p.trim().parse().ok());
+        return Some((collection.name.clone(), index));
+    }
+
+    let meta = |name: &str| {
+        package
+            .meta
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.trim().to_string())
+    };
+    let name = meta("calibre:series").filter(|n| !n.is_empty())?;
+    let index = meta("calibre:series_index").and_then(|i| i.parse().ok());
+    Some((name, index))
+}
+
+/// Whether Apple's display options turn on fixed layout
+fn apple_fixed_layout(epub: &mut EpubArchive) -> bool {
+    if !epub.contains(APPLE_OPTIONS_PATH) {
+        return false;
+    }
+    let Ok(xml) = epub.read_string(APPLE_OPTIONS_PATH) else {
+        return false;
+    };
+    let Ok(doc) = roxmltree::Document::parse(&xml) else {
+        warn!("Ignoring malformed Apple display options");
+        return false;
+    };
+    doc.descendants().any(|node| {
+        node.tag_name().name() == "option"
+            && node.attribute("name") == Some("fixed-layout")
+            && node.text().map(str::trim) == Some("true")
+    })
+}
+
+fn book_layout(epub: &mut EpubArchive) -> Layout {
+    let declared = epub
+        .package
+        .meta
+        .iter()
+        .find(|(key, _)| key == "rendition:layout")
+        .map(|(_, value)| value.trim().to_string());
+    match declared.as_deref() {
+        Some("pre-paginated") => Layout::PrePaginated,
+        Some(_) => Layout::Reflowable,
+        None if apple_fixed_layout(epub) => Layout::PrePaginated,
+        None => Layout::Reflowable,
+    }
+}
+
+/// A spine item's layout, from its `rendition:layout-*` override
+fn item_layout(book: Layout, properties: &[String]) -> Layout {
+    properties
+        .iter()
+        .find_map(|p| match p.as_str() {
+            "rendition:layout-pre-paginated" => Some(Layout::PrePaginated),
+            "rendition:layout-reflowable" => Some(Layout::Reflowable),
+            _ => None,
+        })
+        .unwrap_or(book)
+}
+
+fn css_pixels(value: &str) -> Option<f64> {
+    let value: f64 = value.trim().trim_end_matches("px").trim().parse().ok()?;
+    (value.is_finite() && value > 0.0).then_some(value)
+}
+
+/// Parse `width=1200, height=1600` (commas or semicolons between pairs)
+fn parse_viewport(content: &str) -> Option<Viewport> {
+    let mut width = None;
+    let mut height = None;
+    for pair in content.split([',', ';']) {
+        let Some((key, value)) = pair.split_once('=') else {
+            continue;
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            "width" => width = css_pixels(value),
+            "height" => height = css_pixels(value),
+            _ => {}
+        }
+    }
+    Some(Viewport {
+        width: width?,
+        height: height?,
+    })
+}
+
+/// Viewport a content document declares: a viewport `<meta>` for XHTML,
+/// or the root `viewBox` (then `width`/`height`) for SVG
+fn document_viewport(markup: &str) -> Option<Viewport> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let Ok(doc) = roxmltree::Document::parse_with_options(markup, options) else {
+        let meta = VIEWPORT_META.find(markup)?;
+        let content = CONTENT_ATTR.captures(meta.as_str())?.get(1)?;
+        return parse_viewport(content.as_str());
+    };
+
+    let root = doc.root_element();
+    if root.tag_name().name() == "svg" {
+        let from_view_box = root.attribute("viewBox").and_then(|view_box| {
+            let values: Vec<&str> = view_box
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|v| !v.is_empty())
+                .collect();
+            match values[..] {
+                [_, _, width, height] => Some(Viewport {
+                    width: css_pixels(width)?,
+                    height: css_pixels(height)?,
+                }),
+                _ => None,
+            }
+        });
+        return from_view_box.or_else(|| {
+            Some(Viewport {
+                width: css_pixels(root.attribute("width")?)?,
+                height: css_pixels(root.attribute("height")?)?,
+            })
+        });
+    }
+
+    doc.descendants()
+        .find(|n| {
+            n.tag_name().name() == "meta"
+                && n.attribute("name")
+                    .is_some_and(|name| name.eq_ignore_ascii_case("viewport"))
+        })
+        .and_then(|meta| parse_viewport(meta.attribute("content")?))
+}
+
+/// Layout and viewport of each spine document. Empty for reflowable books,
+/// so their content documents aren't read.
+fn spine_layouts(
+    epub: &mut EpubArchive,
+    book: Layout,
+    fallback: Option<Viewport>,
+) -> Vec<SpineLayout> {
+    let items: Vec<(String, Layout)> = epub
+        .package
+        .spine
+        .iter()
+        .filter_map(|spine| {
+            let item = epub.manifest_item(&spine.idref)?;
+            Some((item.path.clone(), item_layout(book, &spine.properties)))
+        })
+        .collect();
+    if !items
+        .iter()
+        .any(|(_, layout)| *layout == Layout::PrePaginated)
+    {
+        return Vec::new();
+    }
+
+    items
+        .into_iter()
+        .map(|(href, layout)| {
+            let viewport = match layout {
+                Layout::Reflowable => None,
+                Layout::PrePaginated => match epub.read_string(&href) {
+                    Ok(markup) => document_viewport(&markup).or(fallback),
+                    Err(e) => {
+                        warn!("Skipping viewport of {}: {}", href, e);
+                        fallback
+                    }
+                },
+            };
+            SpineLayout {
+                href,
+                layout,
+                viewport,
+            }
+        })
+        .collect()
+}
+
+/// Read metadata from an opened EPUB
+pub fn read_metadata(epub: &mut EpubArchive) -> EpubMetadata {
+    let page_direction = page_direction(epub);
+    let writing_mode = vertical_writing_mode(epub);
+    let (series_name, series_index) = series(epub).unzip();
+    let layout = book_layout(epub);
+    let viewport = epub
+        .package
+        .meta
+        .iter()
+        .find(|(key, _)| key == "rendition:viewport")
+        .and_then(|(_, value)| parse_viewport(value));
+    let spine_layouts = spine_layouts(epub, layout, viewport);
+    let package = &epub.package;
+
+    EpubMetadata {
+        title: package.title.clone(),
+        authors: package.creators.clone(),
+        language: package.language.clone(),
+        identifier: package.unique_identifier.clone(),
+        publisher: package.publisher.clone(),
+        description: package.description.clone(),
+        cover_href: epub.cover_path(),
+        page_direction,
+        writing_mode,
+        series_name,
+        series_index: series_index.flatten(),
+        layout,
+        viewport,
+        spine_layouts,
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Read an EPUB's metadata, including its page progression direction and
+/// fixed-layout pages
+#[tauri::command]
+pub async fn get_epub_metadata(path: String) -> Result<EpubMetadata, String> {
+    info!("Reading EPUB metadata: {}", path);
+
+    let mut epub = EpubArchive::open(&path).map_err(|e| e.to_string())?;
+    Ok(read_metadata(&mut epub))
+}
+
+/// Page progression direction only, without scanning stylesheets
+#[tauri::command]
+pub async fn get_page_direction(path: String) -> Result<PageDirection, String> {
+    let epub = EpubArchive::open(&path).map_err(|e| e.to_string())?;
+    Ok(page_direction(&epub))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewport(width: f64, height: f64) -> Option<Viewport> {
+        Some(Viewport { width, height })
+    }
+
+    #[test]
+    fn spine_overrides_win_over_the_book_layout() {
+        let fixed = ["rendition:layout-pre-paginated".to_string()];
+        let reflow = [
+            "page-spread-left".to_string(),
+            "rendition:layout-reflowable".to_string(),
+        ];
+
+        assert_eq!(
+            item_layout(Layout::Reflowable, &fixed),
+            Layout::PrePaginated
+        );
+        assert_eq!(
+            item_layout(Layout::PrePaginated, &reflow),
+            Layout::Reflowable
+        );
+        assert_eq!(item_layout(Layout::PrePaginated, &[]), Layout::PrePaginated);
+    }
+
+    #[test]
+    fn maps_page_progression_direction() {
+        assert_eq!(parse_page_direction(Some("rtl")), PageDirection::Rtl);
+        assert_eq!(parse_page_direction(Some("ltr")), PageDirection::Ltr);
+        assert_eq!(
+            parse_page_direction(Some("default")),
+            PageDirection::Default
+        );
+        assert_eq!(parse_page_direction(None), PageDirection::Default);
+    }
+
+    #[test]
+    fn finds_vertical_writing_modes() {
+        assert_eq!(
+            css_vertical_writing_mode("body { writing-mode: vertical-rl; }").as_deref(),
+            Some("vertical-rl")
+        );
+        assert_eq!(
+            css_vertical_writing_mode("html { -epub-writing-mode: vertical-rl }").as_deref(),
+            Some("vertical-rl")
+        );
+        assert_eq!(
+            css_vertical_writing_mode("p{-webkit-writing-mode:Vertical-LR !important;}").as_deref(),
+            Some("vertical-lr")
+        );
+        assert_eq!(
+            css_vertical_writing_mode(
+                "h1 { writing-mode: horizontal-tb } p { writing-mode: vertical-rl !important }"
+            )
+            .as_deref(),
+            Some("vertical-rl")
+        );
+        assert_eq!(
+            css_vertical_writing_mode("body { writing-mode: horizontal-tb; }"),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_viewport_declarations() {
+        assert_eq!(
+            parse_viewport("width=1200, height=1600"),
+            viewport(1200.0, 1600.0)
+        );
+        assert_eq!(
+            parse_viewport("height = 768px; width = 1024px"),
+            viewport(1024.0, 768.0)
+        );
+        assert_eq!(parse_viewport("width=device-width, height=600"), None);
+        assert_eq!(parse_viewport("width=800"), None);
+    }
+
+    #[test]
+    fn reads_document_viewports() {
+        let xhtml = r#"<html xmlns="http://www.w3.org/1999/xhtml"><head>
+            <meta name="viewport" content="width=600, height=800"/></head><body/></html>"#;
+        assert_eq!(document_viewport(xhtml), viewport(600.0, 800.0));
+
+        let broken = r#"<html><head><META NAME="viewport" CONTENT="width=300,height=400"><body>"#;
+        assert_eq!(document_viewport(broken), viewport(300.0, 400.0));
+
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 1400 2100"/>"#;
+        assert_eq!(document_viewport(svg), viewport(1400.0, 2100.0));
+
+        let sized = r#"<svg xmlns="http://www.w3.org/2000/svg" width="500" height="700"/>"#;
+        assert_eq!(document_viewport(sized), viewport(500.0, 700.0));
+
+        assert_eq!(document_viewport("<html><body/></html>"), None);
+    }
+}
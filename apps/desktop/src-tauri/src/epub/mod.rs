@@ -0,0 +1,447 @@
+// Read Master Desktop - EPUB
+//
+// EPUB container access and OPF package parsing.
+
+pub mod cfi;
+pub mod fonts;
+pub mod limits;
+pub mod metadata;
+pub mod paginate;
+pub mod repair;
+pub mod resources;
+pub mod structure;
+pub mod text;
+pub mod toc;
+pub mod typography;
+pub mod validate;
+
+use std::collections::HashMap;
+use std::fs::File;
+
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+use crate::error::AppError;
+
+/// Location of the OCF container document
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+
+/// Location of the OCF encryption document
+const ENCRYPTION_PATH: &str = "META-INF/encryption.xml";
+
+/// Font obfuscation algorithm identifiers used in encryption.xml
+const IDPF_OBFUSCATION: &str = "http://www.idpf.org/2008/embedding";
+const ADOBE_OBFUSCATION: &str = "http://ns.adobe.com/pdf/enc#RC";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub struct ManifestItem {
+    pub id: String,
+    /// Archive path, resolved against the OPF location
+    pub path: String,
+    pub media_type: String,
+    pub properties: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpineItem {
+    pub idref: String,
+    pub linear: bool,
+    pub properties: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Package {
+    /// Value of the `dc:identifier` referenced by `unique-identifier`
+    pub unique_identifier: Option<String>,
+    pub title: Option<String>,
+    pub creators: Vec<String>,
+    pub language: Option<String>,
+    pub publisher: Option<String>,
+    pub description: Option<String>,
+    pub identifiers: Vec<String>,
+    /// `<meta>` elements as (property or name, value) pairs
+    pub meta: Vec<(String, String)>,
+    pub manifest: Vec<ManifestItem>,
+    pub spine: Vec<SpineItem>,
+    pub page_progression_direction: Option<String>,
+    pub toc_id: Option<String>,
+    /// EPUB 3 `belongs-to-collection` entries
+    pub collections: Vec<BelongsToCollection>,
+}
+
+/// A `belongs-to-collection` meta with its refinements applied
+#[derive(Debug, Clone, Default)]
+pub struct BelongsToCollection {
+    pub name: String,
+    /// `collection-type` refinement, e.g. "series" or "set"
+    pub collection_type: Option<String>,
+    /// `group-position` refinement, e.g. "2" or "2.5"
+    pub group_position: Option<String>,
+}
+
+/// Font obfuscation scheme declared for a resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Obfuscation {
+    Idpf,
+    Adobe,
+}
+
+/// An opened EPUB file with its parsed package document
+pub struct EpubArchive {
+    zip: ZipArchive<File>,
+    /// File the archive was opened from
+    pub source: String,
+    pub opf_path: String,
+    pub package: Package,
+}
+
+// ============================================================================
+// Paths
+// ============================================================================
+
+/// Directory part of an archive path, including the trailing slash
+pub fn parent_dir(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(i) => &path[..=i],
+        None => "",
+    }
+}
+
+/// Resolve `href` relative to the archive directory `base`, dropping any
+/// fragment and collapsing `.` / `..` segments
+pub fn resolve_href(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or("");
+    let href = percent_decode(href);
+
+    let joined = if href.starts_with('/') {
+        href.trim_start_matches('/').to_string()
+    } else {
+        format!("{}{}", base, href)
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in joined.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+    }
+    segments.join("/")
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn split_properties(value: Option<&str>) -> Vec<String> {
+    value
+        .unwrap_or("")
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+// ============================================================================
+// Archive
+// ============================================================================
+
+impl EpubArchive {
+    /// Open an EPUB and parse its package document. Archives tripping the
+    /// limits in `limits` fail with `AppError::SuspiciousArchive`.
+    pub fn open(path: &str) -> Result<Self, AppError> {
+        let file = File::open(path)?;
+        let mut zip = ZipArchive::new(file)?;
+        limits::check_archive(&mut zip, &limits::current())?;
+
+        let container = read_zip_string(&mut zip, CONTAINER_PATH)?;
+        let opf_path = parse_container(&container).map_err(corrupt)?;
+        let opf = read_zip_string(&mut zip, &opf_path)?;
+        let package = parse_package(&opf, parent_dir(&opf_path)).map_err(corrupt)?;
+
+        Ok(Self {
+            zip,
+            source: path.to_string(),
+            opf_path,
+            package,
+        })
+    }
+
+    /// Directory containing the OPF, which manifest hrefs are relative to
+    pub fn opf_dir(&self) -> &str {
+        parent_dir(&self.opf_path)
+    }
+
+    /// Whether the archive contains an entry at `path`
+    pub fn contains(&self, path: &str) -> bool {
+        self.zip.index_for_name(path).is_some()
+    }
+
+    /// All entry names in the archive
+    pub fn entry_names(&self) -> Vec<String> {
+        self.zip.file_names().map(str::to_string).collect()
+    }
+
+    /// Read the raw bytes of an archive entry
+    pub fn read_bytes(&mut self, path: &str) -> Result<Vec<u8>, String> {
+        read_zip_bytes(&mut self.zip, path).map_err(|e| e.to_string())
+    }
+
+    /// Read an archive entry as UTF-8 text
+    pub fn read_string(&mut self, path: &str) -> Result<String, String> {
+        read_zip_string(&mut self.zip, path).map_err(|e| e.to_string())
+    }
+
+    /// Look up a manifest item by id
+    pub fn manifest_item(&self, id: &str) -> Option<&ManifestItem> {
+        self.package.manifest.iter().find(|item| item.id == id)
+    }
+
+    /// Resources marked as obfuscated in encryption.xml, keyed by archive
+    /// path. Resources encrypted with other algorithms (DRM) are not included.
+    pub fn obfuscated_resources(&mut self) -> Result<HashMap<String, Obfuscation>, String> {
+        if !self.contains(ENCRYPTION_PATH) {
+            return Ok(HashMap::new());
+        }
+
+        let xml = self.read_string(ENCRYPTION_PATH)?;
+        parse_encryption(&xml)
+    }
+
+    /// Archive path of the cover image, from the EPUB 3 `cover-image`
+    /// property or the EPUB 2 `<meta name="cover">` convention
+    pub fn cover_path(&self) -> Option<String> {
+        let manifest = &self.package.manifest;
+
+        manifest
+            .iter()
+            .find(|item| item.properties.iter().any(|p| p == "cover-image"))
+            .or_else(|| {
+                self.package
+                    .meta
+                    .iter()
+                    .find(|(key, _)| key == "cover")
+                    .and_then(|(_, id)| self.manifest_item(id))
+            })
+            .filter(|item| item.media_type.starts_with("image/"))
+            .map(|item| item.path.clone())
+    }
+
+    /// Manifest items of the spine, in reading order
+    pub fn spine_items(&self) -> Vec<&ManifestItem> {
+        self.package
+            .spine
+            .iter()
+            .filter_map(|s| self.manifest_item(&s.idref))
+            .collect()
+    }
+}
+
+fn corrupt(details: String) -> AppError {
+    AppError::CorruptFile {
+        message: "EPUB is damaged or unsupported".to_string(),
+        details: Some(details),
+    }
+}
+
+fn read_zip_bytes(zip: &mut ZipArchive<File>, path: &str) -> Result<Vec<u8>, AppError> {
+    let mut entry = zip.by_name(path).map_err(|_| AppError::NotFound {
+        message: format!("Entry not found in EPUB: {}", path),
+        details: None,
+    })?;
+
+    let size = entry.size();
+    limits::read_limited(&mut entry, path, size, &limits::current())
+}
+
+fn read_zip_string(zip: &mut ZipArchive<File>, path: &str) -> Result<String, AppError> {
+    let bytes = read_zip_bytes(zip, path)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// ============================================================================
+// Package Parsing
+// ============================================================================
+
+/// Find the first rootfile's full-path in container.xml
+fn parse_container(xml: &str) -> Result<String, String> {
+    let doc =
+        roxmltree::Document::parse(xml).map_err(|e| format!("Invalid container.xml: {}", e))?;
+
+    doc.descendants()
+        .find(|n| n.tag_name().name() == "rootfile")
+        .and_then(|n| n.attribute("full-path"))
+        .map(str::to_string)
+        .ok_or_else(|| "container.xml has no rootfile".to_string())
+}
+
+fn parse_package(xml: &str, opf_dir: &str) -> Result<Package, String> {
+    let doc =
+        roxmltree::Document::parse(xml).map_err(|e| format!("Invalid OPF document: {}", e))?;
+    let root = doc.root_element();
+
+    let unique_id_ref = root.attribute("unique-identifier");
+    let mut package = Package::default();
+
+    // (meta id, collection) and (target id, property, value), matched up
+    // once every meta has been seen
+    let mut collection_ids = Vec::new();
+    let mut refinements = Vec::new();
+
+    for node in root.descendants().filter(|n| n.is_element()) {
+        let text = || {
+            node.text()
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+        };
+
+        match node.tag_name().name() {
+            "title" if package.title.is_none() => package.title = text(),
+            "creator" => package.creators.extend(text()),
+            "language" if package.language.is_none() => package.language = text(),
+            "publisher" if package.publisher.is_none() => package.publisher = text(),
+            "description" if package.description.is_none() => package.description = text(),
+            "identifier" => {
+                if let Some(value) = text() {
+                    if unique_id_ref.is_some() && node.attribute("id") == unique_id_ref {
+                        package.unique_identifier = Some(value.clone());
+                    }
+                    package.identifiers.push(value);
+                }
+            }
+            "meta" => {
+                // EPUB 3 uses property + text, EPUB 2 uses name + content
+                let key = node.attribute("property").or(node.attribute("name"));
+                let value = node.attribute("content").map(str::to_string).or_else(text);
+                let (Some(key), Some(value)) = (key, value) else {
+                    continue;
+                };
+
+                if let Some(target) = node.attribute("refines") {
+                    refinements.push((
+                        target.trim_start_matches('#').to_string(),
+                        key.to_string(),
+                        value,
+                    ));
+                    continue;
+                }
+
+                if key == "belongs-to-collection" {
+                    collection_ids.push((
+                        node.attribute("id").map(str::to_string),
+                        BelongsToCollection {
+                            name: value.clone(),
+                            ..Default::default()
+                        },
+                    ));
+                }
+                package.meta.push((key.to_string(), value));
+            }
+            "item" => {
+                if let (Some(id), Some(href)) = (node.attribute("id"), node.attribute("href")) {
+                    package.manifest.push(ManifestItem {
+                        id: id.to_string(),
+                        path: resolve_href(opf_dir, href),
+                        media_type: node.attribute("media-type").unwrap_or("").to_string(),
+                        properties: split_properties(node.attribute("properties")),
+                    });
+                }
+            }
+            "spine" => {
+                package.page_progression_direction = node
+                    .attribute("page-progression-direction")
+                    .map(str::to_string);
+                package.toc_id = node.attribute("toc").map(str::to_string);
+            }
+            "itemref" => {
+                if let Some(idref) = node.attribute("idref") {
+                    package.spine.push(SpineItem {
+                        idref: idref.to_string(),
+                        linear: node.attribute("linear") != Some("no"),
+                        properties: split_properties(node.attribute("properties")),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if package.unique_identifier.is_none() {
+        package.unique_identifier = package.identifiers.first().cloned();
+    }
+
+    for (id, mut collection) in collection_ids {
+        for (target, property, value) in &refinements {
+            if id.as_deref() != Some(target.as_str()) {
+                continue;
+            }
+            match property.as_str() {
+                "collection-type" => collection.collection_type = Some(value.clone()),
+                "group-position" => collection.group_position = Some(value.clone()),
+                _ => {}
+            }
+        }
+        package.collections.push(collection);
+    }
+
+    Ok(package)
+}
+
+/// Map encrypted resource paths to their obfuscation scheme
+fn parse_encryption(xml: &str) -> Result<HashMap<String, Obfuscation>, String> {
+    let doc =
+        roxmltree::Document::parse(xml).map_err(|e| format!("Invalid encryption.xml: {}", e))?;
+
+    let mut resources = HashMap::new();
+
+    for data in doc
+        .descendants()
+        .filter(|n| n.tag_name().name() == "EncryptedData")
+    {
+        let algorithm = data
+            .descendants()
+            .find(|n| n.tag_name().name() == "EncryptionMethod")
+            .and_then(|n| n.attribute("Algorithm"));
+        let uri = data
+            .descendants()
+            .find(|n| n.tag_name().name() == "CipherReference")
+            .and_then(|n| n.attribute("URI"));
+
+        let scheme = match algorithm {
+            Some(IDPF_OBFUSCATION) => Obfuscation::Idpf,
+            Some(ADOBE_OBFUSCATION) => Obfuscation::Adobe,
+            _ => continue,
+        };
+
+        // CipherReference URIs are relative to the container root
+        if let Some(uri) = uri {
+            resources.insert(resolve_href("", uri), scheme);
+        }
+    }
+
+    Ok(resources)
+}
@@ -0,0 +1,588 @@
+// Read Master Desktop - EPUB Pagination
+//
+// Splits a long spine document into page-sized slices, for books that put
+// a whole novel in one XHTML file and make the reader lay it all out at
+// once.
+//
+// Pages break before a block element where one falls in the second half
+// of the page, else after a sentence, else between words; never inside a
+// word or a tag. Each slice has CFI locators for where it starts and ends,
+// the word indexes bookmarks use, and its markup: the body content in
+// range, with the elements it starts inside reopened and the ones it ends
+// inside closed, so every slice is well-formed on its own. The chapter's
+// head (styles and all) is left to the reader.
+
+use log::info;
+use roxmltree::Node;
+use serde::{Deserialize, Serialize};
+
+use super::cfi::{self, Cfi};
+use super::text::SKIPPED_ELEMENTS;
+use super::EpubArchive;
+
+/// Elements a page may start at
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "li",
+    "ul",
+    "ol",
+    "dl",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "table",
+    "figure",
+    "section",
+    "article",
+    "aside",
+    "hr",
+];
+
+/// Elements written without a closing tag
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Characters that end a sentence, and those that may follow the mark
+const SENTENCE_ENDS: &[char] = &['.', '!', '?', '…', '。', '！', '？'];
+const CLOSERS: &[char] = &['"', '\'', '”', '’', '»', ')', ']'];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageSlice {
+    pub index: usize,
+    /// CFI of the first word or block of the page
+    pub start: String,
+    /// CFI where the next page starts, or the end of the chapter
+    pub end: String,
+    /// Index of the first word, counted as bookmarks count them
+    pub start_word: usize,
+    /// Index of the first word after the page
+    pub end_word: usize,
+    /// Body content of the page, well-formed
+    pub html: String,
+}
+
+/// Kinds of page break, worst first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Break {
+    Word,
+    Sentence,
+    Block,
+}
+
+/// A point in the body: before the element at `node`, or `offset`
+/// characters into the text node at `node`. Nodes are numbered in
+/// document order from the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    node: usize,
+    offset: usize,
+}
+
+/// Somewhere a page could start
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    at: Position,
+    kind: Break,
+    /// Characters before it, whitespace runs counted once
+    chars: usize,
+    /// Words before it
+    words: usize,
+}
+
+/// The body's nodes in document order, with where each one's subtree ends
+struct Tree<'a, 'input> {
+    nodes: Vec<Node<'a, 'input>>,
+    last: Vec<usize>,
+}
+
+impl<'a, 'input> Tree<'a, 'input> {
+    fn new(body: Node<'a, 'input>) -> Self {
+        let mut tree = Self {
+            nodes: Vec::new(),
+            last: Vec::new(),
+        };
+        tree.number(body);
+        tree
+    }
+
+    fn number(&mut self, node: Node<'a, 'input>) {
+        let index = self.nodes.len();
+        self.nodes.push(node);
+        self.last.push(index);
+        for child in node.children() {
+            self.number(child);
+        }
+        self.last[index] = self.nodes.len() - 1;
+    }
+
+    fn name(&self, index: usize) -> String {
+        self.nodes[index].tag_name().name().to_ascii_lowercase()
+    }
+}
+
+// ============================================================================
+// Breaks
+// ============================================================================
+
+/// Every place a page could start, in document order, plus the totals
+fn candidates(tree: &Tree) -> (Vec<Candidate>, usize, usize) {
+    let mut found = Vec::new();
+    let mut chars = 0;
+    let mut words = 0;
+    let mut in_word = false;
+    let mut last_mark = ' ';
+    // Ends of the blocks the walk is inside; text after one ends is a new word
+    let mut open_blocks: Vec<usize> = Vec::new();
+    let mut skip_until = 0;
+
+    for index in 1..tree.nodes.len() {
+        if index <= skip_until {
+            continue;
+        }
+        let mut closed = false;
+        while open_blocks.last().is_some_and(|&end| end < index) {
+            open_blocks.pop();
+            closed = true;
+        }
+        if closed && in_word {
+            chars += 1;
+            in_word = false;
+        }
+
+        let node = tree.nodes[index];
+        if node.is_element() {
+            let name = tree.name(index);
+            let block = BLOCK_ELEMENTS.contains(&name.as_str());
+            if SKIPPED_ELEMENTS.contains(&name.as_str()) {
+                skip_until = tree.last[index];
+            } else if block || name == "br" {
+                if block && chars > 0 {
+                    found.push(Candidate {
+                        at: Position {
+                            node: index,
+                            offset: 0,
+                        },
+                        kind: Break::Block,
+                        chars,
+                        words,
+                    });
+                }
+                if in_word {
+                    chars += 1;
+                }
+                in_word = false;
+                if block {
+                    open_blocks.push(tree.last[index]);
+                }
+            }
+            continue;
+        }
+        if !node.is_text() {
+            continue;
+        }
+
+        let mut node_in_word = false;
+        for (offset, c) in node.text().unwrap_or_default().chars().enumerate() {
+            if c.is_whitespace() {
+                if in_word {
+                    chars += 1;
+                }
+                in_word = false;
+                node_in_word = false;
+                continue;
+            }
+            if !node_in_word {
+                // A word split across inline elements is one word to the
+                // reader but one per text node to bookmarks
+                if !in_word && chars > 0 {
+                    let sentence = SENTENCE_ENDS.contains(&last_mark);
+                    found.push(Candidate {
+                        at: Position {
+                            node: index,
+                            offset,
+                        },
+                        kind: if sentence {
+                            Break::Sentence
+                        } else {
+                            Break::Word
+                        },
+                        chars,
+                        words,
+                    });
+                }
+                words += 1;
+            }
+            if !CLOSERS.contains(&c) {
+                last_mark = c;
+            }
+            in_word = true;
+            node_in_word = true;
+            chars += 1;
+        }
+    }
+    (found, chars, words)
+}
+
+/// Candidate the page starting `start_chars` in should end at: the best
+/// break in the second half of the page, else the last break on it, else
+/// (for a word longer than a page) the first break after it
+fn next_break(candidates: &[Candidate], start_chars: usize, per_page: usize) -> Option<usize> {
+    let after = candidates.partition_point(|c| c.chars <= start_chars);
+    let limit = candidates.partition_point(|c| c.chars <= start_chars + per_page);
+    let half = start_chars + per_page.div_ceil(2);
+
+    let page = &candidates[after..limit];
+    let best = page
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| c.chars >= half)
+        .max_by_key(|(i, c)| (c.kind, *i))
+        .map(|(i, _)| after + i);
+    best.or_else(|| (limit > after).then(|| limit - 1))
+        .or_else(|| (after < candidates.len()).then_some(after))
+}
+
+// ============================================================================
+// Slicing
+// ============================================================================
+
+fn escape(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Name of an element or attribute with its namespace prefix, if any
+fn qualified(node: &Node, namespace: Option<&str>, name: &str) -> String {
+    match namespace.and_then(|ns| node.lookup_prefix(ns)) {
+        Some(prefix) if !prefix.is_empty() => format!("{}:{}", prefix, name),
+        _ => name.to_string(),
+    }
+}
+
+/// Write the part of the subtree at `index` between `start` and `end`
+fn write_range(tree: &Tree, index: usize, start: Position, end: Position, out: &mut String) {
+    let node = tree.nodes[index];
+    if node.is_text() {
+        let text = node.text().unwrap_or_default();
+        let from = match index {
+            i if i == start.node => start.offset,
+            i if i > start.node => 0,
+            _ => return,
+        };
+        let to = match index {
+            i if i == end.node => end.offset,
+            i if i < end.node => usize::MAX,
+            _ => return,
+        };
+        let part: String = text.chars().take(to).skip(from).collect();
+        escape(&part, out);
+        return;
+    }
+    if !node.is_element() || tree.last[index] < start.node || index >= end.node {
+        return;
+    }
+    let name = tree.name(index);
+    if name == "script" {
+        return;
+    }
+
+    let tag = qualified(&node, node.tag_name().namespace(), node.tag_name().name());
+    out.push('<');
+    out.push_str(&tag);
+    for attr in node.attributes() {
+        out.push(' ');
+        out.push_str(&qualified(&node, attr.namespace(), attr.name()));
+        out.push_str("=\"");
+        escape(attr.value(), out);
+        out.push('"');
+    }
+    if VOID_ELEMENTS.contains(&name.as_str()) {
+        out.push_str("/>");
+        return;
+    }
+    out.push('>');
+    write_children(tree, index, start, end, out);
+    out.push_str("</");
+    out.push_str(&tag);
+    out.push('>');
+}
+
+fn write_children(tree: &Tree, index: usize, start: Position, end: Position, out: &mut String) {
+    let mut child = index + 1;
+    while child <= tree.last[index] && child <= end.node {
+        write_range(tree, child, start, end, out);
+        child = tree.last[child] + 1;
+    }
+}
+
+/// CFI of a position
+fn locator(tree: &Tree, at: Position, spine_index: usize) -> String {
+    let steps = cfi::steps_to(&tree.nodes[at.node]).unwrap_or_default();
+    Cfi {
+        spine_index,
+        steps,
+        offset: at.offset,
+    }
+    .to_string()
+}
+
+/// Split the XHTML document at `spine_index` into pages of about
+/// `per_page` characters
+pub fn paginate(
+    markup: &str,
+    spine_index: usize,
+    per_page: usize,
+) -> Result<Vec<PageSlice>, String> {
+    let doc = cfi::parse(markup).ok_or_else(|| "Chapter isn't well-formed XHTML".to_string())?;
+    let body = doc
+        .descendants()
+        .find(|n| n.tag_name().name() == "body")
+        .unwrap_or(doc.root_element());
+    let tree = Tree::new(body);
+    let (candidates, total_chars, total_words) = candidates(&tree);
+
+    // The last page runs to the end of the last text, or of the body
+    let end_of_text = (0..tree.nodes.len())
+        .rev()
+        .find(|&i| {
+            let node = &tree.nodes[i];
+            cfi::is_reading_text(node) && node.text().is_some_and(|t| !t.trim().is_empty())
+        })
+        .map(|i| Position {
+            node: i,
+            offset: tree.nodes[i].text().unwrap_or_default().chars().count(),
+        })
+        .unwrap_or(Position { node: 0, offset: 0 });
+    let past_end = Position {
+        node: tree.nodes.len(),
+        offset: 0,
+    };
+
+    let mut starts = vec![(Position { node: 0, offset: 0 }, 0)];
+    let mut start_chars = 0;
+    while total_chars > start_chars + per_page {
+        let Some(next) = next_break(&candidates, start_chars, per_page) else {
+            break;
+        };
+        let next = candidates[next];
+        starts.push((next.at, next.words));
+        start_chars = next.chars;
+    }
+
+    let mut pages = Vec::with_capacity(starts.len());
+    for (index, &(start, start_word)) in starts.iter().enumerate() {
+        let (end, end_locator, end_word) = match starts.get(index + 1) {
+            Some(&(next, words)) => (next, locator(&tree, next, spine_index), words),
+            None => (
+                past_end,
+                locator(&tree, end_of_text, spine_index),
+                total_words,
+            ),
+        };
+        let mut html = String::new();
+        write_children(&tree, 0, start, end, &mut html);
+        pages.push(PageSlice {
+            index,
+            start: locator(&tree, start, spine_index),
+            end: end_locator,
+            start_word,
+            end_word,
+            html,
+        });
+    }
+    Ok(pages)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Split a chapter (by spine position) into pages of about
+/// `chars_per_page` characters
+#[tauri::command]
+pub async fn paginate_chapter(
+    path: String,
+    chapter_index: usize,
+    chars_per_page: usize,
+) -> Result<Vec<PageSlice>, String> {
+    if chars_per_page == 0 {
+        return Err("Pages must hold at least one character".to_string());
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut epub = EpubArchive::open(&path).map_err(|e| e.to_string())?;
+        let href = epub
+            .spine_paths()
+            .into_iter()
+            .nth(chapter_index)
+            .ok_or_else(|| format!("Chapter {} out of range", chapter_index))?;
+        let markup = epub.read_string(&href)?;
+        let pages = paginate(&markup, chapter_index, chars_per_page)
+            .map_err(|e| format!("Failed to paginate {}: {}", href, e))?;
+        info!(
+            "Split chapter {} of {} into {} page(s)",
+            chapter_index,
+            path,
+            pages.len()
+        );
+        Ok(pages)
+    })
+    .await
+    .map_err(|e| format!("Pagination task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(body: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <html xmlns=\"http://www.w3.org/1999/xhtml\" \
+             xmlns:epub=\"http://www.idpf.org/2007/ops\">\n\
+             <head><title>T</title><style>p {{ margin: 0 }}</style></head>\n\
+             <body>\n{}\n</body>\n</html>",
+            body
+        )
+    }
+
+    /// Text of a slice, tags dropped without adding spaces
+    fn words(html: &str) -> Vec<String> {
+        let mut text = String::new();
+        let mut in_tag = false;
+        for c in html.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' if in_tag => in_tag = false,
+                _ if !in_tag => text.push(c),
+                _ => {}
+            }
+        }
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn document_words(markup: &str) -> Vec<String> {
+        let (text, _) = super::super::text::document_text(markup);
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn check_slices(markup: &str, pages: &[PageSlice]) {
+        let all: Vec<String> = pages.iter().flat_map(|p| words(&p.html)).collect();
+        assert_eq!(all, document_words(markup), "slices cover the text once");
+
+        for (i, page) in pages.iter().enumerate() {
+            let wrapped = format!("<body>{}</body>", page.html);
+            assert!(
+                roxmltree::Document::parse(&wrapped).is_ok(),
+                "page {} is well-formed: {}",
+                i,
+                page.html
+            );
+            let start = Cfi::parse(&page.start).unwrap();
+            assert_eq!(start.spine_index, 2);
+            if i > 0 {
+                assert_eq!(cfi::word_index(markup, &start), Some(page.start_word));
+                assert_eq!(pages[i - 1].end, page.start);
+                assert_eq!(pages[i - 1].end_word, page.start_word);
+            }
+        }
+    }
+
+    #[test]
+    fn breaks_before_blocks() {
+        let paragraphs: Vec<String> = (0..12)
+            .map(|i| {
+                format!(
+                    "<p id=\"p{}\">Paragraph {} has a few <em>short</em> words.</p>",
+                    i, i
+                )
+            })
+            .collect();
+        let markup = chapter(&paragraphs.join("\n"));
+        let pages = paginate(&markup, 2, 120).unwrap();
+
+        assert!(pages.len() > 3);
+        check_slices(&markup, &pages);
+        for page in &pages[1..] {
+            assert!(page.html.starts_with("<p id="), "{}", page.html);
+            let steps = Cfi::parse(&page.start).unwrap().steps;
+            assert_eq!(steps.last().unwrap() % 2, 0, "starts at an element");
+        }
+    }
+
+    #[test]
+    fn splits_long_paragraphs_at_sentences() {
+        let sentences: Vec<String> = (0..40)
+            .map(|i| format!("Sentence number {} goes on for a while.", i))
+            .collect();
+        let markup = chapter(&format!(
+            "<div class=\"wrap\"><p>{}</p></div>",
+            sentences.join(" ")
+        ));
+        let pages = paginate(&markup, 2, 200).unwrap();
+
+        assert!(pages.len() > 5);
+        check_slices(&markup, &pages);
+        for page in &pages {
+            // Every page reopens and closes the elements it's inside
+            let html = page.html.trim();
+            assert!(html.starts_with("<div class=\"wrap\"><p>"), "{}", html);
+            assert!(html.ends_with("</p></div>"), "{}", html);
+            assert!(words(&page.html).last().unwrap().ends_with('.'));
+        }
+    }
+
+    #[test]
+    fn never_splits_words() {
+        // No sentences or blocks, and words split across inline elements
+        let text: Vec<String> = (0..200).map(|i| format!("w{}<b>x</b>y", i)).collect();
+        let markup = chapter(&format!("<p>{}</p>", text.join(" ")));
+        let pages = paginate(&markup, 2, 50).unwrap();
+
+        assert!(pages.len() > 10);
+        check_slices(&markup, &pages);
+        for page in &pages {
+            assert!(words(&page.html).iter().all(|w| w.ends_with("xy")));
+        }
+
+        // A word longer than a page gets a page of its own
+        let markup = chapter("<p>short supercalifragilisticexpialidocious end</p>");
+        let pages = paginate(&markup, 2, 8).unwrap();
+        check_slices(&markup, &pages);
+        assert!(pages
+            .iter()
+            .any(|p| words(&p.html) == ["supercalifragilisticexpialidocious"]));
+    }
+
+    #[test]
+    fn short_chapters_are_one_page() {
+        let markup = chapter("<h1 epub:type=\"title\">One</h1><p>Short.</p><script>x()</script>");
+        let pages = paginate(&markup, 2, 1000).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].start_word, 0);
+        assert_eq!(pages[0].end_word, 2);
+        assert!(pages[0].html.contains("<h1 epub:type=\"title\">One</h1>"));
+        assert!(!pages[0].html.contains("script"));
+        assert_eq!(pages[0].end, "epubcfi(/6/6!/4/4/1:6)");
+    }
+}
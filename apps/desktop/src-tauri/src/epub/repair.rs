@@ -0,0 +1,550 @@
+// Read Master Desktop - EPUB Repair
+//
+// Recovers what it can from a damaged EPUB, typically a download cut short
+// so the central directory at the end of the zip is missing or garbled.
+// The file is scanned for local file headers instead, each entry is
+// decompressed and checked against its CRC, and the survivors are written
+// to a fresh archive with `mimetype` first. Entries that fail, and names
+// the central directory lists but that couldn't be found, are reported as
+// lost along with why.
+//
+// The damaged file is only read, never overwritten. Recovered entries
+// count against the same archive limits as any other EPUB.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::DeflateDecoder;
+use flate2::Crc;
+use log::info;
+use serde::{Deserialize, Serialize};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use super::limits::{self, ArchiveLimits};
+
+const LOCAL_HEADER_SIG: &[u8] = b"PK\x03\x04";
+const CENTRAL_HEADER_SIG: &[u8] = b"PK\x01\x02";
+const DESCRIPTOR_SIG: &[u8] = b"PK\x07\x08";
+
+const LOCAL_HEADER_LEN: usize = 30;
+const CENTRAL_HEADER_LEN: usize = 46;
+
+const FLAG_ENCRYPTED: u16 = 0x0001;
+/// CRC and sizes follow the data instead of being in the header
+const FLAG_DESCRIPTOR: u16 = 0x0008;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+const MIMETYPE: &str = "mimetype";
+const CONTAINER: &str = "META-INF/container.xml";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LostReason {
+    /// The file ends before the entry does
+    Truncated,
+    /// The data doesn't decompress or doesn't match its checksum
+    Corrupt,
+    Encrypted,
+    UnsupportedCompression,
+    UnsafePath,
+    /// Larger than the archive limits allow
+    OverLimit,
+    /// Listed in the central directory, but its data wasn't found
+    NotFound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LostEntry {
+    pub path: String,
+    pub reason: LostReason,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairReport {
+    pub recovered: Vec<String>,
+    pub lost: Vec<LostEntry>,
+    /// `mimetype` and the container document both survived, so the result
+    /// can be opened as an EPUB
+    pub readable: bool,
+}
+
+struct LocalHeader {
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    name: String,
+    data_start: usize,
+}
+
+#[derive(Default)]
+struct Recovery {
+    /// Recovered entries in archive order, with their contents
+    entries: Vec<(String, Vec<u8>)>,
+    lost: Vec<LostEntry>,
+}
+
+// ============================================================================
+// Scanning
+// ============================================================================
+
+fn u16_at(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+fn find(data: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|i| from + i)
+}
+
+fn name_at(data: &[u8], pos: usize, len: usize) -> Option<String> {
+    let name = String::from_utf8_lossy(data.get(pos..pos + len)?);
+    let name = name.replace('\\', "/");
+    (!name.is_empty()).then_some(name)
+}
+
+impl LocalHeader {
+    fn parse(data: &[u8], offset: usize) -> Option<Self> {
+        let name_len = u16_at(data, offset + 26)? as usize;
+        let extra_len = u16_at(data, offset + 28)? as usize;
+        let name_start = offset + LOCAL_HEADER_LEN;
+        Some(Self {
+            flags: u16_at(data, offset + 6)?,
+            method: u16_at(data, offset + 8)?,
+            crc: u32_at(data, offset + 14)?,
+            compressed_size: u32_at(data, offset + 18)?,
+            name: name_at(data, name_start, name_len)?,
+            data_start: name_start + name_len + extra_len,
+        })
+    }
+
+    fn has_descriptor(&self) -> bool {
+        self.flags & FLAG_DESCRIPTOR != 0
+    }
+}
+
+/// Names listed in whatever is left of the central directory
+fn central_names(data: &[u8]) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = find(data, CENTRAL_HEADER_SIG, pos) {
+        pos = offset + CENTRAL_HEADER_SIG.len();
+        if let Some(name) = u16_at(data, offset + 28)
+            .and_then(|len| name_at(data, offset + CENTRAL_HEADER_LEN, len as usize))
+        {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Length of stored data followed by a data descriptor: the first
+/// descriptor whose compressed size matches its distance from the start
+fn stored_length(data: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+    while let Some(offset) = find(data, DESCRIPTOR_SIG, pos) {
+        if u32_at(data, offset + 8).is_some_and(|size| size as usize == offset) {
+            return Some(offset);
+        }
+        pos = offset + 1;
+    }
+    None
+}
+
+/// Decompress the entry at `header` and check its CRC. Returns the contents
+/// and where the entry ends.
+fn extract(
+    data: &[u8],
+    header: &LocalHeader,
+    limits: &ArchiveLimits,
+) -> Result<(Vec<u8>, usize), LostReason> {
+    if header.flags & FLAG_ENCRYPTED != 0 {
+        return Err(LostReason::Encrypted);
+    }
+    if limits::is_unsafe_path(&header.name) {
+        return Err(LostReason::UnsafePath);
+    }
+    let rest = data.get(header.data_start..).ok_or(LostReason::Truncated)?;
+
+    let (contents, consumed) = match header.method {
+        METHOD_STORED => {
+            let len = if header.has_descriptor() && header.compressed_size == 0 {
+                stored_length(rest).ok_or(LostReason::Truncated)?
+            } else {
+                header.compressed_size as usize
+            };
+            if len as u64 > limits.max_entry_size {
+                return Err(LostReason::OverLimit);
+            }
+            (rest.get(..len).ok_or(LostReason::Truncated)?.to_vec(), len)
+        }
+        METHOD_DEFLATED => {
+            // Inflate to the end of the stream rather than trusting the
+            // header, whose sizes may be zero or wrong
+            let mut decoder = DeflateDecoder::new(rest);
+            let mut contents = Vec::new();
+            (&mut decoder)
+                .take(limits.max_entry_size.saturating_add(1))
+                .read_to_end(&mut contents)
+                .map_err(|_| LostReason::Corrupt)?;
+            if contents.len() as u64 > limits.max_entry_size {
+                return Err(LostReason::OverLimit);
+            }
+            (contents, decoder.total_in() as usize)
+        }
+        _ => return Err(LostReason::UnsupportedCompression),
+    };
+
+    let mut end = header.data_start + consumed;
+    let expected = if header.has_descriptor() {
+        if data.get(end..end + 4) == Some(DESCRIPTOR_SIG) {
+            end += DESCRIPTOR_SIG.len();
+        }
+        let crc = u32_at(data, end).ok_or(LostReason::Truncated)?;
+        end += 4;
+        crc
+    } else {
+        header.crc
+    };
+
+    let mut crc = Crc::new();
+    crc.update(&contents);
+    if crc.sum() != expected {
+        return Err(if end >= data.len() {
+            LostReason::Truncated
+        } else {
+            LostReason::Corrupt
+        });
+    }
+    Ok((contents, end))
+}
+
+/// Every entry that can be recovered from `data`, and what was lost
+fn recover(data: &[u8], limits: &ArchiveLimits) -> Recovery {
+    let mut recovery = Recovery::default();
+    let mut total = 0u64;
+    let mut pos = 0;
+
+    while let Some(offset) = find(data, LOCAL_HEADER_SIG, pos) {
+        pos = offset + LOCAL_HEADER_SIG.len();
+        let Some(header) = LocalHeader::parse(data, offset) else {
+            continue;
+        };
+        if header.name.ends_with('/') {
+            // Directories are implied by the paths of their files
+            continue;
+        }
+
+        let result = extract(data, &header, limits).and_then(|(contents, end)| {
+            let over = recovery.entries.len() >= limits.max_entries
+                || total.saturating_add(contents.len() as u64) > limits.max_total_size;
+            if over {
+                Err(LostReason::OverLimit)
+            } else {
+                Ok((contents, end))
+            }
+        });
+        recovery.lost.retain(|lost| lost.path != header.name);
+        match result {
+            Ok((contents, end)) => {
+                // A later copy of an entry replaces the earlier one, as
+                // when a zip was appended to
+                if let Some(i) = recovery.entries.iter().position(|(n, _)| *n == header.name) {
+                    let (_, old) = recovery.entries.remove(i);
+                    total -= old.len() as u64;
+                }
+                total += contents.len() as u64;
+                recovery.entries.push((header.name, contents));
+                pos = end;
+            }
+            Err(reason) => {
+                if !recovery.entries.iter().any(|(n, _)| *n == header.name) {
+                    recovery.lost.push(LostEntry {
+                        path: header.name,
+                        reason,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut seen: HashSet<String> = recovery.entries.iter().map(|(n, _)| n.clone()).collect();
+    seen.extend(recovery.lost.iter().map(|lost| lost.path.clone()));
+    for name in central_names(data) {
+        if !name.ends_with('/') && seen.insert(name.clone()) {
+            recovery.lost.push(LostEntry {
+                path: name,
+                reason: LostReason::NotFound,
+            });
+        }
+    }
+    recovery
+}
+
+// ============================================================================
+// Writing
+// ============================================================================
+
+/// Write `entries` as a new archive, `mimetype` first and uncompressed
+fn write_archive(entries: &[(String, Vec<u8>)], out: impl Write + Seek) -> Result<(), String> {
+    let stored = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .last_modified_time(zip::DateTime::default());
+    let deflated = SimpleFileOptions::default().last_modified_time(zip::DateTime::default());
+
+    let mut zip = ZipWriter::new(out);
+    let ordered = entries
+        .iter()
+        .filter(|(name, _)| name == MIMETYPE)
+        .chain(entries.iter().filter(|(name, _)| name != MIMETYPE));
+    for (name, contents) in ordered {
+        let options = if name == MIMETYPE { stored } else { deflated };
+        zip.start_file(name.as_str(), options)
+            .and_then(|_| zip.write_all(contents).map_err(Into::into))
+            .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    }
+    zip.finish()
+        .map_err(|e| format!("Failed to finish repaired EPUB: {}", e))?;
+    Ok(())
+}
+
+/// Whether `a` and `b` name the same file, including through links
+fn same_file(a: &Path, b: &Path) -> bool {
+    a == b
+        || matches!(
+            (std::fs::canonicalize(a), std::fs::canonicalize(b)),
+            (Ok(a), Ok(b)) if a == b
+        )
+}
+
+fn repair(path: &Path, out_path: &Path) -> Result<RepairReport, String> {
+    if same_file(path, out_path) {
+        return Err("Choose a different file for the repaired EPUB".to_string());
+    }
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read EPUB: {}", e))?;
+    let recovery = recover(&data, &limits::current());
+    if recovery.entries.is_empty() {
+        return Err("No entries could be recovered from the EPUB".to_string());
+    }
+
+    let mut partial = out_path.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let written = File::create(&partial)
+        .map_err(|e| format!("Failed to create repaired EPUB: {}", e))
+        .and_then(|file| write_archive(&recovery.entries, file))
+        .and_then(|_| {
+            std::fs::rename(&partial, out_path)
+                .map_err(|e| format!("Failed to save repaired EPUB: {}", e))
+        });
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e);
+    }
+
+    let recovered: Vec<String> = recovery.entries.into_iter().map(|(name, _)| name).collect();
+    let readable = [MIMETYPE, CONTAINER]
+        .iter()
+        .all(|required| recovered.iter().any(|name| name == required));
+    Ok(RepairReport {
+        recovered,
+        lost: recovery.lost,
+        readable,
+    })
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Rebuild a damaged EPUB from its surviving entries into `out_path`,
+/// which must be a different file
+#[tauri::command]
+pub async fn repair_epub(path: String, out_path: String) -> Result<RepairReport, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let report = repair(Path::new(&path), Path::new(&out_path))?;
+        info!(
+            "Repaired {} into {}: {} entries recovered, {} lost",
+            path,
+            out_path,
+            report.recovered.len(),
+            report.lost.len()
+        );
+        Ok(report)
+    })
+    .await
+    .map_err(|e| format!("EPUB repair task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use flate2::write::DeflateEncoder;
+    use flate2::Compression;
+    use zip::ZipArchive;
+
+    use super::*;
+
+    const CHAPTER: &[u8] = b"<html><body><p>Call me Ishmael.</p></body></html>";
+
+    fn epub() -> Vec<u8> {
+        let entries = [
+            (MIMETYPE.to_string(), b"application/epub+zip".to_vec()),
+            (CONTAINER.to_string(), b"<container/>".to_vec()),
+            ("OEBPS/chapter1.xhtml".to_string(), CHAPTER.to_vec()),
+            ("OEBPS/chapter2.xhtml".to_string(), CHAPTER.repeat(20)),
+        ];
+        let mut out = Cursor::new(Vec::new());
+        write_archive(&entries, &mut out).unwrap();
+        out.into_inner()
+    }
+
+    fn names(recovery: &Recovery) -> Vec<&str> {
+        recovery.entries.iter().map(|(n, _)| n.as_str()).collect()
+    }
+
+    fn lost(recovery: &Recovery) -> Vec<(&str, LostReason)> {
+        recovery
+            .lost
+            .iter()
+            .map(|l| (l.path.as_str(), l.reason))
+            .collect()
+    }
+
+    #[test]
+    fn recovers_an_intact_archive() {
+        let recovery = recover(&epub(), &ArchiveLimits::default());
+        assert_eq!(
+            names(&recovery),
+            [
+                MIMETYPE,
+                CONTAINER,
+                "OEBPS/chapter1.xhtml",
+                "OEBPS/chapter2.xhtml"
+            ]
+        );
+        assert!(recovery.lost.is_empty());
+        assert_eq!(recovery.entries[2].1, CHAPTER);
+    }
+
+    #[test]
+    fn recovers_entries_before_a_truncation() {
+        let data = epub();
+        let last = data
+            .windows(4)
+            .rposition(|w| w == LOCAL_HEADER_SIG)
+            .unwrap();
+        let recovery = recover(&data[..last + 60], &ArchiveLimits::default());
+        assert_eq!(
+            names(&recovery),
+            [MIMETYPE, CONTAINER, "OEBPS/chapter1.xhtml"]
+        );
+        assert_eq!(
+            lost(&recovery),
+            [("OEBPS/chapter2.xhtml", LostReason::Truncated)]
+        );
+    }
+
+    #[test]
+    fn reports_corrupt_entries_listed_in_the_directory() {
+        let mut data = epub();
+        let chapter = find(&data, b"OEBPS/chapter1.xhtml", 0).unwrap();
+        data[chapter + 25] ^= 0xff;
+        let recovery = recover(&data, &ArchiveLimits::default());
+        assert_eq!(
+            lost(&recovery),
+            [("OEBPS/chapter1.xhtml", LostReason::Corrupt)]
+        );
+        assert_eq!(recovery.entries.len(), 3);
+    }
+
+    #[test]
+    fn reports_directory_entries_without_data() {
+        let data = epub();
+        let second = find(&data, LOCAL_HEADER_SIG, 4).unwrap();
+        let mut damaged = data.clone();
+        damaged[second..second + 4].copy_from_slice(b"XXXX");
+        let recovery = recover(&damaged, &ArchiveLimits::default());
+        assert_eq!(lost(&recovery), [(CONTAINER, LostReason::NotFound)]);
+    }
+
+    #[test]
+    fn follows_data_descriptors() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(CHAPTER).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut crc = Crc::new();
+        crc.update(CHAPTER);
+        let name = b"chapter.xhtml";
+
+        let mut data = Vec::new();
+        data.extend_from_slice(LOCAL_HEADER_SIG);
+        data.extend_from_slice(&20u16.to_le_bytes());
+        data.extend_from_slice(&FLAG_DESCRIPTOR.to_le_bytes());
+        data.extend_from_slice(&METHOD_DEFLATED.to_le_bytes());
+        data.extend_from_slice(&[0; 16]);
+        data.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(name);
+        data.extend_from_slice(&compressed);
+        data.extend_from_slice(DESCRIPTOR_SIG);
+        data.extend_from_slice(&crc.sum().to_le_bytes());
+        data.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(CHAPTER.len() as u32).to_le_bytes());
+
+        let recovery = recover(&data, &ArchiveLimits::default());
+        assert_eq!(names(&recovery), ["chapter.xhtml"]);
+        assert_eq!(recovery.entries[0].1, CHAPTER);
+    }
+
+    #[test]
+    fn skips_unsafe_paths() {
+        let entries = [("../evil.xhtml".to_string(), CHAPTER.to_vec())];
+        let mut out = Cursor::new(Vec::new());
+        write_archive(&entries, &mut out).unwrap();
+        let recovery = recover(&out.into_inner(), &ArchiveLimits::default());
+        assert_eq!(lost(&recovery), [("../evil.xhtml", LostReason::UnsafePath)]);
+    }
+
+    #[test]
+    fn writes_mimetype_first() {
+        let entries = [
+            ("OEBPS/a.xhtml".to_string(), CHAPTER.to_vec()),
+            (MIMETYPE.to_string(), b"application/epub+zip".to_vec()),
+        ];
+        let mut out = Cursor::new(Vec::new());
+        write_archive(&entries, &mut out).unwrap();
+        let mut zip = ZipArchive::new(out).unwrap();
+        let first = zip.by_index(0).unwrap();
+        assert_eq!(first.name(), MIMETYPE);
+        assert_eq!(first.compression(), CompressionMethod::Stored);
+    }
+
+    #[test]
+    fn refuses_to_overwrite_the_input() {
+        let path = std::env::temp_dir().join(format!("rm-repair-{}.epub", uuid::Uuid::new_v4()));
+        std::fs::write(&path, epub()).unwrap();
+        let result = repair(&path, &path);
+        assert!(result.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), epub());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+// Read Master Desktop - EPUB Resources
+//
+// Resource access with font de-obfuscation (IDPF and Adobe schemes).
+
+use log::info;
+use sha1::{Digest, Sha1};
+
+use super::{resolve_href, EpubArchive, Obfuscation};
+
+/// Number of leading bytes obfuscated by each scheme
+const IDPF_OBFUSCATED_LEN: usize = 1040;
+const ADOBE_OBFUSCATED_LEN: usize = 1024;
+
+/// IDPF key: SHA-1 of the unique identifier with XML whitespace removed
+fn idpf_key(identifier: &str) -> Vec<u8> {
+    let stripped: String = identifier
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '\t' | '\r' | '\n'))
+        .collect();
+    Sha1::digest(stripped.as_bytes()).to_vec()
+}
+
+/// Adobe key: the 16 raw bytes of the package's UUID identifier
+fn adobe_key(identifier: &str) -> Option<Vec<u8>> {
+    let hex: String = identifier
+        .trim()
+        .trim_start_matches("urn:uuid:")
+        .chars()
+        .filter(char::is_ascii_hexdigit)
+        .collect();
+
+    if hex.len() != 32 {
+        return None;
+    }
+
+    (0..16)
+        .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+        .collect()
+}
+
+fn xor_prefix(data: &mut [u8], key: &[u8], len: usize) {
+    for (i, byte) in data.iter_mut().take(len).enumerate() {
+        *byte ^= key[i % key.len()];
+    }
+}
+
+/// Reverse font obfuscation in place. The operation is a XOR, so applying
+/// it to plain data obfuscates it again.
+pub fn deobfuscate(epub: &EpubArchive, scheme: Obfuscation, data: &mut [u8]) -> Result<(), String> {
+    let package = &epub.package;
+
+    match scheme {
+        Obfuscation::Idpf => {
+            let identifier = package
+                .unique_identifier
+                .as_deref()
+                .ok_or_else(|| "EPUB has no unique identifier to de-obfuscate with".to_string())?;
+            xor_prefix(data, &idpf_key(identifier), IDPF_OBFUSCATED_LEN);
+        }
+        Obfuscation::Adobe => {
+            // Adobe keys come from the urn:uuid identifier, whichever it is
+            let key = package
+                .identifiers
+                .iter()
+                .filter(|id| id.starts_with("urn:uuid:"))
+                .chain(package.unique_identifier.iter())
+                .find_map(|id| adobe_key(id))
+                .ok_or_else(|| "EPUB has no UUID identifier to de-obfuscate with".to_string())?;
+            xor_prefix(data, &key, ADOBE_OBFUSCATED_LEN);
+        }
+    }
+
+    Ok(())
+}
+
+impl EpubArchive {
+    /// Read a resource, de-obfuscating it if encryption.xml says so
+    pub fn read_resource(&mut self, path: &str) -> Result<Vec<u8>, String> {
+        let scheme = self.obfuscated_resources()?.get(path).copied();
+        let mut data = self.read_bytes(path)?;
+
+        if let Some(scheme) = scheme {
+            deobfuscate(self, scheme, &mut data)?;
+        }
+
+        Ok(data)
+    }
+
+    /// Map a frontend href to an archive path. Archive paths are accepted
+    /// as-is; anything else is treated as relative to the OPF.
+    pub fn locate(&self, href: &str) -> Option<String> {
+        let direct = resolve_href("", href);
+        if self.contains(&direct) {
+            return Some(direct);
+        }
+
+        let relative = resolve_href(self.opf_dir(), href);
+        self.contains(&relative).then_some(relative)
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Read a resource (image, stylesheet, font) from an EPUB by href
+#[tauri::command]
+pub async fn read_epub_resource(path: String, href: String) -> Result<Vec<u8>, String> {
+    info!("Reading EPUB resource: {} in {}", href, path);
+
+    let mut epub = EpubArchive::open(&path).map_err(|e| e.to_string())?;
+    let resource = epub
+        .locate(&href)
+        .ok_or_else(|| format!("Resource not found in EPUB: {}", href))?;
+
+    epub.read_resource(&resource)
+}
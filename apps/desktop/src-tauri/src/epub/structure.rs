@@ -0,0 +1,235 @@
+// Read Master Desktop - Book Structure Cache
+//
+// Parsed metadata, spine, and table of contents cached as JSON in the app
+// data `cache/` directory, so re-opening a book skips OPF and TOC parsing.
+//
+// Entries are keyed by a content fingerprint plus the file's mtime. The
+// fingerprint hashes the size and the first and last MiB rather than the
+// whole file, keeping lookups cheap for very large books.
+
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Runtime};
+
+use super::metadata::{self, EpubMetadata};
+use super::toc::TocEntry;
+use super::EpubArchive;
+use crate::data_location;
+
+/// Bumped whenever the cached format changes
+const CACHE_VERSION: u32 = 2;
+
+/// Bytes hashed from each end of the file
+const FINGERPRINT_SPAN: u64 = 1 << 20;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpineEntry {
+    pub index: usize,
+    /// Archive path of the document
+    pub href: String,
+    pub media_type: String,
+    pub linear: bool,
+    pub properties: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookStructure {
+    pub metadata: EpubMetadata,
+    pub spine: Vec<SpineEntry>,
+    pub toc: Vec<TocEntry>,
+    /// Served from the cache rather than parsed
+    #[serde(default)]
+    pub cached: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    structure: BookStructure,
+}
+
+// ============================================================================
+// Cache Keys
+// ============================================================================
+
+fn cache_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    let dir = data_location::base_dir(app)?.join("cache");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create cache directory: {}", e))?;
+    Ok(dir)
+}
+
+/// Hash of the file size and its first and last `FINGERPRINT_SPAN` bytes
+fn fingerprint(path: &Path) -> Result<String, String> {
+    let mut file =
+        File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let size = file
+        .metadata()
+        .map_err(|e| format!("Failed to stat {}: {}", path.display(), e))?
+        .len();
+
+    let mut hasher = Sha256::new();
+    hasher.update(size.to_le_bytes());
+
+    let mut buf = Vec::with_capacity(FINGERPRINT_SPAN as usize);
+    let read_err = |e: std::io::Error| format!("Failed to read {}: {}", path.display(), e);
+    (&mut file)
+        .take(FINGERPRINT_SPAN)
+        .read_to_end(&mut buf)
+        .map_err(read_err)?;
+    hasher.update(&buf);
+
+    if size > FINGERPRINT_SPAN * 2 {
+        buf.clear();
+        file.seek(SeekFrom::End(-(FINGERPRINT_SPAN as i64)))
+            .map_err(read_err)?;
+        file.read_to_end(&mut buf).map_err(read_err)?;
+        hasher.update(&buf);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Cache file name: fingerprint plus modification time
+fn cache_key(path: &Path) -> Result<(String, String), String> {
+    let fingerprint = fingerprint(path)?;
+    let mtime = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let name = format!("{}-{}.json", fingerprint, mtime);
+    Ok((fingerprint, name))
+}
+
+/// Remove cache entries for `fingerprint`, except `keep`
+fn remove_entries(dir: &Path, fingerprint: &str, keep: Option<&str>) -> usize {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.starts_with(fingerprint) && Some(name.as_str()) != keep
+        })
+        .filter(|entry| fs::remove_file(entry.path()).is_ok())
+        .count()
+}
+
+// ============================================================================
+// Parsing
+// ============================================================================
+
+fn parse(path: &str) -> Result<BookStructure, String> {
+    let mut epub = EpubArchive::open(path).map_err(|e| e.to_string())?;
+
+    let spine = epub
+        .package
+        .spine
+        .iter()
+        .filter_map(|item| {
+            epub.manifest_item(&item.idref)
+                .map(|manifest| (item, manifest))
+        })
+        .enumerate()
+        .map(|(index, (item, manifest))| SpineEntry {
+            index,
+            href: manifest.path.clone(),
+            media_type: manifest.media_type.clone(),
+            linear: item.linear,
+            properties: item.properties.clone(),
+        })
+        .collect();
+
+    Ok(BookStructure {
+        metadata: metadata::read_metadata(&mut epub),
+        spine,
+        toc: epub.toc(),
+        cached: false,
+    })
+}
+
+/// Cached structure of the book at `path`, parsing and caching it when
+/// there is no valid entry
+pub fn load<R: Runtime>(app: &AppHandle<R>, path: &str) -> Result<BookStructure, String> {
+    let dir = cache_dir(app)?;
+    let (fingerprint, name) = cache_key(Path::new(path))?;
+    let cache_path = dir.join(&name);
+
+    let cached = fs::read(&cache_path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<CacheFile>(&bytes).ok())
+        .filter(|file| file.version == CACHE_VERSION);
+    if let Some(file) = cached {
+        return Ok(BookStructure {
+            cached: true,
+            ..file.structure
+        });
+    }
+
+    info!("Parsing book structure: {}", path);
+    let structure = parse(path)?;
+
+    // Entries for older versions of the file are dead weight
+    remove_entries(&dir, &fingerprint, Some(&name));
+    let file = CacheFile {
+        version: CACHE_VERSION,
+        structure,
+    };
+    match serde_json::to_vec(&file) {
+        Ok(json) => {
+            if let Err(e) = fs::write(&cache_path, json) {
+                warn!("Failed to write structure cache: {}", e);
+            }
+        }
+        Err(e) => warn!("Failed to serialize structure cache: {}", e),
+    }
+
+    Ok(file.structure)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Metadata, spine, and table of contents of an EPUB, from the cache when
+/// the file hasn't changed
+#[tauri::command]
+pub async fn get_book_structure<R: Runtime>(
+    app: AppHandle<R>,
+    path: String,
+) -> Result<BookStructure, String> {
+    tauri::async_runtime::spawn_blocking(move || load(&app, &path))
+        .await
+        .map_err(|e| format!("Structure task failed: {}", e))?
+}
+
+/// Drop cached structure for a book. Returns whether anything was removed.
+#[tauri::command]
+pub async fn invalidate_book_cache<R: Runtime>(
+    app: AppHandle<R>,
+    path: String,
+) -> Result<bool, String> {
+    let dir = cache_dir(&app)?;
+    let fingerprint = fingerprint(Path::new(&path))?;
+    let removed = remove_entries(&dir, &fingerprint, None);
+
+    info!(
+        "Invalidated {} structure cache entries for {}",
+        removed, path
+    );
+    Ok(removed > 0)
+}
@@ -0,0 +1,397 @@
+// Read Master Desktop - EPUB Text
+//
+// Plain-text extraction from spine documents, shared by search indexing,
+// text statistics, and chapter text for TTS.
+//
+// Whole-book extraction runs across a rayon pool sized to the physical
+// cores. A zip archive can't be read from several threads at once, so each
+// worker opens its own handle on the file.
+
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use log::{info, warn};
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use serde::{Deserialize, Serialize};
+
+use super::EpubArchive;
+
+/// Elements whose content is never reading text
+pub(super) const SKIPPED_ELEMENTS: &[&str] = &["head", "script", "style", "noscript", "rt", "rp"];
+
+/// Elements that end a line of text
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p",
+    "div",
+    "br",
+    "li",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "tr",
+    "section",
+    "article",
+    "aside",
+    "figcaption",
+    "dt",
+    "dd",
+    "hr",
+];
+
+/// Extraction threads; parsing is CPU bound, so hyperthreads don't help
+static POOL: LazyLock<ThreadPool> = LazyLock::new(|| {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(num_cpus::get_physical())
+        .thread_name(|i| format!("epub-text-{}", i))
+        .build()
+        .expect("failed to build text extraction pool")
+});
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChapterText {
+    /// Position in the spine
+    pub index: usize,
+    /// Archive path of the document
+    pub href: String,
+    /// First heading of the chapter, if any
+    pub title: Option<String>,
+    pub text: String,
+}
+
+// ============================================================================
+// Extraction
+// ============================================================================
+
+fn push_block_break(out: &mut String) {
+    let trimmed = out.trim_end_matches(' ').len();
+    out.truncate(trimmed);
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+fn collect_text(node: roxmltree::Node, out: &mut String, title: &mut Option<String>) {
+    for child in node.children() {
+        if child.is_text() {
+            let text = child.text().unwrap_or("");
+            // Collapse whitespace the way a browser would
+            for (i, word) in text.split_whitespace().enumerate() {
+                let needs_space = i > 0
+                    || (text.starts_with(char::is_whitespace)
+                        && !out.is_empty()
+                        && !out.ends_with(['\n', ' ']));
+                if needs_space {
+                    out.push(' ');
+                }
+                out.push_str(word);
+            }
+            if text.ends_with(char::is_whitespace) && !out.is_empty() && !out.ends_with('\n') {
+                out.push(' ');
+            }
+            continue;
+        }
+
+        if !child.is_element() {
+            continue;
+        }
+
+        let name = child.tag_name().name().to_ascii_lowercase();
+        if SKIPPED_ELEMENTS.contains(&name.as_str()) {
+            continue;
+        }
+
+        let block = BLOCK_ELEMENTS.contains(&name.as_str());
+        if block {
+            push_block_break(out);
+        }
+
+        let start = out.len();
+        collect_text(child, out, title);
+
+        if title.is_none() && matches!(name.as_str(), "h1" | "h2" | "h3") {
+            let heading = out[start..].trim();
+            if !heading.is_empty() {
+                *title = Some(heading.to_string());
+            }
+        }
+        if block {
+            push_block_break(out);
+        }
+    }
+}
+
+/// Fallback for documents that aren't well-formed XML: drop tags
+fn strip_tags(markup: &str) -> String {
+    let mut out = String::with_capacity(markup.len());
+    let mut in_tag = false;
+    for c in markup.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(' ');
+            }
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Plain text and first heading of an XHTML document
+pub fn document_text(markup: &str) -> (String, Option<String>) {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+
+    match roxmltree::Document::parse_with_options(markup, options) {
+        Ok(doc) => {
+            let mut text = String::new();
+            let mut title = None;
+            let body = doc
+                .descendants()
+                .find(|n| n.tag_name().name() == "body")
+                .unwrap_or(doc.root_element());
+            collect_text(body, &mut text, &mut title);
+            (text.trim().to_string(), title)
+        }
+        Err(_) => (strip_tags(markup), None),
+    }
+}
+
+impl EpubArchive {
+    /// Spine document paths in reading order
+    pub fn spine_paths(&self) -> Vec<String> {
+        self.spine_items()
+            .into_iter()
+            .filter(|item| {
+                item.media_type == "application/xhtml+xml" || item.media_type == "text/html"
+            })
+            .map(|item| item.path.clone())
+            .collect()
+    }
+
+    /// Extract one spine document's text
+    pub fn chapter_text(&mut self, index: usize) -> Result<ChapterText, String> {
+        let href = self
+            .spine_paths()
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| format!("Chapter {} out of range", index))?;
+
+        let markup = self.read_string(&href)?;
+        let (text, title) = document_text(&markup);
+        Ok(ChapterText {
+            index,
+            href,
+            title,
+            text,
+        })
+    }
+
+    /// Extract every spine document's text, in reading order. Unreadable
+    /// chapters are logged and returned empty so indexes stay aligned.
+    pub fn chapter_texts(&self) -> Vec<ChapterText> {
+        extract_chapters(&self.source, 0..self.spine_paths().len())
+    }
+}
+
+/// Number of chapters extracted concurrently
+pub fn parallelism() -> usize {
+    POOL.current_num_threads()
+}
+
+/// Extract a range of chapters of the EPUB at `path` in parallel, in
+/// spine order. Unreadable chapters are logged and returned empty.
+pub fn extract_chapters(path: &str, chapters: Range<usize>) -> Vec<ChapterText> {
+    let empty = |index: usize| ChapterText {
+        index,
+        href: String::new(),
+        title: None,
+        text: String::new(),
+    };
+
+    POOL.install(|| {
+        chapters
+            .into_par_iter()
+            .map_init(
+                || EpubArchive::open(path),
+                |epub, index| match epub {
+                    Ok(epub) => epub.chapter_text(index).unwrap_or_else(|e| {
+                        warn!("Skipping chapter {}: {}", index, e);
+                        empty(index)
+                    }),
+                    Err(e) => {
+                        warn!("Skipping chapter {}: {}", index, e);
+                        empty(index)
+                    }
+                },
+            )
+            .collect()
+    })
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Plain text of a chapter (by spine position)
+#[tauri::command]
+pub async fn get_chapter_text(path: String, index: usize) -> Result<ChapterText, String> {
+    info!("Extracting chapter {} of {}", index, path);
+
+    tauri::async_runtime::spawn_blocking(move || {
+        EpubArchive::open(&path)
+            .map_err(|e| e.to_string())?
+            .chapter_text(index)
+    })
+    .await
+    .map_err(|e| format!("Extraction task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+    use std::time::Instant;
+
+    use zip::write::SimpleFileOptions;
+
+    use super::*;
+
+    /// Write an EPUB with `chapters` chapters of `paragraphs` paragraphs
+    fn synthetic_epub(name: &str, chapters: usize, paragraphs: usize) -> String {
+        let path =
+            std::env::temp_dir().join(format!("read-master-{}-{}.epub", name, std::process::id()));
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let stored =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default();
+
+        zip.start_file("mimetype", stored).unwrap();
+        zip.write_all(b"application/epub+zip").unwrap();
+
+        zip.start_file("META-INF/container.xml", deflated).unwrap();
+        zip.write_all(
+            br#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#,
+        )
+        .unwrap();
+
+        let manifest: String = (0..chapters)
+            .map(|i| {
+                format!(r#"<item id="c{i}" href="c{i}.xhtml" media-type="application/xhtml+xml"/>"#)
+            })
+            .collect();
+        let spine: String = (0..chapters)
+            .map(|i| format!(r#"<itemref idref="c{i}"/>"#))
+            .collect();
+        zip.start_file("OEBPS/content.opf", deflated).unwrap();
+        write!(
+            zip,
+            r#"<?xml version="1.0"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>Bench</dc:title></metadata>
+  <manifest>{manifest}</manifest>
+  <spine>{spine}</spine>
+</package>"#
+        )
+        .unwrap();
+
+        for i in 0..chapters {
+            zip.start_file(format!("OEBPS/c{i}.xhtml"), deflated)
+                .unwrap();
+            write!(
+                zip,
+                r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><h1>Chapter {i}</h1>"#
+            )
+            .unwrap();
+            for p in 0..paragraphs {
+                write!(
+                    zip,
+                    "<p>Paragraph {p} of chapter {i}, with <em>some</em> inline \
+                     markup and enough words to resemble real prose.</p>"
+                )
+                .unwrap();
+            }
+            zip.write_all(b"</body></html>").unwrap();
+        }
+
+        zip.finish().unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn serial(path: &str) -> Vec<ChapterText> {
+        let mut epub = EpubArchive::open(path).unwrap();
+        (0..epub.spine_paths().len())
+            .map(|i| epub.chapter_text(i).unwrap())
+            .collect()
+    }
+
+    fn same_chapters(a: &[ChapterText], b: &[ChapterText]) -> bool {
+        a.len() == b.len()
+            && a.iter()
+                .zip(b)
+                .all(|(a, b)| a.index == b.index && a.title == b.title && a.text == b.text)
+    }
+
+    #[test]
+    fn parallel_extraction_preserves_spine_order() {
+        let path = synthetic_epub("order", 24, 5);
+
+        let parallel = extract_chapters(&path, 0..24);
+        assert!(same_chapters(&parallel, &serial(&path)));
+        assert_eq!(parallel[7].title.as_deref(), Some("Chapter 7"));
+
+        let partial = extract_chapters(&path, 10..13);
+        assert_eq!(
+            partial.iter().map(|c| c.index).collect::<Vec<_>>(),
+            [10, 11, 12]
+        );
+
+        std::fs::remove_file(path).ok();
+    }
+
+    /// Serial vs parallel extraction of a large book. Run with
+    /// `cargo test --release -- --ignored --nocapture extraction_speedup`.
+    #[test]
+    #[ignore]
+    fn extraction_speedup() {
+        let path = synthetic_epub("bench", 80, 2000);
+
+        let started = Instant::now();
+        let expected = serial(&path);
+        let serial_time = started.elapsed();
+
+        let started = Instant::now();
+        let parallel = extract_chapters(&path, 0..80);
+        let parallel_time = started.elapsed();
+
+        assert!(same_chapters(&parallel, &expected));
+        println!(
+            "80 chapters: serial {:?}, parallel {:?} on {} threads ({:.1}x)",
+            serial_time,
+            parallel_time,
+            parallelism(),
+            serial_time.as_secs_f64() / parallel_time.as_secs_f64()
+        );
+
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,214 @@
+// Read Master Desktop - EPUB Table of Contents
+//
+// Table of contents from the EPUB 3 navigation document, falling back to
+// the EPUB 2 NCX.
+
+use serde::{Deserialize, Serialize};
+
+use super::{parent_dir, resolve_href, EpubArchive};
+
+/// Namespace of `epub:type`
+const OPS_NS: &str = "http://www.idpf.org/2007/ops";
+
+pub(super) const NCX_MEDIA_TYPE: &str = "application/x-dtbncx+xml";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TocEntry {
+    pub label: String,
+    /// Archive path of the target document; headings without a link have none
+    pub href: Option<String>,
+    /// Anchor within the document
+    pub fragment: Option<String>,
+    pub children: Vec<TocEntry>,
+}
+
+// ============================================================================
+// Parsing
+// ============================================================================
+
+fn target(base: &str, href: &str) -> (Option<String>, Option<String>) {
+    let fragment = href
+        .split_once('#')
+        .map(|(_, f)| f.to_string())
+        .filter(|f| !f.is_empty());
+    let path = resolve_href(base, href);
+    (Some(path).filter(|p| !p.is_empty()), fragment)
+}
+
+fn node_text(node: roxmltree::Node) -> String {
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn child_element<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+/// Entries of a navigation document `<ol>`
+fn nav_list(list: roxmltree::Node, base: &str) -> Vec<TocEntry> {
+    list.children()
+        .filter(|n| n.is_element() && n.tag_name().name() == "li")
+        .filter_map(|li| {
+            let link = child_element(li, "a").or_else(|| child_element(li, "span"))?;
+            let (href, fragment) = match link.attribute("href") {
+                Some(href) => target(base, href),
+                None => (None, None),
+            };
+            Some(TocEntry {
+                label: node_text(link),
+                href,
+                fragment,
+                children: child_element(li, "ol")
+                    .map(|ol| nav_list(ol, base))
+                    .unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+pub(super) fn parse_nav(xml: &str, base: &str) -> Option<Vec<TocEntry>> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let doc = roxmltree::Document::parse_with_options(xml, options).ok()?;
+
+    let navs: Vec<_> = doc
+        .descendants()
+        .filter(|n| n.tag_name().name() == "nav")
+        .collect();
+    let nav = navs
+        .iter()
+        .find(|n| {
+            n.attribute((OPS_NS, "type"))
+                .is_some_and(|t| t.split_whitespace().any(|t| t == "toc"))
+        })
+        .or(navs.first())?;
+
+    child_element(*nav, "ol").map(|ol| nav_list(ol, base))
+}
+
+fn ncx_points(parent: roxmltree::Node, base: &str) -> Vec<TocEntry> {
+    parent
+        .children()
+        .filter(|n| n.is_element() && n.tag_name().name() == "navPoint")
+        .map(|point| {
+            let (href, fragment) = child_element(point, "content")
+                .and_then(|c| c.attribute("src"))
+                .map(|src| target(base, src))
+                .unwrap_or((None, None));
+            TocEntry {
+                label: child_element(point, "navLabel")
+                    .map(node_text)
+                    .unwrap_or_default(),
+                href,
+                fragment,
+                children: ncx_points(point, base),
+            }
+        })
+        .collect()
+}
+
+pub(super) fn parse_ncx(xml: &str, base: &str) -> Option<Vec<TocEntry>> {
+    let doc = roxmltree::Document::parse(xml).ok()?;
+    let nav_map = doc
+        .descendants()
+        .find(|n| n.tag_name().name() == "navMap")?;
+    Some(ncx_points(nav_map, base))
+}
+
+impl EpubArchive {
+    /// The book's table of contents, empty when it has none
+    pub fn toc(&mut self) -> Vec<TocEntry> {
+        let nav = self
+            .package
+            .manifest
+            .iter()
+            .find(|item| item.properties.iter().any(|p| p == "nav"))
+            .map(|item| item.path.clone());
+        if let Some(path) = nav {
+            if let Some(toc) = self
+                .read_string(&path)
+                .ok()
+                .and_then(|xml| parse_nav(&xml, parent_dir(&path)))
+            {
+                return toc;
+            }
+        }
+
+        let ncx = self
+            .package
+            .toc_id
+            .as_deref()
+            .and_then(|id| self.manifest_item(id))
+            .or_else(|| {
+                self.package
+                    .manifest
+                    .iter()
+                    .find(|item| item.media_type == NCX_MEDIA_TYPE)
+            })
+            .map(|item| item.path.clone());
+        ncx.and_then(|path| {
+            self.read_string(&path)
+                .ok()
+                .and_then(|xml| parse_ncx(&xml, parent_dir(&path)))
+        })
+        .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_nav() {
+        let xml = r##"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<body>
+  <nav epub:type="landmarks"><ol><li><a href="cover.xhtml">Cover</a></li></ol></nav>
+  <nav epub:type="toc"><ol>
+    <li><a href="text/one.xhtml">One</a></li>
+    <li><span>Part Two</span>
+      <ol><li><a href="text/two.xhtml#s1">Two, <em>first</em></a></li></ol>
+    </li>
+  </ol></nav>
+</body></html>"##;
+
+        let toc = parse_nav(xml, "OEBPS/").unwrap();
+        assert_eq!(toc.len(), 2);
+        assert_eq!(toc[0].href.as_deref(), Some("OEBPS/text/one.xhtml"));
+        assert_eq!(toc[1].label, "Part Two");
+        assert_eq!(toc[1].href, None);
+        assert_eq!(toc[1].children[0].label, "Two, first");
+        assert_eq!(toc[1].children[0].fragment.as_deref(), Some("s1"));
+    }
+
+    #[test]
+    fn parses_ncx() {
+        let xml = r#"<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/"><navMap>
+  <navPoint id="a"><navLabel><text>Intro</text></navLabel><content src="intro.html"/>
+    <navPoint id="b"><navLabel><text>Detail</text></navLabel><content src="intro.html#d"/></navPoint>
+  </navPoint>
+</navMap></ncx>"#;
+
+        let toc = parse_ncx(xml, "").unwrap();
+        assert_eq!(toc[0].label, "Intro");
+        assert_eq!(toc[0].children[0].href.as_deref(), Some("intro.html"));
+        assert_eq!(toc[0].children[0].fragment.as_deref(), Some("d"));
+    }
+}
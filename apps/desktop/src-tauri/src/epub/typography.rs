@@ -0,0 +1,204 @@
+// Read Master Desktop - EPUB Typography
+//
+// User typography profiles rendered to a stylesheet that is injected into
+// served XHTML. Fixed-layout books are never touched, and books that embed
+// their own fonts keep them (the font-family override is dropped).
+
+use serde::{Deserialize, Serialize};
+
+use super::EpubArchive;
+
+/// Id of the injected `<style>` element, so the view can find and replace it
+pub const STYLE_ELEMENT_ID: &str = "read-master-user-style";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Palette {
+    pub background: String,
+    pub text: String,
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypographyProfile {
+    pub font_family: Option<String>,
+    /// Font size as a percentage of the book's own size
+    pub font_size_percent: Option<u32>,
+    pub line_height: Option<f32>,
+    /// Page margins in em
+    pub margin_em: Option<f32>,
+    pub text_align: Option<String>,
+    pub palette: Option<Palette>,
+}
+
+/// What the book's own styling allows us to override
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StyleSupport {
+    /// Pre-paginated books lay out every page themselves; leave them alone
+    pub fixed_layout: bool,
+    /// The book ships its own fonts through `@font-face`
+    pub embedded_fonts: bool,
+}
+
+// ============================================================================
+// Detection
+// ============================================================================
+
+/// Apple's display options file, which flags older fixed-layout books
+const APPLE_DISPLAY_OPTIONS: &str = "META-INF/com.apple.ibooks.display-options.xml";
+
+/// Whether the package or any spine item is pre-paginated
+pub fn is_fixed_layout(epub: &mut EpubArchive) -> bool {
+    let package = &epub.package;
+
+    let pre_paginated = package
+        .meta
+        .iter()
+        .any(|(key, value)| key == "rendition:layout" && value.trim() == "pre-paginated")
+        || package.spine.iter().any(|item| {
+            item.properties
+                .iter()
+                .any(|p| p == "rendition:layout-pre-paginated")
+        });
+    if pre_paginated {
+        return true;
+    }
+
+    // <option name="fixed-layout">true</option>
+    epub.read_string(APPLE_DISPLAY_OPTIONS)
+        .ok()
+        .and_then(|xml| {
+            let doc = roxmltree::Document::parse(&xml).ok()?;
+            let fixed = doc.descendants().any(|n| {
+                n.tag_name().name() == "option"
+                    && n.attribute("name") == Some("fixed-layout")
+                    && n.text().map(str::trim) == Some("true")
+            });
+            Some(fixed)
+        })
+        .unwrap_or(false)
+}
+
+/// Whether any stylesheet in the manifest declares `@font-face`
+pub fn uses_font_face(epub: &mut EpubArchive) -> bool {
+    let stylesheets: Vec<String> = epub
+        .package
+        .manifest
+        .iter()
+        .filter(|item| item.media_type == "text/css")
+        .map(|item| item.path.clone())
+        .collect();
+
+    stylesheets.iter().any(|path| {
+        epub.read_string(path)
+            .map(|css| css.to_ascii_lowercase().contains("@font-face"))
+            .unwrap_or(false)
+    })
+}
+
+pub fn style_support(epub: &mut EpubArchive) -> StyleSupport {
+    StyleSupport {
+        fixed_layout: is_fixed_layout(epub),
+        embedded_fonts: uses_font_face(epub),
+    }
+}
+
+// ============================================================================
+// Stylesheet
+// ============================================================================
+
+/// Keep only characters that can't end a declaration or rule
+fn css_safe(value: &str) -> Option<String> {
+    let value = value.trim();
+    let safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_alphanumeric() || " -_#.,'\"()%".contains(c));
+    safe.then(|| value.to_string())
+}
+
+/// Render the profile as `!important` rules. Returns `None` when nothing
+/// should be injected.
+pub fn stylesheet(profile: &TypographyProfile, support: StyleSupport) -> Option<String> {
+    if support.fixed_layout {
+        return None;
+    }
+
+    let mut body = Vec::new();
+    let mut text = Vec::new();
+    let mut extra = Vec::new();
+
+    if let Some(size) = profile.font_size_percent.filter(|s| (50..=300).contains(s)) {
+        body.push(format!("font-size: {}% !important;", size));
+    }
+    if let Some(margin) = profile.margin_em.filter(|m| (0.0..=10.0).contains(m)) {
+        body.push(format!("margin: 0 {}em !important;", margin));
+    }
+    if let Some(family) = profile.font_family.as_deref().and_then(css_safe) {
+        if !support.embedded_fonts {
+            text.push(format!("font-family: {} !important;", family));
+        }
+    }
+    if let Some(height) = profile.line_height.filter(|h| (0.8..=4.0).contains(h)) {
+        text.push(format!("line-height: {} !important;", height));
+    }
+    if let Some(align) = profile.text_align.as_deref() {
+        if matches!(align, "left" | "right" | "center" | "justify" | "start") {
+            text.push(format!("text-align: {} !important;", align));
+        }
+    }
+    if let Some(palette) = &profile.palette {
+        if let (Some(bg), Some(fg)) = (css_safe(&palette.background), css_safe(&palette.text)) {
+            body.push(format!("background-color: {} !important;", bg));
+            text.push(format!("color: {} !important;", fg));
+            text.push("background-color: transparent !important;".to_string());
+        }
+        if let Some(link) = palette.link.as_deref().and_then(css_safe) {
+            extra.push(format!("a, a * {{ color: {} !important; }}", link));
+        }
+    }
+
+    if body.is_empty() && text.is_empty() && extra.is_empty() {
+        return None;
+    }
+
+    let mut css = String::new();
+    if !body.is_empty() {
+        css.push_str(&format!("html, body {{ {} }}\n", body.join(" ")));
+    }
+    if !text.is_empty() {
+        css.push_str(&format!(
+            "body, p, div, span, li, blockquote, td, h1, h2, h3, h4, h5, h6 {{ {} }}\n",
+            text.join(" ")
+        ));
+    }
+    for rule in extra {
+        css.push_str(&rule);
+        css.push('\n');
+    }
+
+    Some(css)
+}
+
+/// Insert the stylesheet as the last element of `<head>` so it wins over
+/// the book's own styles. Documents without a head are returned unchanged.
+pub fn inject(xhtml: &str, css: &str) -> String {
+    let lower = xhtml.to_ascii_lowercase();
+    let Some(pos) = lower.find("</head>") else {
+        return xhtml.to_string();
+    };
+
+    format!(
+        "{}<style id=\"{}\" type=\"text/css\">\n{}</style>\n{}",
+        &xhtml[..pos],
+        STYLE_ELEMENT_ID,
+        css,
+        &xhtml[pos..]
+    )
+}
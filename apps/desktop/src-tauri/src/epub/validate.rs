@@ -0,0 +1,535 @@
+// Read Master Desktop - EPUB Validation
+//
+// A light integrity check in the spirit of epubcheck, covering the
+// problems that break reading rather than every rule of the spec: the
+// mimetype entry, the container and package documents, manifest and spine
+// references, table of contents targets, and links between content
+// documents. Problems are collected rather than failing on the first one,
+// so the import flow can warn about a book before it is opened.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Seek};
+
+use serde::{Deserialize, Serialize};
+use zip::{CompressionMethod, ZipArchive};
+
+use super::toc::{parse_nav, parse_ncx, TocEntry, NCX_MEDIA_TYPE};
+use super::{limits, parent_dir, parse_container, parse_package, resolve_href, Package};
+
+const MIMETYPE: &str = "application/epub+zip";
+
+/// Content document attributes that reference other resources
+const LINK_ATTRIBUTES: &[&str] = &["href", "src"];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Reading will fail or the book can't be opened
+    Error,
+    /// Against the spec, or a broken link the reader can live with
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    /// Stable identifier of the check, e.g. `spine-missing`
+    pub code: String,
+    pub message: String,
+    /// Archive path the issue was found in or refers to
+    pub path: Option<String>,
+}
+
+struct Issues(Vec<ValidationIssue>);
+
+impl Issues {
+    fn push(&mut self, severity: Severity, code: &str, path: Option<&str>, message: String) {
+        self.0.push(ValidationIssue {
+            severity,
+            code: code.to_string(),
+            message,
+            path: path.map(str::to_string),
+        });
+    }
+
+    fn error(&mut self, code: &str, path: Option<&str>, message: String) {
+        self.push(Severity::Error, code, path, message);
+    }
+
+    fn warning(&mut self, code: &str, path: Option<&str>, message: String) {
+        self.push(Severity::Warning, code, path, message);
+    }
+}
+
+// ============================================================================
+// Checks
+// ============================================================================
+
+fn read_entry<R: Read + Seek>(zip: &mut ZipArchive<R>, path: &str) -> Option<String> {
+    let entry = zip.by_name(path).ok()?;
+    let size = entry.size();
+    let bytes = limits::read_limited(entry, path, size, &limits::current()).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn check_mimetype<R: Read + Seek>(zip: &mut ZipArchive<R>, issues: &mut Issues) {
+    let first = zip
+        .by_index(0)
+        .ok()
+        .map(|entry| (entry.name().to_string(), entry.compression()));
+    match first {
+        Some((name, compression)) if name == "mimetype" => {
+            if compression != CompressionMethod::Stored {
+                issues.warning(
+                    "mimetype-compressed",
+                    Some("mimetype"),
+                    "The mimetype entry is compressed".to_string(),
+                );
+            }
+        }
+        _ if zip.index_for_name("mimetype").is_some() => issues.warning(
+            "mimetype-order",
+            Some("mimetype"),
+            "The mimetype entry is not the first in the archive".to_string(),
+        ),
+        _ => {
+            issues.error(
+                "mimetype-missing",
+                None,
+                "The archive has no mimetype entry".to_string(),
+            );
+            return;
+        }
+    }
+
+    match read_entry(zip, "mimetype") {
+        Some(value) if value == MIMETYPE => {}
+        Some(value) if value.trim() == MIMETYPE => issues.warning(
+            "mimetype-whitespace",
+            Some("mimetype"),
+            "The mimetype entry has surrounding whitespace".to_string(),
+        ),
+        value => issues.error(
+            "mimetype-invalid",
+            Some("mimetype"),
+            format!(
+                "The mimetype is '{}', not '{}'",
+                value.unwrap_or_default().trim(),
+                MIMETYPE
+            ),
+        ),
+    }
+}
+
+/// Parse the container and package documents, returning the OPF path and
+/// package if both are usable
+fn check_package<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    issues: &mut Issues,
+) -> Option<(String, Package)> {
+    let Some(container) = read_entry(zip, super::CONTAINER_PATH) else {
+        issues.error(
+            "container-missing",
+            Some(super::CONTAINER_PATH),
+            "The archive has no META-INF/container.xml".to_string(),
+        );
+        return None;
+    };
+    let opf_path = match parse_container(&container) {
+        Ok(path) => path,
+        Err(e) => {
+            issues.error("container-invalid", Some(super::CONTAINER_PATH), e);
+            return None;
+        }
+    };
+
+    let Some(opf) = read_entry(zip, &opf_path) else {
+        issues.error(
+            "opf-missing",
+            Some(&opf_path),
+            format!("The package document {} is missing", opf_path),
+        );
+        return None;
+    };
+    match parse_package(&opf, parent_dir(&opf_path)) {
+        Ok(package) => Some((opf_path, package)),
+        Err(e) => {
+            issues.error("opf-invalid", Some(&opf_path), e);
+            None
+        }
+    }
+}
+
+fn check_references(
+    opf_path: &str,
+    package: &Package,
+    entries: &HashSet<String>,
+    issues: &mut Issues,
+) {
+    let spine_ids: HashSet<&str> = package.spine.iter().map(|s| s.idref.as_str()).collect();
+
+    for item in &package.manifest {
+        if !entries.contains(&item.path) {
+            let severity = if spine_ids.contains(item.id.as_str()) {
+                Severity::Error
+            } else {
+                Severity::Warning
+            };
+            issues.push(
+                severity,
+                "manifest-missing",
+                Some(&item.path),
+                format!("Manifest item '{}' points to a missing file", item.id),
+            );
+        }
+    }
+
+    if package.spine.is_empty() {
+        issues.error(
+            "spine-empty",
+            Some(opf_path),
+            "The spine lists no content documents".to_string(),
+        );
+    }
+    for itemref in &package.spine {
+        if !package.manifest.iter().any(|item| item.id == itemref.idref) {
+            issues.error(
+                "spine-idref",
+                Some(opf_path),
+                format!("Spine item '{}' is not in the manifest", itemref.idref),
+            );
+        }
+    }
+
+    let has_item = |id: &str| package.manifest.iter().any(|item| item.id == id);
+    if let Some(toc_id) = package.toc_id.as_deref().filter(|id| !has_item(id)) {
+        issues.warning(
+            "opf-toc",
+            Some(opf_path),
+            format!("The spine's toc '{}' is not in the manifest", toc_id),
+        );
+    }
+    let cover = package.meta.iter().find(|(key, _)| key == "cover");
+    if let Some((_, id)) = cover.filter(|(_, id)| !has_item(id)) {
+        issues.warning(
+            "opf-cover",
+            Some(opf_path),
+            format!("The cover meta points to unknown item '{}'", id),
+        );
+    }
+}
+
+fn check_toc_entries(
+    entries: &[TocEntry],
+    doc: &str,
+    files: &HashSet<String>,
+    issues: &mut Issues,
+) {
+    for entry in entries {
+        if let Some(href) = entry.href.as_deref().filter(|href| !files.contains(*href)) {
+            issues.error(
+                "toc-missing",
+                Some(doc),
+                format!(
+                    "Table of contents entry '{}' points to missing {}",
+                    entry.label, href
+                ),
+            );
+        }
+        check_toc_entries(&entry.children, doc, files, issues);
+    }
+}
+
+fn check_toc<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    package: &Package,
+    entries: &HashSet<String>,
+    issues: &mut Issues,
+) {
+    let nav = package
+        .manifest
+        .iter()
+        .find(|item| item.properties.iter().any(|p| p == "nav"));
+    let ncx = package
+        .toc_id
+        .as_deref()
+        .and_then(|id| package.manifest.iter().find(|item| item.id == id))
+        .or_else(|| {
+            package
+                .manifest
+                .iter()
+                .find(|item| item.media_type == NCX_MEDIA_TYPE)
+        });
+    if nav.is_none() && ncx.is_none() {
+        issues.warning(
+            "toc-none",
+            None,
+            "The book has no navigation document or NCX".to_string(),
+        );
+    }
+
+    let documents = [
+        nav.map(|item| (item.path.as_str(), parse_nav as fn(&str, &str) -> _)),
+        ncx.map(|item| (item.path.as_str(), parse_ncx as fn(&str, &str) -> _)),
+    ];
+    for (path, parse) in documents.into_iter().flatten() {
+        // Missing files were reported with the manifest
+        let Some(xml) = read_entry(zip, path) else {
+            continue;
+        };
+        match parse(&xml, parent_dir(path)) {
+            Some(toc) => check_toc_entries(&toc, path, entries, issues),
+            None => issues.warning(
+                "toc-invalid",
+                Some(path),
+                "The table of contents can't be parsed".to_string(),
+            ),
+        }
+    }
+}
+
+/// Whether `href` points into the archive rather than the web or a
+/// fragment of the same document
+fn is_internal(href: &str) -> bool {
+    let href = href.trim();
+    !href.is_empty()
+        && !href.starts_with('#')
+        && !href
+            .split_once(':')
+            .is_some_and(|(scheme, _)| !scheme.contains('/'))
+}
+
+fn check_links<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    package: &Package,
+    entries: &HashSet<String>,
+    issues: &mut Issues,
+) {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let spine_paths: Vec<String> = package
+        .spine
+        .iter()
+        .filter_map(|s| package.manifest.iter().find(|item| item.id == s.idref))
+        .map(|item| item.path.clone())
+        .collect();
+
+    for path in spine_paths {
+        let Some(markup) = read_entry(zip, &path) else {
+            continue;
+        };
+        let doc = match roxmltree::Document::parse_with_options(&markup, options) {
+            Ok(doc) => doc,
+            Err(e) => {
+                issues.warning(
+                    "content-invalid",
+                    Some(&path),
+                    format!("Content document is not well-formed XHTML: {}", e),
+                );
+                continue;
+            }
+        };
+
+        let mut reported = HashSet::new();
+        for href in doc
+            .descendants()
+            .filter(|n| n.is_element())
+            .flat_map(|n| LINK_ATTRIBUTES.iter().filter_map(move |a| n.attribute(*a)))
+            .filter(|href| is_internal(href))
+        {
+            let target = resolve_href(parent_dir(&path), href);
+            if !entries.contains(&target) && reported.insert(target.clone()) {
+                issues.warning(
+                    "link-broken",
+                    Some(&path),
+                    format!("Link to missing {}", target),
+                );
+            }
+        }
+    }
+}
+
+/// Check an EPUB archive, most fundamental problems first
+fn validate_archive<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Vec<ValidationIssue> {
+    let mut issues = Issues(Vec::new());
+    if let Err(e) = limits::check_archive(zip, &limits::current()) {
+        issues.error("suspicious-archive", None, e.to_string());
+        return issues.0;
+    }
+    check_mimetype(zip, &mut issues);
+
+    if let Some((opf_path, package)) = check_package(zip, &mut issues) {
+        let entries: HashSet<String> = zip.file_names().map(str::to_string).collect();
+        check_references(&opf_path, &package, &entries, &mut issues);
+        check_toc(zip, &package, &entries, &mut issues);
+        check_links(zip, &package, &entries, &mut issues);
+    }
+    issues.0
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Check an EPUB for problems that would break reading. Only a file that
+/// can't be read at all is an error; everything else is reported as
+/// issues, including a file that isn't a zip archive.
+#[tauri::command]
+pub async fn validate_epub(path: String) -> Result<Vec<ValidationIssue>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let file = File::open(&path).map_err(|e| format!("Failed to open EPUB: {}", e))?;
+        let mut zip = match ZipArchive::new(file) {
+            Ok(zip) => zip,
+            Err(e) => {
+                return Ok(vec![ValidationIssue {
+                    severity: Severity::Error,
+                    code: "not-zip".to_string(),
+                    message: format!("Not a zip archive: {}", e),
+                    path: None,
+                }])
+            }
+        };
+        Ok(validate_archive(&mut zip))
+    })
+    .await
+    .map_err(|e| format!("EPUB validation task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    const CONTAINER: &str = r#"<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles></container>"#;
+
+    const OPF: &str = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+<manifest>
+  <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+  <item id="one" href="text/one.xhtml" media-type="application/xhtml+xml"/>
+  <item id="img" href="images/fig.png" media-type="image/png"/>
+</manifest>
+<spine><itemref idref="one"/></spine>
+</package>"#;
+
+    const NAV: &str = r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><body>
+<nav epub:type="toc"><ol><li><a href="text/one.xhtml">One</a></li></ol></nav>
+</body></html>"#;
+
+    const ONE: &str = r##"<html xmlns="http://www.w3.org/1999/xhtml"><body>
+<p><a href="https://example.com">web</a> <a href="#top">top</a></p>
+<img src="../images/fig.png"/>
+</body></html>"##;
+
+    fn archive(entries: &[(&str, &str)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            let method = if *name == "mimetype" {
+                CompressionMethod::Stored
+            } else {
+                CompressionMethod::Deflated
+            };
+            writer
+                .start_file(
+                    *name,
+                    SimpleFileOptions::default().compression_method(method),
+                )
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    fn valid_entries() -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("mimetype", MIMETYPE),
+            ("META-INF/container.xml", CONTAINER),
+            ("OEBPS/content.opf", OPF),
+            ("OEBPS/nav.xhtml", NAV),
+            ("OEBPS/text/one.xhtml", ONE),
+            ("OEBPS/images/fig.png", "png"),
+        ]
+    }
+
+    fn codes(issues: &[ValidationIssue]) -> Vec<&str> {
+        issues.iter().map(|issue| issue.code.as_str()).collect()
+    }
+
+    #[test]
+    fn accepts_a_valid_book() {
+        assert_eq!(validate_archive(&mut archive(&valid_entries())), []);
+    }
+
+    #[test]
+    fn reports_mimetype_problems() {
+        let mut entries = valid_entries();
+        entries[0] = ("mimetype", "application/zip");
+        assert_eq!(
+            codes(&validate_archive(&mut archive(&entries))),
+            ["mimetype-invalid"]
+        );
+
+        let mut entries = valid_entries();
+        entries.swap(0, 1);
+        assert_eq!(
+            codes(&validate_archive(&mut archive(&entries))),
+            ["mimetype-order"]
+        );
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let entries: Vec<_> = valid_entries()
+            .into_iter()
+            .filter(|(name, _)| !name.ends_with("one.xhtml") && !name.ends_with(".png"))
+            .collect();
+        let issues = validate_archive(&mut archive(&entries));
+
+        assert_eq!(
+            codes(&issues),
+            ["manifest-missing", "manifest-missing", "toc-missing"]
+        );
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert_eq!(issues[0].path.as_deref(), Some("OEBPS/text/one.xhtml"));
+        assert_eq!(issues[1].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn reports_broken_links_and_spine_references() {
+        let opf = OPF.replace(
+            r#"<itemref idref="one"/>"#,
+            r#"<itemref idref="one"/><itemref idref="gone"/>"#,
+        );
+        let one = ONE.replace("../images/fig.png", "../images/missing.png");
+        let mut entries = valid_entries();
+        entries[2] = ("OEBPS/content.opf", &opf);
+        entries[4] = ("OEBPS/text/one.xhtml", &one);
+
+        let issues = validate_archive(&mut archive(&entries));
+        assert_eq!(codes(&issues), ["spine-idref", "link-broken"]);
+        assert!(issues[1].message.ends_with("OEBPS/images/missing.png"));
+    }
+
+    #[test]
+    fn stops_at_a_broken_container() {
+        let mut entries = valid_entries();
+        entries.remove(1);
+        assert_eq!(
+            codes(&validate_archive(&mut archive(&entries))),
+            ["container-missing"]
+        );
+    }
+}
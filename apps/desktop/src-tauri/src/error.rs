@@ -0,0 +1,394 @@
+// Read Master Desktop - Errors
+//
+// Typed errors for IPC commands. Each serializes to
+// `{ code, message, details?, retryable }` so the frontend can switch on
+// `code` instead of matching message text. The codes and field names are
+// part of the frontend contract; don't rename them.
+
+use std::fmt;
+use std::io;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::epub::limits::SuspiciousArchive;
+use crate::formats::djvu::DjvuError;
+use crate::formats::html::ArticleError;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppError {
+    /// A file or record doesn't exist
+    NotFound {
+        message: String,
+        details: Option<String>,
+    },
+    /// The OS refused access to a file
+    PermissionDenied {
+        message: String,
+        details: Option<String>,
+    },
+    /// The caller passed something unusable (bad URL, unknown format, ...)
+    InvalidInput {
+        message: String,
+        details: Option<String>,
+    },
+    /// A book or archive exists but can't be parsed
+    CorruptFile {
+        message: String,
+        details: Option<String>,
+    },
+    /// An archive tripped a size or path limit; `details` names the limit
+    SuspiciousArchive {
+        message: String,
+        details: Option<String>,
+    },
+    /// The format is supported but a system library it needs is missing;
+    /// `details` names the library
+    MissingLibrary {
+        message: String,
+        details: Option<String>,
+    },
+    /// A web page had no readable article; `details` is `paywall` or
+    /// `empty`, and the UI offers importing copied HTML instead
+    NoArticle {
+        message: String,
+        details: Option<String>,
+    },
+    /// Any other filesystem failure
+    Io {
+        message: String,
+        details: Option<String>,
+    },
+    /// JSON that couldn't be read or written
+    Serialization {
+        message: String,
+        details: Option<String>,
+    },
+    /// An update server or other remote host couldn't be reached
+    Network {
+        message: String,
+        details: Option<String>,
+    },
+    /// The Tauri runtime or a plugin failed
+    Tauri {
+        message: String,
+        details: Option<String>,
+    },
+    /// The feature is switched off while the app runs in safe mode
+    UnavailableInSafeMode {
+        message: String,
+        details: Option<String>,
+    },
+    /// Everything else
+    Internal {
+        message: String,
+        details: Option<String>,
+    },
+}
+
+impl AppError {
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::InvalidInput {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn network(message: impl Into<String>, details: impl fmt::Display) -> Self {
+        Self::Network {
+            message: message.into(),
+            details: Some(details.to_string()),
+        }
+    }
+
+    pub fn tauri(message: impl Into<String>, details: impl fmt::Display) -> Self {
+        Self::Tauri {
+            message: message.into(),
+            details: Some(details.to_string()),
+        }
+    }
+
+    pub fn unavailable_in_safe_mode(message: impl Into<String>) -> Self {
+        Self::UnavailableInSafeMode {
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Machine-readable code the frontend switches on
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound { .. } => "not_found",
+            Self::PermissionDenied { .. } => "permission_denied",
+            Self::InvalidInput { .. } => "invalid_input",
+            Self::CorruptFile { .. } => "corrupt_file",
+            Self::SuspiciousArchive { .. } => "suspicious_archive",
+            Self::MissingLibrary { .. } => "missing_library",
+            Self::NoArticle { .. } => "no_article",
+            Self::Io { .. } => "io",
+            Self::Serialization { .. } => "serialization",
+            Self::Network { .. } => "network",
+            Self::Tauri { .. } => "tauri",
+            Self::UnavailableInSafeMode { .. } => "unavailable_in_safe_mode",
+            Self::Internal { .. } => "internal",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::NotFound { message, .. }
+            | Self::PermissionDenied { message, .. }
+            | Self::InvalidInput { message, .. }
+            | Self::CorruptFile { message, .. }
+            | Self::SuspiciousArchive { message, .. }
+            | Self::MissingLibrary { message, .. }
+            | Self::NoArticle { message, .. }
+            | Self::Io { message, .. }
+            | Self::Serialization { message, .. }
+            | Self::Network { message, .. }
+            | Self::Tauri { message, .. }
+            | Self::UnavailableInSafeMode { message, .. }
+            | Self::Internal { message, .. } => message,
+        }
+    }
+
+    pub fn details(&self) -> Option<&str> {
+        match self {
+            Self::NotFound { details, .. }
+            | Self::PermissionDenied { details, .. }
+            | Self::InvalidInput { details, .. }
+            | Self::CorruptFile { details, .. }
+            | Self::SuspiciousArchive { details, .. }
+            | Self::MissingLibrary { details, .. }
+            | Self::NoArticle { details, .. }
+            | Self::Io { details, .. }
+            | Self::Serialization { details, .. }
+            | Self::Network { details, .. }
+            | Self::Tauri { details, .. }
+            | Self::UnavailableInSafeMode { details, .. }
+            | Self::Internal { details, .. } => details.as_deref(),
+        }
+    }
+
+    /// Whether trying the same thing again might succeed
+    pub fn retryable(&self) -> bool {
+        matches!(self, Self::Io { .. } | Self::Network { .. })
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.details() {
+            Some(details) => write!(f, "{}: {}", self.message(), details),
+            None => f.write_str(self.message()),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let details = self.details();
+        let mut state = serializer.serialize_struct("AppError", 3 + details.is_some() as usize)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", self.message())?;
+        if let Some(details) = details {
+            state.serialize_field("details", details)?;
+        }
+        state.serialize_field("retryable", &self.retryable())?;
+        state.end()
+    }
+}
+
+// ============================================================================
+// Conversions
+// ============================================================================
+
+impl From<io::Error> for AppError {
+    fn from(e: io::Error) -> Self {
+        let details = Some(e.to_string());
+        match e.kind() {
+            io::ErrorKind::NotFound => Self::NotFound {
+                message: "File not found".to_string(),
+                details,
+            },
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied {
+                message: "Permission denied".to_string(),
+                details,
+            },
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => Self::CorruptFile {
+                message: "File is damaged or incomplete".to_string(),
+                details,
+            },
+            _ => Self::Io {
+                message: "File operation failed".to_string(),
+                details,
+            },
+        }
+    }
+}
+
+impl From<zip::result::ZipError> for AppError {
+    fn from(e: zip::result::ZipError) -> Self {
+        match e {
+            zip::result::ZipError::Io(e) => e.into(),
+            zip::result::ZipError::FileNotFound => Self::NotFound {
+                message: "Entry not found in archive".to_string(),
+                details: None,
+            },
+            e => Self::CorruptFile {
+                message: "Archive is damaged or unsupported".to_string(),
+                details: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+impl From<SuspiciousArchive> for AppError {
+    fn from(e: SuspiciousArchive) -> Self {
+        Self::SuspiciousArchive {
+            message: e.to_string(),
+            details: Some(e.limit().to_string()),
+        }
+    }
+}
+
+impl From<DjvuError> for AppError {
+    fn from(e: DjvuError) -> Self {
+        match e {
+            DjvuError::LibraryMissing => Self::MissingLibrary {
+                message: e.to_string(),
+                details: Some("djvulibre".to_string()),
+            },
+            DjvuError::Tool { .. } => Self::CorruptFile {
+                message: "DjVu file is damaged or unsupported".to_string(),
+                details: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+impl From<ArticleError> for AppError {
+    fn from(e: ArticleError) -> Self {
+        let details = match e {
+            ArticleError::Paywalled => "paywall",
+            ArticleError::Empty => "empty",
+        };
+        Self::NoArticle {
+            message: e.to_string(),
+            details: Some(details.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        if e.is_io() {
+            return io::Error::from(e).into();
+        }
+        Self::Serialization {
+            message: "Invalid JSON".to_string(),
+            details: Some(e.to_string()),
+        }
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(e: tauri::Error) -> Self {
+        match e {
+            tauri::Error::Io(e) => e.into(),
+            e => Self::tauri("Application error", e),
+        }
+    }
+}
+
+/// Errors from modules still returning `Result<_, String>`
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        Self::Internal {
+            message,
+            details: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn serializes_code_message_details_and_retryable() {
+        let error = AppError::from(io::Error::new(io::ErrorKind::TimedOut, "disk busy"));
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({
+                "code": "io",
+                "message": "File operation failed",
+                "details": "disk busy",
+                "retryable": true,
+            })
+        );
+    }
+
+    #[test]
+    fn omits_missing_details() {
+        assert_eq!(
+            serde_json::to_value(AppError::invalid_input("Invalid URL")).unwrap(),
+            json!({
+                "code": "invalid_input",
+                "message": "Invalid URL",
+                "retryable": false,
+            })
+        );
+    }
+
+    #[test]
+    fn safe_mode_refusals_have_their_own_code() {
+        let error = AppError::unavailable_in_safe_mode("Updates are off in safe mode");
+        assert_eq!(error.code(), "unavailable_in_safe_mode");
+        assert!(!error.retryable());
+    }
+
+    #[test]
+    fn maps_io_kinds_to_codes() {
+        let code = |kind| AppError::from(io::Error::from(kind)).code();
+        assert_eq!(code(io::ErrorKind::NotFound), "not_found");
+        assert_eq!(code(io::ErrorKind::PermissionDenied), "permission_denied");
+        assert_eq!(code(io::ErrorKind::UnexpectedEof), "corrupt_file");
+        assert_eq!(code(io::ErrorKind::Other), "io");
+    }
+
+    #[test]
+    fn maps_zip_and_json_errors() {
+        let zip = AppError::from(zip::result::ZipError::InvalidArchive("bad header".into()));
+        assert_eq!(zip.code(), "corrupt_file");
+        assert!(!zip.retryable());
+        assert_eq!(
+            AppError::from(zip::result::ZipError::FileNotFound).code(),
+            "not_found"
+        );
+
+        let json = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        assert_eq!(AppError::from(json).code(), "serialization");
+        assert_eq!(AppError::from("oops".to_string()).code(), "internal");
+
+        let archive = AppError::from(SuspiciousArchive::UnsafePath {
+            path: "../evil".to_string(),
+        });
+        assert_eq!(archive.code(), "suspicious_archive");
+        assert_eq!(archive.details(), Some("unsafe_path"));
+
+        let missing = AppError::from(DjvuError::LibraryMissing);
+        assert_eq!(missing.code(), "missing_library");
+        assert_eq!(missing.details(), Some("djvulibre"));
+
+        let paywalled = AppError::from(ArticleError::Paywalled);
+        assert_eq!(paywalled.code(), "no_article");
+        assert_eq!(paywalled.details(), Some("paywall"));
+    }
+}
@@ -0,0 +1,589 @@
+// Read Master Desktop - Find in Book
+//
+// The reader's find bar, searching the whole open book.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::db::Database;
+use crate::epub::{cfi, text, EpubArchive};
+use crate::pdf::{self, PdfFile, PdfHandles};
+use crate::reader::ReaderSessions;
+use crate::{import, search};
+
+/// Matches returned with the search; the rest are paged in
+const PAGE_SIZE: usize = 50;
+
+/// Characters of context on each side of a match in its snippet
+const SNIPPET_CONTEXT: usize = 40;
+
+/// Compiled pattern size cap, so a hostile regex can't exhaust memory
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FindOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    /// Treat the query as a regular expression
+    pub regex: bool,
+}
+
+/// Where the reader scrolls to show a match: the CFI of the word it
+/// starts in for EPUBs, or the page and offsets into its text layer for
+/// PDFs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum FindLocation {
+    Epub {
+        href: String,
+        cfi: String,
+    },
+    /// Character offsets into the page's text, which the text layer
+    /// resolves to a rect
+    Pdf {
+        page: u32,
+        offset: usize,
+        length: usize,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindMatch {
+    /// Position in the book's ordered matches
+    pub index: usize,
+    /// Spine position (page index for PDFs)
+    pub chapter: usize,
+    /// Character offsets into the chapter text
+    pub start: usize,
+    pub end: usize,
+    /// Context with the match wrapped in `<mark>`
+    pub snippet: String,
+    /// `None` when the match couldn't be mapped into the document
+    pub location: Option<FindLocation>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindResults {
+    pub total: usize,
+    /// The first page of matches; `find_matches` returns the rest
+    pub matches: Vec<FindMatch>,
+    /// Whether the search index narrowed the chapters searched
+    pub used_index: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Hit {
+    chapter: usize,
+    start: usize,
+    end: usize,
+}
+
+enum Source {
+    Epub { path: String, hrefs: Vec<String> },
+    Pdf(Arc<PdfFile>),
+}
+
+/// Matches of one session's last search, in book order, with a cursor for
+/// Find Next / Find Previous
+struct FindSession {
+    /// Library id, when the session is a reader session
+    book_id: Option<i64>,
+    source: Source,
+    /// Extracted text of each chapter (each page, for PDFs), filled in the
+    /// first time a search needs it and kept until the session closes
+    texts: Vec<Option<Arc<str>>>,
+    /// Hash of the book file, computed the first time the index is used
+    content_hash: Option<String>,
+    hits: Vec<Hit>,
+    cursor: Option<usize>,
+}
+
+/// Find state of open reader sessions and PDFs, by session id
+#[derive(Default)]
+pub struct FindSessions(Mutex<HashMap<String, Arc<Mutex<FindSession>>>>);
+
+impl FindSessions {
+    /// Drop a closed session's text and matches
+    pub fn forget(&self, session_id: &str) {
+        if let Ok(mut sessions) = self.0.lock() {
+            sessions.remove(session_id);
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut sessions) = self.0.lock() {
+            sessions.clear();
+        }
+    }
+
+    fn get(&self, session_id: &str) -> Result<Arc<Mutex<FindSession>>, String> {
+        self.0
+            .lock()
+            .map_err(|_| "Find lock poisoned".to_string())?
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| format!("No search in session {}", session_id))
+    }
+}
+
+// ============================================================================
+// Matching
+// ============================================================================
+
+fn pattern(query: &str, options: FindOptions) -> Result<Regex, String> {
+    let mut source = if options.regex {
+        query.to_string()
+    } else {
+        regex::escape(query)
+    };
+    if options.whole_word {
+        source = format!(r"\b(?:{})\b", source);
+    }
+    RegexBuilder::new(&source)
+        .case_insensitive(!options.case_sensitive)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid search pattern: {}", e))
+}
+
+/// Non-empty matches in `text`, as character offsets
+fn matches_in(text: &str, pattern: &Regex) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    // Character offsets are counted incrementally between matches
+    let (mut byte, mut chars) = (0, 0);
+    for found in pattern.find_iter(text).filter(|m| !m.is_empty()) {
+        chars += text[byte..found.start()].chars().count();
+        let start = chars;
+        chars += found.as_str().chars().count();
+        byte = found.end();
+        matches.push((start, chars));
+    }
+    matches
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Escaped text with whitespace runs, newlines included, as single spaces
+fn collapse(chars: &[char]) -> String {
+    let mut out = String::with_capacity(chars.len());
+    for &c in chars {
+        if !c.is_whitespace() {
+            out.push(c);
+        } else if !out.ends_with(' ') {
+            out.push(' ');
+        }
+    }
+    escape_html(&out)
+}
+
+/// One line of context around `start..end`, with the match marked
+fn snippet(text: &str, start: usize, end: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let end = end.min(chars.len());
+    let start = start.min(end);
+    let from = start.saturating_sub(SNIPPET_CONTEXT);
+    let to = (end + SNIPPET_CONTEXT).min(chars.len());
+
+    format!(
+        "{}{}<mark>{}</mark>{}{}",
+        if from > 0 { "…" } else { "" },
+        collapse(&chars[from..start]),
+        collapse(&chars[start..end]),
+        collapse(&chars[end..to]),
+        if to < chars.len() { "…" } else { "" },
+    )
+}
+
+// ============================================================================
+// Sessions
+// ============================================================================
+
+/// Find state for a reader session id, or a PDF handle from `mmap_pdf_open`
+fn open_session<R: Runtime>(app: &AppHandle<R>, session_id: &str) -> Result<FindSession, String> {
+    let (book_id, source) = match app.state::<ReaderSessions>().book(session_id) {
+        Some((book_id, path)) => {
+            let hrefs = EpubArchive::open(&path)
+                .map_err(|e| e.to_string())?
+                .spine_paths();
+            (Some(book_id), Source::Epub { path, hrefs })
+        }
+        None => {
+            let handle: u32 = session_id
+                .parse()
+                .map_err(|_| format!("Unknown reader session: {}", session_id))?;
+            (None, Source::Pdf(app.state::<PdfHandles>().get(handle)?))
+        }
+    };
+
+    let count = match &source {
+        Source::Epub { hrefs, .. } => hrefs.len(),
+        Source::Pdf(file) => file.num_pages() as usize,
+    };
+    Ok(FindSession {
+        book_id,
+        source,
+        texts: vec![None; count],
+        content_hash: None,
+        hits: Vec::new(),
+        cursor: None,
+    })
+}
+
+impl FindSession {
+    /// Extract whichever of `chapters` aren't loaded yet
+    fn load(&mut self, chapters: &[usize]) {
+        let missing: Vec<usize> = chapters
+            .iter()
+            .copied()
+            .filter(|&chapter| self.texts[chapter].is_none())
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        match &self.source {
+            Source::Epub { path, .. } => {
+                // Contiguous runs extract in parallel
+                let mut runs: Vec<(usize, usize)> = Vec::new();
+                for &chapter in &missing {
+                    match runs.last_mut() {
+                        Some((_, end)) if *end == chapter => *end += 1,
+                        _ => runs.push((chapter, chapter + 1)),
+                    }
+                }
+                for (start, end) in runs {
+                    for extracted in text::extract_chapters(path, start..end) {
+                        self.texts[extracted.index] = Some(extracted.text.into());
+                    }
+                }
+            }
+            Source::Pdf(file) => {
+                for &page in &missing {
+                    let text = pdf::page_text(file, page as u32).unwrap_or_else(|e| {
+                        warn!("Skipping page {} in find: {}", page, e);
+                        String::new()
+                    });
+                    self.texts[page] = Some(text.into());
+                }
+            }
+        }
+    }
+
+    /// Chapters the search index lists for a whole-word phrase, when the
+    /// book has a current index. Only these are extracted and scanned, so
+    /// the first search of a long book doesn't wait on every chapter.
+    fn indexed_chapters(&mut self, db: &Database, phrase: &str) -> Option<Vec<usize>> {
+        let book_id = self.book_id?;
+        let Source::Epub { path, .. } = &self.source else {
+            return None;
+        };
+        if self.content_hash.is_none() {
+            self.content_hash = import::hash_path(Path::new(path))
+                .map_err(|e| warn!("Not using search index: {}", e))
+                .ok();
+        }
+        let hash = self.content_hash.as_deref()?;
+
+        db.with_conn(|conn| search::chapters_with_phrase(conn, book_id, hash, phrase))
+            .map_err(|e| warn!("Not using search index: {}", e))
+            .ok()
+            .flatten()
+            .map(|chapters| {
+                chapters
+                    .into_iter()
+                    .filter(|&chapter| chapter < self.texts.len())
+                    .collect()
+            })
+    }
+
+    fn search(&mut self, db: &Database, query: &str, options: FindOptions) -> Result<bool, String> {
+        let pattern = pattern(query, options)?;
+
+        let indexed = if options.whole_word && !options.regex {
+            self.indexed_chapters(db, query)
+        } else {
+            None
+        };
+        let used_index = indexed.is_some();
+        let chapters = indexed.unwrap_or_else(|| (0..self.texts.len()).collect());
+        self.load(&chapters);
+
+        self.hits = chapters
+            .iter()
+            .filter_map(|&chapter| Some((chapter, self.texts[chapter].clone()?)))
+            .flat_map(|(chapter, text)| {
+                matches_in(&text, &pattern)
+                    .into_iter()
+                    .map(move |(start, end)| Hit {
+                        chapter,
+                        start,
+                        end,
+                    })
+            })
+            .collect();
+        self.cursor = None;
+        Ok(used_index)
+    }
+
+    /// Snippets and locations for a run of matches. Only the matches
+    /// returned are described, a page at a time.
+    fn describe(&self, range: Range<usize>) -> Vec<FindMatch> {
+        let mut epub = None;
+        let mut documents: HashMap<usize, Option<String>> = HashMap::new();
+
+        range
+            .filter_map(|index| Some((index, *self.hits.get(index)?)))
+            .map(|(index, hit)| {
+                let text = self.texts[hit.chapter].as_deref().unwrap_or("");
+                let location = match &self.source {
+                    Source::Epub { path, hrefs } => {
+                        let href = &hrefs[hit.chapter];
+                        let markup = documents.entry(hit.chapter).or_insert_with(|| {
+                            if epub.is_none() {
+                                epub = EpubArchive::open(path)
+                                    .map_err(|e| warn!("Failed to locate matches: {}", e))
+                                    .ok();
+                            }
+                            epub.as_mut()?.read_string(href).ok()
+                        });
+                        markup.as_deref().and_then(|markup| {
+                            let word = cfi::words_before(text, hit.start);
+                            let cfi = cfi::from_word_index(markup, hit.chapter, word)?;
+                            Some(FindLocation::Epub {
+                                href: href.clone(),
+                                cfi: cfi.to_string(),
+                            })
+                        })
+                    }
+                    Source::Pdf(_) => Some(FindLocation::Pdf {
+                        page: hit.chapter as u32,
+                        offset: hit.start,
+                        length: hit.end - hit.start,
+                    }),
+                };
+
+                FindMatch {
+                    index,
+                    chapter: hit.chapter,
+                    start: hit.start,
+                    end: hit.end,
+                    snippet: snippet(text, hit.start, hit.end),
+                    location,
+                }
+            })
+            .collect()
+    }
+
+    /// Move the cursor one match forward or back, wrapping around
+    fn step(&mut self, forward: bool) -> Option<FindMatch> {
+        let total = self.hits.len();
+        if total == 0 {
+            return None;
+        }
+        let next = match (self.cursor, forward) {
+            (None, true) => 0,
+            (None, false) => total - 1,
+            (Some(current), true) => (current + 1) % total,
+            (Some(current), false) => (current + total - 1) % total,
+        };
+        self.cursor = Some(next);
+        self.describe(next..next + 1).pop()
+    }
+}
+
+fn with_session<T: Send + 'static>(
+    session: Arc<Mutex<FindSession>>,
+    f: impl FnOnce(&mut FindSession) -> T + Send + 'static,
+) -> impl std::future::Future<Output = Result<T, String>> {
+    let task = tauri::async_runtime::spawn_blocking(move || {
+        let mut session = session
+            .lock()
+            .map_err(|_| "Find lock poisoned".to_string())?;
+        Ok(f(&mut session))
+    });
+    async move { task.await.map_err(|e| format!("Find task failed: {}", e))? }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Search the whole open book rather than the loaded chapter.
+/// `session_id` is a reader session id, or the handle of a PDF opened with
+/// `mmap_pdf_open`. Replaces the session's previous matches and resets its
+/// cursor.
+#[tauri::command]
+pub async fn find_in_open_book<R: Runtime>(
+    app: AppHandle<R>,
+    finds: State<'_, FindSessions>,
+    session_id: String,
+    query: String,
+    options: Option<FindOptions>,
+) -> Result<FindResults, String> {
+    let options = options.unwrap_or_default();
+    let existing = finds
+        .0
+        .lock()
+        .map_err(|_| "Find lock poisoned".to_string())?
+        .get(&session_id)
+        .cloned();
+    let session = match existing {
+        Some(session) => session,
+        None => {
+            let worker_app = app.clone();
+            let id = session_id.clone();
+            let opened =
+                tauri::async_runtime::spawn_blocking(move || open_session(&worker_app, &id))
+                    .await
+                    .map_err(|e| format!("Find task failed: {}", e))??;
+            let session = Arc::new(Mutex::new(opened));
+            finds
+                .0
+                .lock()
+                .map_err(|_| "Find lock poisoned".to_string())?
+                .entry(session_id.clone())
+                .or_insert(session)
+                .clone()
+        }
+    };
+
+    with_session(session, move |session| {
+        if query.is_empty() {
+            session.hits.clear();
+            session.cursor = None;
+            return Ok(FindResults {
+                total: 0,
+                matches: Vec::new(),
+                used_index: false,
+            });
+        }
+
+        let used_index = session.search(&app.state::<Database>(), &query, options)?;
+        info!(
+            "Find in session {}: {} matches{}",
+            session_id,
+            session.hits.len(),
+            if used_index { " (indexed)" } else { "" }
+        );
+        Ok(FindResults {
+            total: session.hits.len(),
+            matches: session.describe(0..PAGE_SIZE),
+            used_index,
+        })
+    })
+    .await?
+}
+
+/// Matches `offset..offset + limit` of the session's last search
+#[tauri::command]
+pub async fn find_matches(
+    finds: State<'_, FindSessions>,
+    session_id: String,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<Vec<FindMatch>, String> {
+    let end = offset.saturating_add(limit.unwrap_or(PAGE_SIZE));
+    with_session(finds.get(&session_id)?, move |session| {
+        session.describe(offset..end)
+    })
+    .await
+}
+
+/// Move to the next match, wrapping to the first. `None` without matches.
+#[tauri::command]
+pub async fn find_next(
+    finds: State<'_, FindSessions>,
+    session_id: String,
+) -> Result<Option<FindMatch>, String> {
+    with_session(finds.get(&session_id)?, |session| session.step(true)).await
+}
+
+/// Move to the previous match, wrapping to the last
+#[tauri::command]
+pub async fn find_previous(
+    finds: State<'_, FindSessions>,
+    session_id: String,
+) -> Result<Option<FindMatch>, String> {
+    with_session(finds.get(&session_id)?, |session| session.step(false)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(text: &str, query: &str, options: FindOptions) -> Vec<(usize, usize)> {
+        matches_in(text, &pattern(query, options).unwrap())
+    }
+
+    #[test]
+    fn matches_case_insensitively_by_default() {
+        let text = "Café, café; CAFÉ";
+        assert_eq!(
+            find(text, "café", FindOptions::default()),
+            [(0, 4), (6, 10), (12, 16)]
+        );
+
+        let exact = FindOptions {
+            case_sensitive: true,
+            ..Default::default()
+        };
+        assert_eq!(find(text, "café", exact), [(6, 10)]);
+    }
+
+    #[test]
+    fn whole_word_skips_partial_words() {
+        let options = FindOptions {
+            whole_word: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            find("cat catalog a cat.", "cat", options),
+            [(0, 3), (14, 17)]
+        );
+    }
+
+    #[test]
+    fn queries_are_literal_unless_regex() {
+        assert_eq!(find("a.b axb", "a.b", FindOptions::default()), [(0, 3)]);
+
+        let regex = FindOptions {
+            regex: true,
+            ..Default::default()
+        };
+        assert_eq!(find("a.b axb", "a.b", regex), [(0, 3), (4, 7)]);
+        assert!(find("aaa", "x*", regex).is_empty());
+        assert!(pattern("(", regex).is_err());
+    }
+
+    #[test]
+    fn snippets_mark_the_match() {
+        assert_eq!(
+            snippet("one <two>\nthree", 4, 9),
+            "one <mark>&lt;two&gt;</mark> three"
+        );
+
+        let long = format!("{} needle {}", "a ".repeat(40), "b ".repeat(40));
+        let start = long.find("needle").unwrap();
+        let snippet = snippet(&long, start, start + 6);
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(snippet.contains(" <mark>needle</mark> "));
+    }
+}
@@ -0,0 +1,705 @@
+// Read Master Desktop - DjVu
+//
+// Scanned books in DjVu, read in place through DjVuLibre's command-line
+// tools: `djvused` for the page count, metadata, outline, and hidden text
+// layer, and `ddjvu` to render pages. The tools come with every DjVuLibre
+// install and are looked for next to the executable first, so they can be
+// bundled. Without them DjVu files are refused with a `missing_library`
+// error rather than failing halfway through an import.
+//
+// Rendered pages are kept in a bounded cache per (file, page, resolution),
+// so paging back and forth doesn't start `ddjvu` again.
+
+use std::ffi::OsStr;
+use std::fmt;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+
+use image::{DynamicImage, ImageFormat, RgbImage};
+use log::info;
+use lru::LruCache;
+use serde::Serialize;
+use tauri::State;
+
+use crate::epub::toc::TocEntry;
+use crate::error::AppError;
+use crate::images;
+
+const DJVUSED: &str = "djvused";
+const DDJVU: &str = "ddjvu";
+
+/// Rendered pages kept in memory
+const CACHE_CAPACITY: usize = 24;
+
+/// Resolution used when the reader doesn't ask for one
+const DEFAULT_DPI: u32 = 150;
+const MIN_DPI: u32 = 25;
+const MAX_DPI: u32 = 600;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DjvuError {
+    /// DjVuLibre's tools aren't installed
+    LibraryMissing,
+    /// A tool ran but failed, usually on a damaged file
+    Tool { tool: &'static str, message: String },
+}
+
+impl fmt::Display for DjvuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LibraryMissing => f.write_str(
+                "DjVu is supported, but DjVuLibre isn't installed. \
+                 Install DjVuLibre to open DjVu books.",
+            ),
+            Self::Tool { tool, message } => write!(f, "{} failed: {}", tool, message),
+        }
+    }
+}
+
+impl From<DjvuError> for String {
+    fn from(e: DjvuError) -> Self {
+        e.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DjvuMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub year: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DjvuDocument {
+    pub page_count: u32,
+    pub metadata: DjvuMetadata,
+    /// Entries point at pages through `fragment` (see `outline`)
+    pub outline: Vec<TocEntry>,
+}
+
+/// One word of the hidden text layer
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextWord {
+    pub text: String,
+    /// Bounding box in page pixels, from the top-left corner
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageText {
+    /// Page size in pixels at its native resolution
+    pub width: u32,
+    pub height: u32,
+    /// Plain text, one line per text line, for search
+    pub text: String,
+    /// Words with positions, for highlights
+    pub words: Vec<TextWord>,
+}
+
+/// Managed cache of rendered pages, PNG encoded
+pub struct DjvuPages(Mutex<LruCache<(String, u32, u32), Arc<Vec<u8>>>>);
+
+impl Default for DjvuPages {
+    fn default() -> Self {
+        Self(Mutex::new(LruCache::new(
+            NonZeroUsize::new(CACHE_CAPACITY).expect("cache capacity is non-zero"),
+        )))
+    }
+}
+
+// ============================================================================
+// Tools
+// ============================================================================
+
+/// A bundled tool next to the executable, else whatever is on the PATH
+fn tool(name: &str) -> PathBuf {
+    let file = if cfg!(windows) {
+        format!("{}.exe", name)
+    } else {
+        name.to_string()
+    };
+
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(&file)))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(file))
+}
+
+fn run(name: &'static str, args: &[&OsStr]) -> Result<Vec<u8>, DjvuError> {
+    let output = Command::new(tool(name))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => DjvuError::LibraryMissing,
+            _ => DjvuError::Tool {
+                tool: name,
+                message: e.to_string(),
+            },
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(DjvuError::Tool {
+            tool: name,
+            message: if stderr.is_empty() {
+                format!("exited with {}", output.status)
+            } else {
+                stderr
+            },
+        });
+    }
+    Ok(output.stdout)
+}
+
+/// Run a `djvused` script against `path`, with UTF-8 output
+fn djvused(path: &Path, script: &str) -> Result<String, DjvuError> {
+    let stdout = run(
+        DJVUSED,
+        &[
+            OsStr::new("-u"),
+            OsStr::new("-e"),
+            OsStr::new(script),
+            path.as_os_str(),
+        ],
+    )?;
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+/// Whether leading bytes are a DjVu file (single or multi-page)
+pub fn is_djvu(header: &[u8]) -> bool {
+    header.len() >= 16
+        && header.starts_with(b"AT&TFORM")
+        && matches!(&header[12..16], b"DJVU" | b"DJVM")
+}
+
+// ============================================================================
+// S-expressions
+// ============================================================================
+
+/// `djvused` prints text layers, outlines, and metadata as Lisp-style
+/// s-expressions
+#[derive(Debug, Clone, PartialEq)]
+enum Sexp {
+    Atom(String),
+    Str(String),
+    List(Vec<Sexp>),
+}
+
+impl Sexp {
+    fn atom(&self) -> Option<&str> {
+        match self {
+            Self::Atom(atom) => Some(atom),
+            _ => None,
+        }
+    }
+
+    fn number(&self) -> Option<i64> {
+        self.atom()?.parse().ok()
+    }
+
+    fn string(&self) -> Option<&str> {
+        match self {
+            Self::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn parse_next(&mut self) -> Option<Sexp> {
+        self.skip_whitespace();
+        match *self.bytes.get(self.pos)? {
+            b'(' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.bytes.get(self.pos) {
+                        None => break,
+                        Some(b')') => {
+                            self.pos += 1;
+                            break;
+                        }
+                        Some(_) => items.extend(self.parse_next()),
+                    }
+                }
+                Some(Sexp::List(items))
+            }
+            // An unbalanced close paren; skip it
+            b')' => {
+                self.pos += 1;
+                self.parse_next()
+            }
+            b'"' => Some(Sexp::Str(self.string())),
+            _ => {
+                let start = self.pos;
+                while self
+                    .bytes
+                    .get(self.pos)
+                    .is_some_and(|&b| !b.is_ascii_whitespace() && !b"()\"".contains(&b))
+                {
+                    self.pos += 1;
+                }
+                Some(Sexp::Atom(
+                    String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned(),
+                ))
+            }
+        }
+    }
+
+    /// A quoted string, with C escapes and octal bytes decoded
+    fn string(&mut self) -> String {
+        self.pos += 1;
+        let mut out = Vec::new();
+        while let Some(&b) = self.bytes.get(self.pos) {
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escaped) = self.bytes.get(self.pos) else {
+                        break;
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => out.push(b'\n'),
+                        b't' => out.push(b'\t'),
+                        b'r' => out.push(b'\r'),
+                        b'0'..=b'7' => {
+                            let mut value = u32::from(escaped - b'0');
+                            for _ in 0..2 {
+                                match self.bytes.get(self.pos) {
+                                    Some(&digit @ b'0'..=b'7') => {
+                                        value = value * 8 + u32::from(digit - b'0');
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            out.push(value as u8);
+                        }
+                        other => out.push(other),
+                    }
+                }
+                b => out.push(b),
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+}
+
+fn parse_sexps(input: &str) -> Vec<Sexp> {
+    let mut parser = Parser {
+        bytes: input.as_bytes(),
+        pos: 0,
+    };
+    std::iter::from_fn(|| parser.parse_next()).collect()
+}
+
+// ============================================================================
+// Parsing
+// ============================================================================
+
+/// `print-meta` output: one `key "value"` pair per line
+fn parse_metadata(output: &str) -> DjvuMetadata {
+    let mut meta = DjvuMetadata::default();
+    for line in output.lines() {
+        let items = parse_sexps(line);
+        let (Some(key), Some(value)) = (
+            items.first().and_then(Sexp::atom),
+            items.get(1).and_then(Sexp::string),
+        ) else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        let slot = match key.to_ascii_lowercase().as_str() {
+            "title" => &mut meta.title,
+            "author" => &mut meta.author,
+            "year" => &mut meta.year,
+            _ => continue,
+        };
+        slot.get_or_insert_with(|| value.to_string());
+    }
+    meta
+}
+
+/// Outline link target: `#12` is the twelfth page, anything else after the
+/// `#` a page id. Numbered pages become 0-based indexes.
+fn outline_target(url: &str) -> Option<String> {
+    let target = url.strip_prefix('#')?;
+    match target.parse::<u32>() {
+        Ok(page) => Some(page.saturating_sub(1).to_string()),
+        Err(_) => Some(target.to_string()).filter(|t| !t.is_empty()),
+    }
+}
+
+fn outline_entries(items: &[Sexp]) -> Vec<TocEntry> {
+    items
+        .iter()
+        .filter_map(|item| {
+            let Sexp::List(parts) = item else {
+                return None;
+            };
+            let label = parts.first()?.string()?.trim().to_string();
+            Some(TocEntry {
+                label,
+                // Pages aren't files in an archive
+                href: None,
+                fragment: parts.get(1).and_then(Sexp::string).and_then(outline_target),
+                children: outline_entries(parts.get(2..).unwrap_or_default()),
+            })
+        })
+        .collect()
+}
+
+/// `print-outline` output: `(bookmarks ("Title" "#page" children...) ...)`
+fn parse_outline(output: &str) -> Vec<TocEntry> {
+    parse_sexps(output)
+        .iter()
+        .find_map(|sexp| match sexp {
+            Sexp::List(items) if items.first().and_then(Sexp::atom) == Some("bookmarks") => {
+                Some(outline_entries(&items[1..]))
+            }
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+/// Zones that end a line of plain text
+const LINE_ZONES: &[&str] = &["line", "para", "region", "column"];
+
+fn collect_zone(zone: &[Sexp], page_height: i64, page: &mut PageText) {
+    let Some(kind) = zone.first().and_then(Sexp::atom) else {
+        return;
+    };
+    let coords: Vec<i64> = zone[1..].iter().take(4).filter_map(Sexp::number).collect();
+    let [x_min, y_min, x_max, y_max] = coords[..] else {
+        return;
+    };
+
+    match zone.get(5) {
+        // Text at this level: the finest zone the layer goes down to
+        Some(Sexp::Str(text)) => {
+            let text = text.trim();
+            if !text.is_empty() {
+                page.words.push(TextWord {
+                    text: text.to_string(),
+                    x: x_min.max(0) as u32,
+                    // DjVu measures from the bottom-left corner
+                    y: (page_height - y_max).max(0) as u32,
+                    width: (x_max - x_min).max(0) as u32,
+                    height: (y_max - y_min).max(0) as u32,
+                });
+                if !page.text.is_empty() && !page.text.ends_with('\n') {
+                    page.text.push(' ');
+                }
+                page.text.push_str(text);
+            }
+        }
+        _ => {
+            for child in &zone[5..] {
+                if let Sexp::List(child) = child {
+                    collect_zone(child, page_height, page);
+                }
+            }
+        }
+    }
+
+    if LINE_ZONES.contains(&kind) && !page.text.is_empty() && !page.text.ends_with('\n') {
+        page.text.push('\n');
+    }
+}
+
+/// `print-txt` output: nested `(kind xmin ymin xmax ymax children-or-text)`
+/// zones, with `page` at the top
+fn parse_page_text(output: &str) -> PageText {
+    let mut page = PageText::default();
+    let Some(Sexp::List(zone)) = parse_sexps(output).into_iter().next() else {
+        return page;
+    };
+    let coords: Vec<i64> = zone
+        .iter()
+        .skip(1)
+        .take(4)
+        .filter_map(Sexp::number)
+        .collect();
+    if let [_, _, width, height] = coords[..] {
+        page.width = width.max(0) as u32;
+        page.height = height.max(0) as u32;
+    }
+
+    collect_zone(&zone, i64::from(page.height), &mut page);
+    page.text.truncate(page.text.trim_end().len());
+    page
+}
+
+/// Decode a binary PPM (`P6`, 8 bits per channel) as `ddjvu` writes it
+fn parse_ppm(data: &[u8]) -> Result<RgbImage, String> {
+    let invalid = || "Invalid page image from ddjvu".to_string();
+    let mut pos = 0;
+    let mut fields = Vec::with_capacity(4);
+    while fields.len() < 4 {
+        // Whitespace and comments between header fields
+        loop {
+            match data.get(pos) {
+                Some(b) if b.is_ascii_whitespace() => pos += 1,
+                Some(b'#') => {
+                    while data.get(pos).is_some_and(|&b| b != b'\n') {
+                        pos += 1;
+                    }
+                }
+                Some(_) => break,
+                None => return Err(invalid()),
+            }
+        }
+        let start = pos;
+        while data.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        fields.push(std::str::from_utf8(&data[start..pos]).map_err(|_| invalid())?);
+    }
+    // Exactly one whitespace byte before the pixels
+    pos += 1;
+
+    let number = |field: &str| field.parse::<u32>().map_err(|_| invalid());
+    if fields[0] != "P6" || number(fields[3])? != 255 {
+        return Err("Unsupported page image from ddjvu".to_string());
+    }
+    let (width, height) = (number(fields[1])?, number(fields[2])?);
+    let len = width as usize * height as usize * 3;
+    let pixels = data.get(pos..pos + len).ok_or_else(invalid)?;
+    RgbImage::from_raw(width, height, pixels.to_vec()).ok_or_else(invalid)
+}
+
+// ============================================================================
+// Documents
+// ============================================================================
+
+pub fn page_count(path: &Path) -> Result<u32, DjvuError> {
+    let output = djvused(path, "n")?;
+    output.trim().parse().map_err(|_| DjvuError::Tool {
+        tool: DJVUSED,
+        message: format!("Unexpected page count: {}", output.trim()),
+    })
+}
+
+pub fn read_metadata(path: &Path) -> Result<DjvuMetadata, DjvuError> {
+    djvused(path, "print-meta").map(|output| parse_metadata(&output))
+}
+
+/// Outline mapped onto the common TOC structure. Each entry's `fragment`
+/// is the 0-based page index it points to (or a page id).
+pub fn outline(path: &Path) -> Result<Vec<TocEntry>, DjvuError> {
+    djvused(path, "print-outline").map(|output| parse_outline(&output))
+}
+
+/// Hidden text of a page (0-based); empty for pages without a text layer
+pub fn page_text(path: &Path, page: u32) -> Result<PageText, DjvuError> {
+    djvused(path, &format!("select {}; print-txt", page + 1)).map(|output| parse_page_text(&output))
+}
+
+/// Render a page (0-based) at `dpi` as PNG
+pub fn render_page(path: &Path, page: u32, dpi: u32) -> Result<Vec<u8>, String> {
+    let ppm = run(
+        DDJVU,
+        &[
+            OsStr::new("-format=ppm"),
+            OsStr::new(&format!("-page={}", page + 1)),
+            OsStr::new(&format!("-scale={}", dpi)),
+            path.as_os_str(),
+            OsStr::new("-"),
+        ],
+    )?;
+    let image = DynamicImage::from(parse_ppm(&ppm)?);
+    images::encode(&image, ImageFormat::Png)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+async fn blocking<T: Send + 'static>(
+    task: impl FnOnce() -> Result<T, AppError> + Send + 'static,
+) -> Result<T, AppError> {
+    tauri::async_runtime::spawn_blocking(task)
+        .await
+        .map_err(|e| AppError::from(format!("DjVu task failed: {}", e)))?
+}
+
+/// Page count, metadata, and outline of a DjVu file
+#[tauri::command]
+pub async fn djvu_open(path: String) -> Result<DjvuDocument, AppError> {
+    blocking(move || {
+        let path = Path::new(&path);
+        let document = DjvuDocument {
+            page_count: page_count(path)?,
+            metadata: read_metadata(path)?,
+            outline: outline(path)?,
+        };
+        info!(
+            "Opened DjVu {} ({} pages)",
+            path.display(),
+            document.page_count
+        );
+        Ok(document)
+    })
+    .await
+}
+
+/// A page (0-based) rendered as PNG at `dpi` (default 150)
+#[tauri::command]
+pub async fn djvu_render_page(
+    pages: State<'_, DjvuPages>,
+    path: String,
+    page: u32,
+    dpi: Option<u32>,
+) -> Result<Vec<u8>, AppError> {
+    let dpi = dpi.unwrap_or(DEFAULT_DPI).clamp(MIN_DPI, MAX_DPI);
+    let key = (path.clone(), page, dpi);
+    if let Some(png) = pages
+        .0
+        .lock()
+        .ok()
+        .and_then(|mut cache| cache.get(&key).cloned())
+    {
+        return Ok(png.to_vec());
+    }
+
+    let png = blocking(move || Ok(render_page(Path::new(&path), page, dpi)?)).await?;
+    if let Ok(mut cache) = pages.0.lock() {
+        cache.put(key, Arc::new(png.clone()));
+    }
+    Ok(png)
+}
+
+/// The hidden text layer of a page (0-based), with word positions
+#[tauri::command]
+pub async fn djvu_page_text(path: String, page: u32) -> Result<PageText, AppError> {
+    blocking(move || Ok(page_text(Path::new(&path), page)?)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_djvu_headers() {
+        assert!(is_djvu(b"AT&TFORM\x00\x00\x10\x00DJVMDIRM"));
+        assert!(is_djvu(b"AT&TFORM\x00\x00\x10\x00DJVUINFO"));
+        assert!(!is_djvu(b"AT&TFORM\x00\x00\x10\x00AIFF"));
+        assert!(!is_djvu(b"%PDF-1.7"));
+    }
+
+    #[test]
+    fn parses_metadata() {
+        let meta = parse_metadata(
+            "Title\t\"Linear Algebra\"\nauthor\t\"Halmos, P. R.\"\nyear\t\"1958\"\nNote\t\"x\"\n",
+        );
+        assert_eq!(meta.title.as_deref(), Some("Linear Algebra"));
+        assert_eq!(meta.author.as_deref(), Some("Halmos, P. R."));
+        assert_eq!(meta.year.as_deref(), Some("1958"));
+    }
+
+    #[test]
+    fn decodes_escaped_strings() {
+        assert_eq!(
+            parse_sexps(r#"("caf\303\251 \"ok\"\n")"#),
+            [Sexp::List(vec![Sexp::Str(
+                "caf\u{e9} \"ok\"\n".to_string()
+            )])]
+        );
+    }
+
+    #[test]
+    fn maps_outline_to_toc_entries() {
+        let toc = parse_outline(
+            r##"(bookmarks
+                 ("Preface" "#3")
+                 ("Chapter 1" "#12"
+                   ("1.1 Vectors" "#13"))
+                 ("Index" "#idx.djvu"))"##,
+        );
+        assert_eq!(toc.len(), 3);
+        assert_eq!(toc[0].label, "Preface");
+        assert_eq!(toc[0].fragment.as_deref(), Some("2"));
+        assert_eq!(toc[1].children[0].label, "1.1 Vectors");
+        assert_eq!(toc[1].children[0].fragment.as_deref(), Some("12"));
+        assert_eq!(toc[2].fragment.as_deref(), Some("idx.djvu"));
+        assert!(toc.iter().all(|entry| entry.href.is_none()));
+    }
+
+    #[test]
+    fn extracts_words_with_top_left_boxes() {
+        let page = parse_page_text(
+            r#"(page 0 0 1000 2000
+                 (line 100 1800 400 1850
+                   (word 100 1800 200 1850 "Hello")
+                   (word 250 1800 400 1850 "world"))
+                 (line 100 1700 300 1750
+                   (word 100 1700 300 1750 "again")))"#,
+        );
+        assert_eq!((page.width, page.height), (1000, 2000));
+        assert_eq!(page.text, "Hello world\nagain");
+        assert_eq!(
+            page.words[0],
+            TextWord {
+                text: "Hello".to_string(),
+                x: 100,
+                y: 150,
+                width: 100,
+                height: 50,
+            }
+        );
+        assert_eq!(page.words.len(), 3);
+    }
+
+    #[test]
+    fn empty_text_layer_has_no_words() {
+        assert_eq!(parse_page_text(""), PageText::default());
+    }
+
+    #[test]
+    fn decodes_ppm() {
+        let mut data = b"P6\n# ddjvu\n2 1\n255\n".to_vec();
+        data.extend_from_slice(&[255, 0, 0, 0, 0, 255]);
+        let image = parse_ppm(&data).unwrap();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(1, 0).0, [0, 0, 255]);
+
+        assert!(parse_ppm(b"P6\n2 1\n255\n\x00").is_err());
+        assert!(parse_ppm(b"P5\n1 1\n255\n\x00").is_err());
+    }
+}
@@ -0,0 +1,717 @@
+// Read Master Desktop - Web Articles
+//
+// The readable article in a web page, for the URL and clipboard importers.
+// A small readability pass scores the page's blocks by how much prose they
+// hold (long paragraphs, commas, few links) and by class and id hints
+// ("article", "content" against "comment", "sidebar"), keeps the best one,
+// and rewrites it as clean XHTML: scripts, navigation, forms, and other
+// boilerplate are dropped and only a small set of tags survive.
+//
+// Images are fetched by the caller. The extracted markup refers to them by
+// index, and the placeholders are swapped for the stored images' hrefs (or
+// dropped) when the chapter is written.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+use log::warn;
+use scraper::{ElementRef, Html, Node, Selector};
+use url::Url;
+
+use super::{escape, BookInfo, EpubWriter};
+
+/// Article text shorter than this counts as no article
+const MIN_ARTICLE_CHARS: usize = 250;
+
+/// A page marked as subscriber-only whose article is shorter than this is
+/// a teaser, not the article
+const PAYWALL_TEASER_CHARS: usize = 2000;
+
+/// Elements dropped along with everything inside them
+const DROP_TAGS: &[&str] = &[
+    "script", "style", "noscript", "template", "nav", "aside", "footer", "header", "form",
+    "button", "input", "select", "textarea", "iframe", "object", "embed", "canvas", "svg",
+    "dialog", "menu",
+];
+
+/// Elements kept as they are; anything else is replaced by its contents
+const KEEP_TAGS: &[&str] = &[
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "blockquote",
+    "pre",
+    "code",
+    "em",
+    "strong",
+    "i",
+    "b",
+    "u",
+    "s",
+    "sub",
+    "sup",
+    "figure",
+    "figcaption",
+    "table",
+    "thead",
+    "tbody",
+    "tr",
+    "th",
+    "td",
+    "dl",
+    "dt",
+    "dd",
+];
+
+/// Class and id fragments of boilerplate blocks
+const NEGATIVE_HINTS: &[&str] = &[
+    "comment",
+    "sidebar",
+    "footer",
+    "masthead",
+    "menu",
+    "share",
+    "social",
+    "related",
+    "promo",
+    "advert",
+    "sponsor",
+    "newsletter",
+    "subscribe",
+    "cookie",
+    "banner",
+    "popup",
+    "modal",
+    "breadcrumb",
+    "widget",
+    "byline",
+];
+
+/// Class and id fragments of article bodies
+const POSITIVE_HINTS: &[&str] = &[
+    "article", "content", "entry", "main", "post", "story", "text",
+];
+
+/// Image placeholder in extracted markup, followed by the image's index
+const IMAGE_MARKER: &str = "rm-image:";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArticleError {
+    /// The page is marked subscriber-only and only a teaser came through
+    Paywalled,
+    /// Nothing article-like was found, e.g. a page built by scripts
+    Empty,
+}
+
+impl fmt::Display for ArticleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Paywalled => f.write_str("This article is behind a paywall"),
+            Self::Empty => f.write_str("No article text found on this page"),
+        }
+    }
+}
+
+impl From<ArticleError> for String {
+    fn from(e: ArticleError) -> Self {
+        e.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Article {
+    pub title: String,
+    pub author: Option<String>,
+    pub site_name: Option<String>,
+    /// Absolute URLs of the article's images, by placeholder index
+    pub images: Vec<Url>,
+    /// Cleaned XHTML with image placeholders
+    content: String,
+}
+
+/// A fetched image, typed by its contents
+#[derive(Debug, Clone)]
+pub struct ImageData {
+    pub extension: &'static str,
+    pub data: Vec<u8>,
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("valid selector")
+}
+
+fn clean_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn element_text(element: &ElementRef) -> String {
+    clean_text(&element.text().collect::<String>())
+}
+
+/// Lowercase class and id, for matching hints
+fn hints(element: &ElementRef) -> String {
+    let value = element.value();
+    format!(
+        "{} {}",
+        value.attr("class").unwrap_or_default(),
+        value.id().unwrap_or_default()
+    )
+    .to_lowercase()
+}
+
+fn has_hint(hints: &str, fragments: &[&str]) -> bool {
+    fragments.iter().any(|fragment| hints.contains(fragment))
+}
+
+/// Whether a block is boilerplate to skip, by tag or class and id
+fn is_unlikely(element: &ElementRef) -> bool {
+    let hints = hints(element);
+    DROP_TAGS.contains(&element.value().name())
+        || (has_hint(&hints, NEGATIVE_HINTS) && !has_hint(&hints, POSITIVE_HINTS))
+}
+
+fn base_score(element: &ElementRef) -> f64 {
+    let hints = hints(element);
+    let mut score = match element.value().name() {
+        "article" => 10.0,
+        "div" | "section" | "main" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "ul" | "ol" | "form" => -3.0,
+        _ => 0.0,
+    };
+    if has_hint(&hints, NEGATIVE_HINTS) {
+        score -= 25.0;
+    }
+    if has_hint(&hints, POSITIVE_HINTS) {
+        score += 25.0;
+    }
+    score
+}
+
+/// Share of an element's text that sits in links
+fn link_density(element: &ElementRef) -> f64 {
+    let total = element_text(element).chars().count();
+    if total == 0 {
+        return 1.0;
+    }
+    let linked: usize = element
+        .select(&selector("a"))
+        .map(|link| element_text(&link).chars().count())
+        .sum();
+    linked as f64 / total as f64
+}
+
+/// Content of the first matching `<meta>` (by `property` or `name`)
+fn meta(doc: &Html, keys: &[&str]) -> Option<String> {
+    let metas = selector("meta");
+    keys.iter().find_map(|key| {
+        doc.select(&metas).find_map(|element| {
+            let value = element.value();
+            value
+                .attr("property")
+                .or_else(|| value.attr("name"))
+                .filter(|name| name.eq_ignore_ascii_case(key))?;
+            value
+                .attr("content")
+                .map(clean_text)
+                .filter(|content| !content.is_empty())
+        })
+    })
+}
+
+/// Whether structured data marks the page as not free to read
+fn marked_paywalled(doc: &Html) -> bool {
+    doc.select(&selector(r#"script[type="application/ld+json"]"#))
+        .any(|script| {
+            let json: String = script
+                .text()
+                .collect::<String>()
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<String>()
+                .to_lowercase();
+            json.contains(r#""isaccessibleforfree":false"#)
+                || json.contains(r#""isaccessibleforfree":"false""#)
+        })
+}
+
+/// `https` or `http` URL of `href`, resolved against the page
+fn resolve(base: Option<&Url>, href: &str) -> Option<Url> {
+    let href = href.trim();
+    let url = match base {
+        Some(base) => base.join(href).ok()?,
+        None => Url::parse(href).ok()?,
+    };
+    matches!(url.scheme(), "https" | "http").then_some(url)
+}
+
+/// File extension of an image, from its leading bytes
+pub fn image_extension(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG") {
+        Some("png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("jpg")
+    } else if data.starts_with(b"GIF8") {
+        Some("gif")
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some("webp")
+    } else {
+        let head = String::from_utf8_lossy(&data[..data.len().min(256)]).to_lowercase();
+        head.contains("<svg").then_some("svg")
+    }
+}
+
+// ============================================================================
+// Metadata
+// ============================================================================
+
+fn page_title(doc: &Html, site_name: Option<&str>) -> String {
+    let title = meta(doc, &["og:title", "twitter:title"])
+        .or_else(|| {
+            doc.select(&selector("title"))
+                .next()
+                .map(|title| element_text(&title))
+        })
+        .or_else(|| {
+            doc.select(&selector("h1"))
+                .next()
+                .map(|h1| element_text(&h1))
+        })
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| "Untitled".to_string());
+
+    // "Headline | Site" and similar
+    if let Some(site) = site_name {
+        for separator in [" | ", " - ", " \u{2013} ", " \u{2014} ", " \u{b7} "] {
+            if let Some(headline) = title.strip_suffix(&format!("{}{}", separator, site)) {
+                return headline.trim().to_string();
+            }
+        }
+    }
+    title
+}
+
+fn page_author(doc: &Html) -> Option<String> {
+    let author = meta(
+        doc,
+        &["author", "article:author", "parsely-author", "dc.creator"],
+    )
+    // article:author is often a profile URL
+    .filter(|author| !author.starts_with("http"))
+    .or_else(|| {
+        doc.select(&selector(
+            r#"[rel="author"], [itemprop="author"], .byline, .author"#,
+        ))
+        .map(|element| element_text(&element))
+        .find(|text| !text.is_empty() && text.chars().count() <= 80)
+    })?;
+    let author = author
+        .strip_prefix("By ")
+        .or_else(|| author.strip_prefix("by "))
+        .unwrap_or(&author);
+    Some(author.trim().to_string())
+}
+
+// ============================================================================
+// Extraction
+// ============================================================================
+
+/// The block holding the article: the best scored parent of prose blocks
+fn best_candidate(doc: &Html) -> Option<ElementRef<'_>> {
+    let mut scores = HashMap::new();
+
+    for block in doc.select(&selector("p, pre, td, blockquote")) {
+        if block
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .any(|ancestor| is_unlikely(&ancestor))
+        {
+            continue;
+        }
+        let text = element_text(&block);
+        let len = text.chars().count();
+        if len < 25 {
+            continue;
+        }
+
+        let score = 1.0 + text.matches(',').count() as f64 + (len as f64 / 100.0).min(3.0);
+        // Parents get the full score, grandparents half
+        for (depth, ancestor) in block
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take(2)
+            .enumerate()
+        {
+            *scores
+                .entry(ancestor.id())
+                .or_insert_with(|| base_score(&ancestor)) += score / (depth + 1) as f64;
+        }
+    }
+
+    scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let element = doc.tree.get(id).and_then(ElementRef::wrap)?;
+            Some((element, score * (1.0 - link_density(&element))))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(element, _)| element)
+        .or_else(|| doc.select(&selector("article")).next())
+}
+
+/// Writes the kept part of an element tree as XHTML
+struct Cleaner<'a> {
+    base: Option<&'a Url>,
+    title: &'a str,
+    out: String,
+    images: Vec<Url>,
+    /// Characters of text written, for the emptiness check
+    text_chars: usize,
+    pre_depth: usize,
+    title_dropped: bool,
+}
+
+impl Cleaner<'_> {
+    fn children(&mut self, element: ElementRef) {
+        for child in element.children() {
+            if let Some(element) = ElementRef::wrap(child) {
+                self.element(element);
+            } else if let Node::Text(text) = child.value() {
+                self.text(text);
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        self.text_chars += text.chars().filter(|c| !c.is_whitespace()).count();
+        if self.pre_depth > 0 {
+            self.out.push_str(&escape(text));
+            return;
+        }
+        let mut collapsed = clean_text(text);
+        // Keep the spaces around inline elements
+        if text.starts_with(char::is_whitespace) && !collapsed.is_empty() {
+            collapsed.insert(0, ' ');
+        }
+        if text.ends_with(char::is_whitespace) {
+            collapsed.push(' ');
+        }
+        self.out.push_str(&escape(&collapsed));
+    }
+
+    fn element(&mut self, element: ElementRef) {
+        let name = element.value().name();
+        if is_unlikely(&element) {
+            return;
+        }
+
+        match name {
+            "br" | "hr" => self.out.push_str(&format!("<{}/>", name)),
+            "img" => self.image(element),
+            "a" => {
+                let href = element
+                    .value()
+                    .attr("href")
+                    .and_then(|href| resolve(self.base, href));
+                match href {
+                    Some(href) => {
+                        self.out
+                            .push_str(&format!("<a href=\"{}\">", escape(href.as_str())));
+                        self.children(element);
+                        self.out.push_str("</a>");
+                    }
+                    None => self.children(element),
+                }
+            }
+            // The headline is written above the article
+            "h1" if !self.title_dropped && element_text(&element) == self.title => {
+                self.title_dropped = true;
+            }
+            "p" if element_text(&element).is_empty()
+                && element.select(&selector("img")).next().is_none() => {}
+            name if KEEP_TAGS.contains(&name) => {
+                self.out.push_str(&format!("<{}>", name));
+                if name == "pre" {
+                    self.pre_depth += 1;
+                }
+                self.children(element);
+                if name == "pre" {
+                    self.pre_depth -= 1;
+                }
+                self.out.push_str(&format!("</{}>", name));
+            }
+            _ => self.children(element),
+        }
+    }
+
+    fn image(&mut self, element: ElementRef) {
+        let value = element.value();
+        // Lazy-loading pages keep the real source in a data attribute
+        let src = ["data-src", "data-original", "src"]
+            .iter()
+            .filter_map(|attr| value.attr(attr))
+            .find(|src| !src.is_empty() && !src.starts_with("data:"))
+            .or_else(|| {
+                value
+                    .attr("srcset")
+                    .and_then(|srcset| srcset.split_whitespace().next())
+            });
+        let Some(url) = src.and_then(|src| resolve(self.base, src)) else {
+            return;
+        };
+
+        self.out.push_str(&format!(
+            "<img src=\"{}{}\" alt=\"{}\"/>",
+            IMAGE_MARKER,
+            self.images.len(),
+            escape(value.attr("alt").unwrap_or_default())
+        ));
+        self.images.push(url);
+    }
+}
+
+/// Extract the article from a page. `base` resolves relative links and
+/// images; without it only absolute ones are kept.
+pub fn extract(html: &str, base: Option<&Url>) -> Result<Article, ArticleError> {
+    let doc = Html::parse_document(html);
+
+    let site_name = meta(&doc, &["og:site_name", "application-name"]);
+    let title = page_title(&doc, site_name.as_deref());
+    let author = page_author(&doc);
+
+    let root = best_candidate(&doc).ok_or(ArticleError::Empty)?;
+    let mut cleaner = Cleaner {
+        base,
+        title: &title,
+        out: String::new(),
+        images: Vec::new(),
+        text_chars: 0,
+        pre_depth: 0,
+        title_dropped: false,
+    };
+    // The root was picked for its content, whatever its class says
+    cleaner.children(root);
+
+    let (text_chars, content, images) = (cleaner.text_chars, cleaner.out, cleaner.images);
+    if text_chars < PAYWALL_TEASER_CHARS && marked_paywalled(&doc) {
+        return Err(ArticleError::Paywalled);
+    }
+    if text_chars < MIN_ARTICLE_CHARS {
+        return Err(ArticleError::Empty);
+    }
+
+    Ok(Article {
+        title,
+        author,
+        site_name,
+        images,
+        content: content.trim().to_string(),
+    })
+}
+
+impl Article {
+    /// The article's XHTML, with each image placeholder replaced by the
+    /// stored image's href, or removed where there is none
+    pub fn xhtml(&self, hrefs: &[Option<String>]) -> String {
+        let open = format!("<img src=\"{}", IMAGE_MARKER);
+        let mut out = String::with_capacity(self.content.len());
+        let mut rest = self.content.as_str();
+
+        while let Some(start) = rest.find(&open) {
+            out.push_str(&rest[..start]);
+            let tag = &rest[start..];
+            let end = tag.find("/>").map_or(tag.len(), |i| i + 2);
+            let index = tag[open.len()..]
+                .split('"')
+                .next()
+                .and_then(|index| index.parse::<usize>().ok());
+            if let Some((index, href)) = index.and_then(|i| Some((i, hrefs.get(i)?.as_deref()?))) {
+                out.push_str(&tag[..end].replacen(
+                    &format!("{}{}", IMAGE_MARKER, index),
+                    &escape(href),
+                    1,
+                ));
+            }
+            rest = &tag[end..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// Headline, byline, and article, as one chapter
+    fn chapter(&self, hrefs: &[Option<String>], info: &BookInfo) -> String {
+        let byline: Vec<&str> = [
+            self.author.as_deref(),
+            info.publisher.as_deref(),
+            info.date.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        let mut html = format!("<h1>{}</h1>\n", escape(&self.title));
+        if !byline.is_empty() {
+            html.push_str(&format!(
+                "<p class=\"byline\">{}</p>\n",
+                escape(&byline.join(" \u{b7} "))
+            ));
+        }
+        html.push_str(&self.xhtml(hrefs));
+        if let Some(source) = &info.source {
+            html.push_str(&format!(
+                "\n<p class=\"source\"><a href=\"{0}\">{0}</a></p>",
+                escape(source)
+            ));
+        }
+        html
+    }
+}
+
+/// Write `article` as a single-chapter EPUB. `images` holds the fetched
+/// image for each of `article.images`, `None` where fetching failed.
+pub fn write_epub(
+    path: &Path,
+    article: &Article,
+    images: &[Option<ImageData>],
+    info: &BookInfo,
+    identifier: &str,
+) -> Result<(), String> {
+    let mut writer = EpubWriter::create(path)?;
+    // Images go first: the archive takes one entry at a time
+    let hrefs: Vec<Option<String>> = images
+        .iter()
+        .enumerate()
+        .map(|(i, image)| {
+            let image = image.as_ref()?;
+            writer
+                .add_image(
+                    &format!("image-{:03}.{}", i + 1, image.extension),
+                    &image.data,
+                )
+                .map_err(|e| warn!("Skipping article image {}: {}", i + 1, e))
+                .ok()
+        })
+        .collect();
+
+    writer.begin_chapter(&article.title, true)?;
+    writer.write_html(&article.chapter(&hrefs, info))?;
+    writer.finish(info, identifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(body: &str) -> String {
+        format!(
+            r#"<html><head>
+                <title>Why Rivers Bend | The River Review</title>
+                <meta property="og:site_name" content="The River Review">
+                <meta name="author" content="By Ada Banks">
+              </head><body>{}</body></html>"#,
+            body
+        )
+    }
+
+    fn prose() -> String {
+        "<p>Rivers bend because, over time, water erodes the outer bank faster than the inner \
+         one, and sediment settles where the current slows, which deepens every curve.</p>"
+            .repeat(4)
+    }
+
+    #[test]
+    fn extracts_article_and_metadata() {
+        let html = page(&format!(
+            r#"<nav class="menu"><a href="/">Home</a><a href="/about">About</a></nav>
+               <div class="sidebar"><p>Subscribe to our newsletter for more stories, tips, and news.</p></div>
+               <article class="post-content">
+                 <h1>Why Rivers Bend</h1>
+                 {}
+                 <script>track()</script>
+                 <p><a href="/meanders">More on meanders</a> and <img data-src="/img/river.jpg" alt="A river"></p>
+                 <div class="share-buttons"><a href="https://x.example/share">Share</a></div>
+               </article>
+               <footer><p>Copyright, all rights reserved, and so on and so forth.</p></footer>"#,
+            prose()
+        ));
+        let base = Url::parse("https://rivers.example/2024/bends").unwrap();
+        let article = extract(&html, Some(&base)).unwrap();
+
+        assert_eq!(article.title, "Why Rivers Bend");
+        assert_eq!(article.author.as_deref(), Some("Ada Banks"));
+        assert_eq!(article.site_name.as_deref(), Some("The River Review"));
+        assert_eq!(
+            article.images,
+            [Url::parse("https://rivers.example/img/river.jpg").unwrap()]
+        );
+
+        let xhtml = article.xhtml(&[Some("images/image-001.jpg".to_string())]);
+        assert!(xhtml.contains("erodes the outer bank"));
+        assert!(xhtml.contains(r#"<a href="https://rivers.example/meanders">"#));
+        assert!(xhtml.contains(r#"<img src="images/image-001.jpg" alt="A river"/>"#));
+        assert!(!xhtml.contains("<h1>"));
+        for boilerplate in ["track()", "Share", "newsletter", "Copyright", "About"] {
+            assert!(!xhtml.contains(boilerplate), "kept {}", boilerplate);
+        }
+    }
+
+    #[test]
+    fn drops_images_that_were_not_fetched() {
+        let html = page(&format!(
+            r#"<div class="entry">{}<p><img src="https://cdn.example/a.png"> after</p></div>"#,
+            prose()
+        ));
+        let article = extract(&html, None).unwrap();
+        let xhtml = article.xhtml(&[None]);
+        assert!(!xhtml.contains("<img"));
+        assert!(xhtml.contains("after"));
+    }
+
+    #[test]
+    fn empty_pages_have_no_article() {
+        let html = page(r#"<div id="app"></div><script>render()</script>"#);
+        assert_eq!(extract(&html, None), Err(ArticleError::Empty));
+    }
+
+    #[test]
+    fn subscriber_teasers_are_paywalled() {
+        let html = page(&format!(
+            r#"<script type="application/ld+json">{{"@type": "NewsArticle", "isAccessibleForFree": false}}</script>
+               <article>{}</article>"#,
+            prose()
+        ));
+        assert_eq!(extract(&html, None), Err(ArticleError::Paywalled));
+
+        // The whole article (e.g. copied while logged in) is fine
+        let full = html.replace(&prose(), &prose().repeat(4));
+        assert!(extract(&full, None).is_ok());
+    }
+
+    #[test]
+    fn sniffs_image_types() {
+        assert_eq!(image_extension(b"\x89PNG\r\n"), Some("png"));
+        assert_eq!(image_extension(b"\xff\xd8\xff\xe0"), Some("jpg"));
+        assert_eq!(image_extension(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(
+            image_extension(b"<?xml version=\"1.0\"?><svg/>"),
+            Some("svg")
+        );
+        assert_eq!(image_extension(b"<html>"), None);
+    }
+}
@@ -0,0 +1,609 @@
+// Read Master Desktop - Markdown Conversion
+//
+// Markdown with optional YAML frontmatter (title, author, source URL)
+// becomes an EPUB with a chapter per heading up to the configured level.
+// The source is read line by line and each chapter is rendered and written
+// as soon as the next one starts; a chapter that grows past
+// `MAX_CHAPTER_BYTES` is continued in a new file at the next blank line.
+//
+// Output is sanitized: raw HTML is reduced to its text, links keep only
+// safe schemes, and images are either copied into the book (local files)
+// or dropped in favor of their alt text.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+use url::Url;
+
+use super::{media_type, read_line, BookInfo, ConvertOptions, EpubWriter};
+
+/// Chapter size at which the rest continues in a new file
+const MAX_CHAPTER_BYTES: usize = 512 * 1024;
+
+/// Frontmatter longer than this is assumed to be a thematic break instead
+const MAX_FRONTMATTER_LINES: usize = 200;
+
+/// Link schemes kept in the output; anything else is unlinked
+const SAFE_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+// ============================================================================
+// Frontmatter
+// ============================================================================
+
+#[derive(Debug, Default, PartialEq)]
+struct Frontmatter {
+    title: Option<String>,
+    authors: Vec<String>,
+    source: Option<String>,
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|v| v.strip_suffix(quote))
+        {
+            return inner.to_string();
+        }
+    }
+    value.to_string()
+}
+
+impl Frontmatter {
+    fn assign(&mut self, key: &str, value: String) {
+        if value.is_empty() {
+            return;
+        }
+        match key {
+            "title" => {
+                self.title.get_or_insert(value);
+            }
+            "author" | "authors" | "byline" | "creator" => self.authors.push(value),
+            "source" | "url" | "source_url" | "sourceurl" | "link" | "canonical_url" => {
+                self.source.get_or_insert(value);
+            }
+            _ => {}
+        }
+    }
+
+    /// Read the flat `key: value` subset of YAML that article exporters
+    /// write, including `[a, b]` and `- item` lists. Nested maps are ignored.
+    fn parse(lines: &[String]) -> Self {
+        let mut frontmatter = Self::default();
+        let mut list_key: Option<String> = None;
+
+        for line in lines {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if let Some(item) = trimmed.strip_prefix("- ") {
+                if let Some(key) = &list_key {
+                    frontmatter.assign(key, unquote(item));
+                }
+                continue;
+            }
+            if line.starts_with(char::is_whitespace) {
+                continue;
+            }
+
+            let Some((key, value)) = trimmed.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_lowercase();
+            let value = value.trim();
+            if value.is_empty() {
+                list_key = Some(key);
+                continue;
+            }
+            list_key = None;
+
+            match value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+                Some(items) => {
+                    for item in items.split(',') {
+                        frontmatter.assign(&key, unquote(item));
+                    }
+                }
+                None => frontmatter.assign(&key, unquote(value)),
+            }
+        }
+        frontmatter
+    }
+}
+
+// ============================================================================
+// Line Scanning
+// ============================================================================
+
+/// Level and text of an ATX heading (`## Title ##`)
+fn atx_heading(line: &str) -> Option<(u8, &str)> {
+    let hashes = line.chars().take_while(|&c| c == '#').count();
+    if !(1..=6).contains(&hashes) {
+        return None;
+    }
+    let rest = &line[hashes..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+
+    let text = rest.trim();
+    let text = match text.trim_end_matches('#') {
+        stripped if stripped.is_empty() || stripped.ends_with([' ', '\t']) => stripped.trim_end(),
+        _ => text,
+    };
+    Some((hashes as u8, text))
+}
+
+/// Level of a setext underline (`===` or `---`)
+fn setext_level(line: &str) -> Option<u8> {
+    let line = line.trim_end();
+    if line.is_empty() {
+        None
+    } else if line.chars().all(|c| c == '=') {
+        Some(1)
+    } else if line.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Opening code fence marker, e.g. "```" or "~~~~"
+fn fence_marker(line: &str) -> Option<String> {
+    let first = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let run = line.chars().take_while(|&c| c == first).count();
+    (run >= 3).then(|| first.to_string().repeat(run))
+}
+
+fn closes_fence(line: &str, marker: &str) -> bool {
+    let line = line.trim();
+    line.starts_with(marker) && line.chars().all(|c| Some(c) == marker.chars().next())
+}
+
+/// Lines a setext underline can't follow
+fn is_block_start(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with(['#', '>', '|', '-', '*', '+'])
+        || line
+            .split_once(". ")
+            .is_some_and(|(n, _)| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Plain text of inline Markdown, for chapter titles
+fn inline_text(markdown: &str) -> String {
+    Parser::new(markdown)
+        .filter_map(|event| match event {
+            Event::Text(text) | Event::Code(text) => Some(text.into_string()),
+            _ => None,
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// ============================================================================
+// Rendering
+// ============================================================================
+
+fn has_scheme(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        !scheme.is_empty()
+            && !scheme.contains(['/', '?', '#'])
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    })
+}
+
+fn is_safe_link(url: &str) -> bool {
+    let url = url.trim();
+    match url.split_once(':') {
+        Some((scheme, _)) if has_scheme(url) => {
+            SAFE_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str())
+        }
+        _ => true,
+    }
+}
+
+/// Text of raw HTML with the tags removed
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Local image file an image reference points at, resolved against the
+/// source file's folder
+fn local_image(dest: &str, base: &Url) -> Option<PathBuf> {
+    let dest = dest.trim();
+    if dest.is_empty() || (has_scheme(dest) && !dest.starts_with("file:")) {
+        return None;
+    }
+    let path = base.join(dest).ok()?.to_file_path().ok()?;
+    path.is_file().then_some(path)
+}
+
+/// Streams Markdown lines into EPUB chapters
+struct Converter {
+    writer: EpubWriter,
+    /// `file://` URL of the source's folder, for resolving images
+    base: Option<Url>,
+    split_level: u8,
+    /// Title used for text before the first heading
+    fallback_title: String,
+    buffer: String,
+    /// Byte offset of the last line in `buffer`
+    last_line_start: usize,
+    /// The last line was paragraph text, so `---` under it is a heading
+    after_paragraph: bool,
+    fence: Option<String>,
+    title: Option<String>,
+    /// The current chapter was split for size; further parts stay out of
+    /// the contents
+    continued: bool,
+    first_title: Option<String>,
+    /// Copied images by source path
+    images: HashMap<PathBuf, String>,
+}
+
+impl Converter {
+    fn push(&mut self, line: &str) {
+        self.last_line_start = self.buffer.len();
+        self.buffer.push_str(line);
+        self.buffer.push('\n');
+    }
+
+    fn start_chapter(&mut self, level: u8, title: &str) -> Result<(), String> {
+        self.flush()?;
+        let title = inline_text(title);
+        if level == 1 && self.first_title.is_none() && !title.is_empty() {
+            self.first_title = Some(title.clone());
+        }
+        self.title = Some(title).filter(|t| !t.is_empty());
+        self.continued = false;
+        Ok(())
+    }
+
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if let Some(marker) = &self.fence {
+            if closes_fence(trimmed, marker) {
+                self.fence = None;
+            }
+            self.push(line);
+            return Ok(());
+        }
+
+        let after_paragraph = std::mem::replace(&mut self.after_paragraph, false);
+        if indent < 4 {
+            if let Some(marker) = fence_marker(trimmed) {
+                self.fence = Some(marker);
+                self.push(line);
+                return Ok(());
+            }
+            if let Some((level, text)) = atx_heading(trimmed) {
+                if level <= self.split_level {
+                    self.start_chapter(level, text)?;
+                }
+                self.push(line);
+                return Ok(());
+            }
+            if let Some(level) = setext_level(trimmed).filter(|_| after_paragraph) {
+                if level <= self.split_level {
+                    let title = self.buffer.split_off(self.last_line_start);
+                    self.start_chapter(level, title.trim())?;
+                    self.push(title.trim_end_matches('\n'));
+                }
+                self.push(line);
+                return Ok(());
+            }
+        }
+
+        if trimmed.is_empty() {
+            if self.buffer.len() >= MAX_CHAPTER_BYTES {
+                self.flush()?;
+                self.continued = true;
+            }
+        } else {
+            self.after_paragraph = !is_block_start(trimmed);
+        }
+        self.push(line);
+        Ok(())
+    }
+
+    /// Render Markdown to sanitized XHTML, returning images still to copy
+    fn render(&mut self, markdown: &str) -> (String, Vec<(String, PathBuf)>) {
+        let mut pending = Vec::new();
+        let mut dropped_link = false;
+        let mut dropped_image = false;
+
+        let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+        let events = Parser::new_ext(markdown, options).filter_map(|event| match event {
+            Event::Html(raw) | Event::InlineHtml(raw) => {
+                Some(Event::Text(CowStr::from(strip_tags(&raw))))
+            }
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                dropped_link = !is_safe_link(&dest_url);
+                (!dropped_link).then_some(Event::Start(Tag::Link {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }))
+            }
+            Event::End(TagEnd::Link) if dropped_link => {
+                dropped_link = false;
+                None
+            }
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                let copied = self.base.as_ref().and_then(|base| {
+                    let path = local_image(&dest_url, base)?;
+                    media_type(&path.to_string_lossy())?;
+                    if let Some(href) = self.images.get(&path) {
+                        return Some(href.clone());
+                    }
+                    let name = format!(
+                        "{}-{}",
+                        self.images.len() + 1,
+                        path.file_name()?.to_string_lossy().replace(' ', "_")
+                    );
+                    let href = format!("images/{}", name);
+                    self.images.insert(path.clone(), href.clone());
+                    pending.push((name, path));
+                    Some(href)
+                });
+                dropped_image = copied.is_none();
+                copied.map(|href| {
+                    Event::Start(Tag::Image {
+                        link_type,
+                        dest_url: CowStr::from(href),
+                        title,
+                        id,
+                    })
+                })
+            }
+            // Alt text of a dropped image is kept as plain text
+            Event::End(TagEnd::Image) if dropped_image => {
+                dropped_image = false;
+                None
+            }
+            event => Some(event),
+        });
+
+        let mut out = String::with_capacity(markdown.len() * 3 / 2);
+        html::push_html(&mut out, events);
+        (out, pending)
+    }
+
+    /// Write out the buffered chapter
+    fn flush(&mut self) -> Result<(), String> {
+        let markdown = std::mem::take(&mut self.buffer);
+        self.last_line_start = 0;
+        if markdown.trim().is_empty() {
+            return Ok(());
+        }
+
+        let (html, images) = self.render(&markdown);
+        let title = self
+            .title
+            .clone()
+            .unwrap_or_else(|| self.fallback_title.clone());
+        self.writer.begin_chapter(&title, !self.continued)?;
+        self.writer.write_html(&html)?;
+
+        for (name, path) in images {
+            match fs::read(&path) {
+                Ok(data) => {
+                    self.writer.add_image(&name, &data)?;
+                }
+                Err(e) => warn!("Failed to copy image {}: {}", path.display(), e),
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Conversion
+// ============================================================================
+
+/// Convert a Markdown file into an EPUB at `dest`
+pub fn convert(
+    source: &Path,
+    dest: &Path,
+    file_title: &str,
+    identifier: &str,
+    options: ConvertOptions,
+) -> Result<(), String> {
+    let file =
+        File::open(source).map_err(|e| format!("Failed to open {}: {}", source.display(), e))?;
+    let mut reader = BufReader::new(file);
+    let mut buf = Vec::new();
+
+    // Frontmatter is only recognized on the very first line
+    let mut frontmatter_lines = Vec::new();
+    let mut body_lines = Vec::new();
+    let mut frontmatter = Frontmatter::default();
+    if let Some(first) = read_line(&mut reader, &mut buf)? {
+        if first.trim_end() == "---" {
+            let mut closed = false;
+            while frontmatter_lines.len() < MAX_FRONTMATTER_LINES {
+                let Some(line) = read_line(&mut reader, &mut buf)? else {
+                    break;
+                };
+                if matches!(line.trim_end(), "---" | "...") {
+                    closed = true;
+                    break;
+                }
+                frontmatter_lines.push(line);
+            }
+            if closed {
+                frontmatter = Frontmatter::parse(&frontmatter_lines);
+            } else {
+                body_lines.push(first);
+                body_lines.append(&mut frontmatter_lines);
+            }
+        } else {
+            body_lines.push(first);
+        }
+    }
+
+    let base = source
+        .canonicalize()
+        .ok()
+        .and_then(|path| Url::from_file_path(path).ok());
+    let mut converter = Converter {
+        writer: EpubWriter::create(dest)?,
+        base,
+        split_level: options.split_level,
+        fallback_title: frontmatter
+            .title
+            .clone()
+            .unwrap_or_else(|| file_title.to_string()),
+        buffer: String::new(),
+        last_line_start: 0,
+        after_paragraph: false,
+        fence: None,
+        title: None,
+        continued: false,
+        first_title: None,
+        images: HashMap::new(),
+    };
+
+    for line in body_lines {
+        converter.line(&line)?;
+    }
+    while let Some(line) = read_line(&mut reader, &mut buf)? {
+        converter.line(&line)?;
+    }
+    converter.flush()?;
+
+    let info = BookInfo {
+        title: frontmatter
+            .title
+            .or(converter.first_title)
+            .unwrap_or_else(|| file_title.to_string()),
+        author: (!frontmatter.authors.is_empty()).then(|| frontmatter.authors.join(", ")),
+        source: frontmatter.source,
+        ..Default::default()
+    };
+    converter.writer.finish(&info, identifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::{metadata, EpubArchive};
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parses_frontmatter() {
+        let frontmatter = Frontmatter::parse(&lines(
+            "title: \"Why: A Story\"\nauthor:\n  - Ann\n  - 'Bob'\nurl: https://example.com/a?b=1\ntags: [x, y]\nmeta:\n  title: nested",
+        ));
+        assert_eq!(
+            frontmatter,
+            Frontmatter {
+                title: Some("Why: A Story".to_string()),
+                authors: vec!["Ann".to_string(), "Bob".to_string()],
+                source: Some("https://example.com/a?b=1".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn recognizes_headings() {
+        assert_eq!(atx_heading("## Two ##"), Some((2, "Two")));
+        assert_eq!(atx_heading("# C#"), Some((1, "C#")));
+        assert_eq!(atx_heading("#hashtag"), None);
+        assert_eq!(atx_heading("####### seven"), None);
+        assert_eq!(setext_level("==="), Some(1));
+        assert_eq!(setext_level("--- "), Some(2));
+        assert_eq!(setext_level("-=-"), None);
+    }
+
+    #[test]
+    fn sanitizes_links() {
+        assert!(is_safe_link("https://example.com"));
+        assert!(is_safe_link("other.md#part"));
+        assert!(!is_safe_link("javascript:alert(1)"));
+        assert!(!is_safe_link(" JavaScript:alert(1)"));
+        assert_eq!(strip_tags("<b>bold</b> text"), "bold text");
+    }
+
+    #[test]
+    fn converts_chapters_and_images() {
+        let dir = std::env::temp_dir().join(format!("rm-md-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("img")).unwrap();
+        fs::write(dir.join("img/fig one.png"), b"png bytes").unwrap();
+        let source = dir.join("article.md");
+        fs::write(
+            &source,
+            "---\ntitle: Saved Article\nauthor: Jo Writer\nsource: https://example.com/post\n---\n\
+             Intro <script>alert(1)</script> text.\n\n\
+             # First\n\nBody with ![a figure](img/fig%20one.png) and ![remote](https://x.test/y.png).\n\n\
+             ```\n# not a heading\n```\n\n\
+             ### Deep\n\nStill first.\n\n\
+             Second\n------\n\n[bad](javascript:alert(1)) link.\n",
+        )
+        .unwrap();
+
+        let dest = dir.join("article.epub");
+        convert(
+            &source,
+            &dest,
+            "article",
+            "urn:test",
+            ConvertOptions::default(),
+        )
+        .unwrap();
+
+        let mut epub = EpubArchive::open(&dest.to_string_lossy()).unwrap();
+        let meta = metadata::read_metadata(&mut epub);
+        assert_eq!(meta.title.as_deref(), Some("Saved Article"));
+        assert_eq!(meta.authors, ["Jo Writer"]);
+
+        let titles: Vec<String> = epub.toc().into_iter().map(|e| e.title).collect();
+        assert_eq!(titles, ["Saved Article", "First", "Second"]);
+        assert!(epub.contains("OEBPS/images/1-fig_one.png"));
+
+        let intro = epub.read_string("OEBPS/chapter-0001.xhtml").unwrap();
+        assert!(!intro.contains("<script>"));
+        let first = epub.read_string("OEBPS/chapter-0002.xhtml").unwrap();
+        assert!(first.contains("src=\"images/1-fig_one.png\""));
+        assert!(!first.contains("x.test"));
+        assert!(first.contains("# not a heading"));
+        let second = epub.read_string("OEBPS/chapter-0003.xhtml").unwrap();
+        assert!(!second.contains("javascript"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
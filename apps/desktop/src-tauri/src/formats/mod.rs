@@ -0,0 +1,447 @@
+// Read Master Desktop - Text Formats
+//
+// Markdown and plain-text files are converted to EPUB at import, so the
+// reader, TTS, search, and annotations handle them like any other book.
+// Chapters are written into the archive one at a time as the source is
+// read, so a multi-megabyte file never sits in memory as one string.
+//
+// Conversion is deterministic (fixed timestamps, an identifier derived from
+// the source's hash), so importing the same file again produces the same
+// EPUB and is caught as a duplicate.
+//
+// Web articles (`html`) go through the same writer. DjVu scans (`djvu`)
+// aren't converted; they're read in place.
+
+pub mod djvu;
+pub mod html;
+pub mod markdown;
+pub mod text;
+
+use std::fs::{self, File};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use log::info;
+use tauri::{AppHandle, Runtime};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::import;
+use crate::profiles::ProfileStoreExt;
+
+/// Extensions converted at import
+pub const TEXT_EXTENSIONS: &[&str] = &["md", "markdown", "txt"];
+
+const SETTINGS_STORE: &str = "settings.json";
+const SPLIT_LEVEL_SETTING: &str = "import.markdownSplitLevel";
+
+/// Deepest heading that starts a new chapter when none is configured
+const DEFAULT_SPLIT_LEVEL: u8 = 2;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Default)]
+pub struct BookInfo {
+    pub title: String,
+    pub author: Option<String>,
+    /// Where the text came from, e.g. an article URL
+    pub source: Option<String>,
+    /// Site or publisher name
+    pub publisher: Option<String>,
+    /// Publication or retrieval date (ISO 8601)
+    pub date: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConvertOptions {
+    /// Markdown headings up to this level (1-6) start a chapter
+    pub split_level: u8,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            split_level: DEFAULT_SPLIT_LEVEL,
+        }
+    }
+}
+
+/// Conversion options from settings
+pub fn options<R: Runtime>(app: &AppHandle<R>) -> ConvertOptions {
+    let split_level = app
+        .profile_store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(SPLIT_LEVEL_SETTING))
+        .and_then(|v| v.as_u64())
+        .map_or(DEFAULT_SPLIT_LEVEL, |level| level.clamp(1, 6) as u8);
+    ConvertOptions { split_level }
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Escape text for XHTML content and attribute values
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Read one line, replacing invalid UTF-8 and dropping the line ending and
+/// a leading byte order mark. `None` at end of file.
+pub fn read_line(reader: &mut impl BufRead, buf: &mut Vec<u8>) -> Result<Option<String>, String> {
+    buf.clear();
+    let n = reader
+        .read_until(b'\n', buf)
+        .map_err(|e| format!("Failed to read source: {}", e))?;
+    if n == 0 {
+        return Ok(None);
+    }
+
+    let line = String::from_utf8_lossy(buf);
+    let line = line.trim_end_matches(['\n', '\r']);
+    Ok(Some(
+        line.strip_prefix('\u{feff}').unwrap_or(line).to_string(),
+    ))
+}
+
+fn media_type(name: &str) -> Option<&'static str> {
+    let ext = Path::new(name).extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+// ============================================================================
+// EPUB Writer
+// ============================================================================
+
+struct Chapter {
+    href: String,
+    title: String,
+    /// Continuations of an oversized chapter stay out of the contents
+    in_toc: bool,
+}
+
+struct Resource {
+    href: String,
+    media_type: &'static str,
+}
+
+/// Writes an EPUB 3 package chapter by chapter
+pub struct EpubWriter {
+    zip: ZipWriter<File>,
+    chapters: Vec<Chapter>,
+    resources: Vec<Resource>,
+    in_chapter: bool,
+}
+
+impl EpubWriter {
+    fn stored() -> SimpleFileOptions {
+        SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .last_modified_time(zip::DateTime::default())
+    }
+
+    fn deflated() -> SimpleFileOptions {
+        // A fixed timestamp keeps the output identical across imports
+        SimpleFileOptions::default().last_modified_time(zip::DateTime::default())
+    }
+
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create EPUB: {}", e))?;
+        let mut writer = Self {
+            zip: ZipWriter::new(file),
+            chapters: Vec::new(),
+            resources: Vec::new(),
+            in_chapter: false,
+        };
+
+        writer.start("mimetype", Self::stored())?;
+        writer.write_raw(b"application/epub+zip")?;
+        writer.start("META-INF/container.xml", Self::deflated())?;
+        writer.write_raw(
+            br#"<?xml version="1.0" encoding="utf-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#,
+        )?;
+        Ok(writer)
+    }
+
+    fn start(&mut self, name: &str, options: SimpleFileOptions) -> Result<(), String> {
+        self.zip
+            .start_file(name, options)
+            .map_err(|e| format!("Failed to write EPUB: {}", e))
+    }
+
+    fn write_raw(&mut self, data: &[u8]) -> Result<(), String> {
+        self.zip
+            .write_all(data)
+            .map_err(|e| format!("Failed to write EPUB: {}", e))
+    }
+
+    /// Start a chapter; `in_toc` is false for the continuation of a long one
+    pub fn begin_chapter(&mut self, title: &str, in_toc: bool) -> Result<(), String> {
+        self.end_chapter()?;
+
+        let href = format!("chapter-{:04}.xhtml", self.chapters.len() + 1);
+        self.start(&format!("OEBPS/{}", href), Self::deflated())?;
+        self.write_raw(
+            format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n\
+                 <html xmlns=\"http://www.w3.org/1999/xhtml\">\n\
+                 <head><title>{}</title></head>\n<body>\n",
+                escape(title)
+            )
+            .as_bytes(),
+        )?;
+
+        self.chapters.push(Chapter {
+            href,
+            title: title.to_string(),
+            in_toc,
+        });
+        self.in_chapter = true;
+        Ok(())
+    }
+
+    /// Append markup to the open chapter
+    pub fn write_html(&mut self, html: &str) -> Result<(), String> {
+        if !self.in_chapter {
+            return Err("No chapter open".to_string());
+        }
+        self.write_raw(html.as_bytes())
+    }
+
+    pub fn end_chapter(&mut self) -> Result<(), String> {
+        if self.in_chapter {
+            self.write_raw(b"\n</body>\n</html>\n")?;
+            self.in_chapter = false;
+        }
+        Ok(())
+    }
+
+    /// Whether any chapter has been started
+    pub fn has_chapters(&self) -> bool {
+        !self.chapters.is_empty()
+    }
+
+    /// Add an image, returning its href relative to the chapters. Closes
+    /// the open chapter, since the archive takes one entry at a time.
+    pub fn add_image(&mut self, name: &str, data: &[u8]) -> Result<String, String> {
+        let media_type =
+            media_type(name).ok_or_else(|| format!("Unsupported image type: {}", name))?;
+        self.end_chapter()?;
+
+        let href = format!("images/{}", name);
+        self.start(&format!("OEBPS/{}", href), Self::deflated())?;
+        self.write_raw(data)?;
+        self.resources.push(Resource {
+            href: href.clone(),
+            media_type,
+        });
+        Ok(href)
+    }
+
+    /// Write the navigation document and package, and close the archive
+    pub fn finish(mut self, info: &BookInfo, identifier: &str) -> Result<(), String> {
+        self.end_chapter()?;
+        if self.chapters.is_empty() {
+            self.begin_chapter(&info.title, true)?;
+            self.end_chapter()?;
+        }
+
+        let entries: String = self
+            .chapters
+            .iter()
+            .filter(|c| c.in_toc)
+            .map(|c| {
+                format!(
+                    "      <li><a href=\"{}\">{}</a></li>\n",
+                    c.href,
+                    escape(&c.title)
+                )
+            })
+            .collect();
+        self.start("OEBPS/nav.xhtml", Self::deflated())?;
+        self.write_raw(
+            format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n\
+                 <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n\
+                 <head><title>{}</title></head>\n<body>\n  <nav epub:type=\"toc\">\n    <ol>\n{}    </ol>\n  </nav>\n</body>\n</html>\n",
+                escape(&info.title),
+                entries
+            )
+            .as_bytes(),
+        )?;
+
+        let mut metadata = format!(
+            "    <dc:identifier id=\"book-id\">{}</dc:identifier>\n    <dc:title>{}</dc:title>\n    <dc:language>und</dc:language>\n",
+            escape(identifier),
+            escape(&info.title)
+        );
+        if let Some(author) = &info.author {
+            metadata.push_str(&format!(
+                "    <dc:creator>{}</dc:creator>\n",
+                escape(author)
+            ));
+        }
+        if let Some(source) = &info.source {
+            metadata.push_str(&format!("    <dc:source>{}</dc:source>\n", escape(source)));
+        }
+        if let Some(publisher) = &info.publisher {
+            metadata.push_str(&format!(
+                "    <dc:publisher>{}</dc:publisher>\n",
+                escape(publisher)
+            ));
+        }
+        if let Some(date) = &info.date {
+            metadata.push_str(&format!("    <dc:date>{}</dc:date>\n", escape(date)));
+        }
+
+        let mut manifest = String::from(
+            "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+        );
+        let mut spine = String::new();
+        for (i, chapter) in self.chapters.iter().enumerate() {
+            manifest.push_str(&format!(
+                "    <item id=\"c{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+                i + 1,
+                chapter.href
+            ));
+            spine.push_str(&format!("    <itemref idref=\"c{}\"/>\n", i + 1));
+        }
+        for (i, resource) in self.resources.iter().enumerate() {
+            manifest.push_str(&format!(
+                "    <item id=\"r{}\" href=\"{}\" media-type=\"{}\"/>\n",
+                i + 1,
+                escape(&resource.href),
+                resource.media_type
+            ));
+        }
+
+        self.start("OEBPS/content.opf", Self::deflated())?;
+        self.write_raw(
+            format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                 <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n\
+                 \x20 <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n{}  </metadata>\n\
+                 \x20 <manifest>\n{}  </manifest>\n\
+                 \x20 <spine>\n{}  </spine>\n</package>\n",
+                metadata, manifest, spine
+            )
+            .as_bytes(),
+        )?;
+
+        self.zip
+            .finish()
+            .map_err(|e| format!("Failed to write EPUB: {}", e))?;
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Conversion
+// ============================================================================
+
+/// Whether a file is converted rather than imported as is
+pub fn is_text_source(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| TEXT_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Convert a Markdown or text file into an EPUB in `dest_dir`, returning
+/// its path
+pub fn convert(source: &Path, dest_dir: &Path, options: ConvertOptions) -> Result<PathBuf, String> {
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Untitled".to_string());
+    let identifier = format!("urn:sha256:{}", import::hash_path(source)?);
+
+    fs::create_dir_all(dest_dir).map_err(|e| format!("Failed to create library folder: {}", e))?;
+    let dest = import::unique_path(dest_dir, &format!("{}.epub", stem));
+    let partial = dest.with_extension("epub.part");
+
+    let is_markdown = source
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| !ext.eq_ignore_ascii_case("txt"));
+
+    info!("Converting {} to EPUB", source.display());
+    let result = if is_markdown {
+        markdown::convert(source, &partial, &stem, &identifier, options)
+    } else {
+        text::convert(source, &partial, &stem, &identifier)
+    };
+
+    match result.and_then(|_| {
+        fs::rename(&partial, &dest).map_err(|e| format!("Failed to save EPUB: {}", e))
+    }) {
+        Ok(()) => Ok(dest),
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::EpubArchive;
+
+    #[test]
+    fn writes_a_readable_package() {
+        let path = std::env::temp_dir().join(format!("rm-writer-{}.epub", uuid::Uuid::new_v4()));
+        let mut writer = EpubWriter::create(&path).unwrap();
+        writer.begin_chapter("One & Only", true).unwrap();
+        writer.write_html("<p>First</p>").unwrap();
+        writer.begin_chapter("One & Only", false).unwrap();
+        writer.write_html("<p>More</p>").unwrap();
+        writer.add_image("a.png", b"not really a png").unwrap();
+        writer
+            .finish(
+                &BookInfo {
+                    title: "Title".to_string(),
+                    author: Some("Ann <Author>".to_string()),
+                    ..Default::default()
+                },
+                "urn:sha256:abc",
+            )
+            .unwrap();
+
+        let mut epub = EpubArchive::open(&path.to_string_lossy()).unwrap();
+        assert_eq!(epub.spine_paths().len(), 2);
+        assert_eq!(epub.toc().len(), 1);
+        assert!(epub.contains("OEBPS/images/a.png"));
+        assert!(epub.chapter_text(1).unwrap().contains("More"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(escape(r#"a < b & "c""#), "a &lt; b &amp; &quot;c&quot;");
+    }
+}
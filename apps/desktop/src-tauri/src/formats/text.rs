@@ -0,0 +1,84 @@
+// Read Master Desktop - Plain Text Conversion
+//
+// A `.txt` file becomes a single chapter, one paragraph per blank-line
+// separated block. Lines within a block are joined, since plain-text books
+// are usually hard-wrapped.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use super::{escape, read_line, BookInfo, EpubWriter};
+
+/// Paragraphs gathered before they are written out
+const FLUSH_BYTES: usize = 64 * 1024;
+
+/// Convert a text file into an EPUB at `dest`
+pub fn convert(source: &Path, dest: &Path, title: &str, identifier: &str) -> Result<(), String> {
+    let file =
+        File::open(source).map_err(|e| format!("Failed to open {}: {}", source.display(), e))?;
+    let mut reader = BufReader::new(file);
+    let mut writer = EpubWriter::create(dest)?;
+    writer.begin_chapter(title, true)?;
+
+    let mut buf = Vec::new();
+    let mut paragraph: Vec<String> = Vec::new();
+    let mut html = String::new();
+
+    loop {
+        let line = read_line(&mut reader, &mut buf)?;
+        let at_end = line.is_none();
+        let line = line.unwrap_or_default();
+
+        if line.trim().is_empty() {
+            if !paragraph.is_empty() {
+                html.push_str(&format!("<p>{}</p>\n", escape(&paragraph.join(" "))));
+                paragraph.clear();
+            }
+            if html.len() >= FLUSH_BYTES || at_end {
+                writer.write_html(&html)?;
+                html.clear();
+            }
+            if at_end {
+                break;
+            }
+        } else {
+            paragraph.push(line.trim().to_string());
+        }
+    }
+
+    let info = BookInfo {
+        title: title.to_string(),
+        ..Default::default()
+    };
+    writer.finish(&info, identifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::EpubArchive;
+
+    #[test]
+    fn chunks_paragraphs_on_blank_lines() {
+        let dir = std::env::temp_dir().join(format!("rm-txt-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("notes.txt");
+        std::fs::write(
+            &source,
+            "\u{feff}It was a bright\r\ncold day.\r\n\r\n\r\nThe clocks <struck> thirteen.\n",
+        )
+        .unwrap();
+
+        let dest = dir.join("notes.epub");
+        convert(&source, &dest, "notes", "urn:test").unwrap();
+
+        let mut epub = EpubArchive::open(&dest.to_string_lossy()).unwrap();
+        assert_eq!(epub.spine_paths().len(), 1);
+        let markup = epub.read_string("OEBPS/chapter-0001.xhtml").unwrap();
+        assert!(markup.contains("<p>It was a bright cold day.</p>"));
+        assert!(markup.contains("<p>The clocks &lt;struck&gt; thirteen.</p>"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+// Read Master Desktop - Filesystem Utilities
+//
+// Free space checks for commands that write a lot to disk, and the path
+// helpers shared by the commands that move a folder's contents elsewhere.
+// A volume that fills up mid-write can leave a store or the database half
+// saved, so such commands check first that the data they'll write fits,
+// with `RESERVE_BYTES` to spare for the app's own files.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::Serialize;
+use sysinfo::Disks;
+use tauri::{AppHandle, Runtime};
+
+use crate::profiles;
+
+/// Space kept free beyond what a write needs, for stores, the database,
+/// and covers
+pub const RESERVE_BYTES: u64 = 200 * 1024 * 1024;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiskInfo {
+    /// The directory that was checked
+    pub path: String,
+    /// Mount point of the volume holding it
+    pub mount_point: String,
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+    pub required_bytes: u64,
+    /// Whether the required bytes plus the reserve are available
+    pub sufficient: bool,
+}
+
+// ============================================================================
+// Volumes
+// ============================================================================
+
+/// `path`, or its closest existing ancestor, with links resolved
+fn existing_path(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find_map(|dir| dir.canonicalize().ok())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Index of the volume whose mount point is the longest prefix of `path`
+fn volume_index(mount_points: &[&Path], path: &Path) -> Option<usize> {
+    mount_points
+        .iter()
+        .enumerate()
+        .filter(|(_, mount)| path.starts_with(mount))
+        .max_by_key(|(_, mount)| mount.components().count())
+        .map(|(index, _)| index)
+}
+
+fn fits(available: u64, required: u64) -> bool {
+    available >= required.saturating_add(RESERVE_BYTES)
+}
+
+/// Space on the volume holding `path`, and whether `required` bytes fit
+/// there with the reserve to spare
+pub fn disk_info(path: &Path, required: u64) -> Result<DiskInfo, String> {
+    let resolved = existing_path(path);
+    let disks = Disks::new_with_refreshed_list();
+    let mount_points: Vec<&Path> = disks.list().iter().map(|d| d.mount_point()).collect();
+    let disk = volume_index(&mount_points, &resolved)
+        .map(|index| &disks.list()[index])
+        .ok_or_else(|| format!("Failed to find the volume holding {}", path.display()))?;
+
+    Ok(DiskInfo {
+        path: path.to_string_lossy().into_owned(),
+        mount_point: disk.mount_point().to_string_lossy().into_owned(),
+        total_bytes: disk.total_space(),
+        available_bytes: disk.available_space(),
+        required_bytes: required,
+        sufficient: fits(disk.available_space(), required),
+    })
+}
+
+// ============================================================================
+// Moving
+// ============================================================================
+
+/// `path` moved from under `from` to the same place under `to`, or None
+/// when it isn't under `from`
+pub fn rebase(path: &Path, from: &Path, to: &Path) -> Option<PathBuf> {
+    path.strip_prefix(from).ok().map(|rel| to.join(rel))
+}
+
+/// Refuse targets that are the current folder or nested with it, which
+/// would copy the folder into itself. `what` names the folder in errors,
+/// e.g. "data folder".
+pub fn validate_target(current: &Path, target: &Path, what: &str) -> Result<(), String> {
+    if !target.is_absolute() {
+        return Err(format!("The new {} must be an absolute path", what));
+    }
+    if target.starts_with(current) || current.starts_with(target) {
+        return Err(format!(
+            "{} overlaps the current {} {}",
+            target.display(),
+            what,
+            current.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Delete the copies an unfinished move made, then the folders below
+/// `root` that held only them
+pub fn remove_copies<P: AsRef<Path>>(root: &Path, copies: &[P]) {
+    for copy in copies {
+        let copy = copy.as_ref();
+        match fs::remove_file(copy) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove copy {}: {}", copy.display(), e),
+        }
+    }
+    for copy in copies.iter().rev() {
+        for dir in copy.as_ref().ancestors().skip(1) {
+            if dir == root || !dir.starts_with(root) || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Free space on the volume holding the app's data, and whether
+/// `required_bytes` fit there
+#[tauri::command]
+pub fn check_disk_space<R: Runtime>(
+    app: AppHandle<R>,
+    required_bytes: u64,
+) -> Result<DiskInfo, String> {
+    disk_info(&profiles::data_dir(&app)?, required_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_most_specific_mount_point() {
+        let mounts = [Path::new("/"), Path::new("/home"), Path::new("/media/usb")];
+        assert_eq!(volume_index(&mounts, Path::new("/home/sam/data")), Some(1));
+        assert_eq!(
+            volume_index(&mounts, Path::new("/media/usb/books")),
+            Some(2)
+        );
+        assert_eq!(volume_index(&mounts, Path::new("/var/lib")), Some(0));
+        assert_eq!(volume_index(&mounts[1..], Path::new("/var/lib")), None);
+    }
+
+    #[test]
+    fn rebases_paths_under_the_old_root() {
+        let from = Path::new("/data/library");
+        let to = Path::new("/mnt/nas/books");
+        assert_eq!(
+            rebase(Path::new("/data/library/sci-fi/dune.epub"), from, to),
+            Some(PathBuf::from("/mnt/nas/books/sci-fi/dune.epub"))
+        );
+        assert_eq!(rebase(Path::new("/home/me/other.epub"), from, to), None);
+    }
+
+    #[test]
+    fn refuses_nested_targets() {
+        let current = Path::new("/data/library");
+        let check = |target| validate_target(current, Path::new(target), "library folder");
+        assert!(check("/data/library/new").is_err());
+        assert!(check("/data").is_err());
+        assert!(check("/").is_err());
+        assert_eq!(
+            check("relative"),
+            Err("The new library folder must be an absolute path".to_string())
+        );
+        assert!(check("/mnt/nas/books").is_ok());
+    }
+
+    #[test]
+    fn removes_copies_and_the_folders_they_leave_empty() {
+        let root = std::env::temp_dir().join(format!("rm-fsutil-{}", uuid::Uuid::new_v4()));
+        let copies = [root.join("a/b/one.epub"), root.join("a/two.epub")];
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("kept")).unwrap();
+        for copy in &copies {
+            fs::write(copy, b"copy").unwrap();
+        }
+        fs::write(root.join("kept/other.epub"), b"other").unwrap();
+
+        remove_copies(&root, &copies);
+        assert!(!root.join("a").exists());
+        assert!(root.join("kept/other.epub").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_reserve_free() {
+        assert!(fits(RESERVE_BYTES + 10, 10));
+        assert!(!fits(RESERVE_BYTES + 9, 10));
+        assert!(!fits(u64::MAX - 1, u64::MAX));
+    }
+}
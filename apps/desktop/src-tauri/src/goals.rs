@@ -0,0 +1,708 @@
+// Read Master Desktop - Reading Goals
+//
+// Goals like "30 minutes a day" or "24 books this year", tracked natively.
+
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, Timelike, Utc};
+use log::{info, warn};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use crate::activity::local_midnight;
+use crate::db::Database;
+use crate::notification_policy::NotificationCategory;
+use crate::profiles::ProfileStoreExt;
+use crate::{notifications, persist, tray};
+
+const SETTINGS_STORE: &str = "settings.json";
+const REMINDER_HOUR_SETTING: &str = "goals.reminderHour";
+/// Local date of the last daily evaluation, so a restart doesn't repeat it
+const LAST_EVALUATED_SETTING: &str = "goals.lastEvaluated";
+
+/// Local hour of the daily evaluation unless configured
+const DEFAULT_REMINDER_HOUR: u32 = 20;
+
+/// How often the evaluation task wakes to check the clock
+const TICK: Duration = Duration::from_secs(60);
+
+/// Where goal notifications lead
+const GOALS_ROUTE: &str = "/stats";
+
+/// Minutes read in `[?1, ?2)`, counting only the part of each session
+/// inside the range
+const MINUTES_SQL: &str = "
+    SELECT SUM(MIN(julianday(ended_at), julianday(?2))
+             - MAX(julianday(started_at), julianday(?1))) * 1440.0
+    FROM reading_sessions
+    WHERE started_at < ?2 AND ended_at > ?1 AND ended_at > started_at";
+
+/// Reads finished in the period. Imported history may only have a date,
+/// which is compared with the local dates `[?3, ?4)` instead.
+const BOOKS_SQL: &str = "
+    SELECT COUNT(*)
+    FROM reading_history
+    WHERE CASE WHEN length(finished_at) = 10
+               THEN finished_at >= ?3 AND finished_at < ?4
+               ELSE julianday(finished_at) >= julianday(?1)
+                    AND julianday(finished_at) < julianday(?2)
+          END";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GoalKind {
+    ReadingMinutes,
+    BooksFinished,
+}
+
+impl GoalKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::ReadingMinutes => "readingMinutes",
+            Self::BooksFinished => "booksFinished",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "readingMinutes" => Some(Self::ReadingMinutes),
+            "booksFinished" => Some(Self::BooksFinished),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GoalPeriod {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl GoalPeriod {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Year => "year",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "day" => Some(Self::Day),
+            "week" => Some(Self::Week),
+            "month" => Some(Self::Month),
+            "year" => Some(Self::Year),
+            _ => None,
+        }
+    }
+
+    /// The local dates of the period containing `date`, end exclusive.
+    /// Weeks start on Monday.
+    fn bounds(self, date: NaiveDate) -> (NaiveDate, NaiveDate) {
+        let start = match self {
+            Self::Day => date,
+            Self::Week => {
+                date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64)
+            }
+            Self::Month => date.with_day(1).unwrap_or(date),
+            Self::Year => NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap_or(date),
+        };
+        let end = match self {
+            Self::Day => start.succ_opt(),
+            Self::Week => start.checked_add_days(chrono::Days::new(7)),
+            Self::Month => start.checked_add_months(Months::new(1)),
+            Self::Year => start.checked_add_months(Months::new(12)),
+        };
+        (start, end.unwrap_or(start))
+    }
+
+    /// "today", "this week", ...
+    fn phrase(self) -> &'static str {
+        match self {
+            Self::Day => "today",
+            Self::Week => "this week",
+            Self::Month => "this month",
+            Self::Year => "this year",
+        }
+    }
+}
+
+/// A goal, tracked natively so it works from the tray alone. There is at
+/// most one per kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Goal {
+    kind: GoalKind,
+    target: u32,
+    period: GoalPeriod,
+    /// Start of the last period the user was congratulated for
+    congratulated: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoalProgress {
+    pub kind: GoalKind,
+    pub target: u32,
+    pub period: GoalPeriod,
+    /// Minutes read or books finished so far this period
+    pub current: f64,
+    pub completed: bool,
+    pub period_start: NaiveDate,
+    /// Last day of the period
+    pub period_end: NaiveDate,
+    /// Where steady progress would be by the end of today
+    pub on_pace: f64,
+}
+
+impl GoalProgress {
+    fn is_behind(&self) -> bool {
+        !self.completed && self.current < self.on_pace
+    }
+
+    /// e.g. "12/30 min today" or "5/24 books this year"
+    fn summary(&self) -> String {
+        let unit = match self.kind {
+            GoalKind::ReadingMinutes => "min",
+            GoalKind::BooksFinished => "books",
+        };
+        format!(
+            "{}/{} {} {}",
+            self.current.floor(),
+            self.target,
+            unit,
+            self.period.phrase()
+        )
+    }
+}
+
+// ============================================================================
+// Progress
+// ============================================================================
+
+fn load_goals(conn: &Connection) -> rusqlite::Result<Vec<Goal>> {
+    let mut stmt = conn.prepare(
+        "SELECT kind, target, period, congratulated_period FROM reading_goals ORDER BY kind",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, u32>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<String>>(3)?,
+        ))
+    })?;
+
+    let mut goals = Vec::new();
+    for row in rows {
+        let (kind, target, period, congratulated) = row?;
+        // Rows written by a newer version are skipped rather than failing
+        if let (Some(kind), Some(period)) = (GoalKind::parse(&kind), GoalPeriod::parse(&period)) {
+            goals.push(Goal {
+                kind,
+                target,
+                period,
+                congratulated: congratulated.and_then(|date| date.parse().ok()),
+            });
+        }
+    }
+    Ok(goals)
+}
+
+/// Progress on `goal` over the local dates `[start, end)`, whose UTC
+/// bounds come from `midnight`
+fn measure(
+    conn: &Connection,
+    kind: GoalKind,
+    (start, end): (NaiveDate, NaiveDate),
+    midnight: impl Fn(NaiveDate) -> DateTime<Utc>,
+) -> rusqlite::Result<f64> {
+    let (from, to) = (midnight(start).to_rfc3339(), midnight(end).to_rfc3339());
+    match kind {
+        GoalKind::ReadingMinutes => conn
+            .query_row(MINUTES_SQL, params![from, to], |row| {
+                row.get::<_, Option<f64>>(0)
+            })
+            .map(|minutes| minutes.unwrap_or(0.0)),
+        GoalKind::BooksFinished => conn.query_row(
+            BOOKS_SQL,
+            params![from, to, start.to_string(), end.to_string()],
+            |row| row.get::<_, i64>(0).map(|count| count as f64),
+        ),
+    }
+}
+
+/// Share of the target that steady progress reaches by the end of `today`
+fn on_pace(target: u32, (start, end): (NaiveDate, NaiveDate), today: NaiveDate) -> f64 {
+    let total = (end - start).num_days().max(1) as f64;
+    let elapsed = ((today - start).num_days() + 1).clamp(1, total as i64) as f64;
+    target as f64 * elapsed / total
+}
+
+/// Progress on `goal` in the period containing `today`. It's never
+/// accumulated, only recomputed from reading sessions and the reading
+/// history, so a new day, week, or year starts from zero on its own, and
+/// editing or deleting a past session shows the next time it's asked for.
+fn progress_on(
+    conn: &Connection,
+    goal: &Goal,
+    today: NaiveDate,
+    midnight: impl Fn(NaiveDate) -> DateTime<Utc>,
+) -> rusqlite::Result<GoalProgress> {
+    let bounds = goal.period.bounds(today);
+    let current = measure(conn, goal.kind, bounds, midnight)?;
+    Ok(GoalProgress {
+        kind: goal.kind,
+        target: goal.target,
+        period: goal.period,
+        current,
+        completed: current >= goal.target as f64,
+        period_start: bounds.0,
+        period_end: bounds.1.pred_opt().unwrap_or(bounds.0),
+        on_pace: on_pace(goal.target, bounds, today),
+    })
+}
+
+fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+fn all_progress(conn: &Connection) -> rusqlite::Result<Vec<(Goal, GoalProgress)>> {
+    let today = today();
+    load_goals(conn)?
+        .into_iter()
+        .map(|goal| Ok((goal, progress_on(conn, &goal, today, local_midnight)?)))
+        .collect()
+}
+
+/// Goals newly met this period, marked so they aren't congratulated again
+fn take_congratulations(
+    conn: &Connection,
+    progress: &[(Goal, GoalProgress)],
+) -> rusqlite::Result<Vec<GoalProgress>> {
+    let mut due = Vec::new();
+    for (goal, progress) in progress {
+        if progress.completed && goal.congratulated != Some(progress.period_start) {
+            conn.execute(
+                "UPDATE reading_goals SET congratulated_period = ?2 WHERE kind = ?1",
+                params![goal.kind.as_str(), progress.period_start.to_string()],
+            )?;
+            due.push(progress.clone());
+        }
+    }
+    Ok(due)
+}
+
+// ============================================================================
+// Updates
+// ============================================================================
+
+fn notify<R: Runtime>(app: &AppHandle<R>, title: &str, body: &str) {
+    if let Err(e) = notifications::show(
+        app,
+        Some(NotificationCategory::Reminders),
+        title,
+        Some(body),
+        Vec::new(),
+        Some(GOALS_ROUTE.into()),
+    ) {
+        warn!("Failed to show goal notification: {}", e);
+    }
+}
+
+fn congratulate<R: Runtime>(app: &AppHandle<R>, progress: &GoalProgress) {
+    info!("Goal met: {}", progress.summary());
+    notify(app, "Goal reached!", &progress.summary());
+}
+
+/// Recompute progress, update the tray tooltip, emit `goal-progress`, and
+/// congratulate on goals just met. Called after anything that can change
+/// progress.
+pub fn refresh<R: Runtime>(app: &AppHandle<R>) {
+    let Some(db) = app.try_state::<Database>() else {
+        return;
+    };
+    let result = db.with_conn(|conn| {
+        let progress = all_progress(conn)?;
+        let congratulations = take_congratulations(conn, &progress)?;
+        Ok((progress, congratulations))
+    });
+    let (progress, congratulations) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            warn!("Failed to compute goal progress: {}", e);
+            return;
+        }
+    };
+
+    let progress: Vec<GoalProgress> = progress.into_iter().map(|(_, p)| p).collect();
+    let summary: Vec<String> = progress.iter().map(GoalProgress::summary).collect();
+    tray::set_status(
+        app,
+        (!summary.is_empty())
+            .then(|| summary.join(" · "))
+            .as_deref(),
+    );
+    let _ = app.emit("goal-progress", &progress);
+
+    for progress in &congratulations {
+        congratulate(app, progress);
+    }
+}
+
+fn reminder_hour<R: Runtime>(app: &AppHandle<R>) -> u32 {
+    app.profile_store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(REMINDER_HOUR_SETTING))
+        .and_then(|value| value.as_u64())
+        .and_then(|hour| u32::try_from(hour).ok())
+        .filter(|hour| *hour < 24)
+        .unwrap_or(DEFAULT_REMINDER_HOUR)
+}
+
+/// Daily evaluation: congratulate on met goals and remind about the ones
+/// that are behind pace
+fn evaluate<R: Runtime>(app: &AppHandle<R>) {
+    refresh(app);
+
+    let Some(db) = app.try_state::<Database>() else {
+        return;
+    };
+    let behind: Vec<GoalProgress> = match db.with_conn(|conn| all_progress(conn)) {
+        Ok(progress) => progress
+            .into_iter()
+            .map(|(_, p)| p)
+            .filter(GoalProgress::is_behind)
+            .collect(),
+        Err(e) => {
+            warn!("Failed to evaluate goals: {}", e);
+            return;
+        }
+    };
+
+    for progress in behind {
+        info!("Goal behind pace: {}", progress.summary());
+        let body = match progress.period {
+            GoalPeriod::Day => format!("{} so far. There's still time!", progress.summary()),
+            _ => format!(
+                "{}. A little reading today keeps you on track.",
+                progress.summary()
+            ),
+        };
+        notify(app, "Reading goal reminder", &body);
+    }
+}
+
+/// Start the task evaluating goals daily at the configured hour, reminding
+/// the user of goals they're behind on. Needs the database, so it must run
+/// after `db::init`.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    refresh(app);
+
+    let app = app.clone();
+    let spawned = thread::Builder::new()
+        .name("reading-goals".into())
+        .spawn(move || {
+            let mut date = today();
+            loop {
+                thread::sleep(TICK);
+                let now = Local::now();
+
+                // Rollover: the tooltip shows the new period from zero
+                if now.date_naive() != date {
+                    date = now.date_naive();
+                    refresh(&app);
+                }
+
+                if now.hour() < reminder_hour(&app) {
+                    continue;
+                }
+                let Ok(store) = app.profile_store(SETTINGS_STORE) else {
+                    continue;
+                };
+                let evaluated = date.to_string();
+                if store
+                    .get(LAST_EVALUATED_SETTING)
+                    .is_some_and(|last| last.as_str() == Some(evaluated.as_str()))
+                {
+                    continue;
+                }
+                store.set(LAST_EVALUATED_SETTING, evaluated);
+                persist::mark_dirty(&app, SETTINGS_STORE);
+                evaluate(&app);
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start reading goal task: {}", e);
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Set the goal of `kind`, replacing any previous one
+#[tauri::command]
+pub async fn set_goal<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    kind: GoalKind,
+    target: u32,
+    period: GoalPeriod,
+) -> Result<GoalProgress, String> {
+    if target == 0 {
+        return Err("Goal target must be at least 1".to_string());
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let goal = Goal {
+        kind,
+        target,
+        period,
+        congratulated: None,
+    };
+    let progress = db.with_conn(|conn| {
+        // A changed goal can be congratulated again, even if the old one
+        // was already met this period
+        conn.execute(
+            "INSERT INTO reading_goals (kind, target, period, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?4)
+             ON CONFLICT (kind) DO UPDATE SET
+                target = excluded.target,
+                period = excluded.period,
+                congratulated_period = NULL,
+                updated_at = excluded.updated_at",
+            params![kind.as_str(), target, period.as_str(), now],
+        )?;
+        progress_on(conn, &goal, today(), local_midnight)
+    })?;
+
+    info!("Goal set: {}", progress.summary());
+    refresh(&app);
+    Ok(progress)
+}
+
+/// Remove the goal of `kind`
+#[tauri::command]
+pub async fn clear_goal<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    kind: GoalKind,
+) -> Result<(), String> {
+    db.with_conn(|conn| {
+        conn.execute("DELETE FROM reading_goals WHERE kind = ?1", [kind.as_str()])
+    })?;
+    refresh(&app);
+    Ok(())
+}
+
+/// Progress on every goal in its current period
+#[tauri::command]
+pub async fn get_goal_progress(db: State<'_, Database>) -> Result<Vec<GoalProgress>, String> {
+    db.with_conn(|conn| Ok(all_progress(conn)?.into_iter().map(|(_, p)| p).collect()))
+}
+
+/// Local hour (0-23) of the daily goal evaluation; `None` restores the
+/// default of 20:00
+#[tauri::command]
+pub fn set_goal_reminder_hour<R: Runtime>(
+    app: AppHandle<R>,
+    hour: Option<u32>,
+) -> Result<u32, String> {
+    let hour = hour.unwrap_or(DEFAULT_REMINDER_HOUR);
+    if hour >= 24 {
+        return Err(format!("Invalid hour: {}", hour));
+    }
+    let store = app
+        .profile_store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    store.set(REMINDER_HOUR_SETTING, hour);
+    persist::mark_dirty(&app, SETTINGS_STORE);
+    Ok(hour)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveTime, TimeZone};
+
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    /// UTC+2, so local days start at 22:00 UTC the day before
+    fn midnight(date: NaiveDate) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN)) - chrono::Duration::hours(2)
+    }
+
+    /// The app schema with book 1 for sessions and history to point at
+    fn database() -> Connection {
+        let conn = crate::db::test_connection();
+        conn.execute(
+            "INSERT INTO books (id, title, added_at, updated_at) VALUES (1, 'Book', '', '')",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn session(conn: &Connection, start: &str, end: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO reading_sessions (book_id, started_at, ended_at) VALUES (1, ?1, ?2)",
+            [start, end],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    fn goal(kind: GoalKind, target: u32, period: GoalPeriod) -> Goal {
+        Goal {
+            kind,
+            target,
+            period,
+            congratulated: None,
+        }
+    }
+
+    #[test]
+    fn periods_follow_the_calendar() {
+        let day = date("2024-02-29"); // a Thursday
+        assert_eq!(GoalPeriod::Day.bounds(day), (day, date("2024-03-01")));
+        assert_eq!(
+            GoalPeriod::Week.bounds(day),
+            (date("2024-02-26"), date("2024-03-04"))
+        );
+        assert_eq!(
+            GoalPeriod::Month.bounds(day),
+            (date("2024-02-01"), date("2024-03-01"))
+        );
+        assert_eq!(
+            GoalPeriod::Year.bounds(day),
+            (date("2024-01-01"), date("2025-01-01"))
+        );
+    }
+
+    #[test]
+    fn counts_minutes_in_local_days_only() {
+        let conn = database();
+        // 21:30-22:30 UTC is 23:30-00:30 local: half on each day
+        session(
+            &conn,
+            "2024-05-01T21:30:00+00:00",
+            "2024-05-01T22:30:00+00:00",
+        );
+        session(
+            &conn,
+            "2024-05-02T08:00:00+00:00",
+            "2024-05-02T08:20:00+00:00",
+        );
+
+        let goal = goal(GoalKind::ReadingMinutes, 30, GoalPeriod::Day);
+        let first = progress_on(&conn, &goal, date("2024-05-01"), midnight).unwrap();
+        let second = progress_on(&conn, &goal, date("2024-05-02"), midnight).unwrap();
+        assert!((first.current - 30.0).abs() < 0.01);
+        assert!(first.completed);
+        assert!((second.current - 50.0).abs() < 0.01);
+
+        // A new day starts from zero
+        let third = progress_on(&conn, &goal, date("2024-05-03"), midnight).unwrap();
+        assert_eq!(third.current, 0.0);
+        assert!(!third.completed);
+    }
+
+    #[test]
+    fn recomputes_after_session_edits() {
+        let conn = database();
+        let id = session(
+            &conn,
+            "2024-05-02T08:00:00+00:00",
+            "2024-05-02T08:40:00+00:00",
+        );
+        let goal = goal(GoalKind::ReadingMinutes, 30, GoalPeriod::Day);
+        assert!(
+            progress_on(&conn, &goal, date("2024-05-02"), midnight)
+                .unwrap()
+                .completed
+        );
+
+        conn.execute(
+            "UPDATE reading_sessions SET ended_at = ?2 WHERE id = ?1",
+            params![id, "2024-05-02T08:10:00+00:00"],
+        )
+        .unwrap();
+        let edited = progress_on(&conn, &goal, date("2024-05-02"), midnight).unwrap();
+        assert!((edited.current - 10.0).abs() < 0.01);
+        assert!(!edited.completed);
+    }
+
+    #[test]
+    fn counts_books_including_date_only_history() {
+        let conn = database();
+        for finished in [
+            "2024-03-10T12:00:00+00:00",
+            "2024-12-31",
+            "2023-12-31T23:00:00+00:00",
+        ] {
+            conn.execute(
+                "INSERT INTO reading_history (book_id, finished_at, source) VALUES (1, ?1, 'app')",
+                [finished],
+            )
+            .unwrap();
+        }
+
+        // 2023-12-31 23:00 UTC is already 2024 locally
+        let goal = goal(GoalKind::BooksFinished, 24, GoalPeriod::Year);
+        let progress = progress_on(&conn, &goal, date("2024-06-30"), midnight).unwrap();
+        assert_eq!(progress.current, 3.0);
+        assert_eq!(progress.period_end, date("2024-12-31"));
+        assert_eq!(progress.summary(), "3/24 books this year");
+        // Half the year has gone; 3 of 24 is behind
+        assert!((progress.on_pace - 24.0 * 182.0 / 366.0).abs() < 0.01);
+        assert!(progress.is_behind());
+    }
+
+    #[test]
+    fn congratulates_once_per_period() {
+        let conn = database();
+        conn.execute(
+            "INSERT INTO reading_goals (kind, target, period, created_at, updated_at)
+             VALUES ('readingMinutes', 10, 'day', '', '')",
+            [],
+        )
+        .unwrap();
+        session(
+            &conn,
+            "2024-05-02T08:00:00+00:00",
+            "2024-05-02T08:20:00+00:00",
+        );
+
+        let progress = |conn: &Connection| -> Vec<(Goal, GoalProgress)> {
+            load_goals(conn)
+                .unwrap()
+                .into_iter()
+                .map(|goal| {
+                    let p = progress_on(conn, &goal, date("2024-05-02"), midnight).unwrap();
+                    (goal, p)
+                })
+                .collect()
+        };
+        assert_eq!(
+            take_congratulations(&conn, &progress(&conn)).unwrap().len(),
+            1
+        );
+        assert!(take_congratulations(&conn, &progress(&conn))
+            .unwrap()
+            .is_empty());
+    }
+}
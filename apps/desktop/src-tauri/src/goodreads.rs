@@ -0,0 +1,502 @@
+// Read Master Desktop - Goodreads Import
+//
+// Imports a Goodreads library export (CSV) into the library database.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use log::{info, warn};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::db::Database;
+use crate::library::{self, BookFields};
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub total_rows: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub failures: Vec<RowFailure>,
+    #[serde(default)]
+    pub warnings: Vec<RowWarning>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RowFailure {
+    /// Line the CSV record starts on (the header is line 1). Quoted fields
+    /// can span lines, so this can be past the record's index.
+    pub row: u64,
+    pub title: Option<String>,
+    pub reason: String,
+}
+
+/// A row imported without a value that couldn't be read
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RowWarning {
+    /// Line the CSV record starts on, as in `RowFailure`
+    pub row: u64,
+    pub title: String,
+    pub message: String,
+}
+
+/// A parsed Goodreads export row
+#[derive(Debug)]
+struct GoodreadsEntry {
+    title: String,
+    author: Option<String>,
+    isbn: Option<String>,
+    isbn13: Option<String>,
+    rating: Option<i64>,
+    page_count: Option<i64>,
+    date_read: Option<String>,
+    date_added: Option<String>,
+    review: Option<String>,
+    shelves: Vec<String>,
+    /// Values left out of the row, e.g. unreadable dates
+    warnings: Vec<String>,
+}
+
+/// Column name lookup for the header row
+struct Columns(HashMap<String, usize>);
+
+impl Columns {
+    fn get<'r>(&self, record: &'r csv::StringRecord, name: &str) -> Option<&'r str> {
+        self.0
+            .get(name)
+            .and_then(|&i| record.get(i))
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    }
+}
+
+// ============================================================================
+// Parsing
+// ============================================================================
+
+/// Goodreads wraps ISBNs as `="0345391802"` to stop spreadsheets mangling them
+pub fn clean_isbn(raw: &str) -> Option<String> {
+    let isbn: String = raw
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    match isbn.len() {
+        10 | 13 => Some(isbn),
+        _ => None,
+    }
+}
+
+/// Accepts the date formats seen in Goodreads exports, returning ISO 8601
+fn parse_date(raw: &str) -> Result<String, String> {
+    const FORMATS: &[&str] = &["%Y/%m/%d", "%Y-%m-%d", "%m/%d/%Y", "%d %b %Y", "%b %d, %Y"];
+
+    for fmt in FORMATS {
+        if let Ok(date) = NaiveDate::parse_from_str(raw, fmt) {
+            return Ok(date.format("%Y-%m-%d").to_string());
+        }
+    }
+
+    // Some exports only carry a year
+    if let Ok(year) = raw.parse::<i32>() {
+        if let Some(date) = NaiveDate::from_ymd_opt(year, 1, 1) {
+            return Ok(date.format("%Y-%m-%d").to_string());
+        }
+    }
+
+    Err(format!("Unrecognized date '{}'", raw))
+}
+
+fn parse_shelves(columns: &Columns, record: &csv::StringRecord) -> Vec<String> {
+    let mut shelves: Vec<String> = columns
+        .get(record, "Bookshelves")
+        .unwrap_or("")
+        .split(',')
+        .chain(columns.get(record, "Exclusive Shelf"))
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    shelves.sort();
+    shelves.dedup();
+    shelves
+}
+
+fn parse_entry(columns: &Columns, record: &csv::StringRecord) -> Result<GoodreadsEntry, String> {
+    let title = columns
+        .get(record, "Title")
+        .ok_or_else(|| "Missing title".to_string())?
+        .to_string();
+
+    // Goodreads uses 0 for "not rated"
+    let rating = match columns.get(record, "My Rating") {
+        Some(raw) => match raw.parse::<i64>() {
+            Ok(0) => None,
+            Ok(r) if (1..=5).contains(&r) => Some(r),
+            _ => return Err(format!("Invalid rating '{}'", raw)),
+        },
+        None => None,
+    };
+
+    // A bad date drops that date, not the whole book
+    let mut warnings = Vec::new();
+    let mut date = |column: &str| {
+        let raw = columns.get(record, column)?;
+        parse_date(raw)
+            .map_err(|e| warnings.push(format!("{} ignored: {}", column, e)))
+            .ok()
+    };
+    let date_read = date("Date Read");
+    let date_added = date("Date Added");
+
+    Ok(GoodreadsEntry {
+        title,
+        author: columns.get(record, "Author").map(str::to_string),
+        isbn: columns.get(record, "ISBN").and_then(clean_isbn),
+        isbn13: columns.get(record, "ISBN13").and_then(clean_isbn),
+        rating,
+        page_count: columns
+            .get(record, "Number of Pages")
+            .and_then(|p| p.parse().ok()),
+        date_read,
+        date_added,
+        review: columns.get(record, "My Review").map(str::to_string),
+        shelves: parse_shelves(columns, record),
+        warnings,
+    })
+}
+
+// ============================================================================
+// Matching
+// ============================================================================
+
+/// Lowercase, drop series suffixes like "(The Expanse, #1)", and keep only
+/// alphanumerics so punctuation and spacing differences still match
+pub fn normalize_title(title: &str) -> String {
+    let title = match title.find(" (") {
+        Some(i) if title.ends_with(')') => &title[..i],
+        _ => title,
+    };
+    normalize(title)
+}
+
+pub fn normalize(value: &str) -> String {
+    value
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn find_match(conn: &Connection, entry: &GoodreadsEntry) -> rusqlite::Result<Option<i64>> {
+    let isbns: Vec<&str> = [&entry.isbn, &entry.isbn13]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    find_book(conn, &isbns, &entry.title, entry.author.as_deref())
+}
+
+/// Find an existing book by ISBN, then by normalized title + author
+pub fn find_book(
+    conn: &Connection,
+    isbns: &[&str],
+    title: &str,
+    author: Option<&str>,
+) -> rusqlite::Result<Option<i64>> {
+    for isbn in isbns {
+        let id = conn
+            .query_row(
+                "SELECT id FROM books WHERE (isbn = ?1 OR isbn13 = ?1) AND deleted_at IS NULL LIMIT 1",
+                [isbn],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if id.is_some() {
+            return Ok(id);
+        }
+    }
+
+    let title = normalize_title(title);
+    let author = author.map(normalize).unwrap_or_default();
+
+    let mut stmt = conn.prepare("SELECT id, title, author FROM books WHERE deleted_at IS NULL")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let candidate_title: String = row.get(1)?;
+        let candidate_author: Option<String> = row.get(2)?;
+        let candidate_author = candidate_author
+            .as_deref()
+            .map(normalize)
+            .unwrap_or_default();
+
+        if normalize_title(&candidate_title) == title
+            && (author.is_empty() || candidate_author.is_empty() || candidate_author == author)
+        {
+            return Ok(Some(row.get(0)?));
+        }
+    }
+
+    Ok(None)
+}
+
+fn import_entry(conn: &Connection, entry: &GoodreadsEntry) -> rusqlite::Result<bool> {
+    let fields = BookFields {
+        title: entry.title.clone(),
+        author: entry.author.clone(),
+        isbn: entry.isbn.clone(),
+        isbn13: entry.isbn13.clone(),
+        page_count: entry.page_count,
+        rating: entry.rating,
+        review: entry.review.clone(),
+        added_at: entry.date_added.clone(),
+        finished_at: entry.date_read.clone(),
+        ..Default::default()
+    };
+
+    let (book_id, created) = match find_match(conn, entry)? {
+        Some(id) => {
+            library::update_book(conn, id, &fields)?;
+            (id, false)
+        }
+        None => (library::insert_book(conn, &fields)?, true),
+    };
+
+    library::add_tags(conn, book_id, &entry.shelves)?;
+
+    if let Some(date_read) = &entry.date_read {
+        library::record_finished(conn, book_id, date_read, entry.rating, "goodreads")?;
+    }
+
+    Ok(created)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Import a Goodreads library export CSV
+#[tauri::command]
+pub async fn import_goodreads_csv(
+    db: State<'_, Database>,
+    path: String,
+) -> Result<ImportReport, String> {
+    info!("Importing Goodreads CSV: {}", path);
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(&path)
+        .map_err(|e| format!("Failed to open CSV: {}", e))?;
+
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("Failed to read CSV header: {}", e))?;
+    let columns = Columns(
+        headers
+            .iter()
+            .enumerate()
+            .map(|(i, h)| (h.to_string(), i))
+            .collect(),
+    );
+
+    if !columns.0.contains_key("Title") {
+        return Err("Not a Goodreads export: missing 'Title' column".to_string());
+    }
+
+    let mut report = ImportReport::default();
+
+    db.with_conn(|conn| {
+        let tx = conn.transaction()?;
+
+        for result in reader.records() {
+            report.total_rows += 1;
+
+            let record = match result {
+                Ok(record) => record,
+                Err(e) => {
+                    report.failures.push(RowFailure {
+                        row: e.position().map_or(0, |p| p.line()),
+                        title: None,
+                        reason: format!("Malformed CSV row: {}", e),
+                    });
+                    continue;
+                }
+            };
+            let row = record.position().map_or(0, |p| p.line());
+
+            let entry = match parse_entry(&columns, &record) {
+                Ok(entry) => entry,
+                Err(reason) => {
+                    report.failures.push(RowFailure {
+                        row,
+                        title: columns.get(&record, "Title").map(str::to_string),
+                        reason,
+                    });
+                    continue;
+                }
+            };
+
+            report
+                .warnings
+                .extend(entry.warnings.iter().map(|message| RowWarning {
+                    row,
+                    title: entry.title.clone(),
+                    message: message.clone(),
+                }));
+
+            match import_entry(&tx, &entry) {
+                Ok(true) => report.created += 1,
+                Ok(false) => report.updated += 1,
+                Err(e) => {
+                    warn!("Failed to import Goodreads row {}: {}", row, e);
+                    report.failures.push(RowFailure {
+                        row,
+                        title: Some(entry.title),
+                        reason: format!("Database error: {}", e),
+                    });
+                }
+            }
+        }
+
+        tx.commit()
+    })?;
+
+    info!(
+        "Goodreads import complete: {} created, {} updated, {} failed, {} warnings",
+        report.created,
+        report.updated,
+        report.failures.len(),
+        report.warnings.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(names: &[&str]) -> Columns {
+        Columns(
+            names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.to_string(), i))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn cleans_spreadsheet_wrapped_isbns() {
+        assert_eq!(clean_isbn("=\"0345391802\""), Some("0345391802".into()));
+        assert_eq!(
+            clean_isbn("=\"9780345391803\""),
+            Some("9780345391803".into())
+        );
+        assert_eq!(clean_isbn("=\"080442957x\""), Some("080442957X".into()));
+        assert_eq!(
+            clean_isbn("978-0-345-39180-3"),
+            Some("9780345391803".into())
+        );
+        assert_eq!(clean_isbn("=\"\""), None);
+        assert_eq!(clean_isbn("=\"12345\""), None);
+    }
+
+    #[test]
+    fn parses_export_date_formats() {
+        for raw in [
+            "2021/03/04",
+            "2021-03-04",
+            "03/04/2021",
+            "4 Mar 2021",
+            "Mar 04, 2021",
+        ] {
+            assert_eq!(parse_date(raw).as_deref(), Ok("2021-03-04"), "{}", raw);
+        }
+        assert_eq!(parse_date("1999").as_deref(), Ok("1999-01-01"));
+        assert!(parse_date("last spring").is_err());
+        assert!(parse_date("2021/13/01").is_err());
+    }
+
+    #[test]
+    fn shelves_become_deduplicated_tags() {
+        let columns = columns(&["Title", "Bookshelves", "Exclusive Shelf"]);
+        let record =
+            csv::StringRecord::from(vec!["Dune", "Sci-Fi, favorites ,, to-read", "to-read"]);
+        assert_eq!(
+            parse_shelves(&columns, &record),
+            ["favorites", "sci-fi", "to-read"]
+        );
+    }
+
+    #[test]
+    fn bad_dates_warn_and_keep_the_row() {
+        let columns = columns(&["Title", "Date Read", "Date Added", "My Rating"]);
+        let record = csv::StringRecord::from(vec!["Dune", "2021/03/04", "someday", "4"]);
+        let entry = parse_entry(&columns, &record).unwrap();
+        assert_eq!(entry.date_read.as_deref(), Some("2021-03-04"));
+        assert_eq!(entry.date_added, None);
+        assert_eq!(entry.warnings.len(), 1);
+        assert!(entry.warnings[0].starts_with("Date Added ignored"));
+
+        let record = csv::StringRecord::from(vec!["Dune", "", "", "7"]);
+        assert!(parse_entry(&columns, &record).is_err());
+    }
+
+    #[test]
+    fn matches_by_isbn_then_title_and_author() {
+        let conn = crate::db::test_connection();
+        conn.execute_batch(
+            "INSERT INTO books (id, title, author, isbn13, added_at, updated_at) VALUES
+                (1, 'Leviathan Wakes', 'James S. A. Corey', '9780316129084', '', ''),
+                (2, 'Dune', 'Frank Herbert', NULL, '', ''),
+                (3, 'Hyperion', NULL, NULL, '', '');
+            INSERT INTO books (id, title, author, deleted_at, added_at, updated_at) VALUES
+                (4, 'Emma', 'Jane Austen', '2024-01-01', '', '');",
+        )
+        .unwrap();
+
+        // The ISBN wins even when the title differs
+        assert_eq!(
+            find_book(&conn, &["9780316129084"], "Something Else", None).unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            find_book(
+                &conn,
+                &["0000000000"],
+                "Leviathan Wakes (The Expanse, #1)",
+                Some("James S.A. Corey")
+            )
+            .unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            find_book(&conn, &[], "DUNE", Some("frank herbert")).unwrap(),
+            Some(2)
+        );
+        assert_eq!(
+            find_book(&conn, &[], "Dune", Some("Brian Herbert")).unwrap(),
+            None
+        );
+        // A missing author on either side still matches by title
+        assert_eq!(
+            find_book(&conn, &[], "Hyperion", Some("Dan Simmons")).unwrap(),
+            Some(3)
+        );
+        assert_eq!(
+            find_book(&conn, &[], "Emma", Some("Jane Austen")).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,172 @@
+// Read Master Desktop - Highlight Review
+//
+// Past highlights resurfaced for the Daily Review: a random sample from
+// across the library, and the ones made on today's date in earlier years.
+// Highlights are notes with a quote, in a book still in the library.
+// Orphaned ones are left out, since their locator no longer leads anywhere.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::db::Database;
+use crate::library;
+use crate::notes::{self, Note};
+
+/// Most highlights one random draw returns
+const MAX_SAMPLE: usize = 100;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// A highlight with enough of its book to show it and jump back to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub note_id: String,
+    pub book_id: i64,
+    pub book_title: String,
+    pub author: Option<String>,
+    pub locator: Option<String>,
+    pub quote: String,
+    /// The note written on the highlight, if any
+    pub body: String,
+    pub color: Option<String>,
+    pub tags: Vec<String>,
+    pub created_at: String,
+}
+
+// ============================================================================
+// Highlights
+// ============================================================================
+
+/// Every reviewable highlight, with its book
+fn highlights<R: Runtime>(app: &AppHandle<R>) -> Result<Vec<Annotation>, String> {
+    let books: HashMap<i64, (String, Option<String>)> = app
+        .state::<Database>()
+        .with_conn(|conn| library::list_books(conn))?
+        .into_iter()
+        .map(|book| (book.id, (book.title, book.author)))
+        .collect();
+
+    Ok(notes::load_all(app)?
+        .into_iter()
+        .filter_map(|note| annotation(note, &books))
+        .collect())
+}
+
+fn annotation(note: Note, books: &HashMap<i64, (String, Option<String>)>) -> Option<Annotation> {
+    if note.orphaned {
+        return None;
+    }
+    let quote = note.quote.filter(|q| !q.trim().is_empty())?;
+    let book_id: i64 = note.book_id?.parse().ok()?;
+    let (title, author) = books.get(&book_id)?;
+
+    Some(Annotation {
+        note_id: note.id,
+        book_id,
+        book_title: title.clone(),
+        author: author.clone(),
+        locator: note.locator,
+        quote,
+        body: note.body,
+        color: note.color,
+        tags: note.tags,
+        created_at: note.created_at,
+    })
+}
+
+/// `count` items in random order, picked by sorting on a random key each
+fn sample<T>(items: Vec<T>, count: usize, mut key: impl FnMut() -> u128) -> Vec<T> {
+    let mut keyed: Vec<(u128, T)> = items.into_iter().map(|item| (key(), item)).collect();
+    keyed.sort_unstable_by_key(|(k, _)| *k);
+    keyed.truncate(count);
+    keyed.into_iter().map(|(_, item)| item).collect()
+}
+
+fn local_date(timestamp: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|time| time.with_timezone(&Local).date_naive())
+}
+
+/// Whether `date` falls on `today`'s month and day in an earlier year.
+/// Leap-day highlights come back on February 28 in other years.
+fn on_this_day(date: NaiveDate, today: NaiveDate) -> bool {
+    if date.year() >= today.year() {
+        return false;
+    }
+    let same_day = date.month() == today.month() && date.day() == today.day();
+    let leap_day = date.month() == 2
+        && date.day() == 29
+        && today.month() == 2
+        && today.day() == 28
+        && NaiveDate::from_ymd_opt(today.year(), 2, 29).is_none();
+    same_day || leap_day
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Up to `count` (at most 100) highlights from across the library, in
+/// random order
+#[tauri::command]
+pub async fn get_random_highlights<R: Runtime>(
+    app: AppHandle<R>,
+    count: usize,
+) -> Result<Vec<Annotation>, String> {
+    Ok(sample(highlights(&app)?, count.min(MAX_SAMPLE), || {
+        uuid::Uuid::new_v4().as_u128()
+    }))
+}
+
+/// Highlights made on today's date in earlier years, most recent first
+#[tauri::command]
+pub async fn get_highlights_on_this_day<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<Vec<Annotation>, String> {
+    let today = Local::now().date_naive();
+    let mut found: Vec<Annotation> = highlights(&app)?
+        .into_iter()
+        .filter(|a| local_date(&a.created_at).is_some_and(|date| on_this_day(date, today)))
+        .collect();
+    found.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn matches_the_same_day_in_earlier_years() {
+        let today = date(2026, 10, 16);
+        assert!(on_this_day(date(2021, 10, 16), today));
+        assert!(!on_this_day(date(2026, 10, 16), today));
+        assert!(!on_this_day(date(2021, 10, 15), today));
+    }
+
+    #[test]
+    fn leap_days_return_on_february_28() {
+        assert!(on_this_day(date(2024, 2, 29), date(2026, 2, 28)));
+        assert!(!on_this_day(date(2024, 2, 29), date(2028, 2, 28)));
+        assert!(on_this_day(date(2024, 2, 29), date(2028, 2, 29)));
+    }
+
+    #[test]
+    fn samples_by_key_order() {
+        let mut keys = [30u128, 10, 20, 40].into_iter();
+        let picked = sample(vec!["a", "b", "c", "d"], 2, || keys.next().unwrap());
+        assert_eq!(picked, ["b", "c"]);
+        assert!(sample(vec![1, 2], 5, || 0).len() == 2);
+    }
+}
@@ -0,0 +1,561 @@
+// Read Master Desktop - Book Images
+//
+// Figures from a book for the gallery and export. EPUB images are found
+// through the <img>/<svg:image> references in spine documents, so each comes
+// with the chapter it appears in and a caption from its <figcaption> or alt
+// text. PDF images are the image XObjects of each page, read through the
+// memory-mapped document.
+//
+// Images are read one at a time, never the whole book. An image referenced
+// several times is listed once, as are identical files stored under
+// different names. Images smaller than `images.minSize` pixels on either
+// side (rules, bullets, ornaments) are skipped.
+
+use std::collections::HashSet;
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat, ImageReader};
+use log::{info, warn};
+use pdf::enc::StreamFilter;
+use pdf::object::{PlainRef, Ref, Resolve, XObject};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Runtime, State};
+
+use crate::db::Database;
+use crate::epub::{parent_dir, resolve_href, EpubArchive};
+use crate::library;
+use crate::pdf as pdf_file;
+use crate::profiles::ProfileStoreExt;
+
+/// Default for `images.minSize`
+const DEFAULT_MIN_SIZE: u32 = 64;
+
+/// Prefix of PDF image ids, followed by `<object>.<generation>`
+const PDF_ID_PREFIX: &str = "pdf:";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookImage {
+    /// Archive path for EPUBs, `pdf:<object>.<generation>` for PDFs
+    pub id: String,
+    /// Spine index of the first chapter showing the image, or the page
+    /// index for PDFs
+    pub chapter: usize,
+    pub width: u32,
+    pub height: u32,
+    /// Stored format ("png", "jpeg", ...), or "raw" for uncompressed PDF
+    /// samples
+    pub format: String,
+    pub caption: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl ExportFormat {
+    fn image_format(self) -> ImageFormat {
+        match self {
+            Self::Png => ImageFormat::Png,
+            Self::Jpeg => ImageFormat::Jpeg,
+            Self::Webp => ImageFormat::WebP,
+        }
+    }
+}
+
+/// An image reference in an XHTML document
+#[derive(Debug, Clone, PartialEq)]
+struct ImageRef {
+    path: String,
+    caption: Option<String>,
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Format and dimensions from the image header, without decoding it
+fn probe(data: &[u8]) -> Option<(ImageFormat, u32, u32)> {
+    let reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?;
+    let format = reader.format()?;
+    let (width, height) = reader.into_dimensions().ok()?;
+    Some((format, width, height))
+}
+
+fn format_name(format: ImageFormat) -> String {
+    format!("{:?}", format).to_lowercase()
+}
+
+fn node_text(node: roxmltree::Node) -> String {
+    node.descendants()
+        .filter_map(|n| n.text())
+        .flat_map(str::split_whitespace)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn min_size<R: Runtime>(app: &AppHandle<R>) -> u32 {
+    app.profile_store("settings.json")
+        .ok()
+        .and_then(|s| s.get("images.minSize"))
+        .and_then(|v| v.as_u64())
+        .map_or(DEFAULT_MIN_SIZE, |v| v.min(u64::from(u32::MAX)) as u32)
+}
+
+fn book_source(db: &Database, book_id: i64) -> Result<(String, String), String> {
+    let book = db
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book {} not found", book_id))?;
+    let path = book
+        .path
+        .ok_or_else(|| format!("Book {} has no file", book_id))?;
+    let format = book.format.unwrap_or_else(|| {
+        if path.to_lowercase().ends_with(".pdf") {
+            "pdf".to_string()
+        } else {
+            "epub".to_string()
+        }
+    });
+    Ok((path, format))
+}
+
+// ============================================================================
+// EPUB
+// ============================================================================
+
+/// Images referenced by a spine document, with the nearest caption: the
+/// enclosing <figure>'s <figcaption>, else the alt or title text
+fn image_refs(markup: &str, doc_dir: &str) -> Vec<ImageRef> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let Ok(doc) = roxmltree::Document::parse_with_options(markup, options) else {
+        return Vec::new();
+    };
+
+    doc.descendants()
+        .filter(|n| n.is_element())
+        .filter_map(|node| {
+            let href = match node.tag_name().name() {
+                "img" => node.attribute("src"),
+                "image" => node
+                    .attributes()
+                    .find(|a| a.name() == "href")
+                    .map(|a| a.value()),
+                _ => None,
+            }?;
+            if href.starts_with("data:") || href.contains("://") {
+                return None;
+            }
+
+            let figcaption = node
+                .ancestors()
+                .find(|a| a.tag_name().name() == "figure")
+                .and_then(|figure| {
+                    figure
+                        .descendants()
+                        .find(|d| d.tag_name().name() == "figcaption")
+                })
+                .map(node_text);
+            let caption = figcaption
+                .into_iter()
+                .chain(node.attribute("alt").map(str::to_string))
+                .chain(node.attribute("title").map(str::to_string))
+                .map(|c| c.trim().to_string())
+                .find(|c| !c.is_empty());
+
+            Some(ImageRef {
+                path: resolve_href(doc_dir, href),
+                caption,
+            })
+        })
+        .collect()
+}
+
+fn epub_images(path: &str, min_size: u32) -> Result<Vec<BookImage>, String> {
+    let mut epub = EpubArchive::open(path).map_err(|e| e.to_string())?;
+    let mut seen_paths = HashSet::new();
+    let mut seen_hashes = HashSet::new();
+    let mut images = Vec::new();
+
+    for (chapter, doc) in epub.spine_paths().into_iter().enumerate() {
+        let markup = match epub.read_string(&doc) {
+            Ok(markup) => markup,
+            Err(e) => {
+                warn!("Skipping {} for images: {}", doc, e);
+                continue;
+            }
+        };
+
+        for image_ref in image_refs(&markup, parent_dir(&doc)) {
+            if !seen_paths.insert(image_ref.path.clone()) {
+                continue;
+            }
+            let Ok(data) = epub.read_resource(&image_ref.path) else {
+                continue;
+            };
+            // SVG and other formats without a raster header are skipped
+            let Some((format, width, height)) = probe(&data) else {
+                continue;
+            };
+            if width < min_size || height < min_size {
+                continue;
+            }
+            if !seen_hashes.insert(content_hash(&data)) {
+                continue;
+            }
+
+            images.push(BookImage {
+                id: image_ref.path,
+                chapter,
+                width,
+                height,
+                format: format_name(format),
+                caption: image_ref.caption,
+            });
+        }
+    }
+
+    Ok(images)
+}
+
+fn export_epub_image(path: &str, image_id: &str) -> Result<Vec<u8>, String> {
+    let mut epub = EpubArchive::open(path).map_err(|e| e.to_string())?;
+    if !epub
+        .package
+        .manifest
+        .iter()
+        .any(|item| item.path == image_id)
+    {
+        return Err(format!("Image not found in book: {}", image_id));
+    }
+    epub.read_resource(image_id)
+}
+
+// ============================================================================
+// PDF
+// ============================================================================
+
+fn pdf_image_id(reference: PlainRef) -> String {
+    format!("{}{}.{}", PDF_ID_PREFIX, reference.id, reference.gen)
+}
+
+fn parse_pdf_image_id(id: &str) -> Option<PlainRef> {
+    let (object, gen) = id.strip_prefix(PDF_ID_PREFIX)?.split_once('.')?;
+    Some(PlainRef {
+        id: object.parse().ok()?,
+        gen: gen.parse().ok()?,
+    })
+}
+
+/// Whether the stream is stored as a JPEG, which can be exported as is
+fn is_jpeg(filter: Option<&StreamFilter>) -> bool {
+    matches!(filter, Some(StreamFilter::DCTDecode(_)))
+}
+
+fn pdf_images(path: &str, min_size: u32) -> Result<Vec<BookImage>, String> {
+    let file = pdf_file::load(path)?;
+    let resolver = file.resolver();
+    let mut seen_refs = HashSet::new();
+    let mut seen_hashes = HashSet::new();
+    let mut images = Vec::new();
+
+    for (index, page) in file.pages().enumerate() {
+        let Ok(page) = page else {
+            continue;
+        };
+        let Ok(resources) = page.resources() else {
+            continue;
+        };
+
+        for xobject_ref in resources.xobjects.values() {
+            let reference = xobject_ref.get_inner();
+            if !seen_refs.insert((reference.id, reference.gen)) {
+                continue;
+            }
+            let Ok(xobject) = resolver.get(*xobject_ref) else {
+                continue;
+            };
+            let XObject::Image(image) = &*xobject else {
+                continue;
+            };
+            if image.width < min_size || image.height < min_size {
+                continue;
+            }
+            let Ok((data, filter)) = image.raw_image_data(&resolver) else {
+                continue;
+            };
+            if !seen_hashes.insert(content_hash(&data)) {
+                continue;
+            }
+
+            images.push(BookImage {
+                id: pdf_image_id(reference),
+                chapter: index,
+                width: image.width,
+                height: image.height,
+                format: if is_jpeg(filter) { "jpeg" } else { "raw" }.to_string(),
+                caption: None,
+            });
+        }
+    }
+
+    Ok(images)
+}
+
+/// Build an image from decoded 8-bit samples, inferring the color model
+/// from the samples per pixel
+fn from_samples(width: u32, height: u32, samples: &[u8]) -> Result<DynamicImage, String> {
+    let pixels = width as usize * height as usize;
+    let unsupported = || "Unsupported PDF image encoding".to_string();
+    if pixels == 0 || samples.len() % pixels != 0 {
+        return Err(unsupported());
+    }
+
+    let image = match samples.len() / pixels {
+        1 => image::GrayImage::from_raw(width, height, samples.to_vec()).map(DynamicImage::from),
+        3 => image::RgbImage::from_raw(width, height, samples.to_vec()).map(DynamicImage::from),
+        4 => {
+            // DeviceCMYK
+            let rgb = samples
+                .chunks_exact(4)
+                .flat_map(|p| {
+                    let k = 255 - u16::from(p[3]);
+                    [0, 1, 2].map(|i| ((255 - u16::from(p[i])) * k / 255) as u8)
+                })
+                .collect();
+            image::RgbImage::from_raw(width, height, rgb).map(DynamicImage::from)
+        }
+        _ => None,
+    };
+    image.ok_or_else(unsupported)
+}
+
+/// The image's bytes: the JPEG stream when stored as one, else PNG
+fn export_pdf_image(path: &str, image_id: &str) -> Result<Vec<u8>, String> {
+    let reference = parse_pdf_image_id(image_id)
+        .ok_or_else(|| format!("Invalid PDF image id: {}", image_id))?;
+    let file = pdf_file::load(path)?;
+    let resolver = file.resolver();
+
+    let xobject = resolver
+        .get(Ref::<XObject>::new(reference))
+        .map_err(|e| format!("Image not found in book: {}", e))?;
+    let XObject::Image(image) = &*xobject else {
+        return Err(format!("Not an image: {}", image_id));
+    };
+
+    let (raw, filter) = image
+        .raw_image_data(&resolver)
+        .map_err(|e| format!("Failed to read image: {}", e))?;
+    if is_jpeg(filter) {
+        return Ok(raw.to_vec());
+    }
+
+    let samples = image
+        .image_data(&resolver)
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    let decoded = from_samples(image.width, image.height, &samples)?;
+    encode(&decoded, ImageFormat::Png)
+}
+
+/// The largest image drawn on a page, as JPEG or PNG bytes. A scanned page
+/// is a single page-sized image, so this stands in for rendering it.
+pub fn pdf_page_scan(file: &pdf_file::PdfFile, index: u32) -> Result<Option<Vec<u8>>, String> {
+    let page = file
+        .get_page(index)
+        .map_err(|e| format!("Failed to read page {}: {}", index, e))?;
+    let resources = page
+        .resources()
+        .map_err(|e| format!("Failed to read page {} resources: {}", index, e))?;
+    let resolver = file.resolver();
+
+    let mut largest = None;
+    for xobject_ref in resources.xobjects.values() {
+        let Ok(xobject) = resolver.get(*xobject_ref) else {
+            continue;
+        };
+        let XObject::Image(image) = &*xobject else {
+            continue;
+        };
+        let area = u64::from(image.width) * u64::from(image.height);
+        if largest.as_ref().is_none_or(|(best, _)| area > *best) {
+            largest = Some((area, xobject));
+        }
+    }
+    let Some((_, xobject)) = largest else {
+        return Ok(None);
+    };
+    let XObject::Image(image) = &*xobject else {
+        return Ok(None);
+    };
+
+    let (raw, filter) = image
+        .raw_image_data(&resolver)
+        .map_err(|e| format!("Failed to read image: {}", e))?;
+    if is_jpeg(filter) {
+        return Ok(Some(raw.to_vec()));
+    }
+    let samples = image
+        .image_data(&resolver)
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    let decoded = from_samples(image.width, image.height, &samples)?;
+    encode(&decoded, ImageFormat::Png).map(Some)
+}
+
+// ============================================================================
+// Export
+// ============================================================================
+
+pub fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, String> {
+    // JPEG has no alpha channel
+    let image = match format {
+        ImageFormat::Jpeg => DynamicImage::from(image.to_rgb8()),
+        _ => image.clone(),
+    };
+    let mut out = Cursor::new(Vec::new());
+    image
+        .write_to(&mut out, format)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(out.into_inner())
+}
+
+/// Re-encode `data` as `format`, unless it's already stored that way
+fn convert(data: Vec<u8>, format: ExportFormat) -> Result<Vec<u8>, String> {
+    let target = format.image_format();
+    if probe(&data).is_some_and(|(stored, _, _)| stored == target) {
+        return Ok(data);
+    }
+
+    let image =
+        image::load_from_memory(&data).map_err(|e| format!("Failed to decode image: {}", e))?;
+    encode(&image, target)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Images in a book, in reading order, without duplicates or decorations
+#[tauri::command]
+pub async fn list_book_images<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    book_id: i64,
+) -> Result<Vec<BookImage>, String> {
+    let (path, format) = book_source(&db, book_id)?;
+    let min_size = min_size(&app);
+
+    let images = tauri::async_runtime::spawn_blocking(move || match format.as_str() {
+        "pdf" => pdf_images(&path, min_size),
+        _ => epub_images(&path, min_size),
+    })
+    .await
+    .map_err(|e| format!("Image listing task failed: {}", e))??;
+
+    info!("Found {} images in book {}", images.len(), book_id);
+    Ok(images)
+}
+
+/// Save an image to `out_path`, converted to `format` if given. Without a
+/// format the stored bytes are written (PNG for raw PDF samples).
+#[tauri::command]
+pub async fn export_book_image(
+    db: State<'_, Database>,
+    book_id: i64,
+    image_id: String,
+    out_path: String,
+    format: Option<ExportFormat>,
+) -> Result<(), String> {
+    let (path, book_format) = book_source(&db, book_id)?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let data = match book_format.as_str() {
+            "pdf" => export_pdf_image(&path, &image_id)?,
+            _ => export_epub_image(&path, &image_id)?,
+        };
+        let data = match format {
+            Some(format) => convert(data, format)?,
+            None => data,
+        };
+
+        std::fs::write(&out_path, data)
+            .map_err(|e| format!("Failed to write {}: {}", out_path, e))?;
+        info!(
+            "Exported {} from book {} to {}",
+            image_id, book_id, out_path
+        );
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("Image export task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_images_with_captions() {
+        let markup = r#"<html xmlns="http://www.w3.org/1999/xhtml"
+              xmlns:svg="http://www.w3.org/2000/svg"
+              xmlns:xlink="http://www.w3.org/1999/xlink"><body>
+            <figure>
+              <img src="../images/fig1.png" alt="ignored"/>
+              <figcaption>Figure 1. The <em>water</em> cycle</figcaption>
+            </figure>
+            <p><img src="../images/map.jpg" alt="Map of the region"/></p>
+            <svg:svg><svg:image xlink:href="cover.jpg"/></svg:svg>
+            <img src="data:image/png;base64,AAAA"/>
+          </body></html>"#;
+
+        assert_eq!(
+            image_refs(markup, "OEBPS/text/"),
+            [
+                ImageRef {
+                    path: "OEBPS/images/fig1.png".into(),
+                    caption: Some("Figure 1. The water cycle".into()),
+                },
+                ImageRef {
+                    path: "OEBPS/images/map.jpg".into(),
+                    caption: Some("Map of the region".into()),
+                },
+                ImageRef {
+                    path: "OEBPS/text/cover.jpg".into(),
+                    caption: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_pdf_image_ids() {
+        let reference = PlainRef { id: 42, gen: 0 };
+        let parsed = parse_pdf_image_id(&pdf_image_id(reference)).unwrap();
+        assert_eq!((parsed.id, parsed.gen), (42, 0));
+        assert!(parse_pdf_image_id("OEBPS/images/fig1.png").is_none());
+    }
+
+    #[test]
+    fn converts_cmyk_samples() {
+        let image = from_samples(1, 1, &[0, 255, 255, 0]).unwrap();
+        assert_eq!(image.to_rgb8().get_pixel(0, 0).0, [255, 0, 0]);
+        assert!(from_samples(2, 2, &[0; 5]).is_err());
+    }
+}
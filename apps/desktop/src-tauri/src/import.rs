@@ -0,0 +1,657 @@
+// Read Master Desktop - Import
+//
+// Book import pipeline: format detection, hashing, copying into the
+// library folder, metadata extraction, and the library record.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::db::Database;
+use crate::epub::{metadata, EpubArchive};
+use crate::error::AppError;
+use crate::formats::{self, djvu};
+use crate::fsutil::{self, DiskInfo};
+use crate::jobs::{Job, JobKind};
+use crate::jumplist;
+use crate::library::{self, Book, BookDetails, BookFields};
+use crate::mobi;
+use crate::scripting::{self, Hook};
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BookFormat {
+    Epub,
+    Pdf,
+    /// Mobipocket (.mobi, older .azw)
+    Mobi,
+    /// Kindle Format 8
+    Azw3,
+    Djvu,
+    Unknown,
+}
+
+impl BookFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Epub => "epub",
+            Self::Pdf => "pdf",
+            Self::Mobi => "mobi",
+            Self::Azw3 => "azw3",
+            Self::Djvu => "djvu",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    fn is_kindle(self) -> bool {
+        matches!(self, Self::Mobi | Self::Azw3)
+    }
+}
+
+/// Outcome of importing one file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportOutcome {
+    pub book: Book,
+    /// True when the file was already in the library
+    pub duplicate: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ImportProgress {
+    /// Files processed so far, including this one
+    current: usize,
+    total: usize,
+    /// Title of the imported book, or the file name if it failed
+    title: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// Summary emitted as `import-done` when a folder import finishes
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderImportSummary {
+    pub folder: String,
+    pub total: usize,
+    pub imported: usize,
+    pub duplicates: usize,
+    pub failed: Vec<ImportFailure>,
+    /// Stopped by `cancel_job` before every file was processed
+    pub cancelled: bool,
+}
+
+/// Payload of `import-aborted-lowspace`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LowSpaceAbort {
+    pub folder: String,
+    pub disk: DiskInfo,
+}
+
+/// Extensions a folder import picks up; contents are still checked
+const BOOK_EXTENSIONS: &[&str] = &[
+    "epub", "pdf", "mobi", "azw3", "azw", "md", "markdown", "txt", "djvu", "djv",
+];
+
+// ============================================================================
+// Format Detection & Hashing
+// ============================================================================
+
+/// Detect a book's format from its leading bytes, falling back to the
+/// extension only for zip files (EPUBs are zips with a mimetype entry)
+pub fn detect_format(path: &Path) -> Result<BookFormat, String> {
+    let mut header = [0u8; 78];
+    let read = File::open(path)
+        .and_then(|mut f| f.read(&mut header))
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let header = &header[..read];
+
+    if header.starts_with(b"%PDF-") {
+        return Ok(BookFormat::Pdf);
+    }
+
+    if header.starts_with(b"PK\x03\x04") {
+        // The OCF spec puts an uncompressed "mimetype" entry first
+        let is_epub_mimetype = header.len() >= 38 && &header[30..38] == b"mimetype";
+        let has_epub_extension = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("epub"));
+
+        if is_epub_mimetype || has_epub_extension {
+            return Ok(BookFormat::Epub);
+        }
+    }
+
+    if djvu::is_djvu(header) {
+        return Ok(BookFormat::Djvu);
+    }
+
+    if mobi::is_mobi(header) {
+        return Ok(if mobi::is_kf8(path)? {
+            BookFormat::Azw3
+        } else {
+            BookFormat::Mobi
+        });
+    }
+
+    Ok(BookFormat::Unknown)
+}
+
+/// SHA-256 of a file, hex encoded
+pub fn hash_path(path: &Path) -> Result<String, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut reader = BufReader::with_capacity(1 << 16, file);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 16];
+
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// ============================================================================
+// Pipeline
+// ============================================================================
+
+/// Pick a file name in `dir` that doesn't collide with an existing file
+pub fn unique_path(dir: &Path, file_name: &str) -> PathBuf {
+    let candidate = dir.join(file_name);
+    if !candidate.exists() {
+        return candidate;
+    }
+
+    let stem = Path::new(file_name)
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "book".to_string());
+    let ext = Path::new(file_name)
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, ext)))
+        .find(|p| !p.exists())
+        .expect("unbounded range always yields a free name")
+}
+
+fn fields_for(path: &Path, format: BookFormat, hash: String) -> BookFields {
+    let file_title = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Untitled".to_string());
+
+    let mut fields = BookFields {
+        title: file_title,
+        path: Some(path.to_string_lossy().into_owned()),
+        format: Some(format.as_str().to_string()),
+        content_hash: Some(hash),
+        ..Default::default()
+    };
+
+    if format == BookFormat::Epub {
+        match EpubArchive::open(&path.to_string_lossy()) {
+            Ok(mut epub) => {
+                let meta = metadata::read_metadata(&mut epub);
+                if let Some(title) = meta.title {
+                    fields.title = title;
+                }
+                if !meta.authors.is_empty() {
+                    fields.author = Some(meta.authors.join(", "));
+                }
+                fields.series_name = meta.series_name;
+                fields.series_index = meta.series_index;
+            }
+            Err(e) => warn!("Failed to read EPUB metadata for {}: {}", path.display(), e),
+        }
+    }
+
+    if format.is_kindle() {
+        match mobi::read_metadata(path) {
+            Ok(meta) => {
+                if let Some(title) = meta.title {
+                    fields.title = title;
+                }
+                if !meta.authors.is_empty() {
+                    fields.author = Some(meta.authors.join(", "));
+                }
+            }
+            Err(e) => warn!("Failed to read MOBI metadata for {}: {}", path.display(), e),
+        }
+    }
+
+    if format == BookFormat::Djvu {
+        match djvu::read_metadata(path) {
+            Ok(meta) => {
+                if let Some(title) = meta.title {
+                    fields.title = title;
+                }
+                fields.author = meta.author;
+            }
+            Err(e) => warn!("Failed to read DjVu metadata for {}: {}", path.display(), e),
+        }
+    }
+
+    fields
+}
+
+/// Import a book file into the library. Files outside the library folder
+/// are copied in; files already in it (e.g. fresh downloads) are used in
+/// place. Duplicates (same content hash) return the existing record.
+/// Markdown and text files are converted to EPUB first.
+pub fn import_file<R: Runtime>(
+    app: &AppHandle<R>,
+    source: &Path,
+) -> Result<ImportOutcome, AppError> {
+    info!("Importing book: {}", source.display());
+
+    if !formats::is_text_source(source) {
+        return add_to_library(app, source, source);
+    }
+
+    let converted = formats::convert(source, &library::library_dir(app)?, formats::options(app))?;
+    import_converted(app, source, &converted)
+}
+
+/// Import an EPUB converted from `source`, deleting it again unless it
+/// became a new book
+pub fn import_converted<R: Runtime>(
+    app: &AppHandle<R>,
+    source: &Path,
+    converted: &Path,
+) -> Result<ImportOutcome, AppError> {
+    let outcome = add_to_library(app, source, converted);
+    // A re-import converts to the same EPUB, already in the library
+    if !matches!(
+        outcome,
+        Ok(ImportOutcome {
+            duplicate: false,
+            ..
+        })
+    ) {
+        let _ = std::fs::remove_file(converted);
+    }
+    outcome
+}
+
+/// Import `file`, which is `source` or the EPUB it was converted to
+fn add_to_library<R: Runtime>(
+    app: &AppHandle<R>,
+    source: &Path,
+    file: &Path,
+) -> Result<ImportOutcome, AppError> {
+    let format = detect_format(file)?;
+    if format == BookFormat::Unknown {
+        return Err(AppError::invalid_input(format!(
+            "Unsupported book format: {}",
+            source.display()
+        )));
+    }
+    // Refuse archives that trip the size and path limits before copying
+    // them in; other EPUB problems only cost the book its metadata
+    if format == BookFormat::Epub {
+        if let Err(e @ AppError::SuspiciousArchive { .. }) =
+            EpubArchive::open(&file.to_string_lossy())
+        {
+            return Err(e);
+        }
+    }
+    // Refuse DRM-protected Kindle books before copying them in
+    if format.is_kindle() {
+        mobi::read_metadata(file)?;
+    }
+    // Without DjVuLibre the book couldn't be opened; say so up front
+    if format == BookFormat::Djvu {
+        djvu::page_count(file)?;
+    }
+
+    let hash = hash_path(file)?;
+    let db = app.state::<Database>();
+
+    if let Some(id) = db.with_conn(|conn| library::find_by_hash(conn, &hash))? {
+        info!("Skipping duplicate of book {}", id);
+        let book = db
+            .with_conn(|conn| library::get_book(conn, id))?
+            .ok_or_else(|| format!("Book not found: {}", id))?;
+        return Ok(ImportOutcome {
+            book,
+            duplicate: true,
+        });
+    }
+
+    let library_dir = library::library_dir(app)?;
+    let dest = if file.starts_with(&library_dir) {
+        file.to_path_buf()
+    } else {
+        let name = file
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| format!("{}.{}", hash, format.as_str()));
+        let dest = unique_path(&library_dir, &name);
+        std::fs::copy(file, &dest).map_err(|e| format!("Failed to copy book: {}", e))?;
+        dest
+    };
+
+    let fields = fields_for(&dest, format, hash);
+    let id = db.with_conn(|conn| library::insert_book(conn, &fields))?;
+
+    let cover_path = match format {
+        BookFormat::Epub => extract_cover(app, id, &dest),
+        BookFormat::Mobi | BookFormat::Azw3 => extract_mobi_cover(app, id, &dest),
+        _ => None,
+    };
+    if let Some(cover_path) = cover_path {
+        let details = BookDetails {
+            cover_path: Some(cover_path),
+            ..Default::default()
+        };
+        db.with_conn(|conn| library::update_details(conn, id, &details))?;
+    }
+
+    // Runs before the record is returned so renames and tags show up
+    let source_dir = source.parent().map(|p| p.to_string_lossy().into_owned());
+    scripting::trigger(
+        app,
+        Hook::OnImport,
+        Some(id),
+        serde_json::json!({ "sourcePath": source.to_string_lossy(), "sourceDir": source_dir }),
+    );
+
+    let book = db.with_conn(|conn| library::get_book(conn, id))?;
+    jumplist::refresh(app);
+
+    book.map(|book| ImportOutcome {
+        book,
+        duplicate: false,
+    })
+    .ok_or_else(|| AppError::from("Imported book vanished".to_string()))
+}
+
+/// Save an EPUB's cover image next to downloaded covers, returning its path
+fn extract_cover<R: Runtime>(app: &AppHandle<R>, book_id: i64, path: &Path) -> Option<String> {
+    let mut epub = EpubArchive::open(&path.to_string_lossy()).ok()?;
+    let cover = epub.cover_path()?;
+    let data = epub
+        .read_resource(&cover)
+        .map_err(|e| warn!("Failed to read cover of book {}: {}", book_id, e))
+        .ok()?;
+
+    let ext = Path::new(&cover)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_else(|| "jpg".to_string());
+    save_cover(app, book_id, &data, &ext)
+}
+
+/// Save a Mobipocket book's cover record, typed by its magic bytes
+fn extract_mobi_cover<R: Runtime>(app: &AppHandle<R>, book_id: i64, path: &Path) -> Option<String> {
+    let data = mobi::read_cover(path)
+        .map_err(|e| warn!("Failed to read cover of book {}: {}", book_id, e))
+        .ok()??;
+    let ext = if data.starts_with(b"\x89PNG") {
+        "png"
+    } else if data.starts_with(b"GIF8") {
+        "gif"
+    } else {
+        "jpg"
+    };
+    save_cover(app, book_id, &data, ext)
+}
+
+fn save_cover<R: Runtime>(
+    app: &AppHandle<R>,
+    book_id: i64,
+    data: &[u8],
+    ext: &str,
+) -> Option<String> {
+    let dir = library::covers_dir(app).ok()?;
+    let dest = dir.join(format!("{}.{}", book_id, ext));
+
+    std::fs::create_dir_all(&dir)
+        .and_then(|_| std::fs::write(&dest, data))
+        .map_err(|e| warn!("Failed to save cover of book {}: {}", book_id, e))
+        .ok()?;
+    Some(dest.to_string_lossy().into_owned())
+}
+
+// ============================================================================
+// Folder Import
+// ============================================================================
+
+/// Book files under `dir`, recursively, in a stable order. Hidden entries
+/// are skipped and symlinked directories aren't followed, so link loops
+/// can't trap the walk.
+fn book_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Skipping unreadable folder {}: {}", dir.display(), e);
+                continue;
+            }
+        };
+
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| BOOK_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+            {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+/// Bytes importing `files` copies into the library folder; files already
+/// inside it stay where they are
+fn import_size(files: &[PathBuf], library_dir: &Path) -> u64 {
+    files
+        .iter()
+        .filter(|file| !file.starts_with(library_dir))
+        .filter_map(|file| file.metadata().ok())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+/// Space on the library folder's volume for importing `files`
+fn space_for<R: Runtime>(app: &AppHandle<R>, files: &[PathBuf]) -> Result<DiskInfo, String> {
+    let library_dir = library::library_dir(app)?;
+    fsutil::disk_info(&library_dir, import_size(files, &library_dir))
+}
+
+fn import_folder_blocking<R: Runtime>(
+    app: &AppHandle<R>,
+    folder: &Path,
+    files: Vec<PathBuf>,
+    job: &Job<R>,
+) -> FolderImportSummary {
+    let mut summary = FolderImportSummary {
+        folder: folder.to_string_lossy().into_owned(),
+        total: files.len(),
+        ..Default::default()
+    };
+    info!(
+        "Importing {} files from {}",
+        summary.total,
+        folder.display()
+    );
+
+    for (index, path) in files.iter().enumerate() {
+        if job.is_cancelled() {
+            info!(
+                "Folder import cancelled after {} of {} files",
+                index, summary.total
+            );
+            summary.cancelled = true;
+            break;
+        }
+
+        let result = match detect_format(path) {
+            Ok(BookFormat::Unknown) => Err("Not a supported book file".to_string()),
+            Ok(_) => import_file(app, path).map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+
+        let title = match result {
+            Ok(outcome) => {
+                if outcome.duplicate {
+                    summary.duplicates += 1;
+                } else {
+                    summary.imported += 1;
+                }
+                outcome.book.title
+            }
+            Err(error) => {
+                warn!("Failed to import {}: {}", path.display(), error);
+                summary.failed.push(ImportFailure {
+                    path: path.to_string_lossy().into_owned(),
+                    error,
+                });
+                path.file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            }
+        };
+
+        job.progress((index + 1) as u64, summary.total as u64, Some(&title));
+        let _ = app.emit(
+            "import-progress",
+            ImportProgress {
+                current: index + 1,
+                total: summary.total,
+                title,
+            },
+        );
+    }
+
+    summary
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Detect the format of a book file
+#[tauri::command]
+pub async fn detect_book_format(path: String) -> Result<BookFormat, String> {
+    detect_format(Path::new(&path))
+}
+
+/// SHA-256 of a file, hex encoded
+#[tauri::command]
+pub async fn hash_file(path: String) -> Result<String, String> {
+    tauri::async_runtime::spawn_blocking(move || hash_path(Path::new(&path)))
+        .await
+        .map_err(|e| format!("Hashing task failed: {}", e))?
+}
+
+/// Import a single book file into the library
+#[tauri::command]
+pub async fn import_book<R: Runtime>(
+    app: AppHandle<R>,
+    path: String,
+) -> Result<ImportOutcome, AppError> {
+    tauri::async_runtime::spawn_blocking(move || import_file(&app, Path::new(&path)))
+        .await
+        .map_err(|e| AppError::from(format!("Import task failed: {}", e)))?
+}
+
+/// Import every book file under a folder in the background, as a job.
+/// Progress is reported as `import-progress` events and the result as
+/// `import-done`; duplicates of books already in the library are skipped.
+/// If the books won't fit on the library's volume, nothing is imported and
+/// `import-aborted-lowspace` is emitted instead.
+#[tauri::command]
+pub async fn import_folder<R: Runtime>(app: AppHandle<R>, path: String) -> Result<(), String> {
+    let folder = PathBuf::from(&path);
+    if !folder.is_dir() {
+        return Err(format!("Not a folder: {}", path));
+    }
+    // One folder import at a time
+    let job = Job::start(
+        &app,
+        JobKind::FolderImport,
+        "",
+        &format!("Importing {}", path),
+    )?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let files = book_files(&folder);
+        match space_for(&app, &files) {
+            Ok(disk) if !disk.sufficient => {
+                warn!(
+                    "Folder import aborted: {} bytes needed, {} available on {}",
+                    disk.required_bytes, disk.available_bytes, disk.mount_point
+                );
+                let _ = app.emit(
+                    "import-aborted-lowspace",
+                    LowSpaceAbort {
+                        folder: folder.to_string_lossy().into_owned(),
+                        disk,
+                    },
+                );
+                job.finish::<()>(&Err("Not enough disk space".to_string()));
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Skipping disk space check: {}", e),
+        }
+
+        let summary = import_folder_blocking(&app, &folder, files, &job);
+        if summary.cancelled {
+            job.finish_cancelled();
+        } else {
+            job.finish::<()>(&Ok(()));
+        }
+
+        info!(
+            "Folder import done: {} imported, {} duplicates, {} failed",
+            summary.imported,
+            summary.duplicates,
+            summary.failed.len()
+        );
+        let _ = app.emit("import-done", summary);
+    });
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+// Read Master Desktop - Single Instance
+//
+// A second launch exits straight away and hands its arguments to the
+// running instance, so two processes never write the stores at once. The
+// running instance comes forward (even when hidden to the tray) and either
+// runs a jump list action or receives the books to open as `open-files`.
+// A `--hidden` login launch that finds the app already running changes
+// nothing.
+
+use std::path::{Path, PathBuf};
+
+use log::info;
+use tauri::{AppHandle, Emitter, Runtime};
+
+use crate::autostart;
+use crate::jumplist::{self, JumpAction};
+use crate::tray;
+
+/// Extensions of files a launch can be asked to open
+const BOOK_EXTENSIONS: &[&str] = &["epub", "pdf", "djvu", "djv"];
+
+/// Book files among the arguments, resolved against the second launch's
+/// working directory. The first argument is the executable.
+fn book_paths(args: &[String], cwd: &Path) -> Vec<PathBuf> {
+    args.iter()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .map(|arg| cwd.join(arg))
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| BOOK_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect()
+}
+
+/// Callback for the single-instance plugin, run in the first instance
+pub fn handle_second_instance<R: Runtime>(app: &AppHandle<R>, args: Vec<String>, cwd: String) {
+    info!("Second launch forwarded: {:?}", args);
+
+    if let Some(action) = JumpAction::from_args(&args) {
+        jumplist::dispatch(app, action);
+        return;
+    }
+    if args.iter().any(|arg| arg == autostart::HIDDEN_FLAG) {
+        return;
+    }
+
+    tray::show_main_window(app);
+
+    let files: Vec<String> = book_paths(&args, Path::new(&cwd))
+        .into_iter()
+        .filter(|path| path.is_file())
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    if !files.is_empty() {
+        info!("Opening {} file(s) from second launch", files.len());
+        let _ = app.emit("open-files", files);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_book_files_from_arguments() {
+        let args: Vec<String> = [
+            "read-master",
+            "--flag",
+            "notes.txt",
+            "Book.EPUB",
+            "/abs/paper.pdf",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        assert_eq!(
+            book_paths(&args, Path::new("/home/reader")),
+            [
+                PathBuf::from("/home/reader/Book.EPUB"),
+                PathBuf::from("/abs/paper.pdf")
+            ]
+        );
+    }
+}
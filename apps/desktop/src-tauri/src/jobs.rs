@@ -0,0 +1,451 @@
+// Read Master Desktop - Jobs
+//
+// Registry of long-running operations: index builds, OCR, folder imports,
+// library migration, and audio export. Each registers when it starts and
+// gets a job id. Besides its own events it reports `job-progress`, then
+// `job-complete` or `job-failed`, so a task manager can follow any job.
+// `cancel_job` sets the job's cancellation token. The job checks it
+// between units of work (a page, a file, a batch of chapters).
+//
+// A watchdog fails jobs that report no progress for longer than their
+// kind's stall timeout, so a hung operation can't block its slot forever.
+// The stuck work itself can't be interrupted. It is cancelled, and
+// anything it reports after the watchdog gave up is ignored.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+/// How often the watchdog looks for stalled jobs
+const WATCHDOG_TICK: Duration = Duration::from_secs(5);
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JobKind {
+    Index,
+    Ocr,
+    FolderImport,
+    LibraryMigration,
+    LibraryAudit,
+    AudioExport,
+    UpdateDownload,
+    DataMigration,
+}
+
+impl JobKind {
+    /// Longest a job may go without reporting progress
+    fn stall_timeout(self) -> Duration {
+        match self {
+            // One batch of chapters, page, book file, paragraph, or
+            // download chunk
+            Self::Index
+            | Self::Ocr
+            | Self::FolderImport
+            | Self::LibraryAudit
+            | Self::AudioExport
+            | Self::UpdateDownload => Duration::from_secs(10 * 60),
+            // A single large file copied to a slow drive
+            Self::LibraryMigration | Self::DataMigration => Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// A running job, as listed for the task manager
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobInfo {
+    pub job_id: u64,
+    pub kind: JobKind,
+    /// What the job works on (book id, path); one job per kind and key
+    pub key: String,
+    pub label: String,
+    pub started_at: String,
+    pub current: u64,
+    pub total: u64,
+    pub message: Option<String>,
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JobFailed<'a> {
+    job_id: u64,
+    kind: JobKind,
+    error: &'a str,
+    cancelled: bool,
+    timed_out: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JobComplete {
+    job_id: u64,
+    kind: JobKind,
+}
+
+/// Shared flag a job polls to see whether it should stop
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+struct Entry {
+    info: JobInfo,
+    token: CancelToken,
+    last_progress: Instant,
+}
+
+/// Running jobs by id
+#[derive(Default)]
+pub struct JobRegistry {
+    next: AtomicU64,
+    jobs: Mutex<HashMap<u64, Entry>>,
+}
+
+impl JobRegistry {
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, HashMap<u64, Entry>>, String> {
+        self.jobs
+            .lock()
+            .map_err(|_| "Job registry lock poisoned".to_string())
+    }
+
+    /// Register a job, refusing a second one of the same kind and key
+    fn register(
+        &self,
+        kind: JobKind,
+        key: &str,
+        label: &str,
+    ) -> Result<(u64, CancelToken), String> {
+        let mut jobs = self.lock()?;
+        if let Some(running) = jobs
+            .values()
+            .find(|entry| entry.info.kind == kind && entry.info.key == key)
+        {
+            return Err(format!("{} is already running", running.info.label));
+        }
+
+        let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        let token = CancelToken::default();
+        jobs.insert(
+            id,
+            Entry {
+                info: JobInfo {
+                    job_id: id,
+                    kind,
+                    key: key.to_string(),
+                    label: label.to_string(),
+                    started_at: chrono::Utc::now().to_rfc3339(),
+                    current: 0,
+                    total: 0,
+                    message: None,
+                    cancelled: false,
+                },
+                token: token.clone(),
+                last_progress: Instant::now(),
+            },
+        );
+        Ok((id, token))
+    }
+
+    /// Id of the running job of `kind` for `key`
+    pub fn find(&self, kind: JobKind, key: &str) -> Option<u64> {
+        self.lock().ok()?.values().find_map(|entry| {
+            (entry.info.kind == kind && entry.info.key == key).then_some(entry.info.job_id)
+        })
+    }
+
+    /// Cancel a job. Returns whether it was running.
+    pub fn cancel(&self, id: u64) -> bool {
+        let Ok(mut jobs) = self.lock() else {
+            return false;
+        };
+        match jobs.get_mut(&id) {
+            Some(entry) => {
+                entry.token.cancel();
+                entry.info.cancelled = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Record progress, returning the updated job if it's still registered
+    fn progress(
+        &self,
+        id: u64,
+        current: u64,
+        total: u64,
+        message: Option<&str>,
+    ) -> Option<JobInfo> {
+        let mut jobs = self.lock().ok()?;
+        let entry = jobs.get_mut(&id)?;
+        entry.info.current = current;
+        entry.info.total = total;
+        entry.info.message = message.map(str::to_string);
+        entry.last_progress = Instant::now();
+        Some(entry.info.clone())
+    }
+
+    /// Remove a job, returning it if it was still registered
+    fn remove(&self, id: u64) -> Option<JobInfo> {
+        self.lock().ok()?.remove(&id).map(|entry| entry.info)
+    }
+
+    pub fn active(&self) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self
+            .lock()
+            .map(|jobs| jobs.values().map(|entry| entry.info.clone()).collect())
+            .unwrap_or_default();
+        jobs.sort_by_key(|job| job.job_id);
+        jobs
+    }
+
+    /// Cancel and remove jobs silent for longer than their stall timeout
+    fn reap_stalled(&self, now: Instant) -> Vec<JobInfo> {
+        let Ok(mut jobs) = self.lock() else {
+            return Vec::new();
+        };
+        let stalled: Vec<u64> = jobs
+            .values()
+            .filter(|entry| {
+                now.duration_since(entry.last_progress) > entry.info.kind.stall_timeout()
+            })
+            .map(|entry| entry.info.job_id)
+            .collect();
+
+        stalled
+            .into_iter()
+            .filter_map(|id| jobs.remove(&id))
+            .map(|entry| {
+                entry.token.cancel();
+                entry.info
+            })
+            .collect()
+    }
+}
+
+// ============================================================================
+// Job Handle
+// ============================================================================
+
+/// A registered job. Dropping it without `finish` unregisters it quietly.
+pub struct Job<R: Runtime> {
+    app: AppHandle<R>,
+    id: u64,
+    kind: JobKind,
+    token: CancelToken,
+}
+
+impl<R: Runtime> Job<R> {
+    /// Register a job of `kind` working on `key`. Fails if one is already
+    /// running for the same key.
+    pub fn start(
+        app: &AppHandle<R>,
+        kind: JobKind,
+        key: &str,
+        label: &str,
+    ) -> Result<Self, String> {
+        let (id, token) = app.state::<JobRegistry>().register(kind, key, label)?;
+        info!("Job {} started: {}", id, label);
+        Ok(Self {
+            app: app.clone(),
+            id,
+            kind,
+            token,
+        })
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Report progress, which also resets the stall timer
+    pub fn progress(&self, current: u64, total: u64, message: Option<&str>) {
+        let registry = self.app.state::<JobRegistry>();
+        if let Some(info) = registry.progress(self.id, current, total, message) {
+            if let Err(e) = self.app.emit("job-progress", info) {
+                warn!("Failed to emit job-progress: {}", e);
+            }
+        }
+    }
+
+    /// Unregister the job and report how it ended. A job stopped by
+    /// `cancel_job` returns its error and is reported as cancelled.
+    pub fn finish<T>(self, result: &Result<T, String>) {
+        // Already failed by the watchdog
+        if self.app.state::<JobRegistry>().remove(self.id).is_none() {
+            return;
+        }
+
+        let emitted = match result {
+            Ok(_) => {
+                info!("Job {} complete", self.id);
+                self.app.emit(
+                    "job-complete",
+                    JobComplete {
+                        job_id: self.id,
+                        kind: self.kind,
+                    },
+                )
+            }
+            Err(error) => {
+                let cancelled = self.is_cancelled();
+                info!(
+                    "Job {} failed (cancelled: {}): {}",
+                    self.id, cancelled, error
+                );
+                self.app.emit(
+                    "job-failed",
+                    JobFailed {
+                        job_id: self.id,
+                        kind: self.kind,
+                        error,
+                        cancelled,
+                        timed_out: false,
+                    },
+                )
+            }
+        };
+        if let Err(e) = emitted {
+            warn!("Failed to emit job outcome: {}", e);
+        }
+    }
+
+    /// Finish a job that stopped early without an error of its own
+    pub fn finish_cancelled(self) {
+        self.token.cancel();
+        self.finish::<()>(&Err("Cancelled".to_string()));
+    }
+}
+
+impl<R: Runtime> Drop for Job<R> {
+    fn drop(&mut self) {
+        if let Some(registry) = self.app.try_state::<JobRegistry>() {
+            registry.remove(self.id);
+        }
+    }
+}
+
+// ============================================================================
+// Watchdog
+// ============================================================================
+
+/// Start the watchdog thread. Needs `JobRegistry` managed.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    let app = app.clone();
+
+    let spawned = thread::Builder::new()
+        .name("job-watchdog".into())
+        .spawn(move || loop {
+            thread::sleep(WATCHDOG_TICK);
+
+            for job in app.state::<JobRegistry>().reap_stalled(Instant::now()) {
+                warn!(
+                    "Job {} ({}) stalled, giving up on it",
+                    job.job_id, job.label
+                );
+                let _ = app.emit(
+                    "job-failed",
+                    JobFailed {
+                        job_id: job.job_id,
+                        kind: job.kind,
+                        error: "Timed out",
+                        cancelled: true,
+                        timed_out: true,
+                    },
+                );
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start job watchdog: {}", e);
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Cancel a running job. Returns whether it was running.
+#[tauri::command]
+pub fn cancel_job(jobs: State<'_, JobRegistry>, job_id: u64) -> bool {
+    let cancelled = jobs.cancel(job_id);
+    if cancelled {
+        info!("Job {} cancelled", job_id);
+    }
+    cancelled
+}
+
+/// Running jobs, oldest first
+#[tauri::command]
+pub fn list_active_jobs(jobs: State<'_, JobRegistry>) -> Vec<JobInfo> {
+    jobs.active()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_job_per_kind_and_key() {
+        let registry = JobRegistry::default();
+        let (first, _) = registry
+            .register(JobKind::Index, "7", "Indexing book 7")
+            .unwrap();
+        assert!(registry.register(JobKind::Index, "7", "again").is_err());
+        assert!(registry.register(JobKind::Ocr, "7", "OCR").is_ok());
+
+        assert_eq!(registry.find(JobKind::Index, "7"), Some(first));
+        registry.remove(first);
+        assert!(registry
+            .register(JobKind::Index, "7", "Indexing book 7")
+            .is_ok());
+    }
+
+    #[test]
+    fn cancel_sets_the_token() {
+        let registry = JobRegistry::default();
+        let (id, token) = registry.register(JobKind::Ocr, "a.pdf", "OCR").unwrap();
+        assert!(!token.is_cancelled());
+        assert!(registry.cancel(id));
+        assert!(token.is_cancelled());
+        assert!(registry.active()[0].cancelled);
+        assert!(!registry.cancel(id + 1));
+    }
+
+    #[test]
+    fn progress_resets_the_stall_timer() {
+        let registry = JobRegistry::default();
+        let (id, token) = registry.register(JobKind::Index, "1", "Index").unwrap();
+        let timeout = JobKind::Index.stall_timeout();
+        let start = Instant::now();
+
+        assert!(registry.reap_stalled(start + timeout / 2).is_empty());
+        let info = registry.progress(id, 3, 10, Some("Chapter 3")).unwrap();
+        assert_eq!((info.current, info.total), (3, 10));
+
+        let later = Instant::now() + timeout + Duration::from_secs(1);
+        let reaped = registry.reap_stalled(later);
+        assert_eq!(reaped.len(), 1);
+        assert!(token.is_cancelled());
+        assert!(registry.active().is_empty());
+        assert!(registry.progress(id, 4, 10, None).is_none());
+    }
+}
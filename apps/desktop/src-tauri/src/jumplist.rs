@@ -0,0 +1,436 @@
+// Read Master Desktop - Jump List & Dock Menu
+//
+// Quick actions and recent books on the taskbar (Windows jump list) and
+// dock (macOS dock menu). Every item resolves to a `JumpAction` and goes
+// through `tray::show_and_navigate`, the same path the tray menu uses.
+//
+// Windows launches jump list items as a new process with a command-line
+// argument; the single-instance plugin forwards those to the running app,
+// and a cold start queues the action until the main window has loaded.
+
+use std::sync::Mutex;
+
+use log::{info, warn};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::webview::{PageLoadEvent, PageLoadPayload};
+use tauri::{AppHandle, Manager, Runtime, Webview};
+
+use crate::db::Database;
+use crate::tray;
+
+/// Recent books shown in the jump list / dock menu
+pub const MAX_RECENT: usize = 5;
+
+/// Command-line argument prefix used by jump list items
+const ARG_PREFIX: &str = "--jump=";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentBook {
+    pub id: i64,
+    pub title: String,
+}
+
+/// What a jump list or dock menu item does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JumpAction {
+    ContinueReading,
+    ReviewFlashcards,
+    OpenBook(i64),
+}
+
+impl JumpAction {
+    fn route(self) -> String {
+        match self {
+            Self::ContinueReading => "/reader/continue".to_string(),
+            Self::ReviewFlashcards => "/flashcards/review".to_string(),
+            Self::OpenBook(id) => format!("/reader/{}", id),
+        }
+    }
+
+    /// Command-line argument that launches this action
+    pub fn to_arg(self) -> String {
+        match self {
+            Self::ContinueReading => format!("{}continue", ARG_PREFIX),
+            Self::ReviewFlashcards => format!("{}flashcards", ARG_PREFIX),
+            Self::OpenBook(id) => format!("{}book:{}", ARG_PREFIX, id),
+        }
+    }
+
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        match arg.strip_prefix(ARG_PREFIX)? {
+            "continue" => Some(Self::ContinueReading),
+            "flashcards" => Some(Self::ReviewFlashcards),
+            other => other
+                .strip_prefix("book:")
+                .and_then(|id| id.parse().ok())
+                .map(Self::OpenBook),
+        }
+    }
+
+    /// First jump action among command-line arguments
+    pub fn from_args<S: AsRef<str>>(args: &[S]) -> Option<Self> {
+        args.iter().find_map(|arg| Self::from_arg(arg.as_ref()))
+    }
+}
+
+/// Action from a cold start, run once the main window can receive it
+#[derive(Default)]
+pub struct PendingJump(Mutex<Option<JumpAction>>);
+
+// ============================================================================
+// Dispatch
+// ============================================================================
+
+pub fn dispatch<R: Runtime>(app: &AppHandle<R>, action: JumpAction) {
+    info!("Jump action: {:?}", action);
+    tray::show_and_navigate(app, &action.route());
+}
+
+/// Queue the action this process was launched with, if any
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    let args: Vec<String> = std::env::args().collect();
+    let pending = JumpAction::from_args(&args);
+    app.manage(PendingJump(Mutex::new(pending)));
+
+    #[cfg(target_os = "macos")]
+    {
+        let app = app.clone();
+        dock::install(move |action| dispatch(&app, action));
+    }
+
+    refresh(app);
+}
+
+/// Builder-level page load hook running a queued cold-start action
+pub fn handle_page_load<R: Runtime>(webview: &Webview<R>, payload: &PageLoadPayload<'_>) {
+    if payload.event() != PageLoadEvent::Finished || webview.label() != "main" {
+        return;
+    }
+
+    let pending = webview
+        .try_state::<PendingJump>()
+        .and_then(|pending| pending.0.lock().ok()?.take());
+    if let Some(action) = pending {
+        dispatch(webview.app_handle(), action);
+    }
+}
+
+// ============================================================================
+// Publishing
+// ============================================================================
+
+/// Most recently read (or, failing that, added) books
+fn recent_books(conn: &Connection) -> rusqlite::Result<Vec<RecentBook>> {
+    let mut stmt = conn.prepare(
+        "SELECT books.id, books.title FROM books
+         LEFT JOIN (SELECT book_id, MAX(ended_at) AS last_read
+                    FROM reading_sessions GROUP BY book_id) sessions
+             ON sessions.book_id = books.id
+         WHERE books.deleted_at IS NULL
+         ORDER BY COALESCE(sessions.last_read, books.added_at) DESC
+         LIMIT ?1",
+    )?;
+    let books = stmt.query_map([MAX_RECENT as i64], |row| {
+        Ok(RecentBook {
+            id: row.get(0)?,
+            title: row.get(1)?,
+        })
+    })?;
+    books.collect()
+}
+
+fn publish(books: &[RecentBook]) {
+    let books = &books[..books.len().min(MAX_RECENT)];
+
+    #[cfg(target_os = "windows")]
+    if let Err(e) = windows_list::publish(books) {
+        warn!("Failed to update jump list: {}", e);
+    }
+
+    #[cfg(target_os = "macos")]
+    dock::set_recent(books);
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let _ = books;
+}
+
+/// Rebuild the list from the library. Called after imports and reading
+/// sessions; failures only cost a stale list.
+pub fn refresh<R: Runtime>(app: &AppHandle<R>) {
+    let Some(db) = app.try_state::<Database>() else {
+        return;
+    };
+    match db.with_conn(|conn| recent_books(conn)) {
+        Ok(books) => publish(&books),
+        Err(e) => warn!("Failed to load recent books: {}", e),
+    }
+}
+
+// ============================================================================
+// Windows
+// ============================================================================
+
+#[cfg(target_os = "windows")]
+mod windows_list {
+    use windows::core::{Interface, Result, HSTRING, PROPVARIANT};
+    use windows::Win32::Storage::EnhancedStorage::PKEY_Title;
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_INPROC_SERVER,
+        COINIT_APARTMENTTHREADED,
+    };
+    use windows::Win32::UI::Shell::Common::{IObjectArray, IObjectCollection};
+    use windows::Win32::UI::Shell::PropertiesSystem::IPropertyStore;
+    use windows::Win32::UI::Shell::{
+        DestinationList, EnumerableObjectCollection, ICustomDestinationList, IShellLinkW, ShellLink,
+    };
+
+    use super::{JumpAction, RecentBook};
+
+    fn shell_link(exe: &HSTRING, action: JumpAction, title: &str) -> Result<IShellLinkW> {
+        unsafe {
+            let link: IShellLinkW = CoCreateInstance(&ShellLink, None, CLSCTX_INPROC_SERVER)?;
+            link.SetPath(exe)?;
+            link.SetArguments(&HSTRING::from(action.to_arg()))?;
+            link.SetIconLocation(exe, 0)?;
+            link.SetDescription(&HSTRING::from(title))?;
+
+            let props: IPropertyStore = link.cast()?;
+            props.SetValue(&PKEY_Title, &PROPVARIANT::from(title))?;
+            props.Commit()?;
+            Ok(link)
+        }
+    }
+
+    /// Arguments of links the user removed from the list; re-adding them
+    /// makes the whole category fail
+    fn removed_args(removed: &IObjectArray) -> Vec<String> {
+        let count = unsafe { removed.GetCount() }.unwrap_or(0);
+        (0..count)
+            .filter_map(|i| {
+                let link: IShellLinkW = unsafe { removed.GetAt(i) }.ok()?;
+                let mut buf = [0u16; 1024];
+                unsafe { link.GetArguments(&mut buf) }.ok()?;
+                let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+                Some(String::from_utf16_lossy(&buf[..len]))
+            })
+            .collect()
+    }
+
+    fn build(books: &[RecentBook]) -> Result<()> {
+        let exe = HSTRING::from(std::env::current_exe().unwrap_or_default().as_os_str());
+
+        unsafe {
+            let list: ICustomDestinationList =
+                CoCreateInstance(&DestinationList, None, CLSCTX_INPROC_SERVER)?;
+            let mut slots = 0u32;
+            let removed: IObjectArray = list.BeginList(&mut slots)?;
+            let removed = removed_args(&removed);
+
+            let recent: IObjectCollection =
+                CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER)?;
+            for book in books {
+                let action = JumpAction::OpenBook(book.id);
+                if !removed.contains(&action.to_arg()) {
+                    recent.AddObject(&shell_link(&exe, action, &book.title)?)?;
+                }
+            }
+            if recent.GetCount()? > 0 {
+                list.AppendCategory(
+                    &HSTRING::from("Recent Books"),
+                    &recent.cast::<IObjectArray>()?,
+                )?;
+            }
+
+            let tasks: IObjectCollection =
+                CoCreateInstance(&EnumerableObjectCollection, None, CLSCTX_INPROC_SERVER)?;
+            tasks.AddObject(&shell_link(
+                &exe,
+                JumpAction::ContinueReading,
+                "Continue Reading",
+            )?)?;
+            tasks.AddObject(&shell_link(
+                &exe,
+                JumpAction::ReviewFlashcards,
+                "Review Flashcards",
+            )?)?;
+            list.AddUserTasks(&tasks.cast::<IObjectArray>()?)?;
+
+            list.CommitList()
+        }
+    }
+
+    /// Publish the jump list. COM needs an STA thread of its own.
+    pub fn publish(books: &[RecentBook]) -> std::result::Result<(), String> {
+        let books = books.to_vec();
+        std::thread::spawn(move || unsafe {
+            let initialized = CoInitializeEx(None, COINIT_APARTMENTTHREADED).is_ok();
+            let result = build(&books);
+            if initialized {
+                CoUninitialize();
+            }
+            result
+        })
+        .join()
+        .map_err(|_| "Jump list thread panicked".to_string())?
+        .map_err(|e| e.to_string())
+    }
+}
+
+// ============================================================================
+// macOS
+// ============================================================================
+
+#[cfg(target_os = "macos")]
+mod dock {
+    use std::sync::{Mutex, Once, OnceLock};
+
+    use cocoa::appkit::{NSApp, NSMenu, NSMenuItem};
+    use cocoa::base::{id, nil};
+    use cocoa::foundation::{NSAutoreleasePool, NSString};
+    use objc::declare::ClassDecl;
+    use objc::runtime::{class_addMethod, object_getClass, Class, Imp, Object, Sel};
+    use objc::{class, msg_send, sel, sel_impl};
+
+    use super::{JumpAction, RecentBook};
+
+    /// Item tags encoding the action: positive tags are book ids
+    const TAG_CONTINUE: i64 = -1;
+    const TAG_FLASHCARDS: i64 = -2;
+
+    type Handler = Box<dyn Fn(JumpAction) + Send + Sync>;
+
+    static RECENT: Mutex<Vec<RecentBook>> = Mutex::new(Vec::new());
+    static HANDLER: OnceLock<Handler> = OnceLock::new();
+    /// Target object for menu items (menu items hold it weakly)
+    static TARGET: OnceLock<usize> = OnceLock::new();
+
+    pub fn set_recent(books: &[RecentBook]) {
+        if let Ok(mut recent) = RECENT.lock() {
+            *recent = books.to_vec();
+        }
+    }
+
+    fn action_for(tag: i64) -> Option<JumpAction> {
+        match tag {
+            TAG_CONTINUE => Some(JumpAction::ContinueReading),
+            TAG_FLASHCARDS => Some(JumpAction::ReviewFlashcards),
+            id if id > 0 => Some(JumpAction::OpenBook(id)),
+            _ => None,
+        }
+    }
+
+    extern "C" fn item_selected(_this: &Object, _sel: Sel, sender: id) {
+        let tag: i64 = unsafe { msg_send![sender, tag] };
+        if let (Some(action), Some(handler)) = (action_for(tag), HANDLER.get()) {
+            handler(action);
+        }
+    }
+
+    unsafe fn menu_item(title: &str, tag: i64, target: id) -> id {
+        let item = NSMenuItem::alloc(nil)
+            .initWithTitle_action_keyEquivalent_(
+                NSString::alloc(nil).init_str(title).autorelease(),
+                sel!(jumpItemSelected:),
+                NSString::alloc(nil).init_str("").autorelease(),
+            )
+            .autorelease();
+        let _: () = msg_send![item, setTag: tag];
+        let _: () = msg_send![item, setTarget: target];
+        item
+    }
+
+    /// `applicationDockMenu:`, built fresh each time the menu opens
+    extern "C" fn dock_menu(_this: &Object, _sel: Sel, _app: id) -> id {
+        let Some(&target) = TARGET.get() else {
+            return nil;
+        };
+        let target = target as id;
+        let recent = RECENT.lock().map(|r| r.clone()).unwrap_or_default();
+
+        unsafe {
+            let menu = NSMenu::new(nil).autorelease();
+            menu.addItem_(menu_item("Continue Reading", TAG_CONTINUE, target));
+            menu.addItem_(menu_item("Review Flashcards", TAG_FLASHCARDS, target));
+            if !recent.is_empty() {
+                menu.addItem_(NSMenuItem::separatorItem(nil));
+                for book in &recent {
+                    menu.addItem_(menu_item(&book.title, book.id, target));
+                }
+            }
+            menu
+        }
+    }
+
+    /// Add the dock menu to the app delegate. Must run on the main thread
+    /// after launch.
+    pub fn install(handler: impl Fn(JumpAction) + Send + Sync + 'static) {
+        static INSTALL: Once = Once::new();
+
+        let _ = HANDLER.set(Box::new(handler));
+        INSTALL.call_once(|| unsafe {
+            let mut decl = ClassDecl::new("ReadMasterDockTarget", class!(NSObject))
+                .expect("dock target class already registered");
+            decl.add_method(
+                sel!(jumpItemSelected:),
+                item_selected as extern "C" fn(&Object, Sel, id),
+            );
+            let target: id = msg_send![decl.register(), new];
+            let _ = TARGET.set(target as usize);
+
+            let delegate: id = msg_send![NSApp(), delegate];
+            if delegate == nil {
+                log::warn!("No app delegate; dock menu unavailable");
+                return;
+            }
+            let imp: Imp = std::mem::transmute(dock_menu as extern "C" fn(&Object, Sel, id) -> id);
+            class_addMethod(
+                object_getClass(delegate) as *mut Class,
+                sel!(applicationDockMenu:),
+                imp,
+                c"@@:@".as_ptr(),
+            );
+        });
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Replace the recent books in the jump list / dock menu
+#[tauri::command]
+pub async fn update_jump_list(recent_books: Vec<RecentBook>) -> Result<(), String> {
+    info!("Updating jump list with {} book(s)", recent_books.len());
+    tauri::async_runtime::spawn_blocking(move || publish(&recent_books))
+        .await
+        .map_err(|e| format!("Jump list task failed: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_round_trip_through_args() {
+        for action in [
+            JumpAction::ContinueReading,
+            JumpAction::ReviewFlashcards,
+            JumpAction::OpenBook(42),
+        ] {
+            assert_eq!(JumpAction::from_arg(&action.to_arg()), Some(action));
+        }
+    }
+
+    #[test]
+    fn finds_action_among_other_args() {
+        let args = ["read-master.exe", "--flag", "--jump=book:7"];
+        assert_eq!(JumpAction::from_args(&args), Some(JumpAction::OpenBook(7)));
+        assert_eq!(JumpAction::from_args(&["--jump=book:x"]), None);
+    }
+}
@@ -0,0 +1,53 @@
+// Read Master Desktop - Keychain
+//
+// Secrets (API keys, passwords) stored in the OS credential store.
+
+use keyring::Entry;
+use log::info;
+
+/// Service name under which all secrets are stored
+const SERVICE: &str = "com.readmaster.app";
+
+fn entry(key: &str) -> Result<Entry, String> {
+    Entry::new(SERVICE, key).map_err(|e| format!("Failed to access keychain: {}", e))
+}
+
+/// Read a secret, returning `None` when it has never been set
+pub fn get_secret(key: &str) -> Result<Option<String>, String> {
+    match entry(key)?.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to read secret '{}': {}", key, e)),
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Store a secret in the OS keychain
+#[tauri::command]
+pub async fn set_secret(key: String, value: String) -> Result<(), String> {
+    info!("Storing secret: {}", key);
+
+    entry(&key)?
+        .set_password(&value)
+        .map_err(|e| format!("Failed to store secret '{}': {}", key, e))
+}
+
+/// Remove a secret from the OS keychain
+#[tauri::command]
+pub async fn delete_secret(key: String) -> Result<(), String> {
+    info!("Deleting secret: {}", key);
+
+    match entry(&key)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(format!("Failed to delete secret '{}': {}", key, e)),
+    }
+}
+
+/// Whether a secret is set. Secret values are never sent to the frontend.
+#[tauri::command]
+pub async fn has_secret(key: String) -> Result<bool, String> {
+    Ok(get_secret(&key)?.is_some())
+}
@@ -0,0 +1,304 @@
+// Read Master Desktop - Language Detection
+//
+// A book's language from its text, since the declared `dc:language` is
+// often missing or just the publisher's default. Text is sampled from
+// chapters (or pages) spread through the book, so front matter in another
+// language doesn't decide it, and run through whatlang's trigram detector.
+//
+// Codes are ISO 639-1 where one exists, matching dictionary and voice
+// tags; the declared language is used when detection isn't confident.
+
+use std::path::Path;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::epub::EpubArchive;
+use crate::pdf;
+
+/// Characters of text sampled in total
+const SAMPLE_CHARS: usize = 20_000;
+
+/// Chapters or pages the sample is drawn from
+const SAMPLE_PARTS: usize = 8;
+
+/// Detector confidence below which declared metadata is preferred
+const MIN_CONFIDENCE: f64 = 0.5;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LanguageSource {
+    Detected,
+    Metadata,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LanguageDetection {
+    /// ISO 639-1 code, or 639-3 for languages without one
+    pub code: String,
+    /// Detector confidence (0–1), also reported when metadata won
+    pub confidence: f64,
+    pub source: LanguageSource,
+}
+
+// ============================================================================
+// Codes
+// ============================================================================
+
+/// ISO 639-1 code for a whatlang (ISO 639-3) code
+fn iso639_1(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "afr" => "af",
+        "aka" => "ak",
+        "amh" => "am",
+        "ara" => "ar",
+        "aze" => "az",
+        "bel" => "be",
+        "ben" => "bn",
+        "bul" => "bg",
+        "cat" => "ca",
+        "ces" => "cs",
+        "cmn" => "zh",
+        "dan" => "da",
+        "deu" => "de",
+        "ell" => "el",
+        "eng" => "en",
+        "epo" => "eo",
+        "est" => "et",
+        "fin" => "fi",
+        "fra" => "fr",
+        "guj" => "gu",
+        "heb" => "he",
+        "hin" => "hi",
+        "hrv" => "hr",
+        "hun" => "hu",
+        "hye" => "hy",
+        "ind" => "id",
+        "ita" => "it",
+        "jav" => "jv",
+        "jpn" => "ja",
+        "kan" => "kn",
+        "kat" => "ka",
+        "khm" => "km",
+        "kor" => "ko",
+        "lat" => "la",
+        "lav" => "lv",
+        "lit" => "lt",
+        "mal" => "ml",
+        "mar" => "mr",
+        "mkd" => "mk",
+        "mya" => "my",
+        "nep" => "ne",
+        "nld" => "nl",
+        "nob" => "nb",
+        "ori" => "or",
+        "pan" => "pa",
+        "pes" => "fa",
+        "pol" => "pl",
+        "por" => "pt",
+        "ron" => "ro",
+        "rus" => "ru",
+        "sin" => "si",
+        "slk" => "sk",
+        "slv" => "sl",
+        "sna" => "sn",
+        "spa" => "es",
+        "srp" => "sr",
+        "swe" => "sv",
+        "tam" => "ta",
+        "tel" => "te",
+        "tgl" => "tl",
+        "tha" => "th",
+        "tuk" => "tk",
+        "tur" => "tr",
+        "ukr" => "uk",
+        "urd" => "ur",
+        "uzb" => "uz",
+        "vie" => "vi",
+        "yid" => "yi",
+        "zul" => "zu",
+        _ => return None,
+    })
+}
+
+/// Primary subtag of a language tag: "pt-BR" is "pt"
+pub fn primary_subtag(tag: &str) -> &str {
+    tag.split(['-', '_']).next().unwrap_or(tag)
+}
+
+// ============================================================================
+// Sampling
+// ============================================================================
+
+/// Indices of `parts` items spread evenly across `count`
+fn spread(count: usize, parts: usize) -> Vec<usize> {
+    if count <= parts {
+        return (0..count).collect();
+    }
+    (0..parts)
+        .map(|i| (2 * i + 1) * count / (2 * parts))
+        .collect()
+}
+
+/// Append up to `limit` bytes of `text`, cut at a char boundary
+fn push_sample(sample: &mut String, text: &str, limit: usize) {
+    let end = (0..=limit.min(text.len()))
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0);
+    sample.push_str(&text[..end]);
+    sample.push('\n');
+}
+
+/// Sampled text and the declared language of an EPUB
+fn sample_epub(path: &str) -> Result<(String, Option<String>), String> {
+    let mut epub = EpubArchive::open(path).map_err(|e| e.to_string())?;
+    let declared = epub.package.language.clone();
+    let chapters = epub.spine_paths().len();
+
+    let mut sample = String::new();
+    for index in spread(chapters, SAMPLE_PARTS) {
+        match epub.chapter_text(index) {
+            Ok(chapter) => push_sample(&mut sample, &chapter.text, SAMPLE_CHARS / SAMPLE_PARTS),
+            Err(e) => warn!("Skipping chapter {} for language sample: {}", index, e),
+        }
+    }
+    Ok((sample, declared))
+}
+
+fn sample_pdf(path: &str) -> Result<String, String> {
+    let file = pdf::load(path)?;
+    let pages = file.num_pages() as usize;
+
+    let mut sample = String::new();
+    for index in spread(pages, SAMPLE_PARTS) {
+        match pdf::page_text(&file, index as u32) {
+            Ok(text) => push_sample(&mut sample, &text, SAMPLE_CHARS / SAMPLE_PARTS),
+            Err(e) => warn!("Skipping page {} for language sample: {}", index, e),
+        }
+    }
+    Ok(sample)
+}
+
+// ============================================================================
+// Detection
+// ============================================================================
+
+/// Detected language of `text` as (code, confidence)
+fn detect_text(text: &str) -> Option<(String, f64)> {
+    let info = whatlang::detect(text)?;
+    let code = info.lang().code();
+    let code = iso639_1(code).unwrap_or(code);
+    Some((code.to_string(), info.confidence()))
+}
+
+/// Prefer the detector unless it's unsure and the book declares a language
+fn choose(detected: Option<(String, f64)>, declared: Option<&str>) -> Option<LanguageDetection> {
+    let declared = declared
+        .map(str::trim)
+        .filter(|d| !d.is_empty() && *d != "und");
+    match (detected, declared) {
+        (Some((_, confidence)), Some(declared)) if confidence < MIN_CONFIDENCE => {
+            Some(LanguageDetection {
+                code: declared.to_string(),
+                confidence,
+                source: LanguageSource::Metadata,
+            })
+        }
+        (Some((code, confidence)), _) => Some(LanguageDetection {
+            code,
+            confidence,
+            source: LanguageSource::Detected,
+        }),
+        (None, Some(declared)) => Some(LanguageDetection {
+            code: declared.to_string(),
+            confidence: 0.0,
+            source: LanguageSource::Metadata,
+        }),
+        (None, None) => None,
+    }
+}
+
+/// Language of the book at `path` (EPUB or PDF). Blocking.
+pub fn detect(path: &str) -> Result<LanguageDetection, String> {
+    let is_pdf = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"));
+    let (sample, declared) = if is_pdf {
+        (sample_pdf(path)?, None)
+    } else {
+        sample_epub(path)?
+    };
+
+    choose(detect_text(&sample), declared.as_deref())
+        .ok_or_else(|| "Could not detect the book's language".to_string())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Detect a book's language from its text, falling back to the declared
+/// language when the detector isn't confident
+#[tauri::command]
+pub async fn detect_language(path: String) -> Result<LanguageDetection, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let detection = detect(&path)?;
+        info!(
+            "Detected language of {}: {} ({:?}, {:.2})",
+            path, detection.code, detection.source, detection.confidence
+        );
+        Ok(detection)
+    })
+    .await
+    .map_err(|e| format!("Detection task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_with_iso639_1_codes() {
+        let (code, confidence) = detect_text(
+            "Il était une fois, dans un petit village au bord de la mer, une vieille femme \
+             qui vivait seule avec son chat et qui racontait des histoires aux enfants.",
+        )
+        .unwrap();
+        assert_eq!(code, "fr");
+        assert!(confidence > MIN_CONFIDENCE);
+    }
+
+    #[test]
+    fn falls_back_to_metadata_when_unsure() {
+        let unsure = Some(("en".to_string(), 0.2));
+        let chosen = choose(unsure.clone(), Some("de-DE")).unwrap();
+        assert_eq!(chosen.code, "de-DE");
+        assert_eq!(chosen.source, LanguageSource::Metadata);
+        assert_eq!(chosen.confidence, 0.2);
+
+        let sure = Some(("fr".to_string(), 0.9));
+        assert_eq!(choose(sure, Some("en")).unwrap().code, "fr");
+        assert_eq!(choose(unsure, Some("und")).unwrap().code, "en");
+        assert_eq!(choose(None, Some("it")).unwrap().code, "it");
+        assert!(choose(None, None).is_none());
+    }
+
+    #[test]
+    fn spreads_samples_across_the_book() {
+        assert_eq!(spread(3, 8), [0, 1, 2]);
+        assert_eq!(spread(100, 4), [12, 37, 62, 87]);
+    }
+
+    #[test]
+    fn takes_primary_subtags() {
+        assert_eq!(primary_subtag("pt-BR"), "pt");
+        assert_eq!(primary_subtag("en_US"), "en");
+        assert_eq!(primary_subtag("fr"), "fr");
+    }
+}
@@ -0,0 +1,96 @@
+// Read Master Desktop - Book Layout
+//
+// Per-book reading layout (zoom, columns, margins, line height), persisted
+// to `reading-state.json` keyed by book id. The window-state plugin covers
+// the window itself; this covers how the content is laid out inside it.
+// The reader applies the layout on open and saves it, debounced, on change.
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime};
+
+use crate::persist;
+use crate::profiles::ProfileStoreExt;
+
+const STORE_FILE: &str = "reading-state.json";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookLayout {
+    /// Content zoom, 1.0 being 100%
+    pub zoom: f64,
+    pub columns: u32,
+    /// Page margin in CSS pixels
+    pub margin: f64,
+    /// Line height as a multiple of the font size
+    pub line_height: f64,
+}
+
+impl BookLayout {
+    /// Clamp values to what the reader can render, so a bad save can't
+    /// leave a book unreadable
+    fn clamped(self) -> Self {
+        Self {
+            zoom: self.zoom.clamp(0.25, 5.0),
+            columns: self.columns.clamp(1, 3),
+            margin: self.margin.clamp(0.0, 400.0),
+            line_height: self.line_height.clamp(0.8, 3.0),
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        [self.zoom, self.margin, self.line_height]
+            .iter()
+            .all(|v| v.is_finite())
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Remember a book's layout
+#[tauri::command]
+pub async fn save_book_layout<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: String,
+    layout: BookLayout,
+) -> Result<(), String> {
+    if !layout.is_valid() {
+        return Err("Layout values must be finite numbers".to_string());
+    }
+
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    let value = serde_json::to_value(layout.clamped())
+        .map_err(|e| format!("Failed to serialize layout: {}", e))?;
+
+    info!("Saving layout for book {}", book_id);
+    store.set(book_id, value);
+    persist::mark_dirty(&app, STORE_FILE);
+
+    Ok(())
+}
+
+/// A book's saved layout, if it has one
+#[tauri::command]
+pub async fn get_book_layout<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: String,
+) -> Result<Option<BookLayout>, String> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    match store.get(&book_id) {
+        Some(value) => serde_json::from_value(value)
+            .map(Some)
+            .map_err(|e| format!("Corrupt layout data: {}", e)),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,478 @@
+// Read Master Desktop - Library
+//
+// Book records stored in the library database.
+
+use std::path::PathBuf;
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Runtime, State};
+
+use crate::collections;
+use crate::db::Database;
+use crate::goals;
+use crate::profiles::{self, ProfileStoreExt};
+use crate::scripting::{self, Hook};
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Book {
+    pub id: i64,
+    pub title: String,
+    pub author: Option<String>,
+    pub isbn: Option<String>,
+    pub isbn13: Option<String>,
+    pub path: Option<String>,
+    pub format: Option<String>,
+    pub on_disk: bool,
+    pub page_count: Option<i64>,
+    pub word_count: Option<i64>,
+    pub series_name: Option<String>,
+    pub series_index: Option<f64>,
+    /// Reading progress from 0.0 to 1.0
+    pub progress: Option<f64>,
+    pub rating: Option<i64>,
+    pub review: Option<String>,
+    pub added_at: String,
+    pub finished_at: Option<String>,
+    pub updated_at: String,
+    pub description: Option<String>,
+    pub cover_path: Option<String>,
+    pub publish_year: Option<i64>,
+    /// SHA-256 of the book file, for duplicate detection
+    pub content_hash: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// Fields for creating or updating a book record
+#[derive(Debug, Clone, Default)]
+pub struct BookFields {
+    pub title: String,
+    pub author: Option<String>,
+    pub isbn: Option<String>,
+    pub isbn13: Option<String>,
+    pub path: Option<String>,
+    pub format: Option<String>,
+    pub content_hash: Option<String>,
+    pub series_name: Option<String>,
+    pub series_index: Option<f64>,
+    pub page_count: Option<i64>,
+    pub rating: Option<i64>,
+    pub review: Option<String>,
+    pub added_at: Option<String>,
+    pub finished_at: Option<String>,
+}
+
+/// A library row when grouping by series: either a whole series or a
+/// standalone book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum LibraryEntry {
+    #[serde(rename_all = "camelCase")]
+    Series {
+        series_name: String,
+        books: Vec<Book>,
+    },
+    Book {
+        book: Book,
+    },
+}
+
+/// `db_list_books` result: flat, or grouped when `group_by_series` is set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BookListing {
+    Flat(Vec<Book>),
+    Grouped(Vec<LibraryEntry>),
+}
+
+pub(crate) const BOOK_COLUMNS: &str = "books.id, books.title, books.author, books.isbn, \
+     books.isbn13, books.path, books.format, books.on_disk, books.page_count, books.word_count, \
+     books.series_name, books.series_index, books.progress, books.rating, books.review, \
+     books.added_at, books.finished_at, books.updated_at, books.description, books.cover_path, \
+     books.publish_year, books.content_hash";
+
+/// Setting holding a library folder chosen by the user
+pub const ROOT_SETTING: &str = "library.root";
+
+/// Folder covers are kept in inside a chosen library folder
+pub const COVERS_FOLDER: &str = ".covers";
+
+/// The folder set in `library.root`, if any
+fn custom_root<R: Runtime>(app: &AppHandle<R>) -> Option<PathBuf> {
+    app.profile_store("settings.json")
+        .ok()
+        .and_then(|s| s.get(ROOT_SETTING))
+        .and_then(|v| v.as_str().map(PathBuf::from))
+        .filter(|path| path.is_absolute())
+}
+
+fn app_data_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    profiles::data_dir(app)
+}
+
+/// Directory book files are stored in: `library.root`, or `library` in
+/// app data
+pub fn library_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    let dir = match custom_root(app) {
+        Some(dir) => dir,
+        None => app_data_dir(app)?.join("library"),
+    };
+
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create library directory: {}", e))?;
+    Ok(dir)
+}
+
+/// Directory saved covers go in. Inside a chosen library folder, so they
+/// move with it; otherwise `covers` in app data.
+pub fn covers_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    match custom_root(app) {
+        Some(dir) => Ok(dir.join(COVERS_FOLDER)),
+        None => Ok(app_data_dir(app)?.join("covers")),
+    }
+}
+
+// ============================================================================
+// Queries
+// ============================================================================
+
+pub(crate) fn book_from_row(row: &Row) -> rusqlite::Result<Book> {
+    Ok(Book {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        author: row.get(2)?,
+        isbn: row.get(3)?,
+        isbn13: row.get(4)?,
+        path: row.get(5)?,
+        format: row.get(6)?,
+        on_disk: row.get(7)?,
+        page_count: row.get(8)?,
+        word_count: row.get(9)?,
+        series_name: row.get(10)?,
+        series_index: row.get(11)?,
+        progress: row.get(12)?,
+        rating: row.get(13)?,
+        review: row.get(14)?,
+        added_at: row.get(15)?,
+        finished_at: row.get(16)?,
+        updated_at: row.get(17)?,
+        description: row.get(18)?,
+        cover_path: row.get(19)?,
+        publish_year: row.get(20)?,
+        content_hash: row.get(21)?,
+        tags: Vec::new(),
+    })
+}
+
+/// Load the tags of a book, sorted alphabetically
+pub fn book_tags(conn: &Connection, book_id: i64) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT tag FROM book_tags WHERE book_id = ?1 ORDER BY tag")?;
+    let tags = stmt
+        .query_map([book_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(tags)
+}
+
+/// Fetch a single book by id
+pub fn get_book(conn: &Connection, id: i64) -> rusqlite::Result<Option<Book>> {
+    let book = conn
+        .query_row(
+            &format!("SELECT {} FROM books WHERE id = ?1", BOOK_COLUMNS),
+            [id],
+            book_from_row,
+        )
+        .optional()?;
+
+    match book {
+        Some(mut book) => {
+            book.tags = book_tags(conn, book.id)?;
+            Ok(Some(book))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Find a book by the SHA-256 of its file
+pub fn find_by_hash(conn: &Connection, hash: &str) -> rusqlite::Result<Option<i64>> {
+    conn.query_row(
+        "SELECT id FROM books WHERE content_hash = ?1 AND deleted_at IS NULL LIMIT 1",
+        [hash],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// List every book in the library (excluding the trash), most recently
+/// added first
+pub fn list_books(conn: &Connection) -> rusqlite::Result<Vec<Book>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM books WHERE books.deleted_at IS NULL
+         ORDER BY books.added_at DESC, books.id DESC",
+        BOOK_COLUMNS
+    ))?;
+    let mut books = stmt
+        .query_map([], book_from_row)?
+        .collect::<rusqlite::Result<Vec<Book>>>()?;
+
+    for book in &mut books {
+        book.tags = book_tags(conn, book.id)?;
+    }
+
+    Ok(books)
+}
+
+/// Group books by series, keeping the order of `books` (a series sits where
+/// its first book would). Books within a series are ordered by index.
+pub fn group_by_series(books: Vec<Book>) -> Vec<LibraryEntry> {
+    let mut entries: Vec<LibraryEntry> = Vec::new();
+    let mut positions: std::collections::HashMap<String, usize> = Default::default();
+
+    for book in books {
+        let Some(name) = book.series_name.clone() else {
+            entries.push(LibraryEntry::Book { book });
+            continue;
+        };
+
+        let key = name.to_lowercase();
+        match positions.get(&key) {
+            Some(&i) => {
+                if let LibraryEntry::Series { books, .. } = &mut entries[i] {
+                    books.push(book);
+                }
+            }
+            None => {
+                positions.insert(key, entries.len());
+                entries.push(LibraryEntry::Series {
+                    series_name: name,
+                    books: vec![book],
+                });
+            }
+        }
+    }
+
+    for entry in &mut entries {
+        if let LibraryEntry::Series { books, .. } = entry {
+            // Unnumbered books go last
+            books.sort_by(|a, b| match (a.series_index, b.series_index) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => a.title.cmp(&b.title),
+            });
+        }
+    }
+
+    entries
+}
+
+/// Insert a new book record, returning its id
+pub fn insert_book(conn: &Connection, fields: &BookFields) -> rusqlite::Result<i64> {
+    let now = chrono::Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO books (title, author, isbn, isbn13, path, format, on_disk, page_count,
+                            rating, review, added_at, finished_at, updated_at, content_hash,
+                            series_name, series_index)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+        params![
+            fields.title,
+            fields.author,
+            fields.isbn,
+            fields.isbn13,
+            fields.path,
+            fields.format,
+            fields.path.is_some(),
+            fields.page_count,
+            fields.rating,
+            fields.review,
+            fields.added_at.clone().unwrap_or_else(|| now.clone()),
+            fields.finished_at,
+            now,
+            fields.content_hash,
+            fields.series_name,
+            fields.series_index,
+        ],
+    )?;
+
+    let id = conn.last_insert_rowid();
+    collections::refresh_book_memberships(conn, id)?;
+    Ok(id)
+}
+
+/// Merge `fields` into an existing record. Present values overwrite,
+/// absent ones leave the stored value alone.
+pub fn update_book(conn: &Connection, id: i64, fields: &BookFields) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE books SET
+            author = COALESCE(?2, author),
+            isbn = COALESCE(?3, isbn),
+            isbn13 = COALESCE(?4, isbn13),
+            page_count = COALESCE(?5, page_count),
+            rating = COALESCE(?6, rating),
+            review = COALESCE(?7, review),
+            finished_at = COALESCE(?8, finished_at),
+            updated_at = ?9
+         WHERE id = ?1",
+        params![
+            id,
+            fields.author,
+            fields.isbn,
+            fields.isbn13,
+            fields.page_count,
+            fields.rating,
+            fields.review,
+            fields.finished_at,
+            chrono::Utc::now().to_rfc3339(),
+        ],
+    )?;
+
+    collections::refresh_book_memberships(conn, id)
+}
+
+/// Descriptive metadata from an online lookup
+#[derive(Debug, Clone, Default)]
+pub struct BookDetails {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub publish_year: Option<i64>,
+    pub isbn: Option<String>,
+    pub isbn13: Option<String>,
+    pub cover_path: Option<String>,
+}
+
+/// Overwrite descriptive metadata with looked-up values; absent values
+/// leave the stored ones alone
+pub fn update_details(conn: &Connection, id: i64, details: &BookDetails) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE books SET
+            title = COALESCE(?2, title),
+            author = COALESCE(?3, author),
+            description = COALESCE(?4, description),
+            publish_year = COALESCE(?5, publish_year),
+            isbn = COALESCE(?6, isbn),
+            isbn13 = COALESCE(?7, isbn13),
+            cover_path = COALESCE(?8, cover_path),
+            updated_at = ?9
+         WHERE id = ?1",
+        params![
+            id,
+            details.title,
+            details.author,
+            details.description,
+            details.publish_year,
+            details.isbn,
+            details.isbn13,
+            details.cover_path,
+            chrono::Utc::now().to_rfc3339(),
+        ],
+    )?;
+
+    collections::refresh_book_memberships(conn, id)
+}
+
+/// Set a book's reading progress (0.0 to 1.0)
+pub fn set_progress(conn: &Connection, id: i64, progress: Option<f64>) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE books SET progress = ?2, updated_at = ?3 WHERE id = ?1",
+        params![
+            id,
+            progress.map(|p| p.clamp(0.0, 1.0)),
+            chrono::Utc::now().to_rfc3339()
+        ],
+    )?;
+    Ok(())
+}
+
+/// Attach tags to a book, ignoring ones it already has
+pub fn add_tags(conn: &Connection, book_id: i64, tags: &[String]) -> rusqlite::Result<()> {
+    let mut stmt =
+        conn.prepare("INSERT OR IGNORE INTO book_tags (book_id, tag) VALUES (?1, ?2)")?;
+    for tag in tags {
+        stmt.execute(params![book_id, tag])?;
+    }
+
+    collections::refresh_book_memberships(conn, book_id)
+}
+
+/// Detach tags from a book
+pub fn remove_tags(conn: &Connection, book_id: i64, tags: &[String]) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare("DELETE FROM book_tags WHERE book_id = ?1 AND tag = ?2")?;
+    for tag in tags {
+        stmt.execute(params![book_id, tag])?;
+    }
+
+    collections::refresh_book_memberships(conn, book_id)
+}
+
+/// Record a finished read in the reading history
+pub fn record_finished(
+    conn: &Connection,
+    book_id: i64,
+    finished_at: &str,
+    rating: Option<i64>,
+    source: &str,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO reading_history (book_id, finished_at, rating, source)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (book_id, finished_at) DO UPDATE SET rating = excluded.rating",
+        params![book_id, finished_at, rating, source],
+    )?;
+    Ok(())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// List all books in the library, optionally grouped by series
+#[tauri::command]
+pub async fn db_list_books(
+    db: State<'_, Database>,
+    group_by_series: Option<bool>,
+) -> Result<BookListing, String> {
+    let books = db.with_conn(|conn| list_books(conn))?;
+
+    Ok(if group_by_series.unwrap_or(false) {
+        BookListing::Grouped(self::group_by_series(books))
+    } else {
+        BookListing::Flat(books)
+    })
+}
+
+/// Mark a book as finished now, recording the read in the history
+#[tauri::command]
+pub async fn mark_book_finished<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    book_id: i64,
+    rating: Option<i64>,
+) -> Result<(), String> {
+    let now = chrono::Utc::now().to_rfc3339();
+    let found = db.with_conn(|conn| {
+        let updated = conn.execute(
+            "UPDATE books SET finished_at = ?2, rating = COALESCE(?3, rating), updated_at = ?2
+             WHERE id = ?1 AND deleted_at IS NULL",
+            params![book_id, now, rating],
+        )?;
+        if updated == 0 {
+            return Ok(false);
+        }
+        record_finished(conn, book_id, &now, rating, "app")?;
+        collections::refresh_book_memberships(conn, book_id)?;
+        Ok(true)
+    })?;
+    if !found {
+        return Err(format!("Book not found: {}", book_id));
+    }
+
+    scripting::trigger_in_background(&app, Hook::OnFinishBook, Some(book_id), Value::Null);
+    goals::refresh(&app);
+    Ok(())
+}
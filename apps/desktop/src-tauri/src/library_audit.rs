@@ -0,0 +1,811 @@
+// Read Master Desktop - Library Audit
+//
+// Finds library records that need attention, and the tools to fix them.
+// `audit_library` checks every book's file and hash, groups likely
+// duplicates (same file, same ISBN, or same title and author), and lists
+// cover files no record uses.
+//
+// `merge_books` folds duplicates into one record: their notes, bookmarks
+// and tags move to the primary, which keeps the furthest progress, and the
+// duplicates are soft-deleted. Each merge is recorded, so `undo_merge` can
+// take it back for as long as the duplicates can be restored. Moved notes
+// keep the hash of the file they were made in, so re-anchoring finds them.
+//
+// `relink_book` points a record whose file has moved at its new location.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::collections;
+use crate::db::Database;
+use crate::import::{self, BookFormat};
+use crate::jobs::{Job, JobKind};
+use crate::library::{self, Book};
+use crate::library_files::RESTORE_WINDOW_DAYS;
+use crate::notes;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// What the audit needs of a record
+#[derive(Debug, Clone)]
+struct AuditBook {
+    id: i64,
+    title: String,
+    author: Option<String>,
+    isbn: Option<String>,
+    isbn13: Option<String>,
+    path: Option<String>,
+    content_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub book_id: i64,
+    pub title: String,
+    pub author: Option<String>,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HashMismatch {
+    #[serde(flatten)]
+    pub book: AuditEntry,
+    /// Hash recorded at import
+    pub expected_hash: String,
+    /// Hash of the file as it is now
+    pub actual_hash: String,
+}
+
+/// Why books were grouped as duplicates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicateReason {
+    SameFile,
+    SameIsbn,
+    SameTitle,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateCluster {
+    pub reasons: Vec<DuplicateReason>,
+    pub books: Vec<AuditEntry>,
+    /// The oldest book whose file is present, to keep when merging
+    pub suggested_primary: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditReport {
+    /// Books looked at
+    pub checked: usize,
+    pub missing_files: Vec<AuditEntry>,
+    pub hash_mismatches: Vec<HashMismatch>,
+    pub duplicate_clusters: Vec<DuplicateCluster>,
+    /// Files in the covers folder no book (deleted or not) refers to
+    pub orphaned_covers: Vec<String>,
+}
+
+/// What a merge changed, so it can be undone
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeUndo {
+    duplicate_ids: Vec<i64>,
+    /// Moved bookmarks, with the book each came from
+    bookmarks: Vec<(String, i64)>,
+    /// Moved notes, with the book each came from
+    notes: Vec<(String, String)>,
+    /// Tags the primary gained
+    added_tags: Vec<String>,
+    /// The primary's progress before the merge
+    previous_progress: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeReport {
+    /// Pass to `undo_merge`
+    pub merge_id: i64,
+    pub primary: Book,
+    pub merged_ids: Vec<i64>,
+    pub moved_notes: usize,
+    pub moved_bookmarks: usize,
+    pub added_tags: Vec<String>,
+    /// Last moment `undo_merge` will work
+    pub undoable_until: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelinkReport {
+    pub book: Book,
+    /// The new file differs from the one the book was imported from;
+    /// annotations may need re-anchoring
+    pub hash_changed: bool,
+}
+
+// ============================================================================
+// Duplicates
+// ============================================================================
+
+/// Digits (and a final X) of an ISBN-10 or ISBN-13
+fn normalize_isbn(isbn: &str) -> Option<String> {
+    let isbn: String = isbn
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_uppercase();
+    matches!(isbn.len(), 10 | 13).then_some(isbn)
+}
+
+/// Lowercased words, ignoring punctuation and spacing
+fn normalize_words(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn duplicate_keys(book: &AuditBook) -> Vec<(DuplicateReason, String)> {
+    let mut keys = Vec::new();
+    if let Some(hash) = book.content_hash.as_deref().filter(|h| !h.is_empty()) {
+        keys.push((DuplicateReason::SameFile, hash.to_string()));
+    }
+    for isbn in [&book.isbn, &book.isbn13].into_iter().flatten() {
+        if let Some(isbn) = normalize_isbn(isbn) {
+            keys.push((DuplicateReason::SameIsbn, isbn));
+        }
+    }
+    let title = normalize_words(&book.title);
+    if !title.is_empty() {
+        let author = normalize_words(book.author.as_deref().unwrap_or(""));
+        keys.push((DuplicateReason::SameTitle, format!("{}|{}", title, author)));
+    }
+    keys
+}
+
+fn root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+fn entry(book: &AuditBook) -> AuditEntry {
+    AuditEntry {
+        book_id: book.id,
+        title: book.title.clone(),
+        author: book.author.clone(),
+        path: book.path.clone(),
+    }
+}
+
+/// Group books sharing a file hash, ISBN, or title and author. `present`
+/// holds the books whose file exists, preferred as the primary.
+fn duplicate_clusters(books: &[AuditBook], present: &HashSet<i64>) -> Vec<DuplicateCluster> {
+    let mut parents: Vec<usize> = (0..books.len()).collect();
+    let mut first_with_key: HashMap<(DuplicateReason, String), usize> = HashMap::new();
+    let mut matched: Vec<(usize, DuplicateReason)> = Vec::new();
+
+    for (i, book) in books.iter().enumerate() {
+        for key in duplicate_keys(book) {
+            let reason = key.0;
+            match first_with_key.get(&key) {
+                Some(&first) => {
+                    let (a, b) = (root(&mut parents, first), root(&mut parents, i));
+                    parents[b.max(a)] = a.min(b);
+                    matched.push((i, reason));
+                }
+                None => {
+                    first_with_key.insert(key, i);
+                }
+            }
+        }
+    }
+
+    let mut clusters: Vec<(usize, Vec<usize>)> = Vec::new();
+    let mut by_root: HashMap<usize, usize> = HashMap::new();
+    for i in 0..books.len() {
+        let r = root(&mut parents, i);
+        match by_root.get(&r) {
+            Some(&slot) => clusters[slot].1.push(i),
+            None => {
+                by_root.insert(r, clusters.len());
+                clusters.push((r, vec![i]));
+            }
+        }
+    }
+
+    let mut reasons: HashMap<usize, Vec<DuplicateReason>> = HashMap::new();
+    for (i, reason) in matched {
+        let r = root(&mut parents, i);
+        let list = reasons.entry(r).or_default();
+        if !list.contains(&reason) {
+            list.push(reason);
+        }
+    }
+
+    clusters
+        .into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|(r, mut members)| {
+            members.sort_by_key(|&i| books[i].id);
+            let primary = members
+                .iter()
+                .map(|&i| books[i].id)
+                .find(|id| present.contains(id))
+                .unwrap_or(books[members[0]].id);
+            let mut reasons = reasons.remove(&r).unwrap_or_default();
+            reasons.sort();
+
+            DuplicateCluster {
+                reasons,
+                books: members.iter().map(|&i| entry(&books[i])).collect(),
+                suggested_primary: primary,
+            }
+        })
+        .collect()
+}
+
+// ============================================================================
+// Audit
+// ============================================================================
+
+fn audit_books(conn: &Connection) -> rusqlite::Result<Vec<AuditBook>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, author, isbn, isbn13, path, content_hash FROM books
+         WHERE deleted_at IS NULL ORDER BY id",
+    )?;
+    let books = stmt
+        .query_map([], |row| {
+            Ok(AuditBook {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                author: row.get(2)?,
+                isbn: row.get(3)?,
+                isbn13: row.get(4)?,
+                path: row.get(5)?,
+                content_hash: row.get(6)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(books)
+}
+
+/// Covers of every record, deleted ones included since they can come back
+fn referenced_covers(conn: &Connection) -> rusqlite::Result<HashSet<PathBuf>> {
+    let mut stmt = conn.prepare("SELECT cover_path FROM books WHERE cover_path IS NOT NULL")?;
+    let covers = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .map(|path| path.map(PathBuf::from))
+        .collect::<rusqlite::Result<HashSet<_>>>()?;
+    Ok(covers)
+}
+
+fn orphaned_covers(dir: &Path, referenced: &HashSet<PathBuf>) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut orphans: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && !referenced.contains(path))
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    orphans.sort();
+    orphans
+}
+
+fn audit<R: Runtime>(app: &AppHandle<R>, job: &Job<R>) -> Result<AuditReport, String> {
+    let db = app.state::<Database>();
+    let books = db.with_conn(|conn| audit_books(conn))?;
+    let referenced = db.with_conn(|conn| referenced_covers(conn))?;
+
+    let mut report = AuditReport {
+        checked: books.len(),
+        ..Default::default()
+    };
+    let mut present = HashSet::new();
+    let total = books.len() as u64;
+
+    for (index, book) in books.iter().enumerate() {
+        if job.is_cancelled() {
+            return Err("Library audit cancelled".to_string());
+        }
+        job.progress(index as u64, total, Some(&book.title));
+
+        let Some(path) = book.path.as_deref().map(Path::new) else {
+            continue;
+        };
+        if !path.is_file() {
+            report.missing_files.push(entry(book));
+            continue;
+        }
+        present.insert(book.id);
+
+        let Some(expected) = book.content_hash.as_deref() else {
+            continue;
+        };
+        match import::hash_path(path) {
+            Ok(actual) if actual != expected => report.hash_mismatches.push(HashMismatch {
+                book: entry(book),
+                expected_hash: expected.to_string(),
+                actual_hash: actual,
+            }),
+            Ok(_) => {}
+            Err(e) => warn!("Audit couldn't hash book {}: {}", book.id, e),
+        }
+    }
+    job.progress(total, total, None);
+
+    report.duplicate_clusters = duplicate_clusters(&books, &present);
+    report.orphaned_covers = orphaned_covers(&library::covers_dir(app)?, &referenced);
+
+    info!(
+        "Library audit: {} missing, {} changed, {} duplicate group(s), {} orphaned cover(s)",
+        report.missing_files.len(),
+        report.hash_mismatches.len(),
+        report.duplicate_clusters.len(),
+        report.orphaned_covers.len()
+    );
+    Ok(report)
+}
+
+// ============================================================================
+// Merging
+// ============================================================================
+
+/// The primary's progress after absorbing `duplicates` (the furthest of
+/// them all), and the tags it gains
+fn plan_merge(primary: &Book, duplicates: &[Book]) -> (Option<f64>, Vec<String>) {
+    let progress = duplicates
+        .iter()
+        .chain([primary])
+        .filter_map(|book| book.progress)
+        .reduce(f64::max);
+
+    let mut added: Vec<String> = duplicates
+        .iter()
+        .flat_map(|book| book.tags.iter())
+        .filter(|tag| !primary.tags.contains(tag))
+        .cloned()
+        .collect();
+    added.sort();
+    added.dedup();
+    (progress, added)
+}
+
+/// A book that isn't in the trash
+fn live_book(db: &Database, id: i64) -> Result<Book, String> {
+    let deleted: Option<Option<String>> = db.with_conn(|conn| {
+        conn.query_row("SELECT deleted_at FROM books WHERE id = ?1", [id], |row| {
+            row.get(0)
+        })
+        .optional()
+    })?;
+    match deleted {
+        None => return Err(format!("Book not found: {}", id)),
+        Some(Some(_)) => return Err(format!("Book {} is in the trash", id)),
+        Some(None) => {}
+    }
+    db.with_conn(|conn| library::get_book(conn, id))?
+        .ok_or_else(|| format!("Book not found: {}", id))
+}
+
+/// Move bookmarks and tags, take the furthest progress, soft-delete the
+/// duplicates, and record the merge, all in one transaction
+fn merge_records(
+    conn: &mut Connection,
+    primary_id: i64,
+    undo: &mut MergeUndo,
+    progress: Option<f64>,
+) -> rusqlite::Result<i64> {
+    let now = Utc::now().to_rfc3339();
+    let tx = conn.transaction()?;
+
+    for &duplicate_id in &undo.duplicate_ids {
+        let mut stmt = tx.prepare("SELECT id FROM bookmarks WHERE book_id = ?1")?;
+        let moved = stmt
+            .query_map([duplicate_id], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        undo.bookmarks
+            .extend(moved.into_iter().map(|id| (id, duplicate_id)));
+
+        tx.execute(
+            "UPDATE bookmarks SET book_id = ?2, updated_at = ?3 WHERE book_id = ?1",
+            params![duplicate_id, primary_id, now],
+        )?;
+        tx.execute(
+            "UPDATE books SET deleted_at = ?2, updated_at = ?2 WHERE id = ?1",
+            params![duplicate_id, now],
+        )?;
+    }
+
+    library::add_tags(&tx, primary_id, &undo.added_tags)?;
+    if progress != undo.previous_progress {
+        library::set_progress(&tx, primary_id, progress)?;
+    }
+
+    let record = serde_json::to_string(undo)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    tx.execute(
+        "INSERT INTO book_merges (primary_id, undo, merged_at) VALUES (?1, ?2, ?3)",
+        params![primary_id, record, now],
+    )?;
+    let merge_id = tx.last_insert_rowid();
+
+    for &id in undo.duplicate_ids.iter().chain([&primary_id]) {
+        collections::refresh_book_memberships(&tx, id)?;
+    }
+    tx.commit()?;
+    Ok(merge_id)
+}
+
+/// Put the duplicates, bookmarks, tags, and progress back, in one
+/// transaction. Bookmarks and tags changed since the merge stay as they are.
+fn unmerge_records(
+    conn: &mut Connection,
+    merge_id: i64,
+    primary_id: i64,
+    undo: &MergeUndo,
+) -> rusqlite::Result<()> {
+    let now = Utc::now().to_rfc3339();
+    let tx = conn.transaction()?;
+
+    for &duplicate_id in &undo.duplicate_ids {
+        tx.execute(
+            "UPDATE books SET deleted_at = NULL, updated_at = ?2 WHERE id = ?1",
+            params![duplicate_id, now],
+        )?;
+    }
+    for (bookmark_id, book_id) in &undo.bookmarks {
+        tx.execute(
+            "UPDATE bookmarks SET book_id = ?2, updated_at = ?4 WHERE id = ?1 AND book_id = ?3",
+            params![bookmark_id, book_id, primary_id, now],
+        )?;
+    }
+    library::remove_tags(&tx, primary_id, &undo.added_tags)?;
+    library::set_progress(&tx, primary_id, undo.previous_progress)?;
+
+    tx.execute(
+        "UPDATE book_merges SET undone_at = ?2 WHERE id = ?1",
+        params![merge_id, now],
+    )?;
+    for &id in undo.duplicate_ids.iter().chain([&primary_id]) {
+        collections::refresh_book_memberships(&tx, id)?;
+    }
+    tx.commit()
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Check every book's file and hash, and find duplicates and unused
+/// covers. Runs as a job with progress per book.
+#[tauri::command]
+pub async fn audit_library<R: Runtime>(app: AppHandle<R>) -> Result<AuditReport, String> {
+    let job = Job::start(&app, JobKind::LibraryAudit, "", "Library audit")?;
+
+    let worker = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = audit(&worker, &job);
+        job.finish(&result);
+        result
+    })
+    .await
+    .map_err(|e| format!("Library audit task failed: {}", e))?
+}
+
+/// Fold `duplicate_ids` into `primary_id`: notes, bookmarks, and tags move
+/// over, the furthest progress is kept, and the duplicates are
+/// soft-deleted. Undoable with `undo_merge` for `RESTORE_WINDOW_DAYS`.
+#[tauri::command]
+pub async fn merge_books<R: Runtime>(
+    app: AppHandle<R>,
+    primary_id: i64,
+    mut duplicate_ids: Vec<i64>,
+) -> Result<MergeReport, String> {
+    duplicate_ids.sort_unstable();
+    duplicate_ids.dedup();
+    if duplicate_ids.is_empty() {
+        return Err("Choose at least one duplicate to merge".to_string());
+    }
+    if duplicate_ids.contains(&primary_id) {
+        return Err("A book can't be merged into itself".to_string());
+    }
+
+    let db = app.state::<Database>();
+    let primary = live_book(&db, primary_id)?;
+    let duplicates = duplicate_ids
+        .iter()
+        .map(|&id| live_book(&db, id))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    info!("Merging book(s) {:?} into {}", duplicate_ids, primary_id);
+    let (progress, added_tags) = plan_merge(&primary, &duplicates);
+
+    let sources: HashSet<String> = duplicate_ids.iter().map(i64::to_string).collect();
+    let moved_notes: Vec<(String, String)> = notes::load_all(&app)?
+        .into_iter()
+        .filter_map(|note| {
+            let book_id = note.book_id.filter(|id| sources.contains(id))?;
+            Some((note.id, book_id))
+        })
+        .collect();
+
+    let mut undo = MergeUndo {
+        duplicate_ids: duplicate_ids.clone(),
+        bookmarks: Vec::new(),
+        notes: moved_notes,
+        added_tags,
+        previous_progress: primary.progress,
+    };
+    let merge_id = db.with_conn(|conn| merge_records(conn, primary_id, &mut undo, progress))?;
+
+    let note_ids: Vec<String> = undo.notes.iter().map(|(id, _)| id.clone()).collect();
+    notes::move_to_book(&app, &note_ids, &primary_id.to_string())?;
+
+    let primary = db
+        .with_conn(|conn| library::get_book(conn, primary_id))?
+        .ok_or_else(|| format!("Book not found: {}", primary_id))?;
+    Ok(MergeReport {
+        merge_id,
+        primary,
+        merged_ids: duplicate_ids,
+        moved_notes: undo.notes.len(),
+        moved_bookmarks: undo.bookmarks.len(),
+        added_tags: undo.added_tags,
+        undoable_until: Utc::now() + Duration::days(RESTORE_WINDOW_DAYS),
+    })
+}
+
+/// Take back a merge made within the restore window: the duplicates come
+/// back with their notes and bookmarks, and the primary loses what it
+/// gained. Returns the restored duplicates.
+#[tauri::command]
+pub async fn undo_merge<R: Runtime>(app: AppHandle<R>, merge_id: i64) -> Result<Vec<Book>, String> {
+    let db = app.state::<Database>();
+    let record: Option<(i64, String, String, Option<String>)> = db.with_conn(|conn| {
+        conn.query_row(
+            "SELECT primary_id, undo, merged_at, undone_at FROM book_merges WHERE id = ?1",
+            [merge_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()
+    })?;
+    let (primary_id, undo, merged_at, undone_at) =
+        record.ok_or_else(|| format!("Merge not found: {}", merge_id))?;
+    if undone_at.is_some() {
+        return Err("This merge has already been undone".to_string());
+    }
+    let merged_at = DateTime::parse_from_rfc3339(&merged_at)
+        .map_err(|e| format!("Corrupt merge record: {}", e))?;
+    if Utc::now() - merged_at.with_timezone(&Utc) > Duration::days(RESTORE_WINDOW_DAYS) {
+        return Err("This merge is too old to undo".to_string());
+    }
+    let undo: MergeUndo =
+        serde_json::from_str(&undo).map_err(|e| format!("Corrupt merge record: {}", e))?;
+
+    info!("Undoing merge {} into book {}", merge_id, primary_id);
+    db.with_conn(|conn| unmerge_records(conn, merge_id, primary_id, &undo))?;
+
+    // Notes moved to another book since the merge stay where they are
+    let primary = primary_id.to_string();
+    let current: HashMap<String, Option<String>> = notes::load_all(&app)?
+        .into_iter()
+        .map(|note| (note.id, note.book_id))
+        .collect();
+    let mut by_book: HashMap<&str, Vec<String>> = HashMap::new();
+    for (note_id, book_id) in &undo.notes {
+        if current.get(note_id).and_then(|b| b.as_deref()) == Some(primary.as_str()) {
+            by_book
+                .entry(book_id.as_str())
+                .or_default()
+                .push(note_id.clone());
+        }
+    }
+    for (book_id, note_ids) in by_book {
+        notes::move_to_book(&app, &note_ids, book_id)?;
+    }
+
+    db.with_conn(|conn| {
+        let mut books = Vec::new();
+        for &id in &undo.duplicate_ids {
+            books.extend(library::get_book(conn, id)?);
+        }
+        Ok(books)
+    })
+}
+
+/// Point a book at its file's new location. The file must be the same
+/// format; if its contents changed, the new hash is recorded and the
+/// report says so.
+#[tauri::command]
+pub async fn relink_book(
+    db: State<'_, Database>,
+    book_id: i64,
+    new_path: String,
+) -> Result<RelinkReport, String> {
+    let book = db
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+    let path = PathBuf::from(new_path.trim());
+    if !path.is_file() {
+        return Err(format!("File not found: {}", path.display()));
+    }
+
+    let (format, hash) = {
+        let path = path.clone();
+        tauri::async_runtime::spawn_blocking(move || {
+            Ok::<_, String>((import::detect_format(&path)?, import::hash_path(&path)?))
+        })
+        .await
+        .map_err(|e| format!("Relink task failed: {}", e))??
+    };
+    if let Some(expected) = book.format.as_deref() {
+        if format != BookFormat::Unknown && format.as_str() != expected {
+            return Err(format!(
+                "{} is a {} file, but this book is {}",
+                path.display(),
+                format.as_str(),
+                expected
+            ));
+        }
+    }
+
+    let path_str = path.to_string_lossy().into_owned();
+    let taken: Option<i64> = db.with_conn(|conn| {
+        conn.query_row(
+            "SELECT id FROM books WHERE path = ?1 AND id != ?2 AND deleted_at IS NULL",
+            params![path_str, book_id],
+            |row| row.get(0),
+        )
+        .optional()
+    })?;
+    if let Some(other) = taken {
+        return Err(format!("That file already belongs to book {}", other));
+    }
+
+    let hash_changed = book.content_hash.as_deref().is_some_and(|h| h != hash);
+    info!(
+        "Relinking book {} to {} (changed: {})",
+        book_id, path_str, hash_changed
+    );
+    db.with_conn(|conn| {
+        conn.execute(
+            "UPDATE books SET path = ?2, on_disk = 1, content_hash = ?3, updated_at = ?4
+             WHERE id = ?1",
+            params![book_id, path_str, hash, Utc::now().to_rfc3339()],
+        )?;
+        collections::refresh_book_memberships(conn, book_id)
+    })?;
+
+    let book = db
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+    Ok(RelinkReport { book, hash_changed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audit_book(id: i64, title: &str, author: &str) -> AuditBook {
+        AuditBook {
+            id,
+            title: title.to_string(),
+            author: Some(author.to_string()),
+            isbn: None,
+            isbn13: None,
+            path: None,
+            content_hash: None,
+        }
+    }
+
+    fn book(progress: Option<f64>, tags: &[&str]) -> Book {
+        Book {
+            id: 1,
+            title: "Dune".to_string(),
+            author: None,
+            isbn: None,
+            isbn13: None,
+            path: None,
+            format: None,
+            on_disk: false,
+            page_count: None,
+            word_count: None,
+            series_name: None,
+            series_index: None,
+            progress,
+            rating: None,
+            review: None,
+            added_at: String::new(),
+            finished_at: None,
+            updated_at: String::new(),
+            description: None,
+            cover_path: None,
+            publish_year: None,
+            content_hash: None,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn normalizes_isbns() {
+        assert_eq!(
+            normalize_isbn("978-0-441-17271-9"),
+            Some("9780441172719".to_string())
+        );
+        assert_eq!(
+            normalize_isbn("0 441 17271 x"),
+            Some("044117271X".to_string())
+        );
+        assert_eq!(normalize_isbn("12345"), None);
+    }
+
+    #[test]
+    fn clusters_books_sharing_any_key() {
+        let mut a = audit_book(1, "Dune", "Frank Herbert");
+        a.content_hash = Some("abc".to_string());
+        let mut b = audit_book(2, "Dune: Deluxe Edition", "Herbert");
+        b.content_hash = Some("abc".to_string());
+        b.isbn13 = Some("978-0441172719".to_string());
+        let mut c = audit_book(3, "Dune (Deluxe)", "F. Herbert");
+        c.isbn = Some("9780441172719".to_string());
+        let d = audit_book(4, "dune", "frank  herbert!");
+        let e = audit_book(5, "Emma", "Jane Austen");
+
+        let clusters = duplicate_clusters(&[a, b, c, d, e], &[3].into());
+        assert_eq!(clusters.len(), 1);
+
+        let cluster = &clusters[0];
+        let ids: Vec<i64> = cluster.books.iter().map(|b| b.book_id).collect();
+        assert_eq!(ids, [1, 2, 3, 4]);
+        assert_eq!(
+            cluster.reasons,
+            [
+                DuplicateReason::SameFile,
+                DuplicateReason::SameIsbn,
+                DuplicateReason::SameTitle
+            ]
+        );
+        assert_eq!(cluster.suggested_primary, 3);
+    }
+
+    #[test]
+    fn unique_books_form_no_clusters() {
+        let books = [
+            audit_book(1, "Dune", "Herbert"),
+            audit_book(2, "Emma", "Austen"),
+        ];
+        assert!(duplicate_clusters(&books, &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn merge_keeps_furthest_progress_and_adds_new_tags() {
+        let primary = book(Some(0.2), &["sci-fi"]);
+        let duplicates = [
+            book(Some(0.7), &["classic", "sci-fi"]),
+            book(None, &["classic", "to-read"]),
+        ];
+
+        let (progress, tags) = plan_merge(&primary, &duplicates);
+        assert_eq!(progress, Some(0.7));
+        assert_eq!(tags, ["classic", "to-read"]);
+
+        assert_eq!(plan_merge(&book(None, &[]), &[book(None, &[])]).0, None);
+    }
+}
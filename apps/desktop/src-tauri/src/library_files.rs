@@ -0,0 +1,534 @@
+// Read Master Desktop - Library Files
+//
+// Safe deletion and file management for library books. Deleting never
+// removes user files outright: the file goes to the OS trash and the record
+// is soft-deleted, restorable for `RESTORE_WINDOW_DAYS` before it is purged.
+//
+// `trash_book` moves the file into the app's own `trash/` folder instead,
+// which can be restored from on every platform (macOS has no API to take
+// files back out of the system Trash) and is emptied with the records.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::collections;
+use crate::db::Database;
+use crate::import;
+use crate::library::{self, Book};
+use crate::profiles;
+
+/// How long a deleted book can be restored
+pub const RESTORE_WINDOW_DAYS: i64 = 30;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteReport {
+    pub book_id: i64,
+    /// The file was moved to the OS trash
+    pub file_trashed: bool,
+    /// Why the file couldn't be trashed (e.g. its drive isn't connected)
+    pub file_error: Option<String>,
+    /// Last moment `restore_book` will work
+    pub restorable_until: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreReport {
+    pub book: Book,
+    /// The file is back at its original location
+    pub file_restored: bool,
+    pub file_error: Option<String>,
+}
+
+// ============================================================================
+// Trash
+// ============================================================================
+
+fn missing_file_error(path: &Path) -> String {
+    let reason = match path.parent() {
+        Some(parent) if !parent.exists() => "its drive or folder is not available",
+        _ => "the file no longer exists",
+    };
+    format!("Could not move {} to the trash: {}", path.display(), reason)
+}
+
+/// Move a file to the OS trash. A missing file is reported rather than
+/// treated as success, since it usually means an unplugged drive.
+fn trash_file(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Err(missing_file_error(path));
+    }
+
+    trash::delete(path).map_err(|e| format!("Failed to move file to trash: {}", e))
+}
+
+/// The app's own trash folder
+fn trash_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    let dir = profiles::data_dir(app)?.join("trash");
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create trash folder: {}", e))?;
+    Ok(dir)
+}
+
+/// Move a file, copying when it has to cross filesystems
+fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to).map_err(|e| format!("Failed to move {}: {}", from.display(), e))?;
+    fs::remove_file(from).map_err(|e| format!("Failed to remove {}: {}", from.display(), e))
+}
+
+/// Move a file out of the app trash folder to `original`, or next to it
+/// if that name has been taken since. Returns where it ended up.
+fn restore_from_app_trash(trashed: &Path, original: &Path) -> Result<PathBuf, String> {
+    if !trashed.exists() {
+        return Err("The file is no longer in the trash folder".to_string());
+    }
+
+    let dir = original
+        .parent()
+        .ok_or_else(|| format!("Invalid path: {}", original.display()))?;
+    if !dir.exists() {
+        return Err(format!(
+            "Could not restore {}: its drive or folder is not available",
+            original.display()
+        ));
+    }
+    let dest = match original.file_name() {
+        Some(name) if original.exists() => import::unique_path(dir, &name.to_string_lossy()),
+        _ => original.to_path_buf(),
+    };
+
+    move_file(trashed, &dest)?;
+    Ok(dest)
+}
+
+/// Put a trashed file back where it came from
+#[cfg(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+))]
+fn restore_file(path: &Path) -> Result<(), String> {
+    if path.exists() {
+        return Ok(());
+    }
+
+    let items = trash::os_limited::list().map_err(|e| format!("Failed to read trash: {}", e))?;
+    // Newest first, in case the same path was trashed more than once
+    let item = items
+        .into_iter()
+        .filter(|item| item.original_path() == path)
+        .max_by_key(|item| item.time_deleted)
+        .ok_or_else(|| "The file is no longer in the trash".to_string())?;
+
+    trash::os_limited::restore_all([item])
+        .map_err(|e| format!("Failed to restore file from trash: {}", e))
+}
+
+/// macOS offers no API to restore from the trash
+#[cfg(not(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+)))]
+fn restore_file(path: &Path) -> Result<(), String> {
+    if path.exists() {
+        return Ok(());
+    }
+    Err("Restore the file from the Trash in Finder (\"Put Back\")".to_string())
+}
+
+// ============================================================================
+// Queries
+// ============================================================================
+
+fn deleted_at(conn: &Connection, book_id: i64) -> rusqlite::Result<Option<Option<String>>> {
+    conn.query_row(
+        "SELECT deleted_at FROM books WHERE id = ?1",
+        [book_id],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+fn trashed_path(conn: &Connection, book_id: i64) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        "SELECT trashed_path FROM books WHERE id = ?1",
+        [book_id],
+        |row| row.get::<_, Option<String>>(0),
+    )
+    .optional()
+    .map(Option::flatten)
+}
+
+/// Remove books deleted at or before `cutoff`, along with their files in
+/// the app trash folder
+fn purge_deleted_before(conn: &Connection, cutoff: &str) -> rusqlite::Result<usize> {
+    let mut stmt = conn.prepare(
+        "SELECT trashed_path FROM books
+         WHERE deleted_at IS NOT NULL AND deleted_at <= ?1 AND trashed_path IS NOT NULL",
+    )?;
+    let files = stmt
+        .query_map([cutoff], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    // The search index is an FTS table without foreign keys
+    conn.execute(
+        "DELETE FROM search_chunks WHERE book_id IN
+             (SELECT id FROM books WHERE deleted_at IS NOT NULL AND deleted_at <= ?1)",
+        [cutoff],
+    )?;
+    let purged = conn.execute(
+        "DELETE FROM books WHERE deleted_at IS NOT NULL AND deleted_at <= ?1",
+        [cutoff],
+    )?;
+    // Merges that old can't be undone; their duplicates are gone
+    conn.execute("DELETE FROM book_merges WHERE merged_at <= ?1", [cutoff])?;
+
+    for file in files {
+        match fs::remove_file(&file) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove trashed file {}: {}", file, e),
+        }
+    }
+    Ok(purged)
+}
+
+/// Remove soft-deleted books whose restore window has passed
+pub fn purge_expired(conn: &Connection) -> rusqlite::Result<usize> {
+    let cutoff = (Utc::now() - Duration::days(RESTORE_WINDOW_DAYS)).to_rfc3339();
+    purge_deleted_before(conn, &cutoff)
+}
+
+/// Purge expired deletions on startup
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    match app
+        .state::<Database>()
+        .with_conn(|conn| purge_expired(conn))
+    {
+        Ok(0) => {}
+        Ok(n) => info!("Purged {} deleted book(s) past the restore window", n),
+        Err(e) => warn!("Failed to purge deleted books: {}", e),
+    }
+}
+
+// ============================================================================
+// File Manager
+// ============================================================================
+
+#[cfg(target_os = "macos")]
+fn reveal(path: &Path) -> Result<(), String> {
+    Command::new("open")
+        .arg("-R")
+        .arg(path)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to open Finder: {}", e))
+}
+
+#[cfg(target_os = "windows")]
+fn reveal(path: &Path) -> Result<(), String> {
+    use std::os::windows::process::CommandExt;
+
+    // explorer expects `/select,"path"` as a single raw argument
+    Command::new("explorer")
+        .raw_arg(format!("/select,\"{}\"", path.display()))
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to open Explorer: {}", e))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn reveal(path: &Path) -> Result<(), String> {
+    // Most file managers implement the FileManager1 D-Bus interface, which
+    // can select the file; otherwise just open the folder
+    let uri =
+        url::Url::from_file_path(path).map_err(|_| format!("Invalid path: {}", path.display()))?;
+    let selected = Command::new("dbus-send")
+        .args([
+            "--session",
+            "--dest=org.freedesktop.FileManager1",
+            "--type=method_call",
+            "/org/freedesktop/FileManager1",
+            "org.freedesktop.FileManager1.ShowItems",
+        ])
+        .arg(format!("array:string:{}", uri))
+        .arg("string:")
+        .status()
+        .is_ok_and(|status| status.success());
+    if selected {
+        return Ok(());
+    }
+
+    let folder = path.parent().unwrap_or(path);
+    Command::new("xdg-open")
+        .arg(folder)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to open file manager: {}", e))
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Delete a book. The record is soft-deleted for `RESTORE_WINDOW_DAYS`;
+/// with `delete_file` the file also goes to the OS trash. If the file can't
+/// be trashed (e.g. its drive is unplugged) the record is still deleted and
+/// the report says why.
+#[tauri::command]
+pub async fn delete_book(
+    db: State<'_, Database>,
+    book_id: i64,
+    delete_file: bool,
+) -> Result<DeleteReport, String> {
+    let book = db
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+
+    info!("Deleting book {} (delete file: {})", book_id, delete_file);
+
+    let mut report = DeleteReport {
+        book_id,
+        file_trashed: false,
+        file_error: None,
+        restorable_until: Utc::now() + Duration::days(RESTORE_WINDOW_DAYS),
+    };
+
+    if delete_file {
+        if let Some(path) = book.path.as_deref() {
+            match trash_file(Path::new(path)) {
+                Ok(()) => report.file_trashed = true,
+                Err(e) => {
+                    warn!("{}", e);
+                    report.file_error = Some(e);
+                }
+            }
+        }
+    }
+
+    let on_disk = book.on_disk && !report.file_trashed;
+    db.with_conn(|conn| {
+        conn.execute(
+            "UPDATE books SET deleted_at = ?2, on_disk = ?3, updated_at = ?2 WHERE id = ?1",
+            params![book_id, Utc::now().to_rfc3339(), on_disk],
+        )?;
+        collections::refresh_book_memberships(conn, book_id)
+    })?;
+
+    Ok(report)
+}
+
+/// Move a book to the app trash folder. The record is soft-deleted and
+/// the file kept for `RESTORE_WINDOW_DAYS`, then both are purged. A file
+/// that can't be found is reported; the record still goes to the trash.
+#[tauri::command]
+pub async fn trash_book<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: i64,
+) -> Result<DeleteReport, String> {
+    let db = app.state::<Database>();
+    let deleted = db
+        .with_conn(|conn| deleted_at(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+    if deleted.is_some() {
+        return Err(format!("Book {} is already in the trash", book_id));
+    }
+    let book = db
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+
+    info!("Moving book {} to the trash", book_id);
+
+    let mut report = DeleteReport {
+        book_id,
+        file_trashed: false,
+        file_error: None,
+        restorable_until: Utc::now() + Duration::days(RESTORE_WINDOW_DAYS),
+    };
+
+    let mut trashed = None;
+    if let Some(path) = book.path.as_deref().map(Path::new) {
+        let moved = if path.exists() {
+            let name = format!(
+                "{}-{}",
+                book_id,
+                path.file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            );
+            let dest = import::unique_path(&trash_dir(&app)?, &name);
+            move_file(path, &dest).map(|()| dest)
+        } else {
+            Err(missing_file_error(path))
+        };
+        match moved {
+            Ok(dest) => {
+                report.file_trashed = true;
+                trashed = Some(dest.to_string_lossy().into_owned());
+            }
+            Err(e) => {
+                warn!("{}", e);
+                report.file_error = Some(e);
+            }
+        }
+    }
+
+    let on_disk = book.on_disk && !report.file_trashed;
+    db.with_conn(|conn| {
+        conn.execute(
+            "UPDATE books SET deleted_at = ?2, on_disk = ?3, trashed_path = ?4, updated_at = ?2
+             WHERE id = ?1",
+            params![book_id, Utc::now().to_rfc3339(), on_disk, trashed],
+        )?;
+        collections::refresh_book_memberships(conn, book_id)
+    })?;
+
+    Ok(report)
+}
+
+/// Bring back a deleted book, moving its file back from the app trash
+/// folder, or from the OS trash when the platform allows it
+#[tauri::command]
+pub async fn restore_book<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: i64,
+) -> Result<RestoreReport, String> {
+    let db = app.state::<Database>();
+    let deleted = db
+        .with_conn(|conn| deleted_at(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+    if deleted.is_none() {
+        return Err(format!("Book {} is not deleted", book_id));
+    }
+
+    let book = db
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+    let trashed = db.with_conn(|conn| trashed_path(conn, book_id))?;
+
+    info!("Restoring book {}", book_id);
+
+    let mut path = book.path.clone();
+    let (file_restored, file_error) = match (book.path.as_deref(), trashed.as_deref()) {
+        (Some(original), Some(trashed)) => {
+            match restore_from_app_trash(Path::new(trashed), Path::new(original)) {
+                Ok(dest) => {
+                    path = Some(dest.to_string_lossy().into_owned());
+                    (true, None)
+                }
+                Err(e) => {
+                    warn!("Could not restore file of book {}: {}", book_id, e);
+                    (false, Some(e))
+                }
+            }
+        }
+        (Some(original), None) => match restore_file(Path::new(original)) {
+            Ok(()) => (true, None),
+            Err(e) => {
+                warn!("Could not restore file of book {}: {}", book_id, e);
+                (false, Some(e))
+            }
+        },
+        (None, _) => (false, None),
+    };
+
+    let book = db.with_conn(|conn| {
+        let on_disk = path.as_deref().is_some_and(|path| Path::new(path).exists());
+        // A file that couldn't be moved back stays in the trash folder
+        let still_trashed = if file_restored { None } else { trashed.clone() };
+        conn.execute(
+            "UPDATE books SET deleted_at = NULL, on_disk = ?2, path = ?3, trashed_path = ?4,
+                 updated_at = ?5
+             WHERE id = ?1",
+            params![
+                book_id,
+                on_disk,
+                path,
+                still_trashed,
+                Utc::now().to_rfc3339()
+            ],
+        )?;
+        collections::refresh_book_memberships(conn, book_id)?;
+        library::get_book(conn, book_id)
+    })?;
+
+    Ok(RestoreReport {
+        book: book.ok_or_else(|| format!("Book not found: {}", book_id))?,
+        file_restored,
+        file_error,
+    })
+}
+
+/// Permanently remove every deleted book and the files in the app trash
+/// folder, returning how many books were removed
+#[tauri::command]
+pub async fn empty_trash(db: State<'_, Database>) -> Result<usize, String> {
+    let purged = db.with_conn(|conn| purge_deleted_before(conn, &Utc::now().to_rfc3339()))?;
+    info!("Emptied trash: {} book(s) removed", purged);
+    Ok(purged)
+}
+
+/// Open the folder containing a book's file, with the file selected
+#[tauri::command]
+pub async fn reveal_in_file_manager(db: State<'_, Database>, book_id: i64) -> Result<(), String> {
+    let book = db
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+    let path = PathBuf::from(
+        book.path
+            .ok_or_else(|| format!("Book {} has no file", book_id))?,
+    );
+
+    if !path.exists() {
+        return Err(format!("File not found: {}", path.display()));
+    }
+
+    info!("Revealing {}", path.display());
+    reveal(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_next_to_a_file_that_took_the_name() {
+        let dir = std::env::temp_dir().join(format!("rm-trash-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("trash")).unwrap();
+        let original = dir.join("book.epub");
+        let trashed = dir.join("trash/7-book.epub");
+        fs::write(&trashed, b"old").unwrap();
+        fs::write(&original, b"new").unwrap();
+
+        let restored = restore_from_app_trash(&trashed, &original).unwrap();
+        assert_eq!(restored, dir.join("book (1).epub"));
+        assert_eq!(fs::read(&restored).unwrap(), b"old");
+        assert!(!trashed.exists());
+        assert!(restore_from_app_trash(&trashed, &original).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,429 @@
+// Read Master Desktop - Library Migration
+//
+// Moves the library to another folder, e.g. a NAS mount.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use crate::db::Database;
+use crate::jobs::{Job, JobKind, JobRegistry};
+use crate::profiles::{self, ProfileStoreExt};
+use crate::{fsutil, import, library, persist, reader};
+
+const JOURNAL_FILE: &str = "library-migration.json";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Files already copied and verified by an unfinished migration. A run
+/// that fails partway (a full disk, an unplugged drive) leaves its copies
+/// and the journal in place, so running it again with the same target
+/// resumes where it stopped.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Journal {
+    target: PathBuf,
+    move_files: bool,
+    /// Source path to verified destination
+    done: BTreeMap<PathBuf, PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Path,
+    Cover,
+}
+
+/// One file to relocate
+#[derive(Debug, Clone, PartialEq)]
+struct Relocation {
+    book_id: i64,
+    column: Column,
+    from: PathBuf,
+    to: PathBuf,
+    /// Hash recorded at import, for files whose source is already gone
+    content_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MigrationProgress {
+    processed: usize,
+    total: usize,
+    file: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationReport {
+    pub root: String,
+    pub books: usize,
+    pub covers: usize,
+    /// Files verified by an earlier, interrupted run
+    pub resumed: usize,
+    /// Originals that couldn't be removed after moving
+    pub left_behind: Vec<String>,
+}
+
+// ============================================================================
+// Planning
+// ============================================================================
+
+/// Book files inside the current library folder, and saved covers. Books
+/// imported in place from elsewhere stay where they are.
+fn plan(
+    db: &Database,
+    (old_root, new_root): (&Path, &Path),
+    (old_covers, new_covers): (&Path, &Path),
+) -> Result<Vec<Relocation>, String> {
+    let rows: Vec<(i64, Option<String>, Option<String>, Option<String>)> =
+        db.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT id, path, cover_path, content_hash FROM books")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?;
+            rows.collect()
+        })?;
+
+    let mut plan = Vec::new();
+    for (book_id, path, cover_path, content_hash) in rows {
+        let book = path.map(PathBuf::from).and_then(|from| {
+            let to = fsutil::rebase(&from, old_root, new_root)?;
+            Some((Column::Path, from, to, content_hash))
+        });
+        let cover = cover_path.map(PathBuf::from).and_then(|from| {
+            let to = fsutil::rebase(&from, old_covers, new_covers)?;
+            Some((Column::Cover, from, to, None))
+        });
+        for (column, from, to, content_hash) in book.into_iter().chain(cover) {
+            plan.push(Relocation {
+                book_id,
+                column,
+                from,
+                to,
+                content_hash,
+            });
+        }
+    }
+    Ok(plan)
+}
+
+// ============================================================================
+// Journal
+// ============================================================================
+
+fn journal_path<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    profiles::data_dir(app).map(|dir| dir.join(JOURNAL_FILE))
+}
+
+fn load_journal(path: &Path) -> Option<Journal> {
+    let data = std::fs::read(path).ok()?;
+    serde_json::from_slice(&data)
+        .map_err(|e| warn!("Ignoring corrupt migration journal: {}", e))
+        .ok()
+}
+
+/// Write the journal so it is either the old or the new version on disk
+fn save_journal(path: &Path, journal: &Journal) -> Result<(), String> {
+    let data = serde_json::to_vec(journal)
+        .map_err(|e| format!("Failed to encode migration journal: {}", e))?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, data)
+        .and_then(|_| std::fs::rename(&tmp, path))
+        .map_err(|e| format!("Failed to write migration journal: {}", e))
+}
+
+/// Delete the copies a journal records, then the journal
+fn roll_back(path: &Path, journal: &Journal) {
+    if journal.move_files {
+        let copies: Vec<&PathBuf> = journal.done.values().collect();
+        fsutil::remove_copies(&journal.target, &copies);
+    }
+    let _ = std::fs::remove_file(path);
+}
+
+// ============================================================================
+// Copying
+// ============================================================================
+
+fn copy_error(file: &Path, e: io::Error, copied: usize, total: usize) -> String {
+    if e.kind() == io::ErrorKind::StorageFull {
+        format!(
+            "The new library folder is full after {} of {} files. Free some space and \
+             run the migration again to continue.",
+            copied, total
+        )
+    } else {
+        format!("Failed to copy {}: {}", file.display(), e)
+    }
+}
+
+/// Copy (or, when re-linking, find) one file at its destination and check
+/// it matches the original
+fn relocate_file(item: &Relocation, move_files: bool) -> Result<(), io::Error> {
+    let expected = match import::hash_path(&item.from) {
+        Ok(hash) => Some(hash),
+        Err(_) if !move_files => item.content_hash.clone(),
+        Err(e) => return Err(io::Error::new(io::ErrorKind::NotFound, e)),
+    };
+
+    if move_files && !item.to.exists() {
+        if let Some(dir) = item.to.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        if let Err(e) = std::fs::copy(&item.from, &item.to) {
+            let _ = std::fs::remove_file(&item.to);
+            return Err(e);
+        }
+    }
+
+    let actual =
+        import::hash_path(&item.to).map_err(|e| io::Error::new(io::ErrorKind::NotFound, e))?;
+    match expected {
+        Some(expected) if expected != actual => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} doesn't match the original", item.to.display()),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Point every relocated book at its new files, in one transaction
+fn update_paths(db: &Database, plan: &[Relocation]) -> Result<(), String> {
+    db.with_conn(|conn| {
+        let tx = conn.transaction()?;
+        for item in plan {
+            let sql = match item.column {
+                Column::Path => "UPDATE books SET path = ?2 WHERE id = ?1",
+                Column::Cover => "UPDATE books SET cover_path = ?2 WHERE id = ?1",
+            };
+            tx.execute(
+                sql,
+                rusqlite::params![item.book_id, item.to.to_string_lossy()],
+            )?;
+        }
+        tx.commit()
+    })
+}
+
+fn migrate<R: Runtime>(
+    app: &AppHandle<R>,
+    target: PathBuf,
+    move_files: bool,
+    job: &Job<R>,
+) -> Result<MigrationReport, String> {
+    let db = app.state::<Database>();
+    let old_root = library::library_dir(app)?;
+    fsutil::validate_target(&old_root, &target, "library folder")?;
+    if move_files {
+        std::fs::create_dir_all(&target)
+            .map_err(|e| format!("Failed to create {}: {}", target.display(), e))?;
+    } else if !target.is_dir() {
+        return Err(format!("{} is not a folder", target.display()));
+    }
+
+    let old_covers = library::covers_dir(app)?;
+    let new_covers = target.join(library::COVERS_FOLDER);
+    let plan = plan(&db, (&old_root, &target), (&old_covers, &new_covers))?;
+
+    // Resume an interrupted run to the same place; clean up after one
+    // that was headed somewhere else
+    let journal_path = journal_path(app)?;
+    let mut journal = match load_journal(&journal_path) {
+        Some(journal) if journal.target == target && journal.move_files == move_files => journal,
+        Some(stale) => {
+            info!("Discarding migration to {}", stale.target.display());
+            roll_back(&journal_path, &stale);
+            Journal::default()
+        }
+        None => Journal::default(),
+    };
+    journal.target = target.clone();
+    journal.move_files = move_files;
+    let resumed = plan
+        .iter()
+        .filter(|item| journal.done.get(&item.from) == Some(&item.to) && item.to.exists())
+        .count();
+
+    info!(
+        "Migrating {} library file(s) to {} ({} already done)",
+        plan.len(),
+        target.display(),
+        resumed
+    );
+
+    for (index, item) in plan.iter().enumerate() {
+        if job.is_cancelled() {
+            info!("Library migration cancelled, removing copies");
+            roll_back(&journal_path, &journal);
+            return Err("Library migration cancelled".to_string());
+        }
+        let file = item.from.to_string_lossy();
+        job.progress(index as u64, plan.len() as u64, Some(&file));
+        let _ = app.emit(
+            "library-migration-progress",
+            MigrationProgress {
+                processed: index,
+                total: plan.len(),
+                file: file.into_owned(),
+            },
+        );
+
+        if journal.done.get(&item.from) == Some(&item.to) && item.to.exists() {
+            continue;
+        }
+        if !move_files || item.from.exists() {
+            relocate_file(item, move_files)
+                .map_err(|e| copy_error(&item.from, e, journal.done.len(), plan.len()))?;
+        } else {
+            warn!("Skipping missing file {}", item.from.display());
+            continue;
+        }
+        journal.done.insert(item.from.clone(), item.to.clone());
+        save_journal(&journal_path, &journal)?;
+    }
+
+    // Only files that were verified get their paths changed
+    let relocated: Vec<Relocation> = plan
+        .into_iter()
+        .filter(|item| journal.done.get(&item.from) == Some(&item.to))
+        .collect();
+    update_paths(&db, &relocated)?;
+
+    let store = app
+        .profile_store("settings.json")
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    store.set(library::ROOT_SETTING, target.to_string_lossy().into_owned());
+    persist::mark_dirty(app, "settings.json");
+
+    let book_paths: HashMap<i64, String> = relocated
+        .iter()
+        .filter(|item| item.column == Column::Path)
+        .map(|item| (item.book_id, item.to.to_string_lossy().into_owned()))
+        .collect();
+    reader::relocate_sessions(app, &book_paths);
+
+    let mut left_behind = Vec::new();
+    if move_files {
+        for item in &relocated {
+            if let Err(e) = std::fs::remove_file(&item.from) {
+                warn!("Failed to remove {}: {}", item.from.display(), e);
+                left_behind.push(item.from.to_string_lossy().into_owned());
+            }
+        }
+    }
+    let _ = std::fs::remove_file(&journal_path);
+
+    let report = MigrationReport {
+        root: target.to_string_lossy().into_owned(),
+        books: book_paths.len(),
+        covers: relocated.len() - book_paths.len(),
+        resumed,
+        left_behind,
+    };
+    info!(
+        "Library migrated to {}: {} book(s), {} cover(s)",
+        report.root, report.books, report.covers
+    );
+    Ok(report)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Move the library to `new_path` and point `library.root` at it. Files
+/// are copied and verified by hash, and the originals removed once every
+/// path is updated. With `move_files` off nothing is copied: the files must
+/// already be in the new folder, and are verified and re-linked.
+///
+/// Runs as a job, emitting `library-migration-progress` as well as the
+/// generic job events.
+#[tauri::command]
+pub async fn migrate_library<R: Runtime>(
+    app: AppHandle<R>,
+    new_path: String,
+    move_files: bool,
+) -> Result<MigrationReport, String> {
+    // One migration at a time, whatever the target
+    let job = Job::start(&app, JobKind::LibraryMigration, "", "Library migration")?;
+
+    let worker = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = migrate(&worker, PathBuf::from(new_path.trim()), move_files, &job);
+        job.finish(&result);
+        result
+    })
+    .await
+    .map_err(|e| format!("Library migration task failed: {}", e))?
+}
+
+/// Stop the running migration and delete the copies it made
+#[tauri::command]
+pub fn cancel_library_migration(jobs: State<'_, JobRegistry>) -> bool {
+    jobs.find(JobKind::LibraryMigration, "")
+        .is_some_and(|id| jobs.cancel(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rm-migrate-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn copies_and_verifies_files() {
+        let dir = temp_dir();
+        let item = Relocation {
+            book_id: 1,
+            column: Column::Path,
+            from: dir.join("old/book.epub"),
+            to: dir.join("new/nested/book.epub"),
+            content_hash: None,
+        };
+        std::fs::create_dir_all(dir.join("old")).unwrap();
+        std::fs::write(&item.from, b"book contents").unwrap();
+
+        relocate_file(&item, true).unwrap();
+        assert_eq!(std::fs::read(&item.to).unwrap(), b"book contents");
+
+        // Re-linking to a different file is caught
+        std::fs::write(&item.to, b"something else").unwrap();
+        let err = relocate_file(&item, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn journal_round_trips_and_rolls_back_copies() {
+        let dir = temp_dir();
+        let copy = dir.join("copy.epub");
+        std::fs::write(&copy, b"copy").unwrap();
+
+        let path = dir.join(JOURNAL_FILE);
+        let journal = Journal {
+            target: dir.clone(),
+            move_files: true,
+            done: BTreeMap::from([(dir.join("original.epub"), copy.clone())]),
+        };
+        save_journal(&path, &journal).unwrap();
+        assert_eq!(load_journal(&path), Some(journal.clone()));
+
+        roll_back(&path, &journal);
+        assert!(!copy.exists());
+        assert!(!path.exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,312 @@
+// Read Master Desktop - Library Sorting
+//
+// Sorting for the library views, so the frontend doesn't have to get
+// locale-aware ordering right itself. Numbers compare by value ("Book 2"
+// before "Book 10"), accents and case are folded away ("Émile" next to
+// "Emile"), and authors sort by surname ("Ursula K. Le Guin" under L).
+// Books without an author always come last.
+
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Name particles that belong to the surname when capitalized ("Le Guin",
+/// "Van Buren") and are sorted past when not ("Vincent van Gogh")
+const PARTICLES: &[&str] = &[
+    "al", "bin", "da", "de", "del", "della", "der", "des", "di", "du", "la", "le", "st.", "ten",
+    "ter", "van", "von",
+];
+
+const SUFFIXES: &[&str] = &["jr", "jr.", "sr", "sr.", "ii", "iii", "iv"];
+
+/// Separators between co-authors; only the first author is sorted on
+const AUTHOR_SEPARATORS: &[&str] = &[" & ", " and ", ";"];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// The fields of a book that sorting needs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookRef {
+    pub id: i64,
+    pub title: String,
+    #[serde(default)]
+    pub author: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SortKey {
+    Title,
+    Author,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+/// A run of digits or of other characters. Numbers come before text, and
+/// compare by digit count first, so by value.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Chunk {
+    Number(usize, String),
+    Text(String),
+}
+
+// ============================================================================
+// Keys
+// ============================================================================
+
+/// Lowercase `text` without accents. Letters that don't decompose are
+/// spelled out, and punctuation runs become single spaces.
+fn fold(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.nfd().filter(|c| !is_combining_mark(*c)) {
+        let spelled = match c {
+            'ß' => "ss",
+            'æ' | 'Æ' => "ae",
+            'œ' | 'Œ' => "oe",
+            'ø' | 'Ø' => "o",
+            'ł' | 'Ł' => "l",
+            'đ' | 'Đ' => "d",
+            'þ' | 'Þ' => "th",
+            _ if c.is_alphanumeric() => {
+                out.extend(c.to_lowercase());
+                continue;
+            }
+            _ => " ",
+        };
+        if spelled != " " || !(out.is_empty() || out.ends_with(' ')) {
+            out.push_str(spelled);
+        }
+    }
+    out.trim_end().to_string()
+}
+
+/// Natural sort key for `text`
+fn natural_key(text: &str) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut in_digits = false;
+    for c in fold(text).chars() {
+        let digit = c.is_ascii_digit();
+        if digit != in_digits && !current.is_empty() {
+            chunks.push(chunk(std::mem::take(&mut current), in_digits));
+        }
+        in_digits = digit;
+        current.push(c);
+    }
+    if !current.is_empty() {
+        chunks.push(chunk(current, in_digits));
+    }
+    chunks
+}
+
+fn chunk(text: String, digits: bool) -> Chunk {
+    if digits {
+        let value = text.trim_start_matches('0');
+        let value = if value.is_empty() { "0" } else { value };
+        Chunk::Number(value.len(), value.to_string())
+    } else {
+        Chunk::Text(text)
+    }
+}
+
+/// `author` as "surname given names", from the first of several authors.
+/// Names already written "Surname, Given" are kept as they are.
+fn surname_first(author: &str) -> String {
+    let first = AUTHOR_SEPARATORS
+        .iter()
+        .fold(author, |name, sep| name.split(sep).next().unwrap_or(name))
+        .trim();
+    if first.contains(',') {
+        return first.to_string();
+    }
+
+    let mut words: Vec<&str> = first.split_whitespace().collect();
+    let suffix = match words.last() {
+        Some(last) if words.len() > 2 && SUFFIXES.contains(&last.to_lowercase().as_str()) => {
+            words.pop()
+        }
+        _ => None,
+    };
+    if words.len() < 2 {
+        return first.to_string();
+    }
+
+    let mut start = words.len() - 1;
+    while start > 1 {
+        let word = words[start - 1];
+        let capitalized = word.starts_with(char::is_uppercase);
+        if !(capitalized && PARTICLES.contains(&word.to_lowercase().as_str())) {
+            break;
+        }
+        start -= 1;
+    }
+
+    let mut name = words[start..].join(" ");
+    for word in words[..start].iter().chain(&suffix) {
+        name.push(' ');
+        name.push_str(word);
+    }
+    name
+}
+
+struct SortEntry {
+    primary: Option<Vec<Chunk>>,
+    title: Vec<Chunk>,
+    book: BookRef,
+}
+
+impl SortEntry {
+    fn new(book: BookRef, key: SortKey) -> Self {
+        let primary = match key {
+            SortKey::Title => Some(natural_key(&book.title)),
+            SortKey::Author => book
+                .author
+                .as_deref()
+                .map(|author| natural_key(&surname_first(author)))
+                .filter(|key| !key.is_empty()),
+        };
+        Self {
+            primary,
+            title: natural_key(&book.title),
+            book,
+        }
+    }
+}
+
+/// Order two entries. A missing sort value stays last either way, and ids
+/// break ties so equal books don't swap places between calls.
+fn compare(a: &SortEntry, b: &SortEntry, order: Order) -> Ordering {
+    let ordered = |ordering: Ordering| match order {
+        Order::Asc => ordering,
+        Order::Desc => ordering.reverse(),
+    };
+    match (&a.primary, &b.primary) {
+        (Some(x), Some(y)) => ordered(x.cmp(y)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+    .then_with(|| ordered(a.title.cmp(&b.title)))
+    .then_with(|| a.book.id.cmp(&b.book.id))
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// `books` sorted by `key`
+#[tauri::command]
+pub fn sort_library(books: Vec<BookRef>, key: SortKey, order: Order) -> Vec<BookRef> {
+    let mut entries: Vec<SortEntry> = books
+        .into_iter()
+        .map(|book| SortEntry::new(book, key))
+        .collect();
+    entries.sort_by(|a, b| compare(a, b, order));
+    entries.into_iter().map(|entry| entry.book).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: i64, title: &str, author: Option<&str>) -> BookRef {
+        BookRef {
+            id,
+            title: title.to_string(),
+            author: author.map(str::to_string),
+        }
+    }
+
+    fn titles(books: &[BookRef]) -> Vec<&str> {
+        books.iter().map(|b| b.title.as_str()).collect()
+    }
+
+    #[test]
+    fn folds_case_and_accents() {
+        assert_eq!(fold("Émile Zola"), "emile zola");
+        assert_eq!(fold("Straße  —  Ærø"), "strasse aero");
+        assert_eq!(fold("  Hello, World!"), "hello world");
+    }
+
+    #[test]
+    fn sorts_numbers_by_value() {
+        let books = vec![
+            book(1, "Book 10", None),
+            book(2, "Book 2", None),
+            book(3, "book 1", None),
+            book(4, "Book 02", None),
+            book(5, "1984", None),
+        ];
+        let sorted = sort_library(books, SortKey::Title, Order::Asc);
+        assert_eq!(
+            titles(&sorted),
+            ["1984", "book 1", "Book 2", "Book 02", "Book 10"]
+        );
+    }
+
+    #[test]
+    fn descending_reverses_titles() {
+        let books = vec![book(1, "Éclair", None), book(2, "Zebra", None)];
+        let sorted = sort_library(books, SortKey::Title, Order::Desc);
+        assert_eq!(titles(&sorted), ["Zebra", "Éclair"]);
+    }
+
+    #[test]
+    fn puts_surnames_first() {
+        assert_eq!(surname_first("Ursula K. Le Guin"), "Le Guin Ursula K.");
+        assert_eq!(surname_first("Vincent van Gogh"), "Gogh Vincent van");
+        assert_eq!(
+            surname_first("Martin Luther King Jr."),
+            "King Martin Luther Jr."
+        );
+        assert_eq!(surname_first("Austen, Jane"), "Austen, Jane");
+        assert_eq!(surname_first("Homer"), "Homer");
+        assert_eq!(
+            surname_first("Terry Pratchett & Neil Gaiman"),
+            "Pratchett Terry"
+        );
+    }
+
+    #[test]
+    fn sorts_authors_by_surname_with_unknown_last() {
+        let books = vec![
+            book(1, "Anonymous", None),
+            book(2, "The Dispossessed", Some("Ursula K. Le Guin")),
+            book(3, "Dune", Some("Frank Herbert")),
+            book(4, "Emma", Some("Jane Austen")),
+            book(5, "Persuasion", Some("Jane Austen")),
+        ];
+        let sorted = sort_library(books.clone(), SortKey::Author, Order::Asc);
+        assert_eq!(
+            titles(&sorted),
+            [
+                "Emma",
+                "Persuasion",
+                "Dune",
+                "The Dispossessed",
+                "Anonymous"
+            ]
+        );
+
+        let sorted = sort_library(books, SortKey::Author, Order::Desc);
+        assert_eq!(
+            titles(&sorted),
+            [
+                "The Dispossessed",
+                "Dune",
+                "Persuasion",
+                "Emma",
+                "Anonymous"
+            ]
+        );
+    }
+}
@@ -0,0 +1,335 @@
+// Read Master Desktop - Log Files
+//
+// Log output is mirrored from stderr into `read-master.log` in the app log
+// directory, rotated once it passes `MAX_LOG_BYTES` so the directory stays
+// under a few megabytes. Panics are written there with a backtrace, so a
+// crash leaves something to attach to a bug report.
+//
+// The logger starts before Tauri knows where the log directory is; lines
+// logged until `init` opens the file are buffered and written first.
+//
+// env_logger passes everything through and the level is enforced with
+// `log::set_max_level`, so it can be changed at runtime. The chosen level
+// is saved in settings and applied by `init` on the next start.
+
+use std::backtrace::Backtrace;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::{info, warn, LevelFilter};
+use tauri::{AppHandle, Runtime};
+use tauri_plugin_shell::ShellExt;
+
+use crate::persist;
+use crate::profiles::{self, ProfileStoreExt};
+
+const LOG_FILE: &str = "read-master.log";
+
+/// Size at which the log file is rotated
+const MAX_LOG_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Rotated files kept besides the current one (`read-master.1.log`, ...)
+const KEEP_ROTATED: usize = 2;
+
+/// Cap on output buffered before the log file is open
+const MAX_EARLY_BYTES: usize = 64 * 1024;
+
+/// Level used until a saved one is applied
+pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+const SETTINGS_STORE: &str = "settings.json";
+const LEVEL_SETTING: &str = "logging.level";
+
+// ============================================================================
+// Rotating File
+// ============================================================================
+
+struct RotatingFile {
+    dir: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(LOG_FILE))?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    /// Shift `read-master.log` -> `.1.log` -> `.2.log`, dropping the oldest
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..KEEP_ROTATED).rev() {
+            let from = rotated_path(&self.dir, n);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.dir, n + 1))?;
+            }
+        }
+        fs::rename(self.dir.join(LOG_FILE), rotated_path(&self.dir, 1))?;
+
+        *self = Self::open(&self.dir)?;
+        Ok(())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self.size + buf.len() as u64 > MAX_LOG_BYTES {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+}
+
+fn rotated_path(dir: &Path, n: usize) -> PathBuf {
+    dir.join(format!("read-master.{}.log", n))
+}
+
+enum Sink {
+    /// Before `init`: buffered in memory
+    Early(Vec<u8>),
+    Open(RotatingFile),
+    /// The file couldn't be opened; stderr only
+    Closed,
+}
+
+static SINK: Mutex<Sink> = Mutex::new(Sink::Early(Vec::new()));
+
+fn write_to_file(buf: &[u8]) {
+    let Ok(mut sink) = SINK.lock() else {
+        return;
+    };
+    match &mut *sink {
+        Sink::Early(early) => {
+            if early.len() + buf.len() <= MAX_EARLY_BYTES {
+                early.extend_from_slice(buf);
+            }
+        }
+        Sink::Open(file) => {
+            // Nowhere left to report a failure; stderr still has the line
+            let _ = file.write(buf);
+        }
+        Sink::Closed => {}
+    }
+}
+
+/// Logger target writing to stderr and the log file
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        write_to_file(buf);
+        io::stderr().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Ok(Sink::Open(file)) = SINK.lock().as_deref_mut() {
+            file.file.flush()?;
+        }
+        io::stderr().flush()
+    }
+}
+
+// ============================================================================
+// Setup
+// ============================================================================
+
+/// Record panics in the log file. The default hook still prints to stderr.
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic| {
+        let thread = std::thread::current();
+        let report = format!(
+            "[{} PANIC] thread '{}' {}\n{}\n",
+            chrono::Utc::now().to_rfc3339(),
+            thread.name().unwrap_or("<unnamed>"),
+            panic,
+            Backtrace::force_capture()
+        );
+        write_to_file(report.as_bytes());
+        let _ = LogWriter.flush();
+
+        default_hook(panic);
+    }));
+}
+
+/// Parse `error|warn|info|debug|trace`
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    match level.trim().to_ascii_lowercase().as_str() {
+        "error" => Ok(LevelFilter::Error),
+        "warn" => Ok(LevelFilter::Warn),
+        "info" => Ok(LevelFilter::Info),
+        "debug" => Ok(LevelFilter::Debug),
+        "trace" => Ok(LevelFilter::Trace),
+        other => Err(format!(
+            "Unknown log level '{}' (expected error, warn, info, debug, or trace)",
+            other
+        )),
+    }
+}
+
+/// Apply the level saved by `set_log_level`, if any
+fn apply_saved_level<R: Runtime>(app: &AppHandle<R>) {
+    let saved = app
+        .profile_store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(LEVEL_SETTING))
+        .and_then(|value| value.as_str().map(str::to_string));
+
+    if let Some(saved) = saved {
+        match parse_level(&saved) {
+            Ok(level) => log::set_max_level(level),
+            Err(e) => warn!("Ignoring saved log level: {}", e),
+        }
+    }
+}
+
+/// Open the log file, write out anything logged before it existed, and
+/// apply the saved log level
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    apply_saved_level(app);
+
+    let opened = log_dir(app).and_then(|dir| RotatingFile::open(&dir).map_err(|e| e.to_string()));
+
+    let Ok(mut sink) = SINK.lock() else {
+        return;
+    };
+    let early = match &mut *sink {
+        Sink::Early(early) => std::mem::take(early),
+        _ => Vec::new(),
+    };
+
+    match opened {
+        Ok(mut file) => {
+            let _ = file.write(&early);
+            *sink = Sink::Open(file);
+            drop(sink);
+            info!("Logging to {}", log_dir(app).unwrap_or_default().display());
+        }
+        Err(e) => {
+            *sink = Sink::Closed;
+            drop(sink);
+            warn!("Failed to open log file: {}", e);
+        }
+    }
+}
+
+fn log_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    profiles::log_dir(app)
+}
+
+/// The last `lines` lines across the current and rotated log files
+pub fn recent_lines(dir: &Path, lines: usize) -> Result<String, String> {
+    let mut files = vec![dir.join(LOG_FILE)];
+    files.extend((1..=KEEP_ROTATED).map(|n| rotated_path(dir, n)));
+
+    // Newest file first; prepend older files until there are enough lines
+    let mut collected: Vec<String> = Vec::new();
+    for path in files {
+        if collected.len() >= lines {
+            break;
+        }
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        let mut older: Vec<String> = text.lines().map(str::to_string).collect();
+        older.append(&mut collected);
+        collected = older;
+    }
+
+    let skip = collected.len().saturating_sub(lines);
+    Ok(collected[skip..].join("\n"))
+}
+
+/// Recent log lines for a bug report, or nothing if there are none
+pub fn recent_logs<R: Runtime>(app: &AppHandle<R>, lines: usize) -> Option<String> {
+    let _ = LogWriter.flush();
+    log_dir(app)
+        .and_then(|dir| recent_lines(&dir, lines))
+        .map_err(|e| warn!("Failed to read logs: {}", e))
+        .ok()
+        .filter(|logs| !logs.is_empty())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// The last `lines` lines of the log, oldest first
+#[tauri::command]
+pub fn get_recent_logs<R: Runtime>(app: AppHandle<R>, lines: usize) -> Result<String, String> {
+    let _ = LogWriter.flush();
+    recent_lines(&log_dir(&app)?, lines)
+}
+
+/// Change the log level now and on future starts
+#[tauri::command]
+pub fn set_log_level<R: Runtime>(app: AppHandle<R>, level: String) -> Result<(), String> {
+    let level = parse_level(&level)?;
+    log::set_max_level(level);
+    info!("Log level set to {}", level);
+
+    let store = app
+        .profile_store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    store.set(LEVEL_SETTING, level.as_str().to_ascii_lowercase());
+    persist::mark_dirty(&app, SETTINGS_STORE);
+    Ok(())
+}
+
+/// Show the log directory in the file manager
+#[tauri::command]
+pub fn open_log_folder<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
+    let dir = log_dir(&app)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create log directory: {}", e))?;
+
+    #[allow(deprecated)]
+    app.shell()
+        .open(dir.to_string_lossy(), None)
+        .map_err(|e| format!("Failed to open log folder: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_levels() {
+        assert_eq!(parse_level("debug"), Ok(LevelFilter::Debug));
+        assert_eq!(parse_level(" WARN "), Ok(LevelFilter::Warn));
+        assert!(parse_level("verbose").is_err());
+    }
+
+    #[test]
+    fn rotates_and_reads_across_files() {
+        let dir = std::env::temp_dir().join(format!("rm-logs-{}", uuid::Uuid::new_v4()));
+        let mut file = RotatingFile::open(&dir).unwrap();
+
+        let line = format!("{}\n", "x".repeat(1023));
+        for _ in 0..(MAX_LOG_BYTES / 1024 + 1) {
+            file.write(line.as_bytes()).unwrap();
+        }
+        file.write(b"last\n").unwrap();
+
+        assert!(rotated_path(&dir, 1).exists());
+        assert!(file.size < MAX_LOG_BYTES);
+
+        let recent = recent_lines(&dir, 3).unwrap();
+        assert_eq!(recent.lines().count(), 3);
+        assert_eq!(recent.lines().last(), Some("last"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -7,9 +7,92 @@
     windows_subsystem = "windows"
 )]
 
+mod activity;
+mod autostart;
+mod bookmarks;
+mod card_anki;
+mod card_csv;
+mod card_gen;
+mod catalog;
+mod clipboard;
+mod clipper;
+mod collections;
 mod commands;
+mod covers;
+mod data_location;
+mod db;
+mod device_import;
+mod dictionary;
+mod drafts;
+mod epub;
+mod error;
+mod feedback;
+mod find;
+mod formats;
+mod fsutil;
+mod goals;
+mod goodreads;
+mod highlights;
+mod images;
+mod import;
+mod instance;
+mod jobs;
+mod jumplist;
+mod keychain;
+mod language;
+mod layout;
+mod library;
+mod library_audit;
+mod library_files;
+mod library_migration;
+mod library_sort;
+mod logging;
 mod menu;
+mod metadata_lookup;
+mod mobi;
+mod net;
+mod notes;
+mod notification_policy;
+mod notifications;
+mod obsidian;
+mod ocr;
+mod opds;
+mod palette;
+mod pdf;
+mod pdf_reflow;
+mod pdf_render;
+mod pdf_text;
+mod pdf_tiles;
+mod persist;
+mod power;
+mod profiles;
+mod quick_access;
+mod quick_capture;
+mod quote_anchor;
+mod reader;
+mod reading_report;
+mod recent;
+mod rsvp;
+mod safe_mode;
+mod scripting;
+mod search;
+mod series;
+mod session;
+mod sessions;
+mod share_server;
+mod spellcheck;
+mod srs;
+mod sync;
+mod sync_queue;
+mod system_info;
+mod system_theme;
+mod text_stats;
+mod translate;
 mod tray;
+mod tts;
+mod tts_export;
+mod updates;
+mod zoom;
 
 use log::{info, LevelFilter};
 use tauri::{
@@ -17,17 +100,31 @@ use tauri::{
     menu::{Menu, MenuItem},
     tray::TrayIconBuilder,
 };
+use tauri_plugin_window_state::StateFlags;
 
 fn main() {
-    // Initialize logger
+    // Initialize logger (mirrored to the log file once it's open). The
+    // level is enforced by `log::set_max_level` so it can change at runtime.
     env_logger::Builder::new()
-        .filter_level(LevelFilter::Info)
+        .filter_level(LevelFilter::Trace)
+        .target(env_logger::Target::Pipe(Box::new(logging::LogWriter)))
         .init();
+    log::set_max_level(logging::DEFAULT_LEVEL);
+    logging::install_panic_hook();
 
     info!("Starting Read Master Desktop...");
 
+    // Recovery launch that skips saved book state
+    let safe_mode_flag = std::env::args().any(|arg| arg == safe_mode::SAFE_MODE_FLAG);
+
+    // Login launch that stays in the tray until shown
+    let hidden_flag = std::env::args().any(|arg| arg == autostart::HIDDEN_FLAG);
+
     tauri::Builder::default()
-        // Plugins
+        // Plugins (single-instance first, so a second launch exits early)
+        .plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+            instance::handle_second_instance(app, args, cwd);
+        }))
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_notification::init())
@@ -35,11 +132,120 @@ fn main() {
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_clipboard_manager::init())
-        .plugin(tauri_plugin_window_state::Builder::new().build())
+        // Visibility is left to setup, so a hidden launch stays hidden
+        .plugin(
+            tauri_plugin_window_state::Builder::new()
+                .with_state_flags(StateFlags::all() & !StateFlags::VISIBLE)
+                .build(),
+        )
+        // State
+        .manage(translate::TranslationCache::default())
+        .manage(reader::ReaderSessions::default())
+        .manage(find::FindSessions::default())
+        .manage(dictionary::DictionaryCache::default())
+        .manage(dictionary::context::WordContextCache::default())
+        .manage(spellcheck::SpellChecker::default())
+        .manage(covers::CoverThumbnails::default())
+        .manage(jobs::JobRegistry::default())
+        .manage(zoom::ZoomLevels::default())
+        .manage(share_server::ShareServer::default())
+        .manage(pdf::PdfHandles::default())
+        .manage(pdf_tiles::PdfTiles::default())
+        .manage(formats::djvu::DjvuPages::default())
+        .manage(tts::TtsPlayer::default())
+        .manage(activity::ActivityCache::default())
+        .manage(notifications::NotificationRegistry::default())
+        .manage(sync::changelog::ChangeLog::default())
+        // Book resources for the reader
+        .register_uri_scheme_protocol(reader::PROTOCOL, reader::handle_protocol)
+        // Menu events
+        .on_menu_event(menu::handle_menu_event)
+        // Release per-window resources
+        .on_window_event(|window, event| {
+            pdf::handle_window_event(window, event);
+            session::handle_window_event(window, event);
+            zoom::handle_window_event(window, event);
+            system_theme::handle_window_event(window, event);
+        })
+        // Deliver cold-start jump list actions and workspace restores
+        .on_page_load(|webview, payload| {
+            jumplist::handle_page_load(webview, payload);
+            session::handle_page_load(webview, payload);
+            zoom::handle_page_load(webview, payload);
+            drafts::handle_page_load(webview, payload);
+            profiles::handle_page_load(webview, payload);
+            safe_mode::handle_page_load(webview, payload);
+        })
         // Setup
-        .setup(|app| {
+        .setup(move |app| {
             info!("Setting up application...");
 
+            // Default, chosen, or portable data folder (read before any
+            // store or the database is opened)
+            data_location::init(app.handle());
+
+            // Count crashed launches; enter safe mode on the flag or a
+            // third crash in a row
+            safe_mode::init(app.handle(), safe_mode_flag);
+
+            // Stay in the tray on a `--hidden` login launch (not in safe mode)
+            autostart::init(hidden_flag);
+
+            // Pick the profile every data path below resolves into
+            profiles::init(app.handle());
+
+            // Rotating log file in the app log directory, saved log level
+            logging::init(app.handle());
+
+            // Saved zip-bomb limits for EPUB archives
+            epub::limits::init(app.handle());
+
+            // Open library database
+            db::init(app.handle())?;
+            library_files::init(app.handle());
+
+            // Watch for sleep/wake and connectivity changes
+            power::init(app.handle());
+
+            // Pause reading sessions on suspend
+            sessions::init(app.handle());
+
+            // Follow the OS accent color
+            system_theme::init(app.handle());
+
+            // Fail long-running jobs that stop reporting progress
+            jobs::init(app.handle());
+
+            // Trim cover thumbnails to the configured cap
+            covers::init(app.handle());
+
+            // Debounced store saves (flushed on suspend)
+            persist::init(app.handle());
+
+            // Replay annotation drafts left by a crash
+            drafts::init(app.handle())?;
+
+            // Move bookmarks out of the old store (needs the saver above)
+            bookmarks::init(app.handle());
+
+            // Background sync of offline changes (drains on network-online)
+            sync_queue::init(app.handle());
+
+            // Clipboard quick capture (tray item + global shortcut)
+            quick_capture::init(app.handle())?;
+
+            // Global show/hide shortcut (needs the shortcut plugin above)
+            quick_access::init(app.handle());
+
+            // Pause read-aloud when headphones disconnect or the system sleeps
+            tts::init(app.handle());
+
+            // Jump list / dock menu with recent books
+            jumplist::init(app.handle());
+
+            // Reopen last session's windows (after the database is up)
+            session::init(app.handle());
+
             // Create application menu
             let menu = menu::create_menu(app.handle())?;
             app.set_menu(menu)?;
@@ -47,15 +253,27 @@ fn main() {
             // Create system tray
             let tray = tray::create_tray(app.handle())?;
 
+            // Goal progress in the tray tooltip, daily goal reminders
+            goals::init(app.handle());
+
             // Get main window
             if let Some(window) = app.get_webview_window("main") {
                 // Set window title
                 window.set_title("Read Master")?;
 
+                // Created hidden, so a login launch never flashes it
+                if !autostart::launched_hidden() {
+                    window.show()?;
+                }
+
                 // Show window when ready
                 let window_clone = window.clone();
+                let handle = app.handle().clone();
                 window.on_window_event(move |event| {
                     if let tauri::WindowEvent::CloseRequested { api, .. } = event {
+                        // Don't leave unsaved settings behind
+                        persist::flush(&handle);
+
                         // Hide instead of close on macOS
                         #[cfg(target_os = "macos")]
                         {
@@ -75,15 +293,225 @@ fn main() {
             commands::get_app_version,
             commands::get_platform,
             commands::open_file_dialog,
+            clipper::import_url,
+            clipper::import_html,
+            recent::get_recent_files,
+            recent::add_recent_file,
+            recent::pin_recent_file,
+            recent::clear_recent_files,
             commands::save_file_dialog,
+            catalog::export_catalog,
+            obsidian::export_to_obsidian,
             commands::read_file,
             commands::write_file,
+            commands::open_external_url,
             commands::show_notification,
+            notification_policy::get_notification_history,
+            notification_policy::get_notification_policy,
+            notification_policy::set_notification_policy,
             commands::get_store_value,
             commands::set_store_value,
             commands::check_for_updates,
+            updates::download_and_install_update,
+            library::db_list_books,
+            library::mark_book_finished,
+            library_files::delete_book,
+            library_files::restore_book,
+            library_files::trash_book,
+            library_files::empty_trash,
+            library_files::reveal_in_file_manager,
+            library_migration::migrate_library,
+            library_migration::cancel_library_migration,
+            library_audit::audit_library,
+            library_audit::merge_books,
+            library_audit::undo_merge,
+            library_audit::relink_book,
+            library_sort::sort_library,
+            jobs::cancel_job,
+            jobs::list_active_jobs,
+            series::detect_series,
+            series::apply_series,
+            collections::create_collection,
+            collections::list_collections,
+            collections::delete_collection,
+            collections::evaluate_collection,
+            collections::get_book_collections,
+            goodreads::import_goodreads_csv,
+            device_import::import_device_annotations,
+            epub::fonts::list_epub_fonts,
+            epub::resources::read_epub_resource,
+            epub::metadata::get_epub_metadata,
+            epub::validate::validate_epub,
+            epub::repair::repair_epub,
+            epub::limits::get_archive_limits,
+            epub::limits::set_archive_limits,
+            mobi::get_mobi_metadata,
+            epub::metadata::get_page_direction,
+            epub::text::get_chapter_text,
+            epub::paginate::paginate_chapter,
+            epub::structure::get_book_structure,
+            epub::structure::invalidate_book_cache,
+            keychain::set_secret,
+            keychain::delete_secret,
+            keychain::has_secret,
+            translate::translate,
+            translate::translate_text,
+            metadata_lookup::fetch_book_metadata,
+            metadata_lookup::apply_metadata,
+            bookmarks::add_bookmark,
+            bookmarks::list_bookmarks,
+            bookmarks::update_bookmark,
+            bookmarks::delete_bookmark,
+            layout::save_book_layout,
+            layout::get_book_layout,
+            notes::add_note,
+            notes::update_note,
+            notes::delete_note,
+            notes::list_notes,
+            notes::search_notes,
+            notes::reanchor_annotations,
+            highlights::get_random_highlights,
+            highlights::get_highlights_on_this_day,
+            spellcheck::set_spellcheck_languages,
+            spellcheck::check_spelling,
+            spellcheck::add_to_personal_dictionary,
+            import::detect_book_format,
+            import::hash_file,
+            import::import_book,
+            import::import_folder,
+            fsutil::check_disk_space,
+            opds::fetch_opds_feed,
+            opds::download_publication,
+            persist::flush_store,
+            profiles::list_profiles,
+            profiles::create_profile,
+            profiles::switch_profile,
+            profiles::delete_profile,
+            data_location::get_data_directory,
+            data_location::set_data_directory,
+            data_location::set_portable_mode,
+            safe_mode::get_safe_mode,
+            safe_mode::restart_normally,
+            autostart::set_launch_at_login,
+            autostart::get_launch_at_login_status,
+            drafts::autosave_draft,
+            drafts::get_unsaved_drafts,
+            drafts::commit_draft,
+            clipboard::copy_quote,
+            clipboard::get_clipboard_history,
+            clipboard::clear_clipboard_history,
+            share_server::start_library_server,
+            share_server::stop_library_server,
+            power::get_power_state,
+            quick_capture::get_quick_capture,
+            quick_capture::save_quick_capture,
+            quick_access::set_quick_access_shortcut,
+            reader::open_reader_session,
+            reader::close_reader_session,
+            reader::set_typography_profile,
+            dictionary::lookup_word,
+            dictionary::context::get_word_context,
+            search::build_search_index,
+            search::cancel_index,
+            search::search_books,
+            find::find_in_open_book,
+            find::find_matches,
+            find::find_next,
+            find::find_previous,
+            ocr::ocr_pdf,
+            ocr::cancel_ocr,
+            sessions::record_reading_session,
+            sessions::update_reading_session,
+            sessions::delete_reading_session,
+            goals::set_goal,
+            goals::clear_goal,
+            goals::get_goal_progress,
+            goals::set_goal_reminder_hour,
+            text_stats::analyze_book_text,
+            language::detect_language,
+            srs::submit_review,
+            srs::due_cards,
+            card_gen::generate_cards_from_notes,
+            card_gen::save_generated_cards,
+            card_csv::import_cards_csv,
+            card_csv::export_cards_csv,
+            card_anki::import_anki_apkg,
+            sync_queue::enqueue_operation,
+            sync_queue::get_queue_status,
+            sync::webdav::webdav_configure,
+            sync::webdav::webdav_set_accept_invalid_certs,
+            sync::webdav::webdav_sync_now,
+            sync::changelog::get_changes_since,
+            sync::changelog::apply_remote_changes,
+            pdf::mmap_pdf_open,
+            pdf::mmap_pdf_page_text,
+            pdf::mmap_pdf_close,
+            pdf_render::render_pdf_page,
+            pdf_tiles::get_pdf_page_sizes,
+            pdf_tiles::render_pdf_tile,
+            pdf_text::get_pdf_text_layer,
+            pdf_text::search_pdf,
+            formats::djvu::djvu_open,
+            formats::djvu::djvu_render_page,
+            formats::djvu::djvu_page_text,
+            pdf_reflow::pdf_reflow_text,
+            jumplist::update_jump_list,
+            session::report_window_state,
+            session::save_workspace,
+            session::restore_workspace,
+            zoom::set_zoom,
+            zoom::get_zoom,
+            zoom::set_ui_zoom,
+            scripting::list_scripts,
+            scripting::set_script,
+            scripting::run_script_test,
+            system_info::get_system_info,
+            system_theme::get_system_accent_color,
+            feedback::submit_feedback,
+            logging::get_recent_logs,
+            logging::open_log_folder,
+            logging::set_log_level,
+            images::list_book_images,
+            images::export_book_image,
+            covers::get_cover_thumbnails,
+            covers::clear_cover_cache,
+            covers::clear_thumbnail_cache,
+            covers::get_cache_size,
+            covers::get_thumbnail_cache_limit,
+            covers::set_thumbnail_cache_limit,
+            palette::extract_cover_palette,
+            tts::tts_play_book,
+            tts::tts_skip,
+            tts::tts_pause,
+            tts::tts_resume,
+            tts::tts_sleep_timer,
+            tts::tts_stop,
+            tts::autoscroll::set_autoscroll,
+            tts::autoscroll::get_autoscroll,
+            rsvp::get_rsvp_frames,
+            rsvp::stop_rsvp_frames,
+            tts_export::export_chapter_audio,
+            activity::get_activity_heatmap,
+            activity::get_streaks,
+            activity::get_review_forecast,
+            reading_report::generate_reading_report,
         ])
         // Run
-        .run(generate_context!())
-        .expect("error while running tauri application");
+        .build(generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| match event {
+            // Capture the workspace while its windows are still open, and
+            // stop any update download so its partial file can be resumed
+            tauri::RunEvent::ExitRequested { .. } => {
+                session::save(app);
+                updates::abort(app);
+            }
+            // Write pending store changes before quitting
+            tauri::RunEvent::Exit => {
+                persist::flush(app);
+                drafts::flush(app);
+                safe_mode::mark_launched(app);
+            }
+            _ => {}
+        });
 }
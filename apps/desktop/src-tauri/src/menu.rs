@@ -2,12 +2,64 @@
 //
 // Native menu bar configuration.
 
-use log::info;
+use log::{info, warn};
 use tauri::{
-    menu::{Menu, MenuBuilder, MenuItemBuilder, PredefinedMenuItem, SubmenuBuilder},
-    AppHandle, Runtime, Wry,
+    menu::{
+        Menu, MenuBuilder, MenuEvent, MenuItemBuilder, PredefinedMenuItem, Submenu, SubmenuBuilder,
+    },
+    AppHandle, Emitter, Manager, Runtime, Wry,
 };
 
+use crate::recent;
+
+/// Marker in front of pinned recent files
+const PINNED_MARKER: &str = "\u{2605} ";
+
+/// File > Open Recent: pinned files first, then the rest, then Clear
+fn open_recent_menu<R: Runtime>(app: &AppHandle<R>) -> Result<Submenu<R>, tauri::Error> {
+    let files = recent::load(app);
+    let mut submenu = SubmenuBuilder::new(app, "Open Recent");
+
+    if files.is_empty() {
+        submenu = submenu.item(
+            &MenuItemBuilder::with_id("no_recent", "No Recent Files")
+                .enabled(false)
+                .build(app)?,
+        );
+    }
+    let mut pinned_section = false;
+    for file in &files {
+        if pinned_section && !file.pinned {
+            submenu = submenu.separator();
+        }
+        pinned_section = file.pinned;
+        let label = if file.pinned {
+            format!("{}{}", PINNED_MARKER, file.name)
+        } else {
+            file.name.clone()
+        };
+        submenu = submenu.item(
+            &MenuItemBuilder::with_id(format!("{}{}", recent::MENU_PREFIX, file.path), label)
+                .build(app)?,
+        );
+    }
+
+    submenu
+        .separator()
+        .item(
+            &MenuItemBuilder::with_id("clear_recent", "Clear Recent")
+                .enabled(files.iter().any(|file| !file.pinned))
+                .build(app)?,
+        )
+        .build()
+}
+
+/// Rebuild the menu bar, e.g. after the recent files change
+pub fn refresh<R: Runtime>(app: &AppHandle<R>) -> Result<(), tauri::Error> {
+    app.set_menu(create_menu(app)?)?;
+    Ok(())
+}
+
 /// Create the application menu
 pub fn create_menu<R: Runtime>(app: &AppHandle<R>) -> Result<Menu<R>, tauri::Error> {
     info!("Creating application menu...");
@@ -43,10 +95,16 @@ pub fn create_menu<R: Runtime>(app: &AppHandle<R>) -> Result<Menu<R>, tauri::Err
                 &MenuItemBuilder::with_id("import_book", "Import Book...")
                     .accelerator("Cmd+O")
                     .build(app)?,
+                &MenuItemBuilder::with_id("import_url", "Import from URL...")
+                    .accelerator("Cmd+Shift+O")
+                    .build(app)?,
+                &open_recent_menu(app)?,
                 &PredefinedMenuItem::separator(app)?,
                 &MenuItemBuilder::with_id("new_window", "New Window")
                     .accelerator("Cmd+Shift+N")
                     .build(app)?,
+                &MenuItemBuilder::with_id("reopen_session", "Reopen Last Session")
+                    .build(app)?,
                 &PredefinedMenuItem::separator(app)?,
                 &PredefinedMenuItem::close_window(app, None)?,
             ])
@@ -76,6 +134,17 @@ pub fn create_menu<R: Runtime>(app: &AppHandle<R>) -> Result<Menu<R>, tauri::Err
                     .accelerator("Cmd+3")
                     .build(app)?,
                 &PredefinedMenuItem::separator(app)?,
+                &MenuItemBuilder::with_id("zoom_in", "Zoom In")
+                    .accelerator("Cmd+=")
+                    .build(app)?,
+                &MenuItemBuilder::with_id("zoom_out", "Zoom Out")
+                    .accelerator("Cmd+-")
+                    .build(app)?,
+                &MenuItemBuilder::with_id("actual_size", "Actual Size")
+                    .accelerator("Cmd+0")
+                    .enabled(false)
+                    .build(app)?,
+                &PredefinedMenuItem::separator(app)?,
                 &PredefinedMenuItem::fullscreen(app, None)?,
             ])
             .build()?,
@@ -139,6 +208,12 @@ pub fn create_menu<R: Runtime>(app: &AppHandle<R>) -> Result<Menu<R>, tauri::Err
                 &MenuItemBuilder::with_id("import_book", "Import Book...")
                     .accelerator("Ctrl+O")
                     .build(app)?,
+                &MenuItemBuilder::with_id("import_url", "Import from URL...")
+                    .accelerator("Ctrl+Shift+O")
+                    .build(app)?,
+                &open_recent_menu(app)?,
+                &MenuItemBuilder::with_id("reopen_session", "Reopen Last Session")
+                    .build(app)?,
                 &PredefinedMenuItem::separator(app)?,
                 &MenuItemBuilder::with_id("preferences", "Preferences...")
                     .accelerator("Ctrl+,")
@@ -173,6 +248,17 @@ pub fn create_menu<R: Runtime>(app: &AppHandle<R>) -> Result<Menu<R>, tauri::Err
                     .accelerator("Ctrl+3")
                     .build(app)?,
                 &PredefinedMenuItem::separator(app)?,
+                &MenuItemBuilder::with_id("zoom_in", "Zoom In")
+                    .accelerator("Ctrl+=")
+                    .build(app)?,
+                &MenuItemBuilder::with_id("zoom_out", "Zoom Out")
+                    .accelerator("Ctrl+-")
+                    .build(app)?,
+                &MenuItemBuilder::with_id("actual_size", "Actual Size")
+                    .accelerator("Ctrl+0")
+                    .enabled(false)
+                    .build(app)?,
+                &PredefinedMenuItem::separator(app)?,
                 &PredefinedMenuItem::fullscreen(app, None)?,
             ])
             .build()?,
@@ -220,3 +306,73 @@ pub fn create_menu<R: Runtime>(app: &AppHandle<R>) -> Result<Menu<R>, tauri::Err
 
     menu.build()
 }
+
+/// Menu items forwarded to the reader as `reader-action` events
+const READER_ACTIONS: &[&str] = &[
+    "prev_page",
+    "next_page",
+    "toggle_tts",
+    "add_bookmark",
+    "add_note",
+    "search_book",
+    "table_of_contents",
+];
+
+/// Route application menu clicks to the frontend
+pub fn handle_menu_event<R: Runtime>(app: &AppHandle<R>, event: MenuEvent) {
+    let id = event.id().as_ref();
+    info!("Menu event: {}", id);
+
+    // Zoom acts on whichever window is focused
+    if crate::zoom::MENU_ITEMS.contains(&id) {
+        crate::zoom::handle_menu_event(app, id);
+        return;
+    }
+
+    if id == "clear_recent" {
+        if let Err(e) = recent::clear(app) {
+            warn!("Failed to clear recent files: {}", e);
+        }
+        return;
+    }
+
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+
+    if let Some(path) = id.strip_prefix(recent::MENU_PREFIX) {
+        let _ = window.show();
+        let _ = window.set_focus();
+        let _ = window.emit("open-files", vec![path.to_string()]);
+        return;
+    }
+
+    let route = match id {
+        "library" => Some("/library"),
+        "flashcards" => Some("/flashcards"),
+        "social" => Some("/social"),
+        "preferences" => Some("/settings"),
+        "shortcuts" => Some("/settings/shortcuts"),
+        _ => None,
+    };
+
+    if let Some(route) = route {
+        let _ = window.show();
+        let _ = window.set_focus();
+        let _ = window.emit("navigate", route);
+    } else if id == "report_issue" {
+        let _ = window.show();
+        let _ = window.set_focus();
+        let _ = window.emit("report-issue", ());
+    } else if id == "reopen_session" {
+        crate::session::restore(app, false);
+    } else if id == "import_book" {
+        let _ = window.emit("import-book", ());
+    } else if id == "import_url" {
+        let _ = window.show();
+        let _ = window.set_focus();
+        let _ = window.emit("import-url", ());
+    } else if READER_ACTIONS.contains(&id) {
+        let _ = window.emit("reader-action", id);
+    }
+}
@@ -0,0 +1,436 @@
+// Read Master Desktop - Metadata Lookup
+//
+// Book metadata search against Open Library and Google Books.
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime, State};
+
+use crate::db::Database;
+use crate::library::{self, BookDetails};
+use crate::net::{self, RequestError};
+use crate::profiles::ProfileStoreExt;
+
+/// Candidates requested from each provider
+const MAX_RESULTS: usize = 10;
+
+/// Attempts per request, including the first
+const ATTEMPTS: u32 = 3;
+
+/// Subjects kept as tags when applying a candidate
+const MAX_SUBJECT_TAGS: usize = 5;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MetadataProvider {
+    OpenLibrary,
+    GoogleBooks,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataQuery {
+    pub isbn: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    /// Providers in priority order; defaults to the `metadata.providers`
+    /// setting, then Open Library before Google Books
+    pub providers: Option<Vec<MetadataProvider>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetadataCandidate {
+    pub provider: MetadataProvider,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub description: Option<String>,
+    pub cover_url: Option<String>,
+    pub publish_year: Option<i64>,
+    pub subjects: Vec<String>,
+    pub isbns: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum LookupError {
+    /// No network connection; distinct from an empty result list
+    Offline {
+        message: String,
+    },
+    /// The providers are reachable but failing
+    Provider {
+        message: String,
+    },
+    InvalidQuery {
+        message: String,
+    },
+    Library {
+        message: String,
+    },
+}
+
+impl From<RequestError> for LookupError {
+    fn from(e: RequestError) -> Self {
+        match e {
+            RequestError::Offline(_) => Self::Offline {
+                message: e.to_string(),
+            },
+            _ => Self::Provider {
+                message: e.to_string(),
+            },
+        }
+    }
+}
+
+// ============================================================================
+// Open Library
+// ============================================================================
+
+#[derive(Deserialize)]
+struct OpenLibraryResponse {
+    docs: Vec<OpenLibraryDoc>,
+}
+
+#[derive(Deserialize)]
+struct OpenLibraryDoc {
+    title: Option<String>,
+    #[serde(default)]
+    author_name: Vec<String>,
+    first_publish_year: Option<i64>,
+    #[serde(default)]
+    subject: Vec<String>,
+    cover_i: Option<i64>,
+    #[serde(default)]
+    isbn: Vec<String>,
+}
+
+async fn search_open_library(
+    query: &MetadataQuery,
+) -> Result<Vec<MetadataCandidate>, RequestError> {
+    const FIELDS: &str = "title,author_name,first_publish_year,subject,cover_i,isbn";
+    let client = net::client();
+
+    let mut params = vec![
+        ("fields", FIELDS.to_string()),
+        ("limit", MAX_RESULTS.to_string()),
+    ];
+    match &query.isbn {
+        Some(isbn) => params.push(("isbn", isbn.clone())),
+        None => {
+            params.extend(query.title.clone().map(|t| ("title", t)));
+            params.extend(query.author.clone().map(|a| ("author", a)));
+        }
+    }
+
+    let url = "https://openlibrary.org/search.json";
+    let response = net::send_with_retry(url, ATTEMPTS, || client.get(url).query(&params)).await?;
+    let parsed: OpenLibraryResponse = response.json().await?;
+
+    Ok(parsed
+        .docs
+        .into_iter()
+        .filter_map(|doc| {
+            Some(MetadataCandidate {
+                provider: MetadataProvider::OpenLibrary,
+                title: doc.title?,
+                authors: doc.author_name,
+                // Descriptions live on the works endpoint; not worth a
+                // second request per candidate
+                description: None,
+                cover_url: doc
+                    .cover_i
+                    .map(|id| format!("https://covers.openlibrary.org/b/id/{}-L.jpg", id)),
+                publish_year: doc.first_publish_year,
+                subjects: doc.subject.into_iter().take(20).collect(),
+                isbns: doc.isbn.into_iter().take(10).collect(),
+            })
+        })
+        .collect())
+}
+
+// ============================================================================
+// Google Books
+// ============================================================================
+
+#[derive(Deserialize)]
+struct GoogleBooksResponse {
+    #[serde(default)]
+    items: Vec<GoogleBooksItem>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleBooksItem {
+    volume_info: GoogleVolumeInfo,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoogleVolumeInfo {
+    title: Option<String>,
+    subtitle: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    description: Option<String>,
+    published_date: Option<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+    image_links: Option<GoogleImageLinks>,
+    #[serde(default)]
+    industry_identifiers: Vec<GoogleIdentifier>,
+}
+
+#[derive(Deserialize)]
+struct GoogleImageLinks {
+    thumbnail: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GoogleIdentifier {
+    identifier: String,
+}
+
+async fn search_google_books(
+    query: &MetadataQuery,
+) -> Result<Vec<MetadataCandidate>, RequestError> {
+    let client = net::client();
+
+    let q = match &query.isbn {
+        Some(isbn) => format!("isbn:{}", isbn),
+        None => [
+            query.title.as_ref().map(|t| format!("intitle:{}", t)),
+            query.author.as_ref().map(|a| format!("inauthor:{}", a)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" "),
+    };
+    let params = [("q", q), ("maxResults", MAX_RESULTS.to_string())];
+
+    let url = "https://www.googleapis.com/books/v1/volumes";
+    let response = net::send_with_retry(url, ATTEMPTS, || client.get(url).query(&params)).await?;
+    let parsed: GoogleBooksResponse = response.json().await?;
+
+    Ok(parsed
+        .items
+        .into_iter()
+        .filter_map(|item| {
+            let info = item.volume_info;
+            let title = match info.subtitle {
+                Some(subtitle) => format!("{}: {}", info.title?, subtitle),
+                None => info.title?,
+            };
+
+            Some(MetadataCandidate {
+                provider: MetadataProvider::GoogleBooks,
+                title,
+                authors: info.authors,
+                description: info.description,
+                // Thumbnails come back as http with a low zoom level
+                cover_url: info
+                    .image_links
+                    .and_then(|links| links.thumbnail)
+                    .map(|url| url.replace("http://", "https://").replace("&edge=curl", "")),
+                publish_year: info
+                    .published_date
+                    .and_then(|d| d.get(..4).and_then(|y| y.parse().ok())),
+                subjects: info.categories,
+                isbns: info
+                    .industry_identifiers
+                    .into_iter()
+                    .map(|id| id.identifier)
+                    .collect(),
+            })
+        })
+        .collect())
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+fn default_providers<R: Runtime>(app: &AppHandle<R>) -> Vec<MetadataProvider> {
+    app.profile_store("settings.json")
+        .ok()
+        .and_then(|store| store.get("metadata.providers"))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_else(|| vec![MetadataProvider::OpenLibrary, MetadataProvider::GoogleBooks])
+}
+
+fn normalize_query(mut query: MetadataQuery) -> Result<MetadataQuery, LookupError> {
+    let clean = |v: Option<String>| v.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+
+    query.isbn = clean(query.isbn).map(|isbn| {
+        isbn.chars()
+            .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+            .collect()
+    });
+    query.title = clean(query.title);
+    query.author = clean(query.author);
+
+    if query.isbn.is_none() && query.title.is_none() {
+        return Err(LookupError::InvalidQuery {
+            message: "Provide an ISBN or a title".to_string(),
+        });
+    }
+    Ok(query)
+}
+
+fn cover_extension(url: &str, content_type: Option<&str>) -> &'static str {
+    match content_type {
+        Some(t) if t.contains("png") => "png",
+        Some(t) if t.contains("webp") => "webp",
+        _ if url.to_ascii_lowercase().ends_with(".png") => "png",
+        _ => "jpg",
+    }
+}
+
+async fn download_cover<R: Runtime>(
+    app: &AppHandle<R>,
+    book_id: i64,
+    url: &str,
+) -> Result<String, LookupError> {
+    let client = net::client();
+    let response = net::send_with_retry(url, ATTEMPTS, || client.get(url)).await?;
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let bytes = response.bytes().await.map_err(RequestError::from)?;
+
+    let library_error = |e: std::io::Error| LookupError::Library {
+        message: format!("Failed to save cover: {}", e),
+    };
+
+    let dir = library::covers_dir(app).map_err(|message| LookupError::Library { message })?;
+    std::fs::create_dir_all(&dir).map_err(library_error)?;
+
+    let path = dir.join(format!(
+        "{}.{}",
+        book_id,
+        cover_extension(url, content_type.as_deref())
+    ));
+    std::fs::write(&path, &bytes).map_err(library_error)?;
+
+    Ok(path.to_string_lossy().into_owned())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Search the configured providers for metadata candidates. An empty list
+/// means no matches; being offline is reported as an `offline` error.
+#[tauri::command]
+pub async fn fetch_book_metadata<R: Runtime>(
+    app: AppHandle<R>,
+    query: MetadataQuery,
+) -> Result<Vec<MetadataCandidate>, LookupError> {
+    let query = normalize_query(query)?;
+    let providers = query
+        .providers
+        .clone()
+        .unwrap_or_else(|| default_providers(&app));
+
+    info!(
+        "Looking up metadata (isbn: {:?}, title: {:?}) via {:?}",
+        query.isbn, query.title, providers
+    );
+
+    let mut candidates = Vec::new();
+    let mut errors = Vec::new();
+
+    for provider in providers {
+        let result = match provider {
+            MetadataProvider::OpenLibrary => search_open_library(&query).await,
+            MetadataProvider::GoogleBooks => search_google_books(&query).await,
+        };
+
+        match result {
+            Ok(found) => candidates.extend(found),
+            Err(e) => {
+                warn!("Metadata lookup via {:?} failed: {}", provider, e);
+                errors.push(e);
+            }
+        }
+    }
+
+    // Only fail when nothing came back and every provider errored
+    if candidates.is_empty() {
+        if let Some(offline) = errors
+            .iter()
+            .find(|e| matches!(e, RequestError::Offline(_)))
+        {
+            return Err(offline.clone().into());
+        }
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error.into());
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// Apply a candidate to a library record, downloading its cover
+#[tauri::command]
+pub async fn apply_metadata<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    book_id: i64,
+    candidate: MetadataCandidate,
+) -> Result<library::Book, LookupError> {
+    info!(
+        "Applying {:?} metadata to book {}",
+        candidate.provider, book_id
+    );
+
+    // A missing cover shouldn't block the rest of the metadata
+    let cover_path = match &candidate.cover_url {
+        Some(url) => match download_cover(&app, book_id, url).await {
+            Ok(path) => Some(path),
+            Err(e) => {
+                warn!("Failed to download cover for book {}: {:?}", book_id, e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let details = BookDetails {
+        title: Some(candidate.title.clone()),
+        author: (!candidate.authors.is_empty()).then(|| candidate.authors.join(", ")),
+        description: candidate.description.clone(),
+        publish_year: candidate.publish_year,
+        isbn: candidate.isbns.iter().find(|i| i.len() == 10).cloned(),
+        isbn13: candidate.isbns.iter().find(|i| i.len() == 13).cloned(),
+        cover_path,
+    };
+    let tags: Vec<String> = candidate
+        .subjects
+        .iter()
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+        .take(MAX_SUBJECT_TAGS)
+        .collect();
+
+    db.with_conn(|conn| {
+        let tx = conn.transaction()?;
+        library::update_details(&tx, book_id, &details)?;
+        library::add_tags(&tx, book_id, &tags)?;
+        let book = library::get_book(&tx, book_id)?;
+        tx.commit()?;
+        Ok(book)
+    })
+    .map_err(|message| LookupError::Library { message })?
+    .ok_or_else(|| LookupError::Library {
+        message: format!("Book not found: {}", book_id),
+    })
+}
@@ -0,0 +1,465 @@
+// Read Master Desktop - Mobipocket Metadata
+//
+// Title, authors, and cover of Kindle books (.mobi, .azw, .azw3) for the
+// library. These are Palm databases whose first record holds a PalmDOC
+// header, the MOBI header, and EXTH metadata records; images, including
+// the cover, are later records. Only the headers and the cover record are
+// read. Book text isn't decoded yet.
+//
+// DRM-protected books are refused with a `drm-protected` error, since
+// they could never be opened.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use log::info;
+
+use crate::epub::metadata::{EpubMetadata, Layout, PageDirection, Viewport};
+
+/// Error for books with DRM, matched by the frontend
+pub const DRM_ERROR: &str = "drm-protected";
+
+/// Palm database header, up to the record count
+const PDB_HEADER_LEN: usize = 78;
+
+/// Size of each record list entry
+const RECORD_ENTRY_LEN: usize = 8;
+
+/// The PalmDOC header ahead of the MOBI header in record 0
+const PALMDOC_HEADER_LEN: usize = 16;
+
+/// MOBI header flag for an EXTH block
+const EXTH_FLAG: u32 = 0x40;
+
+/// Code page of a UTF-8 book; anything else is Windows-1252
+const UTF8_CODE_PAGE: u32 = 65001;
+
+/// First MOBI format version that is KF8 (AZW3)
+const KF8_VERSION: u32 = 8;
+
+/// No image is stored as this record offset
+const NO_RECORD: u32 = 0xFFFF_FFFF;
+
+// EXTH record types
+const EXTH_AUTHOR: u32 = 100;
+const EXTH_PUBLISHER: u32 = 101;
+const EXTH_DESCRIPTION: u32 = 103;
+const EXTH_ISBN: u32 = 104;
+const EXTH_ASIN: u32 = 113;
+const EXTH_FIXED_LAYOUT: u32 = 122;
+const EXTH_COVER_OFFSET: u32 = 201;
+const EXTH_ORIGINAL_RESOLUTION: u32 = 307;
+const EXTH_UPDATED_TITLE: u32 = 503;
+const EXTH_LANGUAGE: u32 = 524;
+const EXTH_WRITING_MODE: u32 = 525;
+const EXTH_PAGE_DIRECTION: u32 = 527;
+
+/// Windows-1252 characters for bytes 0x80-0x9F; the rest match Latin-1
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// What record 0 says about the book
+#[derive(Debug, Default, PartialEq)]
+struct Header {
+    encrypted: bool,
+    version: u32,
+    /// Record index of the first image
+    first_image: Option<u32>,
+    /// Windows LCID-style locale from the MOBI header
+    locale: u32,
+    utf8: bool,
+    full_name: Option<String>,
+    /// EXTH records as (type, data)
+    exth: Vec<(u32, Vec<u8>)>,
+}
+
+impl Header {
+    fn text(&self, data: &[u8]) -> String {
+        if self.utf8 {
+            String::from_utf8_lossy(data).into_owned()
+        } else {
+            data.iter()
+                .map(|&b| match b {
+                    0x80..=0x9F => CP1252_HIGH[usize::from(b - 0x80)],
+                    _ => char::from(b),
+                })
+                .collect()
+        }
+    }
+
+    /// Text of every EXTH record of `kind`, trimmed, skipping empty ones
+    fn exth_texts(&self, kind: u32) -> Vec<String> {
+        self.exth
+            .iter()
+            .filter(|(k, _)| *k == kind)
+            .map(|(_, data)| self.text(data).trim().to_string())
+            .filter(|text| !text.is_empty())
+            .collect()
+    }
+
+    fn exth_text(&self, kind: u32) -> Option<String> {
+        self.exth_texts(kind).into_iter().next()
+    }
+
+    fn exth_u32(&self, kind: u32) -> Option<u32> {
+        self.exth
+            .iter()
+            .find(|(k, data)| *k == kind && data.len() == 4)
+            .and_then(|(_, data)| read_u32(data, 0))
+    }
+
+    /// Record index of the cover image
+    fn cover_record(&self) -> Option<u32> {
+        let offset = self
+            .exth_u32(EXTH_COVER_OFFSET)
+            .filter(|&o| o != NO_RECORD)?;
+        self.first_image?.checked_add(offset)
+    }
+
+    /// BCP 47 language, from EXTH or else the header locale
+    fn language(&self) -> Option<String> {
+        self.exth_text(EXTH_LANGUAGE).or_else(|| {
+            let code = match self.locale & 0xFF {
+                0x04 => "zh",
+                0x07 => "de",
+                0x09 => "en",
+                0x0A => "es",
+                0x0C => "fr",
+                0x10 => "it",
+                0x11 => "ja",
+                0x12 => "ko",
+                0x13 => "nl",
+                0x15 => "pl",
+                0x16 => "pt",
+                0x19 => "ru",
+                0x1D => "sv",
+                _ => return None,
+            };
+            Some(code.to_string())
+        })
+    }
+
+    fn metadata(&self) -> EpubMetadata {
+        let page_direction = match self.exth_text(EXTH_PAGE_DIRECTION).as_deref() {
+            Some("rtl") => PageDirection::Rtl,
+            Some("ltr") => PageDirection::Ltr,
+            _ => PageDirection::Default,
+        };
+        let layout = match self.exth_text(EXTH_FIXED_LAYOUT).as_deref() {
+            Some("true") => Layout::PrePaginated,
+            _ => Layout::Reflowable,
+        };
+        // "1072x1448"
+        let viewport = self
+            .exth_text(EXTH_ORIGINAL_RESOLUTION)
+            .and_then(|resolution| {
+                let (width, height) = resolution.split_once('x')?;
+                Some(Viewport {
+                    width: width.trim().parse().ok()?,
+                    height: height.trim().parse().ok()?,
+                })
+            });
+
+        EpubMetadata {
+            title: self
+                .exth_text(EXTH_UPDATED_TITLE)
+                .or_else(|| self.full_name.clone()),
+            authors: self.exth_texts(EXTH_AUTHOR),
+            language: self.language(),
+            identifier: self
+                .exth_text(EXTH_ISBN)
+                .or_else(|| self.exth_text(EXTH_ASIN)),
+            publisher: self.exth_text(EXTH_PUBLISHER),
+            description: self.exth_text(EXTH_DESCRIPTION),
+            cover_href: self
+                .cover_record()
+                .map(|record| format!("record:{}", record)),
+            page_direction,
+            writing_mode: self
+                .exth_text(EXTH_WRITING_MODE)
+                .filter(|mode| mode.starts_with("vertical")),
+            series_name: None,
+            series_index: None,
+            layout,
+            viewport,
+            spine_layouts: Vec::new(),
+        }
+    }
+}
+
+// ============================================================================
+// Parsing
+// ============================================================================
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Whether a file starts like a Mobipocket book
+pub fn is_mobi(header: &[u8]) -> bool {
+    header.get(60..68) == Some(b"BOOKMOBI")
+}
+
+fn parse_exth(data: &[u8]) -> Vec<(u32, Vec<u8>)> {
+    let mut records = Vec::new();
+    if data.get(0..4) != Some(b"EXTH") {
+        return records;
+    }
+    let count = read_u32(data, 8).unwrap_or(0);
+    let mut at = 12;
+    for _ in 0..count {
+        let (Some(kind), Some(len)) = (read_u32(data, at), read_u32(data, at + 4)) else {
+            break;
+        };
+        let len = len as usize;
+        let Some(value) = data.get(at + 8..at + len.max(8)) else {
+            break;
+        };
+        records.push((kind, value.to_vec()));
+        at += len.max(8);
+    }
+    records
+}
+
+/// Parse record 0
+fn parse_header(record: &[u8]) -> Result<Header, String> {
+    let corrupt = || "Corrupt MOBI header".to_string();
+    let mobi = record.get(PALMDOC_HEADER_LEN..).ok_or_else(corrupt)?;
+    if mobi.get(0..4) != Some(b"MOBI") {
+        return Err(corrupt());
+    }
+    let header_len = read_u32(mobi, 4).ok_or_else(corrupt)? as usize;
+    let field = |at: usize| read_u32(mobi, at).filter(|_| at + 4 <= header_len);
+
+    let mut header = Header {
+        encrypted: read_u16(record, 12).ok_or_else(corrupt)? != 0,
+        version: field(20).unwrap_or(0),
+        first_image: field(92).filter(|&index| index != NO_RECORD),
+        locale: field(76).unwrap_or(0),
+        utf8: field(12) == Some(UTF8_CODE_PAGE),
+        ..Default::default()
+    };
+
+    if let (Some(offset), Some(len)) = (field(68), field(72)) {
+        let (offset, len) = (offset as usize, len as usize);
+        header.full_name = record
+            .get(offset..offset + len)
+            .map(|name| header.text(name).trim().to_string())
+            .filter(|name| !name.is_empty());
+    }
+    if field(112).is_some_and(|flags| flags & EXTH_FLAG != 0) {
+        let exth = record
+            .get(PALMDOC_HEADER_LEN + header_len..)
+            .unwrap_or_default();
+        header.exth = parse_exth(exth);
+    }
+    Ok(header)
+}
+
+// ============================================================================
+// File Access
+// ============================================================================
+
+/// An open Mobipocket file with its record offsets
+struct MobiFile {
+    file: File,
+    records: Vec<u64>,
+    len: u64,
+}
+
+impl MobiFile {
+    fn open(path: &Path) -> Result<Self, String> {
+        let mut file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+            .len();
+
+        let mut pdb = [0u8; PDB_HEADER_LEN];
+        file.read_exact(&mut pdb)
+            .map_err(|_| "Not a MOBI file".to_string())?;
+        if !is_mobi(&pdb) {
+            return Err("Not a MOBI file".to_string());
+        }
+
+        let count = usize::from(read_u16(&pdb, 76).unwrap_or(0));
+        let mut list = vec![0u8; count * RECORD_ENTRY_LEN];
+        file.read_exact(&mut list)
+            .map_err(|_| "Corrupt MOBI record list".to_string())?;
+        let records = list
+            .chunks_exact(RECORD_ENTRY_LEN)
+            .filter_map(|entry| read_u32(entry, 0).map(u64::from))
+            .collect();
+
+        Ok(Self { file, records, len })
+    }
+
+    fn record(&mut self, index: usize) -> Result<Vec<u8>, String> {
+        let start = *self
+            .records
+            .get(index)
+            .ok_or_else(|| format!("MOBI record {} missing", index))?;
+        let end = self.records.get(index + 1).copied().unwrap_or(self.len);
+        if end < start || end > self.len {
+            return Err(format!("Corrupt MOBI record {}", index));
+        }
+
+        let mut data = vec![0u8; (end - start) as usize];
+        self.file
+            .seek(SeekFrom::Start(start))
+            .and_then(|_| self.file.read_exact(&mut data))
+            .map_err(|e| format!("Failed to read MOBI record {}: {}", index, e))?;
+        Ok(data)
+    }
+
+    fn header(&mut self) -> Result<Header, String> {
+        let header = parse_header(&self.record(0)?)?;
+        if header.encrypted {
+            return Err(DRM_ERROR.to_string());
+        }
+        Ok(header)
+    }
+}
+
+/// Whether the book is KF8 (AZW3) rather than the older Mobipocket format
+pub fn is_kf8(path: &Path) -> Result<bool, String> {
+    let header = parse_header(&MobiFile::open(path)?.record(0)?)?;
+    Ok(header.version >= KF8_VERSION)
+}
+
+/// Metadata of a Mobipocket book. `cover_href` names the cover's record
+/// as `record:<index>`; `read_cover` returns its image.
+pub fn read_metadata(path: &Path) -> Result<EpubMetadata, String> {
+    Ok(MobiFile::open(path)?.header()?.metadata())
+}
+
+/// The cover image, if the book has one
+pub fn read_cover(path: &Path) -> Result<Option<Vec<u8>>, String> {
+    let mut mobi = MobiFile::open(path)?;
+    match mobi.header()?.cover_record() {
+        Some(record) => mobi.record(record as usize).map(Some),
+        None => Ok(None),
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Read a Mobipocket/AZW3 book's metadata
+#[tauri::command]
+pub async fn get_mobi_metadata(path: String) -> Result<EpubMetadata, String> {
+    info!("Reading MOBI metadata: {}", path);
+    read_metadata(Path::new(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exth(records: &[(u32, &[u8])]) -> Vec<u8> {
+        let body: Vec<u8> = records
+            .iter()
+            .flat_map(|(kind, data)| {
+                let mut record = kind.to_be_bytes().to_vec();
+                record.extend((data.len() as u32 + 8).to_be_bytes());
+                record.extend_from_slice(data);
+                record
+            })
+            .collect();
+        let mut block = b"EXTH".to_vec();
+        block.extend((body.len() as u32 + 12).to_be_bytes());
+        block.extend((records.len() as u32).to_be_bytes());
+        block.extend(body);
+        block
+    }
+
+    /// Record 0 with a 232-byte MOBI header, EXTH, and the full name
+    fn record0(encryption: u16, code_page: u32, exth_records: &[(u32, &[u8])]) -> Vec<u8> {
+        const MOBI_LEN: usize = 232;
+        let exth = exth(exth_records);
+        let name = b"Full Name";
+        let name_offset = PALMDOC_HEADER_LEN + MOBI_LEN + exth.len();
+
+        let mut record = vec![0u8; PALMDOC_HEADER_LEN];
+        record[12..14].copy_from_slice(&encryption.to_be_bytes());
+
+        let mut mobi = vec![0u8; MOBI_LEN];
+        mobi[0..4].copy_from_slice(b"MOBI");
+        mobi[4..8].copy_from_slice(&(MOBI_LEN as u32).to_be_bytes());
+        mobi[12..16].copy_from_slice(&code_page.to_be_bytes());
+        mobi[20..24].copy_from_slice(&6u32.to_be_bytes());
+        mobi[68..72].copy_from_slice(&(name_offset as u32).to_be_bytes());
+        mobi[72..76].copy_from_slice(&(name.len() as u32).to_be_bytes());
+        mobi[76..80].copy_from_slice(&0x0409u32.to_be_bytes());
+        mobi[92..96].copy_from_slice(&5u32.to_be_bytes());
+        mobi[112..116].copy_from_slice(&EXTH_FLAG.to_be_bytes());
+
+        record.extend(mobi);
+        record.extend(exth);
+        record.extend_from_slice(name);
+        record
+    }
+
+    #[test]
+    fn reads_exth_metadata() {
+        let record = record0(
+            0,
+            UTF8_CODE_PAGE,
+            &[
+                (EXTH_AUTHOR, "Ursula K. Le Guin".as_bytes()),
+                (EXTH_AUTHOR, b"Second Author"),
+                (EXTH_PUBLISHER, b"Ace"),
+                (EXTH_ISBN, b"9780441478125"),
+                (EXTH_UPDATED_TITLE, "The Left Hand of Darkness".as_bytes()),
+                (EXTH_COVER_OFFSET, &2u32.to_be_bytes()),
+            ],
+        );
+        let meta = parse_header(&record).unwrap().metadata();
+
+        assert_eq!(meta.title.as_deref(), Some("The Left Hand of Darkness"));
+        assert_eq!(meta.authors, ["Ursula K. Le Guin", "Second Author"]);
+        assert_eq!(meta.publisher.as_deref(), Some("Ace"));
+        assert_eq!(meta.identifier.as_deref(), Some("9780441478125"));
+        assert_eq!(meta.language.as_deref(), Some("en"));
+        // First image is record 5
+        assert_eq!(meta.cover_href.as_deref(), Some("record:7"));
+    }
+
+    #[test]
+    fn falls_back_to_the_full_name() {
+        let record = record0(0, 1252, &[(EXTH_AUTHOR, b"Andr\xe9 Gide \x96 Translated")]);
+        let meta = parse_header(&record).unwrap().metadata();
+        assert_eq!(meta.title.as_deref(), Some("Full Name"));
+        assert_eq!(meta.authors, ["André Gide – Translated"]);
+        assert_eq!(meta.cover_href, None);
+    }
+
+    #[test]
+    fn flags_drm() {
+        let header = parse_header(&record0(2, UTF8_CODE_PAGE, &[])).unwrap();
+        assert!(header.encrypted);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(parse_header(b"not a mobi header at all").is_err());
+        let mut pdb = vec![0u8; PDB_HEADER_LEN];
+        pdb[60..68].copy_from_slice(b"BOOKMOBI");
+        assert!(is_mobi(&pdb));
+        pdb[60..68].copy_from_slice(b"TEXtREAd");
+        assert!(!is_mobi(&pdb));
+    }
+}
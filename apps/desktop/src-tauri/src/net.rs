@@ -0,0 +1,213 @@
+// Read Master Desktop - Networking
+//
+// Shared HTTP helpers: per-host rate limiting and retry with backoff, so
+// bulk operations against public APIs stay within their usage limits.
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use log::{info, warn};
+use reqwest::{Client, ClientBuilder, RequestBuilder, Response, StatusCode};
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Instant};
+
+/// Default request timeout
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Minimum spacing between requests to the same host
+const DEFAULT_HOST_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// First retry delay; doubles on each further attempt
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest Retry-After we are willing to honor before giving up
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+// ============================================================================
+// Errors
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub enum RequestError {
+    /// Could not connect at all (no network, DNS failure)
+    Offline(String),
+    Timeout,
+    /// Still rate limited after retries
+    RateLimited,
+    /// Non-success status that isn't worth retrying
+    Status(StatusCode),
+    Other(String),
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Offline(e) => write!(f, "Network unavailable: {}", e),
+            Self::Timeout => write!(f, "Request timed out"),
+            Self::RateLimited => write!(f, "Rate limited by server"),
+            Self::Status(status) => write!(f, "HTTP {}", status),
+            Self::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for RequestError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout
+        } else if e.is_connect() {
+            Self::Offline(e.to_string())
+        } else {
+            Self::Other(e.to_string())
+        }
+    }
+}
+
+// ============================================================================
+// Rate Limiting
+// ============================================================================
+
+/// Spaces out requests per host. Callers wait their turn instead of being
+/// rejected, so bulk jobs naturally slow down to the allowed rate.
+pub struct HostRateLimiter {
+    intervals: HashMap<&'static str, Duration>,
+    next_slot: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostRateLimiter {
+    fn new() -> Self {
+        Self {
+            // Open Library asks for at most one request per second;
+            // Google Books' anonymous quota is similar in practice
+            intervals: HashMap::from([
+                ("openlibrary.org", Duration::from_millis(1000)),
+                ("covers.openlibrary.org", Duration::from_millis(500)),
+                ("www.googleapis.com", Duration::from_millis(1000)),
+            ]),
+            next_slot: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait until a request to `host` is allowed
+    pub async fn acquire(&self, host: &str) {
+        let interval = self
+            .intervals
+            .get(host)
+            .copied()
+            .unwrap_or(DEFAULT_HOST_INTERVAL);
+
+        let wait_until = {
+            let mut slots = self.next_slot.lock().await;
+            let now = Instant::now();
+            let slot = slots.get(host).copied().unwrap_or(now).max(now);
+            slots.insert(host.to_string(), slot + interval);
+            slot
+        };
+
+        sleep(wait_until.saturating_duration_since(Instant::now())).await;
+    }
+
+    /// Push back the next slot for `host`, e.g. after a 429
+    pub async fn penalize(&self, host: &str, delay: Duration) {
+        let mut slots = self.next_slot.lock().await;
+        let until = Instant::now() + delay;
+        let slot = slots.entry(host.to_string()).or_insert(until);
+        *slot = (*slot).max(until);
+    }
+}
+
+/// Process-wide limiter shared by every lookup
+pub static RATE_LIMITER: LazyLock<HostRateLimiter> = LazyLock::new(HostRateLimiter::new);
+
+// ============================================================================
+// Requests
+// ============================================================================
+
+/// Client builder with the app's user agent and default timeout
+pub fn client_builder() -> ClientBuilder {
+    Client::builder()
+        .timeout(DEFAULT_TIMEOUT)
+        .user_agent(concat!(
+            "ReadMaster/",
+            env!("CARGO_PKG_VERSION"),
+            " (+https://github.com/read-master/read-master)"
+        ))
+}
+
+/// HTTP client with the app's user agent and default timeout
+pub fn client() -> Client {
+    client_builder().build().unwrap_or_default()
+}
+
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// Send a request built by `build`, rate limited per host and retried with
+/// exponential backoff on timeouts, 429 and 5xx responses. Connection
+/// failures are not retried: they almost always mean we're offline.
+pub async fn send_with_retry(
+    url: &str,
+    attempts: u32,
+    build: impl Fn() -> RequestBuilder,
+) -> Result<Response, RequestError> {
+    let host = url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_default();
+
+    let mut last_error = RequestError::Other("No attempts made".to_string());
+
+    for attempt in 0..attempts.max(1) {
+        if attempt > 0 {
+            let delay = BASE_BACKOFF * 2u32.pow(attempt - 1);
+            info!("Retrying {} in {:?} (attempt {})", host, delay, attempt + 1);
+            sleep(delay).await;
+        }
+
+        RATE_LIMITER.acquire(&host).await;
+
+        let response = match build().send().await {
+            Ok(response) => response,
+            Err(e) => {
+                let error = RequestError::from(e);
+                if let RequestError::Offline(_) = error {
+                    return Err(error);
+                }
+                warn!("Request to {} failed: {}", host, error);
+                last_error = error;
+                continue;
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let delay = retry_after(&response).unwrap_or(BASE_BACKOFF * 4);
+            if delay > MAX_RETRY_AFTER {
+                return Err(RequestError::RateLimited);
+            }
+            RATE_LIMITER.penalize(&host, delay).await;
+            last_error = RequestError::RateLimited;
+            continue;
+        }
+
+        if status.is_server_error() {
+            last_error = RequestError::Status(status);
+            continue;
+        }
+
+        return Err(RequestError::Status(status));
+    }
+
+    Err(last_error)
+}
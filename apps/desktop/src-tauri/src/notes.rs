@@ -0,0 +1,782 @@
+// Read Master Desktop - Notes
+//
+// Highlight/note storage, persisted to `notes.json` keyed by note id.
+//
+// Highlights keep a text quote anchor (prefix, exact, suffix) next to their
+// locator, and the content hash of the file the locator refers to. When the
+// book's file is replaced, `reanchor_annotations` finds each quote in the
+// new text and rewrites the locator; highlights whose quote can't be found
+// are marked orphaned rather than deleted.
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::bookmarks::{compare_locators, word_byte_offset};
+use crate::db::Database;
+use crate::epub::cfi;
+use crate::epub::text::document_text;
+use crate::epub::EpubArchive;
+use crate::profiles::ProfileStoreExt;
+use crate::quote_anchor::{self, TextQuote};
+use crate::scripting::{self, Hook};
+use crate::sync::changelog::{self, ChangeEntity};
+use crate::{library, pdf, persist};
+
+const STORE_FILE: &str = "notes.json";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Note {
+    pub id: String,
+    /// Absent for notes captured outside a book (quick capture)
+    pub book_id: Option<String>,
+    pub locator: Option<String>,
+    /// Highlighted text the note is attached to
+    pub quote: Option<String>,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub color: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Quote with its surrounding text, for finding the highlight again
+    #[serde(default)]
+    pub anchor: Option<TextQuote>,
+    /// Hash of the book file the locator refers to
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// The book's file changed and the quote wasn't found in it
+    #[serde(default)]
+    pub orphaned: bool,
+    /// How well the quote matched at the last re-anchor, 0.0 to 1.0
+    #[serde(default)]
+    pub anchor_confidence: Option<f64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AnchorStatus {
+    /// Made against the book's current file
+    Current,
+    /// Moved to where its quote appears in the new file
+    Reanchored,
+    /// Not found in the new file; the old locator is kept
+    Orphaned,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReanchorResult {
+    pub note_id: String,
+    pub status: AnchorStatus,
+    /// Match score for re-anchored and orphaned notes that had a quote
+    pub confidence: Option<f64>,
+    pub locator: Option<String>,
+}
+
+/// A highlight from an e-reader or another app, to merge into a book
+#[derive(Debug, Clone)]
+pub struct ImportedHighlight {
+    pub quote: String,
+    /// The reader's own note on the highlight, if any
+    pub body: String,
+    /// RFC 3339; import time when unknown
+    pub created_at: Option<String>,
+}
+
+/// Outcome of `merge_highlights`
+#[derive(Debug, Default)]
+pub struct HighlightMerge {
+    pub added: usize,
+    pub duplicates: usize,
+    /// Duplicates whose note text differed from the existing note's
+    pub conflicts: Vec<String>,
+}
+
+/// A searchable part of a book
+enum Section {
+    /// Spine document, with its index and markup
+    Epub {
+        index: usize,
+        markup: String,
+        text: String,
+    },
+    /// Page text, by 0-based page index
+    PdfPage { page: u32, text: String },
+    /// A whole plain-text file
+    Text { text: String },
+}
+
+impl Section {
+    fn text(&self) -> &str {
+        match self {
+            Self::Epub { text, .. } | Self::PdfPage { text, .. } | Self::Text { text } => text,
+        }
+    }
+
+    /// Locator of the `word`th word: a CFI, `page:offset` for PDFs, or a
+    /// byte offset for plain text, as bookmarks use
+    fn locator(&self, word: usize) -> Option<String> {
+        match self {
+            Self::Epub { index, markup, .. } => {
+                cfi::from_word_index(markup, *index, word).map(|cfi| cfi.to_string())
+            }
+            Self::PdfPage { page, text } => {
+                let byte = word_byte_offset(text, word)?;
+                Some(format!("{}:{}", page, text[..byte].chars().count()))
+            }
+            Self::Text { text } => word_byte_offset(text, word).map(|byte| byte.to_string()),
+        }
+    }
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Lowercase, trim, and dedupe tags
+fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags
+        .into_iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Every note in the store, skipping entries that fail to parse
+pub fn load_all<R: Runtime>(app: &AppHandle<R>) -> Result<Vec<Note>, String> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    Ok(store
+        .values()
+        .into_iter()
+        .filter_map(|value| serde_json::from_value(value).ok())
+        .collect())
+}
+
+/// A book's notes in reading order
+pub fn book_notes<R: Runtime>(app: &AppHandle<R>, book_id: &str) -> Result<Vec<Note>, String> {
+    let mut notes: Vec<Note> = load_all(app)?
+        .into_iter()
+        .filter(|n| n.book_id.as_deref() == Some(book_id))
+        .collect();
+
+    notes.sort_by(|a, b| {
+        compare_locators(
+            a.locator.as_deref().unwrap_or(""),
+            b.locator.as_deref().unwrap_or(""),
+        )
+        .then_with(|| a.created_at.cmp(&b.created_at))
+    });
+    Ok(notes)
+}
+
+fn load<R: Runtime>(app: &AppHandle<R>, id: &str) -> Result<Note, String> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    let value = store
+        .get(id)
+        .ok_or_else(|| format!("Note not found: {}", id))?;
+    serde_json::from_value(value).map_err(|e| format!("Corrupt note data: {}", e))
+}
+
+fn save<R: Runtime>(app: &AppHandle<R>, note: &Note) -> Result<(), String> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    let value =
+        serde_json::to_value(note).map_err(|e| format!("Failed to serialize note: {}", e))?;
+    store.set(&note.id, value);
+    persist::mark_dirty(app, STORE_FILE);
+    changelog::record(app, ChangeEntity::Note, &note.id, change_data(app, note));
+    Ok(())
+}
+
+/// A note as a change log record, with its book named by content hash
+fn change_data<R: Runtime>(app: &AppHandle<R>, note: &Note) -> Option<Value> {
+    let book_hash = note
+        .book_id
+        .as_deref()
+        .and_then(|id| id.parse::<i64>().ok())
+        .and_then(|id| {
+            app.state::<Database>()
+                .with_conn(|conn| library::get_book(conn, id))
+                .ok()
+                .flatten()
+        })
+        .and_then(|book| book.content_hash);
+
+    let mut record = serde_json::to_value(note).ok()?;
+    if let Value::Object(fields) = &mut record {
+        fields.remove("bookId");
+        fields.insert("bookHash".to_string(), book_hash.into());
+    }
+    Some(record)
+}
+
+/// Attach notes to another book, e.g. when duplicates are merged. Returns
+/// how many were found.
+pub fn move_to_book<R: Runtime>(
+    app: &AppHandle<R>,
+    note_ids: &[String],
+    book_id: &str,
+) -> Result<usize, String> {
+    let mut moved = 0;
+    for id in note_ids {
+        let mut note = match load(app, id) {
+            Ok(note) => note,
+            Err(e) => {
+                warn!("Not moving note {}: {}", id, e);
+                continue;
+            }
+        };
+        note.book_id = Some(book_id.to_string());
+        note.updated_at = chrono::Utc::now().to_rfc3339();
+        save(app, &note)?;
+        moved += 1;
+    }
+    Ok(moved)
+}
+
+/// Whether every query term appears in the note's quote, body, or tags
+fn matches(note: &Note, terms: &[String]) -> bool {
+    let haystack = format!(
+        "{}\n{}\n{}",
+        note.quote.as_deref().unwrap_or(""),
+        note.body,
+        note.tags.join(" ")
+    )
+    .to_lowercase();
+
+    terms.iter().all(|term| haystack.contains(term.as_str()))
+}
+
+// ============================================================================
+// Re-anchoring
+// ============================================================================
+
+fn book_sections(path: &str, format: Option<&str>) -> Result<Vec<Section>, String> {
+    match format {
+        Some("epub") => {
+            let mut epub = EpubArchive::open(path).map_err(|e| e.to_string())?;
+            Ok(epub
+                .spine_paths()
+                .into_iter()
+                .enumerate()
+                .filter_map(|(index, href)| {
+                    let markup = epub.read_string(&href).ok()?;
+                    let (text, _) = document_text(&markup);
+                    Some(Section::Epub {
+                        index,
+                        markup,
+                        text,
+                    })
+                })
+                .collect())
+        }
+        Some("pdf") => {
+            let file = pdf::load(path)?;
+            Ok((0..file.num_pages())
+                .filter_map(|page| {
+                    let text = pdf::page_text(&file, page).ok()?;
+                    Some(Section::PdfPage { page, text })
+                })
+                .collect())
+        }
+        _ => {
+            let data = std::fs::read(path).map_err(|e| format!("Failed to read book: {}", e))?;
+            Ok(vec![Section::Text {
+                text: String::from_utf8_lossy(&data).into_owned(),
+            }])
+        }
+    }
+}
+
+/// Find each note's quote in the book's sections. Returns the best match's
+/// confidence and, when it is trusted, the new locator.
+fn relocate(sections: &[Section], notes: &[Note]) -> Vec<(Option<f64>, Option<String>)> {
+    let words: Vec<Vec<String>> = sections
+        .iter()
+        .map(|section| quote_anchor::words(section.text()))
+        .collect();
+
+    notes
+        .iter()
+        .map(|note| {
+            let anchor = match (&note.anchor, &note.quote) {
+                (Some(anchor), _) => anchor.clone(),
+                (None, Some(quote)) => TextQuote {
+                    prefix: String::new(),
+                    exact: quote.clone(),
+                    suffix: String::new(),
+                },
+                (None, None) => return (None, None),
+            };
+
+            let best = sections
+                .iter()
+                .zip(&words)
+                .filter_map(|(section, words)| {
+                    quote_anchor::find_quote(words, &anchor).map(|found| (section, found))
+                })
+                .fold(
+                    None,
+                    |best: Option<(&Section, quote_anchor::QuoteMatch)>, next| match best {
+                        Some(best) if best.1.confidence >= next.1.confidence => Some(best),
+                        _ => Some(next),
+                    },
+                );
+
+            match best {
+                Some((section, found)) if found.confidence >= quote_anchor::MIN_CONFIDENCE => {
+                    (Some(found.confidence), section.locator(found.start))
+                }
+                Some((_, found)) => (Some(found.confidence), None),
+                None => (Some(0.0), None),
+            }
+        })
+        .collect()
+}
+
+/// Merge highlights into a book's notes. Each is placed where its quote
+/// appears in the book's file; one with the same text at the same place as
+/// an existing highlight is skipped, keeping the existing note.
+pub fn merge_highlights<R: Runtime>(
+    app: &AppHandle<R>,
+    book: &library::Book,
+    source: &str,
+    highlights: Vec<ImportedHighlight>,
+) -> Result<HighlightMerge, String> {
+    let book_id = book.id.to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut incoming: Vec<Note> = highlights
+        .into_iter()
+        .filter(|h| !h.quote.trim().is_empty())
+        .map(|h| Note {
+            id: uuid::Uuid::new_v4().to_string(),
+            book_id: Some(book_id.clone()),
+            locator: None,
+            quote: Some(h.quote.trim().to_string()),
+            body: h.body.trim().to_string(),
+            tags: Vec::new(),
+            color: None,
+            source: Some(source.to_string()),
+            anchor: Some(TextQuote {
+                prefix: String::new(),
+                exact: h.quote.trim().to_string(),
+                suffix: String::new(),
+            }),
+            content_hash: None,
+            orphaned: false,
+            anchor_confidence: None,
+            created_at: h.created_at.unwrap_or_else(|| now.clone()),
+            updated_at: now.clone(),
+        })
+        .collect();
+
+    let sections = match &book.path {
+        Some(path) => book_sections(path, book.format.as_deref()).unwrap_or_else(|e| {
+            warn!("Importing highlights for book {} unplaced: {}", book.id, e);
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
+    let found = relocate(&sections, &incoming);
+    for (note, (confidence, locator)) in incoming.iter_mut().zip(found) {
+        note.anchor_confidence = confidence;
+        if locator.is_some() {
+            note.content_hash = book.content_hash.clone();
+        }
+        note.locator = locator;
+    }
+
+    let key = |note: &Note| {
+        let quote = note.quote.as_deref().unwrap_or_default();
+        (quote_anchor::words(quote).join(" "), note.locator.clone())
+    };
+    let mut existing: Vec<((String, Option<String>), String)> = book_notes(app, &book_id)?
+        .iter()
+        .filter(|n| n.quote.is_some())
+        .map(|n| (key(n), n.body.clone()))
+        .collect();
+
+    let mut merge = HighlightMerge::default();
+    for note in incoming {
+        let note_key = key(&note);
+        if let Some((_, body)) = existing.iter().find(|(k, _)| *k == note_key) {
+            merge.duplicates += 1;
+            if !note.body.is_empty() && note.body != *body {
+                merge.conflicts.push(format!(
+                    "Highlight \"{}\" already has a different note; kept the existing one",
+                    note.quote.as_deref().unwrap_or_default()
+                ));
+            }
+            continue;
+        }
+        save(app, &note)?;
+        existing.push((note_key, note.body.clone()));
+        merge.added += 1;
+    }
+    Ok(merge)
+}
+
+// ============================================================================
+// Sync
+// ============================================================================
+
+/// A book's notes as records for a sync bundle, without the local book id
+pub fn sync_records<R: Runtime>(app: &AppHandle<R>, book_id: &str) -> Result<Vec<Value>, String> {
+    book_notes(app, book_id)?
+        .into_iter()
+        .map(|note| {
+            let mut record = serde_json::to_value(&note)
+                .map_err(|e| format!("Failed to serialize note: {}", e))?;
+            if let Value::Object(fields) = &mut record {
+                fields.remove("bookId");
+            }
+            Ok(record)
+        })
+        .collect()
+}
+
+/// Replace a book's notes with merged sync records
+pub fn apply_sync_records<R: Runtime>(
+    app: &AppHandle<R>,
+    book_id: &str,
+    records: &[Value],
+) -> Result<(), String> {
+    let mut notes = Vec::with_capacity(records.len());
+    for record in records {
+        let mut note: Note = serde_json::from_value(record.clone())
+            .map_err(|e| format!("Corrupt note data: {}", e))?;
+        note.book_id = Some(book_id.to_string());
+        notes.push(note);
+    }
+
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    for existing in book_notes(app, book_id)? {
+        if !notes.iter().any(|n| n.id == existing.id) {
+            store.delete(&existing.id);
+            changelog::record(app, ChangeEntity::Note, &existing.id, None);
+        }
+    }
+    for note in &notes {
+        let value =
+            serde_json::to_value(note).map_err(|e| format!("Failed to serialize note: {}", e))?;
+        if store.get(&note.id).as_ref() != Some(&value) {
+            store.set(&note.id, value);
+            changelog::record(app, ChangeEntity::Note, &note.id, change_data(app, note));
+        }
+    }
+    persist::mark_dirty(app, STORE_FILE);
+    Ok(())
+}
+
+/// Apply a synced change to a note without logging it again: `data` is
+/// its change record, or `None` to delete it, and `book_id` the local id
+/// of its book
+pub fn apply_change<R: Runtime>(
+    app: &AppHandle<R>,
+    id: &str,
+    data: Option<&Value>,
+    book_id: Option<i64>,
+) -> Result<(), String> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    match data {
+        Some(data) => {
+            let mut record = data.clone();
+            if let Value::Object(fields) = &mut record {
+                fields.remove("bookHash");
+                fields.insert(
+                    "bookId".to_string(),
+                    book_id.map(|id| id.to_string()).into(),
+                );
+            }
+            let note: Note =
+                serde_json::from_value(record).map_err(|e| format!("Corrupt note data: {}", e))?;
+            if note.id != id {
+                return Err(format!("Change for note {} carries note {}", id, note.id));
+            }
+            let value = serde_json::to_value(&note)
+                .map_err(|e| format!("Failed to serialize note: {}", e))?;
+            store.set(id, value);
+        }
+        None => {
+            store.delete(id);
+        }
+    }
+    persist::mark_dirty(app, STORE_FILE);
+    Ok(())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Create a note
+#[tauri::command]
+pub async fn add_note<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: Option<String>,
+    locator: Option<String>,
+    quote: Option<String>,
+    body: Option<String>,
+    tags: Option<Vec<String>>,
+    color: Option<String>,
+    prefix: Option<String>,
+    suffix: Option<String>,
+) -> Result<Note, String> {
+    let quote = non_empty(quote);
+    let body = non_empty(body).unwrap_or_default();
+    if quote.is_none() && body.is_empty() {
+        return Err("A note needs a quote or some text".to_string());
+    }
+
+    let book_id = non_empty(book_id);
+    let content_hash = match book_id.as_deref().and_then(|id| id.parse::<i64>().ok()) {
+        Some(id) => app
+            .state::<Database>()
+            .with_conn(|conn| library::get_book(conn, id))?
+            .and_then(|book| book.content_hash),
+        None => None,
+    };
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let note = Note {
+        id: uuid::Uuid::new_v4().to_string(),
+        book_id,
+        locator: non_empty(locator),
+        anchor: quote.as_ref().map(|exact| TextQuote {
+            prefix: prefix.unwrap_or_default(),
+            exact: exact.clone(),
+            suffix: suffix.unwrap_or_default(),
+        }),
+        quote,
+        body,
+        tags: normalize_tags(tags.unwrap_or_default()),
+        color: non_empty(color),
+        source: None,
+        content_hash,
+        orphaned: false,
+        anchor_confidence: None,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+
+    info!("Adding note {} to {:?}", note.id, note.book_id);
+    save(&app, &note)?;
+
+    if note.quote.is_some() {
+        let book_id = note.book_id.as_deref().and_then(|id| id.parse().ok());
+        scripting::trigger_in_background(
+            &app,
+            Hook::OnHighlight,
+            book_id,
+            serde_json::json!({ "note": note }),
+        );
+    }
+    Ok(note)
+}
+
+/// Update a note. Omitted fields keep their current value.
+#[tauri::command]
+pub async fn update_note<R: Runtime>(
+    app: AppHandle<R>,
+    id: String,
+    quote: Option<String>,
+    body: Option<String>,
+    tags: Option<Vec<String>>,
+    color: Option<String>,
+) -> Result<Note, String> {
+    let mut note = load(&app, &id)?;
+
+    if let Some(quote) = quote {
+        note.quote = non_empty(Some(quote));
+        note.anchor = match (note.anchor.take(), &note.quote) {
+            (Some(anchor), Some(exact)) => Some(TextQuote {
+                exact: exact.clone(),
+                ..anchor
+            }),
+            _ => None,
+        };
+    }
+    if let Some(body) = body {
+        note.body = body.trim().to_string();
+    }
+    if let Some(tags) = tags {
+        note.tags = normalize_tags(tags);
+    }
+    if let Some(color) = color {
+        note.color = non_empty(Some(color));
+    }
+    note.updated_at = chrono::Utc::now().to_rfc3339();
+
+    info!("Updating note {}", id);
+    save(&app, &note)?;
+    Ok(note)
+}
+
+/// Delete a note
+#[tauri::command]
+pub async fn delete_note<R: Runtime>(app: AppHandle<R>, id: String) -> Result<(), String> {
+    info!("Deleting note {}", id);
+
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    if !store.delete(&id) {
+        return Err(format!("Note not found: {}", id));
+    }
+    persist::mark_dirty(&app, STORE_FILE);
+    changelog::record(&app, ChangeEntity::Note, &id, None);
+    Ok(())
+}
+
+/// List a book's notes in reading order
+#[tauri::command]
+pub async fn list_notes<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: String,
+) -> Result<Vec<Note>, String> {
+    book_notes(&app, &book_id)
+}
+
+/// Search note quotes, bodies, and tags across all books. Every word in
+/// the query must match (case-insensitive); newest notes come first.
+#[tauri::command]
+pub async fn search_notes<R: Runtime>(
+    app: AppHandle<R>,
+    query: String,
+) -> Result<Vec<Note>, String> {
+    let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut notes: Vec<Note> = load_all(&app)?
+        .into_iter()
+        .filter(|note| matches(note, &terms))
+        .collect();
+
+    notes.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    Ok(notes)
+}
+
+/// Re-locate a book's highlights after its file has changed. Notes made
+/// against another version of the file are moved to where their quote
+/// appears now, or marked orphaned when it can't be found. Notes from
+/// before content hashes were recorded are assumed to match the current
+/// file.
+#[tauri::command]
+pub async fn reanchor_annotations<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: String,
+) -> Result<Vec<ReanchorResult>, String> {
+    let id: i64 = book_id
+        .parse()
+        .map_err(|_| format!("Invalid book id: {}", book_id))?;
+    let book = app
+        .state::<Database>()
+        .with_conn(|conn| library::get_book(conn, id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+    let (Some(path), Some(hash)) = (book.path.clone(), book.content_hash.clone()) else {
+        return Err(format!("Book {} has no file to anchor to", book_id));
+    };
+
+    let mut results = Vec::new();
+    let mut stale = Vec::new();
+    for mut note in book_notes(&app, &book_id)? {
+        if note
+            .content_hash
+            .as_ref()
+            .is_some_and(|noted| *noted != hash)
+        {
+            stale.push(note);
+            continue;
+        }
+        if note.content_hash.is_none() {
+            note.content_hash = Some(hash.clone());
+            save(&app, &note)?;
+        }
+        results.push(ReanchorResult {
+            note_id: note.id,
+            status: AnchorStatus::Current,
+            confidence: note.anchor_confidence,
+            locator: note.locator,
+        });
+    }
+    if stale.is_empty() {
+        return Ok(results);
+    }
+
+    info!(
+        "Re-anchoring {} note(s) in changed book {}",
+        stale.len(),
+        book_id
+    );
+    let format = book.format.clone();
+    let (stale, found) = tauri::async_runtime::spawn_blocking(move || {
+        let sections = book_sections(&path, format.as_deref())?;
+        let found = relocate(&sections, &stale);
+        Ok::<_, String>((stale, found))
+    })
+    .await
+    .map_err(|e| format!("Re-anchoring task failed: {}", e))??;
+
+    let now = chrono::Utc::now().to_rfc3339();
+    for (mut note, (confidence, locator)) in stale.into_iter().zip(found) {
+        note.anchor_confidence = confidence;
+        let status = match locator {
+            Some(locator) => {
+                note.locator = Some(locator);
+                note.content_hash = Some(hash.clone());
+                note.orphaned = false;
+                AnchorStatus::Reanchored
+            }
+            None => {
+                warn!("Note {} could not be re-anchored", note.id);
+                note.orphaned = true;
+                AnchorStatus::Orphaned
+            }
+        };
+        note.updated_at = now.clone();
+        save(&app, &note)?;
+
+        results.push(ReanchorResult {
+            note_id: note.id,
+            status,
+            confidence,
+            locator: note.locator,
+        });
+    }
+    Ok(results)
+}
@@ -0,0 +1,475 @@
+// Read Master Desktop - Notification Policy
+//
+// Decides whether a notification is shown, so callers of
+// `notifications::show` don't each have to check the user's preferences.
+// Each category can be switched off, quiet hours hold notifications back
+// on a weekly schedule, and "only when hidden" keeps them away while the
+// main window is on screen. The system's do-not-disturb is respected where
+// the OS reports it: Focus on macOS, and on Windows the busy, full-screen,
+// and presentation states the shell reports.
+//
+// Every notification, shown or held back, goes into the notification
+// center's history, so nothing suppressed is silently lost.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::persist;
+use crate::profiles::ProfileStoreExt;
+
+const SETTINGS_STORE: &str = "settings.json";
+const POLICY_SETTING: &str = "notificationPolicy";
+
+const HISTORY_STORE: &str = "notification-history.json";
+const HISTORY_KEY: &str = "entries";
+
+/// History entries kept; the oldest are dropped first
+pub const MAX_HISTORY: usize = 200;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationCategory {
+    Reminders,
+    Sync,
+    Imports,
+    Updates,
+    Social,
+}
+
+/// Hours during which notifications are held back. `days` are the weekdays
+/// (0 = Monday) on which the quiet period starts; a period that ends
+/// earlier in the day than it starts runs past midnight.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuietHours {
+    pub enabled: bool,
+    /// "HH:MM", local time
+    pub start: String,
+    /// "HH:MM", local time
+    pub end: String,
+    pub days: Vec<u8>,
+}
+
+impl Default for QuietHours {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start: "22:00".to_string(),
+            end: "07:00".to_string(),
+            days: (0..7).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NotificationPolicy {
+    /// Categories switched on or off; missing ones are on
+    pub categories: BTreeMap<NotificationCategory, bool>,
+    pub quiet_hours: QuietHours,
+    /// Only notify while the main window is hidden or minimized
+    pub only_when_hidden: bool,
+    /// Hold notifications back while the system's do-not-disturb is on
+    pub respect_system_dnd: bool,
+}
+
+impl Default for NotificationPolicy {
+    fn default() -> Self {
+        Self {
+            categories: BTreeMap::new(),
+            quiet_hours: QuietHours::default(),
+            only_when_hidden: false,
+            respect_system_dnd: true,
+        }
+    }
+}
+
+/// Why a notification wasn't shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SuppressReason {
+    CategoryDisabled,
+    QuietHours,
+    WindowVisible,
+    DoNotDisturb,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    /// Id of the shown notification; `None` when it was suppressed
+    pub id: Option<u32>,
+    pub title: String,
+    pub body: Option<String>,
+    pub category: Option<NotificationCategory>,
+    pub route: Option<String>,
+    pub created_at: String,
+    pub suppressed: Option<SuppressReason>,
+}
+
+// ============================================================================
+// Policy
+// ============================================================================
+
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|_| format!("Invalid time '{}' (expected HH:MM)", value))
+}
+
+impl QuietHours {
+    /// Whether `now` falls in a quiet period
+    fn contains(&self, now: NaiveDateTime) -> bool {
+        let (Ok(start), Ok(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+            return false;
+        };
+        let starts_on = |date: NaiveDate| {
+            let day = date.weekday().num_days_from_monday() as u8;
+            self.days.contains(&day)
+        };
+        let time = now.time();
+        let today = now.date();
+
+        if start <= end {
+            starts_on(today) && time >= start && time < end
+        } else {
+            // Overnight: tonight's period, or the one that started yesterday
+            (starts_on(today) && time >= start)
+                || (time < end && today.pred_opt().is_some_and(starts_on))
+        }
+    }
+}
+
+impl NotificationPolicy {
+    fn validate(&self) -> Result<(), String> {
+        parse_time(&self.quiet_hours.start)?;
+        parse_time(&self.quiet_hours.end)?;
+        if let Some(day) = self.quiet_hours.days.iter().find(|day| **day > 6) {
+            return Err(format!("Invalid weekday {} (expected 0-6)", day));
+        }
+        Ok(())
+    }
+
+    fn enabled(&self, category: NotificationCategory) -> bool {
+        self.categories.get(&category).copied().unwrap_or(true)
+    }
+}
+
+/// Why a notification in `category` shouldn't be shown at `now`, if it
+/// shouldn't
+fn suppression(
+    policy: &NotificationPolicy,
+    category: Option<NotificationCategory>,
+    now: NaiveDateTime,
+    window_visible: bool,
+    system_dnd: bool,
+) -> Option<SuppressReason> {
+    if category.is_some_and(|c| !policy.enabled(c)) {
+        Some(SuppressReason::CategoryDisabled)
+    } else if policy.quiet_hours.enabled && policy.quiet_hours.contains(now) {
+        Some(SuppressReason::QuietHours)
+    } else if policy.only_when_hidden && window_visible {
+        Some(SuppressReason::WindowVisible)
+    } else if policy.respect_system_dnd && system_dnd {
+        Some(SuppressReason::DoNotDisturb)
+    } else {
+        None
+    }
+}
+
+pub fn load_policy<R: Runtime>(app: &AppHandle<R>) -> NotificationPolicy {
+    app.profile_store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(POLICY_SETTING))
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| warn!("Ignoring corrupt notification policy: {}", e))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+fn window_visible<R: Runtime>(app: &AppHandle<R>) -> bool {
+    app.get_webview_window("main").is_some_and(|window| {
+        window.is_visible().unwrap_or(false) && !window.is_minimized().unwrap_or(false)
+    })
+}
+
+/// Why a notification in `category` shouldn't be shown right now, if it
+/// shouldn't
+pub fn check<R: Runtime>(
+    app: &AppHandle<R>,
+    category: Option<NotificationCategory>,
+) -> Option<SuppressReason> {
+    let policy = load_policy(app);
+    // Only ask the OS when the answer matters
+    let dnd = policy.respect_system_dnd && system_dnd();
+    suppression(
+        &policy,
+        category,
+        Local::now().naive_local(),
+        window_visible(app),
+        dnd,
+    )
+}
+
+// ============================================================================
+// System Do Not Disturb
+// ============================================================================
+
+/// Whether the shell would hold toasts back: busy, a full-screen game, or
+/// presentation mode. Focus Assist itself has no public API.
+#[cfg(target_os = "windows")]
+fn system_dnd() -> bool {
+    use windows::Win32::UI::Shell::{
+        SHQueryUserNotificationState, QUNS_BUSY, QUNS_PRESENTATION_MODE,
+        QUNS_RUNNING_D3D_FULL_SCREEN,
+    };
+
+    match unsafe { SHQueryUserNotificationState() } {
+        Ok(state) => [
+            QUNS_BUSY,
+            QUNS_RUNNING_D3D_FULL_SCREEN,
+            QUNS_PRESENTATION_MODE,
+        ]
+        .contains(&state),
+        Err(e) => {
+            warn!("Failed to query notification state: {}", e);
+            false
+        }
+    }
+}
+
+/// Whether a Focus is on. Active Focus assertions are only readable with
+/// Full Disk Access; without it, fall back to the pre-Monterey setting.
+#[cfg(target_os = "macos")]
+fn system_dnd() -> bool {
+    let assertions = std::env::var_os("HOME")
+        .map(|home| std::path::Path::new(&home).join("Library/DoNotDisturb/DB/Assertions.json"))
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok());
+    if let Some(assertions) = assertions {
+        return assertions["data"].as_array().is_some_and(|data| {
+            data.iter().any(|entry| {
+                entry["storeAssertionRecords"]
+                    .as_array()
+                    .is_some_and(|records| !records.is_empty())
+            })
+        });
+    }
+
+    std::process::Command::new("defaults")
+        .args([
+            "-currentHost",
+            "read",
+            "com.apple.notificationcenterui",
+            "doNotDisturb",
+        ])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "1")
+}
+
+/// Not reported on other platforms
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn system_dnd() -> bool {
+    false
+}
+
+// ============================================================================
+// History
+// ============================================================================
+
+fn load_history<R: Runtime>(app: &AppHandle<R>) -> Vec<HistoryEntry> {
+    app.profile_store(HISTORY_STORE)
+        .ok()
+        .and_then(|store| store.get(HISTORY_KEY))
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| warn!("Ignoring corrupt notification history: {}", e))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+/// Add `entry` at the front, dropping the oldest past the cap
+fn push(entries: &mut Vec<HistoryEntry>, entry: HistoryEntry) {
+    entries.insert(0, entry);
+    entries.truncate(MAX_HISTORY);
+}
+
+/// Record a notification in the history and tell windows about it
+pub fn log<R: Runtime>(app: &AppHandle<R>, entry: HistoryEntry) {
+    if let Some(reason) = entry.suppressed {
+        info!("Notification suppressed ({:?}): {}", reason, entry.title);
+    }
+    let Ok(store) = app.profile_store(HISTORY_STORE) else {
+        warn!("Failed to open notification history");
+        return;
+    };
+    let mut entries = load_history(app);
+    push(&mut entries, entry.clone());
+    store.set(HISTORY_KEY, serde_json::json!(entries));
+    persist::mark_dirty(app, HISTORY_STORE);
+    let _ = app.emit("notification-logged", &entry);
+}
+
+pub fn now() -> String {
+    Utc::now().to_rfc3339()
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// The latest `limit` notifications, shown or suppressed, newest first
+#[tauri::command]
+pub fn get_notification_history<R: Runtime>(app: AppHandle<R>, limit: usize) -> Vec<HistoryEntry> {
+    let mut entries = load_history(&app);
+    entries.truncate(limit);
+    entries
+}
+
+#[tauri::command]
+pub fn get_notification_policy<R: Runtime>(app: AppHandle<R>) -> NotificationPolicy {
+    load_policy(&app)
+}
+
+#[tauri::command]
+pub fn set_notification_policy<R: Runtime>(
+    app: AppHandle<R>,
+    policy: NotificationPolicy,
+) -> Result<(), String> {
+    policy.validate()?;
+    let store = app
+        .profile_store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    store.set(POLICY_SETTING, serde_json::json!(policy));
+    persist::mark_dirty(&app, SETTINGS_STORE);
+    info!("Notification policy updated");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: u32, time: &str) -> NaiveDateTime {
+        // October 2026: the 12th is a Monday
+        NaiveDate::from_ymd_opt(2026, 10, day)
+            .unwrap()
+            .and_time(parse_time(time).unwrap())
+    }
+
+    fn quiet(start: &str, end: &str, days: &[u8]) -> QuietHours {
+        QuietHours {
+            enabled: true,
+            start: start.to_string(),
+            end: end.to_string(),
+            days: days.to_vec(),
+        }
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let hours = quiet("13:00", "15:00", &[0]);
+        assert!(hours.contains(at(12, "13:00")));
+        assert!(!hours.contains(at(12, "15:00")));
+        assert!(!hours.contains(at(13, "14:00")));
+    }
+
+    #[test]
+    fn overnight_quiet_hours_belong_to_the_start_day() {
+        // Friday night only
+        let hours = quiet("22:00", "07:00", &[4]);
+        assert!(hours.contains(at(16, "23:30")));
+        assert!(hours.contains(at(17, "06:59")));
+        assert!(!hours.contains(at(17, "07:00")));
+        assert!(!hours.contains(at(16, "06:00")));
+        assert!(!hours.contains(at(17, "23:00")));
+    }
+
+    #[test]
+    fn suppression_checks_in_order() {
+        let mut policy = NotificationPolicy::default();
+        let noon = at(12, "12:00");
+        assert_eq!(suppression(&policy, None, noon, true, false), None);
+
+        policy.categories.insert(NotificationCategory::Sync, false);
+        assert_eq!(
+            suppression(
+                &policy,
+                Some(NotificationCategory::Sync),
+                noon,
+                false,
+                false
+            ),
+            Some(SuppressReason::CategoryDisabled)
+        );
+        assert_eq!(
+            suppression(
+                &policy,
+                Some(NotificationCategory::Imports),
+                noon,
+                false,
+                false
+            ),
+            None
+        );
+
+        policy.only_when_hidden = true;
+        assert_eq!(
+            suppression(&policy, None, noon, true, false),
+            Some(SuppressReason::WindowVisible)
+        );
+        assert_eq!(
+            suppression(&policy, None, noon, false, true),
+            Some(SuppressReason::DoNotDisturb)
+        );
+        policy.respect_system_dnd = false;
+        assert_eq!(suppression(&policy, None, noon, false, true), None);
+
+        policy.quiet_hours = quiet("11:00", "13:00", &[0]);
+        assert_eq!(
+            suppression(&policy, None, noon, true, true),
+            Some(SuppressReason::QuietHours)
+        );
+    }
+
+    #[test]
+    fn rejects_bad_schedules() {
+        let mut policy = NotificationPolicy::default();
+        assert!(policy.validate().is_ok());
+        policy.quiet_hours.start = "25:00".to_string();
+        assert!(policy.validate().is_err());
+        policy.quiet_hours = quiet("22:00", "07:00", &[7]);
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn history_keeps_the_newest() {
+        let mut entries = Vec::new();
+        for i in 0..=MAX_HISTORY {
+            push(
+                &mut entries,
+                HistoryEntry {
+                    id: Some(i as u32),
+                    title: String::new(),
+                    body: None,
+                    category: None,
+                    route: None,
+                    created_at: String::new(),
+                    suppressed: None,
+                },
+            );
+        }
+        assert_eq!(entries.len(), MAX_HISTORY);
+        assert_eq!(entries[0].id, Some(MAX_HISTORY as u32));
+    }
+}
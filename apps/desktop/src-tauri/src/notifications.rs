@@ -0,0 +1,368 @@
+// Read Master Desktop - Notifications
+//
+// Notifications with a click route and action buttons.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::notification_policy::{self, HistoryEntry, NotificationCategory};
+
+/// Notifications remembered at once; the oldest are forgotten first
+const MAX_REGISTERED: usize = 100;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationAction {
+    pub id: String,
+    pub label: String,
+}
+
+#[derive(Debug, Clone)]
+struct Registered {
+    route: Option<String>,
+    actions: Vec<NotificationAction>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ClickedPayload {
+    id: u32,
+    route: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ActionPayload {
+    id: u32,
+    action_id: String,
+}
+
+/// Shown notifications awaiting a response, by id. The OS only hands back
+/// an identifier, so the route and actions are kept here until the
+/// notification is clicked or dismissed.
+#[derive(Default)]
+pub struct NotificationRegistry {
+    next: AtomicU32,
+    pending: Mutex<BTreeMap<u32, Registered>>,
+}
+
+impl NotificationRegistry {
+    fn register(&self, entry: Registered) -> u32 {
+        let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(id, entry);
+            while pending.len() > MAX_REGISTERED {
+                pending.pop_first();
+            }
+        }
+        id
+    }
+
+    fn take(&self, id: u32) -> Option<Registered> {
+        self.pending.lock().ok()?.remove(&id)
+    }
+}
+
+/// What the user did with a notification
+enum Response {
+    Clicked,
+    /// Button pressed, by action id
+    Action(String),
+    Dismissed,
+}
+
+// ============================================================================
+// Responses
+// ============================================================================
+
+fn respond<R: Runtime>(app: &AppHandle<R>, id: u32, response: Response) {
+    let Some(entry) = app.state::<NotificationRegistry>().take(id) else {
+        return;
+    };
+
+    match response {
+        Response::Clicked => {
+            info!("Notification {} clicked", id);
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.show();
+                let _ = window.unminimize();
+                let _ = window.set_focus();
+            }
+            let _ = app.emit(
+                "notification-clicked",
+                ClickedPayload {
+                    id,
+                    route: entry.route,
+                },
+            );
+        }
+        Response::Action(action_id) => {
+            if !entry.actions.iter().any(|a| a.id == action_id) {
+                warn!("Unknown action {} on notification {}", action_id, id);
+                return;
+            }
+            info!("Notification {} action: {}", id, action_id);
+            let _ = app.emit("notification-action", ActionPayload { id, action_id });
+        }
+        Response::Dismissed => {}
+    }
+}
+
+// ============================================================================
+// Platforms
+// ============================================================================
+
+#[cfg(target_os = "linux")]
+fn show_native<R: Runtime>(
+    app: &AppHandle<R>,
+    id: u32,
+    title: &str,
+    body: Option<&str>,
+    actions: &[NotificationAction],
+) -> Result<(), String> {
+    let supports_actions = notify_rust::get_capabilities()
+        .map(|caps| caps.iter().any(|c| c == "actions"))
+        .unwrap_or(false);
+
+    let mut notification = notify_rust::Notification::new();
+    notification
+        .summary(title)
+        .body(body.unwrap_or_default())
+        .appname("Read Master")
+        // "default" is the body click, shown by most servers without a button
+        .action("default", "Open");
+    if supports_actions {
+        for action in actions {
+            notification.action(&action.id, &action.label);
+        }
+    }
+
+    let handle = notification
+        .show()
+        .map_err(|e| format!("Failed to show notification: {}", e))?;
+
+    let app = app.clone();
+    std::thread::spawn(move || {
+        handle.wait_for_action(|action| {
+            let response = match action {
+                "default" => Response::Clicked,
+                "__closed" => Response::Dismissed,
+                other => Response::Action(other.to_string()),
+            };
+            respond(&app, id, response);
+        });
+    });
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn show_native<R: Runtime>(
+    app: &AppHandle<R>,
+    id: u32,
+    title: &str,
+    body: Option<&str>,
+    actions: &[NotificationAction],
+) -> Result<(), String> {
+    use tauri_winrt_notification::Toast;
+
+    let mut toast = Toast::new(&app.config().identifier)
+        .title(title)
+        .text1(body.unwrap_or_default());
+    for action in actions {
+        toast = toast.add_button(&action.label, &action.id);
+    }
+
+    let activated = app.clone();
+    let dismissed = app.clone();
+    toast
+        // Buttons activate with their argument; the body with none
+        .on_activated(move |argument| {
+            let response = match argument {
+                Some(action_id) if !action_id.is_empty() => Response::Action(action_id),
+                _ => Response::Clicked,
+            };
+            respond(&activated, id, response);
+            Ok(())
+        })
+        .on_dismissed(move |_| {
+            respond(&dismissed, id, Response::Dismissed);
+            Ok(())
+        })
+        .show()
+        .map_err(|e| format!("Failed to show notification: {}", e))
+}
+
+#[cfg(target_os = "macos")]
+fn show_native<R: Runtime>(
+    app: &AppHandle<R>,
+    id: u32,
+    title: &str,
+    body: Option<&str>,
+    actions: &[NotificationAction],
+) -> Result<(), String> {
+    use mac_notification_sys::{MainButton, Notification, NotificationResponse};
+
+    static APPLICATION: std::sync::Once = std::sync::Once::new();
+    let identifier = app.config().identifier.clone();
+    APPLICATION.call_once(|| {
+        if let Err(e) = mac_notification_sys::set_application(&identifier) {
+            warn!("Failed to set notification bundle: {}", e);
+        }
+    });
+
+    let app = app.clone();
+    let title = title.to_string();
+    let body = body.unwrap_or_default().to_string();
+    let actions = actions.to_vec();
+
+    // Sending with wait_for_click blocks until the user responds
+    std::thread::spawn(move || {
+        let labels: Vec<&str> = actions.iter().map(|a| a.label.as_str()).collect();
+        let mut options = Notification::new();
+        options.wait_for_click(true);
+        match labels.as_slice() {
+            [] => {}
+            [label] => {
+                options.main_button(MainButton::SingleAction(*label));
+            }
+            _ => {
+                options.main_button(MainButton::DropdownActions("Actions", &labels));
+            }
+        }
+
+        let response =
+            match mac_notification_sys::send_notification(&title, None, &body, Some(&options)) {
+                Ok(NotificationResponse::Click) => Response::Clicked,
+                // Buttons report their label
+                Ok(NotificationResponse::ActionButton(label)) => actions
+                    .iter()
+                    .find(|a| a.label == label)
+                    .map_or(Response::Clicked, |a| Response::Action(a.id.clone())),
+                Ok(_) => Response::Dismissed,
+                Err(e) => {
+                    warn!("Failed to show notification: {}", e);
+                    Response::Dismissed
+                }
+            };
+        respond(&app, id, response);
+    });
+    Ok(())
+}
+
+/// Other platforms go through the plugin, without click handling
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+fn show_native<R: Runtime>(
+    app: &AppHandle<R>,
+    _id: u32,
+    title: &str,
+    body: Option<&str>,
+    _actions: &[NotificationAction],
+) -> Result<(), String> {
+    use tauri_plugin_notification::NotificationExt;
+
+    let mut notification = app.notification().builder().title(title);
+    if let Some(body) = body {
+        notification = notification.body(body);
+    }
+    notification
+        .show()
+        .map_err(|e| format!("Failed to show notification: {}", e))
+}
+
+// ============================================================================
+// Showing
+// ============================================================================
+
+/// Show a notification in `category`, returning its id, or `None` if the
+/// notification policy held it back. The policy logs it to the
+/// notification center either way. Clicking it shows the main window and
+/// emits `notification-clicked` with `on_click_route`; `actions` become
+/// buttons emitting `notification-action`, and are left off where buttons
+/// aren't supported.
+///
+/// The notification plugin can't report clicks on desktop, so this goes
+/// straight to each platform's API: notify-rust (D-Bus) on Linux, WinRT
+/// toasts on Windows, and NSUserNotification on macOS. Each waits for the
+/// user's response on a thread of its own.
+pub fn show<R: Runtime>(
+    app: &AppHandle<R>,
+    category: Option<NotificationCategory>,
+    title: &str,
+    body: Option<&str>,
+    actions: Vec<NotificationAction>,
+    on_click_route: Option<String>,
+) -> Result<Option<u32>, String> {
+    let mut entry = HistoryEntry {
+        id: None,
+        title: title.to_string(),
+        body: body.map(str::to_string),
+        category,
+        route: on_click_route.clone(),
+        created_at: notification_policy::now(),
+        suppressed: notification_policy::check(app, category),
+    };
+    if entry.suppressed.is_some() {
+        notification_policy::log(app, entry);
+        return Ok(None);
+    }
+
+    let registry = app.state::<NotificationRegistry>();
+    let id = registry.register(Registered {
+        route: on_click_route,
+        actions: actions.clone(),
+    });
+
+    if let Err(e) = show_native(app, id, title, body, &actions) {
+        registry.take(id);
+        return Err(e);
+    }
+    entry.id = Some(id);
+    notification_policy::log(app, entry);
+    Ok(Some(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(route: &str) -> Registered {
+        Registered {
+            route: Some(route.to_string()),
+            actions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn registry_hands_out_each_entry_once() {
+        let registry = NotificationRegistry::default();
+        let first = registry.register(entry("/library"));
+        let second = registry.register(entry("/flashcards/review"));
+        assert_ne!(first, second);
+
+        assert_eq!(
+            registry.take(second).unwrap().route.as_deref(),
+            Some("/flashcards/review")
+        );
+        assert!(registry.take(second).is_none());
+        assert!(registry.take(first).is_some());
+    }
+
+    #[test]
+    fn registry_forgets_the_oldest() {
+        let registry = NotificationRegistry::default();
+        let first = registry.register(entry("/a"));
+        for _ in 0..MAX_REGISTERED {
+            registry.register(entry("/b"));
+        }
+        assert!(registry.take(first).is_none());
+    }
+}
@@ -0,0 +1,942 @@
+// Read Master Desktop - Obsidian Export
+//
+// Writes the library into an Obsidian vault as linked book and highlight notes.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::db::Database;
+use crate::library::{self, Book};
+use crate::notes::{self, Note};
+
+/// Frontmatter key holding the stable id of exported notes. The next
+/// export scans the vault for it, so notes are updated wherever the user
+/// has since moved or renamed them and links follow the current names.
+/// Files without it are never written.
+const ID_KEY: &str = "readmaster-id";
+
+/// The managed block, the only part of a note's body the export rewrites
+const BLOCK_START: &str = "%% readmaster:start %%";
+const BLOCK_END: &str = "%% readmaster:end %%";
+
+/// Frontmatter keys the export owns in book notes
+const BOOK_KEYS: &[&str] = &[
+    ID_KEY,
+    "title",
+    "author",
+    "isbn",
+    "series",
+    "series-index",
+    "tags",
+    "rating",
+    "progress",
+    "status",
+    "added",
+    "finished",
+    "cover",
+];
+
+/// Frontmatter keys the export owns in highlight notes
+const NOTE_KEYS: &[&str] = &[ID_KEY, "book", "tags", "color", "created", "updated"];
+
+/// Bytes read from each vault note when looking for an id
+const FRONTMATTER_BYTES: u64 = 4096;
+
+/// Words of a highlight used in its note's name
+const NAME_WORDS: usize = 8;
+const MAX_NAME_CHARS: usize = 80;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ObsidianOptions {
+    /// Folder for book notes, relative to the vault
+    pub books_folder: String,
+    /// Folder for highlight notes; `{book}` and `{author}` are filled in
+    pub notes_folder: String,
+    /// Folder for covers
+    pub attachments_folder: String,
+    /// Books to export; all of them when `None`
+    pub book_ids: Option<Vec<i64>>,
+    /// Plan the export without writing anything
+    pub dry_run: bool,
+}
+
+impl Default for ObsidianOptions {
+    fn default() -> Self {
+        Self {
+            books_folder: "Read Master/Books".to_string(),
+            notes_folder: "Read Master/Highlights/{book}".to_string(),
+            attachments_folder: "Read Master/Covers".to_string(),
+            book_ids: None,
+            dry_run: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FileAction {
+    Create,
+    Update,
+    Unchanged,
+    /// Left alone, with the reason in the operation
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportedFile {
+    Book,
+    Highlight,
+    Cover,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOperation {
+    pub action: FileAction,
+    pub kind: ExportedFile,
+    /// Path relative to the vault, with `/` separators
+    pub path: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObsidianExport {
+    pub dry_run: bool,
+    pub operations: Vec<FileOperation>,
+}
+
+/// A file to write, with what it will contain
+struct Planned {
+    operation: FileOperation,
+    contents: Option<Vec<u8>>,
+}
+
+/// Where each exported note lives, by id
+struct Placement {
+    /// Vault-relative path
+    path: String,
+    /// Whether it's already in the vault
+    existing: bool,
+}
+
+impl Placement {
+    /// Wikilink target: the file name without `.md`
+    fn link(&self) -> String {
+        let name = self.path.rsplit('/').next().unwrap_or(&self.path);
+        format!("[[{}]]", name.strip_suffix(".md").unwrap_or(name))
+    }
+}
+
+// ============================================================================
+// Names & Paths
+// ============================================================================
+
+/// `text` as a file name Obsidian can link to: no path separators, link
+/// syntax, or characters Windows forbids
+fn file_name(text: &str) -> String {
+    let cleaned: String = text
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let name: String = cleaned
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_NAME_CHARS)
+        .collect();
+    let name = name.trim().trim_matches('.').trim().to_string();
+    if name.is_empty() {
+        "Untitled".to_string()
+    } else {
+        name
+    }
+}
+
+/// `template` with `{book}` and `{author}` filled in, each folder a safe
+/// name, and `None` if it would leave the vault
+fn folder(template: &str, book: &Book) -> Option<String> {
+    let mut segments = Vec::new();
+    for segment in template.split(['/', '\\']) {
+        let segment = segment.trim();
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+        if segment == ".." {
+            return None;
+        }
+        let filled = segment.replace("{book}", &book.title).replace(
+            "{author}",
+            book.author.as_deref().unwrap_or("Unknown Author"),
+        );
+        segments.push(file_name(&filled));
+    }
+    Some(segments.join("/"))
+}
+
+/// Short name for a highlight note from its first few words
+fn note_name(book: &Book, note: &Note) -> String {
+    let text = note
+        .quote
+        .as_deref()
+        .filter(|q| !q.trim().is_empty())
+        .unwrap_or(&note.body);
+    let words: Vec<&str> = text.split_whitespace().take(NAME_WORDS).collect();
+    if words.is_empty() {
+        file_name(&format!("{} - Note", book.title))
+    } else {
+        file_name(&format!("{} - {}", book.title, words.join(" ")))
+    }
+}
+
+fn join(folder: &str, name: &str) -> String {
+    if folder.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", folder, name)
+    }
+}
+
+/// A new note path in `folder` that's neither on disk nor already planned
+fn unique_path(vault: &Path, folder: &str, stem: &str, taken: &mut HashSet<String>) -> String {
+    (1..)
+        .map(|n| match n {
+            1 => join(folder, &format!("{}.md", stem)),
+            n => join(folder, &format!("{} {}.md", stem, n)),
+        })
+        .find(|path| !taken.contains(&path.to_lowercase()) && !vault.join(path).exists())
+        .map(|path| {
+            taken.insert(path.to_lowercase());
+            path
+        })
+        .expect("some numbered name is free")
+}
+
+// ============================================================================
+// Markdown
+// ============================================================================
+
+/// A YAML scalar; JSON strings are valid YAML
+fn yaml(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| "\"\"".to_string())
+}
+
+/// Obsidian tags can't contain spaces
+fn yaml_tags(tags: &[String]) -> String {
+    let tags: Vec<String> = tags
+        .iter()
+        .map(|tag| tag.trim().replace(char::is_whitespace, "-"))
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    serde_json::to_string(&tags).unwrap_or_else(|_| "[]".to_string())
+}
+
+/// The managed block, between its markers
+fn block(content: &str) -> String {
+    format!("{}\n{}\n{}\n", BLOCK_START, content.trim_end(), BLOCK_END)
+}
+
+/// Split a note into its frontmatter lines and body
+fn split_frontmatter(text: &str) -> (Vec<&str>, &str) {
+    let Some(rest) = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    else {
+        return (Vec::new(), text);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        offset += line.len();
+        if line.trim_end() == "---" {
+            let lines = rest[..offset - line.len()].lines().collect();
+            return (lines, &rest[offset..]);
+        }
+    }
+    (Vec::new(), text)
+}
+
+/// Value of `key` in a note's frontmatter
+fn frontmatter_value(text: &str, key: &str) -> Option<String> {
+    let (lines, _) = split_frontmatter(text);
+    lines.iter().find_map(|line| {
+        let value = line.strip_prefix(key)?.strip_prefix(':')?.trim();
+        let value = serde_json::from_str::<String>(value).unwrap_or_else(|_| value.to_string());
+        (!value.is_empty()).then_some(value)
+    })
+}
+
+/// The new note, keeping whatever the user added to the `existing` one:
+/// frontmatter keys the export doesn't own, and everything outside the
+/// managed block. A note whose markers were removed keeps its body.
+fn merge(
+    existing: Option<&str>,
+    owned: &[&str],
+    frontmatter: &[(&str, String)],
+    content: &str,
+) -> String {
+    let mut out = String::from("---\n");
+    for (key, value) in frontmatter {
+        out.push_str(&format!("{}: {}\n", key, value));
+    }
+
+    let Some(existing) = existing else {
+        out.push_str("---\n");
+        out.push_str(&block(content));
+        return out;
+    };
+
+    let (lines, body) = split_frontmatter(existing);
+    let mut keep = false;
+    for line in lines {
+        let top_level = !line.starts_with([' ', '\t', '-']);
+        if top_level {
+            let key = line.split(':').next().unwrap_or("").trim();
+            keep = !owned.contains(&key);
+        }
+        if keep {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out.push_str("---\n");
+
+    match (body.find(BLOCK_START), body.find(BLOCK_END)) {
+        (Some(start), Some(end)) if start < end => {
+            let after = &body[end + BLOCK_END.len()..];
+            let after = after
+                .strip_prefix("\r\n")
+                .or_else(|| after.strip_prefix('\n'))
+                .unwrap_or(after);
+            out.push_str(&body[..start]);
+            out.push_str(&block(content));
+            out.push_str(after);
+        }
+        _ => out.push_str(body),
+    }
+    out
+}
+
+fn book_status(book: &Book) -> &'static str {
+    if book.finished_at.is_some() {
+        "finished"
+    } else if book.progress.unwrap_or(0.0) > 0.0 {
+        "reading"
+    } else {
+        "unread"
+    }
+}
+
+fn book_frontmatter(book: &Book, cover: Option<&str>) -> Vec<(&'static str, String)> {
+    let date = |at: &str| yaml(at.get(..10).unwrap_or(at));
+    let mut fields = vec![
+        (ID_KEY, yaml(&format!("book-{}", book.id))),
+        ("title", yaml(&book.title)),
+    ];
+    let optional = [
+        ("author", book.author.as_deref().map(yaml)),
+        (
+            "isbn",
+            book.isbn13.as_deref().or(book.isbn.as_deref()).map(yaml),
+        ),
+        ("series", book.series_name.as_deref().map(yaml)),
+        ("series-index", book.series_index.map(|i| i.to_string())),
+        (
+            "tags",
+            (!book.tags.is_empty()).then(|| yaml_tags(&book.tags)),
+        ),
+        ("rating", book.rating.map(|r| r.to_string())),
+        (
+            "progress",
+            book.progress
+                .map(|p| ((p * 100.0).round() as i64).clamp(0, 100).to_string()),
+        ),
+        ("status", Some(book_status(book).to_string())),
+        ("added", Some(date(&book.added_at))),
+        ("finished", book.finished_at.as_deref().map(date)),
+        ("cover", cover.map(|c| yaml(&format!("[[{}]]", c)))),
+    ];
+    fields.extend(
+        optional
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?))),
+    );
+    fields
+}
+
+fn book_content(book: &Book, cover: Option<&str>, highlights: &[String]) -> String {
+    let mut out = String::new();
+    if let Some(cover) = cover {
+        out.push_str(&format!("![[{}|200]]\n\n", cover));
+    }
+    out.push_str(&format!("# {}\n", book.title));
+    if let Some(author) = &book.author {
+        out.push_str(&format!("by {}\n", author));
+    }
+    if let Some(progress) = book.progress {
+        out.push_str(&format!("\nProgress: {}%\n", (progress * 100.0).round()));
+    }
+    if !highlights.is_empty() {
+        out.push_str("\n## Highlights\n\n");
+        for link in highlights {
+            out.push_str(&format!("- {}\n", link));
+        }
+    }
+    out
+}
+
+fn note_frontmatter(note: &Note, book_link: &str) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        (ID_KEY, yaml(&format!("note-{}", note.id))),
+        ("book", yaml(book_link)),
+    ];
+    if !note.tags.is_empty() {
+        fields.push(("tags", yaml_tags(&note.tags)));
+    }
+    if let Some(color) = &note.color {
+        fields.push(("color", yaml(color)));
+    }
+    fields.push(("created", yaml(&note.created_at)));
+    fields.push(("updated", yaml(&note.updated_at)));
+    fields
+}
+
+fn note_content(note: &Note, book_link: &str) -> String {
+    let mut out = String::new();
+    if let Some(quote) = note.quote.as_deref().filter(|q| !q.trim().is_empty()) {
+        for line in quote.trim().lines() {
+            out.push_str(&format!("> {}\n", line));
+        }
+        out.push('\n');
+    }
+    if !note.body.trim().is_empty() {
+        out.push_str(note.body.trim());
+        out.push_str("\n\n");
+    }
+    out.push_str(&format!("Source: {}\n", book_link));
+    out
+}
+
+// ============================================================================
+// Planning
+// ============================================================================
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+/// The start of a file, enough for its frontmatter
+fn read_head(path: &Path) -> Option<String> {
+    let mut head = Vec::new();
+    std::fs::File::open(path)
+        .ok()?
+        .take(FRONTMATTER_BYTES)
+        .read_to_end(&mut head)
+        .ok()?;
+    Some(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// Notes in the vault from earlier exports, by id, as vault-relative paths.
+/// Hidden folders like `.obsidian` and `.trash` are skipped.
+fn exported_notes(vault: &Path) -> HashMap<String, String> {
+    let mut found = HashMap::new();
+    let mut dirs = vec![vault.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if is_hidden(&path) {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            // Linked folders can point back into the vault; only real
+            // folders are walked
+            if file_type.is_symlink() && path.is_dir() {
+                continue;
+            }
+            if path.extension().and_then(|e| e.to_str()) != Some("md") {
+                continue;
+            }
+            let Some(id) = read_head(&path).and_then(|head| frontmatter_value(&head, ID_KEY))
+            else {
+                continue;
+            };
+            let Ok(relative) = path.strip_prefix(vault) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            if let Some(first) = found.get(&id) {
+                warn!(
+                    "{} has the same export id as {}; using the first",
+                    relative, first
+                );
+            } else {
+                found.insert(id, relative);
+            }
+        }
+    }
+    found
+}
+
+/// Where the note with `id` goes: where it already is, else a new path
+fn place(
+    vault: &Path,
+    existing: &HashMap<String, String>,
+    id: &str,
+    folder: &str,
+    stem: &str,
+    taken: &mut HashSet<String>,
+) -> Placement {
+    match existing.get(id) {
+        Some(path) => Placement {
+            path: path.clone(),
+            existing: true,
+        },
+        None => Placement {
+            path: unique_path(vault, folder, stem, taken),
+            existing: false,
+        },
+    }
+}
+
+/// Create or update the note at `placement` with new contents
+fn plan_note(
+    vault: &Path,
+    kind: ExportedFile,
+    placement: &Placement,
+    owned: &[&str],
+    frontmatter: &[(&str, String)],
+    content: &str,
+) -> Planned {
+    let path = vault.join(&placement.path);
+    let existing = if placement.existing {
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Some(text),
+            Err(e) => {
+                return Planned {
+                    operation: FileOperation {
+                        action: FileAction::Skip,
+                        kind,
+                        path: placement.path.clone(),
+                        reason: Some(format!("Couldn't read the note: {}", e)),
+                    },
+                    contents: None,
+                }
+            }
+        }
+    } else {
+        None
+    };
+
+    let text = merge(existing.as_deref(), owned, frontmatter, content);
+    let action = match &existing {
+        None => FileAction::Create,
+        Some(old) if *old == text => FileAction::Unchanged,
+        Some(_) => FileAction::Update,
+    };
+    Planned {
+        operation: FileOperation {
+            action,
+            kind,
+            path: placement.path.clone(),
+            reason: None,
+        },
+        contents: (action != FileAction::Unchanged).then(|| text.into_bytes()),
+    }
+}
+
+/// Copy a cover into the vault unless something is already at its path
+fn plan_cover(vault: &Path, source: &Path, path: String) -> Planned {
+    let (action, reason, contents) = match std::fs::read(source) {
+        Err(e) => (
+            FileAction::Skip,
+            Some(format!("Couldn't read the cover: {}", e)),
+            None,
+        ),
+        Ok(data) => match std::fs::read(vault.join(&path)) {
+            Ok(on_disk) if on_disk == data => (FileAction::Unchanged, None, None),
+            Ok(_) => (
+                FileAction::Skip,
+                Some("A different file is already at this path".to_string()),
+                None,
+            ),
+            Err(_) => (FileAction::Create, None, Some(data)),
+        },
+    };
+    Planned {
+        operation: FileOperation {
+            action,
+            kind: ExportedFile::Cover,
+            path,
+            reason,
+        },
+        contents,
+    }
+}
+
+fn plan(
+    vault: &Path,
+    options: &ObsidianOptions,
+    books: &[Book],
+    notes: &[Note],
+) -> Result<Vec<Planned>, String> {
+    let existing = exported_notes(vault);
+    let mut taken = HashSet::new();
+    let mut planned = Vec::new();
+
+    for book in books {
+        let books_folder = folder(&options.books_folder, book)
+            .ok_or_else(|| "The books folder must be inside the vault".to_string())?;
+        let attachments = folder(&options.attachments_folder, book)
+            .ok_or_else(|| "The attachments folder must be inside the vault".to_string())?;
+        let notes_folder = folder(&options.notes_folder, book)
+            .ok_or_else(|| "The highlights folder must be inside the vault".to_string())?;
+        let book_place = place(
+            vault,
+            &existing,
+            &format!("book-{}", book.id),
+            &books_folder,
+            &file_name(&book.title),
+            &mut taken,
+        );
+        let book_link = book_place.link();
+
+        let mut book_notes: Vec<&Note> = notes
+            .iter()
+            .filter(|note| note.book_id.as_deref() == Some(book.id.to_string().as_str()))
+            .collect();
+        book_notes.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+        let mut links = Vec::new();
+        for note in book_notes {
+            let placement = place(
+                vault,
+                &existing,
+                &format!("note-{}", note.id),
+                &notes_folder,
+                &note_name(book, note),
+                &mut taken,
+            );
+            links.push(placement.link());
+            planned.push(plan_note(
+                vault,
+                ExportedFile::Highlight,
+                &placement,
+                NOTE_KEYS,
+                &note_frontmatter(note, &book_link),
+                &note_content(note, &book_link),
+            ));
+        }
+
+        let cover = book.cover_path.as_deref().map(Path::new).map(|source| {
+            let ext = source
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("jpg")
+                .to_lowercase();
+            let name = format!("readmaster-cover-{}.{}", book.id, ext);
+            (plan_cover(vault, source, join(&attachments, &name)), name)
+        });
+        let cover_name = cover
+            .as_ref()
+            .filter(|(planned, _)| planned.operation.action != FileAction::Skip)
+            .map(|(_, name)| name.clone());
+        planned.push(plan_note(
+            vault,
+            ExportedFile::Book,
+            &book_place,
+            BOOK_KEYS,
+            &book_frontmatter(book, cover_name.as_deref()),
+            &book_content(book, cover_name.as_deref(), &links),
+        ));
+        planned.extend(cover.map(|(planned, _)| planned));
+    }
+    Ok(planned)
+}
+
+fn write(vault: &Path, planned: &[Planned]) -> Result<(), String> {
+    for file in planned {
+        let Some(contents) = &file.contents else {
+            continue;
+        };
+        let path: PathBuf = vault.join(&file.operation.path);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        std::fs::write(&path, contents)
+            .map_err(|e| format!("Failed to write {}: {}", file.operation.path, e))?;
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Export books and their highlights into the Obsidian vault at
+/// `vault_path`, or with `dryRun`, only report what would change. Each
+/// book gets a note with its metadata in frontmatter, its cover, and
+/// progress; each highlight or note gets its own, linked to the book note
+/// with [[wikilinks]] and listed in it in turn.
+#[tauri::command]
+pub async fn export_to_obsidian<R: Runtime>(
+    app: AppHandle<R>,
+    vault_path: String,
+    options: Option<ObsidianOptions>,
+) -> Result<ObsidianExport, String> {
+    let options = options.unwrap_or_default();
+    tauri::async_runtime::spawn_blocking(move || {
+        let vault = PathBuf::from(&vault_path);
+        if !vault.is_dir() {
+            return Err(format!("Vault folder not found: {}", vault_path));
+        }
+
+        let mut books: Vec<Book> = app
+            .state::<Database>()
+            .with_conn(|conn| library::list_books(conn))?;
+        if let Some(ids) = &options.book_ids {
+            books.retain(|book| ids.contains(&book.id));
+        }
+        if books.is_empty() {
+            return Ok(ObsidianExport {
+                dry_run: options.dry_run,
+                operations: Vec::new(),
+            });
+        }
+        let notes = notes::load_all(&app)?;
+
+        let planned = plan(&vault, &options, &books, &notes)?;
+        if !options.dry_run {
+            write(&vault, &planned)?;
+        }
+        let operations: Vec<FileOperation> = planned.into_iter().map(|p| p.operation).collect();
+        info!(
+            "{} Obsidian export of {} book(s) to {}: {} file(s) to write",
+            if options.dry_run {
+                "Planned"
+            } else {
+                "Finished"
+            },
+            books.len(),
+            vault_path,
+            operations
+                .iter()
+                .filter(|op| matches!(op.action, FileAction::Create | FileAction::Update))
+                .count()
+        );
+        Ok(ObsidianExport {
+            dry_run: options.dry_run,
+            operations,
+        })
+    })
+    .await
+    .map_err(|e| format!("Obsidian export task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: i64, title: &str) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "title": title,
+            "author": "Frank Herbert",
+            "onDisk": true,
+            "progress": 0.42,
+            "addedAt": "2024-02-03T10:00:00Z",
+            "updatedAt": "2024-02-03T10:00:00Z",
+            "tags": ["science fiction"],
+        }))
+        .unwrap()
+    }
+
+    fn note(id: &str, book_id: i64, quote: &str) -> Note {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "bookId": book_id.to_string(),
+            "quote": quote,
+            "body": "",
+            "createdAt": "2024-03-01T10:00:00Z",
+            "updatedAt": "2024-03-01T10:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    fn vault() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rm-vault-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn run(vault: &Path, books: &[Book], notes: &[Note]) -> Vec<FileOperation> {
+        let planned = plan(vault, &ObsidianOptions::default(), books, notes).unwrap();
+        write(vault, &planned).unwrap();
+        planned.into_iter().map(|p| p.operation).collect()
+    }
+
+    #[test]
+    fn file_names_are_safe_to_link() {
+        assert_eq!(file_name("Dune: Messiah / Part #2"), "Dune Messiah Part 2");
+        assert_eq!(file_name("  ...  "), "Untitled");
+        assert_eq!(
+            folder("A/{book}/../x", &book(1, "Dune")),
+            None,
+            "parent folders are refused"
+        );
+        assert_eq!(
+            folder("Highlights/{author}/{book}", &book(1, "Dune: Messiah")),
+            Some("Highlights/Frank Herbert/Dune Messiah".to_string())
+        );
+    }
+
+    #[test]
+    fn merge_keeps_user_additions() {
+        let existing = concat!(
+            "---\nreadmaster-id: \"book-1\"\ntitle: \"Old\"\naliases:\n  - Spice\n---\n",
+            "My intro\n%% readmaster:start %%\nold\n%% readmaster:end %%\nMy thoughts\n",
+        );
+        let merged = merge(
+            Some(existing),
+            BOOK_KEYS,
+            &[(ID_KEY, yaml("book-1")), ("title", yaml("New"))],
+            "new",
+        );
+        assert_eq!(
+            merged,
+            concat!(
+                "---\nreadmaster-id: \"book-1\"\ntitle: \"New\"\naliases:\n  - Spice\n---\n",
+                "My intro\n%% readmaster:start %%\nnew\n%% readmaster:end %%\nMy thoughts\n",
+            )
+        );
+        assert_eq!(
+            frontmatter_value(&merged, ID_KEY).as_deref(),
+            Some("book-1")
+        );
+    }
+
+    #[test]
+    fn links_books_and_highlights_both_ways() {
+        let vault = vault();
+        let books = [book(1, "Dune")];
+        let notes = [note("n1", 1, "I must not fear. Fear is the mind-killer.")];
+        let ops = run(&vault, &books, &notes);
+        assert!(ops.iter().all(|op| op.action == FileAction::Create));
+
+        let book_note = std::fs::read_to_string(vault.join("Read Master/Books/Dune.md")).unwrap();
+        let highlight =
+            "Read Master/Highlights/Dune/Dune - I must not fear. Fear is the mind-killer.md";
+        let highlight_note = std::fs::read_to_string(vault.join(highlight)).unwrap();
+        assert!(book_note.contains("- [[Dune - I must not fear. Fear is the mind-killer]]"));
+        assert!(book_note.contains("progress: 42"));
+        assert!(highlight_note.contains("book: \"[[Dune]]\""));
+        assert!(highlight_note.contains("> I must not fear."));
+        std::fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[test]
+    fn reruns_update_moved_notes_in_place() {
+        let vault = vault();
+        let books = [book(1, "Dune")];
+        run(&vault, &books, &[]);
+
+        // The user moves and renames the book note, and adds to it
+        let old = vault.join("Read Master/Books/Dune.md");
+        let moved = vault.join("Library/Dune (1965).md");
+        std::fs::create_dir_all(moved.parent().unwrap()).unwrap();
+        let text = std::fs::read_to_string(&old).unwrap() + "\nMy review.\n";
+        std::fs::write(&moved, text).unwrap();
+        std::fs::remove_file(&old).unwrap();
+
+        let notes = [note("n1", 1, "Fear is the mind-killer")];
+        let ops = run(&vault, &books, &notes);
+        let book_op = ops.iter().find(|op| op.kind == ExportedFile::Book).unwrap();
+        assert_eq!(book_op.action, FileAction::Update);
+        assert_eq!(book_op.path, "Library/Dune (1965).md");
+        assert!(!old.exists());
+
+        let book_note = std::fs::read_to_string(&moved).unwrap();
+        assert!(book_note.contains("My review."));
+        assert!(book_note.contains("[[Dune - Fear is the mind-killer]]"));
+        let highlight = std::fs::read_to_string(
+            vault.join("Read Master/Highlights/Dune/Dune - Fear is the mind-killer.md"),
+        )
+        .unwrap();
+        assert!(highlight.contains("[[Dune (1965)]]"));
+
+        let again = run(&vault, &books, &notes);
+        assert!(again.iter().all(|op| op.action == FileAction::Unchanged));
+        std::fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[test]
+    fn never_overwrites_other_files() {
+        let vault = vault();
+        let theirs = vault.join("Read Master/Books/Dune.md");
+        std::fs::create_dir_all(theirs.parent().unwrap()).unwrap();
+        std::fs::write(&theirs, "my own note").unwrap();
+
+        let ops = run(&vault, &[book(1, "Dune")], &[]);
+        assert_eq!(ops[0].path, "Read Master/Books/Dune 2.md");
+        assert_eq!(std::fs::read_to_string(&theirs).unwrap(), "my own note");
+        std::fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_linked_folders() {
+        let vault = vault();
+        run(&vault, &[book(1, "Dune")], &[]);
+        std::os::unix::fs::symlink(&vault, vault.join("Loop")).unwrap();
+        std::os::unix::fs::symlink(&vault, vault.join("Read Master/Back")).unwrap();
+
+        let found = exported_notes(&vault);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found["book-1"], "Read Master/Books/Dune.md");
+
+        let again = run(&vault, &[book(1, "Dune")], &[]);
+        assert_eq!(again[0].action, FileAction::Unchanged);
+        std::fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[test]
+    fn dry_runs_write_nothing() {
+        let vault = vault();
+        let planned = plan(
+            &vault,
+            &ObsidianOptions::default(),
+            &[book(1, "Dune")],
+            &[note("n1", 1, "Quote")],
+        )
+        .unwrap();
+        assert_eq!(planned.len(), 2);
+        assert_eq!(std::fs::read_dir(&vault).unwrap().count(), 0);
+        std::fs::remove_dir_all(&vault).unwrap();
+    }
+}
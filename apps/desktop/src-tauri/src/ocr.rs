@@ -0,0 +1,283 @@
+// Read Master Desktop - PDF OCR
+//
+// Recognized text for image-only PDFs, so scanned books can be searched and
+// read aloud. Each page's scan is handed to the system's Tesseract install;
+// pages that already carry a text layer use it as is, so a text PDF never
+// starts Tesseract at all.
+//
+// Events: `ocr-progress` after each page. `cancel_ocr` (or `cancel_job`)
+// stops a run before its next page.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{info, warn};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Runtime, State};
+use tauri_plugin_shell::ShellExt;
+
+use crate::images;
+use crate::jobs::{Job, JobKind, JobRegistry};
+use crate::pdf;
+
+/// Non-whitespace characters a page's text layer needs to skip OCR
+const MIN_PAGE_CHARS: usize = 16;
+
+#[cfg(target_os = "windows")]
+const TESSERACT: &str = "tesseract.exe";
+#[cfg(not(target_os = "windows"))]
+const TESSERACT: &str = "tesseract";
+
+/// Common install folders that aren't always on the app's `PATH` (GUI apps
+/// on macOS don't get the shell's)
+const INSTALL_DIRS: &[&str] = &[
+    "/opt/homebrew/bin",
+    "/usr/local/bin",
+    "/usr/bin",
+    "C:\\Program Files\\Tesseract-OCR",
+];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OcrProgress<'a> {
+    path: &'a str,
+    processed_pages: u32,
+    total_pages: u32,
+    /// Whether the page went through Tesseract rather than its text layer
+    recognized: bool,
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Whether a page's own text is enough to skip recognizing it
+fn has_text(text: &str) -> bool {
+    text.chars().filter(|c| !c.is_whitespace()).count() >= MIN_PAGE_CHARS
+}
+
+/// Tesseract language codes joined by `+`, e.g. `eng+deu`
+fn valid_lang(lang: &str) -> bool {
+    !lang.is_empty()
+        && lang.split('+').all(|code| {
+            !code.is_empty() && code.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+/// The `tesseract` executable on `PATH` or in a common install folder
+fn find_tesseract() -> Result<PathBuf, String> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .chain(INSTALL_DIRS.iter().map(PathBuf::from))
+        .map(|dir| dir.join(TESSERACT))
+        .find(|exe| exe.is_file())
+        .ok_or_else(|| {
+            "Tesseract is not installed. Install it (for example `brew install tesseract` \
+             or `apt install tesseract-ocr`) to recognize scanned pages."
+                .to_string()
+        })
+}
+
+/// Languages in `tesseract --list-langs` output, after its header line
+fn parse_languages(output: &str) -> Vec<&str> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("List of available languages"))
+        .skip(1)
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// The Tesseract executable, checked to have data for every requested
+/// language
+async fn tesseract<R: Runtime>(app: &AppHandle<R>, lang: &str) -> Result<PathBuf, String> {
+    let exe = find_tesseract()?;
+    let output = app
+        .shell()
+        .command(&exe)
+        .arg("--list-langs")
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run Tesseract: {}", e))?;
+
+    // Older versions print the list on stderr
+    let listing = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let installed = parse_languages(&listing);
+    match lang.split('+').find(|code| !installed.contains(code)) {
+        Some(missing) => Err(format!(
+            "OCR language not available: {} (install its Tesseract language data)",
+            missing
+        )),
+        None => Ok(exe),
+    }
+}
+
+/// Run Tesseract on one page image and return its text
+async fn recognize<R: Runtime>(
+    app: &AppHandle<R>,
+    image: &[u8],
+    lang: &str,
+    tesseract: &Path,
+) -> Result<String, String> {
+    let input = std::env::temp_dir().join(format!("rm-ocr-{}", uuid::Uuid::new_v4()));
+    std::fs::write(&input, image).map_err(|e| format!("Failed to write page image: {}", e))?;
+
+    let input_arg = input.to_string_lossy().into_owned();
+    let output = app
+        .shell()
+        .command(tesseract)
+        .args([input_arg.as_str(), "stdout", "-l", lang])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run Tesseract: {}", e));
+    if let Err(e) = std::fs::remove_file(&input) {
+        warn!("Failed to remove OCR page image {:?}: {}", input, e);
+    }
+
+    let output = output?;
+    if !output.status.success() {
+        return Err(format!(
+            "Tesseract failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+async fn run_ocr<R: Runtime>(
+    app: &AppHandle<R>,
+    path: &str,
+    lang: &str,
+    job: &Job<R>,
+) -> Result<Vec<String>, String> {
+    let owned = path.to_string();
+    let file = Arc::new(
+        tauri::async_runtime::spawn_blocking(move || pdf::load(&owned))
+            .await
+            .map_err(|e| format!("OCR task failed: {}", e))??,
+    );
+    let total_pages = file.num_pages();
+    let mut tesseract_exe = None;
+    let mut pages = Vec::with_capacity(total_pages as usize);
+
+    for index in 0..total_pages {
+        if job.is_cancelled() {
+            info!("OCR of {} cancelled at page {}", path, index);
+            return Err("OCR cancelled".to_string());
+        }
+
+        // Page parsing is blocking; only Tesseract runs on the async side
+        let page_file = Arc::clone(&file);
+        let (text, scan) = tauri::async_runtime::spawn_blocking(move || {
+            let text = pdf::page_text(&page_file, index)?;
+            if has_text(&text) {
+                return Ok((text, None));
+            }
+            images::pdf_page_scan(&page_file, index).map(|scan| (text, scan))
+        })
+        .await
+        .map_err(|e| format!("OCR task failed: {}", e))??;
+
+        let recognized = scan.is_some();
+        let text = match scan {
+            Some(image) => {
+                if tesseract_exe.is_none() {
+                    tesseract_exe = Some(tesseract(app, lang).await?);
+                }
+                let exe = tesseract_exe.as_ref().expect("tesseract resolved above");
+                recognize(app, &image, lang, exe).await?
+            }
+            None => text,
+        };
+        pages.push(text);
+        job.progress(u64::from(index + 1), u64::from(total_pages), None);
+
+        let progress = OcrProgress {
+            path,
+            processed_pages: index + 1,
+            total_pages,
+            recognized,
+        };
+        if let Err(e) = app.emit("ocr-progress", &progress) {
+            warn!("Failed to emit ocr-progress: {}", e);
+        }
+    }
+
+    if tesseract_exe.is_none() {
+        info!("{} already has a text layer, OCR skipped", path);
+    }
+    Ok(pages)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Text of each page of a PDF, recognized with Tesseract where the page has
+/// no text layer of its own. `lang` takes Tesseract codes such as `eng` or
+/// `eng+deu`.
+#[tauri::command]
+pub async fn ocr_pdf<R: Runtime>(
+    app: AppHandle<R>,
+    path: String,
+    lang: String,
+) -> Result<Vec<String>, String> {
+    if !valid_lang(&lang) {
+        return Err(format!("Invalid OCR language: {}", lang));
+    }
+
+    let job = Job::start(&app, JobKind::Ocr, &path, &format!("OCR of {}", path))?;
+    info!("Starting OCR of {} ({})", path, lang);
+    let result = run_ocr(&app, &path, &lang, &job).await;
+    job.finish(&result);
+    result
+}
+
+/// Stop a running OCR job before its next page. Returns whether a job was
+/// running.
+#[tauri::command]
+pub fn cancel_ocr(jobs: State<'_, JobRegistry>, path: String) -> Result<bool, String> {
+    Ok(jobs
+        .find(JobKind::Ocr, &path)
+        .is_some_and(|id| jobs.cancel(id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_pages_with_a_text_layer() {
+        assert!(has_text("Chapter One\nIt was a bright cold day."));
+        assert!(!has_text(""));
+        assert!(!has_text("  12 \n iv "));
+    }
+
+    #[test]
+    fn validates_language_codes() {
+        assert!(valid_lang("eng"));
+        assert!(valid_lang("eng+deu"));
+        assert!(valid_lang("chi_sim"));
+        assert!(!valid_lang(""));
+        assert!(!valid_lang("eng+"));
+        assert!(!valid_lang("../eng"));
+        assert!(!valid_lang("eng --psm 0"));
+    }
+
+    #[test]
+    fn lists_installed_languages() {
+        let output = "List of available languages in \"/usr/share/tessdata/\" (3):\n\
+                      deu\neng\nosd\n";
+        assert_eq!(parse_languages(output), ["deu", "eng", "osd"]);
+        assert!(parse_languages("tesseract: command failed").is_empty());
+    }
+}
@@ -0,0 +1,594 @@
+// Read Master Desktop - OPDS
+//
+// OPDS 1.2 catalog browsing and resumable publication downloads.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use reqwest::header::{
+    HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE,
+    CONTENT_TYPE, ETAG, IF_RANGE, RANGE,
+};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tauri::{AppHandle, Emitter, Runtime};
+
+use crate::import;
+use crate::keychain;
+use crate::library;
+use crate::net;
+use crate::profiles::ProfileStoreExt;
+
+/// Acquisition link relation prefix
+const ACQUISITION_REL: &str = "http://opds-spec.org/acquisition";
+
+/// Minimum bytes between `download-progress` events
+const PROGRESS_STEP: u64 = 256 * 1024;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Credentials for a catalog. Secrets are read from the keychain under
+/// `secret_key` and never passed through IPC.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum OpdsAuth {
+    #[serde(rename_all = "camelCase")]
+    Basic {
+        username: String,
+        secret_key: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Bearer { secret_key: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsLink {
+    pub href: String,
+    pub rel: Option<String>,
+    pub media_type: Option<String>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsEntry {
+    pub id: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub summary: Option<String>,
+    pub updated: Option<String>,
+    pub categories: Vec<String>,
+    pub cover_url: Option<String>,
+    pub thumbnail_url: Option<String>,
+    /// Download links (rel `http://opds-spec.org/acquisition*`)
+    pub acquisitions: Vec<OpdsLink>,
+    /// Link to a sub-catalog, for navigation entries
+    pub navigation: Option<OpdsLink>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpdsFeed {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub updated: Option<String>,
+    pub entries: Vec<OpdsEntry>,
+    pub next: Option<String>,
+    pub previous: Option<String>,
+    pub first: Option<String>,
+    pub last: Option<String>,
+    pub start: Option<String>,
+    /// Search URL with a `{searchTerms}` placeholder, resolved from the
+    /// feed's OpenSearch description when needed
+    pub search_template: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadProgress<'a> {
+    url: &'a str,
+    downloaded: u64,
+    total: Option<u64>,
+    resumed_from: u64,
+}
+
+/// Sidecar kept next to a partial download so it can be resumed
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PartialMeta {
+    etag: Option<String>,
+    total: Option<u64>,
+    file_name: Option<String>,
+}
+
+// ============================================================================
+// HTTP
+// ============================================================================
+
+fn host_of(url: &str) -> Option<String> {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+}
+
+/// Client for `url`, accepting self-signed certificates only for hosts the
+/// user has explicitly trusted (`opds.trustedHosts` setting)
+fn client_for<R: Runtime>(app: &AppHandle<R>, url: &str) -> Result<Client, String> {
+    let trusted: Vec<String> = app
+        .profile_store("settings.json")
+        .ok()
+        .and_then(|store| store.get("opds.trustedHosts"))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+
+    let trust_invalid = host_of(url).is_some_and(|host| trusted.contains(&host));
+    if trust_invalid {
+        warn!("Accepting untrusted certificates for {:?}", host_of(url));
+    }
+
+    Client::builder()
+        // Downloads can take a long time; only the connect is bounded
+        .connect_timeout(net::DEFAULT_TIMEOUT)
+        .danger_accept_invalid_certs(trust_invalid)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))
+}
+
+fn auth_headers(auth: Option<&OpdsAuth>) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+
+    let value = match auth {
+        None => return Ok(headers),
+        Some(OpdsAuth::Basic {
+            username,
+            secret_key,
+        }) => {
+            use base64::Engine;
+            let password = keychain::get_secret(secret_key)?.unwrap_or_default();
+            let encoded = base64::engine::general_purpose::STANDARD
+                .encode(format!("{}:{}", username, password));
+            format!("Basic {}", encoded)
+        }
+        Some(OpdsAuth::Bearer { secret_key }) => {
+            let token = keychain::get_secret(secret_key)?
+                .ok_or_else(|| format!("No token stored for '{}'", secret_key))?;
+            format!("Bearer {}", token)
+        }
+    };
+
+    let mut value = HeaderValue::from_str(&value).map_err(|_| "Invalid credentials".to_string())?;
+    value.set_sensitive(true);
+    headers.insert(AUTHORIZATION, value);
+    Ok(headers)
+}
+
+async fn fetch_text(client: &Client, url: &str, headers: &HeaderMap) -> Result<String, String> {
+    let response = client
+        .get(url)
+        .headers(headers.clone())
+        .timeout(net::DEFAULT_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch {}: {}", url, e))?;
+
+    match response.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Err("Catalog requires valid credentials".to_string())
+        }
+        status if !status.is_success() => Err(format!("Catalog returned HTTP {}", status)),
+        _ => response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read catalog: {}", e)),
+    }
+}
+
+// ============================================================================
+// Feed Parsing
+// ============================================================================
+
+fn resolve_url(base: &url::Url, href: &str) -> String {
+    base.join(href)
+        .map(|u| u.to_string())
+        .unwrap_or_else(|_| href.to_string())
+}
+
+fn child_text(node: roxmltree::Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|c| c.tag_name().name() == name)
+        .and_then(|c| c.text())
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+fn parse_link(node: roxmltree::Node, base: &url::Url) -> Option<OpdsLink> {
+    Some(OpdsLink {
+        href: resolve_url(base, node.attribute("href")?),
+        rel: node.attribute("rel").map(str::to_string),
+        media_type: node.attribute("type").map(str::to_string),
+        title: node.attribute("title").map(str::to_string),
+    })
+}
+
+fn parse_entry(node: roxmltree::Node, base: &url::Url) -> OpdsEntry {
+    let mut entry = OpdsEntry {
+        id: child_text(node, "id").unwrap_or_default(),
+        title: child_text(node, "title").unwrap_or_else(|| "Untitled".to_string()),
+        authors: Vec::new(),
+        summary: child_text(node, "summary").or_else(|| child_text(node, "content")),
+        updated: child_text(node, "updated"),
+        categories: Vec::new(),
+        cover_url: None,
+        thumbnail_url: None,
+        acquisitions: Vec::new(),
+        navigation: None,
+    };
+
+    for child in node.children().filter(|c| c.is_element()) {
+        match child.tag_name().name() {
+            "author" => entry.authors.extend(child_text(child, "name")),
+            "category" => entry.categories.extend(
+                child
+                    .attribute("label")
+                    .or(child.attribute("term"))
+                    .map(str::to_string),
+            ),
+            "link" => {
+                let Some(link) = parse_link(child, base) else {
+                    continue;
+                };
+                let rel = link.rel.as_deref().unwrap_or("");
+                let media_type = link.media_type.as_deref().unwrap_or("");
+
+                if rel.starts_with(ACQUISITION_REL) {
+                    entry.acquisitions.push(link);
+                } else if rel == "http://opds-spec.org/image/thumbnail" {
+                    entry.thumbnail_url = Some(link.href);
+                } else if rel == "http://opds-spec.org/image" {
+                    entry.cover_url = Some(link.href);
+                } else if media_type.contains("profile=opds-catalog") {
+                    entry.navigation = Some(link);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    entry
+}
+
+/// Parse an OPDS Atom feed. Returns the feed and the href of its search
+/// link (which may be an OpenSearch description needing another fetch).
+fn parse_feed(xml: &str, url: &str) -> Result<(OpdsFeed, Option<OpdsLink>), String> {
+    let base = url::Url::parse(url).map_err(|e| format!("Invalid feed URL: {}", e))?;
+    let doc = roxmltree::Document::parse(xml).map_err(|e| format!("Invalid OPDS feed: {}", e))?;
+    let root = doc.root_element();
+
+    if root.tag_name().name() != "feed" {
+        return Err("Not an OPDS/Atom feed".to_string());
+    }
+
+    let mut feed = OpdsFeed {
+        id: child_text(root, "id"),
+        title: child_text(root, "title"),
+        updated: child_text(root, "updated"),
+        entries: Vec::new(),
+        next: None,
+        previous: None,
+        first: None,
+        last: None,
+        start: None,
+        search_template: None,
+    };
+    let mut search = None;
+
+    for child in root.children().filter(|c| c.is_element()) {
+        match child.tag_name().name() {
+            "entry" => feed.entries.push(parse_entry(child, &base)),
+            "link" => {
+                let Some(link) = parse_link(child, &base) else {
+                    continue;
+                };
+                match link.rel.as_deref() {
+                    Some("next") => feed.next = Some(link.href),
+                    Some("previous") | Some("prev") => feed.previous = Some(link.href),
+                    Some("first") => feed.first = Some(link.href),
+                    Some("last") => feed.last = Some(link.href),
+                    Some("start") => feed.start = Some(link.href),
+                    Some("search") => search = Some(link),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok((feed, search))
+}
+
+/// Pick the Atom search template out of an OpenSearch description
+fn parse_opensearch(xml: &str, base: &str) -> Option<String> {
+    let base = url::Url::parse(base).ok()?;
+    let doc = roxmltree::Document::parse(xml).ok()?;
+
+    doc.descendants()
+        .filter(|n| n.tag_name().name() == "Url")
+        .find(|n| {
+            n.attribute("type")
+                .is_some_and(|t| t.starts_with("application/atom+xml"))
+        })
+        .and_then(|n| n.attribute("template"))
+        .map(|template| resolve_url(&base, template).replace("%7BsearchTerms%7D", "{searchTerms}"))
+}
+
+// ============================================================================
+// Downloads
+// ============================================================================
+
+fn downloads_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    let dir = library::library_dir(app)?.join(".downloads");
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create downloads directory: {}", e))?;
+    Ok(dir)
+}
+
+fn read_meta(path: &Path) -> PartialMeta {
+    std::fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn write_meta(path: &Path, meta: &PartialMeta) {
+    if let Ok(bytes) = serde_json::to_vec(meta) {
+        let _ = std::fs::write(path, bytes);
+    }
+}
+
+/// File name from Content-Disposition, else the URL path, with an
+/// extension matching the content type
+fn download_file_name(headers: &HeaderMap, url: &str) -> String {
+    let from_disposition = headers
+        .get(CONTENT_DISPOSITION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| {
+            v.split(';')
+                .find_map(|part| part.trim().strip_prefix("filename="))
+        })
+        .map(|name| name.trim_matches('"').to_string());
+
+    let from_url = url::Url::parse(url).ok().and_then(|u| {
+        u.path_segments()
+            .and_then(|mut s| s.next_back().map(str::to_string))
+            .filter(|s| !s.is_empty())
+    });
+
+    let mut name = from_disposition
+        .or(from_url)
+        .unwrap_or_else(|| "download".to_string())
+        .replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
+
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let ext = match content_type {
+        t if t.starts_with("application/epub+zip") => Some("epub"),
+        t if t.starts_with("application/pdf") => Some("pdf"),
+        _ => None,
+    };
+    if let Some(ext) = ext {
+        if !name.to_ascii_lowercase().ends_with(&format!(".{}", ext)) {
+            name = format!("{}.{}", name, ext);
+        }
+    }
+
+    name
+}
+
+/// Total size from a `Content-Range: bytes a-b/total` header
+fn content_range_total(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit('/').next())
+        .and_then(|t| t.parse().ok())
+}
+
+async fn download_to_library<R: Runtime>(
+    app: &AppHandle<R>,
+    url: &str,
+    auth: Option<&OpdsAuth>,
+) -> Result<PathBuf, String> {
+    let client = client_for(app, url)?;
+    let headers = auth_headers(auth)?;
+
+    // Partial downloads are keyed by URL so a retry finds them
+    let key = format!("{:x}", Sha1::digest(url.as_bytes()));
+    let dir = downloads_dir(app)?;
+    let part_path = dir.join(format!("{}.part", key));
+    let meta_path = dir.join(format!("{}.json", key));
+
+    let mut meta = read_meta(&meta_path);
+    let existing = std::fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+
+    let mut request = client.get(url).headers(headers);
+    if existing > 0 {
+        request = request.header(RANGE, format!("bytes={}-", existing));
+        // Only resume if the file hasn't changed since the partial download
+        if let Some(etag) = &meta.etag {
+            request = request.header(IF_RANGE, etag.as_str());
+        }
+    }
+
+    let mut response = request
+        .send()
+        .await
+        .map_err(|e| format!("Download failed: {}", e))?;
+
+    let status = response.status();
+    let resumed = status == StatusCode::PARTIAL_CONTENT && existing > 0;
+    if !status.is_success() {
+        return Err(format!("Download failed: HTTP {}", status));
+    }
+
+    let response_etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let total = if resumed {
+        content_range_total(response.headers())
+    } else {
+        response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+    };
+
+    if resumed {
+        if meta.etag.is_some() && response_etag.is_some() && meta.etag != response_etag {
+            return Err("Remote file changed during resume; please retry".to_string());
+        }
+        info!("Resuming download of {} at {} bytes", url, existing);
+    } else {
+        meta = PartialMeta {
+            etag: response_etag,
+            total,
+            file_name: Some(download_file_name(response.headers(), url)),
+        };
+        write_meta(&meta_path, &meta);
+    }
+
+    let mut file = if resumed {
+        OpenOptions::new().append(true).open(&part_path)
+    } else {
+        File::create(&part_path)
+    }
+    .map_err(|e| format!("Failed to open download file: {}", e))?;
+
+    let resumed_from = if resumed { existing } else { 0 };
+    let mut downloaded = resumed_from;
+    let mut last_emitted = 0;
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Download interrupted: {}", e))?
+    {
+        file.write_all(&chunk)
+            .map_err(|e| format!("Failed to write download: {}", e))?;
+        downloaded += chunk.len() as u64;
+
+        if downloaded - last_emitted >= PROGRESS_STEP {
+            last_emitted = downloaded;
+            let _ = app.emit(
+                "download-progress",
+                DownloadProgress {
+                    url,
+                    downloaded,
+                    total,
+                    resumed_from,
+                },
+            );
+        }
+    }
+
+    file.sync_all()
+        .map_err(|e| format!("Failed to flush download: {}", e))?;
+    drop(file);
+
+    // Verify against the advertised size before accepting the file
+    let expected = total.or(meta.total);
+    let actual = std::fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+    if let Some(expected) = expected {
+        if actual != expected {
+            return Err(format!(
+                "Download incomplete: got {} of {} bytes; retry to resume",
+                actual, expected
+            ));
+        }
+    }
+
+    let _ = app.emit(
+        "download-progress",
+        DownloadProgress {
+            url,
+            downloaded: actual,
+            total: expected,
+            resumed_from,
+        },
+    );
+
+    let file_name = meta
+        .file_name
+        .clone()
+        .unwrap_or_else(|| format!("{}.bin", key));
+    let dest = import::unique_path(&library::library_dir(app)?, &file_name);
+    std::fs::rename(&part_path, &dest).map_err(|e| format!("Failed to move download: {}", e))?;
+    let _ = std::fs::remove_file(&meta_path);
+
+    Ok(dest)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Fetch and parse an OPDS catalog feed
+#[tauri::command]
+pub async fn fetch_opds_feed<R: Runtime>(
+    app: AppHandle<R>,
+    url: String,
+    auth: Option<OpdsAuth>,
+) -> Result<OpdsFeed, String> {
+    info!("Fetching OPDS feed: {}", url);
+
+    let client = client_for(&app, &url)?;
+    let headers = auth_headers(auth.as_ref())?;
+
+    let xml = fetch_text(&client, &url, &headers).await?;
+    let (mut feed, search) = parse_feed(&xml, &url)?;
+
+    if let Some(search) = search {
+        let media_type = search.media_type.as_deref().unwrap_or("");
+        if media_type.starts_with("application/opensearchdescription+xml") {
+            match fetch_text(&client, &search.href, &headers).await {
+                Ok(xml) => feed.search_template = parse_opensearch(&xml, &search.href),
+                Err(e) => warn!("Failed to fetch OpenSearch description: {}", e),
+            }
+        } else {
+            feed.search_template = Some(search.href.replace("%7BsearchTerms%7D", "{searchTerms}"));
+        }
+    }
+
+    Ok(feed)
+}
+
+/// Download a publication into the library and import it. Interrupted
+/// downloads resume from where they stopped on the next call.
+#[tauri::command]
+pub async fn download_publication<R: Runtime>(
+    app: AppHandle<R>,
+    url: String,
+    auth: Option<OpdsAuth>,
+) -> Result<i64, String> {
+    info!("Downloading publication: {}", url);
+
+    let path = download_to_library(&app, &url, auth.as_ref()).await?;
+
+    let outcome = tauri::async_runtime::spawn_blocking(move || import::import_file(&app, &path))
+        .await
+        .map_err(|e| format!("Import task failed: {}", e))?
+        .map_err(|e| e.to_string())?;
+
+    Ok(outcome.book.id)
+}
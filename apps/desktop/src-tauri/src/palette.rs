@@ -0,0 +1,249 @@
+// Read Master Desktop - Cover Palettes
+//
+// Dominant colors of a book's cover, for accent colors in the library. The
+// cover is read from an EPUB or Mobipocket file (or decoded directly when
+// `path` is an image, such as a saved cover), shrunk, and quantized by
+// median cut, with buckets ranked by weight.
+//
+// Each pixel is weighted by its saturation and brightness, so the white or
+// black margins most covers have don't crowd out the colors that make the
+// cover recognizable. A cover that is all grays still gets a palette, since
+// every pixel keeps a small base weight.
+
+use std::path::Path;
+
+use image::RgbaImage;
+use log::info;
+
+use crate::epub::EpubArchive;
+use crate::import::{self, BookFormat};
+use crate::mobi;
+
+/// Longest side the cover is shrunk to before quantizing
+const SAMPLE_SIZE: u32 = 96;
+
+pub const MAX_COLORS: usize = 16;
+
+/// Weight of a fully unsaturated pixel, relative to a vivid one
+const BASE_WEIGHT: f32 = 0.05;
+
+// ============================================================================
+// Quantization
+// ============================================================================
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    rgb: [u8; 3],
+    weight: f32,
+}
+
+/// HSV saturation times value: vivid colors count most, while whites (no
+/// saturation) and near-blacks (no value) barely count
+fn weight(rgb: [u8; 3]) -> f32 {
+    let max = rgb.iter().copied().max().unwrap_or(0) as f32 / 255.0;
+    let min = rgb.iter().copied().min().unwrap_or(0) as f32 / 255.0;
+    let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+    BASE_WEIGHT + saturation * max
+}
+
+fn samples(image: &RgbaImage) -> Vec<Sample> {
+    image
+        .pixels()
+        // Transparent padding isn't part of the cover
+        .filter(|pixel| pixel[3] >= 128)
+        .map(|pixel| {
+            let rgb = [pixel[0], pixel[1], pixel[2]];
+            Sample {
+                rgb,
+                weight: weight(rgb),
+            }
+        })
+        .collect()
+}
+
+/// A box of samples in color space
+struct Bucket {
+    samples: Vec<Sample>,
+}
+
+impl Bucket {
+    fn total(&self) -> f32 {
+        self.samples.iter().map(|s| s.weight).sum()
+    }
+
+    /// Channel with the widest spread, and that spread
+    fn widest(&self) -> (usize, u8) {
+        (0..3)
+            .map(|channel| {
+                let values = self.samples.iter().map(|s| s.rgb[channel]);
+                let spread = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+                (channel, spread)
+            })
+            .max_by_key(|&(_, spread)| spread)
+            .unwrap_or((0, 0))
+    }
+
+    /// Split at the middle of the widest channel's range. Splitting at the
+    /// median instead would cut a dominant color in two.
+    fn split(self) -> (Bucket, Bucket) {
+        let (channel, _) = self.widest();
+        let values = self.samples.iter().map(|s| s.rgb[channel]);
+        let (low, high) = (values.clone().min().unwrap_or(0), values.max().unwrap_or(0));
+        let middle = low + (high - low) / 2;
+
+        let (below, above) = self
+            .samples
+            .into_iter()
+            .partition(|s| s.rgb[channel] <= middle);
+        (Bucket { samples: below }, Bucket { samples: above })
+    }
+
+    /// Weighted mean color
+    fn color(&self) -> [u8; 3] {
+        let total = self.total();
+        let mut sum = [0.0f32; 3];
+        for sample in &self.samples {
+            for (channel, value) in sum.iter_mut().enumerate() {
+                *value += f32::from(sample.rgb[channel]) * sample.weight;
+            }
+        }
+        sum.map(|value| (value / total).round().clamp(0.0, 255.0) as u8)
+    }
+}
+
+/// Up to `count` colors, most prominent first
+fn quantize(samples: Vec<Sample>, count: usize) -> Vec<[u8; 3]> {
+    if samples.is_empty() || count == 0 {
+        return Vec::new();
+    }
+
+    let mut buckets = vec![Bucket { samples }];
+    while buckets.len() < count {
+        // Split whichever splittable bucket spans most weighted color range
+        let next = buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, bucket)| bucket.samples.len() > 1 && bucket.widest().1 > 0)
+            .max_by(|(_, a), (_, b)| {
+                let score = |bucket: &Bucket| bucket.total() * f32::from(bucket.widest().1);
+                score(a).total_cmp(&score(b))
+            })
+            .map(|(i, _)| i);
+        let Some(index) = next else {
+            break;
+        };
+        let (a, b) = buckets.swap_remove(index).split();
+        buckets.push(a);
+        buckets.push(b);
+    }
+
+    buckets.sort_by(|a, b| b.total().total_cmp(&a.total()));
+    buckets.iter().map(Bucket::color).collect()
+}
+
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+// ============================================================================
+// Covers
+// ============================================================================
+
+/// The cover image of the book at `path`, or the file itself when it's
+/// not a book
+fn cover_data(path: &Path) -> Result<Vec<u8>, String> {
+    let cover = match import::detect_format(path)? {
+        BookFormat::Epub => {
+            let mut epub = EpubArchive::open(&path.to_string_lossy()).map_err(|e| e.to_string())?;
+            match epub.cover_path() {
+                Some(cover) => Some(epub.read_resource(&cover)?),
+                None => None,
+            }
+        }
+        BookFormat::Mobi | BookFormat::Azw3 => mobi::read_cover(path)?,
+        BookFormat::Pdf | BookFormat::Djvu => None,
+        BookFormat::Unknown => Some(
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?,
+        ),
+    };
+    cover.ok_or_else(|| format!("No cover found in {}", path.display()))
+}
+
+/// Dominant colors of the cover at `path`, most prominent first
+pub fn cover_palette(path: &Path, count: usize) -> Result<Vec<String>, String> {
+    let data = cover_data(path)?;
+    let image =
+        image::load_from_memory(&data).map_err(|e| format!("Failed to decode cover: {}", e))?;
+    let sample = image.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_rgba8();
+
+    Ok(quantize(samples(&sample), count.min(MAX_COLORS))
+        .into_iter()
+        .map(hex)
+        .collect())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Up to `count` (at most 16) dominant colors of a book's cover as hex
+/// strings, most prominent first. `path` is a book file or a cover image.
+#[tauri::command]
+pub async fn extract_cover_palette(path: String, count: usize) -> Result<Vec<String>, String> {
+    info!("Extracting cover palette: {}", path);
+    tauri::async_runtime::spawn_blocking(move || cover_palette(Path::new(&path), count))
+        .await
+        .map_err(|e| format!("Palette task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn palette(image: &RgbaImage, count: usize) -> Vec<String> {
+        quantize(samples(image), count)
+            .into_iter()
+            .map(hex)
+            .collect()
+    }
+
+    #[test]
+    fn saturated_colors_outrank_white_margins() {
+        // Mostly white, with a red band
+        let image = RgbaImage::from_fn(10, 10, |_, y| {
+            if y < 3 {
+                Rgba([200, 30, 30, 255])
+            } else {
+                Rgba([250, 250, 250, 255])
+            }
+        });
+
+        assert_eq!(palette(&image, 2), ["#c81e1e", "#fafafa"]);
+    }
+
+    #[test]
+    fn gray_covers_still_get_colors() {
+        let image = RgbaImage::from_pixel(4, 4, Rgba([40, 40, 40, 255]));
+        assert_eq!(palette(&image, 3), ["#282828"]);
+    }
+
+    #[test]
+    fn skips_transparent_pixels() {
+        let image = RgbaImage::from_fn(4, 4, |x, _| {
+            if x == 0 {
+                Rgba([0, 90, 200, 255])
+            } else {
+                Rgba([255, 0, 0, 0])
+            }
+        });
+        assert_eq!(palette(&image, 4), ["#005ac8"]);
+    }
+
+    #[test]
+    fn weights_favor_vivid_pixels() {
+        assert!(weight([220, 40, 40]) > weight([250, 250, 250]));
+        assert!(weight([220, 40, 40]) > weight([12, 2, 2]));
+        assert_eq!(weight([255, 255, 255]), BASE_WEIGHT);
+    }
+}
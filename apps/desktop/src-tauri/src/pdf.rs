@@ -0,0 +1,247 @@
+// Read Master Desktop - PDF
+//
+// Page-level access to PDFs through a memory map. The file is never read
+// into memory as a whole: the cross-reference table is used to resolve only
+// the objects a requested page needs, and the OS pages the rest in and out.
+// This keeps textbooks of a gigabyte or more as cheap to open as small ones.
+//
+// Documents are opened into handles owned by the calling window and closed
+// automatically when that window is destroyed.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use memmap2::Mmap;
+use pdf::content::{Op, TextDrawAdjusted};
+use pdf::file::{CachedFile, FileOptions};
+use serde::{Deserialize, Serialize};
+use tauri::{Manager, Runtime, State, Window, WindowEvent};
+
+use crate::find::FindSessions;
+
+/// TJ adjustment (thousandths of an em) wide enough to count as a word gap
+const WORD_GAP: f32 = 200.0;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+pub type PdfFile = CachedFile<Mmap>;
+
+struct OpenPdf {
+    /// Label of the window that opened the document
+    window: String,
+    path: String,
+    file: Arc<PdfFile>,
+}
+
+/// Open documents by handle
+#[derive(Default)]
+pub struct PdfHandles {
+    next: AtomicU32,
+    open: Mutex<HashMap<u32, OpenPdf>>,
+}
+
+impl PdfHandles {
+    pub fn get(&self, handle: u32) -> Result<Arc<PdfFile>, String> {
+        self.open
+            .lock()
+            .map_err(|_| "PDF handles lock poisoned".to_string())?
+            .get(&handle)
+            .map(|pdf| Arc::clone(&pdf.file))
+            .ok_or_else(|| format!("Unknown PDF handle: {}", handle))
+    }
+
+    /// File an open document was loaded from
+    pub fn path(&self, handle: u32) -> Result<String, String> {
+        self.open
+            .lock()
+            .map_err(|_| "PDF handles lock poisoned".to_string())?
+            .get(&handle)
+            .map(|pdf| pdf.path.clone())
+            .ok_or_else(|| format!("Unknown PDF handle: {}", handle))
+    }
+
+    /// Close every document opened by `window`
+    fn close_window(&self, window: &str) {
+        if let Ok(mut open) = self.open.lock() {
+            open.retain(|handle, pdf| {
+                if pdf.window != window {
+                    return true;
+                }
+                info!("Closing PDF {} ({}) with its window", handle, pdf.path);
+                false
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfHandle {
+    pub handle: u32,
+    pub page_count: u32,
+}
+
+// ============================================================================
+// Loading
+// ============================================================================
+
+/// Memory-map and parse a PDF. Blocking; only the cross-reference table
+/// and trailer are read up front.
+pub fn load(path: &str) -> Result<PdfFile, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open PDF: {}", e))?;
+    // SAFETY: the map is read-only. If another process truncates the file
+    // while it is open, reads past the new end fault, as they would for any
+    // memory-mapped reader; library files aren't modified in place.
+    let map = unsafe { Mmap::map(&file) }.map_err(|e| format!("Failed to map PDF: {}", e))?;
+
+    FileOptions::cached()
+        .load(map)
+        .map_err(|e| format!("Failed to parse PDF {}: {}", path, e))
+}
+
+// ============================================================================
+// Text Extraction
+// ============================================================================
+
+fn push_break(text: &mut String, separator: char) {
+    if !text.is_empty() && !text.ends_with(['\n', ' ']) {
+        text.push(separator);
+    }
+}
+
+/// Text shown on a page, in content-stream order. Strings are decoded as
+/// their raw bytes, which covers the standard encodings most text PDFs use.
+pub fn page_text(file: &PdfFile, index: u32) -> Result<String, String> {
+    let page = file
+        .get_page(index)
+        .map_err(|e| format!("Failed to read page {}: {}", index, e))?;
+    let Some(content) = &page.contents else {
+        return Ok(String::new());
+    };
+    let ops = content
+        .operations(&file.resolver())
+        .map_err(|e| format!("Failed to parse page {}: {}", index, e))?;
+
+    let mut text = String::new();
+    for op in ops {
+        match op {
+            Op::TextDraw { text: s } => text.push_str(&s.to_string_lossy()),
+            Op::TextDrawAdjusted { array } => {
+                for item in array {
+                    match item {
+                        TextDrawAdjusted::Text(s) => text.push_str(&s.to_string_lossy()),
+                        TextDrawAdjusted::Spacing(gap) if gap < -WORD_GAP => {
+                            push_break(&mut text, ' ')
+                        }
+                        TextDrawAdjusted::Spacing(_) => {}
+                    }
+                }
+            }
+            Op::MoveTextPosition { translation } if translation.y == 0.0 => {
+                push_break(&mut text, ' ')
+            }
+            Op::MoveTextPosition { .. } | Op::TextNewline | Op::EndText => {
+                push_break(&mut text, '\n')
+            }
+            _ => {}
+        }
+    }
+
+    Ok(text.trim_end().to_string())
+}
+
+// ============================================================================
+// Window Cleanup
+// ============================================================================
+
+/// Builder-level window event hook releasing a window's documents
+pub fn handle_window_event<R: Runtime>(window: &Window<R>, event: &WindowEvent) {
+    if let WindowEvent::Destroyed = event {
+        window.state::<PdfHandles>().close_window(window.label());
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Memory-map a PDF and return a handle for page access. The handle
+/// belongs to the calling window.
+#[tauri::command]
+pub async fn mmap_pdf_open<R: Runtime>(
+    window: Window<R>,
+    handles: State<'_, PdfHandles>,
+    path: String,
+) -> Result<PdfHandle, String> {
+    info!("Opening PDF: {}", path);
+
+    let path_for_task = path.clone();
+    let pdf = tauri::async_runtime::spawn_blocking(move || load(&path_for_task))
+        .await
+        .map_err(|e| format!("PDF open task failed: {}", e))??;
+
+    let page_count = pdf.num_pages();
+    let handle = handles.next.fetch_add(1, Ordering::Relaxed) + 1;
+
+    handles
+        .open
+        .lock()
+        .map_err(|_| "PDF handles lock poisoned".to_string())?
+        .insert(
+            handle,
+            OpenPdf {
+                window: window.label().to_string(),
+                path,
+                file: Arc::new(pdf),
+            },
+        );
+
+    Ok(PdfHandle { handle, page_count })
+}
+
+/// Text of one page (0-based) of an open PDF
+#[tauri::command]
+pub async fn mmap_pdf_page_text(
+    handles: State<'_, PdfHandles>,
+    handle: u32,
+    page: u32,
+) -> Result<String, String> {
+    let file = handles.get(handle)?;
+    if page >= file.num_pages() {
+        return Err(format!(
+            "Page {} out of range (document has {})",
+            page,
+            file.num_pages()
+        ));
+    }
+
+    tauri::async_runtime::spawn_blocking(move || page_text(&file, page))
+        .await
+        .map_err(|e| format!("PDF text task failed: {}", e))?
+}
+
+/// Release an open PDF and its memory map
+#[tauri::command]
+pub async fn mmap_pdf_close(
+    handles: State<'_, PdfHandles>,
+    finds: State<'_, FindSessions>,
+    handle: u32,
+) -> Result<(), String> {
+    finds.forget(&handle.to_string());
+    let closed = handles
+        .open
+        .lock()
+        .map_err(|_| "PDF handles lock poisoned".to_string())?
+        .remove(&handle);
+
+    match closed {
+        Some(pdf) => info!("Closed PDF {} ({})", handle, pdf.path),
+        None => warn!("Closing unknown PDF handle {}", handle),
+    }
+    Ok(())
+}
@@ -0,0 +1,663 @@
+// Read Master Desktop - PDF Reflow
+//
+// Paragraphs recovered from a PDF's positioned text, so fixed-layout pages
+// can be reflowed like an EPUB in a small window. Each page's content
+// stream is interpreted just far enough to know where every string is
+// drawn; the strings are then grouped into lines, columns, and paragraphs.
+//
+// Columns are found from left edges: a column starts where several lines
+// begin at the same x while other text sits to their left on the same
+// baseline. Text crossing the gutter (titles, figure captions, footnotes)
+// splits the page into bands, each read column by column.
+//
+// Glyph widths aren't looked up in the fonts; each character is taken as
+// half an em. That is close enough to tell word gaps from column gutters.
+
+use std::path::Path;
+
+use log::info;
+use pdf::content::{Matrix as PdfMatrix, Op, TextDrawAdjusted};
+use serde::Serialize;
+
+use crate::pdf::{self as pdf_file, PdfFile};
+
+/// Estimated advance of one character, in ems
+const CHAR_WIDTH: f32 = 0.5;
+
+/// Horizontal gap, in ems, above which two pieces of a line get a space
+const SPACE_GAP: f32 = 0.15;
+
+/// Lines starting at a column's left edge needed to accept the column
+const MIN_COLUMN_LINES: usize = 3;
+
+/// Size ratio over the body text that makes a line a heading
+const HEADING_RATIO: f32 = 1.2;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlockKind {
+    Heading,
+    Paragraph,
+}
+
+/// A paragraph or heading in reading order
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReflowBlock {
+    /// 0-based page the block is on
+    pub page: u32,
+    pub kind: BlockKind,
+    pub text: String,
+    /// 1-based column the block was read from, or 0 for text spanning
+    /// the columns
+    pub column: u32,
+    /// Font size in points, for scaling headings
+    pub font_size: f32,
+}
+
+/// A string drawn at one position, in page space (points, y up)
+#[derive(Debug, Clone, PartialEq)]
+struct TextSpan {
+    x: f32,
+    end_x: f32,
+    /// Baseline
+    y: f32,
+    size: f32,
+    text: String,
+}
+
+/// Spans of a column on one baseline, joined
+#[derive(Debug, Clone)]
+struct Line {
+    x: f32,
+    end_x: f32,
+    y: f32,
+    size: f32,
+    text: String,
+    column: u32,
+}
+
+// ============================================================================
+// Content Stream
+// ============================================================================
+
+/// Affine transform `[a b c d e f]`, applied to row vectors as in the PDF
+/// spec
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Matrix([f32; 6]);
+
+impl Matrix {
+    const IDENTITY: Self = Self([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    fn translate(x: f32, y: f32) -> Self {
+        Self([1.0, 0.0, 0.0, 1.0, x, y])
+    }
+
+    /// `self` followed by `then`
+    fn then(self, then: Self) -> Self {
+        let [a, b, c, d, e, f] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = then.0;
+        Self([
+            a * a2 + b * c2,
+            a * b2 + b * d2,
+            c * a2 + d * c2,
+            c * b2 + d * d2,
+            e * a2 + f * c2 + e2,
+            e * b2 + f * d2 + f2,
+        ])
+    }
+
+    fn origin(self) -> (f32, f32) {
+        (self.0[4], self.0[5])
+    }
+}
+
+impl From<PdfMatrix> for Matrix {
+    fn from(m: PdfMatrix) -> Self {
+        Self([m.a, m.b, m.c, m.d, m.e, m.f])
+    }
+}
+
+/// Text state between operators
+struct TextState {
+    ctm: Matrix,
+    saved: Vec<Matrix>,
+    text: Matrix,
+    line: Matrix,
+    size: f32,
+    leading: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    /// Horizontal scaling as a fraction
+    scaling: f32,
+    rise: f32,
+}
+
+impl TextState {
+    fn new() -> Self {
+        Self {
+            ctm: Matrix::IDENTITY,
+            saved: Vec::new(),
+            text: Matrix::IDENTITY,
+            line: Matrix::IDENTITY,
+            size: 0.0,
+            leading: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            scaling: 1.0,
+            rise: 0.0,
+        }
+    }
+
+    fn move_line(&mut self, x: f32, y: f32) {
+        self.line = Matrix::translate(x, y).then(self.line);
+        self.text = self.line;
+    }
+
+    /// Text rendering matrix at the current position
+    fn rendering(&self) -> Matrix {
+        Matrix([
+            self.size * self.scaling,
+            0.0,
+            0.0,
+            self.size,
+            0.0,
+            self.rise,
+        ])
+        .then(self.text)
+        .then(self.ctm)
+    }
+
+    /// Move the text position along the baseline, in text space units
+    fn advance(&mut self, tx: f32) {
+        self.text = Matrix::translate(tx * self.scaling, 0.0).then(self.text);
+    }
+
+    /// Record `text` as drawn here and move past it
+    fn draw(&mut self, text: &str, spans: &mut Vec<TextSpan>) {
+        let rendering = self.rendering();
+        let (x, y) = rendering.origin();
+        let [_, _, c, d, _, _] = rendering.0;
+        let size = c.hypot(d);
+
+        for ch in text.chars() {
+            let spacing = if ch == ' ' { self.word_spacing } else { 0.0 };
+            self.advance(CHAR_WIDTH * self.size + self.char_spacing + spacing);
+        }
+        let (end_x, _) = self.rendering().origin();
+
+        if !text.trim().is_empty() && size > 0.0 {
+            spans.push(TextSpan {
+                x: x.min(end_x),
+                end_x: x.max(end_x),
+                y,
+                size,
+                text: text.to_string(),
+            });
+        }
+    }
+}
+
+/// Positioned strings of a page
+fn page_spans(file: &PdfFile, index: u32) -> Result<Vec<TextSpan>, String> {
+    let page = file
+        .get_page(index)
+        .map_err(|e| format!("Failed to read page {}: {}", index, e))?;
+    let Some(content) = &page.contents else {
+        return Ok(Vec::new());
+    };
+    let ops = content
+        .operations(&file.resolver())
+        .map_err(|e| format!("Failed to parse page {}: {}", index, e))?;
+
+    let mut state = TextState::new();
+    let mut spans = Vec::new();
+    for op in ops {
+        match op {
+            Op::Save => state.saved.push(state.ctm),
+            Op::Restore => {
+                if let Some(ctm) = state.saved.pop() {
+                    state.ctm = ctm;
+                }
+            }
+            Op::Transform { matrix } => state.ctm = Matrix::from(matrix).then(state.ctm),
+            Op::BeginText => {
+                state.text = Matrix::IDENTITY;
+                state.line = Matrix::IDENTITY;
+            }
+            Op::SetTextMatrix { matrix } => {
+                state.line = Matrix::from(matrix);
+                state.text = state.line;
+            }
+            Op::MoveTextPosition { translation } => state.move_line(translation.x, translation.y),
+            Op::TextNewline => state.move_line(0.0, -state.leading),
+            Op::Leading { leading } => state.leading = leading,
+            Op::TextFont { size, .. } => state.size = size,
+            Op::CharSpacing { char_space } => state.char_spacing = char_space,
+            Op::WordSpacing { word_space } => state.word_spacing = word_space,
+            Op::TextScaling { horiz_scale } => state.scaling = horiz_scale / 100.0,
+            Op::TextRise { rise } => state.rise = rise,
+            Op::TextDraw { text } => state.draw(&text.to_string_lossy(), &mut spans),
+            Op::TextDrawAdjusted { array } => {
+                // Adjustments are in thousandths of an em; wide ones become
+                // spaces when the line is joined
+                for item in array {
+                    match item {
+                        TextDrawAdjusted::Text(s) => state.draw(&s.to_string_lossy(), &mut spans),
+                        TextDrawAdjusted::Spacing(gap) => state.advance(-gap / 1000.0 * state.size),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(spans)
+}
+
+// ============================================================================
+// Layout
+// ============================================================================
+
+fn median(mut values: Vec<f32>) -> Option<f32> {
+    values.sort_by(f32::total_cmp);
+    values.get(values.len() / 2).copied()
+}
+
+/// Spans grouped by baseline, top to bottom, each sorted left to right
+fn baselines(mut spans: Vec<TextSpan>) -> Vec<Vec<TextSpan>> {
+    spans.sort_by(|a, b| b.y.total_cmp(&a.y).then(a.x.total_cmp(&b.x)));
+
+    let mut groups: Vec<Vec<TextSpan>> = Vec::new();
+    for span in spans {
+        match groups.last_mut() {
+            Some(group) if (group[0].y - span.y).abs() < 0.4 * group[0].size.min(span.size) => {
+                group.push(span)
+            }
+            _ => groups.push(vec![span]),
+        }
+    }
+    for group in &mut groups {
+        group.sort_by(|a, b| a.x.total_cmp(&b.x));
+    }
+    groups
+}
+
+/// Left edges of columns after the first. A column edge is an x where at
+/// least `MIN_COLUMN_LINES` baselines start a span while also having text
+/// further left.
+fn column_edges(groups: &[Vec<TextSpan>]) -> Vec<f32> {
+    let Some(size) = median(groups.iter().flatten().map(|s| s.size).collect()) else {
+        return Vec::new();
+    };
+    let tolerance = size * 0.3;
+
+    // Span starts with text at least an em to their left on the baseline
+    let mut starts: Vec<f32> = groups
+        .iter()
+        .flat_map(|group| {
+            let left = group[0].x;
+            group
+                .iter()
+                .filter(move |span| span.x > left + size)
+                .map(|span| span.x)
+        })
+        .collect();
+    starts.sort_by(f32::total_cmp);
+
+    let mut edges: Vec<f32> = Vec::new();
+    let mut i = 0;
+    while i < starts.len() {
+        let cluster = starts[i..]
+            .iter()
+            .take_while(|&&x| x - starts[i] <= tolerance)
+            .count();
+        if cluster >= MIN_COLUMN_LINES.max(groups.len() / 5) {
+            edges.push(starts[i]);
+        }
+        i += cluster;
+    }
+    edges
+}
+
+fn column_of(x: f32, edges: &[f32], tolerance: f32) -> u32 {
+    edges.iter().filter(|&&edge| x >= edge - tolerance).count() as u32 + 1
+}
+
+/// Join pieces of a line, adding spaces where the gap looks like one
+fn join_spans(spans: &[TextSpan], column: u32) -> Line {
+    let mut text = String::new();
+    let mut end_x = f32::MIN;
+    for span in spans {
+        let gap = span.x - end_x;
+        if !text.is_empty()
+            && gap > SPACE_GAP * span.size
+            && !text.ends_with(' ')
+            && !span.text.starts_with(' ')
+        {
+            text.push(' ');
+        }
+        text.push_str(&span.text);
+        end_x = end_x.max(span.end_x);
+    }
+
+    Line {
+        x: spans[0].x,
+        end_x,
+        y: spans[0].y,
+        size: spans.iter().map(|s| s.size).fold(0.0, f32::max),
+        text: text.trim().to_string(),
+        column,
+    }
+}
+
+/// Lines in reading order: bands separated by full-width lines, each
+/// read column by column
+fn reading_order(groups: Vec<Vec<TextSpan>>, edges: &[f32]) -> Vec<Line> {
+    let size = median(groups.iter().flatten().map(|s| s.size).collect()).unwrap_or(10.0);
+    let tolerance = size * 0.3;
+
+    let mut ordered = Vec::new();
+    let mut band: Vec<Line> = Vec::new();
+    let flush = |band: &mut Vec<Line>, ordered: &mut Vec<Line>| {
+        // Stable sort keeps each column top to bottom
+        band.sort_by_key(|line| line.column);
+        ordered.append(band);
+    };
+
+    for group in groups {
+        if edges.is_empty() {
+            band.push(join_spans(&group, 1));
+            continue;
+        }
+
+        // A single-column baseline whose text runs over a gutter spans them
+        let columns: Vec<u32> = group
+            .iter()
+            .map(|span| column_of(span.x, edges, tolerance))
+            .collect();
+        let end_x = group.iter().map(|span| span.end_x).fold(f32::MIN, f32::max);
+        let spans_gutter = columns.iter().all(|&c| c == columns[0])
+            && edges
+                .iter()
+                .any(|&edge| group[0].x < edge - size && end_x > edge + size);
+        if spans_gutter {
+            flush(&mut band, &mut ordered);
+            ordered.push(join_spans(&group, 0));
+            continue;
+        }
+
+        let mut start = 0;
+        for end in 1..=group.len() {
+            if end == group.len() || columns[end] != columns[start] {
+                band.push(join_spans(&group[start..end], columns[start]));
+                start = end;
+            }
+        }
+    }
+    flush(&mut band, &mut ordered);
+    ordered
+}
+
+/// Append a line to a paragraph, rejoining words hyphenated at the break
+fn append_line(text: &mut String, line: &str) {
+    let hyphenated = text.ends_with('-')
+        && text[..text.len() - 1]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_alphabetic)
+        && line.chars().next().is_some_and(char::is_lowercase);
+    if hyphenated {
+        text.pop();
+    } else if !text.is_empty() {
+        text.push(' ');
+    }
+    text.push_str(line);
+}
+
+/// Group ordered lines into paragraphs and headings
+fn blocks(lines: Vec<Line>, page: u32) -> Vec<ReflowBlock> {
+    let Some(body_size) = median(lines.iter().map(|l| l.size).collect()) else {
+        return Vec::new();
+    };
+    // Usual distance between consecutive baselines of a column
+    let spacing = median(
+        lines
+            .windows(2)
+            .filter(|w| w[0].column == w[1].column)
+            .map(|w| w[0].y - w[1].y)
+            .filter(|&gap| gap > 0.0 && gap < 3.0 * body_size)
+            .collect(),
+    )
+    .unwrap_or(body_size * 1.2);
+
+    let left_edge = |column: u32| {
+        lines
+            .iter()
+            .filter(|l| l.column == column)
+            .map(|l| l.x)
+            .fold(f32::MAX, f32::min)
+    };
+    let right_edge = |column: u32| {
+        lines
+            .iter()
+            .filter(|l| l.column == column)
+            .map(|l| l.end_x)
+            .fold(f32::MIN, f32::max)
+    };
+
+    let mut blocks: Vec<ReflowBlock> = Vec::new();
+    let mut previous: Option<&Line> = None;
+    for line in &lines {
+        let kind = if line.size >= body_size * HEADING_RATIO {
+            BlockKind::Heading
+        } else {
+            BlockKind::Paragraph
+        };
+
+        let continues = previous.is_some_and(|prev| {
+            let gap = prev.y - line.y;
+            let indented = line.x > left_edge(line.column) + 0.8 * line.size;
+            let ended_short = prev.end_x < right_edge(prev.column) - 4.0 * prev.size
+                && prev.text.ends_with(['.', '!', '?', ':', '"', '”']);
+            prev.column == line.column
+                && (prev.size - line.size).abs() <= 0.15 * body_size
+                && gap > 0.0
+                && gap <= spacing * 1.5
+                && !indented
+                && !ended_short
+        });
+
+        match blocks.last_mut() {
+            Some(block) if continues && block.kind == kind => {
+                append_line(&mut block.text, &line.text)
+            }
+            _ => blocks.push(ReflowBlock {
+                page,
+                kind,
+                text: line.text.clone(),
+                column: line.column,
+                font_size: line.size,
+            }),
+        }
+        previous = Some(line);
+    }
+    blocks
+}
+
+/// Reflowed blocks of one page from its spans
+fn reflow_page(spans: Vec<TextSpan>, page: u32) -> Vec<ReflowBlock> {
+    let groups = baselines(spans);
+    let edges = column_edges(&groups);
+    blocks(reading_order(groups, &edges), page)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Text of a PDF as paragraphs and headings in reading order, for
+/// reflowing. `page_range` is 0-based and inclusive; the whole document
+/// by default.
+#[tauri::command]
+pub async fn pdf_reflow_text(
+    path: String,
+    page_range: Option<(u32, u32)>,
+) -> Result<Vec<ReflowBlock>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let file = pdf_file::load(&path)?;
+        let pages = file.num_pages();
+        if pages == 0 {
+            return Ok(Vec::new());
+        }
+
+        let (first, last) = page_range.unwrap_or((0, pages - 1));
+        let last = last.min(pages - 1);
+        if first > last {
+            return Err(format!(
+                "Invalid page range {}-{} (document has {})",
+                first, last, pages
+            ));
+        }
+
+        let mut blocks = Vec::new();
+        for index in first..=last {
+            blocks.extend(reflow_page(page_spans(&file, index)?, index));
+        }
+        info!(
+            "Reflowed pages {}-{} of {} into {} blocks",
+            first,
+            last,
+            Path::new(&path).display(),
+            blocks.len()
+        );
+        Ok(blocks)
+    })
+    .await
+    .map_err(|e| format!("PDF reflow task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A span with the estimated width `draw` would give it
+    fn span(x: f32, y: f32, text: &str) -> TextSpan {
+        sized(x, y, 10.0, text)
+    }
+
+    fn sized(x: f32, y: f32, size: f32, text: &str) -> TextSpan {
+        TextSpan {
+            x,
+            end_x: x + text.chars().count() as f32 * CHAR_WIDTH * size,
+            y,
+            size,
+            text: text.to_string(),
+        }
+    }
+
+    fn texts(blocks: &[ReflowBlock]) -> Vec<&str> {
+        blocks.iter().map(|b| b.text.as_str()).collect()
+    }
+
+    #[test]
+    fn composes_matrices() {
+        let scaled = Matrix([2.0, 0.0, 0.0, 2.0, 0.0, 0.0]);
+        let moved = Matrix::translate(5.0, 7.0).then(scaled);
+        assert_eq!(moved.origin(), (10.0, 14.0));
+        assert_eq!(Matrix::IDENTITY.then(moved), moved);
+    }
+
+    #[test]
+    fn joins_lines_into_paragraphs() {
+        let spans = vec![
+            span(
+                72.0,
+                700.0,
+                "The first paragraph runs on for a while and then",
+            ),
+            span(72.0, 688.0, "continues here with a hyphen-"),
+            span(72.0, 676.0, "ated word at the end."),
+            span(84.0, 664.0, "An indented line starts another"),
+            span(72.0, 652.0, "paragraph."),
+        ];
+        let blocks = reflow_page(spans, 3);
+        assert_eq!(
+            texts(&blocks),
+            [
+                "The first paragraph runs on for a while and then continues here \
+                 with a hyphenated word at the end.",
+                "An indented line starts another paragraph.",
+            ]
+        );
+        assert!(blocks
+            .iter()
+            .all(|b| b.page == 3 && b.kind == BlockKind::Paragraph));
+    }
+
+    #[test]
+    fn reads_columns_in_order() {
+        // Drawn row by row, as some generators do
+        let mut spans = Vec::new();
+        for (row, (left, right)) in [
+            ("Left one", "Right one"),
+            ("left two", "right two"),
+            ("left three", "right three"),
+            ("left four.", "right four."),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let y = 700.0 - row as f32 * 12.0;
+            spans.push(span(72.0, y, left));
+            spans.push(span(320.0, y, right));
+        }
+        let blocks = reflow_page(spans, 0);
+
+        assert_eq!(
+            texts(&blocks),
+            [
+                "Left one left two left three left four.",
+                "Right one right two right three right four.",
+            ]
+        );
+        assert_eq!(blocks[1].column, 2);
+    }
+
+    #[test]
+    fn full_width_headings_split_bands() {
+        let mut spans = vec![sized(
+            72.0,
+            740.0,
+            18.0,
+            "A Title Across Both Columns Of Text",
+        )];
+        for row in 0..3 {
+            let y = 700.0 - row as f32 * 12.0;
+            spans.push(span(72.0, y, &format!("left {}", row)));
+            spans.push(span(320.0, y, &format!("right {}", row)));
+        }
+        let blocks = reflow_page(spans, 0);
+
+        assert_eq!(blocks[0].kind, BlockKind::Heading);
+        assert_eq!(blocks[0].column, 0);
+        assert_eq!(
+            texts(&blocks[1..]),
+            ["left 0 left 1 left 2", "right 0 right 1 right 2"]
+        );
+    }
+
+    #[test]
+    fn spaces_separately_drawn_words() {
+        let line = join_spans(
+            &[span(72.0, 700.0, "Hello"), span(100.0, 700.0, "world")],
+            1,
+        );
+        assert_eq!(line.text, "Hello world");
+        let kerned = join_spans(&[span(72.0, 700.0, "Wo"), span(82.0, 700.0, "rld")], 1);
+        assert_eq!(kerned.text, "World");
+    }
+}
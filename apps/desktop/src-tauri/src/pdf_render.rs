@@ -0,0 +1,202 @@
+// Read Master Desktop - PDF Page Rendering
+//
+// Page images for scanned PDFs, recolored for the reader theme. Dimming the
+// webview leaves a scan as a grey page with grey text; recoloring the pixels
+// gives real dark and sepia pages instead.
+//
+// Recoloring works on luminance only: each pixel's lightness is mapped onto
+// the theme's ink-to-paper ramp and its color difference from grey is added
+// back. Black text on white paper becomes light text on a dark page while a
+// red photo stays red, rather than turning cyan as a plain inversion would.
+//
+// Pages that aren't a single scan come back as `None`; the frontend draws
+// those itself with the theme's colors.
+
+use image::{DynamicImage, ImageFormat, RgbImage};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::images;
+use crate::pdf::PdfHandles;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PdfColorMode {
+    /// The scan as stored
+    #[default]
+    Normal,
+    /// White on black
+    Invert,
+    /// Brown ink on cream paper
+    Sepia,
+    /// Pale blue on navy, easier on the eyes than pure black
+    NightBlue,
+}
+
+/// Colors a mode maps lightness onto
+struct Ramp {
+    /// Color of luminance 0 after `invert`
+    dark: [f32; 3],
+    /// Color of luminance 255 after `invert`
+    light: [f32; 3],
+    /// Whether dark pixels become light ones
+    invert: bool,
+}
+
+impl PdfColorMode {
+    fn ramp(self) -> Option<Ramp> {
+        match self {
+            Self::Normal => None,
+            Self::Invert => Some(Ramp {
+                dark: [0.0, 0.0, 0.0],
+                light: [255.0, 255.0, 255.0],
+                invert: true,
+            }),
+            Self::Sepia => Some(Ramp {
+                dark: [67.0, 52.0, 34.0],
+                light: [244.0, 236.0, 216.0],
+                invert: false,
+            }),
+            Self::NightBlue => Some(Ramp {
+                dark: [16.0, 24.0, 40.0],
+                light: [200.0, 212.0, 230.0],
+                invert: true,
+            }),
+        }
+    }
+}
+
+// ============================================================================
+// Recoloring
+// ============================================================================
+
+/// Rec. 601 luma
+fn luma([r, g, b]: [f32; 3]) -> f32 {
+    0.299 * r + 0.587 * g + 0.114 * b
+}
+
+fn recolor_pixel(pixel: [u8; 3], ramp: &Ramp) -> [u8; 3] {
+    let rgb = pixel.map(f32::from);
+    let y = luma(rgb);
+    let t = if ramp.invert { 255.0 - y } else { y } / 255.0;
+
+    std::array::from_fn(|i| {
+        let base = ramp.dark[i] + (ramp.light[i] - ramp.dark[i]) * t;
+        (base + rgb[i] - y).round().clamp(0.0, 255.0) as u8
+    })
+}
+
+/// Recolor an image for `mode`
+pub fn recolor(image: &DynamicImage, mode: PdfColorMode) -> DynamicImage {
+    let Some(ramp) = mode.ramp() else {
+        return image.clone();
+    };
+
+    let mut rgb: RgbImage = image.to_rgb8();
+    // Scans are mostly a few shades of grey; map each shade once
+    let mut grey = [[0u8; 3]; 256];
+    for (level, out) in grey.iter_mut().enumerate() {
+        *out = recolor_pixel([level as u8; 3], &ramp);
+    }
+
+    for pixel in rgb.pixels_mut() {
+        let [r, g, b] = pixel.0;
+        pixel.0 = if r == g && g == b {
+            grey[usize::from(r)]
+        } else {
+            recolor_pixel(pixel.0, &ramp)
+        };
+    }
+    DynamicImage::from(rgb)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Image of one page (0-based) of an open PDF, recolored for `color_mode`,
+/// or `None` when the page isn't a scan. `Normal` returns the scan as
+/// stored (JPEG or PNG); the other modes return PNG.
+#[tauri::command]
+pub async fn render_pdf_page(
+    handles: State<'_, PdfHandles>,
+    handle: u32,
+    page: u32,
+    color_mode: Option<PdfColorMode>,
+) -> Result<Option<Vec<u8>>, String> {
+    let file = handles.get(handle)?;
+    if page >= file.num_pages() {
+        return Err(format!(
+            "Page {} out of range (document has {})",
+            page,
+            file.num_pages()
+        ));
+    }
+    let mode = color_mode.unwrap_or_default();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let Some(scan) = images::pdf_page_scan(&file, page)? else {
+            return Ok(None);
+        };
+        if mode == PdfColorMode::Normal {
+            return Ok(Some(scan));
+        }
+
+        let image = image::load_from_memory(&scan)
+            .map_err(|e| format!("Failed to decode page {}: {}", page, e))?;
+        images::encode(&recolor(&image, mode), ImageFormat::Png).map(Some)
+    })
+    .await
+    .map_err(|e| format!("PDF render task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recolored(pixel: [u8; 3], mode: PdfColorMode) -> [u8; 3] {
+        let image = DynamicImage::from(RgbImage::from_pixel(1, 1, image::Rgb(pixel)));
+        recolor(&image, mode).to_rgb8().get_pixel(0, 0).0
+    }
+
+    #[test]
+    fn inverts_paper_and_ink() {
+        assert_eq!(recolored([255, 255, 255], PdfColorMode::Invert), [0, 0, 0]);
+        assert_eq!(recolored([0, 0, 0], PdfColorMode::Invert), [255, 255, 255]);
+        assert_eq!(
+            recolored([255, 255, 255], PdfColorMode::NightBlue),
+            [16, 24, 40]
+        );
+        assert_eq!(
+            recolored([255, 255, 255], PdfColorMode::Sepia),
+            [244, 236, 216]
+        );
+        assert_eq!(recolored([0, 0, 0], PdfColorMode::Sepia), [67, 52, 34]);
+    }
+
+    #[test]
+    fn keeps_hue_when_inverting() {
+        // A dark red stays red, just lighter
+        let [r, g, b] = recolored([160, 20, 20], PdfColorMode::Invert);
+        assert!(r > g + 100 && r > b + 100);
+        assert!(g > 20);
+    }
+
+    #[test]
+    fn normal_leaves_pixels_alone() {
+        assert_eq!(
+            recolored([12, 200, 99], PdfColorMode::Normal),
+            [12, 200, 99]
+        );
+    }
+
+    #[test]
+    fn parses_modes() {
+        let mode: PdfColorMode = serde_json::from_str("\"nightBlue\"").unwrap();
+        assert_eq!(mode, PdfColorMode::NightBlue);
+    }
+}
@@ -0,0 +1,656 @@
+// Read Master Desktop - PDF Text Layer
+//
+// Character positions for selecting text and highlighting search matches
+// over rendered PDF pages. Rects are in points from the top left of the
+// page as displayed, the same space as the tiles in `pdf_tiles`, whose
+// worker this shares so each document is opened in PDFium only once.
+//
+// PDFium gives a box per character, in the order of the page's text.
+// Those are grouped into runs along a line, each with its direction:
+// left to right, right to left, or top to bottom for vertical CJK. A run
+// ends at a line break, a change of direction, or a wide gap such as
+// between columns. Character rects stay in text order in every direction,
+// so the UI can map text offsets to rects directly. A ligature's glyph is
+// shared by the characters it stands for, so its box is divided between
+// them along the run.
+
+use pdfium_render::bindgen::{FPDF_DOCUMENT, FPDF_PAGE, FPDF_TEXTPAGE, FS_RECTF};
+use pdfium_render::prelude::PdfiumLibraryBindings;
+use serde::Serialize;
+use tauri::State;
+
+use crate::pdf::PdfHandles;
+use crate::pdf_tiles::PdfTiles;
+
+/// Device units per point when converting to display space; PDFium maps to
+/// whole device units
+const PRECISION: f64 = 64.0;
+
+/// Gap along a line, in character heights, that ends a run
+const MAX_GAP: f32 = 3.0;
+
+/// Matches returned for one search at most
+const MAX_MATCHES: usize = 1000;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// A rectangle in points from the top left of the page as displayed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl TextRect {
+    fn right(&self) -> f32 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> f32 {
+        self.y + self.height
+    }
+
+    fn center(&self) -> (f32, f32) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    fn union(&self, other: &TextRect) -> TextRect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        TextRect {
+            x,
+            y,
+            width: self.right().max(other.right()) - x,
+            height: self.bottom().max(other.bottom()) - y,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextDirection {
+    Ltr,
+    Rtl,
+    /// Vertical text, top to bottom
+    Ttb,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextRun {
+    pub text: String,
+    pub direction: TextDirection,
+    pub bounds: TextRect,
+    /// One rect per character of `text`, in the same order
+    pub char_rects: Vec<TextRect>,
+    /// Index of the run's first character in the page's text
+    pub start: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMatch {
+    /// 0-based page
+    pub page: u32,
+    /// Character range in the page's text
+    pub start: usize,
+    pub length: usize,
+    /// One rect per run the match covers, in text order
+    pub rects: Vec<TextRect>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PageChar {
+    ch: char,
+    /// `None` for spaces and line breaks PDFium inserted itself
+    rect: Option<TextRect>,
+}
+
+/// Characters `start..end` of the page's text, laid out along `direction`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Span {
+    start: usize,
+    end: usize,
+    direction: TextDirection,
+}
+
+/// A run still being built, with the box of its last character
+struct OpenRun {
+    start: usize,
+    direction: Option<TextDirection>,
+    last: TextRect,
+}
+
+impl OpenRun {
+    fn close(self, end: usize) -> Span {
+        Span {
+            start: self.start,
+            end,
+            direction: self.direction.unwrap_or(TextDirection::Ltr),
+        }
+    }
+}
+
+// ============================================================================
+// Layout
+// ============================================================================
+
+fn is_line_break(ch: char) -> bool {
+    matches!(ch, '\r' | '\n' | '\u{2028}' | '\u{2029}')
+}
+
+/// Which way `next` lies from `prev`. Display space has y growing down.
+fn direction(prev: &TextRect, next: &TextRect) -> TextDirection {
+    let (px, py) = prev.center();
+    let (nx, ny) = next.center();
+    let (dx, dy) = (nx - px, ny - py);
+    if dy > dx.abs() {
+        TextDirection::Ttb
+    } else if dx < 0.0 {
+        TextDirection::Rtl
+    } else {
+        TextDirection::Ltr
+    }
+}
+
+/// Overlap of two ranges relative to the shorter one
+fn overlap(a: (f32, f32), b: (f32, f32)) -> f32 {
+    let shorter = (a.1 - a.0).min(b.1 - b.0);
+    if shorter <= 0.0 {
+        return 0.0;
+    }
+    (a.1.min(b.1) - a.0.max(b.0)).max(0.0) / shorter
+}
+
+/// Whether `next` continues the same line as `prev` in `direction`
+fn continues(prev: &TextRect, next: &TextRect, direction: TextDirection) -> bool {
+    let (across, gap, size) = match direction {
+        TextDirection::Ltr => (
+            overlap((prev.y, prev.bottom()), (next.y, next.bottom())),
+            next.x - prev.right(),
+            prev.height.max(next.height),
+        ),
+        TextDirection::Rtl => (
+            overlap((prev.y, prev.bottom()), (next.y, next.bottom())),
+            prev.x - next.right(),
+            prev.height.max(next.height),
+        ),
+        TextDirection::Ttb => (
+            overlap((prev.x, prev.right()), (next.x, next.right())),
+            next.y - prev.bottom(),
+            prev.width.max(next.width),
+        ),
+    };
+    across >= 0.5 && gap >= -size && gap <= MAX_GAP * size
+}
+
+/// Group characters into runs
+fn segment(chars: &[PageChar]) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut open: Option<OpenRun> = None;
+    for (i, c) in chars.iter().enumerate() {
+        let rect = match c.rect {
+            Some(rect) if !is_line_break(c.ch) => rect,
+            // Inserted word spaces stay in the run; anything else ends it
+            None if c.ch == ' ' && open.is_some() => continue,
+            _ => {
+                spans.extend(open.take().map(|run| run.close(i)));
+                continue;
+            }
+        };
+        if let Some(run) = &mut open {
+            if run.last == rect {
+                continue;
+            }
+            let next = direction(&run.last, &rect);
+            let same_way = run.direction.is_none() || run.direction == Some(next);
+            if same_way && continues(&run.last, &rect, next) {
+                run.direction = Some(next);
+                run.last = rect;
+                continue;
+            }
+            spans.extend(open.take().map(|run| run.close(i)));
+        }
+        open = Some(OpenRun {
+            start: i,
+            direction: None,
+            last: rect,
+        });
+    }
+    spans.extend(open.map(|run| run.close(chars.len())));
+
+    // Trailing inserted spaces belong to no run
+    for span in &mut spans {
+        while span.end > span.start && chars[span.end - 1].rect.is_none() {
+            span.end -= 1;
+        }
+    }
+    spans
+}
+
+/// Divide a box shared by several characters, as a ligature's is, along
+/// the run, and give inserted spaces an empty rect after the character
+/// before them
+fn fill_rects(chars: &mut [PageChar], spans: &[Span]) {
+    for span in spans {
+        let mut i = span.start;
+        while i < span.end {
+            let Some(rect) = chars[i].rect else {
+                let after = chars[i - 1].rect.map(|prev| match span.direction {
+                    TextDirection::Ltr => TextRect {
+                        x: prev.right(),
+                        width: 0.0,
+                        ..prev
+                    },
+                    TextDirection::Rtl => TextRect { width: 0.0, ..prev },
+                    TextDirection::Ttb => TextRect {
+                        y: prev.bottom(),
+                        height: 0.0,
+                        ..prev
+                    },
+                });
+                chars[i].rect = after;
+                i += 1;
+                continue;
+            };
+
+            let shared = chars[i..span.end]
+                .iter()
+                .take_while(|c| c.rect == Some(rect))
+                .count();
+            for k in 0..shared {
+                let part = |offset: usize| offset as f32 / shared as f32;
+                chars[i + k].rect = Some(match span.direction {
+                    TextDirection::Ltr => TextRect {
+                        x: rect.x + rect.width * part(k),
+                        width: rect.width / shared as f32,
+                        ..rect
+                    },
+                    TextDirection::Rtl => TextRect {
+                        x: rect.x + rect.width * part(shared - 1 - k),
+                        width: rect.width / shared as f32,
+                        ..rect
+                    },
+                    TextDirection::Ttb => TextRect {
+                        y: rect.y + rect.height * part(k),
+                        height: rect.height / shared as f32,
+                        ..rect
+                    },
+                });
+            }
+            i += shared;
+        }
+    }
+}
+
+/// Runs with their character rects, from characters in text order
+fn layout(chars: &mut [PageChar]) -> Vec<Span> {
+    let spans = segment(chars);
+    fill_rects(chars, &spans);
+    spans
+}
+
+fn runs(chars: &[PageChar], spans: &[Span]) -> Vec<TextRun> {
+    spans
+        .iter()
+        .filter_map(|span| {
+            let slice = &chars[span.start..span.end];
+            let char_rects: Vec<TextRect> = slice.iter().filter_map(|c| c.rect).collect();
+            let bounds = char_rects
+                .iter()
+                .skip(1)
+                .fold(*char_rects.first()?, |bounds, rect| bounds.union(rect));
+            Some(TextRun {
+                text: slice.iter().map(|c| c.ch).collect(),
+                direction: span.direction,
+                bounds,
+                char_rects,
+                start: span.start,
+            })
+        })
+        .collect()
+}
+
+/// One rect per run covered by characters `start..start + length`
+fn match_rects(chars: &[PageChar], spans: &[Span], start: usize, length: usize) -> Vec<TextRect> {
+    let end = start + length;
+    spans
+        .iter()
+        .filter_map(|span| {
+            let (from, to) = (span.start.max(start), span.end.min(end));
+            chars
+                .get(from..to)?
+                .iter()
+                .filter_map(|c| c.rect)
+                .reduce(|bounds, rect| bounds.union(&rect))
+        })
+        .collect()
+}
+
+// ============================================================================
+// PDFium
+// ============================================================================
+
+/// Page space to display space, with the page's rotation applied
+fn to_display(
+    bindings: &dyn PdfiumLibraryBindings,
+    page: FPDF_PAGE,
+    size: (f64, f64),
+    (x, y): (f64, f64),
+) -> (f32, f32) {
+    let (mut device_x, mut device_y): (i32, i32) = (0, 0);
+    bindings.FPDF_PageToDevice(
+        page,
+        0,
+        0,
+        (size.0 * PRECISION).round() as i32,
+        (size.1 * PRECISION).round() as i32,
+        0,
+        x,
+        y,
+        &mut device_x,
+        &mut device_y,
+    );
+    (
+        (f64::from(device_x) / PRECISION) as f32,
+        (f64::from(device_y) / PRECISION) as f32,
+    )
+}
+
+fn read_chars(
+    bindings: &dyn PdfiumLibraryBindings,
+    page: FPDF_PAGE,
+    text: FPDF_TEXTPAGE,
+) -> Vec<PageChar> {
+    let size = (
+        f64::from(bindings.FPDF_GetPageWidthF(page)),
+        f64::from(bindings.FPDF_GetPageHeightF(page)),
+    );
+    (0..bindings.FPDFText_CountChars(text).max(0))
+        .map(|index| {
+            let ch = char::from_u32(bindings.FPDFText_GetUnicode(text, index))
+                .unwrap_or(char::REPLACEMENT_CHARACTER);
+            let mut loose = FS_RECTF {
+                left: 0.0,
+                top: 0.0,
+                right: 0.0,
+                bottom: 0.0,
+            };
+            let boxed = bindings.FPDFText_IsGenerated(text, index) != 1
+                && bindings.FPDFText_GetLooseCharBox(text, index, &mut loose) != 0;
+            let rect = boxed.then(|| {
+                let corner =
+                    |x: f32, y: f32| to_display(bindings, page, size, (x.into(), y.into()));
+                let (x1, y1) = corner(loose.left, loose.top);
+                let (x2, y2) = corner(loose.right, loose.bottom);
+                TextRect {
+                    x: x1.min(x2),
+                    y: y1.min(y2),
+                    width: (x2 - x1).abs(),
+                    height: (y2 - y1).abs(),
+                }
+            });
+            PageChar { ch, rect }
+        })
+        .collect()
+}
+
+/// Character ranges matching `query` (UTF-16, nul-terminated), ignoring case
+fn find_all(
+    bindings: &dyn PdfiumLibraryBindings,
+    text: FPDF_TEXTPAGE,
+    query: &[u16],
+) -> Vec<(usize, usize)> {
+    let search = bindings.FPDFText_FindStart(text, query.as_ptr(), 0, 0);
+    if search.is_null() {
+        return Vec::new();
+    }
+    let mut found = Vec::new();
+    while bindings.FPDFText_FindNext(search) != 0 && found.len() < MAX_MATCHES {
+        let start = bindings.FPDFText_GetSchResultIndex(search);
+        let count = bindings.FPDFText_GetSchCount(search);
+        if start >= 0 && count > 0 {
+            found.push((start as usize, count as usize));
+        }
+    }
+    bindings.FPDFText_FindClose(search);
+    found
+}
+
+/// A page's characters, and matches of `query` if given
+fn read_page(
+    bindings: &dyn PdfiumLibraryBindings,
+    document: FPDF_DOCUMENT,
+    index: u32,
+    query: Option<&[u16]>,
+) -> Result<(Vec<PageChar>, Vec<(usize, usize)>), String> {
+    let page = bindings.FPDF_LoadPage(document, index as i32);
+    if page.is_null() {
+        return Err(format!("Failed to load page {}", index));
+    }
+    let text = bindings.FPDFText_LoadPage(page);
+    if text.is_null() {
+        bindings.FPDF_ClosePage(page);
+        return Err(format!("Failed to read the text of page {}", index));
+    }
+    let chars = read_chars(bindings, page, text);
+    let found = query.map_or_else(Vec::new, |query| find_all(bindings, text, query));
+    bindings.FPDFText_ClosePage(text);
+    bindings.FPDF_ClosePage(page);
+    Ok((chars, found))
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Text runs of a page (0-based) of an open PDF, with a rect for every
+/// character, where `session_id` is the handle from `mmap_pdf_open`
+#[tauri::command]
+pub async fn get_pdf_text_layer(
+    handles: State<'_, PdfHandles>,
+    tiles: State<'_, PdfTiles>,
+    session_id: u32,
+    page: u32,
+) -> Result<Vec<TextRun>, String> {
+    let pages = handles.get(session_id)?.num_pages();
+    if page >= pages {
+        return Err(format!(
+            "Page {} out of range (document has {})",
+            page, pages
+        ));
+    }
+    let path = handles.path(session_id)?;
+    tiles
+        .with_document(session_id, path, move |bindings, document| {
+            let (mut chars, _) = read_page(bindings, document, page, None)?;
+            let spans = layout(&mut chars);
+            Ok(runs(&chars, &spans))
+        })
+        .await
+}
+
+/// Case-insensitive matches of `query` throughout an open PDF, each with
+/// the rects to highlight. Pages are searched one job at a time, so tiles
+/// still render in between.
+#[tauri::command]
+pub async fn search_pdf(
+    handles: State<'_, PdfHandles>,
+    tiles: State<'_, PdfTiles>,
+    session_id: u32,
+    query: String,
+) -> Result<Vec<SearchMatch>, String> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Err("Search query is empty".to_string());
+    }
+    let needle: Vec<u16> = query.encode_utf16().chain(Some(0)).collect();
+    let pages = handles.get(session_id)?.num_pages();
+    let path = handles.path(session_id)?;
+
+    let mut matches = Vec::new();
+    for page in 0..pages {
+        let needle = needle.clone();
+        let found = tiles
+            .with_document(session_id, path.clone(), move |bindings, document| {
+                let (mut chars, found) = read_page(bindings, document, page, Some(&needle))?;
+                let spans = layout(&mut chars);
+                Ok(found
+                    .into_iter()
+                    .map(|(start, length)| SearchMatch {
+                        page,
+                        start,
+                        length,
+                        rects: match_rects(&chars, &spans, start, length),
+                    })
+                    .collect::<Vec<_>>())
+            })
+            .await?;
+        matches.extend(found);
+        if matches.len() >= MAX_MATCHES {
+            matches.truncate(MAX_MATCHES);
+            break;
+        }
+    }
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> TextRect {
+        TextRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Characters of `text` laid out from `origin`, one step apart
+    fn line(text: &str, origin: (f32, f32), step: (f32, f32)) -> Vec<PageChar> {
+        text.chars()
+            .enumerate()
+            .map(|(i, ch)| PageChar {
+                ch,
+                rect: Some(rect(
+                    origin.0 + step.0 * i as f32,
+                    origin.1 + step.1 * i as f32,
+                    8.0,
+                    10.0,
+                )),
+            })
+            .collect()
+    }
+
+    fn inserted(ch: char) -> PageChar {
+        PageChar { ch, rect: None }
+    }
+
+    fn texts(chars: &mut [PageChar]) -> Vec<(String, TextDirection)> {
+        let spans = layout(chars);
+        runs(chars, &spans)
+            .into_iter()
+            .map(|run| (run.text, run.direction))
+            .collect()
+    }
+
+    #[test]
+    fn splits_runs_at_line_breaks() {
+        let mut chars = line("Hello", (72.0, 100.0), (8.0, 0.0));
+        chars.push(inserted(' '));
+        chars.extend(line("world", (120.0, 100.0), (8.0, 0.0)));
+        chars.push(inserted('\n'));
+        chars.extend(line("Next", (72.0, 114.0), (8.0, 0.0)));
+
+        assert_eq!(
+            texts(&mut chars),
+            [
+                ("Hello world".to_string(), TextDirection::Ltr),
+                ("Next".to_string(), TextDirection::Ltr)
+            ]
+        );
+        // The inserted space sits at the end of the "o" before it
+        assert_eq!(chars[5].rect, Some(rect(112.0, 100.0, 0.0, 10.0)));
+    }
+
+    #[test]
+    fn splits_runs_between_columns() {
+        let mut chars = line("left", (72.0, 100.0), (8.0, 0.0));
+        chars.extend(line("right", (320.0, 100.0), (8.0, 0.0)));
+        let found: Vec<String> = texts(&mut chars).into_iter().map(|(t, _)| t).collect();
+        assert_eq!(found, ["left", "right"]);
+    }
+
+    #[test]
+    fn detects_right_to_left_and_vertical_runs() {
+        let mut rtl = line("שלום", (300.0, 100.0), (-8.0, 0.0));
+        assert_eq!(texts(&mut rtl), [("שלום".to_string(), TextDirection::Rtl)]);
+        // Rects stay in text order, right to left on the page
+        assert!(rtl[0].rect.unwrap().x > rtl[3].rect.unwrap().x);
+
+        let mut vertical = line("縦書き", (300.0, 100.0), (0.0, 11.0));
+        assert_eq!(
+            texts(&mut vertical),
+            [("縦書き".to_string(), TextDirection::Ttb)]
+        );
+    }
+
+    #[test]
+    fn divides_ligature_boxes() {
+        // "ﬁ" as a single glyph standing for "f" and "i"
+        let mut chars = line("o", (72.0, 100.0), (8.0, 0.0));
+        let glyph = rect(80.0, 100.0, 10.0, 10.0);
+        chars.push(PageChar {
+            ch: 'f',
+            rect: Some(glyph),
+        });
+        chars.push(PageChar {
+            ch: 'i',
+            rect: Some(glyph),
+        });
+        chars.extend(line("x", (90.0, 100.0), (8.0, 0.0)));
+
+        assert_eq!(texts(&mut chars).len(), 1);
+        assert_eq!(chars[1].rect, Some(rect(80.0, 100.0, 5.0, 10.0)));
+        assert_eq!(chars[2].rect, Some(rect(85.0, 100.0, 5.0, 10.0)));
+
+        let mut rtl = vec![
+            PageChar {
+                ch: 'ل',
+                rect: Some(glyph),
+            },
+            PageChar {
+                ch: 'ا',
+                rect: Some(glyph),
+            },
+        ];
+        rtl.extend(line("ب", (70.0, 100.0), (8.0, 0.0)));
+        layout(&mut rtl);
+        assert_eq!(rtl[0].rect, Some(rect(85.0, 100.0, 5.0, 10.0)));
+        assert_eq!(rtl[1].rect, Some(rect(80.0, 100.0, 5.0, 10.0)));
+    }
+
+    #[test]
+    fn match_rects_cover_each_line() {
+        let mut chars = line("find th", (72.0, 100.0), (8.0, 0.0));
+        chars.push(inserted('\n'));
+        chars.extend(line("is text", (72.0, 114.0), (8.0, 0.0)));
+        let spans = layout(&mut chars);
+
+        // "this" across the line break
+        let rects = match_rects(&chars, &spans, 5, 5);
+        assert_eq!(
+            rects,
+            [
+                rect(112.0, 100.0, 16.0, 10.0),
+                rect(72.0, 114.0, 16.0, 10.0)
+            ]
+        );
+    }
+}
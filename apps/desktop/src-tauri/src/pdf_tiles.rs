@@ -0,0 +1,638 @@
+// Read Master Desktop - PDF Tiles
+//
+// Rendered PDF pages in tiles, so zooming in never sends a whole page bitmap.
+
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use image::{DynamicImage, ImageFormat, RgbImage};
+use log::{info, warn};
+use lru::LruCache;
+use pdfium_render::bindgen::{FPDF_DOCUMENT, FS_MATRIX, FS_RECTF, FS_SIZEF};
+use pdfium_render::prelude::{Pdfium, PdfiumLibraryBindings};
+use serde::{Deserialize, Serialize};
+use tauri::ipc::Response;
+use tauri::State;
+
+use crate::images;
+use crate::pdf::PdfHandles;
+
+/// Rendered tiles kept in memory, by document, page, zoom step, and tile
+const CACHE_CAPACITY: usize = 256;
+
+/// Documents kept open in PDFium
+const OPEN_DOCUMENTS: usize = 4;
+
+/// Speculative tiles waiting at most; older ones are dropped
+const MAX_SPECULATIVE: usize = 32;
+
+pub const MIN_ZOOM: f64 = 0.25;
+pub const MAX_ZOOM: f64 = 8.0;
+
+/// Largest tile side in pixels
+const MAX_TILE_PIXELS: u32 = 2048;
+
+/// Render annotations, with RGBA rather than BGRA byte order
+const RENDER_FLAGS: i32 = 0x01 | 0x10;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Part of a page, in points (1/72 inch) from its top left corner as
+/// displayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TileRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A page's size in points, with its rotation applied
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageSize {
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TileKey {
+    handle: u32,
+    page: u32,
+    /// Quarter octaves from zoom 1
+    step: i32,
+    rect: TileRect,
+}
+
+enum Job {
+    Tile {
+        key: TileKey,
+        path: String,
+        /// Where to send the tile; `None` for speculative renders
+        reply: Option<Sender<Result<Arc<Vec<u8>>, String>>>,
+    },
+    Sizes {
+        handle: u32,
+        path: String,
+        reply: Sender<Result<Vec<PageSize>, String>>,
+    },
+    Document {
+        handle: u32,
+        path: String,
+        task: DocumentTask,
+    },
+}
+
+/// Work on an open document, given PDFium or the reason it isn't available
+type DocumentTask =
+    Box<dyn FnOnce(Result<(&dyn PdfiumLibraryBindings, FPDF_DOCUMENT), String>) + Send>;
+
+type TileCache = Arc<Mutex<LruCache<TileKey, Arc<Vec<u8>>>>>;
+
+/// Managed tile cache and the channel to the render worker. PDFium isn't
+/// thread-safe, so one worker thread owns the library and the open
+/// documents and takes jobs in order.
+pub struct PdfTiles {
+    cache: TileCache,
+    worker: Mutex<Option<Sender<Job>>>,
+}
+
+impl Default for PdfTiles {
+    fn default() -> Self {
+        Self {
+            cache: Arc::new(Mutex::new(LruCache::new(
+                NonZeroUsize::new(CACHE_CAPACITY).expect("cache capacity is non-zero"),
+            ))),
+            worker: Mutex::new(None),
+        }
+    }
+}
+
+impl PdfTiles {
+    fn cached(&self, key: &TileKey) -> Option<Arc<Vec<u8>>> {
+        self.cache.lock().ok()?.get(key).cloned()
+    }
+
+    /// Queue a job, starting the worker on first use
+    fn send(&self, job: Job) -> Result<(), String> {
+        let mut worker = self
+            .worker
+            .lock()
+            .map_err(|_| "PDF tile worker lock poisoned".to_string())?;
+        if worker.is_none() {
+            let (sender, receiver) = mpsc::channel();
+            let cache = Arc::clone(&self.cache);
+            thread::Builder::new()
+                .name("pdf-tiles".into())
+                .spawn(move || run_worker(receiver, cache))
+                .map_err(|e| format!("Failed to start PDF renderer: {}", e))?;
+            *worker = Some(sender);
+        }
+        worker
+            .as_ref()
+            .expect("worker was just started")
+            .send(job)
+            .map_err(|_| "PDF renderer stopped".to_string())
+    }
+
+    /// Run `task` on the render worker with the document open, after any
+    /// visible tiles already waiting. Other PDFium work, like the text layer
+    /// in `pdf_text`, goes through here so a document is only opened once.
+    pub async fn with_document<T: Send + 'static>(
+        &self,
+        handle: u32,
+        path: String,
+        task: impl FnOnce(&dyn PdfiumLibraryBindings, FPDF_DOCUMENT) -> Result<T, String>
+            + Send
+            + 'static,
+    ) -> Result<T, String> {
+        let (reply, answer) = mpsc::channel();
+        self.send(Job::Document {
+            handle,
+            path,
+            task: Box::new(move |opened| {
+                let _ =
+                    reply.send(opened.and_then(|(bindings, document)| task(bindings, document)));
+            }),
+        })?;
+
+        tauri::async_runtime::spawn_blocking(move || answer.recv())
+            .await
+            .map_err(|e| format!("PDF task failed: {}", e))?
+            .map_err(|_| "PDF renderer stopped".to_string())?
+    }
+}
+
+// ============================================================================
+// Zoom
+// ============================================================================
+
+/// Quarter-octave step nearest `zoom`. Tiles are rendered at their step's
+/// zoom, so small zoom changes reuse cached tiles and the webview scales
+/// them the rest of the way.
+fn zoom_step(zoom: f64) -> i32 {
+    (zoom.clamp(MIN_ZOOM, MAX_ZOOM).log2() * 4.0).round() as i32
+}
+
+fn step_zoom(step: i32) -> f64 {
+    2f64.powf(f64::from(step) / 4.0)
+}
+
+/// Pixel size of a tile rendered at `zoom`
+fn tile_pixels(rect: &TileRect, zoom: f64) -> Result<(u32, u32), String> {
+    let size = |points: u32| (f64::from(points) * zoom).ceil() as u32;
+    let (width, height) = (size(rect.width), size(rect.height));
+    if width == 0 || height == 0 {
+        return Err("Tile is empty".to_string());
+    }
+    if width > MAX_TILE_PIXELS || height > MAX_TILE_PIXELS {
+        return Err(format!(
+            "Tile of {}x{} pixels is too large (at most {} a side)",
+            width, height, MAX_TILE_PIXELS
+        ));
+    }
+    Ok((width, height))
+}
+
+/// Page-to-tile transform: PDFium first maps the page to points from its
+/// displayed top left, then this scales by `zoom` and moves the tile's
+/// corner to the origin
+fn tile_matrix(rect: &TileRect, zoom: f64) -> FS_MATRIX {
+    FS_MATRIX {
+        a: zoom as f32,
+        b: 0.0,
+        c: 0.0,
+        d: zoom as f32,
+        e: (-f64::from(rect.x) * zoom) as f32,
+        f: (-f64::from(rect.y) * zoom) as f32,
+    }
+}
+
+// ============================================================================
+// Worker
+// ============================================================================
+
+/// PDFium from next to the executable, else the system's
+fn bind_pdfium() -> Result<Pdfium, String> {
+    let bundled = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(PathBuf::from))
+        .map(|dir| Pdfium::pdfium_platform_library_name_at_path(&dir))
+        .ok_or_else(|| "No executable directory".to_string())
+        .and_then(|path| Pdfium::bind_to_library(path).map_err(|e| e.to_string()));
+
+    bundled
+        .or_else(|_| Pdfium::bind_to_system_library().map_err(|e| e.to_string()))
+        .map(Pdfium::new)
+        .map_err(|e| format!("PDF rendering needs the PDFium library: {}", e))
+}
+
+/// Documents open in PDFium, by handle
+struct Documents<'a> {
+    bindings: &'a dyn PdfiumLibraryBindings,
+    open: LruCache<u32, FPDF_DOCUMENT>,
+}
+
+impl<'a> Documents<'a> {
+    fn new(bindings: &'a dyn PdfiumLibraryBindings) -> Self {
+        Self {
+            bindings,
+            open: LruCache::new(NonZeroUsize::new(OPEN_DOCUMENTS).expect("non-zero")),
+        }
+    }
+
+    fn get(&mut self, handle: u32, path: &str) -> Result<FPDF_DOCUMENT, String> {
+        if let Some(document) = self.open.get(&handle) {
+            return Ok(*document);
+        }
+        let document = self.bindings.FPDF_LoadDocument(path, None);
+        if document.is_null() {
+            return Err(format!(
+                "PDFium failed to open {} (error {})",
+                path,
+                self.bindings.FPDF_GetLastError()
+            ));
+        }
+        if let Some((_, evicted)) = self.open.push(handle, document) {
+            self.bindings.FPDF_CloseDocument(evicted);
+        }
+        Ok(document)
+    }
+}
+
+impl Drop for Documents<'_> {
+    fn drop(&mut self) {
+        for (_, document) in self.open.iter() {
+            self.bindings.FPDF_CloseDocument(*document);
+        }
+    }
+}
+
+fn page_sizes(
+    bindings: &dyn PdfiumLibraryBindings,
+    document: FPDF_DOCUMENT,
+) -> Result<Vec<PageSize>, String> {
+    let count = bindings.FPDF_GetPageCount(document);
+    (0..count)
+        .map(|index| {
+            let mut size = FS_SIZEF {
+                width: 0.0,
+                height: 0.0,
+            };
+            if bindings.FPDF_GetPageSizeByIndexF(document, index, &mut size) == 0 {
+                return Err(format!("Failed to read the size of page {}", index));
+            }
+            Ok(PageSize {
+                width: size.width,
+                height: size.height,
+            })
+        })
+        .collect()
+}
+
+/// Render one tile as PNG. PDFium applies the page's /Rotate itself, and
+/// the page is drawn onto white paper, so transparent pages come out as
+/// they print.
+fn render_tile(
+    bindings: &dyn PdfiumLibraryBindings,
+    document: FPDF_DOCUMENT,
+    key: &TileKey,
+) -> Result<Vec<u8>, String> {
+    let zoom = step_zoom(key.step);
+    let (width, height) = tile_pixels(&key.rect, zoom)?;
+
+    let page = bindings.FPDF_LoadPage(document, key.page as i32);
+    if page.is_null() {
+        return Err(format!("Failed to load page {}", key.page));
+    }
+    // No alpha channel: the page is drawn over white paper
+    let bitmap = bindings.FPDFBitmap_Create(width as i32, height as i32, 0);
+    if bitmap.is_null() {
+        bindings.FPDF_ClosePage(page);
+        return Err(format!("Failed to allocate a {}x{} tile", width, height));
+    }
+    bindings.FPDFBitmap_FillRect(bitmap, 0, 0, width as i32, height as i32, 0xFFFF_FFFF);
+
+    let clip = FS_RECTF {
+        left: 0.0,
+        top: 0.0,
+        right: width as f32,
+        bottom: height as f32,
+    };
+    bindings.FPDF_RenderPageBitmapWithMatrix(
+        bitmap,
+        page,
+        &tile_matrix(&key.rect, zoom),
+        &clip,
+        RENDER_FLAGS,
+    );
+
+    let stride = bindings.FPDFBitmap_GetStride(bitmap) as usize;
+    let buffer = bindings.FPDFBitmap_GetBuffer(bitmap) as *const u8;
+    // SAFETY: PDFium owns a buffer of `stride * height` bytes for the
+    // bitmap, valid until it is destroyed below
+    let pixels = unsafe { std::slice::from_raw_parts(buffer, stride * height as usize) };
+    let mut rgb = Vec::with_capacity(width as usize * height as usize * 3);
+    for row in pixels.chunks_exact(stride) {
+        for pixel in row[..width as usize * 4].chunks_exact(4) {
+            rgb.extend_from_slice(&pixel[..3]);
+        }
+    }
+    bindings.FPDFBitmap_Destroy(bitmap);
+    bindings.FPDF_ClosePage(page);
+
+    let image = RgbImage::from_raw(width, height, rgb)
+        .ok_or_else(|| "Tile buffer has the wrong size".to_string())?;
+    images::encode(&DynamicImage::from(image), ImageFormat::Png)
+}
+
+/// Next job: anything waiting for an answer before speculative tiles,
+/// which only run when nothing visible is waiting
+fn next_job(
+    receiver: &Receiver<Job>,
+    urgent: &mut VecDeque<Job>,
+    speculative: &mut VecDeque<Job>,
+) -> Option<Job> {
+    let waiting = if urgent.is_empty() && speculative.is_empty() {
+        Some(receiver.recv().ok()?)
+    } else {
+        None
+    };
+    for job in waiting.into_iter().chain(receiver.try_iter()) {
+        match &job {
+            Job::Tile { reply: None, .. } => speculative.push_back(job),
+            _ => urgent.push_back(job),
+        }
+    }
+    while speculative.len() > MAX_SPECULATIVE {
+        speculative.pop_front();
+    }
+    urgent.pop_front().or_else(|| speculative.pop_back())
+}
+
+fn run_worker(receiver: Receiver<Job>, cache: TileCache) {
+    let pdfium = match bind_pdfium() {
+        Ok(pdfium) => pdfium,
+        Err(e) => {
+            warn!("{}", e);
+            for job in receiver {
+                match job {
+                    Job::Tile {
+                        reply: Some(reply), ..
+                    } => {
+                        let _ = reply.send(Err(e.clone()));
+                    }
+                    Job::Sizes { reply, .. } => {
+                        let _ = reply.send(Err(e.clone()));
+                    }
+                    Job::Document { task, .. } => task(Err(e.clone())),
+                    Job::Tile { reply: None, .. } => {}
+                }
+            }
+            return;
+        }
+    };
+    info!("PDF tile renderer started");
+
+    let bindings = pdfium.bindings();
+    let mut documents = Documents::new(bindings);
+    let mut urgent = VecDeque::new();
+    let mut speculative = VecDeque::new();
+
+    while let Some(job) = next_job(&receiver, &mut urgent, &mut speculative) {
+        match job {
+            Job::Sizes {
+                handle,
+                path,
+                reply,
+            } => {
+                let sizes = documents
+                    .get(handle, &path)
+                    .and_then(|document| page_sizes(bindings, document));
+                let _ = reply.send(sizes);
+            }
+            Job::Document { handle, path, task } => {
+                task(
+                    documents
+                        .get(handle, &path)
+                        .map(|document| (bindings, document)),
+                );
+            }
+            Job::Tile { key, path, reply } => {
+                let cached = cache.lock().ok().and_then(|mut c| c.get(&key).cloned());
+                let result = match cached {
+                    Some(tile) => Ok(tile),
+                    None => documents
+                        .get(key.handle, &path)
+                        .and_then(|document| render_tile(bindings, document, &key))
+                        .map(|png| {
+                            let tile = Arc::new(png);
+                            if let Ok(mut cache) = cache.lock() {
+                                cache.put(key, Arc::clone(&tile));
+                            }
+                            tile
+                        }),
+                };
+                match reply {
+                    Some(reply) => {
+                        let _ = reply.send(result);
+                    }
+                    None => {
+                        if let Err(e) = result {
+                            warn!("Failed to pre-render page {}: {}", key.page, e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Size of every page of an open PDF, in points with rotation applied, so
+/// the reader can lay out pages before rendering any
+#[tauri::command]
+pub async fn get_pdf_page_sizes(
+    handles: State<'_, PdfHandles>,
+    tiles: State<'_, PdfTiles>,
+    session_id: u32,
+) -> Result<Vec<PageSize>, String> {
+    let path = handles.path(session_id)?;
+    let (reply, answer) = mpsc::channel();
+    tiles.send(Job::Sizes {
+        handle: session_id,
+        path,
+        reply,
+    })?;
+
+    tauri::async_runtime::spawn_blocking(move || answer.recv())
+        .await
+        .map_err(|e| format!("PDF size task failed: {}", e))?
+        .map_err(|_| "PDF renderer stopped".to_string())?
+}
+
+/// PNG of part of a page (0-based) of an open PDF at `zoom`, where
+/// `session_id` is the handle from `mmap_pdf_open`. The tile is rendered at
+/// `zoom` rounded to a quarter octave, for the webview to scale to fit the
+/// rect. The same tile of the neighbouring pages is rendered ahead.
+#[tauri::command]
+pub async fn render_pdf_tile(
+    handles: State<'_, PdfHandles>,
+    tiles: State<'_, PdfTiles>,
+    session_id: u32,
+    page: u32,
+    zoom: f64,
+    tile_rect: TileRect,
+) -> Result<Response, String> {
+    let pages = handles.get(session_id)?.num_pages();
+    if page >= pages {
+        return Err(format!(
+            "Page {} out of range (document has {})",
+            page, pages
+        ));
+    }
+    if !zoom.is_finite() || zoom <= 0.0 {
+        return Err(format!("Invalid zoom: {}", zoom));
+    }
+    let step = zoom_step(zoom);
+    tile_pixels(&tile_rect, step_zoom(step))?;
+    let path = handles.path(session_id)?;
+    let key = TileKey {
+        handle: session_id,
+        page,
+        step,
+        rect: tile_rect,
+    };
+
+    let tile = match tiles.cached(&key) {
+        Some(tile) => tile,
+        None => {
+            let (reply, answer) = mpsc::channel();
+            tiles.send(Job::Tile {
+                key,
+                path: path.clone(),
+                reply: Some(reply),
+            })?;
+            tauri::async_runtime::spawn_blocking(move || answer.recv())
+                .await
+                .map_err(|e| format!("PDF tile task failed: {}", e))?
+                .map_err(|_| "PDF renderer stopped".to_string())??
+        }
+    };
+
+    let neighbours = [page.checked_sub(1), Some(page + 1).filter(|p| *p < pages)];
+    for neighbour in neighbours.into_iter().flatten() {
+        let key = TileKey {
+            page: neighbour,
+            ..key
+        };
+        if tiles.cached(&key).is_none() {
+            tiles.send(Job::Tile {
+                key,
+                path: path.clone(),
+                reply: None,
+            })?;
+        }
+    }
+
+    Ok(Response::new(tile.as_ref().clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zoom_rounds_to_quarter_octaves() {
+        assert_eq!(zoom_step(1.0), 0);
+        assert_eq!(zoom_step(2.0), 4);
+        assert_eq!(zoom_step(1.05), 0);
+        assert_eq!(zoom_step(1.15), 1);
+        assert_eq!(zoom_step(100.0), zoom_step(MAX_ZOOM));
+        assert!((step_zoom(4) - 2.0).abs() < 1e-9);
+        assert!((step_zoom(-8) - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sizes_tiles_and_refuses_huge_ones() {
+        let rect = TileRect {
+            x: 0,
+            y: 0,
+            width: 256,
+            height: 100,
+        };
+        assert_eq!(tile_pixels(&rect, 1.5), Ok((384, 150)));
+        assert!(tile_pixels(&rect, 8.0).is_ok());
+        assert!(tile_pixels(&rect, 9.0).is_err());
+        let empty = TileRect { width: 0, ..rect };
+        assert!(tile_pixels(&empty, 1.0).is_err());
+    }
+
+    #[test]
+    fn matrix_moves_the_tile_to_the_origin() {
+        let rect = TileRect {
+            x: 100,
+            y: 50,
+            width: 256,
+            height: 256,
+        };
+        let m = tile_matrix(&rect, 2.0);
+        assert_eq!((m.a, m.d, m.e, m.f), (2.0, 2.0, -200.0, -100.0));
+    }
+
+    #[test]
+    fn visible_tiles_jump_the_queue() {
+        let (sender, receiver) = mpsc::channel();
+        let key = |page| TileKey {
+            handle: 1,
+            page,
+            step: 0,
+            rect: TileRect {
+                x: 0,
+                y: 0,
+                width: 10,
+                height: 10,
+            },
+        };
+        let (reply, _answer) = mpsc::channel();
+        for page in [1, 2] {
+            sender
+                .send(Job::Tile {
+                    key: key(page),
+                    path: String::new(),
+                    reply: None,
+                })
+                .unwrap();
+        }
+        sender
+            .send(Job::Tile {
+                key: key(3),
+                path: String::new(),
+                reply: Some(reply),
+            })
+            .unwrap();
+
+        let mut urgent = VecDeque::new();
+        let mut speculative = VecDeque::new();
+        let order: Vec<u32> = (0..3)
+            .map(
+                |_| match next_job(&receiver, &mut urgent, &mut speculative) {
+                    Some(Job::Tile { key, .. }) => key.page,
+                    _ => panic!("expected a tile"),
+                },
+            )
+            .collect();
+        // The awaited tile first, then the newest speculative ones
+        assert_eq!(order, [3, 2, 1]);
+    }
+}
@@ -0,0 +1,319 @@
+// Read Master Desktop - Store Persistence
+//
+// Debounced saving for the JSON stores. Writers update the in-memory store
+// and mark it dirty; a background thread writes dirty stores to disk at most
+// once per debounce window. The window starts at the first unsaved change
+// and is not extended by later ones, so a crash loses at most `DEBOUNCE`
+// worth of changes even while a slider is being dragged.
+//
+// Stores are also flushed explicitly through `flush_store`, and on window
+// close, quit, and system suspend.
+
+use std::collections::BTreeSet;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::power::{self, PowerEvent};
+use crate::profiles::ProfileStoreExt;
+
+/// Delay between the first unsaved change and the write to disk
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+// ============================================================================
+// Debouncer
+// ============================================================================
+
+type FlushFn = Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+#[derive(Default)]
+struct Pending {
+    names: BTreeSet<String>,
+    deadline: Option<Instant>,
+}
+
+struct Inner {
+    pending: Mutex<Pending>,
+    /// Held while stores are being written, so `flush_all` can't return
+    /// in the middle of a background write
+    writing: Mutex<()>,
+    wake: Condvar,
+    delay: Duration,
+    flush: FlushFn,
+}
+
+/// Collects dirty store names and flushes them after a delay
+#[derive(Clone)]
+pub struct Debouncer {
+    inner: Arc<Inner>,
+}
+
+impl Debouncer {
+    /// Start the flush thread. `flush` writes one named store to disk.
+    pub fn new(
+        delay: Duration,
+        flush: impl Fn(&str) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        let inner = Arc::new(Inner {
+            pending: Mutex::new(Pending::default()),
+            writing: Mutex::new(()),
+            wake: Condvar::new(),
+            delay,
+            flush: Box::new(flush),
+        });
+
+        let worker = Arc::clone(&inner);
+        thread::Builder::new()
+            .name("store-flush".into())
+            .spawn(move || run(&worker))
+            .expect("failed to spawn store flush thread");
+
+        Self { inner }
+    }
+
+    /// Schedule `name` to be written at the end of the current window
+    pub fn mark_dirty(&self, name: &str) {
+        let Ok(mut pending) = self.inner.pending.lock() else {
+            return;
+        };
+
+        pending.names.insert(name.to_string());
+        if pending.deadline.is_none() {
+            pending.deadline = Some(Instant::now() + self.inner.delay);
+            self.inner.wake.notify_one();
+        }
+    }
+
+    /// Write every dirty store now, after any write already under way
+    pub fn flush_all(&self) -> Result<(), String> {
+        let Ok(_writing) = self.inner.writing.lock() else {
+            return Err("Store flush lock poisoned".to_string());
+        };
+        let names = match self.inner.pending.lock() {
+            Ok(mut pending) => take(&mut pending),
+            Err(_) => return Err("Store flush lock poisoned".to_string()),
+        };
+
+        flush_names(&self.inner, names)
+    }
+}
+
+fn take(pending: &mut Pending) -> BTreeSet<String> {
+    pending.deadline = None;
+    std::mem::take(&mut pending.names)
+}
+
+fn flush_names(inner: &Inner, names: BTreeSet<String>) -> Result<(), String> {
+    let mut result = Ok(());
+
+    for name in names {
+        if let Err(e) = (inner.flush)(&name) {
+            warn!("Failed to flush store {}: {}", name, e);
+            result = Err(e);
+        }
+    }
+
+    result
+}
+
+fn run(inner: &Inner) {
+    loop {
+        {
+            let Ok(mut pending) = inner.pending.lock() else {
+                return;
+            };
+
+            // Sleep until something is dirty and its window has elapsed
+            loop {
+                match pending.deadline {
+                    None => {
+                        pending = match inner.wake.wait(pending) {
+                            Ok(guard) => guard,
+                            Err(_) => return,
+                        };
+                    }
+                    Some(deadline) => {
+                        let now = Instant::now();
+                        if now >= deadline {
+                            break;
+                        }
+                        pending = match inner.wake.wait_timeout(pending, deadline - now) {
+                            Ok((guard, _)) => guard,
+                            Err(_) => return,
+                        };
+                    }
+                }
+            }
+        }
+
+        // Take the names only once writing is ours; `flush_all` may have
+        // written them in the meantime
+        let Ok(_writing) = inner.writing.lock() else {
+            return;
+        };
+        let names = match inner.pending.lock() {
+            Ok(mut pending) => take(&mut pending),
+            Err(_) => return,
+        };
+
+        // Flush outside the pending lock so writers are never blocked on
+        // disk I/O
+        let _ = flush_names(inner, names);
+    }
+}
+
+// ============================================================================
+// App Integration
+// ============================================================================
+
+/// Managed debouncer for the app's stores
+pub struct StoreSaver(Debouncer);
+
+/// Register the debouncer and flush on system suspend
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    let handle = app.clone();
+    let debouncer = Debouncer::new(DEBOUNCE, move |name| {
+        handle
+            .profile_store(name)
+            .map_err(|e| format!("Failed to open store: {}", e))?
+            .save()
+            .map_err(|e| format!("Failed to save store: {}", e))
+    });
+    app.manage(StoreSaver(debouncer));
+
+    power::subscribe(app, {
+        let app = app.clone();
+        move |event| {
+            if event == PowerEvent::SystemSuspend {
+                flush(&app);
+            }
+        }
+    });
+}
+
+/// Schedule a debounced save of the store file `name`
+pub fn mark_dirty<R: Runtime>(app: &AppHandle<R>, name: &str) {
+    match app.try_state::<StoreSaver>() {
+        Some(saver) => saver.0.mark_dirty(name),
+        None => warn!("Store saver not initialized; {} not scheduled", name),
+    }
+}
+
+/// Write all pending store changes now, logging failures
+pub fn flush<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(saver) = app.try_state::<StoreSaver>() {
+        let _ = saver.0.flush_all();
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Write all pending store changes to disk
+#[tauri::command]
+pub async fn flush_store(saver: State<'_, StoreSaver>) -> Result<(), String> {
+    info!("Flushing stores");
+    saver.0.flush_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    /// In-memory store that writes its contents as JSON on flush
+    fn file_backed(path: std::path::PathBuf) -> (Arc<Mutex<BTreeMap<String, i64>>>, Debouncer) {
+        let data = Arc::new(Mutex::new(BTreeMap::new()));
+        let snapshot = Arc::clone(&data);
+        let debouncer = Debouncer::new(Duration::from_millis(50), move |_| {
+            let json = serde_json::to_vec(&*snapshot.lock().unwrap()).unwrap();
+            std::fs::write(&path, json).map_err(|e| e.to_string())
+        });
+        (data, debouncer)
+    }
+
+    fn read(path: &std::path::Path) -> BTreeMap<String, i64> {
+        serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rapid_concurrent_writes_keep_last_value_per_key() {
+        let path = std::env::temp_dir().join(format!("persist-test-{}.json", uuid::Uuid::new_v4()));
+        let (data, debouncer) = file_backed(path.clone());
+
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let data = Arc::clone(&data);
+                let debouncer = debouncer.clone();
+                thread::spawn(move || {
+                    for i in 0..=500 {
+                        data.lock().unwrap().insert(format!("slider{}", writer), i);
+                        debouncer.mark_dirty("settings.json");
+                        if i % 100 == 0 {
+                            thread::sleep(Duration::from_millis(20));
+                        }
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        // The background flush picks up the final values without an explicit flush
+        thread::sleep(Duration::from_millis(300));
+        let written = read(&path);
+        assert_eq!(written.len(), 4);
+        assert!(written.values().all(|&v| v == 500));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn flush_all_writes_immediately() {
+        let path = std::env::temp_dir().join(format!("persist-test-{}.json", uuid::Uuid::new_v4()));
+        let (data, debouncer) = file_backed(path.clone());
+
+        data.lock().unwrap().insert("theme".into(), 1);
+        debouncer.mark_dirty("settings.json");
+        data.lock().unwrap().insert("theme".into(), 2);
+        debouncer.mark_dirty("settings.json");
+        debouncer.flush_all().unwrap();
+
+        assert_eq!(read(&path).get("theme"), Some(&2));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn flush_all_waits_for_a_write_under_way() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        let started = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicUsize::new(0));
+        let debouncer = Debouncer::new(Duration::from_millis(10), {
+            let started = Arc::clone(&started);
+            let finished = Arc::clone(&finished);
+            move |_| {
+                started.store(true, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(200));
+                finished.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        });
+
+        debouncer.mark_dirty("settings.json");
+        while !started.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // The background write has taken the name, so nothing is pending
+        debouncer.flush_all().unwrap();
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,725 @@
+// Read Master Desktop - Power & Network
+//
+// Sleep, wake and connectivity changes from the OS, as events and callbacks.
+
+use std::sync::{Mutex, OnceLock, RwLock};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerEvent {
+    SystemSuspend,
+    SystemResume,
+    NetworkOnline,
+    NetworkOffline,
+}
+
+impl PowerEvent {
+    /// Event name emitted to the frontend
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SystemSuspend => "system-suspend",
+            Self::SystemResume => "system-resume",
+            Self::NetworkOnline => "network-online",
+            Self::NetworkOffline => "network-offline",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PowerSnapshot {
+    pub online: bool,
+    /// When the system last went to sleep
+    pub last_suspend: Option<DateTime<Utc>>,
+    /// When the system last woke up
+    pub last_resume: Option<DateTime<Utc>>,
+}
+
+impl PowerSnapshot {
+    fn asleep(&self) -> bool {
+        match (self.last_suspend, self.last_resume) {
+            (Some(suspend), Some(resume)) => suspend > resume,
+            (suspend, _) => suspend.is_some(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PowerEventPayload {
+    at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slept_secs: Option<u64>,
+}
+
+type Listener = Box<dyn Fn(PowerEvent) + Send + Sync>;
+
+/// Managed power/network state plus internal subscribers
+#[derive(Default)]
+pub struct PowerState {
+    snapshot: Mutex<PowerSnapshot>,
+    listeners: RwLock<Vec<Listener>>,
+}
+
+impl PowerState {
+    /// Whether the OS last reported a connection to the internet
+    pub fn is_online(&self) -> bool {
+        self.snapshot.lock().map(|s| s.online).unwrap_or(true)
+    }
+}
+
+/// Register an internal callback for power/network events. Callbacks run
+/// on whichever thread the OS reports on, and a suspend is reported before
+/// the machine sleeps, so they should return quickly.
+pub fn subscribe<R: Runtime>(
+    app: &AppHandle<R>,
+    listener: impl Fn(PowerEvent) + Send + Sync + 'static,
+) {
+    if let Ok(mut listeners) = app.state::<PowerState>().listeners.write() {
+        listeners.push(Box::new(listener));
+    }
+}
+
+// ============================================================================
+// Event Mapping
+// ============================================================================
+
+/// `PBT_APMSUSPEND`
+const PBT_APMSUSPEND: u32 = 0x4;
+/// `PBT_APMRESUMEAUTOMATIC`, sent on every wake. `PBT_APMRESUMESUSPEND`
+/// follows it when the user woke the machine, and is ignored.
+const PBT_APMRESUMEAUTOMATIC: u32 = 0x12;
+
+/// Workspace notifications observed on macOS
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+const WORKSPACE_NOTIFICATIONS: &[&str] = &[
+    "NSWorkspaceWillSleepNotification",
+    "NSWorkspaceDidWakeNotification",
+];
+
+/// `NM_STATE_CONNECTED_GLOBAL`; lower states have no internet access
+const NM_STATE_CONNECTED_GLOBAL: u32 = 70;
+
+/// `kSCNetworkReachabilityFlagsReachable`
+const REACHABLE: u32 = 1 << 1;
+/// `kSCNetworkReachabilityFlagsConnectionRequired`
+const CONNECTION_REQUIRED: u32 = 1 << 2;
+
+/// `NetworkConnectivityLevelHintNone` and `...LocalAccess`
+const HINT_NONE: i32 = 1;
+const HINT_LOCAL_ACCESS: i32 = 2;
+
+fn online_event(online: bool) -> PowerEvent {
+    if online {
+        PowerEvent::NetworkOnline
+    } else {
+        PowerEvent::NetworkOffline
+    }
+}
+
+/// A `WM_POWERBROADCAST` on Windows
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn broadcast_event(code: u32) -> Option<PowerEvent> {
+    match code {
+        PBT_APMSUSPEND => Some(PowerEvent::SystemSuspend),
+        PBT_APMRESUMEAUTOMATIC => Some(PowerEvent::SystemResume),
+        _ => None,
+    }
+}
+
+/// An `NSWorkspace` notification on macOS
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn workspace_event(name: &str) -> Option<PowerEvent> {
+    match name {
+        "NSWorkspaceWillSleepNotification" => Some(PowerEvent::SystemSuspend),
+        "NSWorkspaceDidWakeNotification" => Some(PowerEvent::SystemResume),
+        _ => None,
+    }
+}
+
+/// logind's `PrepareForSleep`, true before sleeping and false after waking
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn prepare_for_sleep_event(starting: bool) -> PowerEvent {
+    if starting {
+        PowerEvent::SystemSuspend
+    } else {
+        PowerEvent::SystemResume
+    }
+}
+
+/// NetworkManager's `State`. Unknown (0) counts as online, so a machine
+/// NetworkManager doesn't manage isn't held offline.
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn nm_online(state: u32) -> bool {
+    state == 0 || state >= NM_STATE_CONNECTED_GLOBAL
+}
+
+/// Reachability flags of the default route on macOS
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn reachability_online(flags: u32) -> bool {
+    flags & REACHABLE != 0 && flags & CONNECTION_REQUIRED == 0
+}
+
+/// A connectivity level hint on Windows
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn hint_online(level: i32) -> bool {
+    !matches!(level, HINT_NONE | HINT_LOCAL_ACCESS)
+}
+
+/// Record `event` in the snapshot, returning what to emit, or `None` when
+/// it changes nothing (a second suspend, or online while already online)
+fn apply(
+    snapshot: &mut PowerSnapshot,
+    event: PowerEvent,
+    now: DateTime<Utc>,
+) -> Option<PowerEventPayload> {
+    let slept_secs = match event {
+        PowerEvent::SystemSuspend if snapshot.asleep() => return None,
+        PowerEvent::SystemResume if !snapshot.asleep() => return None,
+        PowerEvent::SystemSuspend => {
+            snapshot.last_suspend = Some(now);
+            None
+        }
+        PowerEvent::SystemResume => {
+            snapshot.last_resume = Some(now);
+            snapshot
+                .last_suspend
+                .map(|suspend| (now - suspend).num_seconds().max(0) as u64)
+        }
+        PowerEvent::NetworkOnline | PowerEvent::NetworkOffline => {
+            let online = event == PowerEvent::NetworkOnline;
+            if snapshot.online == online {
+                return None;
+            }
+            snapshot.online = online;
+            None
+        }
+    };
+    Some(PowerEventPayload {
+        at: now.to_rfc3339(),
+        slept_secs,
+    })
+}
+
+// ============================================================================
+// Windows
+// ============================================================================
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::ffi::c_void;
+    use std::thread;
+
+    use log::warn;
+    use windows::core::w;
+    use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW, MSG,
+        WINDOW_EX_STYLE, WM_POWERBROADCAST, WNDCLASSW, WS_OVERLAPPED,
+    };
+
+    use super::{broadcast_event, hint_online, online_event, report};
+
+    /// `NL_NETWORK_CONNECTIVITY_HINT`
+    #[repr(C)]
+    struct ConnectivityHint {
+        level: i32,
+        cost: i32,
+        approaching_data_limit: u8,
+        over_data_limit: u8,
+        roaming: u8,
+    }
+
+    #[link(name = "iphlpapi")]
+    extern "system" {
+        fn NotifyNetworkConnectivityHintChange(
+            callback: unsafe extern "system" fn(*const c_void, ConnectivityHint),
+            context: *const c_void,
+            initial_notification: u8,
+            handle: *mut *mut c_void,
+        ) -> u32;
+    }
+
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        if message == WM_POWERBROADCAST {
+            if let Some(event) = broadcast_event(wparam.0 as u32) {
+                report(event);
+            }
+            return LRESULT(1);
+        }
+        DefWindowProcW(hwnd, message, wparam, lparam)
+    }
+
+    unsafe extern "system" fn hint_changed(_context: *const c_void, hint: ConnectivityHint) {
+        report(online_event(hint_online(hint.level)));
+    }
+
+    /// Power broadcasts only reach top-level windows, so listen from a
+    /// hidden one with its own message loop
+    fn run_power_window() -> windows::core::Result<()> {
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let class_name = w!("ReadMasterPowerMonitor");
+            let class = WNDCLASSW {
+                lpfnWndProc: Some(window_proc),
+                hInstance: instance.into(),
+                lpszClassName: class_name,
+                ..Default::default()
+            };
+            if RegisterClassW(&class) == 0 {
+                return Err(windows::core::Error::from_win32());
+            }
+            CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                class_name,
+                w!(""),
+                WS_OVERLAPPED,
+                0,
+                0,
+                0,
+                0,
+                None,
+                None,
+                instance,
+                None,
+            )?;
+
+            let mut message = MSG::default();
+            while GetMessageW(&mut message, None, 0, 0).into() {
+                DispatchMessageW(&message);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn watch() {
+        let spawned = thread::Builder::new()
+            .name("power-broadcasts".into())
+            .spawn(|| {
+                if let Err(e) = run_power_window() {
+                    warn!("Not watching for sleep: {}", e);
+                }
+            });
+        if let Err(e) = spawned {
+            warn!("Failed to start power monitor: {}", e);
+        }
+
+        // The registration lasts as long as the process, so the handle is
+        // never closed
+        let mut handle = std::ptr::null_mut();
+        let status = unsafe {
+            NotifyNetworkConnectivityHintChange(hint_changed, std::ptr::null(), 1, &mut handle)
+        };
+        if status != 0 {
+            warn!("Not watching connectivity (error {})", status);
+        }
+    }
+}
+
+// ============================================================================
+// macOS
+// ============================================================================
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_void, CStr};
+
+    use cocoa::base::{id, nil};
+    use cocoa::foundation::NSString;
+    use log::warn;
+    use objc::declare::ClassDecl;
+    use objc::runtime::{Object, Sel};
+    use objc::{class, msg_send, sel, sel_impl};
+
+    use super::{
+        online_event, reachability_online, report, workspace_event, WORKSPACE_NOTIFICATIONS,
+    };
+
+    /// `sockaddr_in`
+    #[repr(C)]
+    struct SockaddrIn {
+        len: u8,
+        family: u8,
+        port: u16,
+        addr: u32,
+        zero: [u8; 8],
+    }
+
+    const AF_INET: u8 = 2;
+
+    #[link(name = "SystemConfiguration", kind = "framework")]
+    extern "C" {
+        fn SCNetworkReachabilityCreateWithAddress(
+            allocator: *const c_void,
+            address: *const SockaddrIn,
+        ) -> *mut c_void;
+        fn SCNetworkReachabilityGetFlags(target: *mut c_void, flags: *mut u32) -> u8;
+        fn SCNetworkReachabilitySetCallback(
+            target: *mut c_void,
+            callout: extern "C" fn(*mut c_void, u32, *mut c_void),
+            context: *mut c_void,
+        ) -> u8;
+        fn SCNetworkReachabilitySetDispatchQueue(target: *mut c_void, queue: *mut c_void) -> u8;
+    }
+
+    extern "C" {
+        fn dispatch_get_global_queue(identifier: isize, flags: usize) -> *mut c_void;
+    }
+
+    extern "C" fn workspace_notified(_this: &Object, _sel: Sel, notification: id) {
+        let name = unsafe {
+            let name: id = msg_send![notification, name];
+            CStr::from_ptr(name.UTF8String())
+                .to_string_lossy()
+                .into_owned()
+        };
+        if let Some(event) = workspace_event(&name) {
+            report(event);
+        }
+    }
+
+    extern "C" fn reachability_changed(_target: *mut c_void, flags: u32, _info: *mut c_void) {
+        report(online_event(reachability_online(flags)));
+    }
+
+    fn observe_workspace() {
+        unsafe {
+            let mut decl = ClassDecl::new("ReadMasterPowerObserver", class!(NSObject))
+                .expect("power observer class already registered");
+            decl.add_method(
+                sel!(workspaceNotified:),
+                workspace_notified as extern "C" fn(&Object, Sel, id),
+            );
+            let observer: id = msg_send![decl.register(), new];
+
+            let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+            let center: id = msg_send![workspace, notificationCenter];
+            for name in WORKSPACE_NOTIFICATIONS {
+                let name = NSString::alloc(nil).init_str(name);
+                let _: () = msg_send![
+                    center,
+                    addObserver: observer
+                    selector: sel!(workspaceNotified:)
+                    name: name
+                    object: nil
+                ];
+            }
+        }
+    }
+
+    /// Reachability of the default route, which the system works out from
+    /// its interfaces without sending anything. The target is kept for the
+    /// life of the process.
+    fn watch_reachability() -> bool {
+        let address = SockaddrIn {
+            len: std::mem::size_of::<SockaddrIn>() as u8,
+            family: AF_INET,
+            port: 0,
+            addr: 0,
+            zero: [0; 8],
+        };
+        unsafe {
+            let target = SCNetworkReachabilityCreateWithAddress(std::ptr::null(), &address);
+            if target.is_null() {
+                return false;
+            }
+            let mut flags = 0;
+            if SCNetworkReachabilityGetFlags(target, &mut flags) != 0 {
+                report(online_event(reachability_online(flags)));
+            }
+            SCNetworkReachabilitySetCallback(target, reachability_changed, std::ptr::null_mut())
+                != 0
+                && SCNetworkReachabilitySetDispatchQueue(target, dispatch_get_global_queue(0, 0))
+                    != 0
+        }
+    }
+
+    pub fn watch() {
+        observe_workspace();
+        if !watch_reachability() {
+            warn!("Not watching connectivity: reachability unavailable");
+        }
+    }
+}
+
+// ============================================================================
+// Linux
+// ============================================================================
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+mod platform {
+    use std::thread;
+
+    use log::warn;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::OwnedFd;
+
+    use super::{nm_online, online_event, prepare_for_sleep_event, report};
+
+    /// A delay lock, so logind waits for `PrepareForSleep` to be handled
+    /// before suspending
+    fn inhibit(manager: &Proxy<'_>) -> Option<OwnedFd> {
+        manager
+            .call(
+                "Inhibit",
+                &("sleep", "Read Master", "Saving reading progress", "delay"),
+            )
+            .map_err(|e| warn!("Failed to take a sleep delay lock: {}", e))
+            .ok()
+    }
+
+    fn watch_sleep() -> zbus::Result<()> {
+        let conn = Connection::system()?;
+        let manager = Proxy::new(
+            &conn,
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+        )?;
+
+        let mut lock = inhibit(&manager);
+        for signal in manager.receive_signal("PrepareForSleep")? {
+            let starting: bool = signal.body().deserialize()?;
+            report(prepare_for_sleep_event(starting));
+            // Let the suspend go ahead, and take a new lock for the next one
+            if starting {
+                drop(lock.take());
+            } else {
+                lock = inhibit(&manager);
+            }
+        }
+        Ok(())
+    }
+
+    fn watch_network() -> zbus::Result<()> {
+        let conn = Connection::system()?;
+        let manager = Proxy::new(
+            &conn,
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            "org.freedesktop.NetworkManager",
+        )?;
+
+        let changes = manager.receive_signal("StateChanged")?;
+        report(online_event(nm_online(manager.get_property("State")?)));
+        for signal in changes {
+            report(online_event(nm_online(signal.body().deserialize()?)));
+        }
+        Ok(())
+    }
+
+    fn spawn(name: &str, what: &'static str, watch: fn() -> zbus::Result<()>) {
+        let spawned = thread::Builder::new().name(name.into()).spawn(move || {
+            if let Err(e) = watch() {
+                warn!("Not watching {}: {}", what, e);
+            }
+        });
+        if let Err(e) = spawned {
+            warn!("Failed to start {} monitor: {}", what, e);
+        }
+    }
+
+    /// logind's sleep signal and NetworkManager's state, over the system bus
+    pub fn watch() {
+        spawn("power-sleep", "for sleep", watch_sleep);
+        spawn("power-network", "connectivity", watch_network);
+    }
+}
+
+// ============================================================================
+// Monitor
+// ============================================================================
+
+type Sink = Box<dyn Fn(PowerEvent) + Send + Sync>;
+
+/// Where the platform watchers send what the OS reports, set by `init`
+static SINK: OnceLock<Sink> = OnceLock::new();
+
+fn report(event: PowerEvent) {
+    if let Some(sink) = SINK.get() {
+        sink(event);
+    }
+}
+
+fn dispatch<R: Runtime>(app: &AppHandle<R>, event: PowerEvent) {
+    let payload = app
+        .state::<PowerState>()
+        .snapshot
+        .lock()
+        .ok()
+        .and_then(|mut snapshot| apply(&mut snapshot, event, Utc::now()));
+    let Some(payload) = payload else {
+        return;
+    };
+
+    info!("Power event: {}", event.name());
+
+    if let Err(e) = app.emit(event.name(), payload) {
+        warn!("Failed to emit {}: {}", event.name(), e);
+    }
+
+    let state = app.state::<PowerState>();
+    if let Ok(listeners) = state.listeners.read() {
+        for listener in listeners.iter() {
+            listener(event);
+        }
+    }
+}
+
+/// Register managed state and start listening to the OS. Runs in setup,
+/// on the main thread.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    // Online until the OS says otherwise
+    app.manage(PowerState {
+        snapshot: Mutex::new(PowerSnapshot {
+            online: true,
+            ..Default::default()
+        }),
+        listeners: RwLock::default(),
+    });
+
+    let sink: Sink = {
+        let app = app.clone();
+        Box::new(move |event| dispatch(&app, event))
+    };
+    if SINK.set(sink).is_err() {
+        return;
+    }
+
+    platform::watch();
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Current power/network state, for the frontend to query on startup
+#[tauri::command]
+pub fn get_power_state(state: State<'_, PowerState>) -> Result<PowerSnapshot, String> {
+    state
+        .snapshot
+        .lock()
+        .map(|s| s.clone())
+        .map_err(|_| "Power state lock poisoned".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    fn online() -> PowerSnapshot {
+        PowerSnapshot {
+            online: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn maps_power_broadcasts() {
+        assert_eq!(broadcast_event(0x4), Some(PowerEvent::SystemSuspend));
+        assert_eq!(broadcast_event(0x12), Some(PowerEvent::SystemResume));
+        // PBT_APMRESUMESUSPEND follows PBT_APMRESUMEAUTOMATIC
+        assert_eq!(broadcast_event(0x7), None);
+        // PBT_APMPOWERSTATUSCHANGE
+        assert_eq!(broadcast_event(0xA), None);
+    }
+
+    #[test]
+    fn maps_workspace_notifications() {
+        let events: Vec<_> = WORKSPACE_NOTIFICATIONS
+            .iter()
+            .map(|name| workspace_event(name))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                Some(PowerEvent::SystemSuspend),
+                Some(PowerEvent::SystemResume)
+            ]
+        );
+        assert_eq!(
+            workspace_event("NSWorkspaceScreensDidSleepNotification"),
+            None
+        );
+    }
+
+    #[test]
+    fn maps_prepare_for_sleep() {
+        assert_eq!(prepare_for_sleep_event(true), PowerEvent::SystemSuspend);
+        assert_eq!(prepare_for_sleep_event(false), PowerEvent::SystemResume);
+    }
+
+    #[test]
+    fn maps_connectivity() {
+        // Unknown, asleep, disconnected, connecting, local, site, global
+        let states = [0, 10, 20, 40, 50, 60, 70];
+        let online: Vec<_> = states.iter().map(|&s| nm_online(s)).collect();
+        assert_eq!(online, [true, false, false, false, false, false, true]);
+
+        assert!(reachability_online(REACHABLE));
+        assert!(!reachability_online(REACHABLE | CONNECTION_REQUIRED));
+        assert!(!reachability_online(0));
+
+        // Unknown, none, local, internet, constrained, hidden
+        let online: Vec<_> = (0..=5).map(hint_online).collect();
+        assert_eq!(online, [true, false, false, true, true, true]);
+
+        assert_eq!(online_event(true), PowerEvent::NetworkOnline);
+        assert_eq!(online_event(false), PowerEvent::NetworkOffline);
+    }
+
+    #[test]
+    fn suspend_and_resume_record_how_long_the_machine_slept() {
+        let mut snapshot = online();
+
+        let suspend = apply(&mut snapshot, PowerEvent::SystemSuspend, at(0)).unwrap();
+        assert_eq!(suspend.slept_secs, None);
+        assert_eq!(snapshot.last_suspend, Some(at(0)));
+
+        let resume = apply(&mut snapshot, PowerEvent::SystemResume, at(90)).unwrap();
+        assert_eq!(resume.slept_secs, Some(90));
+        assert_eq!(resume.at, at(90).to_rfc3339());
+        assert_eq!(snapshot.last_resume, Some(at(90)));
+    }
+
+    #[test]
+    fn repeated_events_are_dropped() {
+        let mut snapshot = online();
+
+        assert_eq!(apply(&mut snapshot, PowerEvent::SystemResume, at(0)), None);
+        assert!(apply(&mut snapshot, PowerEvent::SystemSuspend, at(1)).is_some());
+        assert_eq!(apply(&mut snapshot, PowerEvent::SystemSuspend, at(2)), None);
+        assert_eq!(snapshot.last_suspend, Some(at(1)));
+        assert!(apply(&mut snapshot, PowerEvent::SystemResume, at(3)).is_some());
+        assert_eq!(apply(&mut snapshot, PowerEvent::SystemResume, at(4)), None);
+
+        assert_eq!(apply(&mut snapshot, PowerEvent::NetworkOnline, at(5)), None);
+        assert!(apply(&mut snapshot, PowerEvent::NetworkOffline, at(6)).is_some());
+        assert!(!snapshot.online);
+        assert_eq!(
+            apply(&mut snapshot, PowerEvent::NetworkOffline, at(7)),
+            None
+        );
+        assert!(apply(&mut snapshot, PowerEvent::NetworkOnline, at(8)).is_some());
+        assert!(snapshot.online);
+    }
+}
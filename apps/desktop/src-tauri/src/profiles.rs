@@ -0,0 +1,386 @@
+// Read Master Desktop - Profiles
+//
+// Separate libraries for people sharing a computer, each with its own data.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::webview::{PageLoadEvent, PageLoadPayload};
+use tauri::{AppHandle, Emitter, Manager, Runtime, Webview};
+use tauri_plugin_store::{Store, StoreExt};
+
+use crate::find::FindSessions;
+use crate::reader::ReaderSessions;
+use crate::{data_location, persist, session, tts};
+
+/// The profile list, at the top of the app data directory outside every
+/// profile
+const REGISTRY_FILE: &str = "profiles.json";
+const PROFILES_KEY: &str = "profiles";
+const ACTIVE_KEY: &str = "active";
+/// Set before restarting into a profile, so the picker isn't shown again
+const SKIP_PICKER_KEY: &str = "skipPicker";
+
+pub const DEFAULT_PROFILE: &str = "default";
+const DEFAULT_NAME: &str = "Default";
+
+/// Subdirectory of the app data and log directories holding profiles
+const PROFILES_DIR: &str = "profiles";
+
+const MAX_NAME_CHARS: usize = 64;
+
+const MAIN_WINDOW: &str = "main";
+
+/// Profile this process runs as, set by `init`. It's fixed for the life
+/// of the process, since background workers (sync, the store saver, the
+/// tray, reminders) hold its data open.
+static ACTIVE: OnceLock<String> = OnceLock::new();
+
+/// Whether the main window should be sent the picker once loaded
+static PICKER_PENDING: AtomicBool = AtomicBool::new(false);
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileInfo {
+    #[serde(flatten)]
+    pub profile: Profile,
+    pub active: bool,
+}
+
+// ============================================================================
+// Paths
+// ============================================================================
+
+/// Id of the profile this process runs as
+pub fn active() -> &'static str {
+    ACTIVE.get().map_or(DEFAULT_PROFILE, String::as_str)
+}
+
+/// Path of a profile's directory below a base directory. Each profile has
+/// its own database, stores, covers, library folder, and logs. Default
+/// keeps the base directory itself, so data from before profiles existed
+/// stays where it is.
+fn profile_dir(base: PathBuf, id: &str) -> PathBuf {
+    if id == DEFAULT_PROFILE {
+        base
+    } else {
+        base.join(PROFILES_DIR).join(id)
+    }
+}
+
+/// Store file `name` of the active profile, as the store plugin expects
+pub fn store_path(name: &str) -> PathBuf {
+    data_location::store_path(profile_dir(PathBuf::new(), active()).join(name))
+}
+
+/// The active profile's data directory
+pub fn data_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    data_location::base_dir(app).map(|dir| profile_dir(dir, active()))
+}
+
+/// The active profile's log directory
+pub fn log_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    data_location::log_dir(app).map(|dir| profile_dir(dir, active()))
+}
+
+/// Stores of the active profile
+pub trait ProfileStoreExt<R: Runtime> {
+    /// The active profile's copy of the store file `name`
+    fn profile_store(&self, name: &str) -> tauri_plugin_store::Result<Arc<Store<R>>>;
+}
+
+impl<R: Runtime, T: Manager<R>> ProfileStoreExt<R> for T {
+    fn profile_store(&self, name: &str) -> tauri_plugin_store::Result<Arc<Store<R>>> {
+        self.store(store_path(name))
+    }
+}
+
+// ============================================================================
+// Registry
+// ============================================================================
+
+fn registry<R: Runtime>(app: &AppHandle<R>) -> Result<Arc<Store<R>>, String> {
+    app.store(data_location::store_path(REGISTRY_FILE))
+        .map_err(|e| format!("Failed to open profiles: {}", e))
+}
+
+fn default_profile() -> Profile {
+    Profile {
+        id: DEFAULT_PROFILE.to_string(),
+        name: DEFAULT_NAME.to_string(),
+        created_at: String::new(),
+    }
+}
+
+/// Every profile, Default first
+fn load<R: Runtime>(app: &AppHandle<R>) -> Result<Vec<Profile>, String> {
+    let stored: Vec<Profile> = registry(app)?
+        .get(PROFILES_KEY)
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| warn!("Ignoring corrupt profile list: {}", e))
+                .ok()
+        })
+        .unwrap_or_default();
+
+    let mut profiles = vec![default_profile()];
+    profiles.extend(stored.into_iter().filter(|p| p.id != DEFAULT_PROFILE));
+    Ok(profiles)
+}
+
+/// Write the registry now; it must be on disk before a restart
+fn save<R: Runtime>(app: &AppHandle<R>, profiles: &[Profile]) -> Result<(), String> {
+    let store = registry(app)?;
+    let stored: Vec<&Profile> = profiles
+        .iter()
+        .filter(|p| p.id != DEFAULT_PROFILE)
+        .collect();
+    store.set(PROFILES_KEY, serde_json::json!(stored));
+    store
+        .save()
+        .map_err(|e| format!("Failed to save profiles: {}", e))
+}
+
+/// Trimmed name, checked for length and clashes with existing profiles
+fn validate_name(name: &str, profiles: &[Profile]) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Profile name can't be empty".to_string());
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!(
+            "Profile names are limited to {} characters",
+            MAX_NAME_CHARS
+        ));
+    }
+    if profiles
+        .iter()
+        .any(|p| p.name.to_lowercase() == name.to_lowercase())
+    {
+        return Err(format!("A profile named {} already exists", name));
+    }
+    Ok(name.to_string())
+}
+
+/// Read the active profile and whether to offer the picker. Runs first in
+/// setup, before anything opens profile data.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    let (profiles, store) = match (load(app), registry(app)) {
+        (Ok(profiles), Ok(store)) => (profiles, store),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("{}; using the default profile", e);
+            let _ = ACTIVE.set(DEFAULT_PROFILE.to_string());
+            return;
+        }
+    };
+
+    let saved = store
+        .get(ACTIVE_KEY)
+        .and_then(|value| value.as_str().map(str::to_string));
+    let active = match saved {
+        Some(id) if profiles.iter().any(|p| p.id == id) => id,
+        Some(id) => {
+            warn!("Active profile {} no longer exists; using default", id);
+            DEFAULT_PROFILE.to_string()
+        }
+        None => DEFAULT_PROFILE.to_string(),
+    };
+    let _ = ACTIVE.set(active);
+
+    let skip_picker = store
+        .get(SKIP_PICKER_KEY)
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+    if skip_picker {
+        store.delete(SKIP_PICKER_KEY);
+        if let Err(e) = store.save() {
+            warn!("Failed to save profiles: {}", e);
+        }
+    }
+    PICKER_PENDING.store(profiles.len() > 1 && !skip_picker, Ordering::Relaxed);
+
+    info!("Using profile {}", active());
+}
+
+/// Builder-level page-load hook offering the picker to the main window as
+/// a `profile-picker` event. It's offered at launch with more than one
+/// profile, except right after a switch.
+pub fn handle_page_load<R: Runtime>(webview: &Webview<R>, payload: &PageLoadPayload<'_>) {
+    if payload.event() != PageLoadEvent::Finished || webview.label() != MAIN_WINDOW {
+        return;
+    }
+    if !PICKER_PENDING.swap(false, Ordering::Relaxed) {
+        return;
+    }
+
+    let app = webview.app_handle();
+    match list(app) {
+        Ok(profiles) => {
+            let _ = webview.emit_to(MAIN_WINDOW, "profile-picker", profiles);
+        }
+        Err(e) => warn!("Failed to offer profile picker: {}", e),
+    }
+}
+
+fn list<R: Runtime>(app: &AppHandle<R>) -> Result<Vec<ProfileInfo>, String> {
+    Ok(load(app)?
+        .into_iter()
+        .map(|profile| ProfileInfo {
+            active: profile.id == active(),
+            profile,
+        })
+        .collect())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Every profile, Default first, marking the active one
+#[tauri::command]
+pub fn list_profiles<R: Runtime>(app: AppHandle<R>) -> Result<Vec<ProfileInfo>, String> {
+    list(&app)
+}
+
+/// Add an empty profile. It's set up the first time it's switched to.
+#[tauri::command]
+pub fn create_profile<R: Runtime>(app: AppHandle<R>, name: String) -> Result<Profile, String> {
+    let mut profiles = load(&app)?;
+    let profile = Profile {
+        id: uuid::Uuid::new_v4().simple().to_string(),
+        name: validate_name(&name, &profiles)?,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    profiles.push(profile.clone());
+    save(&app, &profiles)?;
+
+    info!("Created profile {} ({})", profile.name, profile.id);
+    Ok(profile)
+}
+
+/// Close open books, save everything, and restart as `profile_id`. Does
+/// nothing when it's already active.
+#[tauri::command]
+pub fn switch_profile<R: Runtime>(app: AppHandle<R>, profile_id: String) -> Result<(), String> {
+    if profile_id == active() {
+        return Ok(());
+    }
+    let profiles = load(&app)?;
+    let profile = profiles
+        .iter()
+        .find(|p| p.id == profile_id)
+        .ok_or_else(|| format!("Profile not found: {}", profile_id))?;
+    info!("Switching to profile {} ({})", profile.name, profile.id);
+
+    // Windows close their books before the restart takes them down
+    let _ = app.emit("profile-switching", &profile.id);
+    let _ = tts::tts_stop(app.state());
+    app.state::<ReaderSessions>().clear();
+    if let Some(finds) = app.try_state::<FindSessions>() {
+        finds.clear();
+    }
+    session::save(&app);
+    persist::flush(&app);
+
+    let store = registry(&app)?;
+    store.set(ACTIVE_KEY, serde_json::json!(profile.id));
+    store.set(SKIP_PICKER_KEY, serde_json::json!(true));
+    store
+        .save()
+        .map_err(|e| format!("Failed to save profiles: {}", e))?;
+
+    app.restart()
+}
+
+/// Move a profile's data to the trash and forget it. `confirmation` must be
+/// the profile's name, typed back. The active and Default profiles can't
+/// be deleted.
+#[tauri::command]
+pub fn delete_profile<R: Runtime>(
+    app: AppHandle<R>,
+    profile_id: String,
+    confirmation: String,
+) -> Result<(), String> {
+    if profile_id == DEFAULT_PROFILE {
+        return Err("The default profile can't be deleted".to_string());
+    }
+    if profile_id == active() {
+        return Err("Switch to another profile before deleting this one".to_string());
+    }
+
+    let mut profiles = load(&app)?;
+    let profile = profiles
+        .iter()
+        .find(|p| p.id == profile_id)
+        .cloned()
+        .ok_or_else(|| format!("Profile not found: {}", profile_id))?;
+    if confirmation.trim() != profile.name {
+        return Err(format!(
+            "Type the profile name ({}) to confirm deleting it",
+            profile.name
+        ));
+    }
+
+    let base_dirs = [data_location::base_dir(&app), data_location::log_dir(&app)];
+    for base in base_dirs.into_iter().flatten() {
+        let dir = profile_dir(base, &profile.id);
+        if dir.exists() {
+            trash::delete(&dir)
+                .map_err(|e| format!("Failed to move profile data to trash: {}", e))?;
+        }
+    }
+
+    profiles.retain(|p| p.id != profile.id);
+    save(&app, &profiles)?;
+    info!("Deleted profile {} ({})", profile.name, profile.id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(id: &str, name: &str) -> Profile {
+        Profile {
+            id: id.to_string(),
+            name: name.to_string(),
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn default_profile_uses_the_base_directory() {
+        let base = PathBuf::from("/data");
+        assert_eq!(profile_dir(base.clone(), DEFAULT_PROFILE), base);
+        assert_eq!(
+            profile_dir(base, "abc"),
+            PathBuf::from("/data/profiles/abc")
+        );
+    }
+
+    #[test]
+    fn validates_names() {
+        let profiles = [default_profile(), profile("a", "Sam")];
+
+        assert_eq!(validate_name("  Alex ", &profiles).unwrap(), "Alex");
+        assert!(validate_name("  ", &profiles).is_err());
+        assert!(validate_name("sam", &profiles).is_err());
+        assert!(validate_name("default", &profiles).is_err());
+        assert!(validate_name(&"x".repeat(65), &profiles).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+// Read Master Desktop - Quick Access Shortcut
+//
+// A global shortcut that brings the main window forward from anywhere, or
+// hides it when it is already in front. The accelerator is stored in
+// `shortcuts.quickAccess` and can be changed at runtime.
+
+use std::sync::Mutex;
+
+use log::{info, warn};
+use tauri::{AppHandle, Manager, Runtime, State};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+use crate::profiles::ProfileStoreExt;
+use crate::{persist, tray};
+
+const SETTING_KEY: &str = "shortcuts.quickAccess";
+
+/// Default global shortcut for showing and hiding the window
+const DEFAULT_SHORTCUT: &str = "CommandOrControl+Shift+R";
+
+/// The shortcut currently registered, if any
+#[derive(Default)]
+pub struct QuickAccessShortcut(Mutex<Option<Shortcut>>);
+
+// ============================================================================
+// Shortcut Handling
+// ============================================================================
+
+/// Parse an accelerator such as "CommandOrControl+Shift+R". Shortcuts
+/// without a modifier are refused since they would swallow that key in
+/// every other app.
+fn parse(accelerator: &str) -> Result<Shortcut, String> {
+    let shortcut: Shortcut = accelerator
+        .trim()
+        .parse()
+        .map_err(|e| format!("Invalid shortcut '{}': {}", accelerator, e))?;
+    if shortcut.mods.is_empty() {
+        return Err(format!(
+            "Invalid shortcut '{}': add a modifier such as Ctrl or Alt",
+            accelerator
+        ));
+    }
+    Ok(shortcut)
+}
+
+/// Hide the main window if it's in front, otherwise show and focus it
+fn toggle_main_window<R: Runtime>(app: &AppHandle<R>) {
+    let Some(window) = app.get_webview_window("main") else {
+        return;
+    };
+    let in_front = window.is_visible().unwrap_or(false)
+        && !window.is_minimized().unwrap_or(false)
+        && window.is_focused().unwrap_or(false);
+    if in_front {
+        let _ = window.hide();
+    } else {
+        tray::show_main_window(app);
+    }
+}
+
+fn register<R: Runtime>(app: &AppHandle<R>, shortcut: Shortcut) -> Result<(), String> {
+    app.global_shortcut()
+        .on_shortcut(shortcut, |app, _, event| {
+            if event.state() == ShortcutState::Pressed {
+                toggle_main_window(app);
+            }
+        })
+        .map_err(|e| {
+            format!(
+                "Shortcut '{}' is already in use by another application: {}",
+                shortcut, e
+            )
+        })
+}
+
+/// Register the saved shortcut. Needs the global shortcut plugin, which
+/// `quick_capture::init` installs.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    app.manage(QuickAccessShortcut::default());
+
+    let accelerator = app
+        .profile_store("settings.json")
+        .ok()
+        .and_then(|s| s.get(SETTING_KEY))
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_else(|| DEFAULT_SHORTCUT.to_string());
+
+    let shortcut = match parse(&accelerator) {
+        Ok(shortcut) => shortcut,
+        Err(e) => {
+            warn!("{}; using {}", e, DEFAULT_SHORTCUT);
+            match parse(DEFAULT_SHORTCUT) {
+                Ok(shortcut) => shortcut,
+                Err(_) => return,
+            }
+        }
+    };
+
+    match register(app, shortcut) {
+        Ok(()) => {
+            if let Ok(mut current) = app.state::<QuickAccessShortcut>().0.lock() {
+                *current = Some(shortcut);
+            }
+        }
+        // The tray still shows the window
+        Err(e) => warn!("Failed to register quick access shortcut: {}", e),
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Change the quick access shortcut and save it. The old shortcut stays
+/// registered if the new one is invalid or taken.
+#[tauri::command]
+pub fn set_quick_access_shortcut<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, QuickAccessShortcut>,
+    accelerator: String,
+) -> Result<(), String> {
+    let shortcut = parse(&accelerator)?;
+
+    let mut current = state
+        .0
+        .lock()
+        .map_err(|_| "Shortcut lock poisoned".to_string())?;
+    if *current != Some(shortcut) {
+        if app.global_shortcut().is_registered(shortcut) {
+            return Err(format!(
+                "Shortcut '{}' is already used by another Read Master action",
+                accelerator
+            ));
+        }
+
+        register(&app, shortcut)?;
+        if let Some(old) = current.replace(shortcut) {
+            if let Err(e) = app.global_shortcut().unregister(old) {
+                warn!("Failed to unregister old quick access shortcut: {}", e);
+            }
+        }
+    }
+
+    let store = app
+        .profile_store("settings.json")
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    store.set(SETTING_KEY, accelerator.trim());
+    persist::mark_dirty(&app, "settings.json");
+
+    info!("Quick access shortcut set to {}", accelerator);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_accelerators_with_modifiers() {
+        assert!(parse("CommandOrControl+Shift+R").is_ok());
+        assert!(parse(" Alt+F12 ").is_ok());
+        assert_eq!(
+            parse("Ctrl+Shift+R").unwrap(),
+            parse("Control+Shift+R").unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_accelerators() {
+        assert!(parse("").is_err());
+        assert!(parse("Ctrl+Shift+NotAKey").is_err());
+        assert!(parse("R").unwrap_err().contains("modifier"));
+    }
+}
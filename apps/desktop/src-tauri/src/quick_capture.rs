@@ -0,0 +1,222 @@
+// Read Master Desktop - Quick Capture
+//
+// Turns clipboard text into a flashcard or note from anywhere, via the
+// tray menu or a global shortcut, using a small always-on-top window.
+
+use std::sync::Mutex;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{
+    AppHandle, Emitter, Manager, Runtime, State, WebviewUrl, WebviewWindow, WebviewWindowBuilder,
+};
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+use crate::persist;
+use crate::profiles::ProfileStoreExt;
+
+/// Label of the capture window
+pub const WINDOW_LABEL: &str = "quick-capture";
+
+/// Default global shortcut for quick capture
+const DEFAULT_SHORTCUT: &str = "CommandOrControl+Shift+K";
+
+/// Longest clipboard text accepted, in characters
+const MAX_CAPTURE_CHARS: usize = 2000;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureKind {
+    Card,
+    Note,
+}
+
+/// What the capture window shows when it opens
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapturePayload {
+    /// Clipboard text, absent when the clipboard had no usable text
+    pub text: Option<String>,
+    /// True when the text was cut at the length cap
+    pub truncated: bool,
+    /// Inline message for the window when there is nothing to capture
+    pub message: Option<String>,
+}
+
+/// Payload for the capture window currently open
+#[derive(Default)]
+pub struct QuickCaptureState(Mutex<Option<CapturePayload>>);
+
+// ============================================================================
+// Capture Flow
+// ============================================================================
+
+fn read_clipboard<R: Runtime>(app: &AppHandle<R>) -> CapturePayload {
+    let text = match app.clipboard().read_text() {
+        Ok(text) => text,
+        Err(e) => {
+            info!("Clipboard has no text: {}", e);
+            String::new()
+        }
+    };
+
+    let text = text.trim();
+    if text.is_empty() {
+        return CapturePayload {
+            text: None,
+            truncated: false,
+            message: Some("The clipboard is empty or doesn't contain text.".to_string()),
+        };
+    }
+
+    let truncated = text.chars().count() > MAX_CAPTURE_CHARS;
+    CapturePayload {
+        text: Some(text.chars().take(MAX_CAPTURE_CHARS).collect()),
+        truncated,
+        message: None,
+    }
+}
+
+fn capture_window<R: Runtime>(app: &AppHandle<R>) -> tauri::Result<WebviewWindow<R>> {
+    if let Some(window) = app.get_webview_window(WINDOW_LABEL) {
+        return Ok(window);
+    }
+
+    WebviewWindowBuilder::new(app, WINDOW_LABEL, WebviewUrl::App("/quick-capture".into()))
+        .title("Quick Capture")
+        .inner_size(420.0, 320.0)
+        .resizable(false)
+        .decorations(false)
+        .always_on_top(true)
+        .skip_taskbar(true)
+        .center()
+        .build()
+}
+
+/// Read the clipboard and open (or refresh) the capture window
+pub fn open<R: Runtime>(app: &AppHandle<R>) {
+    info!("Opening quick capture");
+
+    let payload = read_clipboard(app);
+    if let Ok(mut pending) = app.state::<QuickCaptureState>().0.lock() {
+        *pending = Some(payload.clone());
+    }
+
+    match capture_window(app) {
+        Ok(window) => {
+            let _ = window.show();
+            let _ = window.set_focus();
+            // An already-open window won't re-query, so push the new text
+            let _ = window.emit("quick-capture", payload);
+        }
+        Err(e) => warn!("Failed to open quick capture window: {}", e),
+    }
+}
+
+/// Register managed state and the global shortcut
+pub fn init<R: Runtime>(app: &AppHandle<R>) -> Result<(), Box<dyn std::error::Error>> {
+    app.manage(QuickCaptureState::default());
+
+    let shortcut: Shortcut = DEFAULT_SHORTCUT.parse()?;
+    app.plugin(
+        tauri_plugin_global_shortcut::Builder::new()
+            .with_handler(move |app, pressed, event| {
+                if *pressed == shortcut && event.state() == ShortcutState::Pressed {
+                    open(app);
+                }
+            })
+            .build(),
+    )?;
+
+    if let Err(e) = app.global_shortcut().register(shortcut) {
+        // Another app may own the shortcut; the tray item still works
+        warn!("Failed to register quick capture shortcut: {}", e);
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Payload for the capture window, queried when it loads
+#[tauri::command]
+pub fn get_quick_capture(state: State<'_, QuickCaptureState>) -> Option<CapturePayload> {
+    state.0.lock().ok().and_then(|pending| pending.clone())
+}
+
+/// Save the capture as a flashcard or note and close the capture window.
+/// Returns the id of the created item.
+#[tauri::command]
+pub async fn save_quick_capture<R: Runtime>(
+    app: AppHandle<R>,
+    state: State<'_, QuickCaptureState>,
+    kind: CaptureKind,
+    front: String,
+    back: Option<String>,
+    deck_id: Option<String>,
+) -> Result<String, String> {
+    let front = front.trim();
+    if front.is_empty() {
+        return Err("Nothing to save: the captured text is empty".to_string());
+    }
+
+    let back = back.map(|b| b.trim().to_string()).unwrap_or_default();
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let (store_name, record) = match kind {
+        CaptureKind::Card => {
+            if back.is_empty() {
+                return Err("A flashcard needs a back side".to_string());
+            }
+            (
+                "flashcards.json",
+                serde_json::json!({
+                    "id": id,
+                    "deckId": deck_id.unwrap_or_else(|| "default".to_string()),
+                    "front": front,
+                    "back": back,
+                    "source": "quick-capture",
+                    "createdAt": now,
+                }),
+            )
+        }
+        CaptureKind::Note => (
+            "notes.json",
+            serde_json::json!({
+                "id": id,
+                "bookId": null,
+                "quote": front,
+                "body": back,
+                "tags": [],
+                "source": "quick-capture",
+                "createdAt": now,
+                "updatedAt": now,
+            }),
+        ),
+    };
+
+    info!("Saving quick capture {:?} to {}", kind, store_name);
+
+    let store = app
+        .profile_store(store_name)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    store.set(&id, record);
+    persist::mark_dirty(&app, store_name);
+
+    if let Ok(mut pending) = state.0.lock() {
+        *pending = None;
+    }
+    if let Some(window) = app.get_webview_window(WINDOW_LABEL) {
+        let _ = window.close();
+    }
+
+    Ok(id)
+}
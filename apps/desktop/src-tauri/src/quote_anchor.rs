@@ -0,0 +1,208 @@
+// Read Master Desktop - Quote Anchors
+//
+// Text quote selectors in the style of the W3C Web Annotation model: the
+// highlighted text plus a few words of context on each side. Structural
+// positions (CFIs, page offsets) go stale when a book's file is replaced
+// by a new edition, but the quote can usually still be found there, even
+// with a typo fixed inside it. Matching is word by word on normalized
+// words, so punctuation and case changes don't count against a match.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bookmarks::normalize;
+
+/// Matches scoring below this are not trusted
+pub const MIN_CONFIDENCE: f64 = 0.6;
+
+/// Words of prefix and suffix compared around a candidate
+const CONTEXT_WORDS: usize = 8;
+
+/// Quote words used to find candidate positions; the longest are the
+/// least likely to be common words
+const KEY_WORDS: usize = 3;
+
+/// Weight of the quote itself against its context in a match's confidence
+const QUOTE_WEIGHT: f64 = 0.8;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextQuote {
+    /// Text just before the highlight
+    #[serde(default)]
+    pub prefix: String,
+    /// The highlighted text
+    pub exact: String,
+    /// Text just after the highlight
+    #[serde(default)]
+    pub suffix: String,
+}
+
+/// Where a quote was found, in words of the searched text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuoteMatch {
+    pub start: usize,
+    pub len: usize,
+    /// 1.0 for the exact quote in its original context
+    pub confidence: f64,
+}
+
+// ============================================================================
+// Matching
+// ============================================================================
+
+/// Normalized words of `text`, one per whitespace-separated word
+pub fn words(text: &str) -> Vec<String> {
+    text.split_whitespace().map(normalize).collect()
+}
+
+/// 1.0 for identical word sequences, falling with each word inserted,
+/// removed, or changed
+fn similarity(a: &[String], b: &[String]) -> f64 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, word_a) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, word_b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(word_a != word_b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    1.0 - previous[b.len()] as f64 / longest as f64
+}
+
+/// Best match of `quote` in `haystack` (normalized words, as from
+/// `words`), or None when none of its key words appear
+pub fn find_quote(haystack: &[String], quote: &TextQuote) -> Option<QuoteMatch> {
+    let exact_words = words(&quote.exact);
+    let exact = exact_words.as_slice();
+    if exact.is_empty() || haystack.is_empty() {
+        return None;
+    }
+    let prefix = words(&quote.prefix);
+    let prefix = &prefix[prefix.len().saturating_sub(CONTEXT_WORDS)..];
+    let suffix = words(&quote.suffix);
+    let suffix = &suffix[..suffix.len().min(CONTEXT_WORDS)];
+
+    let mut keys: Vec<usize> = (0..exact.len()).filter(|&i| !exact[i].is_empty()).collect();
+    keys.sort_by_key(|&i| std::cmp::Reverse(exact[i].chars().count()));
+    keys.truncate(KEY_WORDS);
+
+    let candidates: BTreeSet<usize> = haystack
+        .iter()
+        .enumerate()
+        .flat_map(|(pos, word)| {
+            keys.iter()
+                .filter(move |&&key| *word == exact[key] && pos >= key)
+                .map(move |&key| pos - key)
+        })
+        .collect();
+
+    let score = |start: usize| {
+        let end = (start + exact.len()).min(haystack.len());
+        let quote_score = similarity(exact, &haystack[start..end]);
+
+        let mut context = Vec::new();
+        if !prefix.is_empty() {
+            let before = &haystack[start.saturating_sub(prefix.len())..start];
+            context.push(similarity(prefix, before));
+        }
+        if !suffix.is_empty() {
+            let after = &haystack[end..(end + suffix.len()).min(haystack.len())];
+            context.push(similarity(suffix, after));
+        }
+        let confidence = match context.len() {
+            0 => quote_score,
+            n => {
+                let context_score = context.iter().sum::<f64>() / n as f64;
+                QUOTE_WEIGHT * quote_score + (1.0 - QUOTE_WEIGHT) * context_score
+            }
+        };
+        QuoteMatch {
+            start,
+            len: end - start,
+            confidence,
+        }
+    };
+
+    candidates
+        .into_iter()
+        .map(score)
+        .fold(None, |best: Option<QuoteMatch>, m| match best {
+            Some(best) if best.confidence >= m.confidence => Some(best),
+            _ => Some(m),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(prefix: &str, exact: &str, suffix: &str) -> TextQuote {
+        TextQuote {
+            prefix: prefix.to_string(),
+            exact: exact.to_string(),
+            suffix: suffix.to_string(),
+        }
+    }
+
+    const TEXT: &str = "It was the best of times, it was the worst of times, it was \
+                        the age of wisdom, it was the age of foolishness, it was the \
+                        epoch of belief, it was the epoch of incredulity.";
+
+    #[test]
+    fn finds_an_exact_quote_with_full_confidence() {
+        let haystack = words(TEXT);
+        let found = find_quote(
+            &haystack,
+            &quote("it was the", "age of foolishness,", "it was the epoch"),
+        )
+        .unwrap();
+        assert_eq!(found.start, 21);
+        assert_eq!(found.len, 3);
+        assert_eq!(found.confidence, 1.0);
+    }
+
+    #[test]
+    fn context_picks_between_repeated_quotes() {
+        let haystack = words(TEXT);
+        let first = find_quote(&haystack, &quote("", "it was the epoch of", "belief")).unwrap();
+        let second =
+            find_quote(&haystack, &quote("", "it was the epoch of", "incredulity")).unwrap();
+        assert_eq!(first.start, 24);
+        assert_eq!(second.start, 30);
+    }
+
+    #[test]
+    fn survives_a_corrected_typo() {
+        let haystack = words(TEXT);
+        let found = find_quote(
+            &haystack,
+            &quote("", "it was the age of wisdomm, it was the age", ""),
+        )
+        .unwrap();
+        assert_eq!(found.start, 12);
+        assert!(found.confidence >= MIN_CONFIDENCE && found.confidence < 1.0);
+    }
+
+    #[test]
+    fn unrelated_text_scores_low_or_not_at_all() {
+        let haystack = words(TEXT);
+        let found = find_quote(&haystack, &quote("", "call me ishmael", ""));
+        assert!(found.is_none());
+
+        let found = find_quote(&haystack, &quote("", "the whale was of times unknown", ""));
+        assert!(found.is_none_or(|m| m.confidence < MIN_CONFIDENCE));
+    }
+}
@@ -0,0 +1,356 @@
+// Read Master Desktop - Reader Sessions
+//
+// An open book in the reader. Sessions serve EPUB resources through the
+// `epub://` protocol (`epub://localhost/<session id>/<href>`) so XHTML can be
+// rewritten on the way out, which is how the user's typography profile is
+// injected without the frontend touching chapter markup.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use log::{info, warn};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State, UriSchemeContext};
+
+use crate::db::Database;
+use crate::epub::typography::{self, StyleSupport, TypographyProfile};
+use crate::epub::EpubArchive;
+use crate::error::AppError;
+use crate::find::FindSessions;
+use crate::library;
+
+/// Custom URI scheme book resources are served from
+pub const PROTOCOL: &str = "epub";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone)]
+struct Session {
+    book_id: i64,
+    path: String,
+    support: StyleSupport,
+    profile: Option<TypographyProfile>,
+}
+
+/// Open reader sessions by id
+#[derive(Default)]
+pub struct ReaderSessions(Mutex<HashMap<String, Session>>);
+
+impl ReaderSessions {
+    /// Book id and file of an open session
+    pub fn book(&self, session_id: &str) -> Option<(i64, String)> {
+        let sessions = self.0.lock().ok()?;
+        let session = sessions.get(session_id)?;
+        Some((session.book_id, session.path.clone()))
+    }
+
+    /// Close every session
+    pub fn clear(&self) {
+        if let Ok(mut sessions) = self.0.lock() {
+            sessions.clear();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReaderSession {
+    pub session_id: String,
+    pub book_id: i64,
+    /// Prefix resource hrefs are resolved against
+    pub base_url: String,
+    pub support: StyleSupport,
+    pub profile: Option<TypographyProfile>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TypographyChanged {
+    session_id: String,
+    book_id: i64,
+    /// Stylesheet to swap into open documents; `None` removes it
+    css: Option<String>,
+    style_element_id: &'static str,
+}
+
+// ============================================================================
+// Profiles
+// ============================================================================
+
+fn load_profile(conn: &Connection, book_id: i64) -> rusqlite::Result<Option<TypographyProfile>> {
+    let json: Option<String> = conn
+        .query_row(
+            "SELECT profile FROM typography_profiles WHERE book_id = ?1",
+            [book_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(json.and_then(|json| match serde_json::from_str(&json) {
+        Ok(profile) => Some(profile),
+        Err(e) => {
+            warn!("Ignoring corrupt typography profile for {}: {}", book_id, e);
+            None
+        }
+    }))
+}
+
+fn save_profile(
+    conn: &Connection,
+    book_id: i64,
+    profile: Option<&TypographyProfile>,
+) -> rusqlite::Result<()> {
+    match profile {
+        Some(profile) => {
+            let json = serde_json::to_string(profile)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+            conn.execute(
+                "INSERT INTO typography_profiles (book_id, profile, updated_at)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT (book_id) DO UPDATE
+                 SET profile = excluded.profile, updated_at = excluded.updated_at",
+                params![book_id, json, chrono::Utc::now().to_rfc3339()],
+            )?;
+        }
+        None => {
+            conn.execute(
+                "DELETE FROM typography_profiles WHERE book_id = ?1",
+                [book_id],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Protocol
+// ============================================================================
+
+fn mime_for(epub: &EpubArchive, path: &str) -> String {
+    if let Some(item) = epub.package.manifest.iter().find(|item| item.path == path) {
+        if !item.media_type.is_empty() {
+            return item.media_type.clone();
+        }
+    }
+
+    let ext = path.rsplit('.').next().unwrap_or("").to_ascii_lowercase();
+    match ext.as_str() {
+        "xhtml" | "xht" => "application/xhtml+xml",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "otf" => "font/otf",
+        "ttf" => "font/ttf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+    .to_string()
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(message.as_bytes().to_vec())
+        .unwrap_or_default()
+}
+
+fn serve(session: &Session, href: &str) -> Result<Response<Vec<u8>>, (StatusCode, String)> {
+    let mut epub = EpubArchive::open(&session.path)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let path = epub.locate(href).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Resource not found in EPUB: {}", href),
+        )
+    })?;
+
+    let mime = mime_for(&epub, &path);
+    let mut body = epub
+        .read_resource(&path)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let is_document = mime == "application/xhtml+xml" || mime == "text/html";
+    if is_document {
+        let css = session
+            .profile
+            .as_ref()
+            .and_then(|p| typography::stylesheet(p, session.support));
+        if let (Some(css), Ok(text)) = (css, std::str::from_utf8(&body)) {
+            body = typography::inject(text, &css).into_bytes();
+        }
+    }
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, mime)
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(body)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Handler for `epub://localhost/<session id>/<href>`
+pub fn handle_protocol<R: Runtime>(
+    ctx: UriSchemeContext<'_, R>,
+    request: Request<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    let path = request.uri().path().trim_start_matches('/');
+    let Some((session_id, href)) = path.split_once('/') else {
+        return error_response(StatusCode::BAD_REQUEST, "Missing resource path");
+    };
+
+    let session = ctx
+        .app_handle()
+        .state::<ReaderSessions>()
+        .0
+        .lock()
+        .ok()
+        .and_then(|sessions| sessions.get(session_id).cloned());
+    let Some(session) = session else {
+        return error_response(StatusCode::NOT_FOUND, "Unknown reader session");
+    };
+
+    match serve(&session, href) {
+        Ok(response) => response,
+        Err((status, message)) => {
+            warn!("Failed to serve {}: {}", href, message);
+            error_response(status, &message)
+        }
+    }
+}
+
+/// Point open sessions at their books' new files after the library has
+/// moved, so open books keep loading
+pub fn relocate_sessions<R: Runtime>(app: &AppHandle<R>, paths: &HashMap<i64, String>) {
+    let Ok(mut sessions) = app.state::<ReaderSessions>().0.lock() else {
+        return;
+    };
+    for session in sessions.values_mut() {
+        if let Some(path) = paths.get(&session.book_id) {
+            session.path = path.clone();
+        }
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Open a book for reading and return its session
+#[tauri::command]
+pub async fn open_reader_session(
+    db: State<'_, Database>,
+    sessions: State<'_, ReaderSessions>,
+    book_id: i64,
+) -> Result<ReaderSession, AppError> {
+    let (book, profile) = db.with_conn(|conn| {
+        Ok((
+            library::get_book(conn, book_id)?,
+            load_profile(conn, book_id)?,
+        ))
+    })?;
+    let book = book.ok_or_else(|| AppError::NotFound {
+        message: format!("Book not found: {}", book_id),
+        details: None,
+    })?;
+    let path = book.path.ok_or_else(|| AppError::NotFound {
+        message: format!("Book {} has no file", book_id),
+        details: None,
+    })?;
+
+    let mut epub = EpubArchive::open(&path)?;
+    let support = typography::style_support(&mut epub);
+
+    let session_id = uuid::Uuid::new_v4().to_string();
+    info!("Opening reader session {} for book {}", session_id, book_id);
+
+    sessions
+        .0
+        .lock()
+        .map_err(|_| "Reader session lock poisoned".to_string())?
+        .insert(
+            session_id.clone(),
+            Session {
+                book_id,
+                path,
+                support,
+                profile: profile.clone(),
+            },
+        );
+
+    Ok(ReaderSession {
+        base_url: format!("{}://localhost/{}/", PROTOCOL, session_id),
+        session_id,
+        book_id,
+        support,
+        profile,
+    })
+}
+
+/// Close a reader session
+#[tauri::command]
+pub fn close_reader_session(
+    sessions: State<'_, ReaderSessions>,
+    finds: State<'_, FindSessions>,
+    session_id: String,
+) -> Result<(), String> {
+    sessions
+        .0
+        .lock()
+        .map_err(|_| "Reader session lock poisoned".to_string())?
+        .remove(&session_id);
+    finds.forget(&session_id);
+    Ok(())
+}
+
+/// Set (or with `null`, clear) the book's typography override. Applies to
+/// documents served from now on; open documents are told to restyle
+/// through a `typography-changed` event.
+#[tauri::command]
+pub async fn set_typography_profile<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    sessions: State<'_, ReaderSessions>,
+    session_id: String,
+    profile: Option<TypographyProfile>,
+) -> Result<(), String> {
+    let session = {
+        let mut sessions = sessions
+            .0
+            .lock()
+            .map_err(|_| "Reader session lock poisoned".to_string())?;
+        let session = sessions
+            .get_mut(&session_id)
+            .ok_or_else(|| format!("Unknown reader session: {}", session_id))?;
+        session.profile = profile.clone();
+        session.clone()
+    };
+
+    info!("Setting typography profile for book {}", session.book_id);
+    db.with_conn(|conn| save_profile(conn, session.book_id, profile.as_ref()))?;
+
+    let css = profile
+        .as_ref()
+        .and_then(|p| typography::stylesheet(p, session.support));
+    if let Err(e) = app.emit(
+        "typography-changed",
+        TypographyChanged {
+            session_id,
+            book_id: session.book_id,
+            css,
+            style_element_id: typography::STYLE_ELEMENT_ID,
+        },
+    ) {
+        warn!("Failed to emit typography-changed: {}", e);
+    }
+
+    Ok(())
+}
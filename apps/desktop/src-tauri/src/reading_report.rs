@@ -0,0 +1,840 @@
+// Read Master Desktop - Year in Books
+//
+// A shareable summary of one year's reading: the books finished, pages,
+// hours read, the longest streak, top genres, and a few favourite quotes.
+// It's written either as a standalone HTML page with the covers embedded,
+// or as a 1200x630 PNG card rendered from an SVG template with resvg.
+//
+// Quotes only come from the user's own highlights, made in the app or on
+// their Kobo or Kindle, never from imported flashcards or clipped pages.
+// A year without any reading still gets a report, just a friendlier one.
+
+use std::collections::{HashMap, HashSet};
+
+use base64::Engine;
+use chrono::{DateTime, NaiveDate, Utc};
+use image::ImageFormat;
+use log::{info, warn};
+use resvg::{tiny_skia, usvg};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::activity;
+use crate::db::Database;
+use crate::images;
+use crate::notes::{self, Note};
+
+/// Books finished within the local year, by date-only or full timestamps
+const FINISHED_SQL: &str = "
+    SELECT b.id, b.title, b.author, b.page_count, COALESCE(MAX(h.rating), b.rating),
+           MAX(h.finished_at), b.cover_path
+    FROM reading_history h JOIN books b ON b.id = h.book_id
+    WHERE b.deleted_at IS NULL
+      AND CASE WHEN length(h.finished_at) = 10
+               THEN h.finished_at >= ?3 AND h.finished_at < ?4
+               ELSE julianday(h.finished_at) >= julianday(?1)
+                    AND julianday(h.finished_at) < julianday(?2)
+          END
+    GROUP BY b.id
+    ORDER BY MAX(h.finished_at)";
+
+/// Reading-status shelves from Goodreads imports, which aren't genres
+const STATUS_SHELVES: &[&str] = &["currently-reading", "read", "to-read"];
+
+const MAX_GENRES: usize = 5;
+
+const MAX_QUOTES: usize = 3;
+
+/// Longer highlights don't make for a shareable quote
+const MAX_QUOTE_CHARS: usize = 280;
+
+/// Cover size in the HTML report, in pixels
+const COVER_WIDTH: u32 = 240;
+const COVER_HEIGHT: u32 = 360;
+
+/// Covers shown on the PNG card
+const CARD_COVERS: usize = 4;
+
+const HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{title}}</title>
+<style>
+body { margin: 0; background: #f6f1e7; color: #2b2620; font-family: Georgia, 'Times New Roman', serif; }
+main { max-width: 760px; margin: 0 auto; padding: 48px 24px 64px; }
+h1 { font-size: 2.6em; margin: 0 0 8px; }
+h2 { font-size: 1.3em; margin: 40px 0 16px; padding-bottom: 6px; border-bottom: 1px solid #d9cfbd; }
+.stats { display: grid; grid-template-columns: repeat(auto-fit, minmax(140px, 1fr)); gap: 16px; margin-top: 32px; }
+.stat { background: #fffdf8; border-radius: 10px; padding: 16px; text-align: center; }
+.stat b { display: block; font-size: 2em; }
+.stat span, .author, .rating, blockquote footer { color: #7a6f60; font-size: 0.9em; }
+.books { list-style: none; padding: 0; display: grid; grid-template-columns: repeat(auto-fill, minmax(140px, 1fr)); gap: 20px; }
+.books img, .books .placeholder { display: block; width: 100%; aspect-ratio: 2 / 3; object-fit: cover; border-radius: 4px; background: #d9cfbd; box-shadow: 0 2px 6px rgba(0, 0, 0, 0.2); }
+.book-title { font-weight: bold; margin-top: 8px; }
+.genres { list-style: none; padding: 0; display: flex; flex-wrap: wrap; gap: 8px; }
+.genres li { background: #2b2620; color: #f6f1e7; border-radius: 999px; padding: 4px 14px; }
+blockquote { margin: 0 0 24px; padding-left: 20px; border-left: 3px solid #b08d57; font-style: italic; }
+blockquote footer { font-style: normal; margin-top: 6px; }
+.empty { text-align: center; padding: 64px 0; }
+.made-with { margin-top: 56px; text-align: center; color: #a39888; font-size: 0.85em; }
+</style>
+</head>
+<body>
+<main>
+{{content}}
+<p class="made-with">Made with Read Master</p>
+</main>
+</body>
+</html>
+"#;
+
+const SVG_TEMPLATE: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="1200" height="630" viewBox="0 0 1200 630">
+<defs>
+<linearGradient id="bg" x1="0" y1="0" x2="1" y2="1">
+<stop offset="0" stop-color="#2b2620"/>
+<stop offset="1" stop-color="#4a3b2a"/>
+</linearGradient>
+</defs>
+<rect width="1200" height="630" fill="url(#bg)"/>
+<text x="64" y="112" font-family="Georgia, serif" font-size="56" font-weight="bold" fill="#f6f1e7">{{title}}</text>
+{{content}}
+<text x="64" y="590" font-family="sans-serif" font-size="20" fill="#a39888">Read Master</text>
+</svg>
+"##;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReportFormat {
+    /// Standalone page with the covers embedded
+    Html,
+    /// Social card image
+    Png,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportBook {
+    pub id: i64,
+    pub title: String,
+    pub author: Option<String>,
+    pub page_count: Option<i64>,
+    pub rating: Option<i64>,
+    pub finished_at: String,
+    pub tags: Vec<String>,
+    #[serde(skip)]
+    pub cover_path: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenreCount {
+    pub tag: String,
+    pub books: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportQuote {
+    pub text: String,
+    pub book_title: String,
+    /// The user's own note on the highlight
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct YearInBooks {
+    pub year: i32,
+    pub books: Vec<ReportBook>,
+    pub pages: i64,
+    pub minutes: f64,
+    pub active_days: u32,
+    pub longest_streak: u32,
+    pub genres: Vec<GenreCount>,
+    pub quotes: Vec<ReportQuote>,
+}
+
+impl YearInBooks {
+    /// Nothing finished, read, or highlighted all year
+    pub fn is_empty(&self) -> bool {
+        self.books.is_empty() && self.minutes < 1.0 && self.quotes.is_empty()
+    }
+}
+
+// ============================================================================
+// Gathering
+// ============================================================================
+
+/// First day of `year` and of the year after
+fn year_bounds(year: i32) -> Option<(NaiveDate, NaiveDate)> {
+    Some((
+        NaiveDate::from_ymd_opt(year, 1, 1)?,
+        NaiveDate::from_ymd_opt(year.checked_add(1)?, 1, 1)?,
+    ))
+}
+
+fn finished_books(
+    conn: &Connection,
+    (first, next): (NaiveDate, NaiveDate),
+    (from, to): (DateTime<Utc>, DateTime<Utc>),
+) -> rusqlite::Result<Vec<ReportBook>> {
+    let mut stmt = conn.prepare(FINISHED_SQL)?;
+    let mut books = stmt
+        .query_map(
+            params![
+                from.to_rfc3339(),
+                to.to_rfc3339(),
+                first.to_string(),
+                next.to_string()
+            ],
+            |row| {
+                Ok(ReportBook {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    author: row.get(2)?,
+                    page_count: row.get(3)?,
+                    rating: row.get(4)?,
+                    finished_at: row.get(5)?,
+                    tags: Vec::new(),
+                    cover_path: row.get(6)?,
+                })
+            },
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut tags = conn.prepare("SELECT tag FROM book_tags WHERE book_id = ?1 ORDER BY tag")?;
+    for book in &mut books {
+        book.tags = tags
+            .query_map([book.id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+    }
+    Ok(books)
+}
+
+fn book_titles(conn: &Connection) -> rusqlite::Result<HashMap<String, String>> {
+    let mut stmt = conn.prepare("SELECT id, title FROM books WHERE deleted_at IS NULL")?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?.to_string(), row.get::<_, String>(1)?))
+    })?;
+    rows.collect()
+}
+
+/// Most common tags among `books`, leaving out reading-status shelves
+fn top_genres(books: &[ReportBook]) -> Vec<GenreCount> {
+    let mut counts: HashMap<&str, u32> = HashMap::new();
+    for tag in books.iter().flat_map(|book| &book.tags) {
+        if !STATUS_SHELVES.contains(&tag.as_str()) {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    let mut genres: Vec<GenreCount> = counts
+        .into_iter()
+        .map(|(tag, books)| GenreCount {
+            tag: tag.to_string(),
+            books,
+        })
+        .collect();
+    genres.sort_by(|a, b| b.books.cmp(&a.books).then_with(|| a.tag.cmp(&b.tag)));
+    genres.truncate(MAX_GENRES);
+    genres
+}
+
+/// A highlight the user made, in the app or on their own e-reader
+fn is_own_highlight(note: &Note) -> bool {
+    note.book_id.is_some()
+        && note.quote.as_deref().is_some_and(|q| !q.trim().is_empty())
+        && matches!(note.source.as_deref(), None | Some("kobo" | "kindle"))
+}
+
+/// Favourite quotes from highlights made in `[from, to)`: ones the user
+/// wrote a note on first, then the most recent, one per book
+fn pick_quotes(
+    notes: &[Note],
+    titles: &HashMap<String, String>,
+    (from, to): (DateTime<Utc>, DateTime<Utc>),
+) -> Vec<ReportQuote> {
+    let mut candidates: Vec<(&Note, &str, &String)> = notes
+        .iter()
+        .filter(|note| is_own_highlight(note))
+        .filter(|note| {
+            DateTime::parse_from_rfc3339(&note.created_at).is_ok_and(|at| at >= from && at < to)
+        })
+        .filter_map(|note| {
+            let quote = note.quote.as_deref()?.trim();
+            let title = titles.get(note.book_id.as_deref()?)?;
+            (quote.chars().count() <= MAX_QUOTE_CHARS).then_some((note, quote, title))
+        })
+        .collect();
+    candidates.sort_by(|(a, _, _), (b, _, _)| {
+        a.body
+            .trim()
+            .is_empty()
+            .cmp(&b.body.trim().is_empty())
+            .then_with(|| b.created_at.cmp(&a.created_at))
+    });
+
+    let mut books = HashSet::new();
+    candidates
+        .into_iter()
+        .filter(|(note, _, _)| books.insert(note.book_id.clone()))
+        .take(MAX_QUOTES)
+        .map(|(note, quote, title)| ReportQuote {
+            text: quote.to_string(),
+            book_title: title.clone(),
+            note: Some(note.body.trim().to_string()).filter(|body| !body.is_empty()),
+        })
+        .collect()
+}
+
+fn gather<R: Runtime>(app: &AppHandle<R>, year: i32) -> Result<YearInBooks, String> {
+    let (first, next) = year_bounds(year).ok_or_else(|| format!("Invalid year {}", year))?;
+    let last = next.pred_opt().unwrap_or(first);
+    let span = (
+        activity::local_midnight(first),
+        activity::local_midnight(next),
+    );
+
+    let db = app.state::<Database>();
+    let (books, days, titles) = db.with_conn(|conn| {
+        Ok((
+            finished_books(conn, (first, next), span)?,
+            activity::daily_reading(conn, first, last)?,
+            book_titles(conn)?,
+        ))
+    })?;
+    let quotes = pick_quotes(&notes::load_all(app)?, &titles, span);
+
+    Ok(YearInBooks {
+        year,
+        pages: books.iter().filter_map(|book| book.page_count).sum(),
+        minutes: days.iter().map(|day| day.value).sum(),
+        active_days: days.iter().filter(|day| day.value > 0.0).count() as u32,
+        longest_streak: activity::longest_streak(&days),
+        genres: top_genres(&books),
+        books,
+        quotes,
+    })
+}
+
+// ============================================================================
+// Rendering
+// ============================================================================
+
+/// Escape text for HTML and SVG alike
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Replace each `{{name}}` slot in `template`
+fn fill(template: &str, slots: &[(&str, &str)]) -> String {
+    slots
+        .iter()
+        .fold(template.to_string(), |out, (name, value)| {
+            out.replace(&format!("{{{{{}}}}}", name), value)
+        })
+}
+
+fn format_count(n: i64) -> String {
+    let digits = n.unsigned_abs().to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    if n < 0 {
+        out.insert(0, '-');
+    }
+    out
+}
+
+/// Hours read, with a decimal while there are only a few
+fn format_hours(minutes: f64) -> String {
+    let hours = minutes / 60.0;
+    if hours < 10.0 {
+        format!("{:.1}", hours)
+    } else {
+        format_count(hours.round() as i64)
+    }
+}
+
+/// Word-wrap `text` to lines of at most `width` characters, ending with
+/// an ellipsis if it needs more than `max_lines`
+fn wrap(text: &str, width: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            let kept: String = last.chars().take(width.saturating_sub(1)).collect();
+            *last = format!("{}…", kept.trim_end());
+        }
+    }
+    lines
+}
+
+/// A cover scaled down and embedded as a data URL
+fn cover_data_url(path: &str, width: u32, height: u32) -> Option<String> {
+    let encoded = std::fs::read(path)
+        .map_err(|e| format!("Failed to read cover: {}", e))
+        .and_then(|data| {
+            image::load_from_memory(&data).map_err(|e| format!("Failed to decode cover: {}", e))
+        })
+        .and_then(|cover| images::encode(&cover.thumbnail(width, height), ImageFormat::Jpeg));
+    match encoded {
+        Ok(data) => Some(format!(
+            "data:image/jpeg;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(data)
+        )),
+        Err(e) => {
+            warn!("Leaving out cover {}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn covers(books: &[ReportBook], limit: usize) -> HashMap<i64, String> {
+    books
+        .iter()
+        .take(limit)
+        .filter_map(|book| {
+            let path = book.cover_path.as_deref()?;
+            Some((book.id, cover_data_url(path, COVER_WIDTH, COVER_HEIGHT)?))
+        })
+        .collect()
+}
+
+fn stars(rating: i64) -> String {
+    let filled = rating.clamp(0, 5) as usize;
+    format!("{}{}", "★".repeat(filled), "☆".repeat(5 - filled))
+}
+
+fn render_html(report: &YearInBooks, covers: &HashMap<i64, String>) -> String {
+    let title = format!("My {} in Books", report.year);
+    let mut content = format!("<h1>{}</h1>\n", escape(&title));
+
+    if report.is_empty() {
+        content.push_str(&format!(
+            "<section class=\"empty\">\n<h2>No finished books in {} yet</h2>\n\
+             <p>Every reading year starts with a first page. Pick up a book and \
+             this page will fill itself in.</p>\n</section>\n",
+            report.year
+        ));
+        return fill(
+            HTML_TEMPLATE,
+            &[("title", &escape(&title)), ("content", &content)],
+        );
+    }
+
+    content.push_str("<section class=\"stats\">\n");
+    for (value, label) in [
+        (format_count(report.books.len() as i64), "books finished"),
+        (format_count(report.pages), "pages"),
+        (format_hours(report.minutes), "hours read"),
+        (format_count(report.longest_streak.into()), "day streak"),
+    ] {
+        content.push_str(&format!(
+            "<div class=\"stat\"><b>{}</b><span>{}</span></div>\n",
+            value, label
+        ));
+    }
+    content.push_str("</section>\n");
+
+    if !report.books.is_empty() {
+        content.push_str("<h2>Books</h2>\n<ul class=\"books\">\n");
+        for book in &report.books {
+            let cover = match covers.get(&book.id) {
+                Some(url) => format!("<img src=\"{}\" alt=\"\">", url),
+                None => "<div class=\"placeholder\"></div>".to_string(),
+            };
+            content.push_str(&format!(
+                "<li>{}<div class=\"book-title\">{}</div>",
+                cover,
+                escape(&book.title)
+            ));
+            if let Some(author) = &book.author {
+                content.push_str(&format!("<div class=\"author\">{}</div>", escape(author)));
+            }
+            if let Some(rating) = book.rating.filter(|r| *r > 0) {
+                content.push_str(&format!("<div class=\"rating\">{}</div>", stars(rating)));
+            }
+            content.push_str("</li>\n");
+        }
+        content.push_str("</ul>\n");
+    }
+
+    if !report.genres.is_empty() {
+        content.push_str("<h2>Top genres</h2>\n<ul class=\"genres\">\n");
+        for genre in &report.genres {
+            content.push_str(&format!("<li>{}</li>\n", escape(&genre.tag)));
+        }
+        content.push_str("</ul>\n");
+    }
+
+    if !report.quotes.is_empty() {
+        content.push_str("<h2>Favourite quotes</h2>\n");
+        for quote in &report.quotes {
+            content.push_str(&format!(
+                "<blockquote><p>{}</p><footer>{}{}</footer></blockquote>\n",
+                escape(&quote.text),
+                escape(&quote.book_title),
+                quote
+                    .note
+                    .as_deref()
+                    .map(|note| format!(" · {}", escape(note)))
+                    .unwrap_or_default()
+            ));
+        }
+    }
+
+    fill(
+        HTML_TEMPLATE,
+        &[("title", &escape(&title)), ("content", &content)],
+    )
+}
+
+fn svg_text(x: u32, y: u32, size: u32, style: &str, text: &str) -> String {
+    format!(
+        "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{}\" {}>{}</text>\n",
+        x,
+        y,
+        size,
+        style,
+        escape(text)
+    )
+}
+
+fn render_svg(report: &YearInBooks, covers: &HashMap<i64, String>) -> String {
+    let title = format!("My {} in Books", report.year);
+    let light = "fill=\"#f6f1e7\"";
+    let muted = "fill=\"#a39888\"";
+    let mut content = String::new();
+
+    if report.is_empty() {
+        content.push_str(&svg_text(64, 280, 44, light, "No finished books yet"));
+        content.push_str(&svg_text(
+            64,
+            330,
+            26,
+            muted,
+            "Every reading year starts with a first page.",
+        ));
+        return fill(
+            SVG_TEMPLATE,
+            &[("title", &escape(&title)), ("content", &content)],
+        );
+    }
+
+    let stats = [
+        (format_count(report.books.len() as i64), "books"),
+        (format_count(report.pages), "pages"),
+        (format_hours(report.minutes), "hours"),
+        (format_count(report.longest_streak.into()), "day streak"),
+    ];
+    for (i, (value, label)) in stats.iter().enumerate() {
+        let x = 64 + i as u32 * 175;
+        content.push_str(&svg_text(
+            x,
+            230,
+            52,
+            &format!("{} font-weight=\"bold\"", light),
+            value,
+        ));
+        content.push_str(&svg_text(x, 262, 20, muted, label));
+    }
+
+    if !report.genres.is_empty() {
+        let genres: Vec<&str> = report.genres.iter().map(|g| g.tag.as_str()).collect();
+        let line = wrap(&format!("Top genres: {}", genres.join(" · ")), 52, 1);
+        content.push_str(&svg_text(64, 330, 24, light, &line.concat()));
+    }
+
+    if let Some(quote) = report.quotes.first() {
+        let lines = wrap(&format!("“{}”", quote.text), 46, 4);
+        let mut y = 400;
+        for line in &lines {
+            content.push_str(&svg_text(
+                64,
+                y,
+                26,
+                &format!("{} font-style=\"italic\"", light),
+                line,
+            ));
+            y += 34;
+        }
+        content.push_str(&svg_text(
+            64,
+            y + 4,
+            20,
+            muted,
+            &format!("— {}", quote.book_title),
+        ));
+    }
+
+    let shown = report.books.iter().filter_map(|book| covers.get(&book.id));
+    for (i, url) in shown.take(CARD_COVERS).enumerate() {
+        let x = 860 + (i as u32 % 2) * 150;
+        let y = 150 + (i as u32 / 2) * 210;
+        content.push_str(&format!(
+            "<image x=\"{}\" y=\"{}\" width=\"130\" height=\"195\" \
+             preserveAspectRatio=\"xMidYMid slice\" href=\"{}\"/>\n",
+            x, y, url
+        ));
+    }
+
+    fill(
+        SVG_TEMPLATE,
+        &[("title", &escape(&title)), ("content", &content)],
+    )
+}
+
+fn render_png(svg: &str) -> Result<Vec<u8>, String> {
+    let mut options = usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = usvg::Tree::from_str(svg, &options)
+        .map_err(|e| format!("Failed to parse report card: {}", e))?;
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| "Failed to allocate report card".to_string())?;
+    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap
+        .encode_png()
+        .map_err(|e| format!("Failed to encode report card: {}", e))
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Write a "year in books" report for `year` to `out_path`, and return the
+/// numbers it was made from
+#[tauri::command]
+pub async fn generate_reading_report<R: Runtime>(
+    app: AppHandle<R>,
+    year: i32,
+    format: ReportFormat,
+    out_path: String,
+) -> Result<YearInBooks, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let report = gather(&app, year)?;
+        let data = match format {
+            ReportFormat::Html => {
+                render_html(&report, &covers(&report.books, report.books.len())).into_bytes()
+            }
+            ReportFormat::Png => {
+                render_png(&render_svg(&report, &covers(&report.books, CARD_COVERS)))?
+            }
+        };
+        std::fs::write(&out_path, data).map_err(|e| format!("Failed to write report: {}", e))?;
+        info!(
+            "Wrote {} reading report to {} ({} books)",
+            year,
+            out_path,
+            report.books.len()
+        );
+        Ok(report)
+    })
+    .await
+    .map_err(|e| format!("Report task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn note(book_id: &str, quote: &str, body: &str, source: Option<&str>, at: &str) -> Note {
+        serde_json::from_value(serde_json::json!({
+            "id": uuid::Uuid::new_v4().to_string(),
+            "bookId": book_id,
+            "locator": null,
+            "quote": quote,
+            "body": body,
+            "color": null,
+            "source": source,
+            "createdAt": at,
+            "updatedAt": at,
+        }))
+        .unwrap()
+    }
+
+    fn book(id: i64, tags: &[&str]) -> ReportBook {
+        ReportBook {
+            id,
+            title: format!("Book {}", id),
+            author: None,
+            page_count: Some(100),
+            rating: None,
+            finished_at: "2025-03-01".to_string(),
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            cover_path: None,
+        }
+    }
+
+    fn report(books: Vec<ReportBook>) -> YearInBooks {
+        YearInBooks {
+            year: 2025,
+            books,
+            pages: 0,
+            minutes: 0.0,
+            active_days: 0,
+            longest_streak: 0,
+            genres: Vec::new(),
+            quotes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn counts_books_finished_within_the_year() {
+        let conn = crate::db::test_connection();
+        conn.execute_batch(
+            "INSERT INTO books (id, title, page_count, added_at, updated_at) VALUES
+                (1, 'Early', 200, '', ''), (2, 'Late', 300, '', ''),
+                (3, 'Last year', 100, '', '');
+            INSERT INTO books (id, title, deleted_at, added_at, updated_at) VALUES
+                (4, 'Trashed', '2025-06-01', '', '');
+            INSERT INTO book_tags (book_id, tag) VALUES (1, 'fantasy'), (1, 'read');
+            INSERT INTO reading_history (book_id, finished_at, rating, source) VALUES
+                (1, '2025-01-01', 4, 'app'), (2, '2025-12-31T22:00:00+00:00', NULL, 'app'),
+                (3, '2024-12-31', NULL, 'app'), (4, '2025-05-01', NULL, 'app');",
+        )
+        .unwrap();
+
+        let (first, next) = year_bounds(2025).unwrap();
+        let span = (
+            utc("2025-01-01T00:00:00+00:00"),
+            utc("2026-01-01T00:00:00+00:00"),
+        );
+        let books = finished_books(&conn, (first, next), span).unwrap();
+        let titles: Vec<&str> = books.iter().map(|b| b.title.as_str()).collect();
+        assert_eq!(titles, ["Early", "Late"]);
+        assert_eq!(books[0].rating, Some(4));
+        assert_eq!(books[0].tags, ["fantasy", "read"]);
+    }
+
+    #[test]
+    fn genres_leave_out_reading_status_shelves() {
+        let books = [
+            book(1, &["read", "fantasy", "classics"]),
+            book(2, &["fantasy", "to-read"]),
+            book(3, &["currently-reading", "history"]),
+        ];
+        let genres: Vec<(String, u32)> = top_genres(&books)
+            .into_iter()
+            .map(|g| (g.tag, g.books))
+            .collect();
+        assert_eq!(
+            genres,
+            [
+                ("fantasy".to_string(), 2),
+                ("classics".to_string(), 1),
+                ("history".to_string(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn quotes_are_the_users_own_highlights_from_the_year() {
+        let titles = HashMap::from([
+            ("1".to_string(), "One".to_string()),
+            ("2".to_string(), "Two".to_string()),
+        ]);
+        let notes = [
+            note("1", "Recent", "", None, "2025-09-01T10:00:00Z"),
+            note(
+                "1",
+                "Annotated",
+                "So true",
+                Some("kobo"),
+                "2025-02-01T10:00:00Z",
+            ),
+            note(
+                "2",
+                "From a card deck",
+                "",
+                Some("anki"),
+                "2025-05-01T10:00:00Z",
+            ),
+            note("2", "Last year", "Noted", None, "2024-05-01T10:00:00Z"),
+            note("3", "Deleted book", "", None, "2025-05-01T10:00:00Z"),
+            note("2", "Kindle", "", Some("kindle"), "2025-04-01T10:00:00Z"),
+        ];
+
+        let quotes = pick_quotes(
+            &notes,
+            &titles,
+            (utc("2025-01-01T00:00:00Z"), utc("2026-01-01T00:00:00Z")),
+        );
+        let picked: Vec<(&str, &str)> = quotes
+            .iter()
+            .map(|q| (q.text.as_str(), q.book_title.as_str()))
+            .collect();
+        assert_eq!(picked, [("Annotated", "One"), ("Kindle", "Two")]);
+        assert_eq!(quotes[0].note.as_deref(), Some("So true"));
+    }
+
+    #[test]
+    fn wraps_and_truncates_lines() {
+        assert_eq!(
+            wrap("one two three four", 9, 3),
+            ["one two", "three", "four"]
+        );
+        assert_eq!(wrap("one two three four", 9, 2), ["one two", "three…"]);
+        assert!(wrap("   ", 10, 2).is_empty());
+    }
+
+    #[test]
+    fn formats_numbers() {
+        assert_eq!(format_count(1234567), "1,234,567");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_hours(90.0), "1.5");
+        assert_eq!(format_hours(6000.0), "100");
+    }
+
+    #[test]
+    fn empty_years_get_a_friendly_page() {
+        let html = render_html(&report(Vec::new()), &HashMap::new());
+        assert!(html.contains("No finished books in 2025 yet"));
+        assert!(!html.contains("{{"));
+
+        let svg = render_svg(&report(Vec::new()), &HashMap::new());
+        assert!(svg.contains("No finished books yet"));
+    }
+
+    #[test]
+    fn escapes_book_text() {
+        let mut book = book(1, &[]);
+        book.title = "<script>alert('x')</script>".to_string();
+        let html = render_html(&report(vec![book]), &HashMap::new());
+        assert!(html.contains("&lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt;"));
+        assert!(!html.contains("<script>"));
+    }
+}
@@ -0,0 +1,222 @@
+// Read Master Desktop - Recent Files
+//
+// Books opened from disk, newest first, for the File > Open Recent menu.
+// The frontend records each file it opens; entries can be pinned, which
+// lists them ahead of the rest and exempts them from both the size cap and
+// Clear Recent. The list lives in its own store and the menu is rebuilt
+// whenever it changes.
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Runtime};
+
+use crate::profiles::ProfileStoreExt;
+use crate::{menu, persist};
+
+const STORE_FILE: &str = "recent.json";
+const STORE_KEY: &str = "files";
+
+/// Unpinned entries kept; pinned entries don't count
+pub const MAX_RECENT: usize = 10;
+
+/// Menu item ids for recent files are this prefix plus the path
+pub const MENU_PREFIX: &str = "recent:";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentFile {
+    pub path: String,
+    /// File name shown in the menu
+    pub name: String,
+    pub opened_at: String,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+// ============================================================================
+// List operations
+// ============================================================================
+
+fn file_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Move `path` to the front (keeping its pin), then drop the oldest
+/// unpinned entries past the cap
+fn record(files: &mut Vec<RecentFile>, path: &str, now: &str) {
+    let pinned = files.iter().any(|file| file.path == path && file.pinned);
+    files.retain(|file| file.path != path);
+    files.insert(
+        0,
+        RecentFile {
+            path: path.to_string(),
+            name: file_name(path),
+            opened_at: now.to_string(),
+            pinned,
+        },
+    );
+
+    trim(files);
+}
+
+/// Drop unpinned entries past the cap, oldest first
+fn trim(files: &mut Vec<RecentFile>) {
+    files.sort_by(|a, b| b.opened_at.cmp(&a.opened_at));
+    let mut unpinned = 0;
+    files.retain(|file| {
+        if file.pinned {
+            return true;
+        }
+        unpinned += 1;
+        unpinned <= MAX_RECENT
+    });
+}
+
+/// Pinned entries first, each group newest first
+fn ordered(mut files: Vec<RecentFile>) -> Vec<RecentFile> {
+    // Stable, so recency order holds within each group
+    files.sort_by_key(|file| !file.pinned);
+    files
+}
+
+// ============================================================================
+// Store
+// ============================================================================
+
+/// Recent files as listed in the menu, pinned first
+pub fn load<R: Runtime>(app: &AppHandle<R>) -> Vec<RecentFile> {
+    let files = app
+        .profile_store(STORE_FILE)
+        .ok()
+        .and_then(|store| store.get(STORE_KEY))
+        .and_then(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| warn!("Ignoring corrupt recent files: {}", e))
+                .ok()
+        })
+        .unwrap_or_default();
+    ordered(files)
+}
+
+/// Write the list, rebuild the menu, and tell windows it changed
+fn save<R: Runtime>(app: &AppHandle<R>, files: &[RecentFile]) -> Result<(), String> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open recent files: {}", e))?;
+    store.set(STORE_KEY, serde_json::json!(files));
+    persist::mark_dirty(app, STORE_FILE);
+
+    if let Err(e) = menu::refresh(app) {
+        warn!("Failed to rebuild menu: {}", e);
+    }
+    let _ = app.emit("recent-files-changed", ordered(files.to_vec()));
+    Ok(())
+}
+
+/// Drop unpinned entries. Shared by the command and the menu item.
+pub fn clear<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let mut files = load(app);
+    files.retain(|file| file.pinned);
+    info!("Cleared recent files ({} pinned kept)", files.len());
+    save(app, &files)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Recent files, pinned first
+#[tauri::command]
+pub fn get_recent_files<R: Runtime>(app: AppHandle<R>) -> Vec<RecentFile> {
+    load(&app)
+}
+
+/// Note that `path` was just opened
+#[tauri::command]
+pub fn add_recent_file<R: Runtime>(app: AppHandle<R>, path: String) -> Result<(), String> {
+    let mut files = load(&app);
+    record(&mut files, &path, &chrono::Utc::now().to_rfc3339());
+    save(&app, &files)
+}
+
+/// Pin or unpin a recent file
+#[tauri::command]
+pub fn pin_recent_file<R: Runtime>(
+    app: AppHandle<R>,
+    path: String,
+    pinned: bool,
+) -> Result<(), String> {
+    let mut files = load(&app);
+    let file = files
+        .iter_mut()
+        .find(|file| file.path == path)
+        .ok_or_else(|| format!("Not a recent file: {}", path))?;
+    file.pinned = pinned;
+    // Unpinning may push the list past the cap
+    trim(&mut files);
+    save(&app, &files)
+}
+
+/// Forget every recent file that isn't pinned
+#[tauri::command]
+pub fn clear_recent_files<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
+    clear(&app)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(files: &[RecentFile]) -> Vec<&str> {
+        files.iter().map(|file| file.path.as_str()).collect()
+    }
+
+    #[test]
+    fn records_newest_first_without_duplicates() {
+        let mut files = Vec::new();
+        record(&mut files, "/books/a.epub", "1");
+        record(&mut files, "/books/b.pdf", "2");
+        record(&mut files, "/books/a.epub", "3");
+
+        assert_eq!(paths(&files), ["/books/a.epub", "/books/b.pdf"]);
+        assert_eq!(files[0].name, "a.epub");
+        assert_eq!(files[0].opened_at, "3");
+    }
+
+    #[test]
+    fn cap_never_evicts_pinned_entries() {
+        let mut files = Vec::new();
+        record(&mut files, "/pinned.epub", "0");
+        files[0].pinned = true;
+        for i in 1..=MAX_RECENT + 2 {
+            record(&mut files, &format!("/{}.epub", i), &format!("{:02}", i));
+        }
+
+        assert_eq!(files.len(), MAX_RECENT + 1);
+        assert!(files.iter().any(|file| file.path == "/pinned.epub"));
+        assert!(!files.iter().any(|file| file.path == "/1.epub"));
+        assert!(!files.iter().any(|file| file.path == "/2.epub"));
+
+        // Reopening a pinned file keeps it pinned
+        record(&mut files, "/pinned.epub", "99");
+        assert!(files[0].pinned);
+    }
+
+    #[test]
+    fn orders_pinned_first() {
+        let mut files = Vec::new();
+        for (i, path) in ["/c.epub", "/b.epub", "/a.epub"].iter().enumerate() {
+            record(&mut files, path, &i.to_string());
+        }
+        files[2].pinned = true;
+
+        assert_eq!(paths(&ordered(files)), ["/c.epub", "/a.epub", "/b.epub"]);
+    }
+}
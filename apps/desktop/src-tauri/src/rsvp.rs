@@ -0,0 +1,402 @@
+// Read Master Desktop - Speed Reading
+//
+// Word frames for RSVP (one word at a time) reading. Chapter text is split
+// into words natively, each with its optimal recognition point (the letter
+// the eye fixes on) and a display time at the requested speed, lengthened
+// for long words, clause and sentence punctuation, and paragraph ends.
+//
+// `get_rsvp_frames` returns the first batch and keeps streaming further
+// batches as `rsvp-frames` events, staying up to half a minute of reading
+// ahead of playback, across chapters until the book ends. A new request or
+// `stop_rsvp_frames` ends the previous stream, so pausing stops it and
+// resuming requests frames from the paused frame's position.
+//
+// Positions are a spine index and a character offset into the chapter's
+// plain text, the same text `tts::autoscroll` anchors into.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::db::Database;
+use crate::epub::EpubArchive;
+use crate::library;
+
+pub const MIN_WPM: u32 = 100;
+pub const MAX_WPM: u32 = 1500;
+
+/// Largest batch one request or event carries
+const MAX_BATCH: usize = 2_000;
+
+/// Reading time streamed ahead of playback
+const LEAD: Duration = Duration::from_secs(30);
+
+/// The stream that may still emit; older streams stop at their next batch
+static CURRENT_STREAM: AtomicU64 = AtomicU64::new(0);
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RsvpPosition {
+    /// Spine index
+    pub chapter: usize,
+    /// Character offset of the word in the chapter's text
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RsvpFrame {
+    pub word: String,
+    /// Character index of the optimal recognition point in `word`
+    pub orp: usize,
+    pub duration_ms: u32,
+    pub position: RsvpPosition,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RsvpBatch {
+    pub book_id: i64,
+    /// Identifies the stream, so events from a replaced one can be ignored
+    pub stream: u64,
+    pub frames: Vec<RsvpFrame>,
+    /// Where the next batch starts, or `None` once the book has ended
+    pub next: Option<RsvpPosition>,
+}
+
+/// A word in chapter text
+#[derive(Debug, Clone, PartialEq)]
+struct Word<'a> {
+    text: &'a str,
+    /// Character offset in the chapter
+    offset: usize,
+    paragraph_end: bool,
+}
+
+// ============================================================================
+// Frames
+// ============================================================================
+
+/// Whitespace-separated words, with character offsets
+fn words(text: &str) -> Vec<Word<'_>> {
+    let mut words = Vec::new();
+    let mut start: Option<(usize, usize)> = None;
+    let mut chars = 0;
+
+    for (byte, c) in text.char_indices() {
+        if c.is_whitespace() {
+            if let Some((start_byte, offset)) = start.take() {
+                words.push(Word {
+                    text: &text[start_byte..byte],
+                    offset,
+                    paragraph_end: false,
+                });
+            }
+            if c == '\n' {
+                if let Some(last) = words.last_mut() {
+                    last.paragraph_end = true;
+                }
+            }
+        } else if start.is_none() {
+            start = Some((byte, chars));
+        }
+        chars += 1;
+    }
+    if let Some((start_byte, offset)) = start {
+        words.push(Word {
+            text: &text[start_byte..],
+            offset,
+            paragraph_end: true,
+        });
+    }
+    if let Some(last) = words.last_mut() {
+        last.paragraph_end = true;
+    }
+    words
+}
+
+/// Letter the eye should fix on: a little left of center, after any
+/// leading punctuation
+fn orp(word: &str) -> usize {
+    let chars: Vec<char> = word.chars().collect();
+    let lead = chars.iter().take_while(|c| !c.is_alphanumeric()).count();
+    let letters = chars[lead..].iter().filter(|c| c.is_alphanumeric()).count();
+    let point = match letters {
+        0 | 1 => 0,
+        2..=5 => 1,
+        6..=9 => 2,
+        10..=13 => 3,
+        _ => 4,
+    };
+    (lead + point).min(chars.len().saturating_sub(1))
+}
+
+/// Display time of a word at `wpm`
+fn duration_ms(word: &Word<'_>, wpm: u32) -> u32 {
+    let base = 60_000.0 / f64::from(wpm);
+    let letters = word.text.chars().filter(|c| c.is_alphanumeric()).count();
+    let end = word
+        .text
+        .trim_end_matches(['"', '\'', '”', '’', ')', ']', '»'])
+        .chars()
+        .last();
+
+    let mut factor = 1.0;
+    if letters > 8 {
+        factor += (letters - 8) as f64 * 0.05;
+    }
+    factor += match end {
+        _ if word.paragraph_end => 1.5,
+        Some('.' | '!' | '?' | '…') => 1.0,
+        Some(',' | ';' | ':' | '—' | '–') => 0.5,
+        _ => 0.0,
+    };
+    (base * factor).round() as u32
+}
+
+fn frames_in(
+    chapter: usize,
+    text: &str,
+    from_offset: usize,
+    wpm: u32,
+) -> impl Iterator<Item = RsvpFrame> + '_ {
+    words(text)
+        .into_iter()
+        .filter(move |word| word.offset >= from_offset)
+        .map(move |word| RsvpFrame {
+            word: word.text.to_string(),
+            orp: orp(word.text),
+            duration_ms: duration_ms(&word, wpm),
+            position: RsvpPosition {
+                chapter,
+                offset: word.offset,
+            },
+        })
+}
+
+/// A book's chapters, read one at a time as frames are needed
+struct Book {
+    epub: EpubArchive,
+    chapters: usize,
+    /// Text of the chapter last read, by spine index
+    current: Option<(usize, String)>,
+}
+
+impl Book {
+    fn open(path: &str) -> Result<Self, String> {
+        let epub = EpubArchive::open(path).map_err(|e| e.to_string())?;
+        let chapters = epub.spine_paths().len();
+        Ok(Self {
+            epub,
+            chapters,
+            current: None,
+        })
+    }
+
+    fn chapter_text(&mut self, index: usize) -> &str {
+        if self.current.as_ref().map(|(i, _)| *i) != Some(index) {
+            let text = match self.epub.chapter_text(index) {
+                Ok(chapter) => chapter.text,
+                Err(e) => {
+                    warn!("Skipping chapter {}: {}", index, e);
+                    String::new()
+                }
+            };
+            self.current = Some((index, text));
+        }
+        self.current.as_ref().map_or("", |(_, text)| text.as_str())
+    }
+
+    /// Up to `count` frames from `from`, and where the next ones start
+    fn frames(
+        &mut self,
+        from: RsvpPosition,
+        count: usize,
+        wpm: u32,
+    ) -> (Vec<RsvpFrame>, Option<RsvpPosition>) {
+        let mut frames = Vec::new();
+        let mut position = from;
+
+        while position.chapter < self.chapters {
+            let text = self.chapter_text(position.chapter);
+            for frame in frames_in(position.chapter, text, position.offset, wpm) {
+                if frames.len() == count {
+                    return (frames, Some(frame.position));
+                }
+                frames.push(frame);
+            }
+            position = RsvpPosition {
+                chapter: position.chapter + 1,
+                offset: 0,
+            };
+        }
+        (frames, None)
+    }
+}
+
+fn total_ms(frames: &[RsvpFrame]) -> u64 {
+    frames.iter().map(|f| u64::from(f.duration_ms)).sum()
+}
+
+/// Emit batches until the book ends or a newer stream replaces this one,
+/// keeping at most `LEAD` of reading time ahead of playback
+fn stream<R: Runtime>(
+    app: &AppHandle<R>,
+    book: &mut Book,
+    mut batch: RsvpBatch,
+    count: usize,
+    wpm: u32,
+) {
+    let started = Instant::now();
+    let mut queued = Duration::from_millis(total_ms(&batch.frames));
+
+    while let Some(from) = batch.next {
+        let ahead = queued.saturating_sub(started.elapsed());
+        if ahead > LEAD {
+            thread::sleep(ahead - LEAD);
+        }
+        if CURRENT_STREAM.load(Ordering::Relaxed) != batch.stream {
+            return;
+        }
+
+        let (frames, next) = book.frames(from, count, wpm);
+        queued += Duration::from_millis(total_ms(&frames));
+        batch = RsvpBatch {
+            frames,
+            next,
+            ..batch
+        };
+        if let Err(e) = app.emit("rsvp-frames", &batch) {
+            warn!("Failed to emit RSVP frames: {}", e);
+        }
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// The first `count` word frames of a book from `from_position` (the start
+/// by default) at `wpm`, clamped to 100–1500. Later batches follow as
+/// `rsvp-frames` events, replacing any earlier stream.
+#[tauri::command]
+pub async fn get_rsvp_frames<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: i64,
+    from_position: Option<RsvpPosition>,
+    count: usize,
+    wpm: u32,
+) -> Result<RsvpBatch, String> {
+    let stream_id = CURRENT_STREAM.fetch_add(1, Ordering::Relaxed) + 1;
+    let count = count.clamp(1, MAX_BATCH);
+    let wpm = wpm.clamp(MIN_WPM, MAX_WPM);
+
+    let book = app
+        .state::<Database>()
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+    if book.format.as_deref() != Some("epub") {
+        return Err("Speed reading is only available for EPUB books".to_string());
+    }
+    let path = book
+        .path
+        .ok_or_else(|| format!("Book {} has no file", book_id))?;
+    let from = from_position.unwrap_or_default();
+
+    let (mut book, batch) = tauri::async_runtime::spawn_blocking(move || {
+        let mut book = Book::open(&path)?;
+        let (frames, next) = book.frames(from, count, wpm);
+        Ok::<_, String>((
+            book,
+            RsvpBatch {
+                book_id,
+                stream: stream_id,
+                frames,
+                next,
+            },
+        ))
+    })
+    .await
+    .map_err(|e| format!("RSVP task failed: {}", e))??;
+
+    info!(
+        "Speed reading book {} from {:?} at {} wpm",
+        book_id, from, wpm
+    );
+
+    if batch.next.is_some() {
+        let first = batch.clone();
+        thread::Builder::new()
+            .name("rsvp".into())
+            .spawn(move || stream(&app, &mut book, first, count, wpm))
+            .map_err(|e| format!("Failed to start RSVP stream: {}", e))?;
+    }
+    Ok(batch)
+}
+
+/// Stop streaming frames, e.g. on pause
+#[tauri::command]
+pub fn stop_rsvp_frames() {
+    CURRENT_STREAM.fetch_add(1, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_words_with_char_offsets() {
+        let found = words("Ça va.\nOui  merci");
+        let offsets: Vec<_> = found.iter().map(|w| (w.text, w.offset)).collect();
+        assert_eq!(offsets, [("Ça", 0), ("va.", 3), ("Oui", 7), ("merci", 12)]);
+        assert!(found[1].paragraph_end);
+        assert!(!found[2].paragraph_end);
+        assert!(found[3].paragraph_end);
+    }
+
+    #[test]
+    fn places_the_recognition_point() {
+        assert_eq!(orp("a"), 0);
+        assert_eq!(orp("word"), 1);
+        assert_eq!(orp("reading"), 2);
+        assert_eq!(orp("\"Hello"), 2);
+        assert_eq!(orp("extraordinarily"), 4);
+        assert_eq!(orp("—"), 0);
+    }
+
+    #[test]
+    fn lengthens_punctuated_and_long_words() {
+        let word = |text, paragraph_end| Word {
+            text,
+            offset: 0,
+            paragraph_end,
+        };
+        assert_eq!(duration_ms(&word("cat", false), 300), 200);
+        assert_eq!(duration_ms(&word("cat,", false), 300), 300);
+        assert_eq!(duration_ms(&word("cat.\"", false), 300), 400);
+        assert_eq!(duration_ms(&word("cat", true), 300), 500);
+        assert_eq!(duration_ms(&word("unbelievable", false), 300), 240);
+    }
+
+    #[test]
+    fn resumes_at_a_frame_position() {
+        let frames: Vec<_> = frames_in(2, "one two three", 4, 300).collect();
+        assert_eq!(frames[0].word, "two");
+        assert_eq!(
+            frames[0].position,
+            RsvpPosition {
+                chapter: 2,
+                offset: 4
+            }
+        );
+        assert_eq!(frames.len(), 2);
+    }
+}
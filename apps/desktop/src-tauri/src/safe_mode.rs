@@ -0,0 +1,234 @@
+// Read Master Desktop - Safe Mode
+//
+// A launch that leaves out everything a bad book or setting could trip over.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::Serialize;
+use tauri::webview::{PageLoadEvent, PageLoadPayload};
+use tauri::{AppHandle, Emitter, Manager, Runtime, Webview};
+use tauri_plugin_store::{Store, StoreExt};
+
+use crate::{data_location, persist};
+
+pub const SAFE_MODE_FLAG: &str = "--safe-mode";
+
+/// Launch state, at the top of the app data directory and outside every
+/// profile. Each launch clears `launchSucceeded` on disk and sets it again
+/// once the app has stayed up a while or quits cleanly; a launch that finds
+/// it still cleared follows one that crashed.
+const LAUNCH_FILE: &str = "launch.json";
+const SUCCEEDED_KEY: &str = "launchSucceeded";
+const CRASHED_KEY: &str = "crashedLaunches";
+
+/// Consecutive crashed launches that switch safe mode on
+const MAX_CRASHED_LAUNCHES: u32 = 3;
+
+/// How long the main window must stay up for a launch to count
+const STARTUP_GRACE: Duration = Duration::from_secs(20);
+
+const MAIN_WINDOW: &str = "main";
+
+/// Why this process is in safe mode, set by `init`
+static STATE: OnceLock<Option<SafeMode>> = OnceLock::new();
+
+/// Whether the startup timer has been started
+static TIMER_STARTED: AtomicBool = AtomicBool::new(false);
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SafeModeReason {
+    /// Launched with `--safe-mode`
+    Flag,
+    /// Too many launches in a row crashed
+    Crashes,
+}
+
+/// A launch that lets the user reach settings and fix whatever crashed the
+/// app: the last workspace isn't reopened (or overwritten), recovered
+/// annotation drafts are held back, user scripts don't run, and the
+/// updater is off.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SafeMode {
+    pub reason: SafeModeReason,
+    /// Crashed launches right before this one
+    pub crashed_launches: u32,
+}
+
+// ============================================================================
+// Launch Tracking
+// ============================================================================
+
+/// Crashed launches in a row, counting the previous one if it never
+/// succeeded
+fn crashed_launches(previous_succeeded: bool, previous_count: u32) -> u32 {
+    if previous_succeeded {
+        0
+    } else {
+        previous_count.saturating_add(1)
+    }
+}
+
+/// Safe mode for this launch, if any
+fn decide(flag: bool, crashed: u32) -> Option<SafeModeReason> {
+    if flag {
+        Some(SafeModeReason::Flag)
+    } else if crashed >= MAX_CRASHED_LAUNCHES {
+        Some(SafeModeReason::Crashes)
+    } else {
+        None
+    }
+}
+
+fn launch_store<R: Runtime>(app: &AppHandle<R>) -> Result<Arc<Store<R>>, String> {
+    app.store(data_location::store_path(LAUNCH_FILE))
+        .map_err(|e| format!("Failed to open launch state: {}", e))
+}
+
+/// Write the launch state now; it must be on disk before a crash
+fn save_launch<R: Runtime>(app: &AppHandle<R>, succeeded: bool, crashed: u32) {
+    let result = launch_store(app).and_then(|store| {
+        store.set(SUCCEEDED_KEY, serde_json::json!(succeeded));
+        store.set(CRASHED_KEY, serde_json::json!(crashed));
+        store
+            .save()
+            .map_err(|e| format!("Failed to save launch state: {}", e))
+    });
+    if let Err(e) = result {
+        warn!("{}", e);
+    }
+}
+
+/// Record that this launch got through startup. Called once the main
+/// window has stayed up a while, and when the app quits cleanly.
+pub fn mark_launched<R: Runtime>(app: &AppHandle<R>) {
+    save_launch(app, true, 0);
+}
+
+/// Count crashed launches and decide on safe mode, `flag` being whether
+/// the app was launched with `--safe-mode`. Runs first in setup, before
+/// anything loads saved state.
+pub fn init<R: Runtime>(app: &AppHandle<R>, flag: bool) {
+    let (succeeded, count) = match launch_store(app) {
+        Ok(store) => (
+            store
+                .get(SUCCEEDED_KEY)
+                .and_then(|value| value.as_bool())
+                .unwrap_or(true),
+            store
+                .get(CRASHED_KEY)
+                .and_then(|value| value.as_u64())
+                .map_or(0, |count| count.min(u64::from(u32::MAX)) as u32),
+        ),
+        Err(e) => {
+            warn!("{}", e);
+            (true, 0)
+        }
+    };
+    let crashed = crashed_launches(succeeded, count);
+    save_launch(app, false, crashed);
+
+    let state = decide(flag, crashed).map(|reason| SafeMode {
+        reason,
+        crashed_launches: crashed,
+    });
+    match &state {
+        Some(state) => warn!(
+            "Starting in safe mode ({:?}, {} crashed launch(es))",
+            state.reason, state.crashed_launches
+        ),
+        None if crashed > 0 => info!("{} crashed launch(es) in a row", crashed),
+        None => {}
+    }
+    let _ = STATE.set(state);
+}
+
+/// This process's safe mode, if it's in one
+pub fn state() -> Option<&'static SafeMode> {
+    STATE.get().and_then(Option::as_ref)
+}
+
+pub fn is_active() -> bool {
+    state().is_some()
+}
+
+/// Builder-level page-load hook sending the main window a `safe-mode` event,
+/// which opens the recovery screen, and counting the launch once the window
+/// has stayed up
+pub fn handle_page_load<R: Runtime>(webview: &Webview<R>, payload: &PageLoadPayload<'_>) {
+    if payload.event() != PageLoadEvent::Finished || webview.label() != MAIN_WINDOW {
+        return;
+    }
+
+    if let Some(state) = state() {
+        let _ = webview.emit_to(MAIN_WINDOW, "safe-mode", state);
+    }
+
+    if TIMER_STARTED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    let app = webview.app_handle().clone();
+    let spawned = thread::Builder::new()
+        .name("launch-check".into())
+        .spawn(move || {
+            thread::sleep(STARTUP_GRACE);
+            mark_launched(&app);
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start launch check: {}", e);
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Why the app is in safe mode, or `None` on a normal launch
+#[tauri::command]
+pub fn get_safe_mode() -> Option<SafeMode> {
+    state().cloned()
+}
+
+/// Restart without safe mode, dropping `--safe-mode` and the crash count
+#[tauri::command]
+pub fn restart_normally<R: Runtime>(app: AppHandle<R>) {
+    info!("Leaving safe mode");
+    persist::flush(&app);
+    mark_launched(&app);
+
+    let mut env = app.env();
+    env.args_os.retain(|arg| arg != SAFE_MODE_FLAG);
+    app.cleanup_before_exit();
+    tauri::process::restart(&env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_consecutive_crashes() {
+        assert_eq!(crashed_launches(true, 2), 0);
+        assert_eq!(crashed_launches(false, 0), 1);
+        assert_eq!(crashed_launches(false, 2), 3);
+        assert_eq!(crashed_launches(false, u32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn enters_safe_mode_on_flag_or_third_crash() {
+        assert_eq!(decide(false, 0), None);
+        assert_eq!(decide(false, 2), None);
+        assert_eq!(decide(false, 3), Some(SafeModeReason::Crashes));
+        assert_eq!(decide(true, 0), Some(SafeModeReason::Flag));
+        assert_eq!(decide(true, 5), Some(SafeModeReason::Flag));
+    }
+}
@@ -0,0 +1,512 @@
+// Read Master Desktop - Scripting
+//
+// User scripts (Rhai) run on library events: `on_import`, `on_finish_book`
+// and `on_highlight`. Scripts see a read-only `payload` map and act through
+// a small API whose calls are collected as effects and applied only after
+// the script finishes cleanly:
+//
+//   add_tag(tag)            remove_tag(tag)
+//   rename_file(name)       (within the library folder only)
+//   notify(title, body)     log(message)
+//
+// Every run has an operation and wall-clock budget, so a runaway loop is
+// cut off. Script failures are logged and shown as a notification; they
+// never fail the operation that triggered the hook.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, EvalAltResult, Scope};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::db::Database;
+use crate::library::{self, Book};
+use crate::notifications;
+use crate::persist;
+use crate::profiles::ProfileStoreExt;
+use crate::safe_mode;
+
+const STORE_FILE: &str = "scripts.json";
+
+/// Operations a script may perform in one run
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Wall-clock limit for one run
+const TIME_BUDGET: Duration = Duration::from_secs(2);
+
+/// Characters not allowed in file names on at least one platform
+const INVALID_FILE_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hook {
+    OnImport,
+    OnFinishBook,
+    OnHighlight,
+}
+
+impl Hook {
+    pub const ALL: [Hook; 3] = [Self::OnImport, Self::OnFinishBook, Self::OnHighlight];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::OnImport => "on_import",
+            Self::OnFinishBook => "on_finish_book",
+            Self::OnHighlight => "on_highlight",
+        }
+    }
+}
+
+/// Something a script asked for, applied after it succeeds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Effect {
+    AddTag { tag: String },
+    RemoveTag { tag: String },
+    RenameFile { name: String },
+    Notify { title: String, body: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptInfo {
+    pub hook: Hook,
+    pub source: Option<String>,
+}
+
+/// Result of a dry run; effects are reported, not applied
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptTestResult {
+    pub effects: Vec<Effect>,
+    pub logs: Vec<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct RunOutput {
+    effects: Vec<Effect>,
+    logs: Vec<String>,
+}
+
+// ============================================================================
+// Engine
+// ============================================================================
+
+/// A sandboxed engine whose API calls are recorded into `output`
+fn engine(output: Arc<Mutex<RunOutput>>) -> Engine {
+    let mut engine = Engine::new();
+
+    // No imports, no eval, bounded everything
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(1 << 20);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+
+    let started = Instant::now();
+    engine.on_progress(move |_| {
+        (started.elapsed() > TIME_BUDGET).then(|| "time budget exceeded".into())
+    });
+
+    let record = |output: &Arc<Mutex<RunOutput>>, effect: Effect| {
+        if let Ok(mut out) = output.lock() {
+            out.effects.push(effect);
+        }
+    };
+
+    let out = Arc::clone(&output);
+    engine.register_fn("add_tag", move |tag: &str| {
+        record(
+            &out,
+            Effect::AddTag {
+                tag: tag.trim().to_string(),
+            },
+        )
+    });
+    let out = Arc::clone(&output);
+    engine.register_fn("remove_tag", move |tag: &str| {
+        record(
+            &out,
+            Effect::RemoveTag {
+                tag: tag.trim().to_string(),
+            },
+        )
+    });
+    let out = Arc::clone(&output);
+    engine.register_fn("rename_file", move |name: &str| {
+        record(
+            &out,
+            Effect::RenameFile {
+                name: name.to_string(),
+            },
+        )
+    });
+    let out = Arc::clone(&output);
+    engine.register_fn("notify", move |title: &str, body: &str| {
+        record(
+            &out,
+            Effect::Notify {
+                title: title.to_string(),
+                body: body.to_string(),
+            },
+        )
+    });
+
+    let out = Arc::clone(&output);
+    let log = move |message: &str| {
+        info!("[script] {}", message);
+        if let Ok(mut out) = out.lock() {
+            out.logs.push(message.to_string());
+        }
+    };
+    let print_log = log.clone();
+    engine.register_fn("log", log);
+    engine.on_print(move |message| print_log(message));
+
+    engine
+}
+
+fn describe(error: &EvalAltResult) -> String {
+    match error {
+        EvalAltResult::ErrorTooManyOperations(_) => {
+            "Script exceeded its operation budget".to_string()
+        }
+        EvalAltResult::ErrorTerminated(..) => format!(
+            "Script exceeded its time budget of {}s",
+            TIME_BUDGET.as_secs()
+        ),
+        e => e.to_string(),
+    }
+}
+
+/// Run `source` against `payload`, returning what it asked for
+fn run(source: &str, payload: &Value) -> Result<RunOutput, (String, RunOutput)> {
+    let output = Arc::new(Mutex::new(RunOutput::default()));
+    let engine = engine(Arc::clone(&output));
+
+    let mut scope = Scope::new();
+    let result = rhai::serde::to_dynamic(payload)
+        .map_err(|e| describe(&e))
+        .and_then(|payload| {
+            scope.push_constant("payload", payload);
+            engine
+                .run_with_scope(&mut scope, source)
+                .map_err(|e| describe(&e))
+        });
+
+    drop(engine);
+    let output = Arc::try_unwrap(output)
+        .ok()
+        .and_then(|m| m.into_inner().ok())
+        .unwrap_or_default();
+
+    match result {
+        Ok(()) => Ok(output),
+        Err(e) => Err((e, output)),
+    }
+}
+
+// ============================================================================
+// Effects
+// ============================================================================
+
+/// Rename a book's file in place, refusing names that would leave its
+/// folder and files outside the library
+fn rename_book_file<R: Runtime>(
+    app: &AppHandle<R>,
+    conn: &Connection,
+    book: &Book,
+    name: &str,
+) -> Result<(), String> {
+    let name: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if INVALID_FILE_CHARS.contains(&c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(format!("Invalid file name: {:?}", name));
+    }
+
+    let current = PathBuf::from(
+        book.path
+            .as_deref()
+            .ok_or_else(|| format!("Book {} has no file", book.id))?,
+    );
+    let library = library::library_dir(app)?
+        .canonicalize()
+        .map_err(|e| format!("Failed to resolve library folder: {}", e))?;
+    let inside = current
+        .canonicalize()
+        .is_ok_and(|path| path.starts_with(&library));
+    if !inside {
+        return Err("Scripts can only rename files inside the library folder".to_string());
+    }
+
+    let target = current.with_file_name(&name);
+    if target == current {
+        return Ok(());
+    }
+    if target.exists() {
+        return Err(format!("{} already exists", target.display()));
+    }
+
+    std::fs::rename(&current, &target).map_err(|e| format!("Failed to rename file: {}", e))?;
+    conn.execute(
+        "UPDATE books SET path = ?2, updated_at = ?3 WHERE id = ?1",
+        params![
+            book.id,
+            target.to_string_lossy(),
+            chrono::Utc::now().to_rfc3339()
+        ],
+    )
+    .map_err(|e| format!("Failed to update book path: {}", e))?;
+
+    info!(
+        "Script renamed {} to {}",
+        current.display(),
+        Path::new(&name).display()
+    );
+    Ok(())
+}
+
+fn apply<R: Runtime>(app: &AppHandle<R>, book_id: Option<i64>, effects: &[Effect]) -> Vec<String> {
+    let db = app.state::<Database>();
+    let mut errors = Vec::new();
+
+    for effect in effects {
+        let result = match (effect, book_id) {
+            (Effect::Notify { title, body }, _) => {
+                notifications::show(app, None, title, Some(body), Vec::new(), None).map(|_| ())
+            }
+            (_, None) => Err("This event has no book to change".to_string()),
+            (Effect::AddTag { tag }, Some(id)) => {
+                db.with_conn(|conn| library::add_tags(conn, id, std::slice::from_ref(tag)))
+            }
+            (Effect::RemoveTag { tag }, Some(id)) => {
+                db.with_conn(|conn| library::remove_tags(conn, id, std::slice::from_ref(tag)))
+            }
+            (Effect::RenameFile { name }, Some(id)) => db
+                .with_conn(|conn| library::get_book(conn, id))
+                .and_then(|book| book.ok_or_else(|| format!("Book not found: {}", id)))
+                .and_then(|book| {
+                    db.with_conn(|conn| Ok(rename_book_file(app, conn, &book, name)))?
+                }),
+        };
+
+        if let Err(e) = result {
+            errors.push(e);
+        }
+    }
+
+    errors
+}
+
+// ============================================================================
+// Hooks
+// ============================================================================
+
+fn script_for<R: Runtime>(app: &AppHandle<R>, hook: Hook) -> Option<String> {
+    app.profile_store(STORE_FILE)
+        .ok()?
+        .get(hook.as_str())
+        .and_then(|v| v.as_str().map(str::to_string))
+        .filter(|s| !s.trim().is_empty())
+}
+
+fn report_failure<R: Runtime>(app: &AppHandle<R>, hook: Hook, error: &str) {
+    warn!("Script {} failed: {}", hook.as_str(), error);
+    let _ = notifications::show(
+        app,
+        None,
+        &format!("Script error in {}", hook.as_str()),
+        Some(error),
+        Vec::new(),
+        None,
+    );
+}
+
+/// Run the user's script for `hook`, if any. `book_id` is the book the
+/// event concerns; `extra` is merged into the payload next to `book`.
+/// Never fails: problems are logged and shown as a notification.
+pub fn trigger<R: Runtime>(app: &AppHandle<R>, hook: Hook, book_id: Option<i64>, extra: Value) {
+    if safe_mode::is_active() {
+        return;
+    }
+    let Some(source) = script_for(app, hook) else {
+        return;
+    };
+
+    let book = book_id.and_then(|id| {
+        app.state::<Database>()
+            .with_conn(|conn| library::get_book(conn, id))
+            .ok()
+            .flatten()
+    });
+    let mut payload = serde_json::json!({ "event": hook.as_str(), "book": book });
+    if let (Value::Object(payload), Value::Object(extra)) = (&mut payload, extra) {
+        payload.extend(extra);
+    }
+
+    info!("Running {} script", hook.as_str());
+    match run(&source, &payload) {
+        Ok(output) => {
+            let errors = apply(app, book_id, &output.effects);
+            if !errors.is_empty() {
+                report_failure(app, hook, &errors.join("\n"));
+            }
+        }
+        Err((error, _)) => report_failure(app, hook, &error),
+    }
+}
+
+/// `trigger` on a background thread, for callers that shouldn't wait
+pub fn trigger_in_background<R: Runtime>(
+    app: &AppHandle<R>,
+    hook: Hook,
+    book_id: Option<i64>,
+    extra: Value,
+) {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || trigger(&app, hook, book_id, extra));
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// The script for every hook
+#[tauri::command]
+pub async fn list_scripts<R: Runtime>(app: AppHandle<R>) -> Result<Vec<ScriptInfo>, String> {
+    Ok(Hook::ALL
+        .into_iter()
+        .map(|hook| ScriptInfo {
+            hook,
+            source: script_for(&app, hook),
+        })
+        .collect())
+}
+
+/// Set or (with an empty source) remove a hook's script. The script must
+/// compile.
+#[tauri::command]
+pub async fn set_script<R: Runtime>(
+    app: AppHandle<R>,
+    hook: Hook,
+    source: Option<String>,
+) -> Result<(), String> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    match source.filter(|s| !s.trim().is_empty()) {
+        Some(source) => {
+            let output = Arc::new(Mutex::new(RunOutput::default()));
+            engine(output)
+                .compile(&source)
+                .map_err(|e| format!("Script doesn't compile: {}", e))?;
+            info!("Setting {} script", hook.as_str());
+            store.set(hook.as_str(), source);
+        }
+        None => {
+            info!("Removing {} script", hook.as_str());
+            store.delete(hook.as_str());
+        }
+    }
+
+    persist::mark_dirty(&app, STORE_FILE);
+    Ok(())
+}
+
+/// Run a hook's script against a sample payload without applying anything
+#[tauri::command]
+pub async fn run_script_test<R: Runtime>(
+    app: AppHandle<R>,
+    hook: Hook,
+    sample_payload: Value,
+) -> Result<ScriptTestResult, String> {
+    let source =
+        script_for(&app, hook).ok_or_else(|| format!("No script set for {}", hook.as_str()))?;
+
+    let result = tauri::async_runtime::spawn_blocking(move || run(&source, &sample_payload))
+        .await
+        .map_err(|e| format!("Script task failed: {}", e))?;
+
+    Ok(match result {
+        Ok(output) => ScriptTestResult {
+            effects: output.effects,
+            logs: output.logs,
+            error: None,
+        },
+        Err((error, output)) => ScriptTestResult {
+            effects: output.effects,
+            logs: output.logs,
+            error: Some(error),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_effects_from_payload() {
+        let payload = serde_json::json!({
+            "book": { "title": "Dune", "author": "Frank Herbert", "path": "/lib/inbox/dune.epub" }
+        });
+        let source = r#"
+            rename_file(payload.book.author + " - " + payload.book.title + ".epub");
+            if payload.book.path.contains("/inbox/") { add_tag("inbox"); }
+        "#;
+
+        let output = run(source, &payload).map_err(|(e, _)| e).unwrap();
+        assert_eq!(
+            output.effects,
+            [
+                Effect::RenameFile {
+                    name: "Frank Herbert - Dune.epub".to_string()
+                },
+                Effect::AddTag {
+                    tag: "inbox".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn infinite_loop_is_cut_off() {
+        let (error, _) = run("loop { }", &Value::Null).unwrap_err();
+        assert!(error.contains("budget"), "{}", error);
+    }
+
+    #[test]
+    fn payload_is_read_only() {
+        let payload = serde_json::json!({ "book": { "title": "A" } });
+        assert!(run(r#"payload.book.title = "B";"#, &payload).is_err());
+    }
+
+    #[test]
+    fn imports_are_disabled() {
+        assert!(run(r#"import "fs" as fs;"#, &Value::Null).is_err());
+    }
+}
@@ -0,0 +1,381 @@
+// Read Master Desktop - Search Index
+//
+// Full-text index of book contents (SQLite FTS5), built chapter by chapter
+// in the background. Progress is committed per chapter, so a cancelled or
+// interrupted build resumes where it stopped; a changed file hash starts
+// over.
+//
+// Events: `index-progress` after each chapter, then `index-complete`, or
+// `index-cancelled` when `cancel_index` (or `cancel_job`) stops a build.
+// Builds run as jobs, so they also report the generic job events.
+
+use std::path::Path;
+
+use log::{info, warn};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use crate::db::Database;
+use crate::epub::{text, EpubArchive};
+use crate::formats::djvu;
+use crate::import;
+use crate::jobs::{Job, JobKind, JobRegistry};
+use crate::library;
+
+/// Default number of search results
+const DEFAULT_LIMIT: u32 = 50;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexProgress {
+    book_id: i64,
+    processed_chapters: usize,
+    total_chapters: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IndexOutcome {
+    Complete,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub book_id: i64,
+    /// Spine position of the chapter (page index for DjVu)
+    pub chapter: i64,
+    /// Matching text with hits wrapped in `<mark>`
+    pub snippet: String,
+}
+
+struct IndexState {
+    content_hash: String,
+    indexed_chapters: usize,
+    complete: bool,
+}
+
+// ============================================================================
+// Index State
+// ============================================================================
+
+fn load_state(conn: &Connection, book_id: i64) -> rusqlite::Result<Option<IndexState>> {
+    conn.query_row(
+        "SELECT content_hash, indexed_chapters, complete
+         FROM search_index_state WHERE book_id = ?1",
+        [book_id],
+        |row| {
+            Ok(IndexState {
+                content_hash: row.get(0)?,
+                indexed_chapters: row.get::<_, i64>(1)? as usize,
+                complete: row.get(2)?,
+            })
+        },
+    )
+    .optional()
+}
+
+/// Drop any existing index for the book and record a fresh start
+fn reset(conn: &mut Connection, book_id: i64, hash: &str, total: usize) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM search_chunks WHERE book_id = ?1", [book_id])?;
+    tx.execute(
+        "INSERT INTO search_index_state
+             (book_id, content_hash, total_chapters, indexed_chapters, complete, updated_at)
+         VALUES (?1, ?2, ?3, 0, 0, ?4)
+         ON CONFLICT (book_id) DO UPDATE SET
+             content_hash = excluded.content_hash,
+             total_chapters = excluded.total_chapters,
+             indexed_chapters = 0,
+             complete = 0,
+             updated_at = excluded.updated_at",
+        params![book_id, hash, total as i64, chrono::Utc::now().to_rfc3339()],
+    )?;
+    tx.commit()
+}
+
+/// Store one chapter and advance the resume point in the same transaction
+fn commit_chapter(
+    conn: &mut Connection,
+    book_id: i64,
+    chapter: usize,
+    text: &str,
+    total: usize,
+) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+
+    // Re-running a chapter after a crash must not duplicate it
+    tx.execute(
+        "DELETE FROM search_chunks WHERE book_id = ?1 AND chapter = ?2",
+        params![book_id, chapter as i64],
+    )?;
+
+    let mut stmt =
+        tx.prepare("INSERT INTO search_chunks (text, book_id, chapter) VALUES (?1, ?2, ?3)")?;
+    for paragraph in text.lines().map(str::trim).filter(|p| !p.is_empty()) {
+        stmt.execute(params![paragraph, book_id, chapter as i64])?;
+    }
+    drop(stmt);
+
+    tx.execute(
+        "UPDATE search_index_state
+         SET indexed_chapters = ?2, complete = ?3, updated_at = ?4
+         WHERE book_id = ?1",
+        params![
+            book_id,
+            (chapter + 1) as i64,
+            chapter + 1 >= total,
+            chrono::Utc::now().to_rfc3339()
+        ],
+    )?;
+    tx.commit()
+}
+
+/// Chapters of a completely indexed book with `phrase` as whole words, or
+/// `None` when the index is missing, unfinished, or was built from a file
+/// with a different hash. Matching ignores case and diacritics, so callers
+/// verify hits against the text.
+pub fn chapters_with_phrase(
+    conn: &Connection,
+    book_id: i64,
+    content_hash: &str,
+    phrase: &str,
+) -> rusqlite::Result<Option<Vec<usize>>> {
+    match load_state(conn, book_id)? {
+        Some(state) if state.complete && state.content_hash == content_hash => {}
+        _ => return Ok(None),
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT chapter FROM search_chunks
+         WHERE search_chunks MATCH ?1 AND book_id = ?2
+         ORDER BY chapter",
+    )?;
+    let chapters = stmt
+        .query_map(
+            params![format!("\"{}\"", phrase.replace('"', "")), book_id],
+            |row| Ok(row.get::<_, i64>(0)? as usize),
+        )?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(Some(chapters))
+}
+
+// ============================================================================
+// Indexing
+// ============================================================================
+
+fn emit_progress<R: Runtime>(
+    app: &AppHandle<R>,
+    job: &Job<R>,
+    book_id: i64,
+    processed: usize,
+    total: usize,
+) {
+    job.progress(processed as u64, total as u64, None);
+    let progress = IndexProgress {
+        book_id,
+        processed_chapters: processed,
+        total_chapters: total,
+    };
+    if let Err(e) = app.emit("index-progress", progress) {
+        warn!("Failed to emit index-progress: {}", e);
+    }
+}
+
+fn run_index<R: Runtime>(
+    app: &AppHandle<R>,
+    book_id: i64,
+    job: &Job<R>,
+) -> Result<IndexOutcome, String> {
+    let db = app.state::<Database>();
+
+    let book = db
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+    // DjVu pages are indexed from their hidden text, one page per chapter
+    let is_djvu = match book.format.as_deref() {
+        Some("epub") => false,
+        Some("djvu") => true,
+        _ => return Err("Only EPUB and DjVu books can be indexed".to_string()),
+    };
+    let path = book
+        .path
+        .ok_or_else(|| format!("Book {} has no file", book_id))?;
+
+    let hash = import::hash_path(Path::new(&path))?;
+    let total = if is_djvu {
+        djvu::page_count(Path::new(&path))? as usize
+    } else {
+        EpubArchive::open(&path)
+            .map_err(|e| e.to_string())?
+            .spine_paths()
+            .len()
+    };
+
+    let state = db.with_conn(|conn| load_state(conn, book_id))?;
+    let start = match state {
+        Some(state) if state.content_hash == hash && state.complete => {
+            emit_progress(app, job, book_id, total, total);
+            return Ok(IndexOutcome::Complete);
+        }
+        Some(state) if state.content_hash == hash => {
+            info!(
+                "Resuming index of book {} at chapter {}/{}",
+                book_id, state.indexed_chapters, total
+            );
+            state.indexed_chapters.min(total)
+        }
+        _ => {
+            db.with_conn(|conn| reset(conn, book_id, &hash, total))?;
+            0
+        }
+    };
+
+    // Chapters are extracted a batch at a time so cancellation and progress
+    // stay responsive while the pool works in parallel
+    let batch = text::parallelism().max(1);
+    let mut chapter = start;
+    while chapter < total {
+        if job.is_cancelled() {
+            info!(
+                "Indexing of book {} cancelled at chapter {}",
+                book_id, chapter
+            );
+            return Ok(IndexOutcome::Cancelled);
+        }
+
+        let end = (chapter + batch).min(total);
+        if is_djvu {
+            for page in chapter..end {
+                let text = djvu::page_text(Path::new(&path), page as u32)
+                    .map(|page| page.text)
+                    .unwrap_or_else(|e| {
+                        warn!("Failed to read text of DjVu page {}: {}", page, e);
+                        String::new()
+                    });
+                db.with_conn(|conn| commit_chapter(conn, book_id, page, &text, total))?;
+                emit_progress(app, job, book_id, page + 1, total);
+            }
+            chapter = end;
+            continue;
+        }
+        // Unreadable chapters come back empty; they just aren't searchable
+        for extracted in text::extract_chapters(&path, chapter..end) {
+            db.with_conn(|conn| {
+                commit_chapter(conn, book_id, extracted.index, &extracted.text, total)
+            })?;
+            emit_progress(app, job, book_id, extracted.index + 1, total);
+        }
+        chapter = end;
+    }
+
+    // Books without any chapters still count as indexed
+    if total == 0 {
+        db.with_conn(|conn| {
+            conn.execute(
+                "UPDATE search_index_state SET complete = 1 WHERE book_id = ?1",
+                [book_id],
+            )
+        })?;
+    }
+
+    Ok(IndexOutcome::Complete)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Build (or resume building) the search index for a book. Resolves when
+/// the build finishes or is cancelled.
+#[tauri::command]
+pub async fn build_search_index<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: i64,
+) -> Result<IndexOutcome, String> {
+    let job = Job::start(
+        &app,
+        JobKind::Index,
+        &book_id.to_string(),
+        &format!("Indexing book {}", book_id),
+    )?;
+
+    let worker_app = app.clone();
+    let outcome = tauri::async_runtime::spawn_blocking(move || {
+        let result = run_index(&worker_app, book_id, &job);
+        match result {
+            Ok(IndexOutcome::Cancelled) => job.finish_cancelled(),
+            _ => job.finish(&result),
+        }
+        result
+    })
+    .await
+    .map_err(|e| format!("Index task failed: {}", e))??;
+
+    let event = match outcome {
+        IndexOutcome::Complete => "index-complete",
+        IndexOutcome::Cancelled => "index-cancelled",
+    };
+    if let Err(e) = app.emit(event, serde_json::json!({ "bookId": book_id })) {
+        warn!("Failed to emit {}: {}", event, e);
+    }
+
+    Ok(outcome)
+}
+
+/// Stop a running index build after the current batch of chapters.
+/// Returns whether a build was running.
+#[tauri::command]
+pub fn cancel_index(jobs: State<'_, JobRegistry>, book_id: i64) -> Result<bool, String> {
+    Ok(jobs
+        .find(JobKind::Index, &book_id.to_string())
+        .is_some_and(|id| jobs.cancel(id)))
+}
+
+/// Search indexed books, optionally within a single book
+#[tauri::command]
+pub async fn search_books(
+    db: State<'_, Database>,
+    query: String,
+    book_id: Option<i64>,
+    limit: Option<u32>,
+) -> Result<Vec<SearchHit>, String> {
+    // Quote each term so user input can't form FTS syntax
+    let fts_query = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "")))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if fts_query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    db.with_conn(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT book_id, chapter, snippet(search_chunks, 0, '<mark>', '</mark>', '…', 16)
+             FROM search_chunks
+             WHERE search_chunks MATCH ?1 AND (?2 IS NULL OR book_id = ?2)
+             ORDER BY rank
+             LIMIT ?3",
+        )?;
+        let hits = stmt
+            .query_map(
+                params![fts_query, book_id, limit.unwrap_or(DEFAULT_LIMIT)],
+                |row| {
+                    Ok(SearchHit {
+                        book_id: row.get(0)?,
+                        chapter: row.get(1)?,
+                        snippet: row.get(2)?,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(hits)
+    })
+}
@@ -0,0 +1,367 @@
+// Read Master Desktop - Series
+//
+// Series detection for books without series metadata. Titles are matched
+// against common numbering patterns ("#3", "Book One", "Vol. 2", "Part IV",
+// Goodreads-style "(The Expanse, #1)") and books sharing a base title are
+// proposed as a series for the user to confirm.
+
+use std::collections::BTreeMap;
+
+use log::info;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::collections;
+use crate::db::Database;
+use crate::goodreads::normalize;
+use crate::library::{self, Book, BOOK_COLUMNS};
+
+const NUMBER_WORDS: &[&str] = &[
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+    "twenty",
+];
+
+const ORDINAL_WORDS: &[&str] = &[
+    "first", "second", "third", "fourth", "fifth", "sixth", "seventh", "eighth", "ninth", "tenth",
+];
+
+/// Words that introduce a volume number
+const VOLUME_WORDS: &[&str] = &["book", "vol", "volume", "part", "tome", "no", "number"];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesMember {
+    pub book_id: i64,
+    pub title: String,
+    pub series_index: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeriesProposal {
+    pub series_name: String,
+    pub author: Option<String>,
+    /// Members ordered by index
+    pub books: Vec<SeriesMember>,
+}
+
+/// Series parsed from a single title
+#[derive(Debug, Clone, PartialEq)]
+struct TitleSeries {
+    name: String,
+    index: Option<f64>,
+    /// The title names the series explicitly, e.g. "(The Expanse, #1)"
+    explicit: bool,
+}
+
+// ============================================================================
+// Title Parsing
+// ============================================================================
+
+fn roman_value(token: &str) -> Option<f64> {
+    let mut total = 0;
+    let mut prev = 0;
+    for c in token.chars().rev() {
+        let value = match c.to_ascii_uppercase() {
+            'I' => 1,
+            'V' => 5,
+            'X' => 10,
+            'L' => 50,
+            _ => return None,
+        };
+        if value < prev {
+            total -= value;
+        } else {
+            total += value;
+            prev = value;
+        }
+    }
+    (1..=50).contains(&total).then_some(total as f64)
+}
+
+/// "3", "3.5", "three", "third", "III"
+fn parse_number(token: &str) -> Option<f64> {
+    let token = token.trim_matches(|c: char| !c.is_alphanumeric() && c != '.');
+    if let Ok(n) = token.parse::<f64>() {
+        return (n >= 0.0).then_some(n);
+    }
+
+    let lower = token.to_lowercase();
+    NUMBER_WORDS
+        .iter()
+        .position(|w| *w == lower)
+        .or_else(|| ORDINAL_WORDS.iter().position(|w| *w == lower))
+        .map(|i| (i + 1) as f64)
+        .or_else(|| roman_value(token))
+}
+
+fn clean_name(name: &str) -> String {
+    name.trim()
+        .trim_end_matches([',', ':', '-', '–', '—', '.', ';'])
+        .trim()
+        .to_string()
+}
+
+/// "Leviathan Wakes (The Expanse, #1)" or "(Discworld #3)"
+fn parenthesized(title: &str) -> Option<TitleSeries> {
+    let inner = title.trim_end().strip_suffix(')')?;
+    let open = inner.rfind('(')?;
+    let inner = &inner[open + 1..];
+
+    let hash = inner.rfind('#')?;
+    let name = clean_name(&inner[..hash]);
+    let index = parse_number(&inner[hash + 1..]);
+    (!name.is_empty()).then_some(TitleSeries {
+        name,
+        index,
+        explicit: true,
+    })
+}
+
+/// "The Expanse #3", "Dune Book Two", "Saga Vol. 2", "Foundation: Part III"
+fn numbered(title: &str) -> Option<TitleSeries> {
+    let title = match title.rfind(" (") {
+        Some(i) if title.ends_with(')') => &title[..i],
+        _ => title,
+    };
+
+    if let Some(hash) = title.rfind('#') {
+        let index = parse_number(&title[hash + 1..])?;
+        let name = clean_name(&title[..hash]);
+        return (!name.is_empty()).then_some(TitleSeries {
+            name,
+            index: Some(index),
+            explicit: false,
+        });
+    }
+
+    let words: Vec<&str> = title.split_whitespace().collect();
+    for i in (0..words.len().saturating_sub(1)).rev() {
+        let marker = words[i]
+            .trim_matches(|c: char| !c.is_alphanumeric())
+            .to_lowercase();
+        if !VOLUME_WORDS.contains(&marker.as_str()) {
+            continue;
+        }
+        let Some(index) = parse_number(words[i + 1]) else {
+            continue;
+        };
+
+        let name = clean_name(&words[..i].join(" "));
+        if name.is_empty() {
+            return None;
+        }
+        return Some(TitleSeries {
+            name,
+            index: Some(index),
+            explicit: false,
+        });
+    }
+
+    None
+}
+
+fn parse_title(title: &str) -> Option<TitleSeries> {
+    parenthesized(title).or_else(|| numbered(title))
+}
+
+// ============================================================================
+// Detection
+// ============================================================================
+
+/// Group books without a series by parsed series name and author. Only
+/// groups of two or more, or single books that name their series
+/// explicitly, are proposed.
+fn propose(books: &[Book]) -> Vec<SeriesProposal> {
+    let mut groups: BTreeMap<(String, String), (TitleSeries, Vec<SeriesMember>)> = BTreeMap::new();
+    let mut authors: BTreeMap<(String, String), Option<String>> = BTreeMap::new();
+
+    for book in books.iter().filter(|b| b.series_name.is_none()) {
+        let Some(parsed) = parse_title(&book.title) else {
+            continue;
+        };
+
+        let author = book.author.as_deref().map(normalize).unwrap_or_default();
+        let key = (normalize(&parsed.name), author);
+        authors
+            .entry(key.clone())
+            .or_insert_with(|| book.author.clone());
+
+        let entry = groups
+            .entry(key)
+            .or_insert_with(|| (parsed.clone(), Vec::new()));
+        // Prefer an explicit name's spelling for the whole group
+        if parsed.explicit && !entry.0.explicit {
+            entry.0 = parsed.clone();
+        }
+        entry.1.push(SeriesMember {
+            book_id: book.id,
+            title: book.title.clone(),
+            series_index: parsed.index,
+        });
+    }
+
+    let mut proposals: Vec<SeriesProposal> = groups
+        .into_iter()
+        .filter(|(_, (parsed, members))| members.len() >= 2 || parsed.explicit)
+        .map(|(key, (parsed, mut members))| {
+            members.sort_by(|a, b| {
+                a.series_index
+                    .partial_cmp(&b.series_index)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            SeriesProposal {
+                series_name: parsed.name,
+                author: authors.get(&key).cloned().flatten(),
+                books: members,
+            }
+        })
+        .collect();
+
+    proposals.sort_by(|a, b| b.books.len().cmp(&a.books.len()));
+    proposals
+}
+
+fn set_series(
+    conn: &Connection,
+    book_id: i64,
+    series_name: Option<&str>,
+    series_index: Option<f64>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE books SET series_name = ?2, series_index = ?3, updated_at = ?4 WHERE id = ?1",
+        params![
+            book_id,
+            series_name,
+            series_index,
+            chrono::Utc::now().to_rfc3339()
+        ],
+    )?;
+    collections::refresh_book_memberships(conn, book_id)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Propose series groupings for books that have no series yet
+#[tauri::command]
+pub async fn detect_series(db: State<'_, Database>) -> Result<Vec<SeriesProposal>, String> {
+    let books = db.with_conn(|conn| {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM books WHERE books.series_name IS NULL AND books.deleted_at IS NULL",
+            BOOK_COLUMNS
+        ))?;
+        let books = stmt
+            .query_map([], library::book_from_row)?
+            .collect::<rusqlite::Result<Vec<Book>>>()?;
+        Ok(books)
+    })?;
+
+    let proposals = propose(&books);
+    info!("Detected {} possible series", proposals.len());
+    Ok(proposals)
+}
+
+/// Assign books to a series. `indices` pairs with `book_ids`; pass `null`
+/// entries for books without a position. An empty name clears the series.
+#[tauri::command]
+pub async fn apply_series(
+    db: State<'_, Database>,
+    book_ids: Vec<i64>,
+    series_name: String,
+    indices: Vec<Option<f64>>,
+) -> Result<(), String> {
+    if book_ids.len() != indices.len() {
+        return Err(format!(
+            "Got {} books but {} indices",
+            book_ids.len(),
+            indices.len()
+        ));
+    }
+
+    let name = Some(series_name.trim()).filter(|n| !n.is_empty());
+    info!("Assigning {} books to series {:?}", book_ids.len(), name);
+
+    db.with_conn(|conn| {
+        let tx = conn.transaction()?;
+        for (book_id, index) in book_ids.iter().zip(&indices) {
+            set_series(&tx, *book_id, name, index.filter(|_| name.is_some()))?;
+        }
+        tx.commit()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(title: &str) -> Option<(String, Option<f64>)> {
+        parse_title(title).map(|s| (s.name, s.index))
+    }
+
+    #[test]
+    fn parses_goodreads_style_suffix() {
+        assert_eq!(
+            series("Leviathan Wakes (The Expanse, #1)"),
+            Some(("The Expanse".to_string(), Some(1.0)))
+        );
+        assert_eq!(
+            series("Guards! Guards! (Discworld #8)"),
+            Some(("Discworld".to_string(), Some(8.0)))
+        );
+    }
+
+    #[test]
+    fn parses_numbering_words() {
+        assert_eq!(
+            series("The Expanse #3"),
+            Some(("The Expanse".to_string(), Some(3.0)))
+        );
+        assert_eq!(
+            series("Wheel of Time Book One"),
+            Some(("Wheel of Time".to_string(), Some(1.0)))
+        );
+        assert_eq!(
+            series("Saga, Vol. 2"),
+            Some(("Saga".to_string(), Some(2.0)))
+        );
+        assert_eq!(
+            series("Foundation: Part IV"),
+            Some(("Foundation".to_string(), Some(4.0)))
+        );
+        assert_eq!(
+            series("Dune Book Third"),
+            Some(("Dune".to_string(), Some(3.0)))
+        );
+    }
+
+    #[test]
+    fn ignores_plain_titles() {
+        assert_eq!(series("The Book Thief"), None);
+        assert_eq!(series("Catch-22"), None);
+    }
+}
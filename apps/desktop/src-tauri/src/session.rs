@@ -0,0 +1,408 @@
+// Read Master Desktop - Workspace Session
+//
+// Reopens the books each window was showing, at the same place, on restart.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::webview::{PageLoadEvent, PageLoadPayload};
+use tauri::{
+    AppHandle, Emitter, Manager, Runtime, State, Webview, WebviewUrl, WebviewWindowBuilder, Window,
+    WindowEvent,
+};
+
+use crate::db::Database;
+use crate::profiles::ProfileStoreExt;
+use crate::sync::changelog;
+use crate::{autostart, persist, quick_capture, safe_mode, tray, zoom};
+
+const STORE_FILE: &str = "workspace.json";
+
+/// How often reported window state is written out
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Windows that are never part of a workspace
+const TRANSIENT_WINDOWS: &[&str] = &[quick_capture::WINDOW_LABEL];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// What one window was showing. The window-state plugin restores
+/// geometry; this restores content.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowState {
+    pub label: String,
+    pub route: String,
+    pub book_id: Option<i64>,
+    /// Scroll offset or EPUB CFI, as the reader reported it
+    pub position: Option<String>,
+}
+
+/// The book read most recently, in any window, so the tray's Continue
+/// Reading can reopen it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LastBook {
+    pub book_id: i64,
+    pub position: Option<String>,
+}
+
+impl LastBook {
+    /// Reader route opening the book at its position
+    pub fn route(&self) -> String {
+        match &self.position {
+            Some(position) => format!(
+                "/reader/{}?loc={}",
+                self.book_id,
+                url::form_urlencoded::byte_serialize(position.as_bytes()).collect::<String>()
+            ),
+            None => format!("/reader/{}", self.book_id),
+        }
+    }
+}
+
+/// Reported window state plus restores waiting for their page to load
+#[derive(Default)]
+pub struct WorkspaceState {
+    windows: Mutex<HashMap<String, WindowState>>,
+    last_book: Mutex<Option<LastBook>>,
+    pending: Mutex<HashMap<String, WindowState>>,
+    dirty: AtomicBool,
+    /// Restore held back until the main window is shown
+    deferred: AtomicBool,
+}
+
+// ============================================================================
+// Saving
+// ============================================================================
+
+/// Write the reported window states to the workspace store. Runs every
+/// `SAVE_INTERVAL` while something changed, and on quit.
+pub fn save<R: Runtime>(app: &AppHandle<R>) {
+    // Safe mode never reopened the workspace; keep it for the next launch
+    if safe_mode::is_active() {
+        return;
+    }
+    let Some(workspace) = app.try_state::<WorkspaceState>() else {
+        return;
+    };
+    // Not reopened yet; the hidden main window alone isn't the workspace
+    if workspace.deferred.load(Ordering::Relaxed) {
+        return;
+    }
+    let windows: Vec<WindowState> = match workspace.windows.lock() {
+        Ok(windows) => windows.values().cloned().collect(),
+        Err(_) => return,
+    };
+
+    let store = match app.profile_store(STORE_FILE) {
+        Ok(store) => store,
+        Err(e) => {
+            warn!("Failed to open workspace store: {}", e);
+            return;
+        }
+    };
+    store.set("windows", serde_json::json!(windows));
+    if let Some(last_book) = workspace.last_book.lock().ok().and_then(|l| l.clone()) {
+        store.set("lastBook", serde_json::json!(last_book));
+    }
+    store.set("savedAt", chrono::Utc::now().to_rfc3339());
+    persist::mark_dirty(app, STORE_FILE);
+    workspace.dirty.store(false, Ordering::Relaxed);
+}
+
+fn start_saver<R: Runtime>(app: &AppHandle<R>) {
+    let app = app.clone();
+    thread::Builder::new()
+        .name("workspace-save".into())
+        .spawn(move || loop {
+            thread::sleep(SAVE_INTERVAL);
+            let dirty = app.state::<WorkspaceState>().dirty.load(Ordering::Relaxed);
+            if dirty {
+                save(&app);
+            }
+        })
+        .expect("failed to spawn workspace save thread");
+}
+
+// ============================================================================
+// Restoring
+// ============================================================================
+
+fn book_exists<R: Runtime>(app: &AppHandle<R>, book_id: i64) -> bool {
+    app.state::<Database>()
+        .with_conn(|conn| {
+            conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM books WHERE id = ?1 AND deleted_at IS NULL)",
+                [book_id],
+                |row| row.get(0),
+            )
+        })
+        .unwrap_or(false)
+}
+
+/// Saved windows, minus any whose book no longer exists. `None` when the
+/// workspace can't be read.
+fn load<R: Runtime>(app: &AppHandle<R>) -> Option<Vec<WindowState>> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| warn!("Failed to open workspace store: {}", e))
+        .ok()?;
+    let Some(value) = store.get("windows") else {
+        return Some(Vec::new());
+    };
+    let windows: Vec<WindowState> = serde_json::from_value(value)
+        .map_err(|e| warn!("Ignoring corrupt workspace: {}", e))
+        .ok()?;
+
+    Some(
+        windows
+            .into_iter()
+            .filter(|w| !TRANSIENT_WINDOWS.contains(&w.label.as_str()))
+            .filter(|w| match w.book_id {
+                Some(id) if !book_exists(app, id) => {
+                    info!("Not restoring {}: book {} is gone", w.label, id);
+                    false
+                }
+                _ => true,
+            })
+            .collect(),
+    )
+}
+
+fn open_window<R: Runtime>(app: &AppHandle<R>, state: &WindowState) -> tauri::Result<()> {
+    WebviewWindowBuilder::new(
+        app,
+        &state.label,
+        WebviewUrl::App(state.route.trim_start_matches('/').into()),
+    )
+    .title("Read Master")
+    .build()?;
+    Ok(())
+}
+
+/// Reopen the saved workspace, sending each window a `restore-state` event.
+/// At launch every window is still loading, so all restores wait for page
+/// load; later (from the menu), windows that are already open get theirs
+/// immediately. A corrupt workspace, or one whose books have all since
+/// been deleted, falls back to just the library.
+pub fn restore<R: Runtime>(app: &AppHandle<R>, at_launch: bool) {
+    let workspace = app.state::<WorkspaceState>();
+    let windows = match load(app) {
+        Some(windows) if !windows.is_empty() => windows,
+        Some(_) => {
+            info!("No previous workspace to restore");
+            return;
+        }
+        None => {
+            tray::show_and_navigate(app, "/library");
+            return;
+        }
+    };
+
+    info!("Restoring workspace with {} window(s)", windows.len());
+
+    for state in windows {
+        match app.get_webview_window(&state.label) {
+            Some(window) if !at_launch => {
+                let _ = window.emit("restore-state", &state);
+            }
+            existing => {
+                if existing.is_none() {
+                    if let Err(e) = open_window(app, &state) {
+                        warn!("Failed to reopen window {}: {}", state.label, e);
+                        continue;
+                    }
+                }
+                if let Ok(mut pending) = workspace.pending.lock() {
+                    pending.insert(state.label.clone(), state);
+                }
+            }
+        }
+    }
+}
+
+/// Builder-level page load hook delivering pending restores
+pub fn handle_page_load<R: Runtime>(webview: &Webview<R>, payload: &PageLoadPayload<'_>) {
+    if payload.event() != PageLoadEvent::Finished {
+        return;
+    }
+
+    let pending = webview
+        .try_state::<WorkspaceState>()
+        .and_then(|workspace| workspace.pending.lock().ok()?.remove(webview.label()));
+    if let Some(state) = pending {
+        let _ = webview.emit_to(webview.label(), "restore-state", state);
+    }
+}
+
+/// The book a window last reported showing
+pub fn window_book<R: Runtime>(app: &AppHandle<R>, label: &str) -> Option<i64> {
+    let workspace = app.try_state::<WorkspaceState>()?;
+    let windows = workspace.windows.lock().ok()?;
+    windows.get(label)?.book_id
+}
+
+/// The most recently read book that still exists, from this session or
+/// the saved workspace
+pub fn last_book<R: Runtime>(app: &AppHandle<R>) -> Option<LastBook> {
+    let reported = app
+        .try_state::<WorkspaceState>()
+        .and_then(|workspace| workspace.last_book.lock().ok()?.clone());
+    let last_book = match reported {
+        Some(last_book) => last_book,
+        None => {
+            let store = app.profile_store(STORE_FILE).ok()?;
+            serde_json::from_value(store.get("lastBook")?)
+                .map_err(|e| warn!("Ignoring corrupt last book: {}", e))
+                .ok()?
+        }
+    };
+    book_exists(app, last_book.book_id).then_some(last_book)
+}
+
+/// Builder-level window event hook forgetting closed windows, and running
+/// a deferred restore once the main window is first shown
+pub fn handle_window_event<R: Runtime>(window: &Window<R>, event: &WindowEvent) {
+    if let WindowEvent::Focused(true) = event {
+        let deferred = window.label() == "main"
+            && window
+                .try_state::<WorkspaceState>()
+                .is_some_and(|workspace| workspace.deferred.swap(false, Ordering::Relaxed));
+        if deferred {
+            info!("Main window shown; restoring the workspace");
+            restore(window.app_handle(), false);
+        }
+    }
+    if let WindowEvent::Destroyed = event {
+        let workspace = window.state::<WorkspaceState>();
+        if let Ok(mut windows) = workspace.windows.lock() {
+            if windows.remove(window.label()).is_some() {
+                workspace.dirty.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Start periodic saving and restore the last workspace unless
+/// `workspace.restoreOnLaunch` is off. A hidden login launch waits until
+/// the main window is first shown, and leaves the saved workspace alone
+/// until then.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    app.manage(WorkspaceState::default());
+    start_saver(app);
+
+    let restore_on_launch = app
+        .profile_store("settings.json")
+        .ok()
+        .and_then(|s| s.get("workspace.restoreOnLaunch"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    if safe_mode::is_active() {
+        info!("Safe mode: not restoring the workspace");
+    } else if restore_on_launch && autostart::launched_hidden() {
+        info!("Launched hidden: restoring the workspace once shown");
+        app.state::<WorkspaceState>()
+            .deferred
+            .store(true, Ordering::Relaxed);
+    } else if restore_on_launch {
+        restore(app, true);
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Record what the calling window is showing. Cheap; call on navigation
+/// and (throttled) as the reading position changes.
+#[tauri::command]
+pub fn report_window_state<R: Runtime>(
+    window: Window<R>,
+    workspace: State<'_, WorkspaceState>,
+    route: String,
+    book_id: Option<i64>,
+    position: Option<String>,
+) -> Result<(), String> {
+    let label = window.label().to_string();
+    if TRANSIENT_WINDOWS.contains(&label.as_str()) {
+        return Ok(());
+    }
+
+    let state = WindowState {
+        label: label.clone(),
+        route,
+        book_id,
+        position: position.clone(),
+    };
+    if let Some(book_id) = book_id {
+        if let Some(locator) = &position {
+            changelog::record_position(window.app_handle(), book_id, locator);
+        }
+        if let Ok(mut last_book) = workspace.last_book.lock() {
+            *last_book = Some(LastBook { book_id, position });
+        }
+    }
+    let switched_book = {
+        let mut windows = workspace
+            .windows
+            .lock()
+            .map_err(|_| "Workspace lock poisoned".to_string())?;
+        let previous = windows.get(&label).and_then(|w| w.book_id);
+        if windows.get(&label) != Some(&state) {
+            windows.insert(label.clone(), state);
+            workspace.dirty.store(true, Ordering::Relaxed);
+        }
+        previous != book_id
+    };
+
+    // A window that switched books takes that book's zoom
+    if switched_book {
+        zoom::restore(window.app_handle(), &label, book_id);
+    }
+    Ok(())
+}
+
+/// Save the workspace now
+#[tauri::command]
+pub fn save_workspace<R: Runtime>(app: AppHandle<R>) {
+    save(&app);
+}
+
+/// Reopen the windows from the last saved workspace
+#[tauri::command]
+pub async fn restore_workspace<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
+    // Async so window creation doesn't block the main thread on Windows
+    restore(&app, false);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_book_route_encodes_the_position() {
+        let last_book = LastBook {
+            book_id: 42,
+            position: Some("epubcfi(/6/4!/4/2:10)".to_string()),
+        };
+        assert_eq!(
+            last_book.route(),
+            "/reader/42?loc=epubcfi%28%2F6%2F4%21%2F4%2F2%3A10%29"
+        );
+
+        let no_position = LastBook {
+            book_id: 7,
+            position: None,
+        };
+        assert_eq!(no_position.route(), "/reader/7");
+    }
+}
@@ -0,0 +1,243 @@
+// Read Master Desktop - Reading Sessions
+//
+// Reading sessions recorded by the reader, and the reading speed derived
+// from them.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use log::info;
+use rusqlite::{params, Connection};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::db::Database;
+use crate::power::{self, PowerEvent};
+use crate::{activity, goals, jumplist};
+
+/// Reading speed used until enough sessions have been recorded
+pub const DEFAULT_WPM: f64 = 250.0;
+
+/// Minutes of timed reading needed before the measured speed is trusted
+const MIN_MEASURED_MINUTES: f64 = 10.0;
+
+/// Only recent sessions count, so the estimate follows the reader's pace
+const WPM_WINDOW_DAYS: i64 = 90;
+
+/// Measured speeds outside this range are treated as bad data
+const PLAUSIBLE_WPM: std::ops::RangeInclusive<f64> = 50.0..=1500.0;
+
+/// Suspends remembered for cutting sessions short
+const MAX_SUSPENDS: usize = 16;
+
+// ============================================================================
+// Sleep
+// ============================================================================
+
+/// When the system recently went to sleep. A session is paused there: the
+/// reader starts a new one on `system-resume`, so time asleep isn't counted.
+#[derive(Default)]
+pub struct SleepLog(Mutex<VecDeque<DateTime<Utc>>>);
+
+impl SleepLog {
+    fn push(&self, at: DateTime<Utc>) {
+        if let Ok(mut suspends) = self.0.lock() {
+            if suspends.len() == MAX_SUSPENDS {
+                suspends.pop_front();
+            }
+            suspends.push_back(at);
+        }
+    }
+
+    /// When a session from `started_at` to `ended_at` stopped counting:
+    /// the first suspend during it, if there was one
+    fn session_end(&self, started_at: DateTime<Utc>, ended_at: DateTime<Utc>) -> DateTime<Utc> {
+        self.0
+            .lock()
+            .ok()
+            .and_then(|suspends| {
+                suspends
+                    .iter()
+                    .copied()
+                    .filter(|&at| at > started_at && at < ended_at)
+                    .min()
+            })
+            .unwrap_or(ended_at)
+    }
+}
+
+/// Pause sessions on suspend. Must run after `power::init`.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    app.manage(SleepLog::default());
+
+    power::subscribe(app, {
+        let app = app.clone();
+        move |event| {
+            if event == PowerEvent::SystemSuspend {
+                app.state::<SleepLog>().push(Utc::now());
+            }
+        }
+    });
+}
+
+// ============================================================================
+// Queries
+// ============================================================================
+
+/// Average words per minute over recent sessions that recorded words read
+pub fn measured_wpm(conn: &Connection) -> rusqlite::Result<Option<f64>> {
+    let since = (Utc::now() - chrono::Duration::days(WPM_WINDOW_DAYS)).to_rfc3339();
+
+    let (words, minutes): (Option<f64>, Option<f64>) = conn.query_row(
+        "SELECT SUM(words_read),
+                SUM((julianday(ended_at) - julianday(started_at)) * 1440.0)
+         FROM reading_sessions
+         WHERE words_read > 0 AND started_at >= ?1 AND ended_at > started_at",
+        [since],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    Ok(match (words, minutes) {
+        (Some(words), Some(minutes)) if minutes >= MIN_MEASURED_MINUTES => {
+            Some(words / minutes).filter(|wpm| PLAUSIBLE_WPM.contains(wpm))
+        }
+        _ => None,
+    })
+}
+
+/// Measured reading speed, or the default when there isn't enough data
+pub fn words_per_minute(conn: &Connection) -> rusqlite::Result<f64> {
+    Ok(measured_wpm(conn)?.unwrap_or(DEFAULT_WPM))
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Record a finished reading session, ending it early if the system slept
+/// before it finished
+#[tauri::command]
+pub async fn record_reading_session<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    sleeps: State<'_, SleepLog>,
+    book_id: i64,
+    started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+    words_read: Option<i64>,
+) -> Result<i64, String> {
+    if ended_at <= started_at {
+        return Err("Session must end after it starts".to_string());
+    }
+
+    let ended_at = sleeps.session_end(started_at, ended_at);
+
+    info!(
+        "Recording reading session for book {} ({} min)",
+        book_id,
+        (ended_at - started_at).num_minutes()
+    );
+
+    let id = db.with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO reading_sessions (book_id, started_at, ended_at, words_read)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                book_id,
+                started_at.to_rfc3339(),
+                ended_at.to_rfc3339(),
+                words_read
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    })?;
+
+    jumplist::refresh(&app);
+    activity::invalidate(&app);
+    goals::refresh(&app);
+    Ok(id)
+}
+
+/// Correct the times or word count of a recorded session
+#[tauri::command]
+pub async fn update_reading_session<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    id: i64,
+    started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+    words_read: Option<i64>,
+) -> Result<(), String> {
+    if ended_at <= started_at {
+        return Err("Session must end after it starts".to_string());
+    }
+
+    let updated = db.with_conn(|conn| {
+        conn.execute(
+            "UPDATE reading_sessions SET started_at = ?2, ended_at = ?3, words_read = ?4
+             WHERE id = ?1",
+            params![
+                id,
+                started_at.to_rfc3339(),
+                ended_at.to_rfc3339(),
+                words_read
+            ],
+        )
+    })?;
+    if updated == 0 {
+        return Err(format!("Reading session not found: {}", id));
+    }
+
+    info!("Updated reading session {}", id);
+    activity::invalidate(&app);
+    goals::refresh(&app);
+    Ok(())
+}
+
+/// Delete a recorded session
+#[tauri::command]
+pub async fn delete_reading_session<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    id: i64,
+) -> Result<(), String> {
+    db.with_conn(|conn| conn.execute("DELETE FROM reading_sessions WHERE id = ?1", [id]))?;
+
+    info!("Deleted reading session {}", id);
+    jumplist::refresh(&app);
+    activity::invalidate(&app);
+    goals::refresh(&app);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minute: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + minute * 60, 0).unwrap()
+    }
+
+    #[test]
+    fn sessions_end_at_the_first_suspend_during_them() {
+        let sleeps = SleepLog::default();
+        assert_eq!(sleeps.session_end(at(0), at(30)), at(30));
+
+        sleeps.push(at(-5));
+        sleeps.push(at(20));
+        sleeps.push(at(10));
+        sleeps.push(at(45));
+        assert_eq!(sleeps.session_end(at(0), at(30)), at(10));
+        assert_eq!(sleeps.session_end(at(21), at(40)), at(40));
+    }
+
+    #[test]
+    fn only_recent_suspends_are_kept() {
+        let sleeps = SleepLog::default();
+        for minute in 0..=MAX_SUSPENDS as i64 {
+            sleeps.push(at(minute));
+        }
+        // The suspend at minute 0 has been dropped
+        assert_eq!(sleeps.session_end(at(-1), at(100)), at(1));
+    }
+}
@@ -0,0 +1,239 @@
+// Read Master Desktop - Shared Library Feed
+//
+// OPDS 1.2 acquisition feed of the local library: one entry per book with
+// links to its file and cover, paginated, plus the OpenSearch description
+// reader apps use to search it.
+
+use crate::formats::escape;
+use crate::library::Book;
+
+/// Entries per feed page
+pub const PAGE_SIZE: usize = 50;
+
+pub const FEED_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+pub const OPENSEARCH_TYPE: &str = "application/opensearchdescription+xml";
+
+pub const FEED_PATH: &str = "/opds";
+pub const SEARCH_PATH: &str = "/opds/search.xml";
+
+/// MIME type a book file is served as
+pub fn book_type(format: Option<&str>) -> &'static str {
+    match format {
+        Some("pdf") => "application/pdf",
+        _ => "application/epub+zip",
+    }
+}
+
+/// Books whose title, author, or tags contain every query term
+pub fn search<'a>(books: &'a [Book], query: Option<&str>) -> Vec<&'a Book> {
+    let terms: Vec<String> = query
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect();
+
+    books
+        .iter()
+        .filter(|book| {
+            let haystack = format!(
+                "{}\n{}\n{}",
+                book.title,
+                book.author.as_deref().unwrap_or_default(),
+                book.tags.join("\n")
+            )
+            .to_lowercase();
+            terms.iter().all(|term| haystack.contains(term))
+        })
+        .collect()
+}
+
+/// Number of pages `count` entries fill (at least one, possibly empty)
+pub fn page_count(count: usize) -> usize {
+    count.div_ceil(PAGE_SIZE).max(1)
+}
+
+fn page_href(page: usize, query: Option<&str>) -> String {
+    let mut href = format!("{}?page={}", FEED_PATH, page);
+    if let Some(query) = query {
+        href.push_str("&amp;q=");
+        href.push_str(&escape(
+            &url::form_urlencoded::byte_serialize(query.as_bytes()).collect::<String>(),
+        ));
+    }
+    href
+}
+
+fn link(rel: &str, href: &str, media_type: &str) -> String {
+    format!(
+        "  <link rel=\"{}\" href=\"{}\" type=\"{}\"/>\n",
+        rel, href, media_type
+    )
+}
+
+fn entry(book: &Book) -> String {
+    let id = match &book.content_hash {
+        Some(hash) => format!("urn:sha256:{}", hash),
+        None => format!("urn:readmaster:book:{}", book.id),
+    };
+
+    let mut xml = format!(
+        "  <entry>\n    <title>{}</title>\n    <id>{}</id>\n    <updated>{}</updated>\n",
+        escape(&book.title),
+        id,
+        escape(&book.updated_at)
+    );
+    if let Some(author) = &book.author {
+        xml.push_str(&format!(
+            "    <author><name>{}</name></author>\n",
+            escape(author)
+        ));
+    }
+    if let Some(description) = &book.description {
+        xml.push_str(&format!("    <summary>{}</summary>\n", escape(description)));
+    }
+    for tag in &book.tags {
+        xml.push_str(&format!("    <category term=\"{}\"/>\n", escape(tag)));
+    }
+    if book.cover_path.is_some() {
+        let cover = format!("/books/{}/cover", book.id);
+        xml.push_str(&format!(
+            "  {}  {}",
+            link("http://opds-spec.org/image", &cover, "image/jpeg"),
+            link("http://opds-spec.org/image/thumbnail", &cover, "image/jpeg")
+        ));
+    }
+    xml.push_str(&format!(
+        "  {}",
+        link(
+            "http://opds-spec.org/acquisition",
+            &format!("/books/{}/file", book.id),
+            book_type(book.format.as_deref())
+        )
+    ));
+    xml.push_str("  </entry>\n");
+    xml
+}
+
+/// One page (1-based) of the acquisition feed over `books`
+pub fn acquisition_feed(
+    books: &[&Book],
+    page: usize,
+    query: Option<&str>,
+    updated: &str,
+) -> String {
+    let pages = page_count(books.len());
+    let page = page.clamp(1, pages);
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\" \
+         xmlns:opds=\"http://opds-spec.org/2010/catalog\" \
+         xmlns:opensearch=\"http://a9.com/-/spec/opensearch/1.1/\">\n",
+    );
+    xml.push_str("  <id>urn:readmaster:library</id>\n");
+    xml.push_str(&format!(
+        "  <title>{}</title>\n",
+        match query {
+            Some(query) => escape(&format!("Read Master: {}", query)),
+            None => "Read Master Library".to_string(),
+        }
+    ));
+    xml.push_str(&format!("  <updated>{}</updated>\n", escape(updated)));
+    xml.push_str(&format!(
+        "  <opensearch:totalResults>{}</opensearch:totalResults>\n  <opensearch:itemsPerPage>{}</opensearch:itemsPerPage>\n",
+        books.len(),
+        PAGE_SIZE
+    ));
+
+    xml.push_str(&link("self", &page_href(page, query), FEED_TYPE));
+    xml.push_str(&link("start", FEED_PATH, FEED_TYPE));
+    xml.push_str(&link("search", SEARCH_PATH, OPENSEARCH_TYPE));
+    if page > 1 {
+        xml.push_str(&link("first", &page_href(1, query), FEED_TYPE));
+        xml.push_str(&link("previous", &page_href(page - 1, query), FEED_TYPE));
+    }
+    if page < pages {
+        xml.push_str(&link("next", &page_href(page + 1, query), FEED_TYPE));
+        xml.push_str(&link("last", &page_href(pages, query), FEED_TYPE));
+    }
+
+    for book in books.iter().skip((page - 1) * PAGE_SIZE).take(PAGE_SIZE) {
+        xml.push_str(&entry(book));
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// OpenSearch description pointing searches back at the feed
+pub fn opensearch_description() -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <OpenSearchDescription xmlns=\"http://a9.com/-/spec/opensearch/1.1/\">\n\
+         \x20 <ShortName>Read Master</ShortName>\n\
+         \x20 <Description>Search the Read Master library</Description>\n\
+         \x20 <Url type=\"{}\" template=\"{}?q={{searchTerms}}\"/>\n\
+         </OpenSearchDescription>\n",
+        escape(FEED_TYPE),
+        FEED_PATH
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: i64, title: &str, author: &str) -> Book {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "title": title,
+            "author": author,
+            "format": "epub",
+            "onDisk": true,
+            "addedAt": "2024-01-01T00:00:00Z",
+            "updatedAt": "2024-01-01T00:00:00Z",
+            "tags": ["classics"],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn searches_title_author_and_tags() {
+        let books = [
+            book(1, "Moby Dick", "Herman Melville"),
+            book(2, "Emma", "Jane Austen"),
+        ];
+        assert_eq!(search(&books, Some("austen")).len(), 1);
+        assert_eq!(search(&books, Some("MOBY melville")).len(), 1);
+        assert_eq!(search(&books, Some("classics")).len(), 2);
+        assert_eq!(search(&books, None).len(), 2);
+    }
+
+    #[test]
+    fn paginates_with_navigation_links() {
+        let books: Vec<Book> = (0..120).map(|i| book(i, "Title", "Author")).collect();
+        let all: Vec<&Book> = books.iter().collect();
+        assert_eq!(page_count(all.len()), 3);
+        assert_eq!(page_count(0), 1);
+
+        let xml = acquisition_feed(&all, 2, Some("a b"), "now");
+        assert_eq!(xml.matches("<entry>").count(), PAGE_SIZE);
+        assert!(xml.contains("rel=\"next\" href=\"/opds?page=3&amp;q=a+b\""));
+        assert!(xml.contains("rel=\"previous\" href=\"/opds?page=1&amp;q=a+b\""));
+        assert!(roxmltree::Document::parse(&xml).is_ok());
+
+        let last = acquisition_feed(&all, 9, None, "now");
+        assert_eq!(last.matches("<entry>").count(), 20);
+        assert!(!last.contains("rel=\"next\""));
+    }
+
+    #[test]
+    fn entries_link_the_book_file() {
+        let books = [book(7, "Emma & Co", "Jane Austen")];
+        let xml = acquisition_feed(&[&books[0]], 1, None, "now");
+        assert!(xml.contains("<title>Emma &amp; Co</title>"));
+        assert!(xml.contains(
+            "rel=\"http://opds-spec.org/acquisition\" href=\"/books/7/file\" type=\"application/epub+zip\""
+        ));
+        assert!(roxmltree::Document::parse(&opensearch_description()).is_ok());
+    }
+}
@@ -0,0 +1,514 @@
+// Read Master Desktop - Library Sharing
+//
+// Serves the library over HTTP on the local network so reader apps on other
+// devices can browse and download books: an OPDS acquisition feed (see
+// `feed`), the book files, and their covers.
+//
+// The server binds to private LAN addresses only, never every interface,
+// unless `share.allowAllInterfaces` is set, and is advertised over mDNS as
+// `_opds._tcp`. With auth on, clients sign in with HTTP Basic auth using a
+// PIN generated for each start (any username); an address that keeps
+// failing is locked out until the next start.
+//
+// Events: `share-device-connected` the first time each device makes an
+// authorized request.
+
+pub mod feed;
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Cursor;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use base64::Engine;
+use log::{info, warn};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::db::Database;
+use crate::library::{self, Book};
+use crate::profiles::ProfileStoreExt;
+
+const DEFAULT_PORT: u16 = 8787;
+const SERVICE_TYPE: &str = "_opds._tcp.local.";
+
+/// Failed sign-ins from one address before it is refused outright
+const MAX_FAILED_ATTEMPTS: u32 = 10;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerInfo {
+    pub port: u16,
+    /// Feed URLs, one per address served
+    pub urls: Vec<String>,
+    /// PIN to show the user; `None` when auth is off
+    pub pin: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceConnected {
+    address: String,
+    user_agent: Option<String>,
+}
+
+/// Per-start state shared by the request threads
+struct Session {
+    pin: Option<String>,
+    devices: Mutex<HashSet<IpAddr>>,
+    failures: Mutex<HashMap<IpAddr, u32>>,
+}
+
+struct Running {
+    servers: Vec<Arc<Server>>,
+    threads: Vec<JoinHandle<()>>,
+    mdns: Option<ServiceDaemon>,
+    info: ServerInfo,
+}
+
+/// The running server, if any
+#[derive(Default)]
+pub struct ShareServer(Mutex<Option<Running>>);
+
+// ============================================================================
+// Addresses
+// ============================================================================
+
+/// Private or link-local IPv4 addresses of this machine
+fn lan_addresses() -> Vec<Ipv4Addr> {
+    let interfaces = if_addrs::get_if_addrs().unwrap_or_else(|e| {
+        warn!("Failed to list network interfaces: {}", e);
+        Vec::new()
+    });
+    let mut addresses: Vec<Ipv4Addr> = interfaces
+        .into_iter()
+        .filter_map(|interface| match interface.ip() {
+            IpAddr::V4(ip) if ip.is_private() || ip.is_link_local() => Some(ip),
+            _ => None,
+        })
+        .collect();
+    addresses.sort();
+    addresses.dedup();
+    addresses
+}
+
+fn allow_all_interfaces<R: Runtime>(app: &AppHandle<R>) -> bool {
+    app.profile_store("settings.json")
+        .ok()
+        .and_then(|s| s.get("share.allowAllInterfaces"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Six random digits
+fn generate_pin() -> String {
+    let bytes = *uuid::Uuid::new_v4().as_bytes();
+    let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    format!("{:06}", value % 1_000_000)
+}
+
+/// Whether a Basic `Authorization` header carries `pin` as its password
+fn authorized(header: Option<&str>, pin: &str) -> bool {
+    let Some(encoded) = header.and_then(|h| h.strip_prefix("Basic ")) else {
+        return false;
+    };
+    let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(encoded.trim()) else {
+        return false;
+    };
+    let Ok(credentials) = String::from_utf8(decoded) else {
+        return false;
+    };
+    credentials
+        .split_once(':')
+        .is_some_and(|(_, password)| password == pin)
+}
+
+// ============================================================================
+// Requests
+// ============================================================================
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+fn text_response(status: u16, body: &str) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+}
+
+fn request_header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|h| h.value.as_str())
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+        .filter(|value| !value.trim().is_empty())
+}
+
+fn shared_books<R: Runtime>(app: &AppHandle<R>) -> Result<Vec<Book>, String> {
+    Ok(app
+        .state::<Database>()
+        .with_conn(|conn| library::list_books(conn))?
+        .into_iter()
+        .filter(|book| book.on_disk && book.path.is_some())
+        .collect())
+}
+
+fn shared_book<R: Runtime>(app: &AppHandle<R>, id: &str) -> Option<Book> {
+    let id: i64 = id.parse().ok()?;
+    shared_books(app).ok()?.into_iter().find(|b| b.id == id)
+}
+
+fn serve_file(
+    path: &str,
+    media_type: &str,
+    download_name: Option<String>,
+) -> Result<Response<File>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let mut response = Response::from_file(file).with_header(header("Content-Type", media_type));
+    if let Some(name) = download_name {
+        // Header values must be plain ASCII
+        let name: String = name
+            .chars()
+            .filter(|c| (c.is_ascii_graphic() || *c == ' ') && !matches!(c, '"' | '\\'))
+            .collect();
+        response = response.with_header(header(
+            "Content-Disposition",
+            &format!("attachment; filename=\"{}\"", name),
+        ));
+    }
+    Ok(response)
+}
+
+fn cover_type(path: &str) -> &'static str {
+    match Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
+/// Refuse the request unless it carries the PIN, handing it back if it
+/// may go on
+fn check_auth(session: &Session, request: Request, client: IpAddr) -> Option<Request> {
+    let Some(pin) = &session.pin else {
+        return Some(request);
+    };
+
+    let mut failures = session.failures.lock().ok()?;
+    let failed = failures.get(&client).copied().unwrap_or(0);
+    if failed >= MAX_FAILED_ATTEMPTS {
+        drop(failures);
+        let _ = request.respond(text_response(403, "Too many failed attempts"));
+        return None;
+    }
+    if authorized(request_header(&request, "Authorization"), pin) {
+        failures.remove(&client);
+        return Some(request);
+    }
+
+    // Reader apps send a first request without credentials; only count
+    // attempts that carried some
+    if request_header(&request, "Authorization").is_some() {
+        failures.insert(client, failed + 1);
+    }
+    drop(failures);
+    let _ = request.respond(
+        text_response(401, "PIN required")
+            .with_header(header("WWW-Authenticate", "Basic realm=\"Read Master\"")),
+    );
+    None
+}
+
+fn handle<R: Runtime>(app: &AppHandle<R>, session: &Session, request: Request) {
+    let client = request
+        .remote_addr()
+        .map(SocketAddr::ip)
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let Some(request) = check_auth(session, request, client) else {
+        return;
+    };
+
+    let is_new = session
+        .devices
+        .lock()
+        .map(|mut devices| devices.insert(client))
+        .unwrap_or(false);
+    if is_new {
+        let device = DeviceConnected {
+            address: client.to_string(),
+            user_agent: request_header(&request, "User-Agent").map(str::to_string),
+        };
+        info!("Library share: device connected from {}", device.address);
+        if let Err(e) = app.emit("share-device-connected", &device) {
+            warn!("Failed to emit share-device-connected: {}", e);
+        }
+    }
+
+    if request.method() != &Method::Get && request.method() != &Method::Head {
+        let _ = request.respond(text_response(405, "Method not allowed"));
+        return;
+    }
+
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let result = match segments.as_slice() {
+        ["" | "opds"] => shared_books(app).map(|books| {
+            let search = query_param(query, "q");
+            let page = query_param(query, "page")
+                .and_then(|page| page.parse().ok())
+                .unwrap_or(1);
+            let matches = feed::search(&books, search.as_deref());
+            let xml = feed::acquisition_feed(
+                &matches,
+                page,
+                search.as_deref(),
+                &chrono::Utc::now().to_rfc3339(),
+            );
+            request.respond(
+                Response::from_string(xml).with_header(header("Content-Type", feed::FEED_TYPE)),
+            )
+        }),
+        ["opds", "search.xml"] => Ok(request.respond(
+            Response::from_string(feed::opensearch_description())
+                .with_header(header("Content-Type", feed::OPENSEARCH_TYPE)),
+        )),
+        ["books", id, "file"] => match shared_book(app, id) {
+            Some(book) => {
+                let path = book.path.unwrap_or_default();
+                let name = Path::new(&path)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned());
+                serve_file(&path, feed::book_type(book.format.as_deref()), name)
+                    .map(|response| request.respond(response))
+            }
+            None => Ok(request.respond(text_response(404, "Book not found"))),
+        },
+        ["books", id, "cover"] => match shared_book(app, id).and_then(|b| b.cover_path) {
+            Some(cover) => serve_file(&cover, cover_type(&cover), None)
+                .map(|response| request.respond(response)),
+            None => Ok(request.respond(text_response(404, "Cover not found"))),
+        },
+        _ => Ok(request.respond(text_response(404, "Not found"))),
+    };
+
+    match result {
+        Ok(Err(e)) => warn!("Library share: failed to send {}: {}", path, e),
+        Err(e) => warn!("Library share: {} failed: {}", path, e),
+        Ok(Ok(())) => {}
+    }
+}
+
+// ============================================================================
+// Lifecycle
+// ============================================================================
+
+fn advertise(port: u16, addresses: &[Ipv4Addr]) -> Result<ServiceDaemon, String> {
+    let daemon = ServiceDaemon::new().map_err(|e| format!("Failed to start mDNS: {}", e))?;
+    let host = format!(
+        "{}.local.",
+        sysinfo::System::host_name().unwrap_or_else(|| "read-master".to_string())
+    );
+    let ips: Vec<IpAddr> = addresses.iter().copied().map(IpAddr::V4).collect();
+    let properties = [("path", feed::FEED_PATH)];
+
+    let service = ServiceInfo::new(
+        SERVICE_TYPE,
+        "Read Master Library",
+        &host,
+        &ips[..],
+        port,
+        &properties[..],
+    )
+    .map_err(|e| format!("Failed to describe mDNS service: {}", e))?;
+    daemon
+        .register(service)
+        .map_err(|e| format!("Failed to advertise library: {}", e))?;
+    Ok(daemon)
+}
+
+fn shutdown(running: Running) {
+    if let Some(mdns) = running.mdns {
+        let _ = mdns.shutdown();
+    }
+    for server in &running.servers {
+        server.unblock();
+    }
+    for thread in running.threads {
+        let _ = thread.join();
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Start sharing the library on the LAN. With `auth` (the default) a fresh
+/// PIN is generated and returned for display.
+#[tauri::command]
+pub fn start_library_server<R: Runtime>(
+    app: AppHandle<R>,
+    share: State<'_, ShareServer>,
+    port: Option<u16>,
+    auth: Option<bool>,
+) -> Result<ServerInfo, String> {
+    let mut running = share
+        .0
+        .lock()
+        .map_err(|_| "Share server lock poisoned".to_string())?;
+    if let Some(current) = &running {
+        return Err(format!(
+            "Library is already being shared on port {}",
+            current.info.port
+        ));
+    }
+
+    let port = port.unwrap_or(DEFAULT_PORT);
+    let addresses = lan_addresses();
+    let bind: Vec<IpAddr> = if allow_all_interfaces(&app) {
+        vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED)]
+    } else if addresses.is_empty() {
+        return Err("No local network connection to share on".to_string());
+    } else {
+        addresses.iter().copied().map(IpAddr::V4).collect()
+    };
+
+    let mut servers = Vec::new();
+    for ip in &bind {
+        let server = Server::http(SocketAddr::new(*ip, port))
+            .map_err(|e| format!("Failed to listen on {}:{}: {}", ip, port, e))?;
+        servers.push(Arc::new(server));
+    }
+
+    let pin = auth.unwrap_or(true).then(generate_pin);
+    let session = Arc::new(Session {
+        pin: pin.clone(),
+        devices: Mutex::default(),
+        failures: Mutex::default(),
+    });
+
+    let mut threads = Vec::new();
+    for server in &servers {
+        let server = Arc::clone(server);
+        let session = Arc::clone(&session);
+        let app = app.clone();
+        let thread = thread::Builder::new()
+            .name("library-share".into())
+            .spawn(move || {
+                for request in server.incoming_requests() {
+                    handle(&app, &session, request);
+                }
+            })
+            .map_err(|e| format!("Failed to start share server: {}", e))?;
+        threads.push(thread);
+    }
+
+    let mdns = match advertise(port, &addresses) {
+        Ok(daemon) => Some(daemon),
+        Err(e) => {
+            warn!("{}", e);
+            None
+        }
+    };
+
+    let info = ServerInfo {
+        port,
+        urls: addresses
+            .iter()
+            .map(|ip| format!("http://{}:{}{}", ip, port, feed::FEED_PATH))
+            .collect(),
+        pin,
+    };
+    info!("Sharing library on port {} ({:?})", port, bind);
+
+    *running = Some(Running {
+        servers,
+        threads,
+        mdns,
+        info: info.clone(),
+    });
+    Ok(info)
+}
+
+/// Stop sharing the library. Returns whether it was being shared.
+#[tauri::command]
+pub fn stop_library_server(share: State<'_, ShareServer>) -> Result<bool, String> {
+    let running = share
+        .0
+        .lock()
+        .map_err(|_| "Share server lock poisoned".to_string())?
+        .take();
+
+    match running {
+        Some(running) => {
+            info!("Stopping library share on port {}", running.info.port);
+            shutdown(running);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic(credentials: &str) -> String {
+        format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(credentials)
+        )
+    }
+
+    #[test]
+    fn accepts_the_pin_as_password() {
+        assert!(authorized(Some(&basic("kobo:123456")), "123456"));
+        assert!(authorized(Some(&basic(":123456")), "123456"));
+        assert!(!authorized(Some(&basic("kobo:654321")), "123456"));
+        assert!(!authorized(Some("Bearer 123456"), "123456"));
+        assert!(!authorized(None, "123456"));
+    }
+
+    #[test]
+    fn pins_are_six_digits() {
+        for _ in 0..20 {
+            let pin = generate_pin();
+            assert_eq!(pin.len(), 6);
+            assert!(pin.chars().all(|c| c.is_ascii_digit()));
+        }
+    }
+
+    #[test]
+    fn reads_query_parameters() {
+        assert_eq!(
+            query_param("page=2&q=jane+austen", "q").as_deref(),
+            Some("jane austen")
+        );
+        assert_eq!(query_param("page=2&q=", "q"), None);
+        assert_eq!(query_param("", "page"), None);
+    }
+}
@@ -0,0 +1,517 @@
+// Read Master Desktop - Spell Check
+//
+// Spell checking for the note editor, using Hunspell dictionaries so it
+// works the same on every platform. A language's `.aff`/`.dic` pair is
+// looked up in the bundled `hunspell` resource directory, then in the OS
+// dictionary folders. Dictionaries are several MB each, so they're parsed
+// the first time a check needs them and kept until their language is
+// turned off.
+//
+// Several languages can be active at once; a word is accepted when any of
+// them (or the personal dictionary) knows it, which keeps mixed-language
+// notes quiet. The active languages live in `spellcheck.languages`, and
+// the personal dictionary in `spellcheck.json`, both per profile.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use serde::Serialize;
+use spellbook::Dictionary;
+use tauri::{AppHandle, Manager, Runtime, State};
+
+use crate::persist;
+use crate::profiles::ProfileStoreExt;
+
+const SETTINGS_STORE: &str = "settings.json";
+const LANGUAGES_SETTING: &str = "spellcheck.languages";
+
+const PERSONAL_STORE: &str = "spellcheck.json";
+const PERSONAL_KEY: &str = "words";
+
+/// Resource directory holding bundled dictionaries
+const RESOURCE_DIR: &str = "hunspell";
+
+/// Language used when none is configured and the OS doesn't say
+const FALLBACK_LANGUAGE: &str = "en_US";
+
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Shared OS dictionary folders
+#[cfg(target_os = "linux")]
+const SYSTEM_DIRS: &[&str] = &[
+    "/usr/share/hunspell",
+    "/usr/share/myspell",
+    "/usr/share/myspell/dicts",
+];
+#[cfg(target_os = "macos")]
+const SYSTEM_DIRS: &[&str] = &["/Library/Spelling"];
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+const SYSTEM_DIRS: &[&str] = &[];
+
+/// Clitics an English word may end in after an apostrophe
+const SUFFIX_CLITICS: &[&str] = &["s", "t", "d", "m", "ll", "re", "ve"];
+
+/// Longest elided word before an apostrophe, as in "l'", "d'" or "qu'"
+const MAX_ELISION_CHARS: usize = 3;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// A word no active dictionary accepts. Offsets are in characters.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Misspelling {
+    pub word: String,
+    pub offset: usize,
+    pub length: usize,
+    pub suggestions: Vec<String>,
+}
+
+struct DictionaryFiles {
+    aff: PathBuf,
+    dic: PathBuf,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Active languages, read from settings on first use
+    languages: Option<Vec<String>>,
+    /// Parsed dictionaries by language
+    dictionaries: HashMap<String, Arc<Dictionary>>,
+    /// Lowercased personal words, read from the store on first use
+    personal: Option<HashSet<String>>,
+}
+
+/// Active languages, loaded dictionaries, and the personal dictionary
+#[derive(Default)]
+pub struct SpellChecker(Mutex<Inner>);
+
+// ============================================================================
+// Words
+// ============================================================================
+
+fn is_apostrophe(c: char) -> bool {
+    c == '\'' || c == '\u{2019}'
+}
+
+/// Letters, plus combining accents for text that isn't precomposed
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || ('\u{0300}'..='\u{036f}').contains(&c)
+}
+
+/// Words in `text` with their character offsets. An apostrophe between
+/// letters stays inside the word ("don't", "l'homme"); words with digits
+/// are skipped.
+fn words(text: &str) -> Vec<(usize, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if !is_word_char(chars[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() {
+            let joins = is_apostrophe(chars[i])
+                && i > start
+                && chars.get(i + 1).is_some_and(|&c| c.is_alphabetic());
+            if !is_word_char(chars[i]) && !joins {
+                break;
+            }
+            i += 1;
+        }
+
+        let word: String = chars[start..i].iter().collect();
+        if !word.chars().any(|c| c.is_numeric()) {
+            words.push((start, word));
+        }
+    }
+    words
+}
+
+/// Whether `word` is spelled right according to `check`. Typographic
+/// apostrophes are read as straight ones, and a contraction the
+/// dictionary lacks is accepted when its parts are: an elided article
+/// before the apostrophe, or an English clitic after it.
+fn accepted(word: &str, check: impl Fn(&str) -> bool) -> bool {
+    let word = word.replace('\u{2019}', "'");
+    if check(&word) {
+        return true;
+    }
+    let Some((head, tail)) = word.split_once('\'') else {
+        return false;
+    };
+
+    let elided = head.chars().count() <= MAX_ELISION_CHARS && check(tail);
+    let clitic = SUFFIX_CLITICS.contains(&tail.to_lowercase().as_str()) && check(head);
+    elided || clitic
+}
+
+// ============================================================================
+// Dictionaries
+// ============================================================================
+
+/// Language tag as Hunspell files name it: "pt-BR" becomes "pt_BR"
+fn normalize_language(lang: &str) -> String {
+    lang.trim().replace('-', "_")
+}
+
+/// The OS language, from a locale like "de_DE.UTF-8"
+fn system_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|locale| parse_locale(&locale))
+}
+
+fn parse_locale(locale: &str) -> Option<String> {
+    let lang = locale.split(['.', '@']).next()?.trim();
+    match lang {
+        "" | "C" | "POSIX" => None,
+        lang => Some(normalize_language(lang)),
+    }
+}
+
+fn search_dirs<R: Runtime>(app: &AppHandle<R>) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Ok(resources) = app.path().resource_dir() {
+        dirs.push(resources.join(RESOURCE_DIR));
+    }
+    if cfg!(target_os = "macos") {
+        if let Ok(home) = app.path().home_dir() {
+            dirs.push(home.join("Library/Spelling"));
+        }
+    }
+    dirs.extend(SYSTEM_DIRS.iter().map(PathBuf::from));
+    dirs
+}
+
+fn files_for(dir: &Path, stem: &str) -> Option<DictionaryFiles> {
+    let files = DictionaryFiles {
+        aff: dir.join(format!("{}.aff", stem)),
+        dic: dir.join(format!("{}.dic", stem)),
+    };
+    (files.aff.is_file() && files.dic.is_file()).then_some(files)
+}
+
+/// Dictionary files for `lang`: an exact match in any directory, or else
+/// a regional variant of a bare language ("en" finds "en_GB")
+fn find_files(dirs: &[PathBuf], lang: &str) -> Option<DictionaryFiles> {
+    if let Some(files) = dirs.iter().find_map(|dir| files_for(dir, lang)) {
+        return Some(files);
+    }
+    if lang.contains('_') {
+        return None;
+    }
+
+    let prefix = format!("{}_", lang);
+    dirs.iter().find_map(|dir| {
+        let mut stems: Vec<String> = std::fs::read_dir(dir)
+            .ok()?
+            .flatten()
+            .filter_map(|entry| {
+                let path = entry.path();
+                let is_aff = path.extension().is_some_and(|ext| ext == "aff");
+                let stem = path.file_stem()?.to_str()?.to_string();
+                (is_aff && stem.starts_with(&prefix)).then_some(stem)
+            })
+            .collect();
+        stems.sort();
+        stems.iter().find_map(|stem| files_for(dir, stem))
+    })
+}
+
+/// Read a dictionary file, falling back to Latin-1 for older ones
+fn read_text(path: &Path) -> Result<String, String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let text = match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => e.into_bytes().iter().map(|&b| char::from(b)).collect(),
+    };
+    Ok(text.trim_start_matches('\u{feff}').to_string())
+}
+
+fn load_dictionary(files: &DictionaryFiles) -> Result<Dictionary, String> {
+    let aff = read_text(&files.aff)?;
+    let dic = read_text(&files.dic)?;
+    Dictionary::new(&aff, &dic)
+        .map_err(|e| format!("Failed to parse dictionary {}: {}", files.dic.display(), e))
+}
+
+// ============================================================================
+// Checker
+// ============================================================================
+
+fn saved_languages<R: Runtime>(app: &AppHandle<R>) -> Vec<String> {
+    let saved: Option<Vec<String>> = app
+        .profile_store(SETTINGS_STORE)
+        .ok()
+        .and_then(|s| s.get(LANGUAGES_SETTING))
+        .and_then(|v| serde_json::from_value(v).ok());
+    saved
+        .unwrap_or_else(|| vec![system_language().unwrap_or_else(|| FALLBACK_LANGUAGE.to_string())])
+}
+
+fn saved_personal<R: Runtime>(app: &AppHandle<R>) -> HashSet<String> {
+    let words: Vec<String> = app
+        .profile_store(PERSONAL_STORE)
+        .ok()
+        .and_then(|s| s.get(PERSONAL_KEY))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    words.into_iter().map(|w| w.to_lowercase()).collect()
+}
+
+/// Misspelled words in `text`, judged by `dictionaries` and `personal`
+fn misspellings(
+    text: &str,
+    dictionaries: &[Arc<Dictionary>],
+    personal: &HashSet<String>,
+) -> Vec<Misspelling> {
+    if dictionaries.is_empty() {
+        return Vec::new();
+    }
+
+    let check = |word: &str| {
+        personal.contains(&word.to_lowercase()) || dictionaries.iter().any(|d| d.check(word))
+    };
+    words(text)
+        .into_iter()
+        .filter(|(_, word)| !accepted(word, check))
+        .map(|(offset, word)| {
+            let mut suggestions: Vec<String> = Vec::new();
+            for dictionary in dictionaries {
+                let mut found = Vec::new();
+                dictionary.suggest(&word, &mut found);
+                for suggestion in found {
+                    if !suggestions.contains(&suggestion) {
+                        suggestions.push(suggestion);
+                    }
+                }
+            }
+            suggestions.truncate(MAX_SUGGESTIONS);
+
+            Misspelling {
+                length: word.chars().count(),
+                word,
+                offset,
+                suggestions,
+            }
+        })
+        .collect()
+}
+
+impl SpellChecker {
+    /// The active languages' dictionaries and the personal words, parsing
+    /// any dictionary not loaded yet. Parsing happens outside the lock.
+    fn prepare<R: Runtime>(
+        &self,
+        app: &AppHandle<R>,
+    ) -> Result<(Vec<Arc<Dictionary>>, HashSet<String>), String> {
+        let (languages, personal) = {
+            let mut inner = self.lock()?;
+            let languages = inner
+                .languages
+                .get_or_insert_with(|| saved_languages(app))
+                .clone();
+            let personal = inner
+                .personal
+                .get_or_insert_with(|| saved_personal(app))
+                .clone();
+            (languages, personal)
+        };
+
+        let dirs = search_dirs(app);
+        let mut dictionaries = Vec::new();
+        for lang in languages {
+            if let Some(dictionary) = self.lock()?.dictionaries.get(&lang) {
+                dictionaries.push(Arc::clone(dictionary));
+                continue;
+            }
+            let Some(files) = find_files(&dirs, &lang) else {
+                warn!("No spell-check dictionary for {}", lang);
+                continue;
+            };
+
+            info!("Loading spell-check dictionary: {:?}", files.dic);
+            let dictionary = Arc::new(load_dictionary(&files)?);
+            self.lock()?
+                .dictionaries
+                .insert(lang, Arc::clone(&dictionary));
+            dictionaries.push(dictionary);
+        }
+        Ok((dictionaries, personal))
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Inner>, String> {
+        self.0
+            .lock()
+            .map_err(|_| "Spell checker lock poisoned".to_string())
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Set the languages notes are checked in, e.g. `["en-US", "de"]`. Every
+/// language needs a dictionary; an empty list turns spell check off.
+#[tauri::command]
+pub fn set_spellcheck_languages<R: Runtime>(
+    app: AppHandle<R>,
+    checker: State<'_, SpellChecker>,
+    langs: Vec<String>,
+) -> Result<(), String> {
+    let dirs = search_dirs(&app);
+    let mut languages: Vec<String> = Vec::new();
+    for lang in langs.iter().map(|l| normalize_language(l)) {
+        if lang.is_empty() || languages.contains(&lang) {
+            continue;
+        }
+        if find_files(&dirs, &lang).is_none() {
+            return Err(format!("Spell-check dictionary not available: {}", lang));
+        }
+        languages.push(lang);
+    }
+
+    let store = app
+        .profile_store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings: {}", e))?;
+    store.set(LANGUAGES_SETTING, serde_json::json!(languages));
+    persist::mark_dirty(&app, SETTINGS_STORE);
+
+    let mut inner = checker.lock()?;
+    inner
+        .dictionaries
+        .retain(|lang, _| languages.contains(lang));
+    info!("Spell-check languages: {:?}", languages);
+    inner.languages = Some(languages);
+    Ok(())
+}
+
+/// Misspelled words in `text`, with character offsets and suggestions
+#[tauri::command]
+pub async fn check_spelling<R: Runtime>(
+    app: AppHandle<R>,
+    text: String,
+) -> Result<Vec<Misspelling>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let (dictionaries, personal) = app.state::<SpellChecker>().prepare(&app)?;
+        Ok(misspellings(&text, &dictionaries, &personal))
+    })
+    .await
+    .map_err(|e| format!("Spell check task failed: {}", e))?
+}
+
+/// Accept `word` from now on, in this profile
+#[tauri::command]
+pub fn add_to_personal_dictionary<R: Runtime>(
+    app: AppHandle<R>,
+    checker: State<'_, SpellChecker>,
+    word: String,
+) -> Result<(), String> {
+    let word = word.trim().replace('\u{2019}', "'");
+    if word.is_empty() || word.chars().any(char::is_whitespace) {
+        return Err("Only single words can be added to the dictionary".to_string());
+    }
+
+    let store = app
+        .profile_store(PERSONAL_STORE)
+        .map_err(|e| format!("Failed to open personal dictionary: {}", e))?;
+    let mut words: Vec<String> = store
+        .get(PERSONAL_KEY)
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    if !words
+        .iter()
+        .any(|w| w.to_lowercase() == word.to_lowercase())
+    {
+        words.push(word.clone());
+        words.sort_by_key(|w| w.to_lowercase());
+        store.set(PERSONAL_KEY, serde_json::json!(words));
+        persist::mark_dirty(&app, PERSONAL_STORE);
+    }
+
+    let mut inner = checker.lock()?;
+    if let Some(personal) = inner.personal.as_mut() {
+        personal.insert(word.to_lowercase());
+    }
+    info!("Added {} to the personal dictionary", word);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dictionary(words: &[&str]) -> Arc<Dictionary> {
+        let dic = format!("{}\n{}\n", words.len(), words.join("\n"));
+        Arc::new(Dictionary::new("SET UTF-8\n", &dic).unwrap())
+    }
+
+    #[test]
+    fn splits_words_with_offsets() {
+        assert_eq!(
+            words("Don’t stop, café-au-lait 42nd l'été"),
+            [
+                (0, "Don’t".to_string()),
+                (6, "stop".to_string()),
+                (12, "café".to_string()),
+                (17, "au".to_string()),
+                (20, "lait".to_string()),
+                (30, "l'été".to_string()),
+            ]
+        );
+        assert_eq!(
+            words("'quoted' rock'n"),
+            [(1, "quoted".to_string()), (9, "rock'n".to_string())]
+        );
+    }
+
+    #[test]
+    fn accepts_contractions_by_their_parts() {
+        let known = |word: &str| ["homme", "Sam", "can't"].contains(&word);
+
+        assert!(accepted("can’t", known));
+        assert!(accepted("l'homme", known));
+        assert!(accepted("Sam's", known));
+        assert!(!accepted("Sam'x", known));
+        assert!(!accepted("truly'homme", known));
+    }
+
+    #[test]
+    fn any_active_dictionary_accepts_a_word() {
+        let english = dictionary(&["hello", "world"]);
+        let french = dictionary(&["bonjour", "café"]);
+        let personal: HashSet<String> = ["readmaster".to_string()].into();
+
+        let found = misspellings(
+            "Hello bonjour café ReadMaster wrold",
+            &[english, french],
+            &personal,
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].word.as_str(), found[0].offset), ("wrold", 30));
+        assert_eq!(found[0].length, 5);
+    }
+
+    #[test]
+    fn nothing_is_flagged_without_dictionaries() {
+        assert!(misspellings("zzxq", &[], &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn reads_languages_from_locales() {
+        assert_eq!(parse_locale("de_DE.UTF-8"), Some("de_DE".to_string()));
+        assert_eq!(parse_locale("sr_RS@latin"), Some("sr_RS".to_string()));
+        assert_eq!(parse_locale("C.UTF-8"), None);
+        assert_eq!(normalize_language(" pt-BR "), "pt_BR");
+    }
+}
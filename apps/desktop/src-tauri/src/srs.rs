@@ -0,0 +1,340 @@
+// Read Master Desktop - Spaced Repetition
+//
+// SM-2 scheduling for flashcards. Card states live in `flashcards.json`
+// keyed by card id, alongside the card content written by quick capture.
+
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::db::Database;
+use crate::profiles::ProfileStoreExt;
+use crate::{activity, persist};
+
+const STORE_FILE: &str = "flashcards.json";
+
+/// Starting ease factor for new cards
+pub const DEFAULT_EASE: f64 = 2.5;
+
+/// SM-2 never lets the ease factor fall below this
+pub const MIN_EASE: f64 = 1.3;
+
+/// Lowest grade that counts as a successful recall
+const PASSING_GRADE: u8 = 3;
+
+const MAX_GRADE: u8 = 5;
+
+/// Longest interval between reviews, about a century. Without a limit a
+/// long run of perfect reviews grows it past the dates chrono can hold.
+const MAX_INTERVAL_DAYS: u32 = 36_500;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CardState {
+    pub id: String,
+    pub deck_id: String,
+    pub front: String,
+    pub back: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Shown on request before the back is revealed
+    #[serde(default)]
+    pub hint: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Note the card was generated from, if any
+    #[serde(default)]
+    pub note_id: Option<String>,
+    pub created_at: String,
+    /// Successful reviews in a row; reset by a lapse
+    #[serde(default)]
+    pub repetitions: u32,
+    #[serde(default)]
+    pub interval_days: u32,
+    #[serde(default = "default_ease")]
+    pub ease_factor: f64,
+    /// Absent for cards that have never been reviewed
+    #[serde(default)]
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_reviewed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub lapses: u32,
+}
+
+/// A flashcard together with its review state
+pub type FlashCard = CardState;
+
+fn default_ease() -> f64 {
+    DEFAULT_EASE
+}
+
+// ============================================================================
+// Scheduling
+// ============================================================================
+
+/// Apply a review graded 0 (blackout) to 5 (perfect) at the current time
+pub fn review_card(card: CardState, grade: u8) -> CardState {
+    review_card_at(card, grade, Utc::now())
+}
+
+/// Apply a review at `now`. Grades above 5 are treated as 5.
+pub fn review_card_at(mut card: CardState, grade: u8, now: DateTime<Utc>) -> CardState {
+    let grade = grade.min(MAX_GRADE);
+
+    if grade >= PASSING_GRADE {
+        card.interval_days = match card.repetitions {
+            0 => 1,
+            1 => 6,
+            _ => (card.interval_days.max(1) as f64 * card.ease_factor)
+                .round()
+                .min(f64::from(MAX_INTERVAL_DAYS)) as u32,
+        };
+        card.repetitions += 1;
+    } else {
+        // Lapse: relearn from the start, but keep the (reduced) ease
+        if card.repetitions > 0 || card.due_at.is_some() {
+            card.lapses += 1;
+        }
+        card.repetitions = 0;
+        card.interval_days = 1;
+    }
+
+    let q = f64::from(MAX_GRADE - grade);
+    card.ease_factor = (card.ease_factor + 0.1 - q * (0.08 + q * 0.02)).max(MIN_EASE);
+
+    card.last_reviewed_at = Some(now);
+    card.due_at = Some(
+        now.checked_add_signed(Duration::days(i64::from(card.interval_days)))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC),
+    );
+    card
+}
+
+/// Whether a card should be shown at `now`. New cards are always due.
+pub fn is_due(card: &CardState, now: DateTime<Utc>) -> bool {
+    card.due_at.is_none_or(|due| due <= now)
+}
+
+/// Due cards of a deck, most overdue first and new cards last
+pub fn filter_due(cards: Vec<CardState>, deck_id: &str, now: DateTime<Utc>) -> Vec<CardState> {
+    let mut due: Vec<CardState> = cards
+        .into_iter()
+        .filter(|card| card.deck_id == deck_id && is_due(card, now))
+        .collect();
+
+    due.sort_by(|a, b| match (a.due_at, b.due_at) {
+        (Some(a_due), Some(b_due)) => a_due.cmp(&b_due),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.created_at.cmp(&b.created_at),
+    });
+    due
+}
+
+// ============================================================================
+// Store
+// ============================================================================
+
+/// Every card in the store, skipping entries that fail to parse
+pub fn load_all<R: Runtime>(app: &AppHandle<R>) -> Result<Vec<CardState>, String> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    Ok(store
+        .values()
+        .into_iter()
+        .filter_map(|value| serde_json::from_value(value).ok())
+        .collect())
+}
+
+/// Insert or replace a card
+pub fn save<R: Runtime>(app: &AppHandle<R>, card: &CardState) -> Result<(), String> {
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+
+    let value =
+        serde_json::to_value(card).map_err(|e| format!("Failed to serialize card: {}", e))?;
+    store.set(&card.id, value);
+    persist::mark_dirty(app, STORE_FILE);
+    Ok(())
+}
+
+/// Due cards of a deck at `now`
+pub fn get_due_cards<R: Runtime>(
+    app: &AppHandle<R>,
+    deck_id: &str,
+    now: DateTime<Utc>,
+) -> Result<Vec<CardState>, String> {
+    Ok(filter_due(load_all(app)?, deck_id, now))
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Grade a review of a card (0-5) and reschedule it
+#[tauri::command]
+pub async fn submit_review<R: Runtime>(
+    app: AppHandle<R>,
+    card_id: String,
+    grade: u8,
+) -> Result<CardState, String> {
+    if grade > MAX_GRADE {
+        return Err(format!("Grade must be between 0 and 5, got {}", grade));
+    }
+
+    let store = app
+        .profile_store(STORE_FILE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    let value = store
+        .get(&card_id)
+        .ok_or_else(|| format!("Card not found: {}", card_id))?;
+    let card: CardState =
+        serde_json::from_value(value).map_err(|e| format!("Corrupt card data: {}", e))?;
+
+    let card = review_card(card, grade);
+    info!(
+        "Reviewed card {} (grade {}), next in {} day(s)",
+        card.id, grade, card.interval_days
+    );
+
+    save(&app, &card)?;
+
+    // The review log only feeds statistics; the card itself is saved
+    let logged = app.state::<Database>().with_conn(|conn| {
+        conn.execute(
+            "INSERT INTO card_reviews (card_id, grade, reviewed_at) VALUES (?1, ?2, ?3)",
+            params![card.id, grade, Utc::now().to_rfc3339()],
+        )
+    });
+    if let Err(e) = logged {
+        warn!("Failed to log review of card {}: {}", card.id, e);
+    }
+    activity::invalidate(&app);
+    Ok(card)
+}
+
+/// Cards of a deck due for review now
+#[tauri::command]
+pub async fn due_cards<R: Runtime>(
+    app: AppHandle<R>,
+    deck_id: String,
+) -> Result<Vec<CardState>, String> {
+    get_due_cards(&app, &deck_id, Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_card(id: &str) -> CardState {
+        CardState {
+            id: id.to_string(),
+            deck_id: "default".to_string(),
+            front: "front".to_string(),
+            back: "back".to_string(),
+            tags: Vec::new(),
+            hint: None,
+            source: None,
+            note_id: None,
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+            repetitions: 0,
+            interval_days: 0,
+            ease_factor: DEFAULT_EASE,
+            due_at: None,
+            last_reviewed_at: None,
+            lapses: 0,
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2026-03-01T12:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn first_review_schedules_one_day() {
+        let card = review_card_at(new_card("a"), 4, now());
+        assert_eq!(card.repetitions, 1);
+        assert_eq!(card.interval_days, 1);
+        assert_eq!(card.due_at, Some(now() + Duration::days(1)));
+        assert_eq!(card.lapses, 0);
+        assert!((card.ease_factor - DEFAULT_EASE).abs() < 1e-9);
+    }
+
+    #[test]
+    fn successful_reviews_follow_sm2_intervals() {
+        let card = review_card_at(new_card("a"), 5, now());
+        let card = review_card_at(card, 5, now());
+        assert_eq!(card.interval_days, 6);
+        let ease = card.ease_factor;
+        let card = review_card_at(card, 5, now());
+        assert_eq!(card.interval_days, (6.0 * ease).round() as u32);
+        assert_eq!(card.repetitions, 3);
+    }
+
+    #[test]
+    fn intervals_stop_growing_at_the_limit() {
+        let mut card = new_card("a");
+        for _ in 0..100 {
+            card = review_card_at(card, 5, now());
+        }
+        assert_eq!(card.interval_days, MAX_INTERVAL_DAYS);
+        assert_eq!(
+            card.due_at,
+            Some(now() + Duration::days(i64::from(MAX_INTERVAL_DAYS)))
+        );
+    }
+
+    #[test]
+    fn failing_a_new_card_is_not_a_lapse() {
+        let card = review_card_at(new_card("a"), 1, now());
+        assert_eq!(card.repetitions, 0);
+        assert_eq!(card.interval_days, 1);
+        assert_eq!(card.lapses, 0);
+    }
+
+    #[test]
+    fn lapse_resets_repetitions_and_lowers_ease() {
+        let card = review_card_at(new_card("a"), 4, now());
+        let card = review_card_at(card, 4, now());
+        let card = review_card_at(card, 0, now());
+        assert_eq!(card.repetitions, 0);
+        assert_eq!(card.interval_days, 1);
+        assert_eq!(card.lapses, 1);
+        assert!(card.ease_factor < DEFAULT_EASE);
+    }
+
+    #[test]
+    fn ease_never_drops_below_minimum() {
+        let mut card = new_card("a");
+        for _ in 0..20 {
+            card = review_card_at(card, 0, now());
+        }
+        assert!((card.ease_factor - MIN_EASE).abs() < 1e-9);
+    }
+
+    #[test]
+    fn due_cards_put_new_cards_last() {
+        let reviewed = review_card_at(new_card("old"), 4, now() - Duration::days(3));
+        let future = review_card_at(new_card("future"), 4, now());
+        let mut other_deck = new_card("other");
+        other_deck.deck_id = "spanish".to_string();
+
+        let due = filter_due(
+            vec![new_card("new"), reviewed, future, other_deck],
+            "default",
+            now(),
+        );
+        let ids: Vec<&str> = due.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["old", "new"]);
+    }
+}
@@ -0,0 +1,533 @@
+// Read Master Desktop - Change Log
+//
+// An append-only feed of changes to notes, bookmarks, and reading
+// positions, for incremental sync. Each create, update, or delete is
+// appended to `changes.log` in the profile's data directory as one JSON
+// line, with a sequence number that only ever increases, the time of the
+// change, and the device it was made on. A peer asks for everything after
+// the last sequence number it saw.
+//
+// Records name books by content hash, since library ids differ between
+// devices. Remote changes are merged last-writer-wins per record: a change
+// is applied only if it's newer than the latest one logged here for the
+// same record, with the device id breaking ties so every device settles on
+// the same winner. Applied changes are appended to the local log with their
+// original time and device, so they pass on to further peers unchanged.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::db::Database;
+use crate::profiles::{self, ProfileStoreExt};
+use crate::{bookmarks, library, notes, persist};
+
+pub const LOG_FILE: &str = "changes.log";
+
+const SETTINGS_STORE: &str = "settings.json";
+const DEVICE_SETTING: &str = "sync.deviceId";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeEntity {
+    Note,
+    Bookmark,
+    /// A book's reading position, by the book's content hash
+    Position,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeOp {
+    Upsert,
+    Delete,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeRecord {
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    /// Device the change was made on
+    pub device: String,
+    pub entity: ChangeEntity,
+    /// Note or bookmark id, or the content hash for positions
+    pub id: String,
+    pub op: ChangeOp,
+    /// The record after the change; absent for deletes
+    #[serde(default)]
+    pub data: Option<Value>,
+}
+
+impl ChangeRecord {
+    fn key(&self) -> (ChangeEntity, &str) {
+        (self.entity, self.id.as_str())
+    }
+
+    fn version(&self) -> Version<'_> {
+        (self.timestamp, self.device.as_str())
+    }
+}
+
+/// What last-writer-wins compares: time, then device
+type Version<'a> = (DateTime<Utc>, &'a str);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeResult {
+    pub applied: usize,
+    /// Older than the latest local change to the same record
+    pub stale: usize,
+    /// Already in the local log
+    pub duplicates: usize,
+    /// For books not in this library
+    pub missing_books: usize,
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Apply,
+    Stale,
+    Duplicate,
+}
+
+/// Serializes appends and remembers what was logged
+#[derive(Default)]
+pub struct ChangeLog {
+    /// Last sequence number used, once read from the log
+    last_seq: Mutex<Option<u64>>,
+    /// Position last logged per book, so unchanged reports aren't logged
+    positions: Mutex<HashMap<i64, String>>,
+}
+
+// ============================================================================
+// Log File
+// ============================================================================
+
+fn log_path<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    Ok(profiles::data_dir(app)?.join(LOG_FILE))
+}
+
+/// Every record in the log, in sequence order. A line cut short by a crash
+/// mid-append is skipped.
+fn read_log(path: &Path) -> Result<Vec<ChangeRecord>, String> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to open change log: {}", e)),
+    };
+
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("Failed to read change log: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<ChangeRecord>(&line) {
+            Ok(record) => records.push(record),
+            Err(e) => warn!("Skipping corrupt change log entry: {}", e),
+        }
+    }
+    records.sort_by_key(|r| r.seq);
+    Ok(records)
+}
+
+/// Number `records` after the last logged change and append them
+fn append<R: Runtime>(app: &AppHandle<R>, records: &mut [ChangeRecord]) -> Result<(), String> {
+    if records.is_empty() {
+        return Ok(());
+    }
+    let state = app.state::<ChangeLog>();
+    let mut last_seq = state
+        .last_seq
+        .lock()
+        .map_err(|_| "Change log lock poisoned".to_string())?;
+    let path = log_path(app)?;
+
+    let mut seq = match *last_seq {
+        Some(seq) => seq,
+        None => read_log(&path)?.last().map_or(0, |r| r.seq),
+    };
+    let mut lines = String::new();
+    for record in records.iter_mut() {
+        seq += 1;
+        record.seq = seq;
+        let line = serde_json::to_string(record)
+            .map_err(|e| format!("Failed to serialize change: {}", e))?;
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("Failed to open change log: {}", e))?;
+    file.write_all(lines.as_bytes())
+        .map_err(|e| format!("Failed to write change log: {}", e))?;
+    *last_seq = Some(seq);
+    Ok(())
+}
+
+/// This device's sync id, created on first use
+fn device_id<R: Runtime>(app: &AppHandle<R>) -> Result<String, String> {
+    let store = app
+        .profile_store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    if let Some(id) = store
+        .get(DEVICE_SETTING)
+        .and_then(|v| v.as_str().map(str::to_string))
+    {
+        return Ok(id);
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    store.set(DEVICE_SETTING, id.clone());
+    persist::mark_dirty(app, SETTINGS_STORE);
+    Ok(id)
+}
+
+// ============================================================================
+// Recording
+// ============================================================================
+
+/// Log a local change. `data` is the record after the change, or `None`
+/// for a delete. Failures are logged rather than failing the edit itself.
+pub fn record<R: Runtime>(app: &AppHandle<R>, entity: ChangeEntity, id: &str, data: Option<Value>) {
+    if app.try_state::<ChangeLog>().is_none() {
+        return;
+    }
+    let result = device_id(app).and_then(|device| {
+        append(
+            app,
+            &mut [ChangeRecord {
+                seq: 0,
+                timestamp: Utc::now(),
+                device,
+                entity,
+                id: id.to_string(),
+                op: if data.is_some() {
+                    ChangeOp::Upsert
+                } else {
+                    ChangeOp::Delete
+                },
+                data,
+            }],
+        )
+    });
+    if let Err(e) = result {
+        warn!("Failed to log {:?} change {}: {}", entity, id, e);
+    }
+}
+
+/// Log a book's reading position if it moved since it was last logged
+pub fn record_position<R: Runtime>(app: &AppHandle<R>, book_id: i64, locator: &str) {
+    let Some(state) = app.try_state::<ChangeLog>() else {
+        return;
+    };
+    if let Ok(mut positions) = state.positions.lock() {
+        if positions.get(&book_id).map(String::as_str) == Some(locator) {
+            return;
+        }
+        positions.insert(book_id, locator.to_string());
+    }
+
+    let book = match app
+        .state::<Database>()
+        .with_conn(|conn| library::get_book(conn, book_id))
+    {
+        Ok(Some(book)) => book,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("Failed to log reading position: {}", e);
+            return;
+        }
+    };
+    // Without a hash the position can't be matched on another device
+    let Some(hash) = book.content_hash else {
+        return;
+    };
+    record(
+        app,
+        ChangeEntity::Position,
+        &hash,
+        Some(serde_json::json!({ "locator": locator, "progress": book.progress })),
+    );
+}
+
+// ============================================================================
+// Merging
+// ============================================================================
+
+/// Latest version logged for each record
+fn latest_versions(records: &[ChangeRecord]) -> HashMap<(ChangeEntity, &str), Version<'_>> {
+    let mut latest: HashMap<(ChangeEntity, &str), Version<'_>> = HashMap::new();
+    for record in records {
+        let version = record.version();
+        latest
+            .entry(record.key())
+            .and_modify(|v| *v = (*v).max(version))
+            .or_insert(version);
+    }
+    latest
+}
+
+/// Sort incoming changes oldest first and decide which win. Each winner
+/// becomes the latest version, so an older change to the same record later
+/// in the batch loses to it.
+fn plan<'a>(
+    mut latest: HashMap<(ChangeEntity, &'a str), Version<'a>>,
+    incoming: &'a mut [ChangeRecord],
+) -> Vec<(&'a ChangeRecord, Verdict)> {
+    incoming.sort_by(|a, b| a.version().cmp(&b.version()));
+    let incoming: &'a [ChangeRecord] = incoming;
+    incoming
+        .iter()
+        .map(|change| {
+            let verdict = match latest.get(&change.key()) {
+                Some(v) if *v == change.version() => Verdict::Duplicate,
+                Some(v) if *v > change.version() => Verdict::Stale,
+                _ => Verdict::Apply,
+            };
+            if verdict == Verdict::Apply {
+                latest.insert(change.key(), change.version());
+            }
+            (change, verdict)
+        })
+        .collect()
+}
+
+/// The local id of the book with `hash`
+fn book_for_hash<R: Runtime>(app: &AppHandle<R>, hash: &str) -> Result<Option<i64>, String> {
+    app.state::<Database>()
+        .with_conn(|conn| library::find_by_hash(conn, hash))
+}
+
+/// Apply one change to local data. Returns false if it's for a book this
+/// library doesn't have.
+fn apply<R: Runtime>(app: &AppHandle<R>, change: &ChangeRecord) -> Result<bool, String> {
+    let data = match (change.op, &change.data) {
+        (ChangeOp::Upsert, Some(data)) => Some(data),
+        (ChangeOp::Upsert, None) => return Err(format!("Change {} has no data", change.seq)),
+        (ChangeOp::Delete, _) => None,
+    };
+    let book_hash = data.and_then(|d| d.get("bookHash")).and_then(Value::as_str);
+
+    match change.entity {
+        ChangeEntity::Note => {
+            let book_id = match book_hash {
+                Some(hash) => match book_for_hash(app, hash)? {
+                    Some(id) => Some(id),
+                    None => return Ok(false),
+                },
+                None => None,
+            };
+            notes::apply_change(app, &change.id, data, book_id)?;
+        }
+        ChangeEntity::Bookmark => {
+            let db = app.state::<Database>();
+            match data {
+                Some(data) => {
+                    let Some(book_id) =
+                        book_hash.map_or(Ok(None), |hash| book_for_hash(app, hash))?
+                    else {
+                        return Ok(false);
+                    };
+                    let book = db
+                        .with_conn(|conn| library::get_book(conn, book_id))?
+                        .ok_or_else(|| format!("Book not found: {}", book_id))?;
+                    db.with_conn(|conn| bookmarks::apply_sync_record(conn, &book, data))?;
+                }
+                None => {
+                    db.with_conn(|conn| bookmarks::remove(conn, &change.id))?;
+                }
+            }
+        }
+        ChangeEntity::Position => {
+            let Some(data) = data else {
+                return Ok(true);
+            };
+            let Some(book_id) = book_for_hash(app, &change.id)? else {
+                return Ok(false);
+            };
+            let progress = data.get("progress").and_then(Value::as_f64);
+            if progress.is_some() {
+                app.state::<Database>()
+                    .with_conn(|conn| library::set_progress(conn, book_id, progress))?;
+            }
+            let _ = app.emit(
+                "reading-position-synced",
+                serde_json::json!({
+                    "bookId": book_id,
+                    "locator": data.get("locator"),
+                    "progress": progress,
+                }),
+            );
+        }
+    }
+    Ok(true)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Changes logged after sequence number `seq`, oldest first
+#[tauri::command]
+pub async fn get_changes_since<R: Runtime>(
+    app: AppHandle<R>,
+    seq: u64,
+) -> Result<Vec<ChangeRecord>, String> {
+    let path = log_path(&app)?;
+    let mut records = tauri::async_runtime::spawn_blocking(move || read_log(&path))
+        .await
+        .map_err(|e| format!("Change log task failed: {}", e))??;
+    records.retain(|r| r.seq > seq);
+    Ok(records)
+}
+
+/// Merge changes from another device, last writer wins per record
+#[tauri::command]
+pub async fn apply_remote_changes<R: Runtime>(
+    app: AppHandle<R>,
+    changes: Vec<ChangeRecord>,
+) -> Result<MergeResult, String> {
+    let mut changes = changes;
+    let local = read_log(&log_path(&app)?)?;
+    let mut result = MergeResult::default();
+    let mut applied = Vec::new();
+
+    for (change, verdict) in plan(latest_versions(&local), &mut changes) {
+        match verdict {
+            Verdict::Stale => result.stale += 1,
+            Verdict::Duplicate => result.duplicates += 1,
+            Verdict::Apply => match apply(&app, change) {
+                Ok(true) => {
+                    result.applied += 1;
+                    applied.push(change.clone());
+                }
+                Ok(false) => result.missing_books += 1,
+                Err(e) => {
+                    warn!(
+                        "Failed to apply change {} from {}: {}",
+                        change.id, change.device, e
+                    );
+                    result.errors.push(e);
+                }
+            },
+        }
+    }
+    append(&app, &mut applied)?;
+
+    info!(
+        "Merged remote changes: {} applied, {} stale, {} duplicate, {} for missing books",
+        result.applied, result.stale, result.duplicates, result.missing_books
+    );
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(id: &str, minute: u32, device: &str) -> ChangeRecord {
+        ChangeRecord {
+            seq: 0,
+            timestamp: format!("2026-10-16T12:{:02}:00Z", minute).parse().unwrap(),
+            device: device.to_string(),
+            entity: ChangeEntity::Note,
+            id: id.to_string(),
+            op: ChangeOp::Upsert,
+            data: Some(Value::Null),
+        }
+    }
+
+    fn verdicts(local: &[ChangeRecord], incoming: &[ChangeRecord]) -> Vec<(String, u32, Verdict)> {
+        let mut incoming = incoming.to_vec();
+        plan(latest_versions(local), &mut incoming)
+            .into_iter()
+            .map(|(c, v)| {
+                (
+                    c.id.clone(),
+                    c.timestamp.format("%M").to_string().parse().unwrap(),
+                    v,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn newer_changes_win() {
+        let local = [change("a", 5, "here"), change("b", 5, "here")];
+        let incoming = [change("a", 10, "there"), change("b", 1, "there")];
+        assert_eq!(
+            verdicts(&local, &incoming),
+            [
+                ("b".to_string(), 1, Verdict::Stale),
+                ("a".to_string(), 10, Verdict::Apply),
+            ]
+        );
+    }
+
+    #[test]
+    fn ties_break_by_device_and_repeats_are_duplicates() {
+        let local = [change("a", 5, "m")];
+        let incoming = [
+            change("a", 5, "z"),
+            change("a", 5, "b"),
+            change("a", 5, "m"),
+        ];
+        let result = verdicts(&local, &incoming);
+        assert_eq!(result[0].2, Verdict::Stale);
+        assert_eq!(result[1].2, Verdict::Duplicate);
+        assert_eq!(result[2].2, Verdict::Apply);
+    }
+
+    #[test]
+    fn the_latest_change_in_a_batch_wins() {
+        let incoming = [
+            change("a", 9, "x"),
+            change("a", 3, "y"),
+            change("c", 1, "y"),
+        ];
+        let result = verdicts(&[], &incoming);
+        assert!(result.iter().all(|(_, _, v)| *v == Verdict::Apply));
+        // Applied oldest first, so the newest is written last
+        assert_eq!(result[1], ("a".to_string(), 3, Verdict::Apply));
+        assert_eq!(result[2], ("a".to_string(), 9, Verdict::Apply));
+    }
+
+    #[test]
+    fn reads_back_in_order_skipping_torn_lines() {
+        let dir = std::env::temp_dir().join(format!("changelog-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(LOG_FILE);
+
+        let mut second = change("b", 2, "x");
+        second.seq = 2;
+        let mut first = change("a", 1, "x");
+        first.seq = 1;
+        let lines = format!(
+            "{}\n{}\n{{\"seq\":3,\"time",
+            serde_json::to_string(&second).unwrap(),
+            serde_json::to_string(&first).unwrap()
+        );
+        fs::write(&path, lines).unwrap();
+
+        assert_eq!(read_log(&path).unwrap(), [first, second]);
+        assert!(read_log(&dir.join("missing.log")).unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
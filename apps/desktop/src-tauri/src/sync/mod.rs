@@ -0,0 +1,281 @@
+// Read Master Desktop - Sync Bundles
+//
+// Per-book reading data (progress, bookmarks, annotations) packed into a
+// versioned JSON bundle for file-based sync backends. Bundles are named by
+// the book's content hash, since library ids differ between devices.
+//
+// Conflicts are resolved with a three-way merge against the last bundle
+// both sides agreed on, which each backend keeps locally. Records are
+// matched by id: a side that left a record as it was in the base takes the
+// other side's change, an edit beats a delete, and when both sides edited
+// a record the later `updatedAt` wins.
+
+pub mod changelog;
+pub mod webdav;
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::db::Database;
+use crate::library::{self, Book};
+use crate::{bookmarks, notes};
+
+/// Bundle format version; bundles from newer versions are left alone
+pub const BUNDLE_VERSION: u32 = 1;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// The synced part of a bundle, compared to decide what changed
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleContent {
+    pub progress: Option<f64>,
+    #[serde(default)]
+    pub bookmarks: Vec<Value>,
+    #[serde(default)]
+    pub annotations: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bundle {
+    pub version: u32,
+    pub content_hash: String,
+    pub title: String,
+    pub updated_at: String,
+    #[serde(flatten)]
+    pub content: BundleContent,
+}
+
+impl Bundle {
+    pub fn new(book: &Book, hash: &str, content: BundleContent) -> Self {
+        Self {
+            version: BUNDLE_VERSION,
+            content_hash: hash.to_string(),
+            title: book.title.clone(),
+            updated_at: chrono::Utc::now().to_rfc3339(),
+            content,
+        }
+    }
+}
+
+// ============================================================================
+// Merging
+// ============================================================================
+
+fn record_id(record: &Value) -> Option<&str> {
+    record.get("id").and_then(Value::as_str)
+}
+
+fn updated_at(record: &Value) -> &str {
+    record
+        .get("updatedAt")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+fn by_id(records: &[Value]) -> BTreeMap<&str, &Value> {
+    records
+        .iter()
+        .filter_map(|r| record_id(r).map(|id| (id, r)))
+        .collect()
+}
+
+/// Merge record lists by id, in id order
+pub fn merge_records(base: &[Value], local: &[Value], remote: &[Value]) -> Vec<Value> {
+    let base = by_id(base);
+    let local = by_id(local);
+    let remote = by_id(remote);
+
+    let mut ids: Vec<&str> = local.keys().chain(remote.keys()).copied().collect();
+    ids.sort_unstable();
+    ids.dedup();
+
+    ids.into_iter()
+        .filter_map(|id| {
+            let base = base.get(id).copied();
+            let merged = match (local.get(id).copied(), remote.get(id).copied()) {
+                (Some(local), Some(remote)) => {
+                    if Some(local) == base {
+                        remote
+                    } else if Some(remote) == base || updated_at(local) >= updated_at(remote) {
+                        local
+                    } else {
+                        remote
+                    }
+                }
+                // Deleted on the other side: drop it unless edited since
+                (Some(only), None) | (None, Some(only)) => {
+                    if Some(only) == base {
+                        return None;
+                    }
+                    only
+                }
+                (None, None) => return None,
+            };
+            Some(merged.clone())
+        })
+        .collect()
+}
+
+/// Progress changed on both sides keeps the furthest position
+fn merge_progress(base: Option<f64>, local: Option<f64>, remote: Option<f64>) -> Option<f64> {
+    if local == base {
+        remote
+    } else if remote == base {
+        local
+    } else {
+        match (local, remote) {
+            (Some(l), Some(r)) => Some(l.max(r)),
+            (l, r) => l.or(r),
+        }
+    }
+}
+
+/// Three-way merge of bundle contents. Without a base every record is new
+/// to the other side, so nothing is deleted.
+pub fn merge(
+    base: Option<&BundleContent>,
+    local: &BundleContent,
+    remote: &BundleContent,
+) -> BundleContent {
+    let empty = BundleContent::default();
+    let base = base.unwrap_or(&empty);
+    BundleContent {
+        progress: merge_progress(base.progress, local.progress, remote.progress),
+        bookmarks: merge_records(&base.bookmarks, &local.bookmarks, &remote.bookmarks),
+        annotations: merge_records(&base.annotations, &local.annotations, &remote.annotations),
+    }
+}
+
+// ============================================================================
+// Local Data
+// ============================================================================
+
+fn sorted(mut records: Vec<Value>) -> Vec<Value> {
+    records.sort_by(|a, b| record_id(a).cmp(&record_id(b)));
+    records
+}
+
+/// A book's current reading data
+pub fn local_content<R: Runtime>(app: &AppHandle<R>, book: &Book) -> Result<BundleContent, String> {
+    let bookmarks = app
+        .state::<Database>()
+        .with_conn(|conn| bookmarks::sync_records(conn, book.id))?;
+    let annotations = notes::sync_records(app, &book.id.to_string())?;
+
+    Ok(BundleContent {
+        progress: book.progress,
+        bookmarks: sorted(bookmarks),
+        annotations: sorted(annotations),
+    })
+}
+
+/// Replace a book's reading data with merged content. Progress and
+/// bookmarks change in one transaction; annotations are written after.
+/// Bookmark and annotation changes go into the change log.
+pub fn apply_content<R: Runtime>(
+    app: &AppHandle<R>,
+    book: &Book,
+    content: &BundleContent,
+) -> Result<(), String> {
+    let before = app.state::<Database>().with_conn(|conn| {
+        let tx = conn.transaction()?;
+        let before = bookmarks::sync_records(&tx, book.id)?;
+        library::set_progress(&tx, book.id, content.progress)?;
+        bookmarks::apply_sync_records(&tx, book, &content.bookmarks)?;
+        tx.commit()?;
+        Ok(before)
+    })?;
+    bookmarks::record_sync_changes(app, book, &before, &content.bookmarks);
+    notes::apply_sync_records(app, &book.id.to_string(), &content.annotations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn note(id: &str, body: &str, updated_at: &str) -> Value {
+        json!({ "id": id, "body": body, "updatedAt": updated_at })
+    }
+
+    #[test]
+    fn takes_the_side_that_changed() {
+        let base = [note("a", "old", "1"), note("b", "keep", "1")];
+        let local = [note("a", "old", "1"), note("b", "keep", "1")];
+        let remote = [note("a", "new", "2"), note("b", "keep", "1")];
+
+        let merged = merge_records(&base, &local, &remote);
+        assert_eq!(merged, [note("a", "new", "2"), note("b", "keep", "1")]);
+        assert_eq!(merge_records(&base, &remote, &local), merged);
+    }
+
+    #[test]
+    fn both_edited_keeps_the_later_edit() {
+        let base = [note("a", "old", "1")];
+        let local = [note("a", "local", "3")];
+        let remote = [note("a", "remote", "2")];
+        assert_eq!(merge_records(&base, &local, &remote), local);
+        assert_eq!(merge_records(&base, &remote, &local), local);
+    }
+
+    #[test]
+    fn deletes_unless_edited_on_the_other_side() {
+        let base = [note("a", "old", "1"), note("b", "old", "1")];
+        let local = [note("a", "old", "1"), note("b", "edited", "2")];
+        let remote: [Value; 0] = [];
+        assert_eq!(
+            merge_records(&base, &local, &remote),
+            [note("b", "edited", "2")]
+        );
+    }
+
+    #[test]
+    fn keeps_additions_from_both_sides() {
+        let local = [note("a", "local", "1")];
+        let remote = [note("b", "remote", "1")];
+        assert_eq!(merge_records(&[], &local, &remote).len(), 2);
+    }
+
+    #[test]
+    fn merges_progress() {
+        assert_eq!(merge_progress(Some(0.2), Some(0.2), Some(0.5)), Some(0.5));
+        assert_eq!(merge_progress(Some(0.2), Some(0.4), Some(0.2)), Some(0.4));
+        assert_eq!(merge_progress(Some(0.2), Some(0.4), Some(0.3)), Some(0.4));
+        assert_eq!(merge_progress(None, None, Some(0.3)), Some(0.3));
+    }
+
+    #[test]
+    fn merge_is_stable_once_applied() {
+        let base = BundleContent {
+            progress: Some(0.1),
+            bookmarks: vec![note("a", "old", "1")],
+            annotations: Vec::new(),
+        };
+        let local = BundleContent {
+            progress: Some(0.3),
+            bookmarks: vec![note("a", "old", "1"), note("c", "new", "2")],
+            annotations: Vec::new(),
+        };
+        let remote = BundleContent {
+            progress: Some(0.2),
+            bookmarks: Vec::new(),
+            annotations: vec![note("n", "body", "2")],
+        };
+
+        let merged = merge(Some(&base), &local, &remote);
+        assert_eq!(merged.progress, Some(0.3));
+        assert_eq!(merged.bookmarks, [note("c", "new", "2")]);
+        assert_eq!(merged.annotations.len(), 1);
+
+        // A retry after a partial failure arrives at the same result
+        assert_eq!(merge(Some(&base), &merged, &merged), merged);
+        assert_eq!(merge(Some(&base), &local, &merged), merged);
+    }
+}
@@ -0,0 +1,613 @@
+// Read Master Desktop - WebDAV Sync
+//
+// Syncs reading data with a WebDAV server such as Nextcloud or ownCloud.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{info, warn};
+use reqwest::header::{HeaderValue, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH};
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use url::Url;
+
+use super::{Bundle, BundleContent, BUNDLE_VERSION};
+use crate::db::Database;
+use crate::library::{self, Book};
+use crate::net::RequestError;
+use crate::profiles::{self, ProfileStoreExt};
+use crate::{keychain, persist};
+
+const SETTINGS_STORE: &str = "settings.json";
+const URL_SETTING: &str = "webdav.url";
+const USERNAME_SETTING: &str = "webdav.username";
+const ACCEPT_INVALID_CERTS_SETTING: &str = "webdav.acceptInvalidCerts";
+
+/// Keychain entry holding the WebDAV password
+const PASSWORD_KEY: &str = "webdav.password";
+
+/// Collection the bundles live in, relative to the configured URL. Each
+/// book is one bundle, `{content hash}.json`.
+const COLLECTION: &str = "ReadMaster/";
+
+/// Upload attempts per book when the bundle keeps changing underneath us
+const MAX_ATTEMPTS: u32 = 3;
+
+static SYNC_RUNNING: AtomicBool = AtomicBool::new(false);
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BookSyncStatus {
+    /// Both sides already agreed
+    Unchanged,
+    Uploaded,
+    Downloaded,
+    /// Changes from both sides were combined
+    Merged,
+    Failed,
+}
+
+/// Result for one book, emitted as `webdav-sync-book`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookSyncResult {
+    pub book_id: i64,
+    pub content_hash: String,
+    pub title: String,
+    pub status: BookSyncStatus,
+    pub error: Option<String>,
+}
+
+/// Totals for a sync, emitted as `webdav-sync-done`
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncSummary {
+    pub total: usize,
+    pub unchanged: usize,
+    pub uploaded: usize,
+    pub downloaded: usize,
+    pub merged: usize,
+    pub failed: usize,
+}
+
+impl SyncSummary {
+    fn count(&mut self, status: BookSyncStatus) {
+        match status {
+            BookSyncStatus::Unchanged => self.unchanged += 1,
+            BookSyncStatus::Uploaded => self.uploaded += 1,
+            BookSyncStatus::Downloaded => self.downloaded += 1,
+            BookSyncStatus::Merged => self.merged += 1,
+            BookSyncStatus::Failed => self.failed += 1,
+        }
+    }
+}
+
+/// Last bundle both sides agreed on, kept with its etag in
+/// `app_data/webdav/` as the base of the three-way merge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Snapshot {
+    etag: Option<String>,
+    content: BundleContent,
+}
+
+/// Condition attached to an upload (`If-Match`/`If-None-Match`), so a
+/// concurrent change from another device is never overwritten
+enum Precondition {
+    /// The bundle must not exist yet
+    Absent,
+    /// The bundle must still have this etag
+    Matches(String),
+    /// The server gave no etag; upload unconditionally
+    Unconditional,
+}
+
+enum PutResult {
+    Stored(Option<String>),
+    /// The bundle changed since it was read
+    Conflict,
+}
+
+// ============================================================================
+// Settings
+// ============================================================================
+
+fn setting<R: Runtime>(app: &AppHandle<R>, key: &str) -> Option<serde_json::Value> {
+    app.profile_store(SETTINGS_STORE).ok()?.get(key)
+}
+
+fn accepts_invalid_certs<R: Runtime>(app: &AppHandle<R>) -> bool {
+    setting(app, ACCEPT_INVALID_CERTS_SETTING)
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Parse the server URL, making sure it ends in a slash so the collection
+/// is joined below it rather than replacing its last segment
+fn parse_url(url: &str) -> Result<Url, String> {
+    let mut url = Url::parse(url.trim()).map_err(|e| format!("Invalid WebDAV URL: {}", e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("Unsupported WebDAV URL scheme: {}", url.scheme()));
+    }
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    Ok(url)
+}
+
+fn snapshot_dir<R: Runtime>(app: &AppHandle<R>) -> Result<PathBuf, String> {
+    profiles::data_dir(app).map(|dir| dir.join("webdav"))
+}
+
+fn load_snapshot(dir: &Path, hash: &str) -> Option<Snapshot> {
+    let bytes = fs::read(dir.join(format!("{}.json", hash))).ok()?;
+    serde_json::from_slice(&bytes)
+        .map_err(|e| warn!("Ignoring corrupt sync snapshot for {}: {}", hash, e))
+        .ok()
+}
+
+/// Write through a temporary file so a crash never leaves half a snapshot
+fn save_snapshot(dir: &Path, hash: &str, snapshot: &Snapshot) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create snapshot directory: {}", e))?;
+    let json = serde_json::to_vec(snapshot)
+        .map_err(|e| format!("Failed to serialize sync snapshot: {}", e))?;
+
+    let path = dir.join(format!("{}.json", hash));
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, json).map_err(|e| format!("Failed to write sync snapshot: {}", e))?;
+    fs::rename(&temp, &path).map_err(|e| format!("Failed to write sync snapshot: {}", e))
+}
+
+// ============================================================================
+// Server
+// ============================================================================
+
+fn status_error(status: StatusCode) -> String {
+    match status {
+        StatusCode::UNAUTHORIZED => "WebDAV authentication failed".to_string(),
+        StatusCode::FORBIDDEN => "WebDAV access denied".to_string(),
+        StatusCode::INSUFFICIENT_STORAGE => "WebDAV server is out of space".to_string(),
+        status => format!("WebDAV server returned HTTP {}", status),
+    }
+}
+
+fn request_error(e: reqwest::Error) -> String {
+    RequestError::from(e).to_string()
+}
+
+fn response_etag(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+struct Server {
+    client: Client,
+    root: Url,
+    collection: Url,
+    username: String,
+    password: String,
+}
+
+impl Server {
+    fn new(
+        url: &str,
+        username: &str,
+        password: &str,
+        accept_invalid_certs: bool,
+    ) -> Result<Self, String> {
+        let root = parse_url(url)?;
+        let collection = root
+            .join(COLLECTION)
+            .map_err(|e| format!("Invalid WebDAV URL: {}", e))?;
+        let client = crate::net::client_builder()
+            .danger_accept_invalid_certs(accept_invalid_certs)
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+        Ok(Self {
+            client,
+            root,
+            collection,
+            username: username.to_string(),
+            password: password.to_string(),
+        })
+    }
+
+    /// The configured server, with the password from the keychain
+    fn from_settings<R: Runtime>(app: &AppHandle<R>) -> Result<Self, String> {
+        let url = setting(app, URL_SETTING)
+            .and_then(|v| v.as_str().map(str::to_string))
+            .ok_or("WebDAV sync is not configured")?;
+        let username = setting(app, USERNAME_SETTING)
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let password = keychain::get_secret(PASSWORD_KEY)?.unwrap_or_default();
+
+        Self::new(&url, &username, &password, accepts_invalid_certs(app))
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        self.client
+            .request(method, url)
+            .basic_auth(&self.username, Some(&self.password))
+    }
+
+    fn bundle_url(&self, hash: &str) -> Result<Url, String> {
+        self.collection
+            .join(&format!("{}.json", hash))
+            .map_err(|e| format!("Invalid bundle URL: {}", e))
+    }
+
+    /// Check the URL and credentials with a depth-0 PROPFIND
+    async fn check(&self) -> Result<(), String> {
+        let propfind = Method::from_bytes(b"PROPFIND").map_err(|e| e.to_string())?;
+        let response = self
+            .request(propfind, self.root.clone())
+            .header("Depth", "0")
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::NOT_FOUND => Err(format!("WebDAV folder not found: {}", self.root)),
+            status => Err(status_error(status)),
+        }
+    }
+
+    /// Create the `ReadMaster/` collection; 405 means it already exists
+    async fn ensure_collection(&self) -> Result<(), String> {
+        let mkcol = Method::from_bytes(b"MKCOL").map_err(|e| e.to_string())?;
+        let response = self
+            .request(mkcol, self.collection.clone())
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        match response.status() {
+            status if status.is_success() => {
+                info!("Created WebDAV collection {}", self.collection);
+                Ok(())
+            }
+            StatusCode::METHOD_NOT_ALLOWED => Ok(()),
+            status => Err(status_error(status)),
+        }
+    }
+
+    /// A book's bundle and its etag, or `None` if there is none yet
+    async fn get(&self, hash: &str) -> Result<Option<(Bundle, Option<String>)>, String> {
+        let response = self
+            .request(Method::GET, self.bundle_url(hash)?)
+            .send()
+            .await
+            .map_err(request_error)?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let etag = response_etag(&response);
+                let bytes = response.bytes().await.map_err(request_error)?;
+                let bundle = serde_json::from_slice(&bytes)
+                    .map_err(|e| format!("Corrupt remote bundle: {}", e))?;
+                Ok(Some((bundle, etag)))
+            }
+            status => Err(status_error(status)),
+        }
+    }
+
+    async fn put(
+        &self,
+        hash: &str,
+        bundle: &Bundle,
+        precondition: Precondition,
+    ) -> Result<PutResult, String> {
+        let body = serde_json::to_vec_pretty(bundle)
+            .map_err(|e| format!("Failed to serialize bundle: {}", e))?;
+        let url = self.bundle_url(hash)?;
+
+        let mut request = self
+            .request(Method::PUT, url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        request = match &precondition {
+            Precondition::Absent => request.header(IF_NONE_MATCH, "*"),
+            Precondition::Matches(etag) => {
+                let etag =
+                    HeaderValue::from_str(etag).map_err(|e| format!("Invalid etag: {}", e))?;
+                request.header(IF_MATCH, etag)
+            }
+            Precondition::Unconditional => request,
+        };
+
+        let response = request.send().await.map_err(request_error)?;
+        match response.status() {
+            StatusCode::PRECONDITION_FAILED => Ok(PutResult::Conflict),
+            status if status.is_success() => {
+                if let Some(etag) = response_etag(&response) {
+                    return Ok(PutResult::Stored(Some(etag)));
+                }
+                // Not every server returns the new etag; ask for it
+                let head = self
+                    .request(Method::HEAD, url)
+                    .send()
+                    .await
+                    .map_err(request_error)?;
+                Ok(PutResult::Stored(response_etag(&head)))
+            }
+            status => Err(status_error(status)),
+        }
+    }
+}
+
+// ============================================================================
+// Syncing
+// ============================================================================
+
+/// Sync one book, returning what changed. A 412 means fetch, merge, and
+/// try again. Merged data is uploaded before it's applied locally, and the
+/// snapshot is only advanced once both have happened, so a sync that fails
+/// part way leaves the old base in place and the next one merges to the
+/// same result.
+async fn sync_book<R: Runtime>(
+    app: &AppHandle<R>,
+    server: &Server,
+    snapshots: &Path,
+    book: &Book,
+    hash: &str,
+) -> Result<BookSyncStatus, String> {
+    let base = load_snapshot(snapshots, hash);
+
+    for attempt in 0..MAX_ATTEMPTS {
+        let local = super::local_content(app, book)?;
+        let (merged, status, precondition) = match server.get(hash).await? {
+            None => (
+                local.clone(),
+                BookSyncStatus::Uploaded,
+                Precondition::Absent,
+            ),
+            Some((remote, etag)) => {
+                if remote.version > BUNDLE_VERSION {
+                    return Err(format!(
+                        "Remote bundle is version {}, newer than this app supports ({})",
+                        remote.version, BUNDLE_VERSION
+                    ));
+                }
+
+                // Untouched since our last sync: local changes apply as they are
+                let remote_unchanged =
+                    etag.is_some() && base.as_ref().is_some_and(|b| b.etag == etag);
+                let merged = if remote_unchanged {
+                    local.clone()
+                } else {
+                    super::merge(base.as_ref().map(|b| &b.content), &local, &remote.content)
+                };
+
+                let status = match (merged == local, merged == remote.content) {
+                    (true, true) => BookSyncStatus::Unchanged,
+                    (true, false) => BookSyncStatus::Uploaded,
+                    (false, true) => BookSyncStatus::Downloaded,
+                    (false, false) => BookSyncStatus::Merged,
+                };
+                let precondition = match etag {
+                    Some(etag) => Precondition::Matches(etag),
+                    None => Precondition::Unconditional,
+                };
+                (merged, status, precondition)
+            }
+        };
+
+        // The server accepts the merge before anything changes locally
+        let etag = match (status, precondition) {
+            (BookSyncStatus::Uploaded | BookSyncStatus::Merged, precondition) => {
+                let bundle = Bundle::new(book, hash, merged.clone());
+                match server.put(hash, &bundle, precondition).await? {
+                    PutResult::Stored(etag) => etag,
+                    PutResult::Conflict => {
+                        info!(
+                            "Bundle for book {} changed during sync (attempt {})",
+                            book.id,
+                            attempt + 1
+                        );
+                        continue;
+                    }
+                }
+            }
+            (_, Precondition::Matches(etag)) => Some(etag),
+            _ => None,
+        };
+
+        if merged != local {
+            super::apply_content(app, book, &merged)?;
+        }
+        save_snapshot(
+            snapshots,
+            hash,
+            &Snapshot {
+                etag,
+                content: merged,
+            },
+        )?;
+        return Ok(status);
+    }
+
+    Err("The remote bundle kept changing; try again later".to_string())
+}
+
+async fn sync_all<R: Runtime>(app: &AppHandle<R>) -> Result<SyncSummary, String> {
+    let server = Server::from_settings(app)?;
+    server.ensure_collection().await?;
+    let snapshots = snapshot_dir(app)?;
+
+    // Bundles are keyed by content hash; books without one can't be matched
+    let books: Vec<Book> = app
+        .state::<Database>()
+        .with_conn(|conn| library::list_books(conn))?
+        .into_iter()
+        .filter(|book| book.content_hash.is_some())
+        .collect();
+
+    let mut summary = SyncSummary {
+        total: books.len(),
+        ..Default::default()
+    };
+    info!("WebDAV sync of {} books", books.len());
+
+    for book in &books {
+        let hash = book.content_hash.as_deref().unwrap_or_default();
+        let (status, error) = match sync_book(app, &server, &snapshots, book, hash).await {
+            Ok(status) => (status, None),
+            Err(e) => {
+                warn!("WebDAV sync of book {} failed: {}", book.id, e);
+                (BookSyncStatus::Failed, Some(e))
+            }
+        };
+        summary.count(status);
+
+        let _ = app.emit(
+            "webdav-sync-book",
+            BookSyncResult {
+                book_id: book.id,
+                content_hash: hash.to_string(),
+                title: book.title.clone(),
+                status,
+                error,
+            },
+        );
+    }
+
+    info!(
+        "WebDAV sync done: {} uploaded, {} downloaded, {} merged, {} failed",
+        summary.uploaded, summary.downloaded, summary.merged, summary.failed
+    );
+    Ok(summary)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Save the WebDAV server and credentials after checking they work.
+/// Pointing at a different server forgets the merge bases of the old one.
+#[tauri::command]
+pub async fn webdav_configure<R: Runtime>(
+    app: AppHandle<R>,
+    url: String,
+    username: String,
+    password: String,
+) -> Result<(), String> {
+    let root = parse_url(&url)?;
+    let server = Server::new(
+        root.as_str(),
+        &username,
+        &password,
+        accepts_invalid_certs(&app),
+    )?;
+    server.check().await?;
+
+    let previous = setting(&app, URL_SETTING).and_then(|v| v.as_str().map(str::to_string));
+    let previous_user =
+        setting(&app, USERNAME_SETTING).and_then(|v| v.as_str().map(str::to_string));
+    if previous.as_deref() != Some(root.as_str())
+        || previous_user.as_deref() != Some(username.as_str())
+    {
+        let dir = snapshot_dir(&app)?;
+        if dir.exists() {
+            fs::remove_dir_all(&dir)
+                .map_err(|e| format!("Failed to clear sync snapshots: {}", e))?;
+        }
+    }
+
+    keychain::set_secret(PASSWORD_KEY.to_string(), password).await?;
+    let store = app
+        .profile_store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    store.set(URL_SETTING, root.as_str());
+    store.set(USERNAME_SETTING, username);
+    persist::mark_dirty(&app, SETTINGS_STORE);
+
+    info!("WebDAV sync configured for {}", root);
+    Ok(())
+}
+
+/// Trust self-signed and otherwise invalid certificates from the server,
+/// which are rejected by default
+#[tauri::command]
+pub fn webdav_set_accept_invalid_certs<R: Runtime>(
+    app: AppHandle<R>,
+    accept: bool,
+) -> Result<(), String> {
+    if accept {
+        warn!("WebDAV certificate verification disabled");
+    } else {
+        info!("WebDAV certificate verification enabled");
+    }
+
+    let store = app
+        .profile_store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open store: {}", e))?;
+    store.set(ACCEPT_INVALID_CERTS_SETTING, accept);
+    persist::mark_dirty(&app, SETTINGS_STORE);
+    Ok(())
+}
+
+/// Sync every book with the server. Emits `webdav-sync-book` per book and
+/// `webdav-sync-done` with the totals, which are also returned.
+#[tauri::command]
+pub async fn webdav_sync_now<R: Runtime>(app: AppHandle<R>) -> Result<SyncSummary, String> {
+    if SYNC_RUNNING.swap(true, Ordering::SeqCst) {
+        return Err("A WebDAV sync is already running".to_string());
+    }
+
+    let result = sync_all(&app).await;
+    SYNC_RUNNING.store(false, Ordering::SeqCst);
+
+    let summary = result?;
+    let _ = app.emit("webdav-sync-done", &summary);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_server_urls() {
+        let url = parse_url("https://cloud.example.com/remote.php/dav/files/me").unwrap();
+        assert_eq!(
+            url.join(COLLECTION).unwrap().as_str(),
+            "https://cloud.example.com/remote.php/dav/files/me/ReadMaster/"
+        );
+        assert!(parse_url("ftp://example.com/").is_err());
+        assert!(parse_url("not a url").is_err());
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let dir = std::env::temp_dir().join(format!("rm-webdav-{}", uuid::Uuid::new_v4()));
+        let snapshot = Snapshot {
+            etag: Some("\"abc\"".to_string()),
+            content: BundleContent {
+                progress: Some(0.5),
+                ..Default::default()
+            },
+        };
+
+        save_snapshot(&dir, "hash", &snapshot).unwrap();
+        let loaded = load_snapshot(&dir, "hash").unwrap();
+        assert_eq!(loaded.etag, snapshot.etag);
+        assert_eq!(loaded.content, snapshot.content);
+        assert!(load_snapshot(&dir, "other").is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
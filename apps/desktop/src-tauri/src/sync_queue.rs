@@ -0,0 +1,499 @@
+// Read Master Desktop - Sync Queue
+//
+// Offline-first queue for the social/cloud API. Operations are appended to
+// a SQLite table and drained in order by a background worker whenever the
+// network is available, so changes made offline survive restarts.
+//
+// Each operation carries an idempotency key sent with every attempt, so a
+// retry after a lost response can't apply it twice. Failed attempts back off
+// exponentially; a 409 from the server is surfaced as a `sync-conflict`
+// event with both the local and the server version for the UI to resolve.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use reqwest::{Method, StatusCode};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tokio::sync::Notify;
+
+use crate::db::Database;
+use crate::keychain;
+use crate::net::{self, RequestError};
+use crate::power::{self, PowerEvent, PowerState};
+use crate::profiles::ProfileStoreExt;
+
+/// Queue size used when `sync.queueLimit` isn't set
+pub const DEFAULT_QUEUE_LIMIT: usize = 1000;
+
+/// First retry delay; doubles with each failed attempt
+const BASE_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Longest wait between retries
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// Keychain entry holding the API token
+const TOKEN_KEY: &str = "sync.token";
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// A change to push to the server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum SyncOperation {
+    CreateAnnotation { book_id: String, annotation: Value },
+    UpdateProgress { book_id: String, progress: Value },
+    PostReview { book_id: String, review: Value },
+}
+
+impl SyncOperation {
+    /// HTTP method, path relative to the API base, and body
+    fn request(&self) -> (Method, String, &Value) {
+        match self {
+            Self::CreateAnnotation {
+                book_id,
+                annotation,
+            } => (
+                Method::POST,
+                format!("books/{}/annotations", book_id),
+                annotation,
+            ),
+            Self::UpdateProgress { book_id, progress } => {
+                (Method::PUT, format!("books/{}/progress", book_id), progress)
+            }
+            Self::PostReview { book_id, review } => {
+                (Method::POST, format!("books/{}/reviews", book_id), review)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedOperation {
+    pub id: i64,
+    pub idempotency_key: String,
+    pub operation: SyncOperation,
+    pub queued_at: String,
+    pub attempts: u32,
+    pub next_attempt_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueStatus {
+    pub pending: usize,
+    /// Operations that have failed at least once
+    pub retrying: usize,
+    pub limit: usize,
+    pub online: bool,
+    /// The operation at the head of the queue, which blocks the rest
+    pub next: Option<QueuedOperation>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConflictPayload {
+    id: i64,
+    idempotency_key: String,
+    local: SyncOperation,
+    /// The server's current version, as returned with the 409
+    remote: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DroppedPayload {
+    dropped: usize,
+    limit: usize,
+}
+
+/// Wakes the background worker
+pub struct SyncWorker {
+    wake: Arc<Notify>,
+}
+
+// ============================================================================
+// Queue
+// ============================================================================
+
+fn operation_from_row(row: &rusqlite::Row) -> rusqlite::Result<QueuedOperation> {
+    let json: String = row.get(2)?;
+    let operation = serde_json::from_str(&json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(2, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+
+    Ok(QueuedOperation {
+        id: row.get(0)?,
+        idempotency_key: row.get(1)?,
+        operation,
+        queued_at: row.get(3)?,
+        attempts: row.get(4)?,
+        next_attempt_at: row.get(5)?,
+        last_error: row.get(6)?,
+    })
+}
+
+const SELECT_OPERATION: &str = "SELECT id, idempotency_key, operation, queued_at, attempts,
+     next_attempt_at, last_error FROM sync_queue";
+
+/// Append an operation. Re-enqueueing with a key already in the queue
+/// returns the existing entry instead of adding a duplicate.
+pub fn enqueue(
+    conn: &Connection,
+    operation: &SyncOperation,
+    idempotency_key: &str,
+) -> rusqlite::Result<QueuedOperation> {
+    let json = serde_json::to_string(operation)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    conn.execute(
+        "INSERT OR IGNORE INTO sync_queue (idempotency_key, operation, queued_at)
+         VALUES (?1, ?2, ?3)",
+        params![idempotency_key, json, Utc::now().to_rfc3339()],
+    )?;
+
+    conn.query_row(
+        &format!("{} WHERE idempotency_key = ?1", SELECT_OPERATION),
+        [idempotency_key],
+        operation_from_row,
+    )
+}
+
+/// Drop the oldest operations beyond `limit`, returning how many went
+pub fn enforce_limit(conn: &Connection, limit: usize) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM sync_queue WHERE id NOT IN
+             (SELECT id FROM sync_queue ORDER BY id DESC LIMIT ?1)",
+        [limit as i64],
+    )
+}
+
+fn head(conn: &Connection) -> rusqlite::Result<Option<QueuedOperation>> {
+    conn.query_row(
+        &format!("{} ORDER BY id LIMIT 1", SELECT_OPERATION),
+        [],
+        operation_from_row,
+    )
+    .optional()
+}
+
+fn remove(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM sync_queue WHERE id = ?1", [id])?;
+    Ok(())
+}
+
+/// Delay before retrying after `attempts` failures
+pub fn backoff(attempts: u32) -> Duration {
+    BASE_BACKOFF
+        .checked_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .unwrap_or(MAX_BACKOFF)
+        .min(MAX_BACKOFF)
+}
+
+fn record_failure(conn: &Connection, op: &QueuedOperation, error: &str) -> rusqlite::Result<()> {
+    let attempts = op.attempts + 1;
+    let delay = chrono::Duration::from_std(backoff(attempts)).unwrap_or_default();
+
+    conn.execute(
+        "UPDATE sync_queue SET attempts = ?2, next_attempt_at = ?3, last_error = ?4 WHERE id = ?1",
+        params![op.id, attempts, (Utc::now() + delay).to_rfc3339(), error],
+    )?;
+    Ok(())
+}
+
+// ============================================================================
+// Settings
+// ============================================================================
+
+fn setting<R: Runtime>(app: &AppHandle<R>, key: &str) -> Option<Value> {
+    app.profile_store("settings.json")
+        .ok()
+        .and_then(|s| s.get(key))
+}
+
+fn queue_limit<R: Runtime>(app: &AppHandle<R>) -> usize {
+    setting(app, "sync.queueLimit")
+        .and_then(|v| v.as_u64())
+        .map(|n| n.max(1) as usize)
+        .unwrap_or(DEFAULT_QUEUE_LIMIT)
+}
+
+fn api_url<R: Runtime>(app: &AppHandle<R>) -> Option<String> {
+    setting(app, "sync.apiUrl")
+        .and_then(|v| v.as_str().map(str::to_string))
+        .filter(|url| !url.is_empty())
+}
+
+// ============================================================================
+// Worker
+// ============================================================================
+
+/// What the worker should do after a drain pass
+enum Drained {
+    /// Nothing left, offline, or not configured; wait to be woken
+    Idle,
+    /// The head of the queue is backing off for this long
+    Backoff(Duration),
+}
+
+enum Outcome {
+    Done,
+    Conflict(Value),
+    Offline,
+    Retry(String),
+    Rejected(String),
+}
+
+async fn send(base: &str, token: Option<&str>, op: &QueuedOperation) -> Outcome {
+    let (method, path, body) = op.operation.request();
+    let url = format!("{}/{}", base.trim_end_matches('/'), path);
+
+    let mut request = net::client()
+        .request(method, &url)
+        .header("Idempotency-Key", &op.idempotency_key)
+        .json(body);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            return match RequestError::from(e) {
+                RequestError::Offline(_) => Outcome::Offline,
+                error => Outcome::Retry(error.to_string()),
+            }
+        }
+    };
+
+    let status = response.status();
+    match status {
+        s if s.is_success() => Outcome::Done,
+        StatusCode::CONFLICT => Outcome::Conflict(response.json().await.unwrap_or(Value::Null)),
+        // Retried: the server or our credentials may recover
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            Outcome::Retry(format!("HTTP {}", status))
+        }
+        s if s.is_server_error() => Outcome::Retry(format!("HTTP {}", status)),
+        _ => Outcome::Rejected(format!("HTTP {}", status)),
+    }
+}
+
+/// Send queued operations in order until the queue is empty or the head
+/// operation has to wait
+async fn drain<R: Runtime>(app: &AppHandle<R>) -> Drained {
+    let Some(base) = api_url(app) else {
+        return Drained::Idle;
+    };
+    let token = keychain::get_secret(TOKEN_KEY).unwrap_or_else(|e| {
+        warn!("{}", e);
+        None
+    });
+    let db = app.state::<Database>();
+
+    while app.state::<PowerState>().is_online() {
+        let op = match db.with_conn(|conn| head(conn)) {
+            Ok(Some(op)) => op,
+            Ok(None) => return Drained::Idle,
+            Err(e) => {
+                warn!("Failed to read sync queue: {}", e);
+                return Drained::Idle;
+            }
+        };
+
+        // Later operations wait behind the head so they apply in order
+        if let Some(next) = op.next_attempt_at.as_deref() {
+            let wait = DateTime::parse_from_rfc3339(next)
+                .ok()
+                .and_then(|at| (at.with_timezone(&Utc) - Utc::now()).to_std().ok());
+            if let Some(wait) = wait.filter(|w| !w.is_zero()) {
+                return Drained::Backoff(wait);
+            }
+        }
+
+        let result = match send(&base, token.as_deref(), &op).await {
+            Outcome::Done => db.with_conn(|conn| remove(conn, op.id)),
+            Outcome::Conflict(remote) => {
+                warn!("Sync conflict for operation {}", op.id);
+                let _ = app.emit(
+                    "sync-conflict",
+                    ConflictPayload {
+                        id: op.id,
+                        idempotency_key: op.idempotency_key.clone(),
+                        local: op.operation.clone(),
+                        remote,
+                    },
+                );
+                db.with_conn(|conn| remove(conn, op.id))
+            }
+            Outcome::Offline => return Drained::Idle,
+            Outcome::Retry(error) => {
+                warn!("Sync of operation {} failed: {}", op.id, error);
+                db.with_conn(|conn| record_failure(conn, &op, &error))
+            }
+            Outcome::Rejected(error) => {
+                warn!(
+                    "Server rejected operation {}, dropping it: {}",
+                    op.id, error
+                );
+                db.with_conn(|conn| remove(conn, op.id))
+            }
+        };
+
+        if let Err(e) = result {
+            warn!("Failed to update sync queue: {}", e);
+            return Drained::Idle;
+        }
+    }
+
+    Drained::Idle
+}
+
+async fn run<R: Runtime>(app: AppHandle<R>, wake: Arc<Notify>) {
+    loop {
+        match drain(&app).await {
+            Drained::Idle => wake.notified().await,
+            Drained::Backoff(wait) => {
+                let _ = tokio::time::timeout(wait, wake.notified()).await;
+            }
+        }
+    }
+}
+
+/// Start the worker. Must run after `power::init`; the worker drains
+/// whatever was left from the last session and again on `network-online`.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    let wake = Arc::new(Notify::new());
+
+    let on_network = Arc::clone(&wake);
+    power::subscribe(app, move |event| {
+        if event == PowerEvent::NetworkOnline {
+            on_network.notify_one();
+        }
+    });
+
+    app.manage(SyncWorker {
+        wake: Arc::clone(&wake),
+    });
+    tauri::async_runtime::spawn(run(app.clone(), wake));
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Queue an operation for the server. Pass an idempotency key to make
+/// re-submitting the same change safe; one is generated otherwise.
+#[tauri::command]
+pub async fn enqueue_operation<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    worker: State<'_, SyncWorker>,
+    op: SyncOperation,
+    idempotency_key: Option<String>,
+) -> Result<QueuedOperation, String> {
+    let key = idempotency_key.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let limit = queue_limit(&app);
+
+    let (queued, dropped) = db.with_conn(|conn| {
+        let queued = enqueue(conn, &op, &key)?;
+        Ok((queued, enforce_limit(conn, limit)?))
+    })?;
+
+    if dropped > 0 {
+        warn!(
+            "Sync queue over its limit of {}, dropped {} oldest operation(s)",
+            limit, dropped
+        );
+        let _ = app.emit("sync-queue-overflow", DroppedPayload { dropped, limit });
+    }
+
+    info!("Queued sync operation {}", queued.id);
+    worker.wake.notify_one();
+    Ok(queued)
+}
+
+/// Size of the queue and the state of its head operation
+#[tauri::command]
+pub async fn get_queue_status<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    power: State<'_, PowerState>,
+) -> Result<QueueStatus, String> {
+    let (pending, retrying, next) = db.with_conn(|conn| {
+        let (pending, retrying): (i64, i64) = conn.query_row(
+            "SELECT COUNT(*), COUNT(*) FILTER (WHERE attempts > 0) FROM sync_queue",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok((pending, retrying, head(conn)?))
+    })?;
+
+    Ok(QueueStatus {
+        pending: pending as usize,
+        retrying: retrying as usize,
+        limit: queue_limit(&app),
+        online: power.is_online(),
+        next,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(book: &str, percent: u32) -> SyncOperation {
+        SyncOperation::UpdateProgress {
+            book_id: book.to_string(),
+            progress: serde_json::json!({ "percent": percent }),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(1), BASE_BACKOFF);
+        assert_eq!(backoff(2), BASE_BACKOFF * 2);
+        assert_eq!(backoff(4), BASE_BACKOFF * 8);
+        assert_eq!(backoff(40), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn same_key_is_not_queued_twice() {
+        let conn = crate::db::test_connection();
+        let first = enqueue(&conn, &progress("a", 10), "key-1").unwrap();
+        let again = enqueue(&conn, &progress("a", 20), "key-1").unwrap();
+
+        assert_eq!(first.id, again.id);
+        assert_eq!(again.operation, progress("a", 10));
+    }
+
+    #[test]
+    fn limit_drops_oldest() {
+        let conn = crate::db::test_connection();
+        for i in 0..5 {
+            enqueue(&conn, &progress("a", i), &format!("key-{}", i)).unwrap();
+        }
+
+        assert_eq!(enforce_limit(&conn, 3).unwrap(), 2);
+        assert_eq!(head(&conn).unwrap().unwrap().idempotency_key, "key-2");
+    }
+
+    #[test]
+    fn operations_serialize_tagged() {
+        let json = serde_json::to_value(progress("b", 5)).unwrap();
+        assert_eq!(json["type"], "updateProgress");
+        assert_eq!(json["bookId"], "b");
+    }
+}
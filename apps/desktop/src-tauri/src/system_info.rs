@@ -0,0 +1,128 @@
+// Read Master Desktop - System Info
+//
+// Read-only system details for diagnostics and bug reports.
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind, System};
+use tauri::{AppHandle, Runtime};
+
+use crate::profiles;
+
+/// Cap on attached log text, keeping the issue URL within browser limits
+const REPORT_LOG_CHARS: usize = 4000;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemInfo {
+    pub app_version: String,
+    /// e.g. "macOS 14.4 Sonoma" or "Windows 11 Pro"
+    pub os: String,
+    pub os_version: Option<String>,
+    pub kernel_version: Option<String>,
+    pub arch: String,
+    pub logical_cores: usize,
+    pub physical_cores: Option<usize>,
+    pub total_memory_bytes: u64,
+    pub available_memory_bytes: u64,
+    pub webview_version: Option<String>,
+    pub app_data_dir: Option<String>,
+}
+
+// ============================================================================
+// Collection
+// ============================================================================
+
+/// Gather system details. Only memory and the CPU list are refreshed, so
+/// this is cheap enough to call on demand.
+pub fn collect<R: Runtime>(app: &AppHandle<R>) -> SystemInfo {
+    let sys = System::new_with_specifics(
+        RefreshKind::new()
+            .with_memory(MemoryRefreshKind::new().with_ram())
+            .with_cpu(CpuRefreshKind::new()),
+    );
+
+    SystemInfo {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        os: System::long_os_version()
+            .or_else(System::name)
+            .unwrap_or_else(|| std::env::consts::OS.to_string()),
+        os_version: System::os_version(),
+        kernel_version: System::kernel_version(),
+        arch: std::env::consts::ARCH.to_string(),
+        logical_cores: sys.cpus().len(),
+        physical_cores: sys.physical_core_count(),
+        total_memory_bytes: sys.total_memory(),
+        available_memory_bytes: sys.available_memory(),
+        webview_version: tauri::webview_version()
+            .map_err(|e| warn!("Failed to read webview version: {}", e))
+            .ok(),
+        app_data_dir: profiles::data_dir(app)
+            .ok()
+            .map(|dir| dir.to_string_lossy().into_owned()),
+    }
+}
+
+fn gib(bytes: u64) -> String {
+    format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64)
+}
+
+/// Bug report template with the system details filled in
+pub fn issue_body(info: &SystemInfo) -> String {
+    let unknown = || "unknown".to_string();
+    format!(
+        "### What happened?\n\n\n\
+         ### Steps to reproduce\n\n1. \n\n\
+         ### System\n\n\
+         | | |\n|---|---|\n\
+         | App version | {} |\n\
+         | OS | {} ({}) |\n\
+         | Kernel | {} |\n\
+         | CPU cores | {} logical, {} physical |\n\
+         | Memory | {} total, {} available |\n\
+         | Webview | {} |\n",
+        info.app_version,
+        info.os,
+        info.arch,
+        info.kernel_version.clone().unwrap_or_else(unknown),
+        info.logical_cores,
+        info.physical_cores
+            .map(|n| n.to_string())
+            .unwrap_or_else(unknown),
+        gib(info.total_memory_bytes),
+        gib(info.available_memory_bytes),
+        info.webview_version.clone().unwrap_or_else(unknown),
+    )
+}
+
+/// Collapsed section with the tail of the log, trimmed to `REPORT_LOG_CHARS`
+pub fn logs_section(logs: &str) -> String {
+    let start = logs
+        .char_indices()
+        .rev()
+        .nth(REPORT_LOG_CHARS)
+        .map_or(0, |(i, _)| i);
+    // Start on a whole line
+    let tail = match logs[start..].find('\n') {
+        Some(newline) if start > 0 => &logs[start + newline + 1..],
+        _ => &logs[start..],
+    };
+    format!(
+        "\n<details><summary>Recent logs</summary>\n\n```\n{}\n```\n</details>\n",
+        tail
+    )
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// OS, hardware, webview, and path details for diagnostics
+#[tauri::command]
+pub async fn get_system_info<R: Runtime>(app: AppHandle<R>) -> Result<SystemInfo, String> {
+    Ok(collect(&app))
+}
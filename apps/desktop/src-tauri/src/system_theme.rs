@@ -0,0 +1,417 @@
+// Read Master Desktop - System Theme
+//
+// Reads the OS accent color so selections and highlights in the reader can
+// match the rest of the desktop, and emits `system-theme-changed` when the
+// user picks a different one.
+//
+// The accent comes from UISettings on Windows, NSColor.controlAccentColor
+// on macOS, and on Linux from the GNOME accent setting, KDE's kdeglobals,
+// or failing those the selection color of the GTK theme. None of these
+// are watched directly: a monitor thread polls, and also checks right away
+// when a window reports a light/dark change, since themes often switch
+// accents along with it.
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, Runtime, State, Window, WindowEvent};
+
+const EVENT: &str = "system-theme-changed";
+
+/// How often the accent color is checked
+const TICK: Duration = Duration::from_secs(5);
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// An sRGB color, 0 to 255 per channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rgb(u8, u8, u8);
+
+impl Rgb {
+    fn hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ThemeChangedPayload {
+    accent_color: String,
+}
+
+/// Last accent color seen, and a way to make the monitor look again
+pub struct SystemTheme {
+    accent: Mutex<Option<String>>,
+    wake: Mutex<Sender<()>>,
+}
+
+// ============================================================================
+// Windows
+// ============================================================================
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::Win32::System::Com::{CoInitializeEx, COINIT_MULTITHREADED};
+    use windows::UI::ViewManagement::{UIColorType, UISettings};
+
+    use super::Rgb;
+
+    pub fn accent_color() -> Option<Rgb> {
+        let color = UISettings::new()
+            .ok()?
+            .GetColorValue(UIColorType::Accent)
+            .ok()?;
+        Some(Rgb(color.R, color.G, color.B))
+    }
+
+    /// COM stays initialized for the monitor thread's lifetime
+    pub fn init_thread() {
+        let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+    }
+}
+
+// ============================================================================
+// macOS
+// ============================================================================
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use cocoa::base::{id, nil};
+    use objc::rc::autoreleasepool;
+    use objc::runtime::{BOOL, NO};
+    use objc::{class, msg_send, sel, sel_impl};
+
+    use super::Rgb;
+
+    /// `controlAccentColor` (macOS 10.14 and later) converted to sRGB
+    pub fn accent_color() -> Option<Rgb> {
+        autoreleasepool(|| unsafe {
+            let ns_color = class!(NSColor);
+            let supported: BOOL = msg_send![ns_color, respondsToSelector: sel!(controlAccentColor)];
+            if supported == NO {
+                return None;
+            }
+            let accent: id = msg_send![ns_color, controlAccentColor];
+            let srgb: id = msg_send![class!(NSColorSpace), sRGBColorSpace];
+            let color: id = msg_send![accent, colorUsingColorSpace: srgb];
+            if color == nil {
+                return None;
+            }
+            let (mut r, mut g, mut b, mut a) = (0.0f64, 0.0f64, 0.0f64, 0.0f64);
+            let _: () = msg_send![
+                color,
+                getRed: &mut r as *mut f64
+                green: &mut g as *mut f64
+                blue: &mut b as *mut f64
+                alpha: &mut a as *mut f64
+            ];
+            let channel = |v: f64| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+            Some(Rgb(channel(r), channel(g), channel(b)))
+        })
+    }
+
+    pub fn init_thread() {}
+}
+
+// ============================================================================
+// Linux
+// ============================================================================
+
+/// libadwaita's colors for GNOME's named accents
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+const GNOME_ACCENTS: &[(&str, Rgb)] = &[
+    ("blue", Rgb(0x35, 0x84, 0xe4)),
+    ("teal", Rgb(0x21, 0x90, 0xa4)),
+    ("green", Rgb(0x3a, 0x94, 0x4a)),
+    ("yellow", Rgb(0xc8, 0x88, 0x00)),
+    ("orange", Rgb(0xed, 0x5b, 0x00)),
+    ("red", Rgb(0xe6, 0x2d, 0x42)),
+    ("pink", Rgb(0xd5, 0x61, 0x99)),
+    ("purple", Rgb(0x91, 0x41, 0xac)),
+    ("slate", Rgb(0x6f, 0x83, 0x96)),
+];
+
+/// `#rgb` or `#rrggbb`
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn parse_hex(text: &str) -> Option<Rgb> {
+    let digits = text.trim().strip_prefix('#')?;
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match digits.len() {
+        3 => {
+            let mut c = digits.chars().map(|c| c.to_digit(16).map(|d| d as u8 * 17));
+            Some(Rgb(c.next()??, c.next()??, c.next()??))
+        }
+        6 => Some(Rgb(
+            channel(digits.get(0..2)?)?,
+            channel(digits.get(2..4)?)?,
+            channel(digits.get(4..6)?)?,
+        )),
+        _ => None,
+    }
+}
+
+/// The output of `gsettings get org.gnome.desktop.interface accent-color`,
+/// e.g. `'teal'`
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn parse_gnome_accent(value: &str) -> Option<Rgb> {
+    let name = value.trim().trim_matches('\'');
+    GNOME_ACCENTS
+        .iter()
+        .find(|(accent, _)| *accent == name)
+        .map(|(_, rgb)| *rgb)
+}
+
+/// `AccentColor` from kdeglobals' `[General]` group, else the selection
+/// background of its color scheme. Both are `r,g,b`.
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn parse_kdeglobals(text: &str) -> Option<Rgb> {
+    let mut group = "";
+    let mut accent = None;
+    let mut selection = None;
+    for line in text.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            group = name;
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match (group, key.trim()) {
+            ("General", "AccentColor") => accent = parse_kde_rgb(value),
+            ("Colors:Selection", "BackgroundNormal") => selection = parse_kde_rgb(value),
+            _ => {}
+        }
+    }
+    accent.or(selection)
+}
+
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn parse_kde_rgb(value: &str) -> Option<Rgb> {
+    let channels: Vec<u8> = value
+        .split(',')
+        .map(|c| c.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    match channels[..] {
+        [r, g, b] | [r, g, b, _] => Some(Rgb(r, g, b)),
+        _ => None,
+    }
+}
+
+/// The selection color a GTK theme's stylesheet defines
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn parse_gtk_css(css: &str) -> Option<Rgb> {
+    [
+        "theme_selected_bg_color",
+        "selected_bg_color",
+        "accent_bg_color",
+    ]
+    .iter()
+    .find_map(|name| {
+        css.lines().find_map(|line| {
+            let rest = line.trim().strip_prefix("@define-color")?.trim_start();
+            let value = rest.strip_prefix(name)?;
+            if !value.starts_with(char::is_whitespace) {
+                return None;
+            }
+            parse_hex(value.trim().trim_end_matches(';'))
+        })
+    })
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+mod platform {
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    use super::{parse_gnome_accent, parse_gtk_css, parse_kdeglobals, Rgb};
+
+    fn gsettings(key: &str) -> Option<String> {
+        let output = Command::new("gsettings")
+            .args(["get", "org.gnome.desktop.interface", key])
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn config_dir() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    }
+
+    fn kde_accent() -> Option<Rgb> {
+        let text = std::fs::read_to_string(config_dir()?.join("kdeglobals")).ok()?;
+        parse_kdeglobals(&text)
+    }
+
+    /// Selection color of the GTK theme, from the user's themes folder or
+    /// the system's
+    fn gtk_theme_accent() -> Option<Rgb> {
+        let theme = gsettings("gtk-theme")?;
+        let theme = theme.trim_matches('\'');
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let roots = [
+            home.as_ref().map(|h| h.join(".themes")),
+            home.as_ref().map(|h| h.join(".local/share/themes")),
+            Some(PathBuf::from("/usr/share/themes")),
+        ];
+        roots.iter().flatten().find_map(|root| {
+            ["gtk-4.0/gtk.css", "gtk-3.0/gtk.css"]
+                .iter()
+                .find_map(|file| std::fs::read_to_string(root.join(theme).join(file)).ok())
+                .and_then(|css| parse_gtk_css(&css))
+        })
+    }
+
+    pub fn accent_color() -> Option<Rgb> {
+        gsettings("accent-color")
+            .and_then(|value| parse_gnome_accent(&value))
+            .or_else(kde_accent)
+            .or_else(gtk_theme_accent)
+    }
+
+    pub fn init_thread() {}
+}
+
+// ============================================================================
+// Monitor
+// ============================================================================
+
+/// Read the accent color, and tell the frontend if it changed
+fn check<R: Runtime>(app: &AppHandle<R>) {
+    let Some(accent) = platform::accent_color().map(Rgb::hex) else {
+        return;
+    };
+    let changed = app
+        .state::<SystemTheme>()
+        .accent
+        .lock()
+        .map(|mut last| {
+            last.replace(accent.clone())
+                .is_some_and(|old| old != accent)
+        })
+        .unwrap_or(false);
+    if !changed {
+        return;
+    }
+
+    info!("System accent color changed to {}", accent);
+    let payload = ThemeChangedPayload {
+        accent_color: accent,
+    };
+    if let Err(e) = app.emit(EVENT, payload) {
+        warn!("Failed to emit {}: {}", EVENT, e);
+    }
+}
+
+/// Register managed state and start the monitor thread
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    let (wake, woken) = mpsc::channel();
+    app.manage(SystemTheme {
+        accent: Mutex::new(None),
+        wake: Mutex::new(wake),
+    });
+
+    let app = app.clone();
+    let spawned = thread::Builder::new()
+        .name("theme-monitor".into())
+        .spawn(move || {
+            platform::init_thread();
+            loop {
+                check(&app);
+                match woken.recv_timeout(TICK) {
+                    Ok(()) | Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start theme monitor: {}", e);
+    }
+}
+
+/// Check the accent as soon as a window sees the system theme change
+pub fn handle_window_event<R: Runtime>(window: &Window<R>, event: &WindowEvent) {
+    if let WindowEvent::ThemeChanged(_) = event {
+        if let Some(theme) = window.try_state::<SystemTheme>() {
+            if let Ok(wake) = theme.wake.lock() {
+                let _ = wake.send(());
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// The OS accent color as `#rrggbb`
+#[tauri::command]
+pub async fn get_system_accent_color(state: State<'_, SystemTheme>) -> Result<String, String> {
+    if let Some(accent) = state.accent.lock().ok().and_then(|last| last.clone()) {
+        return Ok(accent);
+    }
+    let accent = tauri::async_runtime::spawn_blocking(|| {
+        platform::init_thread();
+        platform::accent_color()
+    })
+    .await
+    .map_err(|e| format!("Accent color task failed: {}", e))?
+    .map(Rgb::hex)
+    .ok_or_else(|| "The system doesn't report an accent color".to_string())?;
+
+    if let Ok(mut last) = state.accent.lock() {
+        last.get_or_insert_with(|| accent.clone());
+    }
+    Ok(accent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_hex() {
+        assert_eq!(Rgb(0x35, 0x84, 0xe4).hex(), "#3584e4");
+        assert_eq!(Rgb(0, 0, 0).hex(), "#000000");
+    }
+
+    #[test]
+    fn parses_gnome_accents() {
+        assert_eq!(parse_gnome_accent("'teal'\n"), Some(Rgb(0x21, 0x90, 0xa4)));
+        assert_eq!(parse_gnome_accent("'magenta'"), None);
+    }
+
+    #[test]
+    fn prefers_kde_accent_over_selection() {
+        let globals = "[Colors:Selection]\nBackgroundNormal=61,174,233\n\n\
+                       [General]\nAccentColor=233,100,62\n";
+        assert_eq!(parse_kdeglobals(globals), Some(Rgb(233, 100, 62)));
+
+        let globals = "[Colors:Selection]\nBackgroundNormal=61,174,233\n";
+        assert_eq!(parse_kdeglobals(globals), Some(Rgb(61, 174, 233)));
+        assert_eq!(parse_kdeglobals("[General]\nAccentColor=bad\n"), None);
+    }
+
+    #[test]
+    fn reads_gtk_selection_color() {
+        let css = "@define-color theme_fg_color #eeeeec;\n\
+                   @define-color theme_selected_bg_color_backdrop #000;\n\
+                   @define-color theme_selected_bg_color #15539e;\n";
+        assert_eq!(parse_gtk_css(css), Some(Rgb(0x15, 0x53, 0x9e)));
+        assert_eq!(
+            parse_gtk_css("@define-color selected_bg_color #f0a;"),
+            Some(Rgb(0xff, 0x00, 0xaa))
+        );
+        assert_eq!(parse_gtk_css("* { color: red; }"), None);
+    }
+}
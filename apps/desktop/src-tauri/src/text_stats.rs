@@ -0,0 +1,275 @@
+// Read Master Desktop - Text Statistics
+//
+// Length and difficulty of a book's text. Counts are cached per book and
+// recomputed only when the file's content hash changes; reading time is
+// derived on every call from the user's current reading speed.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use log::{info, warn};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::db::Database;
+use crate::epub::EpubArchive;
+use crate::import;
+use crate::library;
+use crate::sessions;
+
+/// Characters sampled for language detection
+const LANGUAGE_SAMPLE_CHARS: usize = 20_000;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Counts that depend only on the text, cached in the database
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextCounts {
+    word_count: u64,
+    unique_word_count: u64,
+    sentence_count: u64,
+    syllable_count: u64,
+    /// Words of three or more syllables (for Gunning-Fog)
+    complex_word_count: u64,
+    /// ISO 639-3 code from language detection
+    language: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextStats {
+    pub word_count: u64,
+    pub unique_word_count: u64,
+    pub sentence_count: u64,
+    pub average_sentence_length: f64,
+    /// US grade level; only computed for English text
+    pub flesch_kincaid_grade: Option<f64>,
+    /// Years of education; only computed for English text
+    pub gunning_fog: Option<f64>,
+    pub language: Option<String>,
+    pub words_per_minute: f64,
+    pub reading_time_minutes: f64,
+    /// Served from the cache rather than recomputed
+    pub cached: bool,
+}
+
+// ============================================================================
+// Analysis
+// ============================================================================
+
+/// Vowel-group syllable estimate for English words
+fn syllables(word: &str) -> u64 {
+    let word = word.to_lowercase();
+    let chars: Vec<char> = word.chars().filter(|c| c.is_alphabetic()).collect();
+    if chars.len() <= 3 {
+        return 1;
+    }
+
+    let is_vowel = |c: char| matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y');
+    let mut count = 0;
+    let mut prev_vowel = false;
+    for &c in &chars {
+        let vowel = is_vowel(c);
+        if vowel && !prev_vowel {
+            count += 1;
+        }
+        prev_vowel = vowel;
+    }
+
+    // Silent trailing "e" (but not "-le" as in "table")
+    let n = chars.len();
+    if chars[n - 1] == 'e' && !(chars[n - 2] == 'l' && !is_vowel(chars[n - 3])) {
+        count -= 1;
+    }
+
+    count.max(1)
+}
+
+fn is_sentence_end(word: &str) -> bool {
+    word.trim_end_matches(['"', '\'', ')', ']', '”', '’', '»'])
+        .ends_with(['.', '!', '?', '…', '。', '！', '？'])
+}
+
+fn count_text<'a>(chapters: impl Iterator<Item = &'a str>) -> TextCounts {
+    let mut counts = TextCounts::default();
+    let mut unique = HashSet::new();
+    let mut sample = String::new();
+
+    for text in chapters {
+        if sample.len() < LANGUAGE_SAMPLE_CHARS {
+            let wanted = (LANGUAGE_SAMPLE_CHARS - sample.len()).min(text.len());
+            let end = (0..=wanted)
+                .rev()
+                .find(|&i| text.is_char_boundary(i))
+                .unwrap_or(0);
+            sample.push_str(&text[..end]);
+            sample.push('\n');
+        }
+
+        for line in text.lines() {
+            let mut words_in_sentence = 0;
+            for token in line.split_whitespace() {
+                let word = token.trim_matches(|c: char| !c.is_alphanumeric());
+                if word.is_empty() {
+                    continue;
+                }
+
+                counts.word_count += 1;
+                words_in_sentence += 1;
+                unique.insert(word.to_lowercase());
+
+                let syl = syllables(word);
+                counts.syllable_count += syl;
+                if syl >= 3 {
+                    counts.complex_word_count += 1;
+                }
+
+                if is_sentence_end(token) {
+                    counts.sentence_count += 1;
+                    words_in_sentence = 0;
+                }
+            }
+            // Headings and list items end without punctuation
+            if words_in_sentence > 0 {
+                counts.sentence_count += 1;
+            }
+        }
+    }
+
+    counts.unique_word_count = unique.len() as u64;
+    counts.language = whatlang::detect(&sample)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang().code().to_string());
+    counts
+}
+
+fn build_stats(counts: &TextCounts, wpm: f64, cached: bool) -> TextStats {
+    let words = counts.word_count as f64;
+    let sentences = counts.sentence_count.max(1) as f64;
+    let average_sentence_length = if counts.word_count > 0 {
+        words / sentences
+    } else {
+        0.0
+    };
+
+    // Syllable-based formulas are only calibrated for English
+    let english = counts.language.as_deref() == Some("eng") && counts.word_count > 0;
+    let round = |v: f64| (v * 10.0).round() / 10.0;
+
+    TextStats {
+        word_count: counts.word_count,
+        unique_word_count: counts.unique_word_count,
+        sentence_count: counts.sentence_count,
+        average_sentence_length: round(average_sentence_length),
+        flesch_kincaid_grade: english.then(|| {
+            round(
+                0.39 * average_sentence_length + 11.8 * (counts.syllable_count as f64 / words)
+                    - 15.59,
+            )
+        }),
+        gunning_fog: english.then(|| {
+            round(
+                0.4 * (average_sentence_length + 100.0 * counts.complex_word_count as f64 / words),
+            )
+        }),
+        language: counts.language.clone(),
+        words_per_minute: round(wpm),
+        reading_time_minutes: round(words / wpm),
+        cached,
+    }
+}
+
+// ============================================================================
+// Cache
+// ============================================================================
+
+fn cached_counts(
+    conn: &Connection,
+    book_id: i64,
+    hash: &str,
+) -> rusqlite::Result<Option<TextCounts>> {
+    let json: Option<String> = conn
+        .query_row(
+            "SELECT stats FROM text_stats WHERE book_id = ?1 AND content_hash = ?2",
+            params![book_id, hash],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(json.and_then(|json| serde_json::from_str(&json).ok()))
+}
+
+fn store_counts(
+    conn: &Connection,
+    book_id: i64,
+    hash: &str,
+    counts: &TextCounts,
+) -> rusqlite::Result<()> {
+    let json = serde_json::to_string(counts)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+    conn.execute(
+        "INSERT INTO text_stats (book_id, content_hash, stats, computed_at)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (book_id) DO UPDATE
+         SET content_hash = excluded.content_hash, stats = excluded.stats,
+             computed_at = excluded.computed_at",
+        params![book_id, hash, json, chrono::Utc::now().to_rfc3339()],
+    )?;
+    conn.execute(
+        "UPDATE books SET word_count = ?2 WHERE id = ?1",
+        params![book_id, counts.word_count as i64],
+    )?;
+    Ok(())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Word counts, readability, and estimated reading time for a book
+#[tauri::command]
+pub async fn analyze_book_text<R: Runtime>(
+    app: AppHandle<R>,
+    book_id: i64,
+) -> Result<TextStats, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let db = app.state::<Database>();
+
+        let book = db
+            .with_conn(|conn| library::get_book(conn, book_id))?
+            .ok_or_else(|| format!("Book not found: {}", book_id))?;
+        let path = book
+            .path
+            .ok_or_else(|| format!("Book {} has no file", book_id))?;
+        if book.format.as_deref() != Some("epub") {
+            return Err("Text statistics are only available for EPUB books".to_string());
+        }
+
+        // The stored hash may be stale if the file was replaced on disk
+        let hash = import::hash_path(Path::new(&path))?;
+        if book.content_hash.as_deref() != Some(hash.as_str()) {
+            warn!("Content hash of book {} changed; recomputing", book_id);
+        }
+
+        let wpm = db.with_conn(|conn| sessions::words_per_minute(conn))?;
+        if let Some(counts) = db.with_conn(|conn| cached_counts(conn, book_id, &hash))? {
+            return Ok(build_stats(&counts, wpm, true));
+        }
+
+        info!("Analyzing text of book {}", book_id);
+        let chapters = EpubArchive::open(&path)
+            .map_err(|e| e.to_string())?
+            .chapter_texts();
+        let counts = count_text(chapters.iter().map(|c| c.text.as_str()));
+
+        db.with_conn(|conn| store_counts(conn, book_id, &hash, &counts))?;
+        Ok(build_stats(&counts, wpm, false))
+    })
+    .await
+    .map_err(|e| format!("Analysis task failed: {}", e))?
+}
@@ -0,0 +1,377 @@
+// Read Master Desktop - Translation
+//
+// Text translation through pluggable providers with an LRU cache. Single
+// words fall back to bilingual dictionaries when every online provider fails.
+
+mod providers;
+
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use log::{info, warn};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime, State};
+
+pub use providers::{DeepL, DictionaryWord, HttpEndpoint, LibreTranslate, OfflineModel};
+
+use crate::data_location;
+use crate::dictionary::DictionaryCache;
+use crate::profiles::ProfileStoreExt;
+
+/// Number of cached translations kept in memory
+const CACHE_CAPACITY: usize = 500;
+
+/// Default provider order when none is configured
+const DEFAULT_PROVIDERS: &[&str] = &["deepl", "libretranslate", "http", "offline"];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Translation {
+    pub text: String,
+    /// Source language as reported by the provider, or the requested one
+    pub detected_source_lang: Option<String>,
+    pub target_lang: String,
+    pub provider: String,
+    pub cached: bool,
+}
+
+/// Provider errors, tagged so the UI can tell configuration problems
+/// apart from connectivity problems
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TranslateError {
+    /// No API key configured for the provider
+    MissingApiKey { provider: String },
+    /// The provider rejected the API key
+    InvalidApiKey { provider: String },
+    /// Too many requests; retry later
+    RateLimited {
+        provider: String,
+        retry_after_secs: Option<u64>,
+    },
+    /// Account character quota used up
+    QuotaExceeded { provider: String },
+    /// Could not reach the provider
+    Network { provider: String, message: String },
+    /// The provider can't translate this language pair
+    UnsupportedLanguage { provider: String, message: String },
+    /// The provider is not installed or configured (e.g. no offline model)
+    Unavailable { provider: String, message: String },
+    /// Any other provider failure
+    Provider { provider: String, message: String },
+    /// Only whitespace was given
+    EmptyText,
+    /// No providers configured at all
+    NoProviders,
+}
+
+impl std::fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingApiKey { provider } => write!(f, "{}: no API key configured", provider),
+            Self::InvalidApiKey { provider } => write!(f, "{}: invalid API key", provider),
+            Self::RateLimited { provider, .. } => write!(f, "{}: rate limited", provider),
+            Self::QuotaExceeded { provider } => write!(f, "{}: quota exceeded", provider),
+            Self::Network { provider, message }
+            | Self::UnsupportedLanguage { provider, message }
+            | Self::Unavailable { provider, message }
+            | Self::Provider { provider, message } => write!(f, "{}: {}", provider, message),
+            Self::EmptyText => write!(f, "Nothing to translate"),
+            Self::NoProviders => write!(f, "No translation providers configured"),
+        }
+    }
+}
+
+impl TranslateError {
+    /// Errors where trying the next provider makes sense: everything but
+    /// what every provider would hit. A language pair one provider can't do
+    /// may still be covered by an offline model or a dictionary.
+    fn should_fall_back(&self) -> bool {
+        !matches!(self, Self::EmptyText | Self::NoProviders)
+    }
+}
+
+/// A translation backend
+#[async_trait::async_trait]
+pub trait TranslationProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Translate `text`. A `None` source asks the provider to detect it.
+    async fn translate(
+        &self,
+        text: &str,
+        source_lang: Option<&str>,
+        target_lang: &str,
+    ) -> Result<Translation, TranslateError>;
+}
+
+#[derive(Hash, PartialEq, Eq)]
+struct CacheKey {
+    text: String,
+    source_lang: Option<String>,
+    target_lang: String,
+}
+
+/// Recently translated text, shared across windows
+pub struct TranslationCache(Mutex<LruCache<CacheKey, Translation>>);
+
+impl Default for TranslationCache {
+    fn default() -> Self {
+        Self(Mutex::new(LruCache::new(
+            NonZeroUsize::new(CACHE_CAPACITY).expect("cache capacity is non-zero"),
+        )))
+    }
+}
+
+// ============================================================================
+// Provider Configuration
+// ============================================================================
+
+/// Build the configured providers, in priority order. Order and endpoints
+/// come from the `translation.providers` / `translation.libretranslateUrl`
+/// settings; API keys come from the keychain. The dictionary fallback is
+/// always tried last unless the order places it elsewhere.
+fn configured_providers<R: Runtime>(app: &AppHandle<R>) -> Vec<Box<dyn TranslationProvider>> {
+    let store = app.profile_store("settings.json").ok();
+    let setting = |key: &str| store.as_ref().and_then(|s| s.get(key));
+
+    let mut order: Vec<String> = setting("translation.providers")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_else(|| DEFAULT_PROVIDERS.iter().map(|s| s.to_string()).collect());
+    if !order.iter().any(|name| name == "dictionary") {
+        order.push("dictionary".to_string());
+    }
+
+    let dictionaries: HashMap<String, PathBuf> = setting("translation.dictionaries")
+        .and_then(|v| serde_json::from_value::<HashMap<String, String>>(v).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(pair, path)| (pair.to_lowercase(), PathBuf::from(path)))
+        .collect();
+
+    let libretranslate_url =
+        setting("translation.libretranslateUrl").and_then(|v| v.as_str().map(str::to_string));
+
+    let models_dir = data_location::base_dir(app)
+        .ok()
+        .map(|dir| dir.join("translation-models"));
+
+    order
+        .iter()
+        .filter_map(|name| -> Option<Box<dyn TranslationProvider>> {
+            match name.as_str() {
+                "deepl" => Some(Box::new(DeepL::new())),
+                "http" => Some(Box::new(HttpEndpoint::new())),
+                "dictionary" => Some(Box::new(DictionaryWord::new(
+                    app.state::<DictionaryCache>().inner().clone(),
+                    dictionaries.clone(),
+                ))),
+                "libretranslate" => libretranslate_url
+                    .clone()
+                    .map(|url| Box::new(LibreTranslate::new(url)) as Box<dyn TranslationProvider>),
+                "offline" => models_dir
+                    .clone()
+                    .map(|dir| Box::new(OfflineModel::new(dir)) as Box<dyn TranslationProvider>),
+                other => {
+                    warn!("Unknown translation provider: {}", other);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Try each provider in order, falling back on recoverable errors. If all
+/// fail, the first provider's error is returned since it reflects the
+/// user's preferred setup.
+pub async fn translate_with(
+    providers: &[Box<dyn TranslationProvider>],
+    text: &str,
+    source_lang: Option<&str>,
+    target_lang: &str,
+) -> Result<Translation, TranslateError> {
+    if text.trim().is_empty() {
+        return Err(TranslateError::EmptyText);
+    }
+
+    let mut first_error = None;
+
+    for provider in providers {
+        match provider.translate(text, source_lang, target_lang).await {
+            Ok(translation) => return Ok(translation),
+            Err(e) => {
+                warn!("Translation via {} failed: {}", provider.name(), e);
+                let fall_back = e.should_fall_back();
+                first_error.get_or_insert(e);
+                if !fall_back {
+                    break;
+                }
+            }
+        }
+    }
+
+    Err(first_error.unwrap_or(TranslateError::NoProviders))
+}
+
+/// Translate through the provider chain, serving repeated requests from
+/// the cache
+async fn translate_cached<R: Runtime>(
+    app: &AppHandle<R>,
+    cache: &TranslationCache,
+    text: String,
+    source_lang: Option<String>,
+    target_lang: String,
+) -> Result<Translation, TranslateError> {
+    let text = text.trim().to_string();
+    let source_lang = source_lang.filter(|l| !l.is_empty() && l != "auto");
+
+    let key = CacheKey {
+        text: text.clone(),
+        source_lang: source_lang.clone(),
+        target_lang: target_lang.clone(),
+    };
+
+    if let Some(hit) = cache.0.lock().ok().and_then(|mut c| c.get(&key).cloned()) {
+        return Ok(Translation {
+            cached: true,
+            ..hit
+        });
+    }
+
+    info!(
+        "Translating {} chars {:?} -> {}",
+        text.len(),
+        source_lang,
+        target_lang
+    );
+
+    let providers = configured_providers(app);
+    let translation =
+        translate_with(&providers, &text, source_lang.as_deref(), &target_lang).await?;
+
+    if let Ok(mut c) = cache.0.lock() {
+        c.put(key, translation.clone());
+    }
+
+    Ok(translation)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Translate text, serving repeated requests from the cache
+#[tauri::command]
+pub async fn translate<R: Runtime>(
+    app: AppHandle<R>,
+    cache: State<'_, TranslationCache>,
+    text: String,
+    source_lang: Option<String>,
+    target_lang: String,
+) -> Result<Translation, TranslateError> {
+    translate_cached(&app, &cache, text, source_lang, target_lang).await
+}
+
+/// Translate a selection, returning just the translated text. `from` may
+/// be "auto" to let the provider detect it.
+#[tauri::command]
+pub async fn translate_text<R: Runtime>(
+    app: AppHandle<R>,
+    cache: State<'_, TranslationCache>,
+    text: String,
+    from: String,
+    to: String,
+) -> Result<String, String> {
+    translate_cached(&app, &cache, text, Some(from), to)
+        .await
+        .map(|translation| translation.text)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fails with the given error, or echoes the text upper-cased
+    struct Fake(Option<TranslateError>);
+
+    #[async_trait::async_trait]
+    impl TranslationProvider for Fake {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        async fn translate(
+            &self,
+            text: &str,
+            source_lang: Option<&str>,
+            target_lang: &str,
+        ) -> Result<Translation, TranslateError> {
+            match &self.0 {
+                Some(e) => Err(e.clone()),
+                None => Ok(Translation {
+                    text: text.to_uppercase(),
+                    detected_source_lang: source_lang.map(str::to_string),
+                    target_lang: target_lang.to_string(),
+                    provider: self.name().to_string(),
+                    cached: false,
+                }),
+            }
+        }
+    }
+
+    fn unsupported() -> TranslateError {
+        TranslateError::UnsupportedLanguage {
+            provider: "deepl".to_string(),
+            message: "Value for 'target_lang' not supported.".to_string(),
+        }
+    }
+
+    fn run(
+        providers: Vec<Box<dyn TranslationProvider>>,
+        text: &str,
+    ) -> Result<Translation, TranslateError> {
+        tauri::async_runtime::block_on(translate_with(&providers, text, Some("fi"), "cy"))
+    }
+
+    #[test]
+    fn unsupported_languages_fall_back_to_the_next_provider() {
+        let providers: Vec<Box<dyn TranslationProvider>> =
+            vec![Box::new(Fake(Some(unsupported()))), Box::new(Fake(None))];
+        let translation = run(providers, "kirja").unwrap();
+        assert_eq!(translation.text, "KIRJA");
+    }
+
+    #[test]
+    fn the_first_error_is_kept_when_every_provider_fails() {
+        let providers: Vec<Box<dyn TranslationProvider>> = vec![
+            Box::new(Fake(Some(unsupported()))),
+            Box::new(Fake(Some(TranslateError::Unavailable {
+                provider: "offline".to_string(),
+                message: "No fi-cy model installed".to_string(),
+            }))),
+        ];
+        let err = run(providers, "kirja").unwrap_err();
+        assert!(matches!(err, TranslateError::UnsupportedLanguage { .. }));
+    }
+
+    #[test]
+    fn empty_text_stops_before_any_provider() {
+        let providers: Vec<Box<dyn TranslationProvider>> = vec![Box::new(Fake(None))];
+        assert!(matches!(
+            run(providers, "  \n"),
+            Err(TranslateError::EmptyText)
+        ));
+        assert!(matches!(
+            run(Vec::new(), "kirja"),
+            Err(TranslateError::NoProviders)
+        ));
+    }
+}
@@ -0,0 +1,626 @@
+// Read Master Desktop - Translation Providers
+//
+// DeepL, LibreTranslate, and custom HTTP backends, an offline Bergamot
+// model, and a word-level fallback using bilingual dictionaries.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use reqwest::{Client, Response, StatusCode};
+use serde::Deserialize;
+
+use super::{TranslateError, Translation, TranslationProvider};
+use crate::dictionary::{self, DictionaryCache};
+use crate::keychain;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(15);
+
+fn http_client() -> Client {
+    Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+}
+
+fn network_error(provider: &str, e: reqwest::Error) -> TranslateError {
+    TranslateError::Network {
+        provider: provider.to_string(),
+        message: e.to_string(),
+    }
+}
+
+fn retry_after(response: &Response) -> Option<u64> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+fn api_key(provider: &str, secret: &str) -> Result<String, TranslateError> {
+    match keychain::get_secret(secret) {
+        Ok(Some(key)) => Ok(key),
+        Ok(None) => Err(TranslateError::MissingApiKey {
+            provider: provider.to_string(),
+        }),
+        Err(message) => Err(TranslateError::Unavailable {
+            provider: provider.to_string(),
+            message,
+        }),
+    }
+}
+
+// ============================================================================
+// DeepL
+// ============================================================================
+
+pub struct DeepL {
+    client: Client,
+}
+
+#[derive(Deserialize)]
+struct DeepLResponse {
+    translations: Vec<DeepLTranslation>,
+}
+
+#[derive(Deserialize)]
+struct DeepLTranslation {
+    detected_source_language: Option<String>,
+    text: String,
+}
+
+impl DeepL {
+    const NAME: &'static str = "deepl";
+    const SECRET: &'static str = "translation.deepl";
+
+    pub fn new() -> Self {
+        Self {
+            client: http_client(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TranslationProvider for DeepL {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    async fn translate(
+        &self,
+        text: &str,
+        source_lang: Option<&str>,
+        target_lang: &str,
+    ) -> Result<Translation, TranslateError> {
+        let key = api_key(Self::NAME, Self::SECRET)?;
+
+        // Free-plan keys end in ":fx" and use a separate host
+        let endpoint = if key.ends_with(":fx") {
+            "https://api-free.deepl.com/v2/translate"
+        } else {
+            "https://api.deepl.com/v2/translate"
+        };
+
+        let mut body = serde_json::json!({
+            "text": [text],
+            "target_lang": target_lang.to_uppercase(),
+        });
+        if let Some(source) = source_lang {
+            body["source_lang"] = source.to_uppercase().into();
+        }
+
+        let response = self
+            .client
+            .post(endpoint)
+            .header("Authorization", format!("DeepL-Auth-Key {}", key))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| network_error(Self::NAME, e))?;
+
+        let provider = Self::NAME.to_string();
+        match response.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                return Err(TranslateError::InvalidApiKey { provider })
+            }
+            StatusCode::TOO_MANY_REQUESTS => {
+                return Err(TranslateError::RateLimited {
+                    retry_after_secs: retry_after(&response),
+                    provider,
+                })
+            }
+            // DeepL-specific "quota exceeded"
+            status if status.as_u16() == 456 => {
+                return Err(TranslateError::QuotaExceeded { provider })
+            }
+            StatusCode::BAD_REQUEST => {
+                return Err(TranslateError::UnsupportedLanguage {
+                    message: response.text().await.unwrap_or_default(),
+                    provider,
+                })
+            }
+            status if !status.is_success() => {
+                return Err(TranslateError::Provider {
+                    message: format!("HTTP {}", status),
+                    provider,
+                })
+            }
+            _ => {}
+        }
+
+        let parsed: DeepLResponse = response
+            .json()
+            .await
+            .map_err(|e| network_error(Self::NAME, e))?;
+        let first =
+            parsed
+                .translations
+                .into_iter()
+                .next()
+                .ok_or_else(|| TranslateError::Provider {
+                    provider: provider.clone(),
+                    message: "Empty response".to_string(),
+                })?;
+
+        Ok(Translation {
+            text: first.text,
+            detected_source_lang: first
+                .detected_source_language
+                .map(|l| l.to_lowercase())
+                .or_else(|| source_lang.map(str::to_string)),
+            target_lang: target_lang.to_string(),
+            provider,
+            cached: false,
+        })
+    }
+}
+
+// ============================================================================
+// LibreTranslate
+// ============================================================================
+
+pub struct LibreTranslate {
+    client: Client,
+    base_url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LibreResponse {
+    translated_text: String,
+    detected_language: Option<LibreDetected>,
+}
+
+#[derive(Deserialize)]
+struct LibreDetected {
+    language: String,
+}
+
+#[derive(Deserialize)]
+struct LibreError {
+    error: String,
+}
+
+impl LibreTranslate {
+    const NAME: &'static str = "libretranslate";
+    const SECRET: &'static str = "translation.libretranslate";
+
+    pub fn new(base_url: String) -> Self {
+        Self {
+            client: http_client(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TranslationProvider for LibreTranslate {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    async fn translate(
+        &self,
+        text: &str,
+        source_lang: Option<&str>,
+        target_lang: &str,
+    ) -> Result<Translation, TranslateError> {
+        // Self-hosted instances often run without keys
+        let key = match api_key(Self::NAME, Self::SECRET) {
+            Ok(key) => Some(key),
+            Err(TranslateError::MissingApiKey { .. }) => None,
+            Err(e) => return Err(e),
+        };
+
+        let response = self
+            .client
+            .post(format!("{}/translate", self.base_url))
+            .json(&serde_json::json!({
+                "q": text,
+                "source": source_lang.unwrap_or("auto"),
+                "target": target_lang,
+                "format": "text",
+                "api_key": key,
+            }))
+            .send()
+            .await
+            .map_err(|e| network_error(Self::NAME, e))?;
+
+        let provider = Self::NAME.to_string();
+        let status = response.status();
+
+        if !status.is_success() {
+            let retry_after_secs = retry_after(&response);
+            let message = response
+                .json::<LibreError>()
+                .await
+                .map(|e| e.error)
+                .unwrap_or_else(|_| format!("HTTP {}", status));
+
+            return Err(match status {
+                StatusCode::FORBIDDEN if key.is_none() => {
+                    TranslateError::MissingApiKey { provider }
+                }
+                StatusCode::FORBIDDEN => TranslateError::InvalidApiKey { provider },
+                StatusCode::TOO_MANY_REQUESTS => TranslateError::RateLimited {
+                    provider,
+                    retry_after_secs,
+                },
+                StatusCode::BAD_REQUEST => {
+                    TranslateError::UnsupportedLanguage { provider, message }
+                }
+                _ => TranslateError::Provider { provider, message },
+            });
+        }
+
+        let parsed: LibreResponse = response
+            .json()
+            .await
+            .map_err(|e| network_error(Self::NAME, e))?;
+
+        Ok(Translation {
+            text: parsed.translated_text,
+            detected_source_lang: parsed
+                .detected_language
+                .map(|d| d.language)
+                .or_else(|| source_lang.map(str::to_string)),
+            target_lang: target_lang.to_string(),
+            provider,
+            cached: false,
+        })
+    }
+}
+
+// ============================================================================
+// Custom HTTP
+// ============================================================================
+
+/// A self-hosted translation endpoint. Both the endpoint URL and the
+/// bearer key live in the keychain, since the URL often embeds a token.
+///
+/// Request: `POST {"text", "source", "target"}`; the response may carry
+/// the result as `translation`, `translatedText`, or `text`.
+pub struct HttpEndpoint {
+    client: Client,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HttpEndpointResponse {
+    #[serde(alias = "translatedText", alias = "text")]
+    translation: String,
+    #[serde(alias = "detectedLanguage", alias = "source")]
+    detected_source_lang: Option<String>,
+}
+
+impl HttpEndpoint {
+    const NAME: &'static str = "http";
+    const ENDPOINT_SECRET: &'static str = "translation.http.endpoint";
+    const KEY_SECRET: &'static str = "translation.http.key";
+
+    pub fn new() -> Self {
+        Self {
+            client: http_client(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TranslationProvider for HttpEndpoint {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    async fn translate(
+        &self,
+        text: &str,
+        source_lang: Option<&str>,
+        target_lang: &str,
+    ) -> Result<Translation, TranslateError> {
+        let endpoint = match api_key(Self::NAME, Self::ENDPOINT_SECRET) {
+            Ok(endpoint) => endpoint,
+            Err(TranslateError::MissingApiKey { provider }) => {
+                return Err(TranslateError::Unavailable {
+                    provider,
+                    message: "No endpoint configured".to_string(),
+                })
+            }
+            Err(e) => return Err(e),
+        };
+        let key = match api_key(Self::NAME, Self::KEY_SECRET) {
+            Ok(key) => Some(key),
+            Err(TranslateError::MissingApiKey { .. }) => None,
+            Err(e) => return Err(e),
+        };
+
+        let mut request = self.client.post(&endpoint).json(&serde_json::json!({
+            "text": text,
+            "source": source_lang,
+            "target": target_lang,
+        }));
+        if let Some(key) = &key {
+            request = request.bearer_auth(key);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| network_error(Self::NAME, e))?;
+
+        let provider = Self::NAME.to_string();
+        let status = response.status();
+
+        if !status.is_success() {
+            let retry_after_secs = retry_after(&response);
+            let message = response
+                .text()
+                .await
+                .ok()
+                .filter(|body| !body.trim().is_empty())
+                .unwrap_or_else(|| format!("HTTP {}", status));
+
+            return Err(match status {
+                StatusCode::UNAUTHORIZED if key.is_none() => {
+                    TranslateError::MissingApiKey { provider }
+                }
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                    TranslateError::InvalidApiKey { provider }
+                }
+                StatusCode::TOO_MANY_REQUESTS => TranslateError::RateLimited {
+                    provider,
+                    retry_after_secs,
+                },
+                StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => {
+                    TranslateError::UnsupportedLanguage { provider, message }
+                }
+                _ => TranslateError::Provider { provider, message },
+            });
+        }
+
+        let parsed: HttpEndpointResponse =
+            response
+                .json()
+                .await
+                .map_err(|e| TranslateError::Provider {
+                    provider: provider.clone(),
+                    message: format!("Unexpected response: {}", e),
+                })?;
+
+        Ok(Translation {
+            text: parsed.translation,
+            detected_source_lang: parsed
+                .detected_source_lang
+                .or_else(|| source_lang.map(str::to_string)),
+            target_lang: target_lang.to_string(),
+            provider,
+            cached: false,
+        })
+    }
+}
+
+// ============================================================================
+// Dictionary (single words)
+// ============================================================================
+
+/// Word-level fallback using bilingual StarDict/dictd dictionaries,
+/// configured in the `translation.dictionaries` setting as
+/// `{"es-en": "/path/to/es-en.ifo"}`. Only single words can be translated,
+/// and the source language must be known.
+pub struct DictionaryWord {
+    cache: DictionaryCache,
+    /// Lowercased "from-to" pair -> dictionary path
+    paths: HashMap<String, PathBuf>,
+}
+
+impl DictionaryWord {
+    const NAME: &'static str = "dictionary";
+
+    pub fn new(cache: DictionaryCache, paths: HashMap<String, PathBuf>) -> Self {
+        Self { cache, paths }
+    }
+
+    fn path_for(&self, source_lang: &str, target_lang: &str) -> Option<PathBuf> {
+        let base = |lang: &str| lang.split(['-', '_']).next().unwrap_or(lang).to_lowercase();
+
+        [
+            format!("{}-{}", source_lang, target_lang).to_lowercase(),
+            format!("{}-{}", base(source_lang), base(target_lang)),
+        ]
+        .iter()
+        .find_map(|key| self.paths.get(key).cloned())
+    }
+}
+
+#[async_trait::async_trait]
+impl TranslationProvider for DictionaryWord {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    async fn translate(
+        &self,
+        text: &str,
+        source_lang: Option<&str>,
+        target_lang: &str,
+    ) -> Result<Translation, TranslateError> {
+        let provider = Self::NAME.to_string();
+
+        let word = text.trim().trim_matches(|c: char| !c.is_alphanumeric());
+        if word.is_empty() || word.contains(char::is_whitespace) {
+            return Err(TranslateError::Unavailable {
+                provider,
+                message: "Only single words can be translated offline".to_string(),
+            });
+        }
+        let Some(source_lang) = source_lang else {
+            return Err(TranslateError::Unavailable {
+                provider,
+                message: "Source language required for dictionary lookup".to_string(),
+            });
+        };
+        let Some(path) = self.path_for(source_lang, target_lang) else {
+            return Err(TranslateError::UnsupportedLanguage {
+                provider,
+                message: format!("No {}-{} dictionary installed", source_lang, target_lang),
+            });
+        };
+
+        let cache = self.cache.clone();
+        let (word, from) = (word.to_string(), source_lang.to_string());
+        let result = tauri::async_runtime::spawn_blocking(move || {
+            dictionary::translate_word(&cache, path, &word, &from)
+        })
+        .await
+        .map_err(|e| TranslateError::Provider {
+            provider: provider.clone(),
+            message: e.to_string(),
+        })?
+        .map_err(|message| TranslateError::Unavailable {
+            provider: provider.clone(),
+            message,
+        })?;
+
+        let text = result.ok_or_else(|| TranslateError::Provider {
+            provider: provider.clone(),
+            message: format!("'{}' not found in dictionary", text.trim()),
+        })?;
+
+        Ok(Translation {
+            text,
+            detected_source_lang: Some(source_lang.to_string()),
+            target_lang: target_lang.to_string(),
+            provider,
+            cached: false,
+        })
+    }
+}
+
+// ============================================================================
+// Offline (Bergamot)
+// ============================================================================
+
+/// Local translation with Bergamot models. Each language pair lives in
+/// `<models_dir>/<source>-<target>/config.yml` and is run through the
+/// `bergamot` CLI, bundled next to the executable or found on PATH.
+pub struct OfflineModel {
+    models_dir: PathBuf,
+}
+
+impl OfflineModel {
+    const NAME: &'static str = "offline";
+
+    pub fn new(models_dir: PathBuf) -> Self {
+        Self { models_dir }
+    }
+
+    fn binary() -> PathBuf {
+        let name = if cfg!(windows) {
+            "bergamot.exe"
+        } else {
+            "bergamot"
+        };
+
+        std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join(name)))
+            .filter(|path| path.exists())
+            .unwrap_or_else(|| PathBuf::from(name))
+    }
+
+    fn unavailable(message: impl Into<String>) -> TranslateError {
+        TranslateError::Unavailable {
+            provider: Self::NAME.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TranslationProvider for OfflineModel {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    async fn translate(
+        &self,
+        text: &str,
+        source_lang: Option<&str>,
+        target_lang: &str,
+    ) -> Result<Translation, TranslateError> {
+        // Bergamot models are per language pair, so detection isn't possible
+        let source = source_lang.ok_or_else(|| TranslateError::UnsupportedLanguage {
+            provider: Self::NAME.to_string(),
+            message: "Offline translation requires a source language".to_string(),
+        })?;
+
+        let config = self
+            .models_dir
+            .join(format!("{}-{}", source, target_lang))
+            .join("config.yml");
+        if !config.exists() {
+            return Err(Self::unavailable(format!(
+                "No offline model installed for {}-{}",
+                source, target_lang
+            )));
+        }
+
+        let input = text.to_string();
+        let output = tauri::async_runtime::spawn_blocking(move || {
+            let mut child = Command::new(Self::binary())
+                .arg("--model-config-paths")
+                .arg(&config)
+                .arg("--cpu-threads")
+                .arg("1")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()?;
+
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(input.as_bytes())?;
+            }
+            child.wait_with_output()
+        })
+        .await
+        .map_err(|e| Self::unavailable(e.to_string()))?
+        .map_err(|e| Self::unavailable(format!("Failed to run bergamot: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(TranslateError::Provider {
+                provider: Self::NAME.to_string(),
+                message: format!("bergamot exited with {}", output.status),
+            });
+        }
+
+        Ok(Translation {
+            text: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+            detected_source_lang: Some(source.to_string()),
+            target_lang: target_lang.to_string(),
+            provider: Self::NAME.to_string(),
+            cached: false,
+        })
+    }
+}
@@ -7,9 +7,56 @@ use tauri::{
     image::Image,
     menu::{Menu, MenuBuilder, MenuItemBuilder, PredefinedMenuItem},
     tray::{TrayIcon, TrayIconBuilder},
-    AppHandle, Manager, Runtime,
+    AppHandle, Emitter, Manager, Runtime,
 };
 
+/// Id of the app's tray icon
+const TRAY_ID: &str = "main";
+
+/// Bring the main window forward, including when hidden to the tray or
+/// minimized
+pub fn show_main_window<R: Runtime>(app: &AppHandle<R>) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.show();
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+/// Bring the main window forward and route it to `route`. Shared by
+/// every shell entry point (tray, jump list, dock menu) that opens a view.
+pub fn show_and_navigate<R: Runtime>(app: &AppHandle<R>, route: &str) {
+    show_main_window(app);
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.emit("navigate", route);
+    }
+}
+
+/// Open the book read most recently at its saved position, or the library
+/// when there isn't one
+pub fn continue_reading<R: Runtime>(app: &AppHandle<R>) {
+    match crate::session::last_book(app) {
+        Some(last_book) => show_and_navigate(app, &last_book.route()),
+        None => {
+            info!("No book to continue, opening the library");
+            show_and_navigate(app, "/library");
+        }
+    }
+}
+
+/// Show `status` (e.g. goal progress) under the app name in the tray
+/// tooltip, or just the app name when `None`
+pub fn set_status<R: Runtime>(app: &AppHandle<R>, status: Option<&str>) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let tooltip = match status {
+        Some(status) => format!("Read Master\n{}", status),
+        None => "Read Master".to_string(),
+    };
+    let _ = tray.set_tooltip(Some(tooltip));
+}
+
 /// Create the system tray icon and menu
 pub fn create_tray<R: Runtime>(app: &AppHandle<R>) -> Result<TrayIcon<R>, tauri::Error> {
     info!("Creating system tray...");
@@ -32,6 +79,8 @@ pub fn create_tray<R: Runtime>(app: &AppHandle<R>) -> Result<TrayIcon<R>, tauri:
                 .build(app)?,
             &MenuItemBuilder::with_id("tray_flashcards", "Review Flashcards")
                 .build(app)?,
+            &MenuItemBuilder::with_id("tray_quick_capture", "New Card from Clipboard")
+                .build(app)?,
             &PredefinedMenuItem::separator(app)?,
             &MenuItemBuilder::with_id("tray_settings", "Settings")
                 .build(app)?,
@@ -42,52 +91,26 @@ pub fn create_tray<R: Runtime>(app: &AppHandle<R>) -> Result<TrayIcon<R>, tauri:
         .build()?;
 
     // Create tray icon
-    let tray = TrayIconBuilder::new()
+    let tray = TrayIconBuilder::with_id(TRAY_ID)
         .menu(&menu)
         .tooltip("Read Master")
         .on_menu_event(move |app, event| {
             info!("Tray menu event: {:?}", event.id());
 
             match event.id().as_ref() {
-                "tray_show" => {
-                    if let Some(window) = app.get_webview_window("main") {
-                        let _ = window.show();
-                        let _ = window.set_focus();
-                    }
-                }
+                "tray_show" => show_main_window(app),
                 "tray_hide" => {
                     if let Some(window) = app.get_webview_window("main") {
                         let _ = window.hide();
                     }
                 }
-                "tray_library" => {
-                    if let Some(window) = app.get_webview_window("main") {
-                        let _ = window.show();
-                        let _ = window.set_focus();
-                        let _ = window.emit("navigate", "/library");
-                    }
-                }
-                "tray_continue" => {
-                    if let Some(window) = app.get_webview_window("main") {
-                        let _ = window.show();
-                        let _ = window.set_focus();
-                        let _ = window.emit("navigate", "/reader/continue");
-                    }
-                }
-                "tray_flashcards" => {
-                    if let Some(window) = app.get_webview_window("main") {
-                        let _ = window.show();
-                        let _ = window.set_focus();
-                        let _ = window.emit("navigate", "/flashcards/review");
-                    }
-                }
-                "tray_settings" => {
-                    if let Some(window) = app.get_webview_window("main") {
-                        let _ = window.show();
-                        let _ = window.set_focus();
-                        let _ = window.emit("navigate", "/settings");
-                    }
+                "tray_library" => show_and_navigate(app, "/library"),
+                "tray_continue" => continue_reading(app),
+                "tray_flashcards" => show_and_navigate(app, "/flashcards/review"),
+                "tray_quick_capture" => {
+                    crate::quick_capture::open(app);
                 }
+                "tray_settings" => show_and_navigate(app, "/settings"),
                 "tray_quit" => {
                     app.exit(0);
                 }
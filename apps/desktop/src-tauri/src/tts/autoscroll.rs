@@ -0,0 +1,221 @@
+// Read Master Desktop - Read-Aloud Autoscroll
+//
+// Keeps the line being spoken in view. Each sentence emits `tts-boundary`
+// with a scroll anchor: the chapter's archive href plus the sentence's
+// character offset into the chapter's spoken text (paragraphs joined by
+// newlines, sentences by spaces), and that text's length, so the reader can
+// map it onto the rendered chapter. The payload carries the book's
+// autoscroll setting; `speed` scales how fast the reader glides from one
+// boundary to the next.
+//
+// Settings are stored per book. Books without a row scroll at normal speed.
+
+use log::info;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::TtsPosition;
+use crate::db::Database;
+
+pub const MIN_SPEED: f32 = 0.25;
+pub const MAX_SPEED: f32 = 4.0;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Autoscroll {
+    pub enabled: bool,
+    /// Scroll speed multiplier between boundaries
+    pub speed: f32,
+}
+
+impl Default for Autoscroll {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            speed: 1.0,
+        }
+    }
+}
+
+/// Where the spoken sentence starts within its chapter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrollAnchor {
+    /// Archive path of the chapter document
+    pub href: String,
+    /// Character offset of the sentence in the chapter's spoken text
+    pub offset: usize,
+    /// Characters in the chapter's spoken text
+    pub length: usize,
+}
+
+fn clamp_speed(speed: f32) -> f32 {
+    if speed.is_finite() {
+        speed.clamp(MIN_SPEED, MAX_SPEED)
+    } else {
+        1.0
+    }
+}
+
+// ============================================================================
+// Anchors
+// ============================================================================
+
+/// Characters in a paragraph's spoken text, sentences joined by spaces
+fn paragraph_len(paragraph: &[String]) -> usize {
+    paragraph.iter().map(|s| s.chars().count()).sum::<usize>() + paragraph.len().saturating_sub(1)
+}
+
+/// Characters in a chapter's spoken text, paragraphs joined by newlines
+pub fn chapter_len(paragraphs: &[Vec<String>]) -> usize {
+    paragraphs.iter().map(|p| paragraph_len(p)).sum::<usize>() + paragraphs.len().saturating_sub(1)
+}
+
+/// Character offset of the sentence at `position` in its chapter
+pub fn sentence_offset(paragraphs: &[Vec<String>], position: TtsPosition) -> usize {
+    let before: usize = paragraphs
+        .iter()
+        .take(position.paragraph)
+        .map(|p| paragraph_len(p) + 1)
+        .sum();
+    let within: usize = paragraphs
+        .get(position.paragraph)
+        .map(|p| {
+            p.iter()
+                .take(position.sentence)
+                .map(|s| s.chars().count() + 1)
+                .sum()
+        })
+        .unwrap_or(0);
+    before + within
+}
+
+// ============================================================================
+// Storage
+// ============================================================================
+
+pub fn load(conn: &Connection, book_id: i64) -> rusqlite::Result<Autoscroll> {
+    let settings = conn
+        .query_row(
+            "SELECT enabled, speed FROM book_autoscroll WHERE book_id = ?1",
+            [book_id],
+            |row| {
+                Ok(Autoscroll {
+                    enabled: row.get(0)?,
+                    speed: clamp_speed(row.get::<_, f64>(1)? as f32),
+                })
+            },
+        )
+        .optional()?;
+    Ok(settings.unwrap_or_default())
+}
+
+fn save(conn: &Connection, book_id: i64, settings: Autoscroll) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO book_autoscroll (book_id, enabled, speed, updated_at)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (book_id) DO UPDATE
+         SET enabled = excluded.enabled, speed = excluded.speed,
+             updated_at = excluded.updated_at",
+        params![
+            book_id,
+            settings.enabled,
+            f64::from(settings.speed),
+            chrono::Utc::now().to_rfc3339()
+        ],
+    )?;
+    Ok(())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Turn autoscroll during read-aloud on or off for a book and set its
+/// speed, clamped to 0.25–4. Applies from the next sentence.
+#[tauri::command]
+pub fn set_autoscroll(
+    db: State<'_, Database>,
+    book_id: i64,
+    enabled: bool,
+    speed: f32,
+) -> Result<Autoscroll, String> {
+    let settings = Autoscroll {
+        enabled,
+        speed: clamp_speed(speed),
+    };
+    db.with_conn(|conn| save(conn, book_id, settings))?;
+    info!("Autoscroll for book {}: {:?}", book_id, settings);
+    Ok(settings)
+}
+
+#[tauri::command]
+pub fn get_autoscroll(db: State<'_, Database>, book_id: i64) -> Result<Autoscroll, String> {
+    db.with_conn(|conn| load(conn, book_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(paragraphs: &[&[&str]]) -> Vec<Vec<String>> {
+        paragraphs
+            .iter()
+            .map(|p| p.iter().map(|s| s.to_string()).collect())
+            .collect()
+    }
+
+    fn at(paragraph: usize, sentence: usize) -> TtsPosition {
+        TtsPosition {
+            chapter: 0,
+            paragraph,
+            sentence,
+        }
+    }
+
+    #[test]
+    fn offsets_index_the_joined_chapter_text() {
+        let paragraphs = chapter(&[&["One two.", "Three."], &["Four…", "Fünf."]]);
+        let text = "One two. Three.\nFour… Fünf.";
+        let offset_of = |needle: &str| text[..text.find(needle).unwrap()].chars().count();
+
+        assert_eq!(sentence_offset(&paragraphs, at(0, 0)), 0);
+        assert_eq!(sentence_offset(&paragraphs, at(0, 1)), offset_of("Three."));
+        assert_eq!(sentence_offset(&paragraphs, at(1, 0)), offset_of("Four"));
+        assert_eq!(sentence_offset(&paragraphs, at(1, 1)), offset_of("Fünf"));
+        assert_eq!(chapter_len(&paragraphs), text.chars().count());
+    }
+
+    #[test]
+    fn clamps_speed() {
+        assert_eq!(clamp_speed(10.0), MAX_SPEED);
+        assert_eq!(clamp_speed(0.0), MIN_SPEED);
+        assert_eq!(clamp_speed(f32::NAN), 1.0);
+        assert_eq!(clamp_speed(1.5), 1.5);
+    }
+
+    #[test]
+    fn stores_settings_per_book() {
+        let conn = crate::db::test_connection();
+        conn.execute(
+            "INSERT INTO books (id, title, added_at, updated_at) VALUES (1, 'Book', '', '')",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(load(&conn, 1).unwrap(), Autoscroll::default());
+        let slow = Autoscroll {
+            enabled: false,
+            speed: 0.5,
+        };
+        save(&conn, 1, slow).unwrap();
+        save(&conn, 1, slow).unwrap();
+        assert_eq!(load(&conn, 1).unwrap(), slow);
+        assert_eq!(load(&conn, 2).unwrap(), Autoscroll::default());
+    }
+}
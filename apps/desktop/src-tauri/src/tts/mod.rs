@@ -0,0 +1,849 @@
+// Read Master Desktop - Text-to-Speech
+//
+// Whole-book read-aloud on the OS speech engine, one sentence at a time.
+
+pub mod autoscroll;
+mod route;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use self::autoscroll::{Autoscroll, ScrollAnchor};
+use crate::db::Database;
+use crate::epub::EpubArchive;
+use crate::power::{self, PowerEvent};
+use crate::{language, library};
+
+/// How often the playback thread checks the engine and its controls
+const POLL: Duration = Duration::from_millis(100);
+
+/// Time allowed for an utterance to start before silence means it ended
+const START_GRACE: Duration = Duration::from_millis(400);
+
+/// Speaking rate assumed when the engine can't report whether it's busy
+const FALLBACK_WPM: f64 = 180.0;
+
+/// Words that end with a period without ending a sentence
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr", "vs", "etc", "e.g", "i.e", "no", "vol",
+    "ch", "fig", "pp", "cf", "approx", "gen", "col", "capt", "lt", "rev", "mt",
+];
+
+const SUPERSCRIPTS: &[char] = &['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹', '†', '‡'];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TtsPosition {
+    /// Spine index
+    pub chapter: usize,
+    pub paragraph: usize,
+    pub sentence: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SkipUnit {
+    Sentence,
+    Paragraph,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SkipDirection {
+    Forward,
+    Back,
+}
+
+/// `tts-position`, emitted for each sentence so the reader can follow
+/// along and save the position
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PositionPayload {
+    book_id: i64,
+    #[serde(flatten)]
+    position: TtsPosition,
+    text: String,
+}
+
+/// `tts-boundary`, with a scroll anchor for `autoscroll`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BoundaryPayload {
+    book_id: i64,
+    position: TtsPosition,
+    anchor: ScrollAnchor,
+    autoscroll: Autoscroll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum StopReason {
+    Finished,
+    Stopped,
+    SleepTimer,
+    Error,
+}
+
+/// `tts-stopped`, emitted when playback ends for any reason
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StoppedPayload {
+    book_id: i64,
+    reason: StopReason,
+    position: TtsPosition,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+enum Control {
+    Skip(SkipUnit, SkipDirection),
+    Sleep(Option<Instant>),
+    Pause,
+    Resume,
+    Stop,
+}
+
+struct Playback {
+    id: u64,
+    book_id: i64,
+    paused: bool,
+    control: Sender<Control>,
+}
+
+/// The book currently being read aloud, if any
+#[derive(Default)]
+pub struct TtsPlayer(Mutex<Option<Playback>>);
+
+static NEXT_PLAYBACK: AtomicU64 = AtomicU64::new(1);
+
+impl TtsPlayer {
+    fn send(&self, control: Control) -> Result<(), String> {
+        let mut playback = self.0.lock().map_err(|_| "TTS lock poisoned".to_string())?;
+        let playback = playback
+            .as_mut()
+            .ok_or_else(|| "Nothing is being read aloud".to_string())?;
+        // Skipping resumes paused playback
+        let resumes = matches!(control, Control::Skip(..));
+        playback
+            .control
+            .send(control)
+            .map_err(|_| "Nothing is being read aloud".to_string())?;
+        if resumes {
+            playback.paused = false;
+        }
+        Ok(())
+    }
+
+    /// Pause or resume playback. Returns false if it was already in that
+    /// state or nothing is playing.
+    fn set_paused(&self, paused: bool) -> Result<bool, String> {
+        let mut playback = self.0.lock().map_err(|_| "TTS lock poisoned".to_string())?;
+        let Some(playback) = playback.as_mut().filter(|p| p.paused != paused) else {
+            return Ok(false);
+        };
+        let control = if paused {
+            Control::Pause
+        } else {
+            Control::Resume
+        };
+        playback
+            .control
+            .send(control)
+            .map_err(|_| "Nothing is being read aloud".to_string())?;
+        playback.paused = paused;
+        Ok(true)
+    }
+
+    /// Whether a book is being read aloud and isn't paused
+    fn is_speaking(&self) -> bool {
+        self.0
+            .lock()
+            .map(|p| p.as_ref().is_some_and(|p| !p.paused))
+            .unwrap_or(false)
+    }
+}
+
+/// Pause reading aloud, if a book is playing. Returns whether it paused.
+/// `route` calls this when audio falls back from headphones to the
+/// built-in speakers.
+pub fn pause_speaking<R: Runtime>(app: &AppHandle<R>) -> bool {
+    match app.state::<TtsPlayer>().set_paused(true) {
+        Ok(paused) => paused,
+        Err(e) => {
+            warn!("Failed to pause TTS: {}", e);
+            false
+        }
+    }
+}
+
+/// Start the audio route monitor, and pause reading aloud before the
+/// system sleeps. Must run after `power::init`.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    route::init(app);
+
+    power::subscribe(app, {
+        let app = app.clone();
+        move |event| {
+            if event == PowerEvent::SystemSuspend && pause_speaking(&app) {
+                info!("Paused reading aloud for system sleep");
+            }
+        }
+    });
+}
+
+/// A chapter as paragraphs of sentences
+type Chapter = Arc<Vec<Vec<String>>>;
+
+// ============================================================================
+// Segmentation
+// ============================================================================
+
+/// Running heads and folios: bare numbers, roman numerals, "Page 12"
+fn is_page_number(line: &str) -> bool {
+    let line = line.trim();
+    let number = line
+        .strip_prefix("Page ")
+        .or_else(|| line.strip_prefix("page "))
+        .or_else(|| line.strip_prefix("p. "))
+        .unwrap_or(line);
+
+    let arabic =
+        !number.is_empty() && number.len() <= 4 && number.chars().all(|c| c.is_ascii_digit());
+    let roman = !number.is_empty()
+        && number.len() <= 7
+        && (number.chars().all(|c| "ivxlcdm".contains(c))
+            || number.chars().all(|c| "IVXLCDM".contains(c)));
+    arabic || roman
+}
+
+/// Remove footnote references: `[12]`, `[3, 4]`, superscripts, daggers,
+/// and note numbers glued to punctuation (`end.12 Next`)
+fn strip_footnote_markers(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '[' {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|&c| c == ']')
+                .map(|p| i + 1 + p);
+            if let Some(end) = end {
+                let inner = &chars[i + 1..end];
+                let is_reference = !inner.is_empty()
+                    && inner.iter().any(char::is_ascii_digit)
+                    && inner
+                        .iter()
+                        .all(|c| c.is_ascii_digit() || matches!(c, ',' | ' ' | '-'));
+                if is_reference {
+                    i = end + 1;
+                    continue;
+                }
+            }
+        }
+
+        if SUPERSCRIPTS.contains(&c) {
+            i += 1;
+            continue;
+        }
+
+        out.push(c);
+        i += 1;
+
+        if matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | '"' | '”' | '’' | ')') {
+            let digits = chars[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let then_space = chars.get(i + digits).is_none_or(|c| c.is_whitespace());
+            let after_word = out.chars().rev().nth(1).is_some_and(char::is_alphabetic);
+            if digits > 0 && digits <= 3 && then_space && after_word {
+                i += digits;
+            }
+        }
+    }
+
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn is_abbreviation(word: &str) -> bool {
+    let word = word
+        .trim_start_matches(|c: char| !c.is_alphanumeric())
+        .trim_end_matches('.')
+        .to_lowercase();
+    // Initials like "J." in "J. R. R. Tolkien"
+    let initial = word.chars().count() == 1 && word.chars().all(char::is_alphabetic);
+    initial || ABBREVIATIONS.contains(&word.as_str())
+}
+
+/// Split a paragraph into sentences
+pub fn split_sentences(paragraph: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut words = paragraph.split_whitespace().peekable();
+
+    while let Some(word) = words.next() {
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+
+        let core = word.trim_end_matches(['"', '\'', '”', '’', ')', ']']);
+        let ends = core.ends_with(['.', '!', '?', '…']);
+        let next_starts = words
+            .peek()
+            .and_then(|next| {
+                next.trim_start_matches(['"', '\'', '“', '‘', '(', '['])
+                    .chars()
+                    .next()
+            })
+            .is_none_or(|c| c.is_uppercase() || c.is_ascii_digit());
+
+        if ends && next_starts && !(core.ends_with('.') && is_abbreviation(core)) {
+            sentences.push(std::mem::take(&mut current));
+        }
+    }
+
+    if !current.is_empty() {
+        sentences.push(current);
+    }
+    sentences
+}
+
+/// Spoken paragraphs of a chapter, each split into sentences. Footnote
+/// markers and page numbers carried over from print editions are dropped.
+pub fn segment(text: &str) -> Vec<Vec<String>> {
+    text.lines()
+        .filter(|line| !is_page_number(line))
+        .map(strip_footnote_markers)
+        .map(|paragraph| split_sentences(&paragraph))
+        .filter(|sentences| !sentences.is_empty())
+        .collect()
+}
+
+// ============================================================================
+// Chapters
+// ============================================================================
+
+/// Loads and caches segmented chapters, prefetching in the background. The
+/// OS synthesizers can't overlap two utterances, so there's no crossfade
+/// between chapters; the next one is ready while the current one plays and
+/// starts without a gap.
+#[derive(Clone)]
+struct Chapters {
+    path: String,
+    /// Archive path of each spine item
+    hrefs: Arc<Vec<String>>,
+    count: usize,
+    cache: Arc<Mutex<HashMap<usize, Chapter>>>,
+}
+
+impl Chapters {
+    fn open(path: &str) -> Result<Self, String> {
+        let hrefs = EpubArchive::open(path)
+            .map_err(|e| e.to_string())?
+            .spine_paths();
+        Ok(Self {
+            path: path.to_string(),
+            count: hrefs.len(),
+            hrefs: Arc::new(hrefs),
+            cache: Arc::default(),
+        })
+    }
+
+    fn load(&self, index: usize) -> Chapter {
+        if let Some(chapter) = self.cache.lock().ok().and_then(|c| c.get(&index).cloned()) {
+            return chapter;
+        }
+
+        let paragraphs = EpubArchive::open(&self.path)
+            .map_err(|e| e.to_string())
+            .and_then(|mut epub| epub.chapter_text(index))
+            .map(|chapter| segment(&chapter.text))
+            .unwrap_or_else(|e| {
+                warn!("Skipping chapter {} for TTS: {}", index, e);
+                Vec::new()
+            });
+        let chapter = Arc::new(paragraphs);
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(index, Arc::clone(&chapter));
+        }
+        chapter
+    }
+
+    fn prefetch(&self, index: usize) {
+        if index >= self.count {
+            return;
+        }
+        let chapters = self.clone();
+        thread::spawn(move || {
+            chapters.load(index);
+        });
+    }
+}
+
+/// Position plus the chapter it points into
+struct Cursor {
+    position: TtsPosition,
+    chapter: Chapter,
+}
+
+impl Cursor {
+    fn sentence(&self) -> Option<&String> {
+        self.chapter
+            .get(self.position.paragraph)?
+            .get(self.position.sentence)
+    }
+
+    fn anchor(&self, chapters: &Chapters) -> ScrollAnchor {
+        ScrollAnchor {
+            href: chapters
+                .hrefs
+                .get(self.position.chapter)
+                .cloned()
+                .unwrap_or_default(),
+            offset: autoscroll::sentence_offset(&self.chapter, self.position),
+            length: autoscroll::chapter_len(&self.chapter),
+        }
+    }
+
+    /// Move to the first sentence of the next non-empty chapter after
+    /// `from`. False at the end of the book.
+    fn next_chapter(&mut self, chapters: &Chapters, from: usize) -> bool {
+        for index in from + 1..chapters.count {
+            let chapter = chapters.load(index);
+            if !chapter.is_empty() {
+                chapters.prefetch(index + 1);
+                self.chapter = chapter;
+                self.position = TtsPosition {
+                    chapter: index,
+                    paragraph: 0,
+                    sentence: 0,
+                };
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Move to the last paragraph of the previous non-empty chapter
+    fn previous_chapter(&mut self, chapters: &Chapters) -> bool {
+        for index in (0..self.position.chapter).rev() {
+            let chapter = chapters.load(index);
+            if let Some(last) = chapter.len().checked_sub(1) {
+                self.chapter = chapter;
+                self.position = TtsPosition {
+                    chapter: index,
+                    paragraph: last,
+                    sentence: 0,
+                };
+                return true;
+            }
+        }
+        false
+    }
+
+    fn forward(&mut self, chapters: &Chapters, unit: SkipUnit) -> bool {
+        let p = &mut self.position;
+        let sentences = self.chapter.get(p.paragraph).map_or(0, Vec::len);
+        if unit == SkipUnit::Sentence && p.sentence + 1 < sentences {
+            p.sentence += 1;
+            return true;
+        }
+        if p.paragraph + 1 < self.chapter.len() {
+            p.paragraph += 1;
+            p.sentence = 0;
+            return true;
+        }
+        let current = p.chapter;
+        self.next_chapter(chapters, current)
+    }
+
+    fn back(&mut self, chapters: &Chapters, unit: SkipUnit) -> bool {
+        let p = &mut self.position;
+        match unit {
+            SkipUnit::Sentence if p.sentence > 0 => p.sentence -= 1,
+            SkipUnit::Sentence if p.paragraph > 0 => {
+                p.paragraph -= 1;
+                p.sentence = self.chapter[p.paragraph].len().saturating_sub(1);
+            }
+            SkipUnit::Paragraph if p.paragraph > 0 => {
+                p.paragraph -= 1;
+                p.sentence = 0;
+            }
+            _ => return self.previous_chapter(chapters),
+        }
+        true
+    }
+}
+
+// ============================================================================
+// Playback
+// ============================================================================
+
+/// Wait for the current utterance to finish, handling controls meanwhile.
+/// Returns the control that interrupted it, if any.
+fn wait_for_utterance(
+    engine: &tts::Tts,
+    sentence: &str,
+    controls: &Receiver<Control>,
+    sleep_at: &mut Option<Instant>,
+) -> Option<Control> {
+    let started = Instant::now();
+    let can_poll = engine.supported_features().is_speaking;
+    let estimate =
+        Duration::from_secs_f64(sentence.split_whitespace().count() as f64 / FALLBACK_WPM * 60.0);
+
+    loop {
+        match controls.recv_timeout(POLL) {
+            Ok(Control::Sleep(at)) => *sleep_at = at,
+            Ok(control) => return Some(control),
+            Err(RecvTimeoutError::Disconnected) => return Some(Control::Stop),
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        let done = if can_poll {
+            started.elapsed() > START_GRACE && !engine.is_speaking().unwrap_or(false)
+        } else {
+            started.elapsed() >= estimate
+        };
+        if done {
+            return None;
+        }
+    }
+}
+
+/// Block until playback is resumed, stopped, or skipped. Skipping also
+/// resumes.
+fn wait_while_paused(
+    controls: &Receiver<Control>,
+    sleep_at: &mut Option<Instant>,
+) -> Option<Control> {
+    loop {
+        match controls.recv() {
+            Ok(Control::Sleep(at)) => *sleep_at = at,
+            Ok(Control::Pause) => {}
+            Ok(control) => return Some(control),
+            Err(_) => return Some(Control::Stop),
+        }
+    }
+}
+
+/// Switch to a voice for the book's language, if the engine has one
+fn select_voice(engine: &mut tts::Tts, path: &str) {
+    if !engine.supported_features().voice {
+        return;
+    }
+    let lang = match language::detect(path) {
+        Ok(detection) => detection.code,
+        Err(e) => {
+            warn!("Keeping default voice: {}", e);
+            return;
+        }
+    };
+    let lang = language::primary_subtag(&lang);
+
+    let voices = engine.voices().unwrap_or_default();
+    let voice = voices
+        .iter()
+        .find(|v| language::primary_subtag(v.language().as_str()).eq_ignore_ascii_case(lang));
+    match voice {
+        Some(voice) => {
+            info!("Using voice {} for language {}", voice.name(), lang);
+            if let Err(e) = engine.set_voice(voice) {
+                warn!("Failed to set voice: {}", e);
+            }
+        }
+        None => info!("No voice for language {}; keeping default", lang),
+    }
+}
+
+fn play<R: Runtime>(
+    app: &AppHandle<R>,
+    book_id: i64,
+    chapters: &Chapters,
+    from: TtsPosition,
+    controls: &Receiver<Control>,
+) -> (StopReason, TtsPosition, Option<String>) {
+    let mut engine = match tts::Tts::default() {
+        Ok(engine) => engine,
+        Err(e) => {
+            return (
+                StopReason::Error,
+                from,
+                Some(format!("Speech engine unavailable: {}", e)),
+            )
+        }
+    };
+
+    select_voice(&mut engine, &chapters.path);
+
+    let mut cursor = Cursor {
+        position: from,
+        chapter: chapters.load(from.chapter),
+    };
+    chapters.prefetch(from.chapter + 1);
+    if cursor.sentence().is_none() && !cursor.next_chapter(chapters, from.chapter) {
+        return (StopReason::Finished, from, None);
+    }
+
+    let mut sleep_at = None;
+    loop {
+        let Some(sentence) = cursor.sentence().cloned() else {
+            // Reached the end of the chapter
+            let current = cursor.position.chapter;
+            if cursor.next_chapter(chapters, current) {
+                continue;
+            }
+            return (StopReason::Finished, cursor.position, None);
+        };
+
+        let _ = app.emit(
+            "tts-position",
+            PositionPayload {
+                book_id,
+                position: cursor.position,
+                text: sentence.clone(),
+            },
+        );
+        // Read per sentence so `set_autoscroll` applies mid-playback
+        let autoscroll = app
+            .state::<Database>()
+            .with_conn(|conn| autoscroll::load(conn, book_id))
+            .unwrap_or_default();
+        let _ = app.emit(
+            "tts-boundary",
+            BoundaryPayload {
+                book_id,
+                position: cursor.position,
+                anchor: cursor.anchor(chapters),
+                autoscroll,
+            },
+        );
+
+        if let Err(e) = engine.speak(sentence.as_str(), true) {
+            return (StopReason::Error, cursor.position, Some(e.to_string()));
+        }
+
+        let mut control = wait_for_utterance(&engine, &sentence, controls, &mut sleep_at);
+        if let Some(Control::Pause) = control {
+            let _ = engine.stop();
+            control = wait_while_paused(controls, &mut sleep_at);
+        }
+        match control {
+            Some(Control::Stop) => {
+                let _ = engine.stop();
+                return (StopReason::Stopped, cursor.position, None);
+            }
+            Some(Control::Skip(unit, direction)) => {
+                let _ = engine.stop();
+                let moved = match direction {
+                    SkipDirection::Forward => cursor.forward(chapters, unit),
+                    SkipDirection::Back => cursor.back(chapters, unit),
+                };
+                if !moved && direction == SkipDirection::Forward {
+                    return (StopReason::Finished, cursor.position, None);
+                }
+                continue;
+            }
+            // Repeat the interrupted sentence
+            Some(Control::Resume) => continue,
+            Some(Control::Pause) | Some(Control::Sleep(_)) | None => {}
+        }
+
+        // The timer lets the current sentence finish
+        if sleep_at.is_some_and(|at| Instant::now() >= at) {
+            return (StopReason::SleepTimer, cursor.position, None);
+        }
+
+        let p = &mut cursor.position;
+        if p.sentence + 1 < cursor.chapter[p.paragraph].len() {
+            p.sentence += 1;
+        } else {
+            p.paragraph += 1;
+            p.sentence = 0;
+        }
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Read a book aloud from `from_position` (the start by default), replacing
+/// any current playback. Playback runs on into the next spine item.
+#[tauri::command]
+pub async fn tts_play_book<R: Runtime>(
+    app: AppHandle<R>,
+    db: State<'_, Database>,
+    player: State<'_, TtsPlayer>,
+    book_id: i64,
+    from_position: Option<TtsPosition>,
+) -> Result<(), String> {
+    let path = db
+        .with_conn(|conn| library::get_book(conn, book_id))?
+        .and_then(|book| book.path)
+        .ok_or_else(|| format!("Book {} has no file", book_id))?;
+    let chapters = Chapters::open(&path)?;
+    let from = from_position.unwrap_or_default();
+
+    let (control, controls) = mpsc::channel();
+    let id = NEXT_PLAYBACK.fetch_add(1, Ordering::Relaxed);
+    {
+        let mut playback = player
+            .0
+            .lock()
+            .map_err(|_| "TTS lock poisoned".to_string())?;
+        if let Some(previous) = playback.take() {
+            let _ = previous.control.send(Control::Stop);
+        }
+        *playback = Some(Playback {
+            id,
+            book_id,
+            paused: false,
+            control,
+        });
+    }
+
+    info!("Reading book {} aloud from {:?}", book_id, from);
+
+    thread::Builder::new()
+        .name("tts".into())
+        .spawn(move || {
+            let (reason, position, message) = play(&app, book_id, &chapters, from, &controls);
+            if let Some(message) = &message {
+                warn!("TTS stopped: {}", message);
+            }
+
+            // Clear the slot unless a newer playback already took it
+            let player = app.state::<TtsPlayer>();
+            if let Ok(mut playback) = player.0.lock() {
+                if playback.as_ref().is_some_and(|p| p.id == id) {
+                    *playback = None;
+                }
+            }
+
+            let _ = app.emit(
+                "tts-stopped",
+                StoppedPayload {
+                    book_id,
+                    reason,
+                    position,
+                    message,
+                },
+            );
+        })
+        .map_err(|e| format!("Failed to start TTS: {}", e))?;
+
+    Ok(())
+}
+
+/// Jump by a sentence or paragraph, crossing chapter boundaries
+#[tauri::command]
+pub fn tts_skip(
+    player: State<'_, TtsPlayer>,
+    unit: SkipUnit,
+    direction: SkipDirection,
+) -> Result<(), String> {
+    player.send(Control::Skip(unit, direction))
+}
+
+/// Pause reading aloud; `tts_resume` repeats the interrupted sentence
+#[tauri::command]
+pub fn tts_pause(player: State<'_, TtsPlayer>) -> Result<(), String> {
+    player.set_paused(true).map(|_| ())
+}
+
+#[tauri::command]
+pub fn tts_resume(player: State<'_, TtsPlayer>) -> Result<(), String> {
+    player.set_paused(false).map(|_| ())
+}
+
+/// Stop after the sentence being spoken once `minutes` have passed. `None`
+/// or 0 cancels the timer. Returns when the timer ends.
+#[tauri::command]
+pub fn tts_sleep_timer(
+    player: State<'_, TtsPlayer>,
+    minutes: Option<u32>,
+) -> Result<Option<String>, String> {
+    let minutes = minutes.filter(|&m| m > 0);
+    let deadline = minutes.map(|m| Instant::now() + Duration::from_secs(u64::from(m) * 60));
+    player.send(Control::Sleep(deadline))?;
+
+    Ok(minutes.map(|m| (Utc::now() + chrono::Duration::minutes(i64::from(m))).to_rfc3339()))
+}
+
+/// Stop reading aloud
+#[tauri::command]
+pub fn tts_stop(player: State<'_, TtsPlayer>) -> Result<(), String> {
+    let playback = player
+        .0
+        .lock()
+        .map_err(|_| "TTS lock poisoned".to_string())?
+        .take();
+    if let Some(playback) = playback {
+        info!("Stopping TTS for book {}", playback.book_id);
+        let _ = playback.control.send(Control::Stop);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_sentences_around_abbreviations() {
+        assert_eq!(
+            split_sentences("Mr. Smith met Dr. J. R. Jones. They talked! Then what? Nothing."),
+            [
+                "Mr. Smith met Dr. J. R. Jones.",
+                "They talked!",
+                "Then what?",
+                "Nothing."
+            ]
+        );
+        assert_eq!(
+            split_sentences("\"Stop.\" She left. and kept going"),
+            ["\"Stop.\"", "She left. and kept going"]
+        );
+    }
+
+    #[test]
+    fn strips_footnote_markers() {
+        assert_eq!(
+            strip_footnote_markers("As shown[12] before.3 Next¹ line[4, 5]."),
+            "As shown before. Next line."
+        );
+        // Numbers that are part of the text stay
+        assert_eq!(
+            strip_footnote_markers("In 1984, 3.5 people [sic] came."),
+            "In 1984, 3.5 people [sic] came."
+        );
+    }
+
+    #[test]
+    fn drops_page_numbers() {
+        let text = "First paragraph.\n42\nxiv\nPage 7\nSecond paragraph. Two sentences.";
+        assert_eq!(
+            segment(text),
+            [
+                vec!["First paragraph."],
+                vec!["Second paragraph.", "Two sentences."]
+            ]
+        );
+    }
+}
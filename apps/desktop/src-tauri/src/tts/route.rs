@@ -0,0 +1,354 @@
+// Read Master Desktop - Audio Route Monitor
+//
+// Pauses read-aloud when the audio output falls back to the built-in
+// speakers, e.g. because headphones were unplugged or a Bluetooth headset
+// disconnected, and emits `tts-auto-paused`. Controlled by the
+// `tts.autoPauseOnDisconnect` setting (on by default).
+//
+// This polls rather than subscribing to OS notifications, and only while
+// a book is being read aloud. The default output device is read from
+// CoreAudio on macOS, the MMDevice API on Windows, and PulseAudio
+// (`pactl`, also served by PipeWire) on Linux.
+
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use super::{pause_speaking, TtsPlayer};
+use crate::profiles::ProfileStoreExt;
+
+const SETTING_KEY: &str = "tts.autoPauseOnDisconnect";
+
+/// How often the output device is checked during playback
+const TICK: Duration = Duration::from_secs(1);
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Where audio is going, as far as auto-pause cares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Route {
+    /// The machine's own speakers
+    Speakers,
+    /// Headphones, headsets, and any other external output
+    External,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AutoPausedPayload {
+    book_id: i64,
+}
+
+// ============================================================================
+// macOS
+// ============================================================================
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::c_void;
+
+    use super::Route;
+
+    #[repr(C)]
+    struct PropertyAddress {
+        selector: u32,
+        scope: u32,
+        element: u32,
+    }
+
+    #[link(name = "CoreAudio", kind = "framework")]
+    extern "C" {
+        fn AudioObjectGetPropertyData(
+            object: u32,
+            address: *const PropertyAddress,
+            qualifier_size: u32,
+            qualifier: *const c_void,
+            data_size: *mut u32,
+            data: *mut c_void,
+        ) -> i32;
+    }
+
+    const fn fourcc(code: &[u8; 4]) -> u32 {
+        u32::from_be_bytes(*code)
+    }
+
+    const SYSTEM_OBJECT: u32 = 1;
+    const DEFAULT_OUTPUT_DEVICE: u32 = fourcc(b"dOut");
+    const TRANSPORT_TYPE: u32 = fourcc(b"tran");
+    const DATA_SOURCE: u32 = fourcc(b"ssrc");
+    const SCOPE_GLOBAL: u32 = fourcc(b"glob");
+    const SCOPE_OUTPUT: u32 = fourcc(b"outp");
+    const TRANSPORT_BUILT_IN: u32 = fourcc(b"bltn");
+    const SOURCE_HEADPHONES: u32 = fourcc(b"hdpn");
+
+    fn property(object: u32, selector: u32, scope: u32) -> Option<u32> {
+        let address = PropertyAddress {
+            selector,
+            scope,
+            element: 0,
+        };
+        let mut value = 0u32;
+        let mut size = std::mem::size_of::<u32>() as u32;
+        let status = unsafe {
+            AudioObjectGetPropertyData(
+                object,
+                &address,
+                0,
+                std::ptr::null(),
+                &mut size,
+                &mut value as *mut u32 as *mut c_void,
+            )
+        };
+        (status == 0).then_some(value)
+    }
+
+    /// Built-in devices report headphones through their data source;
+    /// everything else (USB, Bluetooth, HDMI, AirPlay) is external
+    pub fn current_route() -> Option<Route> {
+        let device = property(SYSTEM_OBJECT, DEFAULT_OUTPUT_DEVICE, SCOPE_GLOBAL)?;
+        if property(device, TRANSPORT_TYPE, SCOPE_GLOBAL)? != TRANSPORT_BUILT_IN {
+            return Some(Route::External);
+        }
+        match property(device, DATA_SOURCE, SCOPE_OUTPUT) {
+            Some(SOURCE_HEADPHONES) => Some(Route::External),
+            _ => Some(Route::Speakers),
+        }
+    }
+
+    pub fn init_thread() {}
+}
+
+// ============================================================================
+// Windows
+// ============================================================================
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use windows::Win32::Media::Audio::{
+        eConsole, eRender, IMMDeviceEnumerator, MMDeviceEnumerator, PKEY_AudioEndpoint_FormFactor,
+    };
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CLSCTX_ALL, COINIT_MULTITHREADED, STGM_READ,
+    };
+
+    use super::Route;
+
+    /// `EndpointFormFactor::Speakers`
+    const FORM_FACTOR_SPEAKERS: u32 = 1;
+
+    /// Judge the default render endpoint by its form factor
+    pub fn current_route() -> Option<Route> {
+        unsafe {
+            let devices: IMMDeviceEnumerator =
+                CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL).ok()?;
+            let device = devices.GetDefaultAudioEndpoint(eRender, eConsole).ok()?;
+            let props = device.OpenPropertyStore(STGM_READ).ok()?;
+            let form_factor = props.GetValue(&PKEY_AudioEndpoint_FormFactor).ok()?;
+            match u32::try_from(&form_factor).ok()? {
+                FORM_FACTOR_SPEAKERS => Some(Route::Speakers),
+                _ => Some(Route::External),
+            }
+        }
+    }
+
+    /// COM stays initialized for the monitor thread's lifetime
+    pub fn init_thread() {
+        let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+    }
+}
+
+// ============================================================================
+// Linux
+// ============================================================================
+
+/// Route of the sink named `default` in `pactl list sinks` output
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn parse_sinks(listing: &str, default: &str) -> Option<Route> {
+    let mut name = None;
+    let mut port = None;
+    for line in listing.lines().chain(std::iter::once("Sink #")) {
+        let line = line.trim();
+        if line.starts_with("Sink #") {
+            if name == Some(default) {
+                return Some(classify_sink(default, port));
+            }
+            name = None;
+            port = None;
+        } else if let Some(value) = line.strip_prefix("Name:") {
+            name = Some(value.trim());
+        } else if let Some(value) = line.strip_prefix("Active Port:") {
+            port = Some(value.trim());
+        }
+    }
+    None
+}
+
+#[cfg_attr(any(target_os = "macos", target_os = "windows"), allow(dead_code))]
+fn classify_sink(name: &str, port: Option<&str>) -> Route {
+    let name = name.to_lowercase();
+    let port = port.unwrap_or_default().to_lowercase();
+    let external = name.starts_with("bluez")
+        || name.contains("usb")
+        || name.contains("hdmi")
+        || port.contains("headphone")
+        || port.contains("headset");
+    if external {
+        Route::External
+    } else {
+        Route::Speakers
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+mod platform {
+    use std::process::Command;
+
+    use super::{parse_sinks, Route};
+
+    fn pactl(args: &[&str]) -> Option<String> {
+        let output = Command::new("pactl")
+            .args(args)
+            .env("LC_ALL", "C")
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    pub fn current_route() -> Option<Route> {
+        let default = pactl(&["get-default-sink"])?;
+        parse_sinks(&pactl(&["list", "sinks"])?, default.trim())
+    }
+
+    pub fn init_thread() {}
+}
+
+// ============================================================================
+// Monitor
+// ============================================================================
+
+fn enabled<R: Runtime>(app: &AppHandle<R>) -> bool {
+    app.profile_store("settings.json")
+        .ok()
+        .and_then(|s| s.get(SETTING_KEY))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true)
+}
+
+/// Start the route monitor thread. Needs `TtsPlayer` managed.
+pub fn init<R: Runtime>(app: &AppHandle<R>) {
+    let app = app.clone();
+
+    let spawned = thread::Builder::new()
+        .name("audio-route".into())
+        .spawn(move || {
+            platform::init_thread();
+
+            // Route when playback was last checked; None while idle
+            let mut last = None;
+            loop {
+                thread::sleep(TICK);
+
+                let player = app.state::<TtsPlayer>();
+                if !player.is_speaking() || !enabled(&app) {
+                    last = None;
+                    continue;
+                }
+
+                let Some(route) = platform::current_route() else {
+                    continue;
+                };
+                let fell_back = last == Some(Route::External) && route == Route::Speakers;
+                last = Some(route);
+                if !fell_back {
+                    continue;
+                }
+
+                let book_id = player
+                    .0
+                    .lock()
+                    .ok()
+                    .and_then(|p| p.as_ref().map(|p| p.book_id));
+                let Some(book_id) = book_id else {
+                    continue;
+                };
+                if pause_speaking(&app) {
+                    info!(
+                        "Audio moved to the speakers; paused reading book {}",
+                        book_id
+                    );
+                    let _ = app.emit("tts-auto-paused", AutoPausedPayload { book_id });
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start audio route monitor: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SINKS: &str = "\
+Sink #0
+\tState: SUSPENDED
+\tName: alsa_output.pci-0000_00_1f.3.analog-stereo
+\tDescription: Built-in Audio Analog Stereo
+\tPorts:
+\t\tanalog-output-speaker: Speakers (type: Speaker, priority: 10000)
+\t\tanalog-output-headphones: Headphones (type: Headphones, priority: 9900)
+\tActive Port: analog-output-headphones
+Sink #1
+\tState: RUNNING
+\tName: bluez_output.00_1B_66_AA_BB_CC.1
+\tActive Port: headphone-output
+";
+
+    #[test]
+    fn finds_the_default_sinks_route() {
+        assert_eq!(
+            parse_sinks(SINKS, "alsa_output.pci-0000_00_1f.3.analog-stereo"),
+            Some(Route::External)
+        );
+        let unplugged = SINKS.replace(
+            "Active Port: analog-output-headphones",
+            "Active Port: analog-output-speaker",
+        );
+        assert_eq!(
+            parse_sinks(&unplugged, "alsa_output.pci-0000_00_1f.3.analog-stereo"),
+            Some(Route::Speakers)
+        );
+        assert_eq!(parse_sinks(SINKS, "missing"), None);
+    }
+
+    #[test]
+    fn classifies_sinks() {
+        assert_eq!(
+            classify_sink("bluez_output.00_1B_66_AA_BB_CC.1", None),
+            Route::External
+        );
+        assert_eq!(
+            classify_sink("alsa_output.usb-Generic_USB_Audio-00.analog-stereo", None),
+            Route::External
+        );
+        assert_eq!(
+            classify_sink(
+                "alsa_output.pci.analog-stereo",
+                Some("analog-output-speaker")
+            ),
+            Route::Speakers
+        );
+        assert_eq!(
+            classify_sink("alsa_output.pci.analog-stereo", None),
+            Route::Speakers
+        );
+    }
+}
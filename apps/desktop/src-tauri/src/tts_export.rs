@@ -0,0 +1,390 @@
+// Read Master Desktop - Audio Export
+//
+// Renders a chapter to an audio file for listening away from the app. The
+// OS speech engine's file output is used one paragraph at a time (`say` on
+// macOS, System.Speech on Windows, espeak-ng on Linux), the resulting WAV
+// clips are joined, and the whole is written as WAV or encoded to MP3.
+// Each paragraph emits `tts-export-progress`. Exports run as jobs and can
+// be cancelled between paragraphs with `cancel_job`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Runtime};
+
+use crate::epub::EpubArchive;
+use crate::jobs::{Job, JobKind};
+use crate::{language, tts};
+
+/// MP3 bitrate; plenty for speech
+const MP3_BITRATE: mp3lame_encoder::Bitrate = mp3lame_encoder::Bitrate::Kbps64;
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    Wav,
+    Mp3,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportProgress {
+    path: String,
+    chapter_index: usize,
+    processed_paragraphs: usize,
+    total_paragraphs: usize,
+}
+
+/// 16-bit PCM audio
+#[derive(Debug, Clone, PartialEq)]
+struct Pcm {
+    channels: u16,
+    sample_rate: u32,
+    samples: Vec<i16>,
+}
+
+// ============================================================================
+// WAV
+// ============================================================================
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Samples from a 16-bit PCM WAV file. A data chunk claiming more bytes
+/// than the file holds (written while streaming) is read to the end.
+fn parse_wav(data: &[u8]) -> Result<Pcm, String> {
+    if data.get(0..4) != Some(b"RIFF") || data.get(8..12) != Some(b"WAVE") {
+        return Err("Speech engine output is not a WAV file".to_string());
+    }
+
+    let mut format = None;
+    let mut at = 12;
+    while let (Some(id), Some(size)) = (data.get(at..at + 4), read_u32(data, at + 4)) {
+        let body = at + 8;
+        match id {
+            b"fmt " => {
+                let tag = read_u16(data, body);
+                let channels = read_u16(data, body + 2);
+                let rate = read_u32(data, body + 4);
+                let bits = read_u16(data, body + 14);
+                format = match (tag, channels, rate, bits) {
+                    (Some(1), Some(channels), Some(rate), Some(16)) => Some((channels, rate)),
+                    _ => return Err("Speech engine output is not 16-bit PCM".to_string()),
+                };
+            }
+            b"data" => {
+                let (channels, sample_rate) =
+                    format.ok_or_else(|| "WAV data before its format".to_string())?;
+                let end = body.saturating_add(size as usize).min(data.len());
+                let samples = data[body..end]
+                    .chunks_exact(2)
+                    .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                    .collect();
+                return Ok(Pcm {
+                    channels,
+                    sample_rate,
+                    samples,
+                });
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        at = body.saturating_add(size as usize + (size as usize & 1));
+    }
+    Err("Speech engine output has no audio".to_string())
+}
+
+fn encode_wav(pcm: &Pcm) -> Vec<u8> {
+    let data_len = (pcm.samples.len() * 2) as u32;
+    let block_align = pcm.channels * 2;
+
+    let mut out = Vec::with_capacity(44 + data_len as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&pcm.channels.to_le_bytes());
+    out.extend_from_slice(&pcm.sample_rate.to_le_bytes());
+    out.extend_from_slice(&(pcm.sample_rate * u32::from(block_align)).to_le_bytes());
+    out.extend_from_slice(&block_align.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    for sample in &pcm.samples {
+        out.extend_from_slice(&sample.to_le_bytes());
+    }
+    out
+}
+
+fn encode_mp3(pcm: &Pcm) -> Result<Vec<u8>, String> {
+    use mp3lame_encoder::{Builder, FlushNoGap, InterleavedPcm, MonoPcm, Quality};
+
+    let mut builder = Builder::new().ok_or_else(|| "Failed to start MP3 encoder".to_string())?;
+    let channels = u8::try_from(pcm.channels).map_err(|_| "Too many audio channels".to_string())?;
+    builder
+        .set_num_channels(channels)
+        .and_then(|_| builder.set_sample_rate(pcm.sample_rate))
+        .and_then(|_| builder.set_brate(MP3_BITRATE))
+        .and_then(|_| builder.set_quality(Quality::Good))
+        .map_err(|e| format!("Failed to configure MP3 encoder: {}", e))?;
+    let mut encoder = builder
+        .build()
+        .map_err(|e| format!("Failed to start MP3 encoder: {}", e))?;
+
+    let mut out = Vec::with_capacity(mp3lame_encoder::max_required_buffer_size(pcm.samples.len()));
+    let encoded = match pcm.channels {
+        1 => encoder.encode_to_vec(MonoPcm(&pcm.samples), &mut out),
+        _ => encoder.encode_to_vec(InterleavedPcm(&pcm.samples), &mut out),
+    };
+    encoded.map_err(|e| format!("Failed to encode MP3: {}", e))?;
+    encoder
+        .flush_to_vec::<FlushNoGap>(&mut out)
+        .map_err(|e| format!("Failed to encode MP3: {}", e))?;
+    Ok(out)
+}
+
+// ============================================================================
+// Synthesis
+// ============================================================================
+
+/// Speak the text in `text_file` into `wav` with the OS speech engine
+fn synthesize(text_file: &Path, wav: &Path, lang: Option<&str>) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    let output = {
+        let _ = lang;
+        Command::new("say")
+            .arg("-o")
+            .arg(wav)
+            .args(["--file-format=WAVE", "--data-format=LEI16@22050", "-f"])
+            .arg(text_file)
+            .output()
+    };
+
+    #[cfg(target_os = "windows")]
+    let output = {
+        let _ = lang;
+        let quote = |path: &Path| path.to_string_lossy().replace('\'', "''");
+        let script = format!(
+            "Add-Type -AssemblyName System.Speech; \
+             $s = New-Object System.Speech.Synthesis.SpeechSynthesizer; \
+             $s.SetOutputToWaveFile('{}'); \
+             $s.Speak([IO.File]::ReadAllText('{}')); \
+             $s.Dispose()",
+            quote(wav),
+            quote(text_file)
+        );
+        Command::new("powershell")
+            .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+            .output()
+    };
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let output = {
+        let mut command = Command::new("espeak-ng");
+        command.arg("-w").arg(wav).arg("-f").arg(text_file);
+        if let Some(lang) = lang {
+            command.args(["-v", lang]);
+        }
+        command.output()
+    };
+
+    let output = output.map_err(|e| format!("Failed to run the speech engine: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Speech engine failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Append `clip` to `pcm`, which takes the format of the first clip
+fn append(pcm: &mut Option<Pcm>, clip: Pcm) -> Result<(), String> {
+    match pcm {
+        None => *pcm = Some(clip),
+        Some(pcm) if pcm.channels == clip.channels && pcm.sample_rate == clip.sample_rate => {
+            pcm.samples.extend(clip.samples);
+        }
+        Some(_) => return Err("Speech engine changed audio format mid-chapter".to_string()),
+    }
+    Ok(())
+}
+
+fn export<R: Runtime>(
+    app: &AppHandle<R>,
+    path: &str,
+    chapter_index: usize,
+    out_path: &str,
+    format: AudioFormat,
+    scratch: &Path,
+    job: &Job<R>,
+) -> Result<(), String> {
+    let chapter = EpubArchive::open(path)
+        .map_err(|e| e.to_string())?
+        .chapter_text(chapter_index)?;
+    let paragraphs: Vec<String> = tts::segment(&chapter.text)
+        .into_iter()
+        .map(|sentences| sentences.join(" "))
+        .collect();
+    if paragraphs.is_empty() {
+        return Err(format!("Chapter {} has no text to read", chapter_index));
+    }
+
+    let lang = language::detect(path)
+        .ok()
+        .map(|detection| language::primary_subtag(&detection.code).to_string());
+
+    std::fs::create_dir_all(scratch)
+        .map_err(|e| format!("Failed to create temporary folder: {}", e))?;
+    let text_file = scratch.join("paragraph.txt");
+    let wav_file = scratch.join("paragraph.wav");
+
+    let mut pcm = None;
+    for (index, paragraph) in paragraphs.iter().enumerate() {
+        if job.is_cancelled() {
+            return Err("Audio export cancelled".to_string());
+        }
+        job.progress(index as u64, paragraphs.len() as u64, None);
+        let _ = app.emit(
+            "tts-export-progress",
+            ExportProgress {
+                path: path.to_string(),
+                chapter_index,
+                processed_paragraphs: index,
+                total_paragraphs: paragraphs.len(),
+            },
+        );
+
+        std::fs::write(&text_file, paragraph)
+            .map_err(|e| format!("Failed to write temporary file: {}", e))?;
+        synthesize(&text_file, &wav_file, lang.as_deref())?;
+        let data = std::fs::read(&wav_file)
+            .map_err(|e| format!("Failed to read synthesized audio: {}", e))?;
+        append(&mut pcm, parse_wav(&data)?)?;
+    }
+    let pcm = pcm.ok_or_else(|| "No audio was produced".to_string())?;
+
+    let data = match format {
+        AudioFormat::Wav => encode_wav(&pcm),
+        AudioFormat::Mp3 => encode_mp3(&pcm)?,
+    };
+    std::fs::write(out_path, data).map_err(|e| format!("Failed to write {}: {}", out_path, e))?;
+
+    let _ = app.emit(
+        "tts-export-progress",
+        ExportProgress {
+            path: path.to_string(),
+            chapter_index,
+            processed_paragraphs: paragraphs.len(),
+            total_paragraphs: paragraphs.len(),
+        },
+    );
+    Ok(())
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Read a chapter (spine index) of an EPUB aloud into a WAV or MP3 file
+#[tauri::command]
+pub async fn export_chapter_audio<R: Runtime>(
+    app: AppHandle<R>,
+    path: String,
+    chapter_index: usize,
+    out_path: String,
+    format: AudioFormat,
+) -> Result<(), String> {
+    info!(
+        "Exporting chapter {} of {} as {:?} to {}",
+        chapter_index, path, format, out_path
+    );
+
+    let job = Job::start(
+        &app,
+        JobKind::AudioExport,
+        &out_path,
+        &format!("Exporting chapter {} to {}", chapter_index, out_path),
+    )?;
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let scratch: PathBuf =
+            std::env::temp_dir().join(format!("readmaster-tts-{}", uuid::Uuid::new_v4()));
+        let result = export(
+            &app,
+            &path,
+            chapter_index,
+            &out_path,
+            format,
+            &scratch,
+            &job,
+        );
+        let _ = std::fs::remove_dir_all(&scratch);
+        job.finish(&result);
+        result
+    })
+    .await
+    .map_err(|e| format!("Audio export task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pcm(samples: &[i16]) -> Pcm {
+        Pcm {
+            channels: 1,
+            sample_rate: 22050,
+            samples: samples.to_vec(),
+        }
+    }
+
+    #[test]
+    fn wav_round_trips() {
+        let audio = pcm(&[0, 1, -1, i16::MAX, i16::MIN]);
+        assert_eq!(parse_wav(&encode_wav(&audio)).unwrap(), audio);
+    }
+
+    #[test]
+    fn skips_unknown_chunks_and_clamps_streamed_sizes() {
+        let mut wav = encode_wav(&pcm(&[5, 6, 7]));
+        // A LIST chunk (odd-sized, so padded) between fmt and data
+        let list = [b"LIST".as_slice(), &3u32.to_le_bytes(), b"abc\0"].concat();
+        wav.splice(36..36, list);
+        // Data size left at its streaming placeholder
+        let data_size = wav.len() - 6 - 4;
+        wav[data_size..data_size + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert_eq!(parse_wav(&wav).unwrap().samples, [5, 6, 7]);
+    }
+
+    #[test]
+    fn rejects_non_pcm_audio() {
+        assert!(parse_wav(b"ID3\x04not a wav").is_err());
+    }
+
+    #[test]
+    fn joins_clips_of_the_same_format() {
+        let mut joined = None;
+        append(&mut joined, pcm(&[1, 2])).unwrap();
+        append(&mut joined, pcm(&[3])).unwrap();
+        assert_eq!(joined.unwrap().samples, [1, 2, 3]);
+
+        let mut stereo = pcm(&[4]);
+        stereo.channels = 2;
+        let mut mixed = Some(pcm(&[1]));
+        assert!(append(&mut mixed, stereo).is_err());
+    }
+}
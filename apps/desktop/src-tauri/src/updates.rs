@@ -0,0 +1,438 @@
+// Read Master Desktop - Update Downloads
+//
+// Downloads updates with resume support, for installers too large to fetch
+// in one go over a flaky connection. The artifact is written to a partial
+// file in the app cache as it arrives; a dropped connection is retried
+// with an HTTP range request from the bytes already on disk, and so is the
+// next attempt after the app was quit mid-download.
+//
+// The updater plugin only verifies signatures on its own downloads, so the
+// finished file is checked here against the release's minisign signature
+// and the updater public key before it is handed to `install`. A file that
+// fails verification is deleted; an interrupted one is always kept.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use log::{info, warn};
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::StatusCode;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+use tauri_plugin_updater::{Update, UpdaterExt};
+
+use crate::error::AppError;
+use crate::jobs::{Job, JobKind, JobRegistry};
+use crate::{data_location, net, safe_mode};
+
+const UPDATES_DIR: &str = "updates";
+
+/// Connection attempts per download, each resuming where the last stopped
+const MAX_ATTEMPTS: u32 = 5;
+
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Whole-request timeout; installers can take a while on slow links
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Least time between `update-progress` events
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+// ============================================================================
+// Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateProgress<'a> {
+    version: &'a str,
+    downloaded: u64,
+    total: Option<u64>,
+    /// Bytes already on disk when this attempt started
+    resumed_from: u64,
+}
+
+/// How to treat a response to a download request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    /// The server honored the range; append from `offset`
+    Append { offset: u64, total: Option<u64> },
+    /// The whole file is coming; start over
+    Restart { total: Option<u64> },
+    /// The partial file already holds everything
+    Complete,
+}
+
+// ============================================================================
+// Downloading
+// ============================================================================
+
+/// `bytes 100-199/1000` -> (100, Some(1000)); `*` totals are unknown
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let range = value.trim().strip_prefix("bytes ")?;
+    let (span, total) = range.split_once('/')?;
+    let (start, _) = span.split_once('-')?;
+    Some((start.trim().parse().ok()?, total.trim().parse().ok()))
+}
+
+fn resume_plan(
+    status: StatusCode,
+    partial: u64,
+    content_range: Option<&str>,
+    content_length: Option<u64>,
+) -> Result<Resume, String> {
+    match status {
+        StatusCode::PARTIAL_CONTENT => match content_range.and_then(parse_content_range) {
+            Some((start, total)) if start == partial => Ok(Resume::Append {
+                offset: start,
+                total,
+            }),
+            // A range we didn't ask for can't be stitched onto the file
+            _ => Err("Server returned an unexpected byte range".to_string()),
+        },
+        // Asked for bytes past the end: everything is already here
+        StatusCode::RANGE_NOT_SATISFIABLE if partial > 0 => Ok(Resume::Complete),
+        status if status.is_success() => Ok(Resume::Restart {
+            total: content_length,
+        }),
+        status => Err(format!("Download failed: HTTP {}", status)),
+    }
+}
+
+/// Partial file for `url`; a different artifact gets a different file
+fn partial_path(dir: &Path, url: &str) -> PathBuf {
+    let digest = Sha256::digest(url.as_bytes());
+    let name: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    dir.join(format!("{}.part", name))
+}
+
+/// Remove partial files of other artifacts, e.g. a superseded version
+fn remove_stale_partials(dir: &Path, keep: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path != keep && path.extension().is_some_and(|ext| ext == "part") {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+/// One connection's worth of downloading. Returns whether the file is
+/// complete; an interrupted transfer leaves what arrived on disk.
+async fn download_attempt<R: Runtime>(
+    app: &AppHandle<R>,
+    job: &Job<R>,
+    update: &Update,
+    path: &Path,
+) -> Result<bool, String> {
+    let partial = fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+    let client = net::client_builder()
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+
+    let mut request = client.get(update.download_url.clone());
+    if partial > 0 {
+        request = request.header(RANGE, format!("bytes={}-", partial));
+    }
+    let mut response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to download update: {}", e))?;
+
+    let content_range = response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let plan = resume_plan(
+        response.status(),
+        partial,
+        content_range.as_deref(),
+        response.content_length(),
+    )?;
+
+    let (mut file, offset, total) = match plan {
+        Resume::Complete => return Ok(true),
+        Resume::Append { offset, total } => {
+            info!("Resuming update download at {} bytes", offset);
+            let file = OpenOptions::new()
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Failed to open partial update: {}", e))?;
+            (file, offset, total)
+        }
+        Resume::Restart { total } => {
+            let file =
+                File::create(path).map_err(|e| format!("Failed to create update file: {}", e))?;
+            (file, 0, total)
+        }
+    };
+
+    let mut downloaded = offset;
+    let mut last_emit = Instant::now() - PROGRESS_INTERVAL;
+    loop {
+        if job.is_cancelled() {
+            // Everything written so far stays for the next attempt
+            return Err("Cancelled".to_string());
+        }
+        let chunk = match response.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => return Err(format!("Update download interrupted: {}", e)),
+        };
+        // Written straight through, so the file on disk is always a
+        // prefix of the artifact, however the app stops
+        file.write_all(&chunk)
+            .map_err(|e| format!("Failed to write update: {}", e))?;
+        downloaded += chunk.len() as u64;
+
+        if last_emit.elapsed() >= PROGRESS_INTERVAL {
+            last_emit = Instant::now();
+            job.progress(downloaded, total.unwrap_or(0), None);
+            let _ = app.emit(
+                "update-progress",
+                UpdateProgress {
+                    version: &update.version,
+                    downloaded,
+                    total,
+                    resumed_from: offset,
+                },
+            );
+        }
+    }
+
+    file.sync_all()
+        .map_err(|e| format!("Failed to write update: {}", e))?;
+    match total {
+        Some(total) if downloaded < total => Err(format!(
+            "Update download ended early ({} of {} bytes)",
+            downloaded, total
+        )),
+        _ => Ok(true),
+    }
+}
+
+/// Download `update` to `path`, retrying and resuming on failure
+async fn download<R: Runtime>(
+    app: &AppHandle<R>,
+    job: &Job<R>,
+    update: &Update,
+    path: &Path,
+) -> Result<(), String> {
+    let mut last_error = String::new();
+    for attempt in 0..MAX_ATTEMPTS {
+        if attempt > 0 {
+            warn!(
+                "Update download failed ({}), retrying (attempt {})",
+                last_error,
+                attempt + 1
+            );
+            tokio::time::sleep(RETRY_DELAY * attempt).await;
+        }
+        match download_attempt(app, job, update, path).await {
+            Ok(_) => return Ok(()),
+            Err(e) if job.is_cancelled() => return Err(e),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+// ============================================================================
+// Verification
+// ============================================================================
+
+fn decode_base64_text(value: &str) -> Result<String, String> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(value.trim())
+        .map_err(|e| format!("Invalid base64: {}", e))?;
+    String::from_utf8(bytes).map_err(|e| format!("Invalid key or signature text: {}", e))
+}
+
+/// Check `data` against a base64 minisign `signature` made with the key
+/// `pubkey`, both in the updater's encoding
+fn verify_signature(data: &[u8], signature: &str, pubkey: &str) -> Result<(), String> {
+    let pubkey = minisign_verify::PublicKey::decode(&decode_base64_text(pubkey)?)
+        .map_err(|e| format!("Invalid update public key: {}", e))?;
+    let signature = minisign_verify::Signature::decode(&decode_base64_text(signature)?)
+        .map_err(|e| format!("Invalid update signature: {}", e))?;
+    pubkey
+        .verify(data, &signature, true)
+        .map_err(|e| format!("Update signature doesn't match: {}", e))
+}
+
+fn updater_pubkey<R: Runtime>(app: &AppHandle<R>) -> Option<String> {
+    app.config()
+        .plugins
+        .0
+        .get("updater")
+        .and_then(|updater| updater.get("pubkey"))
+        .and_then(|pubkey| pubkey.as_str())
+        .filter(|pubkey| !pubkey.is_empty())
+        .map(str::to_string)
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+/// Stop a running update download, keeping what has arrived. Called when
+/// the app quits.
+pub fn abort<R: Runtime>(app: &AppHandle<R>) {
+    let Some(registry) = app.try_state::<JobRegistry>() else {
+        return;
+    };
+    if let Some(id) = registry.find(JobKind::UpdateDownload, "") {
+        info!("Stopping update download; the partial file is kept");
+        registry.cancel(id);
+    }
+}
+
+/// Download the available update, resuming any earlier partial download,
+/// verify its signature, and install it. Emits `update-progress` while
+/// downloading. Returns the installed version, or `None` when up to date.
+#[tauri::command]
+pub async fn download_and_install_update<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<Option<String>, AppError> {
+    if safe_mode::is_active() {
+        return Err(AppError::unavailable_in_safe_mode(
+            "Updates are off in safe mode",
+        ));
+    }
+    let updater = app
+        .updater()
+        .map_err(|e| AppError::tauri("Updater not available", e))?;
+    let Some(update) = updater
+        .check()
+        .await
+        .map_err(|e| AppError::network("Failed to check for updates", e))?
+    else {
+        return Ok(None);
+    };
+    let pubkey = updater_pubkey(&app)
+        .ok_or_else(|| AppError::from("Update signing key is not configured".to_string()))?;
+
+    let dir = data_location::cache_dir(&app)
+        .map_err(AppError::from)?
+        .join(UPDATES_DIR);
+    fs::create_dir_all(&dir)?;
+    let path = partial_path(&dir, update.download_url.as_str());
+    remove_stale_partials(&dir, &path);
+
+    let job = Job::start(
+        &app,
+        JobKind::UpdateDownload,
+        "",
+        &format!("Downloading update {}", update.version),
+    )?;
+    let result = download(&app, &job, &update, &path).await;
+    if job.is_cancelled() {
+        job.finish_cancelled();
+        return Err(AppError::from("Update download cancelled".to_string()));
+    }
+    job.finish(&result);
+    result.map_err(|e| AppError::network("Failed to download update", e))?;
+
+    let data = fs::read(&path)?;
+    if let Err(e) = verify_signature(&data, &update.signature, &pubkey) {
+        // A corrupt file would fail the same way on every resume
+        let _ = fs::remove_file(&path);
+        return Err(AppError::CorruptFile {
+            message: "Downloaded update failed verification".to_string(),
+            details: Some(e),
+        });
+    }
+    info!(
+        "Update {} verified (sha256 {:x})",
+        update.version,
+        Sha256::digest(&data)
+    );
+
+    update
+        .install(&data)
+        .map_err(|e| AppError::tauri("Failed to install update", e))?;
+    let _ = fs::remove_file(&path);
+    info!("Update {} installed", update.version);
+    Ok(Some(update.version.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_content_ranges() {
+        assert_eq!(
+            parse_content_range("bytes 100-199/1000"),
+            Some((100, Some(1000)))
+        );
+        assert_eq!(parse_content_range("bytes 5-9/*"), Some((5, None)));
+        assert_eq!(parse_content_range("items 1-2/3"), None);
+    }
+
+    #[test]
+    fn plans_resume_from_the_response() {
+        assert_eq!(
+            resume_plan(
+                StatusCode::PARTIAL_CONTENT,
+                100,
+                Some("bytes 100-999/1000"),
+                Some(900)
+            ),
+            Ok(Resume::Append {
+                offset: 100,
+                total: Some(1000)
+            })
+        );
+        // Range ignored: the whole file comes again
+        assert_eq!(
+            resume_plan(StatusCode::OK, 100, None, Some(1000)),
+            Ok(Resume::Restart { total: Some(1000) })
+        );
+        assert_eq!(
+            resume_plan(StatusCode::RANGE_NOT_SATISFIABLE, 1000, None, None),
+            Ok(Resume::Complete)
+        );
+        assert!(resume_plan(
+            StatusCode::PARTIAL_CONTENT,
+            100,
+            Some("bytes 0-999/1000"),
+            None
+        )
+        .is_err());
+        assert!(resume_plan(StatusCode::NOT_FOUND, 0, None, None).is_err());
+    }
+
+    #[test]
+    fn keeps_one_partial_file_per_artifact() {
+        let dir = std::env::temp_dir().join(format!("rm-updates-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let current = partial_path(&dir, "https://example.com/app-1.1.0.msi");
+        let stale = partial_path(&dir, "https://example.com/app-1.0.9.msi");
+        assert_ne!(current, stale);
+
+        fs::write(&current, b"new").unwrap();
+        fs::write(&stale, b"old").unwrap();
+        remove_stale_partials(&dir, &current);
+        assert!(current.exists());
+        assert!(!stale.exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rejects_bad_signatures() {
+        let encode = |s: &str| base64::engine::general_purpose::STANDARD.encode(s);
+        assert!(
+            verify_signature(b"data", &encode("not a signature"), &encode("nor a key")).is_err()
+        );
+        assert!(verify_signature(b"data", "%%%", "%%%").is_err());
+    }
+}
@@ -0,0 +1,324 @@
+// Read Master Desktop - Zoom
+//
+// Native webview zoom per window. A reader window's level is remembered per
+// book and reapplied whenever a window reports it is showing that book; the
+// library window keeps a single app-wide level in `zoom.library`, which is
+// also the UI scale setting (`set_ui_zoom`).
+//
+// The View menu's zoom items act on the focused window and are disabled
+// once its level reaches a limit.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use log::{info, warn};
+use rusqlite::{params, Connection, OptionalExtension};
+use tauri::menu::MenuItem;
+use tauri::webview::{PageLoadEvent, PageLoadPayload};
+use tauri::{AppHandle, Manager, Runtime, State, Webview, Window, WindowEvent};
+
+use crate::db::Database;
+use crate::profiles::ProfileStoreExt;
+use crate::{persist, session};
+
+pub const MIN_ZOOM: f64 = 0.5;
+pub const MAX_ZOOM: f64 = 3.0;
+
+/// Change per Zoom In / Zoom Out
+const ZOOM_STEP: f64 = 0.1;
+
+const LIBRARY_WINDOW: &str = "main";
+const LIBRARY_SETTING: &str = "zoom.library";
+const SETTINGS_STORE: &str = "settings.json";
+
+/// Menu item ids handled here
+pub const MENU_ITEMS: &[&str] = &["zoom_in", "zoom_out", "actual_size"];
+
+// ============================================================================
+// Types
+// ============================================================================
+
+/// Current zoom factor of each window, by label. The webview has no getter.
+#[derive(Default)]
+pub struct ZoomLevels(Mutex<HashMap<String, f64>>);
+
+impl ZoomLevels {
+    fn get(&self, label: &str) -> Option<f64> {
+        self.0.lock().ok()?.get(label).copied()
+    }
+
+    fn set(&self, label: &str, factor: f64) {
+        if let Ok(mut levels) = self.0.lock() {
+            levels.insert(label.to_string(), factor);
+        }
+    }
+
+    fn remove(&self, label: &str) {
+        if let Ok(mut levels) = self.0.lock() {
+            levels.remove(label);
+        }
+    }
+}
+
+// ============================================================================
+// Levels
+// ============================================================================
+
+/// Limit a factor to the supported range, to two decimals so repeated
+/// steps land on round values
+pub fn clamp(factor: f64) -> f64 {
+    if !factor.is_finite() {
+        return 1.0;
+    }
+    ((factor * 100.0).round() / 100.0).clamp(MIN_ZOOM, MAX_ZOOM)
+}
+
+/// The level a zoom menu item moves `current` to
+fn step(current: f64, item: &str) -> Option<f64> {
+    match item {
+        "zoom_in" => Some(clamp(current + ZOOM_STEP)),
+        "zoom_out" => Some(clamp(current - ZOOM_STEP)),
+        "actual_size" => Some(1.0),
+        _ => None,
+    }
+}
+
+fn load_book_zoom(conn: &Connection, book_id: i64) -> rusqlite::Result<Option<f64>> {
+    conn.query_row(
+        "SELECT factor FROM book_zoom WHERE book_id = ?1",
+        [book_id],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Store a book's level; actual size is stored as no row
+fn save_book_zoom(conn: &Connection, book_id: i64, factor: f64) -> rusqlite::Result<()> {
+    if factor == 1.0 {
+        conn.execute("DELETE FROM book_zoom WHERE book_id = ?1", [book_id])?;
+    } else {
+        conn.execute(
+            "INSERT INTO book_zoom (book_id, factor, updated_at)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT (book_id) DO UPDATE
+             SET factor = excluded.factor, updated_at = excluded.updated_at",
+            params![book_id, factor, chrono::Utc::now().to_rfc3339()],
+        )?;
+    }
+    Ok(())
+}
+
+fn library_zoom<R: Runtime>(app: &AppHandle<R>) -> f64 {
+    app.profile_store(SETTINGS_STORE)
+        .ok()
+        .and_then(|s| s.get(LIBRARY_SETTING))
+        .and_then(|v| v.as_f64())
+        .map(clamp)
+        .unwrap_or(1.0)
+}
+
+fn save_library_zoom<R: Runtime>(app: &AppHandle<R>, factor: f64) -> Result<(), String> {
+    let store = app
+        .profile_store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open settings: {}", e))?;
+    store.set(LIBRARY_SETTING, serde_json::json!(factor));
+    persist::mark_dirty(app, SETTINGS_STORE);
+    Ok(())
+}
+
+/// Remember a window's level for its book, or app-wide for the library
+fn persist_level<R: Runtime>(app: &AppHandle<R>, label: &str, factor: f64) -> Result<(), String> {
+    if let Some(book_id) = session::window_book(app, label) {
+        return app
+            .state::<Database>()
+            .with_conn(|conn| save_book_zoom(conn, book_id, factor));
+    }
+    if label == LIBRARY_WINDOW {
+        save_library_zoom(app, factor)?;
+    }
+    Ok(())
+}
+
+/// Zoom a window's webview without persisting the level
+fn apply<R: Runtime>(app: &AppHandle<R>, label: &str, factor: f64) -> Result<(), String> {
+    let window = app
+        .get_webview_window(label)
+        .ok_or_else(|| format!("Window not found: {}", label))?;
+    window
+        .set_zoom(factor)
+        .map_err(|e| format!("Failed to set zoom: {}", e))?;
+    app.state::<ZoomLevels>().set(label, factor);
+
+    if window.is_focused().unwrap_or(false) {
+        update_menu(app, factor);
+    }
+    Ok(())
+}
+
+/// Reapply the saved level for what a window now shows: its book's, or the
+/// app-wide level once the library window is back from a book
+pub fn restore<R: Runtime>(app: &AppHandle<R>, label: &str, book_id: Option<i64>) {
+    let factor = match book_id {
+        Some(book_id) => app
+            .state::<Database>()
+            .with_conn(|conn| load_book_zoom(conn, book_id))
+            .unwrap_or_else(|e| {
+                warn!("Failed to load zoom for book {}: {}", book_id, e);
+                None
+            })
+            .map(clamp)
+            .unwrap_or(1.0),
+        None if label == LIBRARY_WINDOW => library_zoom(app),
+        None => return,
+    };
+
+    if let Err(e) = apply(app, label, factor) {
+        warn!("Failed to restore zoom of {}: {}", label, e);
+    }
+}
+
+// ============================================================================
+// Menu
+// ============================================================================
+
+fn menu_item<R: Runtime>(app: &AppHandle<R>, id: &str) -> Option<MenuItem<R>> {
+    let menu = app.menu()?;
+    menu.items()
+        .ok()?
+        .iter()
+        .filter_map(|item| item.as_submenu()?.get(id))
+        .find_map(|item| item.as_menuitem().cloned())
+}
+
+/// Enable the zoom items the focused window's level still allows
+fn update_menu<R: Runtime>(app: &AppHandle<R>, factor: f64) {
+    let states = [
+        ("zoom_in", factor < MAX_ZOOM),
+        ("zoom_out", factor > MIN_ZOOM),
+        ("actual_size", factor != 1.0),
+    ];
+    for (id, enabled) in states {
+        if let Some(item) = menu_item(app, id) {
+            let _ = item.set_enabled(enabled);
+        }
+    }
+}
+
+fn current<R: Runtime>(app: &AppHandle<R>, label: &str) -> f64 {
+    app.state::<ZoomLevels>().get(label).unwrap_or(1.0)
+}
+
+/// Step the focused window's zoom from the View menu
+pub fn handle_menu_event<R: Runtime>(app: &AppHandle<R>, item: &str) {
+    let label = app
+        .webview_windows()
+        .into_iter()
+        .find(|(_, window)| window.is_focused().unwrap_or(false))
+        .map(|(label, _)| label)
+        .unwrap_or_else(|| LIBRARY_WINDOW.to_string());
+
+    let Some(factor) = step(current(app, &label), item) else {
+        return;
+    };
+    if let Err(e) = set(app, &label, factor) {
+        warn!("Failed to zoom {}: {}", label, e);
+    }
+}
+
+// ============================================================================
+// Window Hooks
+// ============================================================================
+
+/// Builder-level page load hook reapplying a window's level after
+/// navigation; the library window starts at the app-wide level
+pub fn handle_page_load<R: Runtime>(webview: &Webview<R>, payload: &PageLoadPayload<'_>) {
+    if payload.event() != PageLoadEvent::Finished {
+        return;
+    }
+
+    let app = webview.app_handle();
+    let label = webview.label();
+    let factor = match app.state::<ZoomLevels>().get(label) {
+        Some(factor) => factor,
+        None if label == LIBRARY_WINDOW => library_zoom(app),
+        None => return,
+    };
+    if let Err(e) = apply(app, label, factor) {
+        warn!("Failed to apply zoom to {}: {}", label, e);
+    }
+}
+
+/// Builder-level window event hook tracking focus and closed windows
+pub fn handle_window_event<R: Runtime>(window: &Window<R>, event: &WindowEvent) {
+    match event {
+        WindowEvent::Focused(true) => update_menu(
+            window.app_handle(),
+            current(window.app_handle(), window.label()),
+        ),
+        WindowEvent::Destroyed => window.state::<ZoomLevels>().remove(window.label()),
+        _ => {}
+    }
+}
+
+// ============================================================================
+// Commands
+// ============================================================================
+
+fn set<R: Runtime>(app: &AppHandle<R>, label: &str, factor: f64) -> Result<f64, String> {
+    let factor = clamp(factor);
+    apply(app, label, factor)?;
+    persist_level(app, label, factor)?;
+    info!("Zoom of {} set to {}", label, factor);
+    Ok(factor)
+}
+
+/// Zoom a window's webview, clamped to 0.5–3.0, and remember the level for
+/// its book (or app-wide for the library). Returns the applied factor.
+#[tauri::command]
+pub fn set_zoom<R: Runtime>(
+    app: AppHandle<R>,
+    window_label: String,
+    factor: f64,
+) -> Result<f64, String> {
+    set(&app, &window_label, factor)
+}
+
+/// Zoom the whole main window UI, clamped to 0.5–3.0. The level is saved
+/// app-wide, even while the window shows a book, and reapplied at launch.
+#[tauri::command]
+pub fn set_ui_zoom<R: Runtime>(app: AppHandle<R>, factor: f64) -> Result<(), String> {
+    let factor = clamp(factor);
+    apply(&app, LIBRARY_WINDOW, factor)?;
+    save_library_zoom(&app, factor)?;
+    info!("UI zoom set to {}", factor);
+    Ok(())
+}
+
+/// A window's current zoom factor
+#[tauri::command]
+pub fn get_zoom(levels: State<'_, ZoomLevels>, window_label: String) -> f64 {
+    levels.get(&window_label).unwrap_or(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamps_to_the_supported_range() {
+        assert_eq!(clamp(0.1), MIN_ZOOM);
+        assert_eq!(clamp(4.0), MAX_ZOOM);
+        assert_eq!(clamp(1.234), 1.23);
+        assert_eq!(clamp(f64::NAN), 1.0);
+    }
+
+    #[test]
+    fn steps_stop_at_the_limits() {
+        assert_eq!(step(1.0, "zoom_in"), Some(1.1));
+        assert_eq!(step(1.1, "zoom_out"), Some(1.0));
+        assert_eq!(step(2.95, "zoom_in"), Some(MAX_ZOOM));
+        assert_eq!(step(0.5, "zoom_out"), Some(MIN_ZOOM));
+        assert_eq!(step(2.0, "actual_size"), Some(1.0));
+        assert_eq!(step(1.0, "library"), None);
+    }
+}